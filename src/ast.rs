@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::keyword::Keyword;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Integer32(i32),
@@ -7,6 +9,8 @@ pub enum Expr {
     Float(f64),
     Bool(bool),
     String(String),
+    Bytes(Vec<u8>),    // #b"..." / #x"..." literal
+    Keyword(Keyword),  // :name literal
     Symbol(String),
     List(Vec<Expr>),
     If {
@@ -14,23 +18,130 @@ pub enum Expr {
         then_branch: Box<Expr>,
         else_branch: Box<Expr>,
     },
+    /// `(let x v body)`: `x` is bound only while evaluating `body`.
     Let {
         name: String,
         type_ann: Option<Type>,
         value: Box<Expr>,
-        body: Option<Box<Expr>>,  // Optional body for let-in expressions
+        body: Box<Expr>,
+    },
+    /// `(def x v)`: a global definition. Only allowed at the top level.
+    Def {
+        name: String,
+        type_ann: Option<Type>,
+        value: Box<Expr>,
+        /// Declared with `defconst`: may not be defined again.
+        constant: bool,
+    },
+    /// `(defparam *x* v)`: a global whose value `parameterize` can
+    /// override for a dynamic extent. Only allowed at the top level.
+    Defparam {
+        name: String,
+        value: Box<Expr>,
+    },
+    /// `(deftype Shape (Circle f64) (Rect f64 f64) Empty)`: a new type
+    /// whose values are made by one of its constructors, each taking the
+    /// fields listed. Only allowed at the top level.
+    Deftype {
+        name: String,
+        variants: Vec<(String, Vec<Type>)>,
+    },
+    /// `(defstruct Point [x: f64 y: f64])`: a type with one constructor,
+    /// named like the type, whose fields can also be read by name with
+    /// `(.x p)` or `(get p :x)`. Only allowed at the top level.
+    Defstruct {
+        name: String,
+        fields: Vec<(String, Type)>,
     },
+    /// `(parameterize [*x* v ...] body...)`: run `body` with each
+    /// parameter set to its new value, for everything `body` calls too.
+    Parameterize {
+        bindings: Vec<(String, Expr)>,
+        body: Box<Expr>,
+    },
+    /// `(with-open [f (open-file p) ...] body...)`: bind like `let`, then
+    /// close each resource, last first, however `body` exits.
+    WithOpen {
+        bindings: Vec<(String, Expr)>,
+        body: Box<Expr>,
+    },
+    /// `(defer e)`: evaluate `e` when the enclosing function body or
+    /// `with-open` exits, whether it returns or fails.
+    Defer(Box<Expr>),
     Defn {
         name: String,
         params: Vec<(String, Type)>,
         return_type: Type,
         body: Box<Expr>,
+        /// Declared with `defn-`: usable only inside its own module.
+        private: bool,
+        meta: Meta,
+    },
+    /// `(defn name ([params] body...) ...)`: one function with a `Defn` for
+    /// each number of arguments it takes. A call runs the one that fits.
+    MultiArity(Vec<Expr>),
+    /// `(defgen name [params] body...)`: calling `name` returns a
+    /// generator that runs `body` up to each `yield` as it's consumed.
+    Defgen {
+        name: String,
+        params: Vec<(String, Type)>,
+        body: Box<Expr>,
+        meta: Meta,
     },
     Lambda {
         params: Vec<(String, Type)>,
         return_type: Option<Type>,
         body: Box<Expr>,
     },
+    /// `(loop [i 0 acc 1] body)`: bindings that `recur` in `body` rebinds.
+    Loop {
+        bindings: Vec<(String, Option<Type>, Expr)>,
+        body: Box<Expr>,
+    },
+    /// `(do a b c)`: evaluate in order, giving the last value. Also what
+    /// a `loop` or `defgen` with several body forms parses to.
+    Do(Vec<Expr>),
+    /// `(yield v)`: hand `v` to whoever is consuming the generator and
+    /// suspend until the next value is asked for.
+    Yield(Box<Expr>),
+    /// `(recur a b)`: jump back to the enclosing `loop`, `defn` or `fn`
+    /// with new values for its bindings. Only valid in tail position.
+    Recur(Vec<Expr>),
+    /// `(return v)`: leave the enclosing `defn` or `fn` with `v` right away.
+    Return(Box<Expr>),
+    /// `(the T e)`: `e`, checked to have type `T`. An `i32` is widened when
+    /// `T` is `i64`; otherwise it leaves no trace at runtime.
+    The {
+        ty: Type,
+        expr: Box<Expr>,
+    },
+    /// `(block :name body)`: `body`, unless a `return-from :name` in it
+    /// leaves early with a value of its own.
+    Block {
+        label: Keyword,
+        body: Box<Expr>,
+    },
+    /// `(return-from :name v)`: leave the enclosing `block :name` with `v`.
+    ReturnFrom {
+        label: Keyword,
+        value: Box<Expr>,
+    },
+    /// `(reset body)`: `body`, as the end of what a `shift` inside it
+    /// captures.
+    Reset(Box<Expr>),
+    /// `(shift k body)`: take the rest of the evaluation up to the
+    /// enclosing `reset` out, bind it to `k` as a function, and run `body`
+    /// in its place: its value is the `reset`'s.
+    Shift {
+        name: String,
+        body: Box<Expr>,
+    },
+    /// `(set-reader-macro! #\$ handler)`: already in effect by the time
+    /// it's evaluated, since the reader acts on it.
+    SetReaderMacro {
+        ch: char,
+        handler: String,
+    },
     Call {
         func: Box<Expr>,
         args: Vec<Expr>,
@@ -57,6 +168,7 @@ pub enum Pattern {
     LiteralF64(f64),
     LiteralBool(bool),
     LiteralString(String),
+    LiteralKeyword(Keyword),
     Nil,                                    // nil / ()
     Cons(Box<Pattern>, Box<Pattern>),       // (cons head tail)
     /// `(<pat> as name)` — match `<pat>` and additionally bind the whole
@@ -71,6 +183,10 @@ pub enum Pattern {
     /// bind the same set of names with the same types (soundness). The
     /// first matching branch's bindings are used by the arm body.
     Or(Vec<Pattern>),
+    /// `(Circle r)` — a value a `deftype` constructor made, with a pattern
+    /// for each of its fields. A constructor without fields is just its
+    /// name: `Empty`.
+    Constructor(String, Vec<Pattern>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -80,12 +196,82 @@ pub enum Type {
     F64,
     Bool,
     String,
+    /// What an expression run only for its effect gives (`println`,
+    /// `set-ref!`). Its one value is `()`.
+    Unit,
+    /// Mutable, growable string buffer (`string-builder`).
+    StringBuilder,
+    /// Raw byte buffer (`#b"..."`, `read-file-bytes`).
+    Bytes,
+    /// A point in time in UTC.
+    DateTime,
+    /// A point in time at a fixed UTC offset.
+    ZonedDateTime,
+    /// A signed span of time.
+    Duration,
+    /// Handle on a persistent key-value store (`kv-open`).
+    KvStore,
+    /// Dense f64 matrix (`mat`).
+    Matrix,
+    /// Interned `:name`.
+    Keyword,
+    /// Where output goes: `stdout`, `stderr` or a `string-port`.
+    Port,
     Function {
         params: Vec<Type>,
         return_type: Box<Type>,
     },
+    /// A multi-arity `defn`: one `Function` per number of arguments.
+    MultiArity(Vec<Type>),
     List(Box<Type>),  // List type, e.g., List<i32>
+    /// Mutable cell holding a `T` (`ref`, `set-ref!`).
+    Ref(Box<Type>),
+    /// Lazy sequence of `T`s from a `defgen`.
+    Gen(Box<Type>),
+    /// Dotted pair `(a . b)`: a cons cell whose tail isn't a list.
+    Pair(Box<Type>, Box<Type>),
+    /// Fixed-size array of exactly this many `T`s (`#[1 2 3]`).
+    Array(Box<Type>, usize),
+    /// Growable vector with O(1) indexing (`[1 2 3]`).
+    Vector(Box<Type>),
+    /// Hash map from `K`s to `V`s (`{:a 1 :b 2}`).
+    Map(Box<Type>, Box<Type>),
+    /// View into part of an array (`slice`); shares its elements.
+    Slice(Box<Type>),
+    /// A type itself, as a value (`type-of`).
+    Type,
+    /// Embedder data (see `foreign`) of the type with this name. Only
+    /// `type-of` makes it: there's no annotation for it.
+    Foreign(String),
+    /// A type declared with `deftype`, by name.
+    Named(String),
     Inferred,
+    /// A type the checker is still working out, by number: unification
+    /// solves it as it learns more (see `types::resolve`). Shown as `_`.
+    Var(u32),
+    /// The `n`th type parameter of a generalized `let`, `def` or `defn`,
+    /// shown as `'a`, `'b`, ...: each use of the name gets fresh variables
+    /// for them, so `(defn id [x] x)` works on anything.
+    Generic(u32),
+}
+
+/// `^{:key value ...}` on a definition: literal values, read by `meta`,
+/// the lints and anything else that looks at the form.
+pub type Meta = Vec<(Keyword, Expr)>;
+
+/// `meta` as written, followed by a space; nothing if it's empty.
+fn write_meta(f: &mut fmt::Formatter, meta: &Meta) -> fmt::Result {
+    if meta.is_empty() {
+        return Ok(());
+    }
+    write!(f, "^{{")?;
+    for (i, (key, value)) in meta.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{} {}", key, value)?;
+    }
+    write!(f, "}} ")
 }
 
 impl fmt::Display for Type {
@@ -96,6 +282,16 @@ impl fmt::Display for Type {
             Type::F64 => write!(f, "f64"),
             Type::Bool => write!(f, "bool"),
             Type::String => write!(f, "String"),
+            Type::Unit => write!(f, "Unit"),
+            Type::StringBuilder => write!(f, "StringBuilder"),
+            Type::Bytes => write!(f, "Bytes"),
+            Type::DateTime => write!(f, "DateTime"),
+            Type::ZonedDateTime => write!(f, "ZonedDateTime"),
+            Type::Duration => write!(f, "Duration"),
+            Type::KvStore => write!(f, "KvStore"),
+            Type::Matrix => write!(f, "Matrix"),
+            Type::Port => write!(f, "Port"),
+            Type::Keyword => write!(f, "Keyword"),
             Type::Function { params, return_type } => {
                 write!(f, "fn(")?;
                 for (i, param) in params.iter().enumerate() {
@@ -106,8 +302,28 @@ impl fmt::Display for Type {
                 }
                 write!(f, ") -> {}", return_type)
             }
+            Type::MultiArity(arities) => {
+                let arities: Vec<String> = arities.iter().map(Type::to_string).collect();
+                write!(f, "{}", arities.join(" | "))
+            }
             Type::List(elem_type) => write!(f, "List<{}>", elem_type),
-            Type::Inferred => write!(f, "_"),
+            Type::Ref(inner) => write!(f, "Ref<{}>", inner),
+            Type::Gen(inner) => write!(f, "Gen<{}>", inner),
+            Type::Pair(head, tail) => write!(f, "Pair<{}, {}>", head, tail),
+            Type::Array(elem, len) => write!(f, "Array<{}, {}>", elem, len),
+            Type::Slice(elem) => write!(f, "Slice<{}>", elem),
+            Type::Vector(elem) => write!(f, "Vec<{}>", elem),
+            Type::Map(key, value) => write!(f, "Map<{}, {}>", key, value),
+            Type::Type => write!(f, "Type"),
+            Type::Foreign(name) | Type::Named(name) => write!(f, "{}", name),
+            Type::Inferred | Type::Var(_) => write!(f, "_"),
+            Type::Generic(n) => {
+                write!(f, "'{}", (b'a' + (n % 26) as u8) as char)?;
+                if *n >= 26 {
+                    write!(f, "{}", n / 26)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -117,9 +333,11 @@ impl fmt::Display for Expr {
         match self {
             Expr::Integer32(n) => write!(f, "{}", n),
             Expr::Integer64(n) => write!(f, "{}", n),
-            Expr::Float(n) => write!(f, "{}", n),
+            Expr::Float(n) => write!(f, "{}", render_float(*n)),
             Expr::Bool(b) => write!(f, "{}", b),
-            Expr::String(s) => write!(f, "\"{}\"", s),
+            Expr::String(s) => write!(f, "{}", render_string(s)),
+            Expr::Bytes(b) => write!(f, "{}", render_bytes(b)),
+            Expr::Keyword(k) => write!(f, "{}", k),
             Expr::Symbol(s) => write!(f, "{}", s),
             Expr::List(exprs) => {
                 write!(f, "(")?;
@@ -135,22 +353,24 @@ impl fmt::Display for Expr {
                 write!(f, "(if {} {} {})", condition, then_branch, else_branch)
             }
             Expr::Let { name, type_ann, value, body } => {
-                if let Some(b) = body {
-                    if let Some(ty) = type_ann {
-                        write!(f, "(let {} {} {} {})", name, ty, value, b)
-                    } else {
-                        write!(f, "(let {} {} {})", name, value, b)
-                    }
+                if let Some(ty) = type_ann {
+                    write!(f, "(let {} {} {} {})", name, ty, value, body)
                 } else {
-                    if let Some(ty) = type_ann {
-                        write!(f, "(let {} {} {})", name, ty, value)
-                    } else {
-                        write!(f, "(let {} {})", name, value)
-                    }
+                    write!(f, "(let {} {} {})", name, value, body)
+                }
+            }
+            Expr::Def { name, type_ann, value, constant } => {
+                let head = if *constant { "defconst" } else { "def" };
+                if let Some(ty) = type_ann {
+                    write!(f, "({} {} {} {})", head, name, ty, value)
+                } else {
+                    write!(f, "({} {} {})", head, name, value)
                 }
             }
-            Expr::Defn { name, params, return_type, body } => {
-                write!(f, "(defn {} [", name)?;
+            Expr::Defn { name, params, return_type, body, private, meta } => {
+                write!(f, "(defn{} ", if *private { "-" } else { "" })?;
+                write_meta(f, meta)?;
+                write!(f, "{} [", name)?;
                 for (i, (param_name, param_type)) in params.iter().enumerate() {
                     if i > 0 {
                         write!(f, " ")?;
@@ -159,6 +379,19 @@ impl fmt::Display for Expr {
                 }
                 write!(f, "] -> {} {})", return_type, body)
             }
+            Expr::MultiArity(defns) => {
+                let Some(Expr::Defn { name, private, meta, .. }) = defns.first() else { return write!(f, "(defn)") };
+                write!(f, "(defn{} ", if *private { "-" } else { "" })?;
+                write_meta(f, meta)?;
+                write!(f, "{}", name)?;
+                for defn in defns {
+                    if let Expr::Defn { params, return_type, body, .. } = defn {
+                        let params: Vec<String> = params.iter().map(|(n, t)| format!("{}: {}", n, t)).collect();
+                        write!(f, " ([{}] -> {} {})", params.join(" "), return_type, body)?;
+                    }
+                }
+                write!(f, ")")
+            }
             Expr::Lambda { params, return_type, body } => {
                 write!(f, "(fn [")?;
                 for (i, (param_name, param_type)) in params.iter().enumerate() {
@@ -173,6 +406,91 @@ impl fmt::Display for Expr {
                 }
                 write!(f, " {})", body)
             }
+            Expr::Loop { bindings, body } => {
+                write!(f, "(loop [")?;
+                for (i, (name, type_ann, value)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    match type_ann {
+                        Some(ty) => write!(f, "{}: {} {}", name, ty, value)?,
+                        None => write!(f, "{} {}", name, value)?,
+                    }
+                }
+                write!(f, "] {})", body)
+            }
+            Expr::Defparam { name, value } => write!(f, "(defparam {} {})", name, value),
+            Expr::Defstruct { name, fields } => {
+                write!(f, "(defstruct {} [", name)?;
+                for (i, (field, ty)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}: {}", field, ty)?;
+                }
+                write!(f, "])")
+            }
+            Expr::Deftype { name, variants } => {
+                write!(f, "(deftype {}", name)?;
+                for (ctor, fields) in variants {
+                    if fields.is_empty() {
+                        write!(f, " {}", ctor)?;
+                        continue;
+                    }
+                    write!(f, " ({}", ctor)?;
+                    for field in fields {
+                        write!(f, " {}", field)?;
+                    }
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
+            }
+            Expr::Parameterize { bindings, body } | Expr::WithOpen { bindings, body } => {
+                let head = if matches!(self, Expr::WithOpen { .. }) { "with-open" } else { "parameterize" };
+                write!(f, "({} [", head)?;
+                for (i, (name, value)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{} {}", name, value)?;
+                }
+                write!(f, "] {})", body)
+            }
+            Expr::Defer(expr) => write!(f, "(defer {})", expr),
+            Expr::Defgen { name, params, body, meta } => {
+                write!(f, "(defgen ")?;
+                write_meta(f, meta)?;
+                write!(f, "{} [", name)?;
+                for (i, (param_name, param_type)) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}: {}", param_name, param_type)?;
+                }
+                write!(f, "] {})", body)
+            }
+            Expr::Do(exprs) => {
+                write!(f, "(do")?;
+                for expr in exprs {
+                    write!(f, " {}", expr)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Yield(value) => write!(f, "(yield {})", value),
+            Expr::Return(value) => write!(f, "(return {})", value),
+            Expr::The { ty, expr } => write!(f, "(the {} {})", ty, expr),
+            Expr::Block { label, body } => write!(f, "(block {} {})", label, body),
+            Expr::ReturnFrom { label, value } => write!(f, "(return-from {} {})", label, value),
+            Expr::Reset(body) => write!(f, "(reset {})", body),
+            Expr::Shift { name, body } => write!(f, "(shift {} {})", name, body),
+            Expr::SetReaderMacro { ch, handler } => write!(f, "(set-reader-macro! #\\{} {})", ch, handler),
+            Expr::Recur(args) => {
+                write!(f, "(recur")?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
             Expr::Call { func, args } => {
                 write!(f, "({}", func)?;
                 for arg in args {
@@ -192,6 +510,45 @@ impl fmt::Display for Expr {
     }
 }
 
+/// Render bytes as a `#b"..."` literal that reads back to the same
+/// value: printable ASCII verbatim, everything else as `\xNN`.
+pub fn render_bytes(bytes: &[u8]) -> String {
+    let mut out = String::from("#b\"");
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Render text as a `"..."` literal that reads back to the same string.
+pub fn render_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Render a float so it reads back as one: `1.0`, not `1`.
+pub fn render_float(n: f64) -> String {
+    let text = n.to_string();
+    if n.is_finite() && !text.contains('.') { format!("{}.0", text) } else { text }
+}
+
 impl fmt::Display for Pattern {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -199,9 +556,10 @@ impl fmt::Display for Pattern {
             Pattern::Variable(name) => write!(f, "{}", name),
             Pattern::LiteralI32(n) => write!(f, "{}", n),
             Pattern::LiteralI64(n) => write!(f, "{}", n),
-            Pattern::LiteralF64(n) => write!(f, "{}", n),
+            Pattern::LiteralF64(n) => write!(f, "{}", render_float(*n)),
             Pattern::LiteralBool(b) => write!(f, "{}", b),
-            Pattern::LiteralString(s) => write!(f, "\"{}\"", s),
+            Pattern::LiteralString(s) => write!(f, "{}", render_string(s)),
+            Pattern::LiteralKeyword(k) => write!(f, "{}", k),
             Pattern::Nil => write!(f, "nil"),
             Pattern::Cons(head, tail) => write!(f, "(cons {} {})", head, tail),
             Pattern::As(inner, name) => write!(f, "({} as {})", inner, name),
@@ -213,6 +571,14 @@ impl fmt::Display for Pattern {
                 }
                 write!(f, ")")
             }
+            Pattern::Constructor(name, fields) if fields.is_empty() => write!(f, "{}", name),
+            Pattern::Constructor(name, fields) => {
+                write!(f, "({}", name)?;
+                for field in fields {
+                    write!(f, " {}", field)?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
\ No newline at end of file
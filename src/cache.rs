@@ -0,0 +1,100 @@
+//! The `rusp build` cache: artifacts from earlier builds, keyed by a
+//! hash of everything that went into them.
+//!
+//! Imports are expanded before codegen, so a build emits one artifact
+//! for the whole program; its key covers the source of every module the
+//! program loaded (with the path each was found at) and the build's
+//! options. Editing any module, or changing which file an import
+//! resolves to, gives a new key, and an unchanged program is copied out
+//! of the cache instead of going through LLVM again.
+//!
+//! The cache directory is `$RUSP_CACHE` if that's set, otherwise
+//! `.rusp-cache` next to the file being built. Entries are never
+//! evicted; deleting the directory is always safe.
+
+use std::path::{Path, PathBuf};
+
+/// 64-bit FNV-1a: stable across runs and Rust versions, unlike
+/// `DefaultHasher`, which is what a key kept on disk needs.
+#[derive(Clone, Copy)]
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    /// Add `bytes`, length first so `("ab", "c")` and `("a", "bc")`
+    /// differ.
+    fn write(&mut self, bytes: &[u8]) {
+        for b in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// The key for building `files` (as `modules::Loader::files` lists
+/// them) with `options`, everything else that changes the output, e.g.
+/// the emit kind and `-g`. The rusp version is always included.
+pub fn key(files: &[PathBuf], options: &[&str]) -> Result<String, String> {
+    let mut hash = Fnv::new();
+    hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+    for option in options {
+        hash.write(option.as_bytes());
+    }
+    for file in files {
+        let source = std::fs::read(file).map_err(|e| format!("could not read {}: {}", file.display(), e))?;
+        hash.write(file.to_string_lossy().as_bytes());
+        hash.write(&source);
+    }
+    Ok(format!("{:016x}", hash.0))
+}
+
+/// A directory of artifacts, one file per key.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// The cache for building `file`.
+    pub fn for_file(file: &str) -> Self {
+        match std::env::var_os("RUSP_CACHE") {
+            Some(dir) => Cache::new(dir),
+            None => {
+                let dir = Path::new(file).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                Cache::new(dir.join(".rusp-cache"))
+            }
+        }
+    }
+
+    fn entry(&self, key: &str, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, ext))
+    }
+
+    /// Copy the artifact for `key` to `out`, if there is one. False on
+    /// a miss.
+    pub fn fetch(&self, key: &str, ext: &str, out: &Path) -> Result<bool, String> {
+        let entry = self.entry(key, ext);
+        if !entry.is_file() {
+            return Ok(false);
+        }
+        std::fs::copy(&entry, out).map_err(|e| format!("could not write {}: {}", out.display(), e))?;
+        Ok(true)
+    }
+
+    /// Keep a copy of `built` as the artifact for `key`. The copy is
+    /// renamed into place, so a build running at the same time never
+    /// sees half an entry.
+    pub fn store(&self, key: &str, ext: &str, built: &Path) -> Result<(), String> {
+        let failed = |e: std::io::Error| format!("could not cache {}: {}", built.display(), e);
+        std::fs::create_dir_all(&self.dir).map_err(failed)?;
+        let partial = self.dir.join(format!("{}.{}.{}.tmp", key, ext, std::process::id()));
+        std::fs::copy(built, &partial).map_err(failed)?;
+        std::fs::rename(&partial, self.entry(key, ext)).map_err(failed)
+    }
+}
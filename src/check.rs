@@ -0,0 +1,281 @@
+//! `rusp check`: type-check a program without running it, independent
+//! top-level forms in parallel.
+//!
+//! Top-level forms only see each other through the names they define,
+//! so they split into groups: a form joins the group of every earlier
+//! form that defines a name it mentions (or defines again), and of the
+//! `defn` of any function it calls before that's defined. Each group
+//! is checked in source order with a type environment of its own, which
+//! gives the same results as checking everything in one, and groups run
+//! on as many threads as there are cores. Findings are put back in
+//! source order, so the output doesn't depend on which thread got there
+//! first.
+//!
+//! Type environments aren't `Send`, but forms are: each worker builds its
+//! own environment, after interning the main thread's keywords so the
+//! forms' keywords mean the same there.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Expr, Pattern, Type};
+use crate::codegen::srcmap::SourceLoc;
+use crate::keyword::Keyword;
+use crate::modules::{SourceForm, defined_names};
+use crate::types::{TypeEnv, declare_defns, type_check};
+
+/// A type error or warning, at the top-level form it's in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub loc: SourceLoc,
+    pub error: bool,
+    /// The checker's error, or the lint (with its `[name]`).
+    pub message: String,
+}
+
+/// The groups `forms` split into: indices, in source order within a
+/// group, groups ordered by their first form.
+pub fn groups(forms: &[SourceForm]) -> Vec<Vec<usize>> {
+    let mut parent: Vec<usize> = (0..forms.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    // A `defn` is declared for the whole program (see `declare_defns`),
+    // so mentioning one links to it even before it comes.
+    let mut declared: HashMap<&str, usize> = HashMap::new();
+    for (i, sf) in forms.iter().enumerate() {
+        if matches!(sf.form, Expr::Defn { .. } | Expr::MultiArity(_)) {
+            for name in defined_names(&sf.form) {
+                declared.entry(name).or_insert(i);
+            }
+        }
+    }
+    // Name → the last form so far that defined it.
+    let mut definer: HashMap<String, usize> = HashMap::new();
+    for (i, sf) in forms.iter().enumerate() {
+        let mut mentioned = HashSet::new();
+        crate::tier::symbols(&sf.form, &mut mentioned);
+        type_mentions(&sf.form, &mut mentioned);
+        let mut defined = Vec::new();
+        definitions(&sf.form, &mut defined);
+        for name in mentioned.iter().map(String::as_str).chain(defined.iter().copied()) {
+            if let Some(&j) = definer.get(name).or_else(|| declared.get(name)) {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+        for name in defined {
+            definer.insert(name.to_string(), i);
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+    for i in 0..forms.len() {
+        let r = root(&mut parent, i);
+        let g = *group_of.entry(r).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[g].push(i);
+    }
+    groups
+}
+
+/// The globals `form` defines: its own name, or the definitions of a
+/// top-level `do`.
+fn definitions<'a>(form: &'a Expr, out: &mut Vec<&'a str>) {
+    match form {
+        Expr::Do(forms) => forms.iter().for_each(|form| definitions(form, out)),
+        form => out.extend(defined_names(form)),
+    }
+}
+
+/// The types `form`'s annotations name and the constructors its
+/// patterns take apart: mentions of a `deftype` or `defstruct` that
+/// `tier::symbols` doesn't see.
+fn type_mentions(form: &Expr, out: &mut HashSet<String>) {
+    let mut ty = |t: &Type| named_types(t, out);
+    match form {
+        Expr::Let { type_ann, value, body, .. } => {
+            type_ann.iter().for_each(&mut ty);
+            type_mentions(value, out);
+            type_mentions(body, out);
+        }
+        Expr::Def { type_ann, value, .. } => {
+            type_ann.iter().for_each(&mut ty);
+            type_mentions(value, out);
+        }
+        Expr::Deftype { variants, .. } => variants.iter().flat_map(|(_, fields)| fields).for_each(ty),
+        Expr::Defstruct { fields, .. } => fields.iter().for_each(|(_, t)| ty(t)),
+        Expr::Defn { params, return_type, body, .. } => {
+            params.iter().for_each(|(_, t)| ty(t));
+            ty(return_type);
+            type_mentions(body, out);
+        }
+        Expr::Defgen { params, body, .. } => {
+            params.iter().for_each(|(_, t)| ty(t));
+            type_mentions(body, out);
+        }
+        Expr::Lambda { params, return_type, body } => {
+            params.iter().for_each(|(_, t)| ty(t));
+            return_type.iter().for_each(&mut ty);
+            type_mentions(body, out);
+        }
+        Expr::Loop { bindings, body } => {
+            for (_, t, value) in bindings {
+                t.iter().for_each(|t| named_types(t, out));
+                type_mentions(value, out);
+            }
+            type_mentions(body, out);
+        }
+        Expr::The { ty: t, expr } => {
+            ty(t);
+            type_mentions(expr, out);
+        }
+        Expr::Match { scrutinee, arms } => {
+            type_mentions(scrutinee, out);
+            for (pattern, body) in arms {
+                pattern_mentions(pattern, out);
+                type_mentions(body, out);
+            }
+        }
+        Expr::If { condition, then_branch, else_branch } => {
+            [condition, then_branch, else_branch].into_iter().for_each(|e| type_mentions(e, out))
+        }
+        Expr::List(items) | Expr::Do(items) | Expr::Recur(items) | Expr::MultiArity(items) => {
+            items.iter().for_each(|e| type_mentions(e, out))
+        }
+        Expr::Call { func, args } => {
+            type_mentions(func, out);
+            args.iter().for_each(|e| type_mentions(e, out));
+        }
+        Expr::Parameterize { bindings, body } | Expr::WithOpen { bindings, body } => {
+            bindings.iter().for_each(|(_, value)| type_mentions(value, out));
+            type_mentions(body, out);
+        }
+        Expr::Yield(value)
+        | Expr::Return(value)
+        | Expr::Block { body: value, .. }
+        | Expr::ReturnFrom { value, .. }
+        | Expr::Reset(value)
+        | Expr::Shift { body: value, .. }
+        | Expr::Defer(value)
+        | Expr::Defparam { value, .. } => type_mentions(value, out),
+        _ => {}
+    }
+}
+
+fn pattern_mentions(pattern: &Pattern, out: &mut HashSet<String>) {
+    match pattern {
+        Pattern::Constructor(tag, fields) => {
+            out.insert(tag.clone());
+            fields.iter().for_each(|p| pattern_mentions(p, out));
+        }
+        Pattern::Guard(inner, guard) => {
+            pattern_mentions(inner, out);
+            type_mentions(guard, out);
+        }
+        Pattern::As(inner, _) => pattern_mentions(inner, out),
+        Pattern::Cons(head, tail) => {
+            pattern_mentions(head, out);
+            pattern_mentions(tail, out);
+        }
+        Pattern::Or(branches) => branches.iter().for_each(|p| pattern_mentions(p, out)),
+        _ => {}
+    }
+}
+
+fn named_types(ty: &Type, out: &mut HashSet<String>) {
+    match ty {
+        Type::Named(name) => {
+            out.insert(name.clone());
+        }
+        Type::List(inner)
+        | Type::Ref(inner)
+        | Type::Gen(inner)
+        | Type::Array(inner, _)
+        | Type::Vector(inner)
+        | Type::Slice(inner) => named_types(inner, out),
+        Type::Pair(a, b) | Type::Map(a, b) => {
+            named_types(a, out);
+            named_types(b, out);
+        }
+        Type::Function { params, return_type } => {
+            params.iter().for_each(|t| named_types(t, out));
+            named_types(return_type, out);
+        }
+        Type::MultiArity(arities) => arities.iter().for_each(|t| named_types(t, out)),
+        _ => {}
+    }
+}
+
+/// Lint and type-check every form, on up to `jobs` threads, returning
+/// what was found in the order of the forms. Unlike `rusp run`, a
+/// failing form doesn't stop the rest.
+pub fn check(forms: Vec<SourceForm>, jobs: usize) -> Vec<Finding> {
+    let groups = groups(&forms);
+    let mut forms: Vec<Option<SourceForm>> = forms.into_iter().map(Some).collect();
+    // Each form with its position, to sort the findings back by.
+    let groups: Vec<Vec<(usize, SourceForm)>> = groups
+        .into_iter()
+        .map(|group| {
+            group.into_iter().map(|i| (i, forms[i].take().expect("each form is in one group"))).collect()
+        })
+        .collect();
+
+    // Biggest groups first, each to the worker with the least so far.
+    let mut workers: Vec<Vec<Vec<(usize, SourceForm)>>> = (0..jobs.max(1)).map(|_| Vec::new()).collect();
+    let mut load = vec![0; workers.len()];
+    let mut groups = groups;
+    groups.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    for group in groups {
+        let w = (0..workers.len()).min_by_key(|&w| load[w]).expect("at least one worker");
+        load[w] += group.len();
+        workers[w].push(group);
+    }
+
+    let keywords = Keyword::interned();
+    let mut findings: Vec<(usize, Finding)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = workers
+            .into_iter()
+            .filter(|groups| !groups.is_empty())
+            .map(|groups| {
+                let keywords = &keywords;
+                std::thread::Builder::new()
+                    .stack_size(crate::parser::STACK_SIZE)
+                    .spawn_scoped(scope, move || {
+                        Keyword::seed(keywords);
+                        groups.iter().flat_map(|group| check_group(group)).collect::<Vec<_>>()
+                    })
+                    .expect("could not start a check worker")
+            })
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().expect("a check worker panicked")).collect()
+    });
+    // A form is checked by one worker, in order, so the (stable) sort
+    // keeps each form's findings in the order they came.
+    findings.sort_by_key(|(i, _)| *i);
+    findings.into_iter().map(|(_, finding)| finding).collect()
+}
+
+/// One group, in order, in an environment of its own.
+fn check_group(group: &[(usize, SourceForm)]) -> Vec<(usize, Finding)> {
+    let mut env = TypeEnv::new();
+    declare_defns(group.iter().map(|(_, sf)| &sf.form), &mut env);
+    let mut findings = Vec::new();
+    for (i, sf) in group {
+        let warning =
+            |lint: crate::lint::Lint| (*i, Finding { loc: sf.loc_of(&lint), error: false, message: lint.to_string() });
+        findings.extend(crate::lint::check(&sf.form).into_iter().map(warning));
+        let checked = type_check(&sf.form, &mut env);
+        findings.extend(env.take_warnings().into_iter().map(warning));
+        match checked {
+            Ok(ty) => findings.extend(crate::lint::unused_result(&sf.form, &ty).map(warning)),
+            Err(message) => findings.push((*i, Finding { loc: sf.loc.clone(), error: true, message })),
+        }
+    }
+    findings
+}
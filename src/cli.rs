@@ -0,0 +1,281 @@
+//! Command-line support for scripts run with `rusp run FILE ARGS...`.
+//!
+//! `(args)` returns the script's arguments, and `(cli/parse spec (args))`
+//! turns them into an association list using a declarative spec. Each
+//! spec entry is a list of strings:
+//!
+//! ```text
+//! ("flag"   name short help)           ; --name / -s, "true" or "false"
+//! ("option" name short help)           ; --name VALUE, required
+//! ("option" name short help default)   ; --name VALUE, optional
+//! ("arg"    name help)                 ; positional, required, in order
+//! ```
+//!
+//! `short` may be `""`. The result is `(("name" "value") ...)` in spec
+//! order; read it back with `cli/get` / `cli/flag?`. `--help` (and `-h`,
+//! unless the spec claims it) stops the script with [`HELP_REQUESTED`];
+//! the driver then prints the generated usage and exits successfully.
+//!
+//! The arguments live in a thread-local set once by the driver, like the
+//! tier state, because builtins only see their own arguments.
+
+use std::cell::RefCell;
+
+use crate::env::Value;
+
+thread_local! {
+    static ARGS: RefCell<(String, Vec<String>)> = RefCell::new(("rusp".to_string(), Vec::new()));
+    /// Usage from a `cli/parse` that saw `--help`, for the driver.
+    static HELP: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The error `cli/parse` stops a script with when `--help` was given.
+pub const HELP_REQUESTED: &str = "cli/parse: --help requested";
+
+/// Record the script name and its arguments (everything after FILE).
+pub fn set_args(program: &str, args: Vec<String>) {
+    ARGS.with(|a| *a.borrow_mut() = (program.to_string(), args));
+}
+
+pub fn args() -> Vec<String> {
+    ARGS.with(|a| a.borrow().1.clone())
+}
+
+/// Keep `usage` for [`take_help`] and return the error that stops the
+/// script.
+pub fn request_help(usage: String) -> String {
+    HELP.with(|h| *h.borrow_mut() = Some(usage));
+    HELP_REQUESTED.to_string()
+}
+
+/// The usage text of a `--help` the script stopped for, if it did.
+pub fn take_help() -> Option<String> {
+    HELP.with(|h| h.borrow_mut().take())
+}
+
+fn program() -> String {
+    ARGS.with(|a| a.borrow().0.clone())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Flag,
+    /// `None` means the option is required.
+    Option(Option<String>),
+    Positional,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    kind: Kind,
+    name: String,
+    short: Option<char>,
+    help: String,
+}
+
+/// What `cli/parse` found.
+pub enum Parsed {
+    Values(Vec<(String, String)>),
+    /// `--help` was given; the generated usage text.
+    Help(String),
+}
+
+/// Parse `argv` against a spec value (see the module docs).
+pub fn parse(spec: &Value, argv: &[String]) -> Result<Parsed, String> {
+    let entries = parse_spec(spec)?;
+    let usage = usage(&entries);
+    match parse_args(&entries, argv) {
+        Ok(Some(values)) => Ok(Parsed::Values(values)),
+        Ok(None) => Ok(Parsed::Help(usage)),
+        Err(e) => Err(format!("{}\n\n{}", e, usage)),
+    }
+}
+
+fn parse_spec(spec: &Value) -> Result<Vec<Entry>, String> {
+    let items = match spec {
+        Value::List(items) => items.as_slice(),
+        Value::Nil => &[],
+        _ => return Err("cli/parse: spec must be a list".to_string()),
+    };
+    let mut entries = Vec::with_capacity(items.len());
+    for item in items {
+        let fields: Vec<&str> = match item {
+            Value::List(fields) => fields
+                .iter()
+                .map(|f| match f {
+                    Value::String(s) => Ok(s.as_str()),
+                    other => Err(format!("cli/parse: spec fields must be strings, got {}", other)),
+                })
+                .collect::<Result<_, _>>()?,
+            other => return Err(format!("cli/parse: spec entry must be a list, got {}", other)),
+        };
+        let short = |s: &str| -> Result<Option<char>, String> {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (None, _) => Ok(None),
+                (Some(c), None) => Ok(Some(c)),
+                _ => Err(format!("cli/parse: short name {:?} must be one character", s)),
+            }
+        };
+        let entry = match fields.as_slice() {
+            ["flag", name, s, help] => Entry { kind: Kind::Flag, name: name.to_string(), short: short(s)?, help: help.to_string() },
+            ["option", name, s, help] => Entry { kind: Kind::Option(None), name: name.to_string(), short: short(s)?, help: help.to_string() },
+            ["option", name, s, help, default] => Entry {
+                kind: Kind::Option(Some(default.to_string())),
+                name: name.to_string(),
+                short: short(s)?,
+                help: help.to_string(),
+            },
+            ["arg", name, help] => Entry { kind: Kind::Positional, name: name.to_string(), short: None, help: help.to_string() },
+            _ => return Err(format!("cli/parse: malformed spec entry {}", item)),
+        };
+        if entries.iter().any(|e: &Entry| e.name == entry.name) {
+            return Err(format!("cli/parse: duplicate spec entry {:?}", entry.name));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// `Ok(None)` means help was requested.
+fn parse_args(entries: &[Entry], argv: &[String]) -> Result<Option<Vec<(String, String)>>, String> {
+    let mut values: Vec<Option<String>> = entries
+        .iter()
+        .map(|e| match &e.kind {
+            Kind::Flag => Some("false".to_string()),
+            Kind::Option(default) => default.clone(),
+            Kind::Positional => None,
+        })
+        .collect();
+    let help_short = !entries.iter().any(|e| e.short == Some('h'));
+    let positionals: Vec<usize> = (0..entries.len()).filter(|&i| entries[i].kind == Kind::Positional).collect();
+    let mut next_positional = positionals.iter();
+
+    let mut i = 0;
+    let mut only_positional = false;
+    while i < argv.len() {
+        let arg = &argv[i];
+        i += 1;
+        if only_positional || arg == "-" || !arg.starts_with('-') {
+            let &idx = next_positional
+                .next()
+                .ok_or_else(|| format!("unexpected argument {:?}", arg))?;
+            values[idx] = Some(arg.clone());
+            continue;
+        }
+        if arg == "--" {
+            only_positional = true;
+            continue;
+        }
+        if arg == "--help" || (help_short && arg == "-h") {
+            return Ok(None);
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((n, v)) => (n, Some(v.to_string())),
+                None => (long, None),
+            };
+            let idx = entries
+                .iter()
+                .position(|e| e.name == name && e.kind != Kind::Positional)
+                .ok_or_else(|| format!("unknown option --{}", name))?;
+            values[idx] = Some(take_value(&entries[idx], inline, argv, &mut i)?);
+        } else {
+            // Short cluster: flags may be combined (`-vq`); an option
+            // takes the rest of the cluster or the next argument.
+            let cluster: Vec<char> = arg[1..].chars().collect();
+            for (k, c) in cluster.iter().enumerate() {
+                let idx = entries
+                    .iter()
+                    .position(|e| e.short == Some(*c))
+                    .ok_or_else(|| format!("unknown option -{}", c))?;
+                if entries[idx].kind == Kind::Flag {
+                    values[idx] = Some("true".to_string());
+                    continue;
+                }
+                let rest: String = cluster[k + 1..].iter().collect();
+                let inline = (!rest.is_empty()).then_some(rest);
+                values[idx] = Some(take_value(&entries[idx], inline, argv, &mut i)?);
+                break;
+            }
+        }
+    }
+
+    entries
+        .iter()
+        .zip(values)
+        .map(|(e, v)| match v {
+            Some(v) => Ok((e.name.clone(), v)),
+            None if e.kind == Kind::Positional => Err(format!("missing argument {}", e.name.to_uppercase())),
+            None => Err(format!("missing required option --{}", e.name)),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn take_value(entry: &Entry, inline: Option<String>, argv: &[String], i: &mut usize) -> Result<String, String> {
+    if entry.kind == Kind::Flag {
+        return match inline {
+            None => Ok("true".to_string()),
+            Some(_) => Err(format!("flag --{} does not take a value", entry.name)),
+        };
+    }
+    if let Some(v) = inline {
+        return Ok(v);
+    }
+    let v = argv
+        .get(*i)
+        .ok_or_else(|| format!("option --{} requires a value", entry.name))?;
+    *i += 1;
+    Ok(v.clone())
+}
+
+fn usage(entries: &[Entry]) -> String {
+    let mut out = format!("Usage: {}", program());
+    if entries.iter().any(|e| e.kind != Kind::Positional) {
+        out.push_str(" [OPTIONS]");
+    }
+    for e in entries.iter().filter(|e| e.kind == Kind::Positional) {
+        out.push_str(&format!(" {}", e.name.to_uppercase()));
+    }
+
+    let mut rows: Vec<(String, String)> = Vec::new();
+    for e in entries {
+        let left = match &e.kind {
+            Kind::Positional => e.name.to_uppercase(),
+            kind => {
+                let short = e.short.map(|c| format!("-{}, ", c)).unwrap_or_default();
+                let value = if *kind == Kind::Flag { String::new() } else { format!(" <{}>", e.name) };
+                format!("{}--{}{}", short, e.name, value)
+            }
+        };
+        let right = match &e.kind {
+            Kind::Option(Some(d)) => format!("{} [default: {}]", e.help, d),
+            _ => e.help.clone(),
+        };
+        rows.push((left, right));
+    }
+    rows.push(("--help".to_string(), "Print this help".to_string()));
+    let width = rows.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+    out.push_str("\n\n");
+    for (left, right) in rows {
+        out.push_str(&format!("  {:width$}  {}\n", left, right, width = width));
+    }
+    out
+}
+
+/// Look `name` up in a `cli/parse` result.
+pub fn get<'a>(parsed: &'a Value, name: &str) -> Result<&'a str, String> {
+    if let Value::List(pairs) = parsed {
+        for pair in pairs {
+            if let Value::List(kv) = pair
+                && let [Value::String(k), Value::String(v)] = kv.as_slice()
+                && k == name
+            {
+                return Ok(v);
+            }
+        }
+    }
+    Err(format!("cli/get: no argument named {:?}", name))
+}
@@ -10,69 +10,133 @@
 //! There is no support for top-level expressions in AOT mode — they
 //! have nowhere to live without a thunk, and rather than inventing
 //! one we keep the surface simple: write `(defn main [] -> i32 ...)`.
+//!
+//! Runtime faults (integer division by zero / overflow) print a message
+//! to stderr and exit with status 101. Pass a `SourceMap` through
+//! `AotOptions` to get `file:line:col` in those messages; with
+//! `debug_info` set as well, the object also carries DWARF function,
+//! line and parameter info (see `debuginfo.rs`).
+//!
+//! `AotOptions::target` cross-compiles: the module gets that triple and
+//! its data layout, and the object is written by a target machine for
+//! it (with a generic CPU, since the host's says nothing about the
+//! target's). Without one, everything is for the host.
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::path::Path;
 
 use inkwell::OptimizationLevel;
 use inkwell::context::Context;
 use inkwell::targets::{
-    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
 };
 use inkwell::values::FunctionValue;
 
 use crate::ast::Expr;
 
-use super::jit::{JitError, emit_defn};
+use super::debuginfo::DebugCtx;
+use super::jit::{FormCtx, JitError, emit_defn};
+use super::srcmap::{FaultMode, FaultTable, SourceMap};
+
+/// Knobs for the AOT pipeline. `Default` matches `compile_to_ll` /
+/// `compile_to_obj`.
+#[derive(Debug, Clone, Default)]
+pub struct AotOptions {
+    /// Where each form in `forms` came from. Indexed like `forms`.
+    pub source_map: Option<SourceMap>,
+    /// Emit DWARF debug info (`rusp build -g`). Line info comes from
+    /// `source_map`; without one, every function is on line 0.
+    pub debug_info: bool,
+    /// Target triple (`rusp build --target`), e.g.
+    /// `aarch64-unknown-linux-gnu`. None: the host.
+    pub target: Option<String>,
+}
 
 /// Emit LLVM IR (textual `.ll`) for the program. The program must be a
 /// sequence of `defn`s ending with `(defn main [] -> i32 ...)`.
 /// Returns the IR as a string; the caller decides where to write it.
 pub fn compile_to_ll(forms: &[Expr]) -> Result<String, JitError> {
+    compile_to_ll_with(forms, &AotOptions::default())
+}
+
+/// `compile_to_ll` with explicit options.
+pub fn compile_to_ll_with(forms: &[Expr], opts: &AotOptions) -> Result<String, JitError> {
     let context = Context::create();
-    let ir = build_module_ir(&context, forms)?;
+    // Host IR is left without a triple, as before; a target's needs one.
+    let machine = opts.target.as_ref().map(|_| target_machine(opts)).transpose()?;
+    let ir = build_module_ir(&context, forms, opts, machine.as_ref())?;
     Ok(ir)
 }
 
 /// Emit a native object file at `out_path` for the program. Same input
-/// shape as `compile_to_ll`. Uses the host triple and the default
-/// reloc/code models, which is good enough for `cc out.o -o out`.
+/// shape as `compile_to_ll`. Uses the host triple (unless `opts` names
+/// a target) and the default reloc/code models, which is good enough
+/// for `cc out.o -o out`.
 pub fn compile_to_obj(forms: &[Expr], out_path: &Path) -> Result<(), JitError> {
+    compile_to_obj_with(forms, out_path, &AotOptions::default())
+}
+
+/// `compile_to_obj` with explicit options.
+pub fn compile_to_obj_with(
+    forms: &[Expr],
+    out_path: &Path,
+    opts: &AotOptions,
+) -> Result<(), JitError> {
+    let machine = target_machine(opts)?;
     let context = Context::create();
-    let module = build_module(&context, forms)?;
+    let module = build_module(&context, forms, opts, Some(&machine))?;
 
-    // Initialize the native target backend. Cheap if already done.
-    Target::initialize_native(&InitializationConfig::default())
-        .map_err(|e| format!("failed to initialize native target: {}", e))?;
-    let triple = TargetMachine::get_default_triple();
+    machine
+        .write_to_file(&module, FileType::Object, out_path)
+        .map_err(|e| format!("failed to write object file: {}", e))?;
+    Ok(())
+}
+
+/// A target machine for `opts.target`, or for the host.
+fn target_machine(opts: &AotOptions) -> Result<TargetMachine, JitError> {
+    let (triple, cpu, features) = match &opts.target {
+        Some(triple) => {
+            // Every backend this LLVM was built with. Cheap if already done.
+            Target::initialize_all(&InitializationConfig::default());
+            (TargetTriple::create(triple), "generic".to_string(), String::new())
+        }
+        None => {
+            // Initialize the native target backend. Cheap if already done.
+            Target::initialize_native(&InitializationConfig::default())
+                .map_err(|e| format!("failed to initialize native target: {}", e))?;
+            let cpu = TargetMachine::get_host_cpu_name();
+            let features = TargetMachine::get_host_cpu_features();
+            (
+                TargetMachine::get_default_triple(),
+                cpu.to_str().unwrap_or("generic").to_string(),
+                features.to_str().unwrap_or("").to_string(),
+            )
+        }
+    };
     let target = Target::from_triple(&triple)
         .map_err(|e| format!("failed to look up target {}: {}", triple, e))?;
-    let cpu = TargetMachine::get_host_cpu_name();
-    let features = TargetMachine::get_host_cpu_features();
-    let machine = target
+    target
         .create_target_machine(
             &triple,
-            cpu.to_str().unwrap_or("generic"),
-            features.to_str().unwrap_or(""),
+            &cpu,
+            &features,
             OptimizationLevel::Default,
             RelocMode::PIC,
             CodeModel::Default,
         )
-        .ok_or_else(|| format!("failed to create target machine for {}", triple))?;
-
-    machine
-        .write_to_file(&module, FileType::Object, out_path)
-        .map_err(|e| format!("failed to write object file: {}", e))?;
-    Ok(())
+        .ok_or_else(|| format!("failed to create target machine for {}", triple))
 }
 
 /// Shared core: build the LLVM module from a slice of `defn` forms.
 /// Validates that the last form is `(defn main [] -> i32 ...)` and
-/// that no leading form is anything other than a `defn`.
+/// that no leading form is anything other than a `defn`. With a
+/// `machine`, the module is given its triple and data layout.
 fn build_module<'ctx>(
     context: &'ctx Context,
     forms: &[Expr],
+    opts: &AotOptions,
+    machine: Option<&TargetMachine>,
 ) -> Result<inkwell::module::Module<'ctx>, JitError> {
     if forms.is_empty() {
         return Err("--emit: empty program (need at least `(defn main ...)`)".to_string());
@@ -104,17 +168,40 @@ fn build_module<'ctx>(
     }
 
     let module = context.create_module("rusp_aot");
+    if let Some(machine) = machine {
+        module.set_triple(&machine.get_triple());
+        module.set_data_layout(&machine.get_target_data().get_data_layout());
+    }
     let builder = context.create_builder();
     let mut functions: HashMap<String, FunctionValue<'_>> = HashMap::new();
     let lambda_counter: Cell<u32> = Cell::new(0);
-    for f in forms {
-        emit_defn(context, &module, &builder, &mut functions, &lambda_counter, f)?;
+    let fault_table = RefCell::new(FaultTable::default());
+    let debug = opts.debug_info.then(|| {
+        let file = opts.source_map.as_ref().map_or("<unknown>", |m| m.file());
+        DebugCtx::new(context, &module, file)
+    });
+    for (i, f) in forms.iter().enumerate() {
+        let form = FormCtx {
+            faults: &fault_table,
+            fault_mode: FaultMode::Abort,
+            loc: opts.source_map.as_ref().and_then(|m| m.form_loc(i)),
+            debug: debug.as_ref(),
+        };
+        emit_defn(context, &module, &builder, &mut functions, &lambda_counter, &form, f)?;
+    }
+    if let Some(debug) = &debug {
+        debug.finalize();
     }
     Ok(module)
 }
 
 /// `build_module` + render to textual IR.
-fn build_module_ir(context: &Context, forms: &[Expr]) -> Result<String, JitError> {
-    let module = build_module(context, forms)?;
+fn build_module_ir(
+    context: &Context,
+    forms: &[Expr],
+    opts: &AotOptions,
+    machine: Option<&TargetMachine>,
+) -> Result<String, JitError> {
+    let module = build_module(context, forms, opts, machine)?;
     Ok(module.print_to_string().to_string())
 }
@@ -0,0 +1,171 @@
+//! DWARF debug info for AOT builds (`rusp build -g`).
+//!
+//! Like fault locations (`srcmap.rs`), debug info is form-granular: the
+//! AST carries no spans, so every instruction of a `defn` (and of the
+//! lambdas inside it) is attributed to the line where the `defn` form
+//! starts. That's enough for `gdb`/`lldb` backtraces and `perf report`
+//! to name rusp frames and point at the right definition.
+//!
+//! Parameters are spilled to stack slots with `llvm.dbg.declare` so a
+//! debugger can print them; the body keeps using the SSA values, so the
+//! spills are dead stores the optimizer removes at `-O1` and up.
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::debug_info::{
+    AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DIType, DWARFEmissionKind,
+    DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::values::FunctionValue;
+
+use crate::ast::Type;
+
+use super::jit::JitError;
+
+// DW_ATE_* encodings from the DWARF spec.
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+
+/// Per-module debug info state: the DIBuilder plus the compile unit and
+/// file every subprogram hangs off.
+pub(crate) struct DebugCtx<'ctx> {
+    context: &'ctx Context,
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    file: DIFile<'ctx>,
+}
+
+impl<'ctx> DebugCtx<'ctx> {
+    /// Start debug info for `module`, compiled from `path`.
+    pub(crate) fn new(context: &'ctx Context, module: &Module<'ctx>, path: &str) -> Self {
+        let p = std::path::Path::new(path);
+        let filename = p.file_name().and_then(|f| f.to_str()).unwrap_or(path);
+        let directory = match p.parent().and_then(|d| d.to_str()) {
+            Some(d) if !d.is_empty() => d,
+            _ => ".",
+        };
+
+        // Without this flag LLVM silently drops all debug metadata.
+        let version = context.i32_type().const_int(3, false);
+        module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, version);
+        let dwarf = context.i32_type().const_int(4, false);
+        module.add_basic_value_flag("Dwarf Version", FlagBehavior::Warning, dwarf);
+
+        // There's no DWARF language code for Rusp; C is what debuggers
+        // handle best for plain scalar frames.
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            filename,
+            directory,
+            concat!("rusp ", env!("CARGO_PKG_VERSION")),
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let file = unit.get_file();
+        DebugCtx { context, builder, unit, file }
+    }
+
+    /// DWARF type for a Rusp scalar. Functions and lists have no useful
+    /// debugger representation yet, so they get `None` (and parameters
+    /// of those types are simply not described).
+    fn di_type(&self, ty: &Type) -> Option<DIType<'ctx>> {
+        let (name, bits, encoding) = match ty {
+            Type::I32 => ("i32", 32, DW_ATE_SIGNED),
+            Type::I64 => ("i64", 64, DW_ATE_SIGNED),
+            Type::F64 => ("f64", 64, DW_ATE_FLOAT),
+            Type::Bool => ("bool", 8, DW_ATE_BOOLEAN),
+            _ => return None,
+        };
+        self.builder
+            .create_basic_type(name, bits, encoding, DIFlags::ZERO)
+            .ok()
+            .map(|t| t.as_type())
+    }
+
+    /// Attach a subprogram to `function`, point `builder` at `line`
+    /// inside it, and describe its parameters. Must be called with
+    /// `builder` positioned at the end of the function's entry block.
+    pub(crate) fn begin_function(
+        &self,
+        builder: &Builder<'ctx>,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        line: u32,
+        params: &[(String, Type)],
+        return_type: &Type,
+    ) -> Result<(), JitError> {
+        let param_types: Vec<DIType<'ctx>> =
+            params.iter().filter_map(|(_, t)| self.di_type(t)).collect();
+        let subroutine = self.builder.create_subroutine_type(
+            self.file,
+            self.di_type(return_type),
+            &param_types,
+            DIFlags::ZERO,
+        );
+        let linkage = function.get_name().to_str().unwrap_or(name).to_string();
+        let subprogram = self.builder.create_function(
+            self.unit.as_debug_info_scope(),
+            name,
+            Some(&linkage),
+            self.file,
+            line,
+            subroutine,
+            false,
+            true,
+            line,
+            DIFlags::ZERO,
+            false,
+        );
+        function.set_subprogram(subprogram);
+
+        let scope = subprogram.as_debug_info_scope();
+        let loc = self.builder.create_debug_location(self.context, line, 1, scope, None);
+        builder.set_current_debug_location(loc);
+
+        let entry = function
+            .get_first_basic_block()
+            .ok_or_else(|| format!("`{}`: debug info needs an entry block", name))?;
+        for (i, (pname, pty)) in params.iter().enumerate() {
+            let Some(di_ty) = self.di_type(pty) else { continue };
+            let value = function
+                .get_nth_param(i as u32)
+                .ok_or_else(|| format!("`{}`: missing param {}", name, i))?;
+            let slot = builder
+                .build_alloca(value.get_type(), pname)
+                .map_err(|e| format!("LLVM build_alloca failed: {}", e))?;
+            builder
+                .build_store(slot, value)
+                .map_err(|e| format!("LLVM build_store failed: {}", e))?;
+            let var = self.builder.create_parameter_variable(
+                scope,
+                pname,
+                i as u32 + 1,
+                self.file,
+                line,
+                di_ty,
+                true,
+                DIFlags::ZERO,
+            );
+            self.builder
+                .insert_declare_at_end(slot, Some(var), None, loc, entry);
+        }
+        Ok(())
+    }
+
+    /// Resolve all pending debug metadata. Call once after the last
+    /// function is emitted and before the module is verified or written.
+    pub(crate) fn finalize(&self) {
+        self.builder.finalize();
+    }
+}
@@ -1,8 +1,7 @@
 //! Minimal JIT entry point for Steps 2–8: parse → type-check → codegen → run.
 //!
 //! Supports:
-//! - i32/i64 literals and integer arithmetic (`+`, `-`, `*`, `/`)
-//! - f64 literals and float arithmetic (`+.`, `-.`, `*.`, `/.`)
+//! - i32/i64/f64 literals and arithmetic (`+`, `-`, `*`, `/`) on each
 //! - bool literals
 //! - comparison (`=`, `<`, `>`, `<=`, `>=`) on i32, i64, and f64
 //! - `if` (with phi-merge)
@@ -19,11 +18,17 @@
 //! in an anonymous thunk (`__expr() -> T`), JITs it via `ExecutionEngine`,
 //! and looks the function up by name. Per-call Context keeps lifetimes
 //! simple and tests well isolated.
+//!
+//! Integer division is guarded against the two inputs that make `sdiv`
+//! trap (zero divisor, `MIN / -1`); a guarded fault comes back as an
+//! `Err` naming the function and sub-expression (see `srcmap.rs`).
 
 use inkwell::OptimizationLevel;
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
 use inkwell::execution_engine::ExecutionEngine;
+use inkwell::intrinsics::Intrinsic;
 use inkwell::module::Module;
 use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum};
 use inkwell::values::{
@@ -31,11 +36,14 @@ use inkwell::values::{
 };
 use inkwell::{FloatPredicate, IntPredicate};
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
 use crate::ast::{Expr, Type};
-use crate::types::{type_check, TypeEnv};
+use crate::types::{ascribe_lambda, type_check, TypeEnv};
+
+use super::debuginfo::DebugCtx;
+use super::srcmap::{FaultKind, FaultMode, FaultSite, FaultTable, SourceLoc};
 
 pub type JitError = String;
 
@@ -128,36 +136,111 @@ fn split_program(forms: &[Expr]) -> Result<(Vec<&Expr>, &Expr), JitError> {
     Ok((defns, last))
 }
 
+/// Per-form emission context: fault bookkeeping shared by every function
+/// in the module, plus where the top-level form being emitted came from.
+/// The driver hands out one `FormCtx` per form.
+#[derive(Clone)]
+pub(crate) struct FormCtx<'a, 'ctx> {
+    pub(crate) faults: &'a RefCell<FaultTable>,
+    pub(crate) fault_mode: FaultMode,
+    pub(crate) loc: Option<SourceLoc>,
+    /// Set when emitting DWARF (`rusp build -g`).
+    pub(crate) debug: Option<&'a DebugCtx<'ctx>>,
+}
+
+impl FormCtx<'_, '_> {
+    /// Line the form starts on, for debug info. `0` means "unknown".
+    fn line(&self) -> u32 {
+        self.loc.as_ref().map_or(0, |l| l.line as u32)
+    }
+}
+
+/// Name of the module global holding the id of the first fault hit
+/// (`0` = none). Only present in `FaultMode::Propagate` modules.
+pub(crate) const FAULT_GLOBAL: &str = "__rusp_fault";
+
+/// Add the zero-initialized `__rusp_fault` global to `module`.
+pub(crate) fn declare_fault_global<'ctx>(context: &'ctx Context, module: &Module<'ctx>) {
+    let i32_t = context.i32_type();
+    let global = module.add_global(i32_t, None, FAULT_GLOBAL);
+    global.set_initializer(&i32_t.const_zero());
+}
+
+/// Emit `__rusp_fault_site() -> i32`, the accessor the host calls after
+/// running compiled code to learn whether a guarded fault cut it short.
+pub(crate) fn emit_fault_getter<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+) -> Result<(), JitError> {
+    let i32_t = context.i32_type();
+    let getter = module.add_function("__rusp_fault_site", i32_t.fn_type(&[], false), None);
+    builder.position_at_end(context.append_basic_block(getter, "entry"));
+    let fault_ptr = module
+        .get_global(FAULT_GLOBAL)
+        .ok_or("internal: fault global missing from JIT module")?
+        .as_pointer_value();
+    let site = builder
+        .build_load(i32_t, fault_ptr, "fault")
+        .map_err(|e| format!("LLVM build_load failed: {}", e))?;
+    builder
+        .build_return(Some(&site))
+        .map(|_| ())
+        .map_err(|e| format!("LLVM build_return failed: {}", e))
+}
+
+/// Add the function for the `defn` `expr` to `module`, without a body.
+/// Declared ahead of emitting, it lets `defn`s call each other whatever
+/// order they're emitted in; `emit_defn` fills the body in.
+pub(crate) fn declare_defn<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    expr: &Expr,
+) -> Result<FunctionValue<'ctx>, JitError> {
+    let Expr::Defn { name, params, return_type, .. } = expr else {
+        return Err("declare_defn called with non-Defn".to_string());
+    };
+    let param_tys: Vec<BasicMetadataTypeEnum> = params
+        .iter()
+        .map(|(_, ty)| -> Result<BasicMetadataTypeEnum, JitError> {
+            Ok(type_to_basic(context, ty)?.into())
+        })
+        .collect::<Result<_, _>>()?;
+    let fn_t = type_to_basic(context, return_type)?.fn_type(&param_tys, false);
+    Ok(module.add_function(name, fn_t, None))
+}
+
 /// Emit a `defn` as a real LLVM function in `module`. Registers the
 /// function value in `functions` *before* emitting the body so
-/// recursive calls resolve. Parameters become entries in the body's
-/// `env`. The body must produce a value matching the declared return
-/// type.
+/// recursive calls resolve; a bodiless declaration from `declare_defn`
+/// is filled in rather than added again. Parameters become entries in
+/// the body's `env`. The body must produce a value matching the
+/// declared return type.
 pub(crate) fn emit_defn<'ctx>(
     context: &'ctx Context,
     module: &Module<'ctx>,
     builder: &Builder<'ctx>,
     functions: &mut HashMap<String, FunctionValue<'ctx>>,
     lambda_counter: &Cell<u32>,
+    form: &FormCtx<'_, 'ctx>,
     expr: &Expr,
 ) -> Result<(), JitError> {
-    let Expr::Defn { name, params, return_type, body } = expr else {
+    let Expr::Defn { name, params, return_type, body, .. } = expr else {
         return Err("emit_defn called with non-Defn".to_string());
     };
 
-    let param_tys: Vec<BasicMetadataTypeEnum> = params
-        .iter()
-        .map(|(_, ty)| -> Result<BasicMetadataTypeEnum, JitError> {
-            Ok(type_to_basic(context, ty)?.into())
-        })
-        .collect::<Result<_, _>>()?;
     let ret_basic = type_to_basic(context, return_type)?;
-    let fn_t = ret_basic.fn_type(&param_tys, false);
-    let function = module.add_function(name, fn_t, None);
+    let function = match functions.get(name) {
+        Some(declared) if declared.count_basic_blocks() == 0 => *declared,
+        _ => declare_defn(context, module, expr)?,
+    };
     functions.insert(name.clone(), function);
 
     let entry = context.append_basic_block(function, "entry");
     builder.position_at_end(entry);
+    if let Some(debug) = form.debug {
+        debug.begin_function(builder, function, name, form.line(), params, return_type)?;
+    }
 
     // Bind params into the env.
     let mut env: HashMap<String, EmitVal<'ctx>> = HashMap::new();
@@ -173,9 +256,12 @@ pub(crate) fn emit_defn<'ctx>(
         module,
         builder,
         function,
+        fn_label: name.clone(),
         env,
         functions: functions.clone(),
         lambda_counter,
+        form: form.clone(),
+        returned: false,
     };
     let body_val = cg.emit(body)?;
 
@@ -220,18 +306,40 @@ pub(crate) fn emit_defn<'ctx>(
 
 /// Map a Rusp `Type` to the LLVM basic type used for a function
 /// parameter or return slot. Only scalar Rusp types are supported.
-fn type_to_basic<'ctx>(context: &'ctx Context, ty: &Type) -> Result<BasicTypeEnum<'ctx>, JitError> {
+pub(crate) fn type_to_basic<'ctx>(context: &'ctx Context, ty: &Type) -> Result<BasicTypeEnum<'ctx>, JitError> {
     Ok(match ty {
         Type::I32 => context.i32_type().into(),
         Type::I64 => context.i64_type().into(),
         Type::Bool => context.bool_type().into(),
         Type::F64 => context.f64_type().into(),
-        Type::String => return Err("--llvm: String type is not supported by the MVP".to_string()),
+        Type::String
+        | Type::StringBuilder
+        | Type::Bytes
+        | Type::DateTime
+        | Type::ZonedDateTime
+        | Type::Duration
+        | Type::KvStore
+        | Type::Matrix
+        | Type::Keyword
+        | Type::Port
+        | Type::Type
+        | Type::Unit
+        | Type::Foreign(_)
+        | Type::Named(_) => {
+            return Err(format!("--llvm: {} type is not supported by the MVP", ty));
+        }
         Type::List(_) => return Err("--llvm: List type is not supported by the MVP".to_string()),
-        Type::Function { .. } => {
+        Type::Ref(_) => return Err("--llvm: Ref type is not supported by the MVP".to_string()),
+        Type::Gen(_) => return Err("--llvm: Gen type is not supported by the MVP".to_string()),
+        Type::Pair(..) => return Err("--llvm: Pair type is not supported by the MVP".to_string()),
+        Type::Array(..) => return Err("--llvm: Array type is not supported by the MVP".to_string()),
+        Type::Map(..) => return Err("--llvm: Map type is not supported by the MVP".to_string()),
+        Type::Vector(_) => return Err("--llvm: Vec type is not supported by the MVP".to_string()),
+        Type::Slice(_) => return Err("--llvm: Slice type is not supported by the MVP".to_string()),
+        Type::Function { .. } | Type::MultiArity(_) => {
             return Err("--llvm: first-class function types are not supported by the MVP".to_string());
         }
-        Type::Inferred => {
+        Type::Inferred | Type::Var(_) | Type::Generic(_) => {
             return Err(
                 "--llvm: inferred type leaked to codegen — type checker should have resolved it"
                     .to_string(),
@@ -310,8 +418,16 @@ fn compile_program_and_run(
     // recursive self-calls resolve.
     let mut functions: HashMap<String, FunctionValue<'_>> = HashMap::new();
     let lambda_counter: Cell<u32> = Cell::new(0);
+    let fault_table = RefCell::new(FaultTable::default());
+    let form = FormCtx {
+        faults: &fault_table,
+        fault_mode: FaultMode::Propagate,
+        loc: None,
+        debug: None,
+    };
+    declare_fault_global(context, &module);
     for d in &defns {
-        emit_defn(context, &module, &builder, &mut functions, &lambda_counter, d)?;
+        emit_defn(context, &module, &builder, &mut functions, &lambda_counter, &form, d)?;
     }
 
     // Now emit the top-level thunk for the final expression.
@@ -331,9 +447,12 @@ fn compile_program_and_run(
         module: &module,
         builder: &builder,
         function,
+        fn_label: "<top-level>".to_string(),
         env: HashMap::new(),
         functions,
         lambda_counter: &lambda_counter,
+        form: form.clone(),
+        returned: false,
     };
     let value = cg.emit(expr)?;
 
@@ -365,6 +484,8 @@ fn compile_program_and_run(
         }
     };
 
+    emit_fault_getter(context, &module, &builder)?;
+
     let engine: ExecutionEngine = module
         .create_jit_execution_engine(OptimizationLevel::None)
         .map_err(|e| format!("failed to create JIT execution engine: {}", e))?;
@@ -400,6 +521,21 @@ fn compile_program_and_run(
             }
         }
     };
+
+    // SAFETY: `__rusp_fault_site` was emitted above as `() -> i32`.
+    let site = unsafe {
+        engine
+            .get_function::<unsafe extern "C" fn() -> i32>("__rusp_fault_site")
+            .map_err(|e| format!("failed to look up __rusp_fault_site: {}", e))?
+            .call()
+    };
+    if site != 0 {
+        let table = fault_table.borrow();
+        return Err(match table.get(site as u32) {
+            Some(s) => s.message(),
+            None => format!("runtime error: unknown fault site {}", site),
+        });
+    }
     Ok(result)
 }
 
@@ -475,6 +611,9 @@ struct ExprCg<'ctx, 'a> {
     module: &'a Module<'ctx>,
     builder: &'a Builder<'ctx>,
     function: FunctionValue<'ctx>,
+    /// Human-readable name of `function` for fault messages: the `defn`
+    /// name, `<lambda>`, or `<top-level>`.
+    fn_label: String,
     /// Lexical environment for `let`-bound names and function
     /// parameters. Flat (no parent chain): rebinding via shadowing is
     /// implemented by snapshotting the displaced entry and restoring
@@ -488,6 +627,12 @@ struct ExprCg<'ctx, 'a> {
     /// Shared counter for `__lambda_N` name generation. A `Cell` so
     /// nested emits can bump it without re-borrowing `&mut self`.
     lambda_counter: &'a Cell<u32>,
+    /// Where trapping regions get registered, how they fail, and which
+    /// form (and debug scope) the code belongs to.
+    form: FormCtx<'a, 'ctx>,
+    /// Set once a `(return v)` has emitted its `ret`: the builder now sits
+    /// in a block nothing branches to. `emit_if` clears it per arm.
+    returned: bool,
 }
 
 impl<'ctx, 'a> ExprCg<'ctx, 'a> {
@@ -518,7 +663,8 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
             // `let-in`: bind value, emit body in extended env, restore.
             // Top-level `let` (no body) doesn't make sense for a
             // single-thunk JIT and is rejected here.
-            Expr::Let { name, value, body, .. } => self.emit_let(name, value, body.as_deref()),
+            Expr::Let { name, type_ann, value, body } => self.emit_let(name, type_ann.as_ref(), value, body),
+            Expr::Def { .. } => Err("--llvm: `def` / `defconst` is not supported in JIT mode".to_string()),
 
             // `(fn [params] -> ret body)` — a capture-free anonymous
             // function. We emit it as a real LLVM function with a
@@ -535,6 +681,19 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
                 self.emit_if(condition, then_branch, else_branch)
             }
 
+            Expr::Return(value) => self.emit_return(value),
+
+            // `(the T e)` was settled by the checker; only an i32 going
+            // to i64 needs an instruction.
+            Expr::The { ty, expr } => match self.emit(expr)? {
+                EmitVal::Int(iv) if *ty == Type::I64 && iv.get_type().get_bit_width() == 32 => self
+                    .builder
+                    .build_int_s_extend(iv, self.context.i64_type(), "thetmp")
+                    .map(EmitVal::Int)
+                    .map_err(|e| format!("LLVM build_int_s_extend failed: {}", e)),
+                other => Ok(other),
+            },
+
             // S-expression forms `(head a b ...)` parse as `Expr::List`.
             // The head determines what to do: built-in operators get
             // dedicated codegen; anything else is treated as a user-
@@ -545,12 +704,12 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
                 if let Expr::Symbol(op) = &exprs[0] {
                     let args = &exprs[1..];
                     match op.as_str() {
-                        "+" | "-" | "*" | "/" => self.gen_int_arith(op, args),
-                        "+." | "-." | "*." | "/." => self.gen_float_arith(op, args),
+                        "+" | "-" | "*" | "/" => self.gen_arith(op, args),
                         "=" | "<" | ">" | "<=" | ">=" => self.gen_cmp(op, args),
                         "and" => self.gen_and(args),
                         "or" => self.gen_or(args),
                         "not" => self.gen_not(args),
+                        "neg" => self.gen_neg(args),
                         // Fall through to user-function call.
                         _ => self.emit_user_call(op, args),
                     }
@@ -573,10 +732,54 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
         }
     }
 
-    /// Generate `(op arg0 arg1 ...)` for binary integer arithmetic.
-    /// Variadic in source (`(+ 1 2 3)`) is left-folded. Width is taken
-    /// from the first operand.
-    fn gen_int_arith(&mut self, op: &str, args: &[Expr]) -> Result<EmitVal<'ctx>, JitError> {
+    /// End an `if` arm: jump to the merge block, unless the arm `return`ed
+    /// and is left in a dead block.
+    fn branch_to_merge(&self, returned: bool, merge_bb: BasicBlock<'ctx>) -> Result<(), JitError> {
+        if returned {
+            self.builder
+                .build_unreachable()
+                .map(|_| ())
+                .map_err(|e| format!("LLVM build_unreachable failed: {}", e))
+        } else {
+            self.builder
+                .build_unconditional_branch(merge_bb)
+                .map(|_| ())
+                .map_err(|e| format!("LLVM build_unconditional_branch failed: {}", e))
+        }
+    }
+
+    /// `(return v)`: `ret v` on the spot. Whatever the enclosing forms emit
+    /// afterwards goes into a fresh block nothing branches to, with `v`
+    /// standing in for the value `(return v)` never has.
+    fn emit_return(&mut self, value: &Expr) -> Result<EmitVal<'ctx>, JitError> {
+        if self.fn_label == "<top-level>" {
+            return Err("--llvm: `return` is only allowed inside defn or fn".to_string());
+        }
+        let v = self.emit(value)?;
+        let basic = v
+            .as_basic_value_enum()
+            .map_err(|e| format!("`return` in {}: {}", self.fn_label, e))?;
+        if self.function.get_type().get_return_type() != Some(basic.get_type()) {
+            return Err(format!(
+                "`return` in {}: value type {} doesn't match the return type",
+                self.fn_label,
+                v.type_name()
+            ));
+        }
+        self.builder
+            .build_return(Some(&basic as &dyn BasicValue))
+            .map_err(|e| format!("LLVM build_return failed: {}", e))?;
+        let dead = self.context.append_basic_block(self.function, "afterret");
+        self.builder.position_at_end(dead);
+        self.returned = true;
+        Ok(v)
+    }
+
+    /// Generate `(op arg0 arg1 ...)` for binary arithmetic. Variadic in
+    /// source (`(+ 1 2 3)`) is left-folded. The checker has made every
+    /// operand the same kind of number, so the first one picks integer or
+    /// float codegen.
+    fn gen_arith(&mut self, op: &str, args: &[Expr]) -> Result<EmitVal<'ctx>, JitError> {
         if args.len() < 2 {
             return Err(format!(
                 "operator `{}` requires at least 2 arguments, got {}",
@@ -585,8 +788,18 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
             ));
         }
         let first = self.emit(&args[0])?;
+        match first {
+            EmitVal::Float(_) => self.gen_float_arith(op, first, args),
+            _ => self.gen_int_arith(op, first, args),
+        }
+    }
+
+    /// Integer arithmetic after `gen_arith` has emitted the first operand.
+    /// Width is taken from the first operand.
+    fn gen_int_arith(&mut self, op: &str, first: EmitVal<'ctx>, args: &[Expr]) -> Result<EmitVal<'ctx>, JitError> {
         let mut acc = self.expect_int(&first, op)?;
         let acc_width = acc.get_type().get_bit_width();
+        let rendered = render_form(op, args);
         if acc_width != 32 && acc_width != 64 {
             return Err(format!(
                 "operator `{}` requires i32/i64 operands, got i{}",
@@ -596,69 +809,44 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
         for arg in &args[1..] {
             let rhs_v = self.emit(arg)?;
             let rhs = self.expect_int(&rhs_v, op)?;
-            if rhs.get_type().get_bit_width() != acc_width {
-                return Err(format!(
-                    "operator `{}`: operand width mismatch (i{} vs i{}) — \
-                     this is a codegen invariant that the type checker should have caught",
-                    op,
-                    acc_width,
-                    rhs.get_type().get_bit_width()
-                ));
-            }
+            let (lhs, rhs) = self.widen_ints(acc, rhs)?;
             acc = match op {
-                "+" => self
-                    .builder
-                    .build_int_add(acc, rhs, "addtmp")
-                    .map_err(|e| format!("LLVM build_int_add failed: {}", e))?,
-                "-" => self
-                    .builder
-                    .build_int_sub(acc, rhs, "subtmp")
-                    .map_err(|e| format!("LLVM build_int_sub failed: {}", e))?,
-                "*" => self
-                    .builder
-                    .build_int_mul(acc, rhs, "multmp")
-                    .map_err(|e| format!("LLVM build_int_mul failed: {}", e))?,
-                "/" => self
-                    .builder
-                    .build_int_signed_div(acc, rhs, "divtmp")
-                    .map_err(|e| format!("LLVM build_int_signed_div failed: {}", e))?,
+                "+" => self.checked_int_op("llvm.sadd.with.overflow", lhs, rhs, &rendered)?,
+                "-" => self.checked_int_op("llvm.ssub.with.overflow", lhs, rhs, &rendered)?,
+                "*" => self.checked_int_op("llvm.smul.with.overflow", lhs, rhs, &rendered)?,
+                "/" => {
+                    self.guard_division(lhs, rhs, &rendered)?;
+                    self.builder
+                        .build_int_signed_div(lhs, rhs, "divtmp")
+                        .map_err(|e| format!("LLVM build_int_signed_div failed: {}", e))?
+                }
                 _ => unreachable!("operator dispatch checked already"),
             };
         }
         Ok(EmitVal::Int(acc))
     }
 
-    /// Generate `(op. arg0 arg1 ...)` for binary float arithmetic.
-    /// Mirrors `gen_int_arith`. Rusp's float ops are syntactically
-    /// distinct from int ones (`+.` vs `+`), so the type checker has
-    /// already guaranteed every operand is f64.
-    fn gen_float_arith(&mut self, op: &str, args: &[Expr]) -> Result<EmitVal<'ctx>, JitError> {
-        if args.len() < 2 {
-            return Err(format!(
-                "operator `{}` requires at least 2 arguments, got {}",
-                op,
-                args.len()
-            ));
-        }
-        let first = self.emit(&args[0])?;
+    /// Float arithmetic after `gen_arith` has emitted the first operand.
+    /// Mirrors `gen_int_arith`.
+    fn gen_float_arith(&mut self, op: &str, first: EmitVal<'ctx>, args: &[Expr]) -> Result<EmitVal<'ctx>, JitError> {
         let mut acc = self.expect_float(&first, op)?;
         for arg in &args[1..] {
             let rhs_v = self.emit(arg)?;
             let rhs = self.expect_float(&rhs_v, op)?;
             acc = match op {
-                "+." => self
+                "+" => self
                     .builder
                     .build_float_add(acc, rhs, "faddtmp")
                     .map_err(|e| format!("LLVM build_float_add failed: {}", e))?,
-                "-." => self
+                "-" => self
                     .builder
                     .build_float_sub(acc, rhs, "fsubtmp")
                     .map_err(|e| format!("LLVM build_float_sub failed: {}", e))?,
-                "*." => self
+                "*" => self
                     .builder
                     .build_float_mul(acc, rhs, "fmultmp")
                     .map_err(|e| format!("LLVM build_float_mul failed: {}", e))?,
-                "/." => self
+                "/" => self
                     .builder
                     .build_float_div(acc, rhs, "fdivtmp")
                     .map_err(|e| format!("LLVM build_float_div failed: {}", e))?,
@@ -668,31 +856,38 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
         Ok(EmitVal::Float(acc))
     }
 
-    /// Generate a binary comparison `(op a b)` → `i1`.
-    /// Dispatches on the LHS's value kind: integers use signed
-    /// predicates, floats use ordered predicates (OEQ/OLT/...).
-    /// "Ordered" means NaN compares false, matching the interpreter's
-    /// IEEE-754 semantics.
+    /// Generate a comparison `(op a b ...)` → `i1`: every operand is
+    /// emitted once, then each neighbouring pair is compared and the
+    /// results are and-ed together.
     fn gen_cmp(&mut self, op: &str, args: &[Expr]) -> Result<EmitVal<'ctx>, JitError> {
-        if args.len() != 2 {
+        if args.len() < 2 {
             return Err(format!(
-                "comparison `{}` requires exactly 2 arguments, got {}",
+                "comparison `{}` requires at least 2 arguments, got {}",
                 op,
                 args.len()
             ));
         }
-        let lhs = self.emit(&args[0])?;
-        let rhs = self.emit(&args[1])?;
+        let operands = args.iter().map(|a| self.emit(a)).collect::<Result<Vec<_>, _>>()?;
+        let mut acc = self.compare(op, operands[0], operands[1])?;
+        for pair in operands[1..].windows(2) {
+            let next = self.compare(op, pair[0], pair[1])?;
+            acc = self
+                .builder
+                .build_and(acc, next, "cmpchain")
+                .map_err(|e| format!("LLVM build_and failed: {}", e))?;
+        }
+        Ok(EmitVal::Int(acc))
+    }
+
+    /// One comparison `(op lhs rhs)`. Dispatches on the LHS's value kind:
+    /// integers use signed predicates, floats use ordered predicates
+    /// (OEQ/OLT/...). "Ordered" means NaN compares false, matching the
+    /// interpreter's IEEE-754 semantics.
+    fn compare(&self, op: &str, lhs: EmitVal<'ctx>, rhs: EmitVal<'ctx>) -> Result<IntValue<'ctx>, JitError> {
         match (lhs, rhs) {
             (EmitVal::Int(l), EmitVal::Int(r)) => {
+                let (l, r) = self.widen_ints(l, r)?;
                 let lw = l.get_type().get_bit_width();
-                let rw = r.get_type().get_bit_width();
-                if lw != rw {
-                    return Err(format!(
-                        "comparison `{}`: operand width mismatch (i{} vs i{})",
-                        op, lw, rw
-                    ));
-                }
                 if lw != 32 && lw != 64 {
                     return Err(format!(
                         "comparison `{}` only supports i32/i64 integer operands, got i{}",
@@ -711,7 +906,7 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
                     .builder
                     .build_int_compare(pred, l, r, "cmptmp")
                     .map_err(|e| format!("LLVM build_int_compare failed: {}", e))?;
-                Ok(EmitVal::Int(r))
+                Ok(r)
             }
             (EmitVal::Float(l), EmitVal::Float(r)) => {
                 let pred = match op {
@@ -726,7 +921,7 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
                     .builder
                     .build_float_compare(pred, l, r, "fcmptmp")
                     .map_err(|e| format!("LLVM build_float_compare failed: {}", e))?;
-                Ok(EmitVal::Int(r))
+                Ok(r)
             }
             (l, r) => Err(format!(
                 "comparison `{}`: cannot compare {} with {}",
@@ -763,21 +958,29 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
             .build_conditional_branch(cond_int, then_bb, else_bb)
             .map_err(|e| format!("LLVM build_conditional_branch failed: {}", e))?;
 
+        let outer_returned = std::mem::take(&mut self.returned);
+
         // then arm
         self.builder.position_at_end(then_bb);
         let then_v = self.emit(then_e)?;
         let then_end = self.builder.get_insert_block().expect("then arm has insert block");
-        self.builder
-            .build_unconditional_branch(merge_bb)
-            .map_err(|e| format!("LLVM build_unconditional_branch failed: {}", e))?;
+        let then_returned = std::mem::take(&mut self.returned);
+        self.branch_to_merge(then_returned, merge_bb)?;
 
         // else arm
         self.builder.position_at_end(else_bb);
         let else_v = self.emit(else_e)?;
         let else_end = self.builder.get_insert_block().expect("else arm has insert block");
-        self.builder
-            .build_unconditional_branch(merge_bb)
-            .map_err(|e| format!("LLVM build_unconditional_branch failed: {}", e))?;
+        let else_returned = std::mem::take(&mut self.returned);
+        self.branch_to_merge(else_returned, merge_bb)?;
+
+        // An arm that `return`ed never reaches the merge, so the value is
+        // the other arm's (and if both did, the merge is dead too).
+        self.returned = outer_returned || (then_returned && else_returned);
+        if then_returned || else_returned {
+            self.builder.position_at_end(merge_bb);
+            return Ok(if then_returned { else_v } else { then_v });
+        }
 
         // Both arms must produce the same kind. The type checker enforces
         // this; check defensively. FuncRef can't go through phi (no
@@ -869,26 +1072,223 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
             .try_as_basic_value()
             .basic()
             .ok_or_else(|| format!("--llvm: `{}` returns void; can't use as a value", name))?;
+        self.bail_if_faulted()?;
         basic_to_emit(ret)
     }
 
+    /// `lhs op rhs` through one of LLVM's `llvm.s*.with.overflow`
+    /// intrinsics, trapping when the result doesn't fit. The interpreter
+    /// reports overflow as an error rather than wrapping, so compiled code
+    /// has to as well or a tiered function's results would change partway
+    /// through a run.
+    fn checked_int_op(
+        &mut self,
+        intrinsic: &str,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        rendered: &str,
+    ) -> Result<IntValue<'ctx>, JitError> {
+        let declaration = Intrinsic::find(intrinsic)
+            .and_then(|i| i.get_declaration(self.module, &[lhs.get_type().into()]))
+            .ok_or_else(|| format!("LLVM intrinsic {} is unavailable", intrinsic))?;
+        let pair = self
+            .builder
+            .build_call(declaration, &[lhs.into(), rhs.into()], "ovftmp")
+            .map_err(|e| format!("LLVM build_call failed: {}", e))?
+            .try_as_basic_value()
+            .basic()
+            .ok_or_else(|| format!("LLVM intrinsic {} returned void", intrinsic))?
+            .into_struct_value();
+        let result = self
+            .builder
+            .build_extract_value(pair, 0, "result")
+            .map_err(|e| format!("LLVM build_extract_value failed: {}", e))?
+            .into_int_value();
+        let overflow = self
+            .builder
+            .build_extract_value(pair, 1, "overflow")
+            .map_err(|e| format!("LLVM build_extract_value failed: {}", e))?
+            .into_int_value();
+        self.trap_if(overflow, FaultKind::Overflow, rendered)?;
+        Ok(result)
+    }
+
+    /// Guard `lhs / rhs` against the inputs `sdiv` traps on: a zero
+    /// divisor, and `MIN / -1` whose quotient overflows.
+    fn guard_division(
+        &mut self,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        rendered: &str,
+    ) -> Result<(), JitError> {
+        let ty = rhs.get_type();
+        let is_zero = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs, ty.const_zero(), "divzero")
+            .map_err(|e| format!("LLVM build_int_compare failed: {}", e))?;
+        self.trap_if(is_zero, FaultKind::DivisionByZero, rendered)?;
+
+        let min = ty.const_int(1u64 << (ty.get_bit_width() - 1), false);
+        let lhs_min = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, lhs, min, "lhsmin")
+            .map_err(|e| format!("LLVM build_int_compare failed: {}", e))?;
+        let rhs_neg1 = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs, ty.const_all_ones(), "rhsneg1")
+            .map_err(|e| format!("LLVM build_int_compare failed: {}", e))?;
+        let overflow = self
+            .builder
+            .build_and(lhs_min, rhs_neg1, "divovf")
+            .map_err(|e| format!("LLVM build_and failed: {}", e))?;
+        self.trap_if(overflow, FaultKind::DivisionOverflow, rendered)
+    }
+
+    /// Branch to a fault block when `cond` holds; continue emitting in a
+    /// fresh block otherwise. The site is registered with the enclosing
+    /// function and form location so the fault can be explained later.
+    fn trap_if(
+        &mut self,
+        cond: IntValue<'ctx>,
+        kind: FaultKind,
+        rendered: &str,
+    ) -> Result<(), JitError> {
+        let site = FaultSite {
+            kind,
+            function: self.fn_label.clone(),
+            loc: self.form.loc.clone(),
+            expr: rendered.to_string(),
+        };
+        let message = site.message();
+        let id = self.form.faults.borrow_mut().register(site);
+
+        let fault_bb = self.context.append_basic_block(self.function, "fault");
+        let cont_bb = self.context.append_basic_block(self.function, "nofault");
+        self.builder
+            .build_conditional_branch(cond, fault_bb, cont_bb)
+            .map_err(|e| format!("LLVM build_conditional_branch failed: {}", e))?;
+
+        self.builder.position_at_end(fault_bb);
+        match self.form.fault_mode {
+            FaultMode::Propagate => {
+                let fault_ptr = self.fault_global()?;
+                self.builder
+                    .build_store(fault_ptr, self.context.i32_type().const_int(u64::from(id), false))
+                    .map_err(|e| format!("LLVM build_store failed: {}", e))?;
+                self.return_zero()?;
+            }
+            FaultMode::Abort => self.emit_abort(&message)?,
+        }
+
+        self.builder.position_at_end(cont_bb);
+        Ok(())
+    }
+
+    /// After a call in a `Propagate` module: if the callee recorded a
+    /// fault, return immediately so it unwinds to the host.
+    fn bail_if_faulted(&mut self) -> Result<(), JitError> {
+        if self.form.fault_mode != FaultMode::Propagate {
+            return Ok(());
+        }
+        let i32_t = self.context.i32_type();
+        let fault_ptr = self.fault_global()?;
+        let current = self
+            .builder
+            .build_load(i32_t, fault_ptr, "fault")
+            .map_err(|e| format!("LLVM build_load failed: {}", e))?
+            .into_int_value();
+        let faulted = self
+            .builder
+            .build_int_compare(IntPredicate::NE, current, i32_t.const_zero(), "faulted")
+            .map_err(|e| format!("LLVM build_int_compare failed: {}", e))?;
+
+        let bail_bb = self.context.append_basic_block(self.function, "bail");
+        let cont_bb = self.context.append_basic_block(self.function, "callcont");
+        self.builder
+            .build_conditional_branch(faulted, bail_bb, cont_bb)
+            .map_err(|e| format!("LLVM build_conditional_branch failed: {}", e))?;
+        self.builder.position_at_end(bail_bb);
+        self.return_zero()?;
+        self.builder.position_at_end(cont_bb);
+        Ok(())
+    }
+
+    fn fault_global(&self) -> Result<inkwell::values::PointerValue<'ctx>, JitError> {
+        self.module
+            .get_global(FAULT_GLOBAL)
+            .map(|g| g.as_pointer_value())
+            .ok_or_else(|| "internal: fault global missing from JIT module".to_string())
+    }
+
+    /// `ret <zero>` of the enclosing function's return type. The value is
+    /// never observed: the host (or a bailing caller) sees the fault first.
+    fn return_zero(&mut self) -> Result<(), JitError> {
+        let ret_t = self
+            .function
+            .get_type()
+            .get_return_type()
+            .ok_or_else(|| format!("internal: `{}` has no return type", self.fn_label))?;
+        self.builder
+            .build_return(Some(&ret_t.const_zero()))
+            .map(|_| ())
+            .map_err(|e| format!("LLVM build_return failed: {}", e))
+    }
+
+    /// AOT fault block: `write(2, msg, len); exit(101); unreachable`.
+    fn emit_abort(&mut self, message: &str) -> Result<(), JitError> {
+        let text = format!("{}\n", message);
+        let i32_t = self.context.i32_type();
+        let i64_t = self.context.i64_type();
+        let ptr_t = self.context.ptr_type(inkwell::AddressSpace::default());
+        let write = self.module.get_function("write").unwrap_or_else(|| {
+            let t = i64_t.fn_type(&[i32_t.into(), ptr_t.into(), i64_t.into()], false);
+            self.module.add_function("write", t, None)
+        });
+        let exit = self.module.get_function("exit").unwrap_or_else(|| {
+            let t = self.context.void_type().fn_type(&[i32_t.into()], false);
+            self.module.add_function("exit", t, None)
+        });
+        let msg = self
+            .builder
+            .build_global_string_ptr(&text, "fault_msg")
+            .map_err(|e| format!("LLVM build_global_string_ptr failed: {}", e))?;
+        self.builder
+            .build_call(
+                write,
+                &[
+                    i32_t.const_int(2, false).into(),
+                    msg.as_pointer_value().into(),
+                    i64_t.const_int(text.len() as u64, false).into(),
+                ],
+                "",
+            )
+            .map_err(|e| format!("LLVM build_call failed: {}", e))?;
+        self.builder
+            .build_call(exit, &[i32_t.const_int(101, false).into()], "")
+            .map_err(|e| format!("LLVM build_call failed: {}", e))?;
+        self.builder
+            .build_unreachable()
+            .map(|_| ())
+            .map_err(|e| format!("LLVM build_unreachable failed: {}", e))
+    }
+
     /// `(let name value body)` — let-in. Bind `name` to the value of
     /// `value`, emit `body` with that binding visible, then restore the
     /// previous binding (or remove it). Top-level `let` (no body) is
     /// rejected here because the JIT compiles a single expression.
     ///
     /// Shadowing is supported: if `name` already exists, save the old
-    /// value before inserting and put it back after the body.
+    /// value before inserting and put it back after the body. A `fn`
+    /// bound under a function type takes its parameter types from it.
     fn emit_let(
         &mut self,
         name: &str,
+        type_ann: Option<&Type>,
         value: &Expr,
-        body: Option<&Expr>,
+        body: &Expr,
     ) -> Result<EmitVal<'ctx>, JitError> {
-        let body = body.ok_or_else(|| {
-            "--llvm: top-level `let` (without body) is not supported in JIT mode".to_string()
-        })?;
-        let val = self.emit(value)?;
+        let ascribed = type_ann.and_then(|ann| ascribe_lambda(value, ann));
+        let val = self.emit(ascribed.as_ref().unwrap_or(value))?;
         let prev = self.env.insert(name.to_string(), val);
         let result = self.emit(body);
         match prev {
@@ -962,12 +1362,25 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
         let lambda_name = format!("__lambda_{}", id);
         let lambda_fv = self.module.add_function(&lambda_name, fn_t, None);
 
-        // Save builder position so we can restore after emitting body.
+        // Save builder position (and debug location, which belongs to
+        // the enclosing function's scope) so we can restore after
+        // emitting body.
         let saved_block = self.builder.get_insert_block();
+        let saved_loc = self.builder.get_current_debug_location();
 
         // Emit body in a fresh env with only the lambda's params.
         let entry = self.context.append_basic_block(lambda_fv, "entry");
         self.builder.position_at_end(entry);
+        if let Some(debug) = self.form.debug {
+            debug.begin_function(
+                self.builder,
+                lambda_fv,
+                "<lambda>",
+                self.form.line(),
+                params,
+                ret_ty,
+            )?;
+        }
 
         let mut lambda_env: HashMap<String, EmitVal<'ctx>> = HashMap::new();
         for (i, (pname, _)) in params.iter().enumerate() {
@@ -982,9 +1395,12 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
             module: self.module,
             builder: self.builder,
             function: lambda_fv,
+            fn_label: "<lambda>".to_string(),
             env: lambda_env,
             functions: self.functions.clone(),
             lambda_counter: self.lambda_counter,
+            form: self.form.clone(),
+            returned: false,
         };
         let body_val = inner.emit(body)?;
 
@@ -1022,6 +1438,9 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
         if let Some(bb) = saved_block {
             self.builder.position_at_end(bb);
         }
+        if let Some(loc) = saved_loc {
+            self.builder.set_current_debug_location(loc);
+        }
 
         Ok(EmitVal::FuncRef(lambda_fv))
     }
@@ -1075,6 +1494,9 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
 
         self.builder.position_at_end(eval_bb);
         let rhs_v = self.emit(rhs_expr)?;
+        if self.returned {
+            return Err(format!("--llvm: `return` inside `{}` is not supported yet", kind.name()));
+        }
         let rhs = self.expect_bool(&rhs_v, kind.name())?;
         let eval_end = self.builder.get_insert_block().expect("eval has insert block");
         self.builder
@@ -1118,6 +1540,49 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
         Ok(EmitVal::Int(r))
     }
 
+    /// `(neg x)`, also what `(- x)` and `-x` read as. Like the other
+    /// integer ops this wraps rather than checking for overflow.
+    fn gen_neg(&mut self, args: &[Expr]) -> Result<EmitVal<'ctx>, JitError> {
+        if args.len() != 1 {
+            return Err(format!(
+                "`neg` requires exactly 1 argument, got {}",
+                args.len()
+            ));
+        }
+        match self.emit(&args[0])? {
+            // `0 - x`, so negating the minimum traps like the interpreter.
+            EmitVal::Int(v) if v.get_type().get_bit_width() != 1 => self
+                .checked_int_op("llvm.ssub.with.overflow", v.get_type().const_zero(), v, &render_form("neg", args))
+                .map(EmitVal::Int),
+            EmitVal::Float(v) => self
+                .builder
+                .build_float_neg(v, "fnegtmp")
+                .map(EmitVal::Float)
+                .map_err(|e| format!("LLVM build_float_neg failed: {}", e)),
+            other => Err(format!("`neg` requires a number, got {}", other.type_name())),
+        }
+    }
+
+    /// Bring an i32/i64 operand pair to a common width, sign-extending the
+    /// narrower one — the checker types mixed-width arithmetic as i64.
+    fn widen_ints(
+        &self,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+    ) -> Result<(IntValue<'ctx>, IntValue<'ctx>), JitError> {
+        let (lw, rw) = (l.get_type().get_bit_width(), r.get_type().get_bit_width());
+        let widen = |v: IntValue<'ctx>| {
+            self.builder
+                .build_int_s_extend(v, self.context.i64_type(), "widentmp")
+                .map_err(|e| format!("LLVM build_int_s_extend failed: {}", e))
+        };
+        match (lw, rw) {
+            (32, 64) => Ok((widen(l)?, r)),
+            (64, 32) => Ok((l, widen(r)?)),
+            _ => Ok((l, r)),
+        }
+    }
+
     fn expect_int(&self, v: &EmitVal<'ctx>, op: &str) -> Result<IntValue<'ctx>, JitError> {
         match v {
             EmitVal::Int(iv) if iv.get_type().get_bit_width() != 1 => Ok(*iv),
@@ -1152,6 +1617,17 @@ impl<'ctx, 'a> ExprCg<'ctx, 'a> {
     }
 }
 
+/// Render `(op arg ...)` back as Rusp source for fault messages.
+fn render_form(op: &str, args: &[Expr]) -> String {
+    let mut out = format!("({}", op);
+    for a in args {
+        out.push(' ');
+        out.push_str(&a.to_string());
+    }
+    out.push(')');
+    out
+}
+
 /// Internal tag for `short_circuit` so it can pick branch direction and
 /// short-circuit constant without two near-duplicate methods.
 #[derive(Debug, Clone, Copy)]
@@ -4,7 +4,10 @@
 //! supports i32 literals and i32 arithmetic via JIT.
 
 pub mod aot;
+mod debuginfo;
 pub mod jit;
+pub mod srcmap;
+pub mod tier;
 
 use inkwell::context::Context;
 
@@ -19,8 +22,9 @@ pub fn smoke_module() -> String {
     module.print_to_string().to_string()
 }
 
-pub use aot::{compile_to_ll, compile_to_obj};
+pub use aot::{compile_to_ll, compile_to_ll_with, compile_to_obj, compile_to_obj_with, AotOptions};
 pub use jit::{
     jit_eval_bool, jit_eval_bool_program, jit_eval_f64, jit_eval_f64_program, jit_eval_i32,
     jit_eval_i32_program, jit_eval_i64, jit_eval_i64_program, JitError,
 };
+pub use srcmap::{SourceLoc, SourceMap};
@@ -0,0 +1,164 @@
+//! Source mapping for runtime faults in compiled code.
+//!
+//! The AST carries no spans, so the finest location we can recover is the
+//! top-level form: the driver records where each form starts in its file
+//! (`SourceMap`), and codegen registers every instruction region that can
+//! trap (integer division) as a `FaultSite` tagged with the enclosing
+//! function, that form's location, and the rendered sub-expression.
+//!
+//! Each site gets a small integer id. The guarded region branches to a
+//! fault block when its operands would trap, and what that block does
+//! depends on the backend (`FaultMode`):
+//!
+//! - JIT (`Propagate`): store the id into the module global
+//!   `__rusp_fault` and return a zero value. Every call site checks the
+//!   global afterwards and bails out the same way, so the fault unwinds to
+//!   the `__expr` thunk, where the host reads the id back through
+//!   `__rusp_fault_site()` and turns it into an error message.
+//! - AOT (`Abort`): write the site's message to stderr and `exit(101)`.
+//!   There's no host to hand the fault back to, and 101 mirrors what a
+//!   Rust panic exits with.
+
+use std::fmt;
+
+/// Where a top-level form starts in its source file (1-based).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLoc {
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+/// Start positions of every top-level form in one source file, indexed
+/// in the same order as the forms handed to codegen.
+/// Forms may come from several files once imports are expanded; `file`
+/// is the one the build was started from.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    file: String,
+    forms: Vec<SourceLoc>,
+}
+
+impl SourceMap {
+    /// Build a map from byte offsets into `source` (one per form).
+    pub fn from_offsets(file: &str, source: &str, offsets: &[usize]) -> Self {
+        let forms = offsets
+            .iter()
+            .map(|&o| {
+                let (line, col) = line_col(source, o);
+                SourceLoc { file: file.to_string(), line, col }
+            })
+            .collect();
+        SourceMap { file: file.to_string(), forms }
+    }
+
+    /// Build a map from already-resolved form locations.
+    pub fn from_locs(file: &str, forms: Vec<SourceLoc>) -> Self {
+        SourceMap { file: file.to_string(), forms }
+    }
+
+    /// The file the forms were read from.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Location of the `idx`-th top-level form, if it was recorded.
+    pub fn form_loc(&self, idx: usize) -> Option<SourceLoc> {
+        self.forms.get(idx).cloned()
+    }
+}
+
+/// 1-based (line, column) of byte `offset` in `source`. Columns count
+/// characters, not bytes, so non-ASCII text before the offset doesn't
+/// skew them.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
+}
+
+/// What went wrong at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    DivisionByZero,
+    /// `MIN / -1` — the quotient doesn't fit, and `sdiv` traps on it.
+    DivisionOverflow,
+    /// `+`, `-`, `*` or `neg` whose result doesn't fit its width.
+    Overflow,
+}
+
+impl FaultKind {
+    fn describe(&self) -> &'static str {
+        match self {
+            FaultKind::DivisionByZero => "division by zero",
+            FaultKind::DivisionOverflow => "integer overflow in division",
+            FaultKind::Overflow => "integer overflow",
+        }
+    }
+}
+
+/// One trapping instruction region, with enough context to explain it.
+#[derive(Debug, Clone)]
+pub struct FaultSite {
+    pub kind: FaultKind,
+    /// Enclosing function: the `defn` name, `<lambda>`, or `<top-level>`
+    /// for the JIT's `__expr` thunk.
+    pub function: String,
+    pub loc: Option<SourceLoc>,
+    /// The faulting sub-expression, rendered back as Rusp syntax.
+    pub expr: String,
+}
+
+impl FaultSite {
+    /// `runtime error: division by zero at f.rusp:3:1 in function `f`: (/ a b)`
+    pub fn message(&self) -> String {
+        match &self.loc {
+            Some(loc) => format!(
+                "runtime error: {} at {} in function `{}`: {}",
+                self.kind.describe(),
+                loc,
+                self.function,
+                self.expr
+            ),
+            None => format!(
+                "runtime error: {} in function `{}`: {}",
+                self.kind.describe(),
+                self.function,
+                self.expr
+            ),
+        }
+    }
+}
+
+/// How a fault block reacts once a trap condition is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FaultMode {
+    Propagate,
+    Abort,
+}
+
+/// Fault sites registered while emitting one module. Ids are 1-based so
+/// `0` in `__rusp_fault` means "no fault".
+#[derive(Debug, Default)]
+pub(crate) struct FaultTable {
+    sites: Vec<FaultSite>,
+}
+
+impl FaultTable {
+    pub(crate) fn register(&mut self, site: FaultSite) -> u32 {
+        self.sites.push(site);
+        self.sites.len() as u32
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<&FaultSite> {
+        (id as usize).checked_sub(1).and_then(|i| self.sites.get(i))
+    }
+}
@@ -0,0 +1,246 @@
+//! Native entry points for the interpreter's tiered execution.
+//!
+//! `crate::tier` decides *when* a hot `defn` gets compiled; this module
+//! does the compiling. The `defn` (plus every `defn` it calls) is
+//! emitted into its own module together with a uniform trampoline
+//!
+//! ```text
+//! i64 @__tier_entry(ptr %args)   ; args: one 64-bit slot per parameter
+//! ```
+//!
+//! so the interpreter can call any compiled function through a single
+//! Rust signature, `extern "C" fn(*const u64) -> u64`, without knowing
+//! its arity or parameter types at compile time. Scalars travel through
+//! the slots as their bit patterns (ints sign-extended, bools as 0/1,
+//! `f64` via bitcast).
+//!
+//! A compiled function outlives the call that created it, so its LLVM
+//! `Context` is leaked to get the `'static` lifetime the execution engine
+//! needs. That's one small allocation per compiled function, bounded by
+//! the number of distinct hot `defn`s in a session.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use inkwell::OptimizationLevel;
+use inkwell::context::Context;
+use inkwell::execution_engine::JitFunction;
+use inkwell::types::BasicType;
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue};
+
+use crate::ast::{Expr, Type};
+use crate::env::Value;
+
+use super::jit::{
+    FAULT_GLOBAL, FormCtx, JitError, declare_defn, declare_fault_global, emit_defn,
+    emit_fault_getter, type_to_basic,
+};
+use super::srcmap::{FaultMode, FaultTable};
+
+const ENTRY: &str = "__tier_entry";
+
+type EntryFn = unsafe extern "C" fn(*const u64) -> u64;
+type FaultFn = unsafe extern "C" fn() -> i32;
+
+/// A JIT-compiled `defn`, callable with interpreter `Value`s.
+pub struct NativeEntry {
+    params: Vec<Type>,
+    return_type: Type,
+    entry: JitFunction<'static, EntryFn>,
+    fault_site: JitFunction<'static, FaultFn>,
+    faults: FaultTable,
+}
+
+impl NativeEntry {
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Run the compiled function. Arguments must already match the
+    /// declared parameter types (the type checker guarantees this for
+    /// well-typed programs); a mismatch is reported, not reinterpreted.
+    pub fn call(&self, args: &[Value]) -> Result<Value, String> {
+        if args.len() != self.params.len() {
+            return Err(format!(
+                "Wrong number of arguments: expected {}, got {}",
+                self.params.len(),
+                args.len()
+            ));
+        }
+        let slots = args
+            .iter()
+            .zip(&self.params)
+            .map(|(v, ty)| to_slot(v, ty))
+            .collect::<Result<Vec<u64>, String>>()?;
+
+        // SAFETY: `__tier_entry` reads exactly `params.len()` slots, and
+        // both functions were emitted with these signatures in `compile`.
+        let (raw, site) = unsafe { (self.entry.call(slots.as_ptr()), self.fault_site.call()) };
+        if site != 0 {
+            return Err(match self.faults.get(site as u32) {
+                Some(s) => s.message(),
+                None => format!("runtime error: unknown fault site {}", site),
+            });
+        }
+        Ok(from_slot(raw, &self.return_type))
+    }
+}
+
+/// Compile `defns` (which must include `entry`) and return a handle on
+/// `entry`. Fails if any of them fall outside the JIT's supported
+/// subset; the caller is expected to keep interpreting in that case.
+pub fn compile(defns: &[Expr], entry: &str) -> Result<NativeEntry, JitError> {
+    let target = defns
+        .iter()
+        .find(|d| matches!(d, Expr::Defn { name, .. } if name == entry))
+        .ok_or_else(|| format!("tier: no defn named `{}`", entry))?;
+    let Expr::Defn { params, return_type, .. } = target else {
+        unreachable!("matched Defn above")
+    };
+    let param_tys: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
+
+    let context: &'static Context = Box::leak(Box::new(Context::create()));
+    let module = context.create_module("rusp_tier");
+    let builder = context.create_builder();
+
+    let mut functions: HashMap<String, FunctionValue<'static>> = HashMap::new();
+    let lambda_counter: Cell<u32> = Cell::new(0);
+    let fault_table = RefCell::new(FaultTable::default());
+    let form = FormCtx {
+        faults: &fault_table,
+        fault_mode: FaultMode::Propagate,
+        loc: None,
+        debug: None,
+    };
+    declare_fault_global(context, &module);
+    // Declared first, so mutually recursive `defn`s can call each other.
+    for d in defns {
+        if let Expr::Defn { name, .. } = d {
+            functions.insert(name.clone(), declare_defn(context, &module, d)?);
+        }
+    }
+    for d in defns {
+        emit_defn(context, &module, &builder, &mut functions, &lambda_counter, &form, d)?;
+    }
+    let callee = functions[entry];
+
+    // Trampoline: clear the fault flag, unpack the slots, call, repack.
+    let i64_t = context.i64_type();
+    let ptr_t = context.ptr_type(inkwell::AddressSpace::default());
+    let tramp = module.add_function(ENTRY, i64_t.fn_type(&[ptr_t.into()], false), None);
+    builder.position_at_end(context.append_basic_block(tramp, "entry"));
+    let fault_ptr = module
+        .get_global(FAULT_GLOBAL)
+        .ok_or("internal: fault global missing from tier module")?
+        .as_pointer_value();
+    builder
+        .build_store(fault_ptr, context.i32_type().const_zero())
+        .map_err(|e| format!("LLVM build_store failed: {}", e))?;
+
+    let args_ptr = tramp
+        .get_nth_param(0)
+        .ok_or("internal: trampoline has no args param")?
+        .into_pointer_value();
+    let mut call_args: Vec<BasicMetadataValueEnum> = Vec::with_capacity(param_tys.len());
+    for (i, ty) in param_tys.iter().enumerate() {
+        // SAFETY: the caller passes one slot per parameter.
+        let slot_ptr = unsafe {
+            builder
+                .build_gep(i64_t, args_ptr, &[i64_t.const_int(i as u64, false)], "slot")
+                .map_err(|e| format!("LLVM build_gep failed: {}", e))?
+        };
+        let raw = builder
+            .build_load(i64_t, slot_ptr, "raw")
+            .map_err(|e| format!("LLVM build_load failed: {}", e))?
+            .into_int_value();
+        let want = type_to_basic(context, ty)?;
+        let arg: BasicValueEnum = match ty {
+            Type::I64 => raw.into(),
+            Type::I32 | Type::Bool => builder
+                .build_int_truncate(raw, want.into_int_type(), "arg")
+                .map_err(|e| format!("LLVM build_int_truncate failed: {}", e))?
+                .into(),
+            Type::F64 => builder
+                .build_bit_cast(raw, want, "arg")
+                .map_err(|e| format!("LLVM build_bit_cast failed: {}", e))?,
+            other => return Err(format!("tier: unsupported parameter type {}", other)),
+        };
+        call_args.push(arg.into());
+    }
+
+    let ret = builder
+        .build_call(callee, &call_args, "ret")
+        .map_err(|e| format!("LLVM build_call failed: {}", e))?
+        .try_as_basic_value()
+        .basic()
+        .ok_or_else(|| format!("tier: `{}` returns void", entry))?;
+    let packed = match return_type {
+        Type::I64 => ret.into_int_value(),
+        Type::I32 => builder
+            .build_int_s_extend(ret.into_int_value(), i64_t, "packed")
+            .map_err(|e| format!("LLVM build_int_s_extend failed: {}", e))?,
+        Type::Bool => builder
+            .build_int_z_extend(ret.into_int_value(), i64_t, "packed")
+            .map_err(|e| format!("LLVM build_int_z_extend failed: {}", e))?,
+        Type::F64 => builder
+            .build_bit_cast(ret, i64_t.as_basic_type_enum(), "packed")
+            .map_err(|e| format!("LLVM build_bit_cast failed: {}", e))?
+            .into_int_value(),
+        other => return Err(format!("tier: unsupported return type {}", other)),
+    };
+    builder
+        .build_return(Some(&packed))
+        .map_err(|e| format!("LLVM build_return failed: {}", e))?;
+
+    emit_fault_getter(context, &module, &builder)?;
+    module
+        .verify()
+        .map_err(|e| format!("tier: module failed verification: {}", e))?;
+
+    let engine = module
+        .create_jit_execution_engine(OptimizationLevel::Default)
+        .map_err(|e| format!("failed to create JIT execution engine: {}", e))?;
+    // SAFETY: both functions were emitted above with these signatures.
+    let (entry_fn, fault_fn) = unsafe {
+        (
+            engine
+                .get_function::<EntryFn>(ENTRY)
+                .map_err(|e| format!("failed to look up {}: {}", ENTRY, e))?,
+            engine
+                .get_function::<FaultFn>("__rusp_fault_site")
+                .map_err(|e| format!("failed to look up __rusp_fault_site: {}", e))?,
+        )
+    };
+    Ok(NativeEntry {
+        params: param_tys,
+        return_type: return_type.clone(),
+        entry: entry_fn,
+        fault_site: fault_fn,
+        faults: fault_table.into_inner(),
+    })
+}
+
+fn to_slot(v: &Value, ty: &Type) -> Result<u64, String> {
+    Ok(match (v, ty) {
+        (Value::Integer32(n), Type::I32) => *n as i64 as u64,
+        (Value::Integer64(n), Type::I64) => *n as u64,
+        (Value::Bool(b), Type::Bool) => u64::from(*b),
+        (Value::Float(f), Type::F64) => f.to_bits(),
+        (v, ty) => {
+            return Err(format!(
+                "compiled function expects {}, got {}",
+                ty,
+                v.type_name()
+            ));
+        }
+    })
+}
+
+fn from_slot(raw: u64, ty: &Type) -> Value {
+    match ty {
+        Type::I32 => Value::Integer32(raw as i64 as i32),
+        Type::Bool => Value::Bool(raw != 0),
+        Type::F64 => Value::Float(f64::from_bits(raw)),
+        _ => Value::Integer64(raw as i64),
+    }
+}
@@ -0,0 +1,469 @@
+//! DEFLATE (RFC 1951) and gzip (RFC 1952), std-only.
+//!
+//! Decompression handles all three block types, so anything `gzip(1)`
+//! produces can be read back. Compression is deliberately simple: greedy
+//! LZ77 over a 32 KiB window, emitted as a single fixed-Huffman block.
+//! That gets most of the win on logs and other repetitive text without a
+//! dynamic-table encoder.
+
+const MAX_BITS: usize = 15;
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// How many earlier positions with the same 3-byte prefix to try.
+const MAX_CHAIN: usize = 64;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order code-length code lengths are stored in (RFC 1951 §3.2.7).
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Whether `data` starts with the gzip magic number.
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+/// Wrap `data` in a single gzip member.
+pub fn gzip_compress(data: &[u8]) -> Vec<u8> {
+    // No mtime, no file name, "unknown" OS: the output depends only on
+    // the input.
+    let mut out = vec![GZIP_MAGIC[0], GZIP_MAGIC[1], 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Decompress every gzip member in `data` and concatenate the results,
+/// checking each member's CRC and length.
+pub fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    loop {
+        pos = gzip_member(data, pos, &mut out)?;
+        if pos == data.len() {
+            return Ok(out);
+        }
+        if !is_gzip(&data[pos..]) {
+            return Err(format!("gzip: trailing garbage at byte {}", pos));
+        }
+    }
+}
+
+/// Decode one member starting at `pos`; returns the offset just past it.
+fn gzip_member(data: &[u8], pos: usize, out: &mut Vec<u8>) -> Result<usize, String> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let header = data.get(pos..pos + 10).ok_or("gzip: truncated header")?;
+    if !is_gzip(header) {
+        return Err("gzip: not gzip data (bad magic)".to_string());
+    }
+    if header[2] != 8 {
+        return Err(format!("gzip: unsupported compression method {}", header[2]));
+    }
+    let flags = header[3];
+    let mut p = pos + 10;
+    if flags & FEXTRA != 0 {
+        let len = data.get(p..p + 2).ok_or("gzip: truncated header")?;
+        p += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let nul = data
+                .get(p..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or("gzip: truncated header")?;
+            p += nul + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        p += 2;
+    }
+    if p > data.len() {
+        return Err("gzip: truncated header".to_string());
+    }
+
+    let start = out.len();
+    let consumed = inflate_into(&data[p..], out)?;
+    p += consumed;
+    let trailer = data.get(p..p + 8).ok_or("gzip: truncated trailer")?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    let member = &out[start..];
+    if crc32(member) != crc {
+        return Err("gzip: CRC mismatch".to_string());
+    }
+    if member.len() as u32 != size {
+        return Err("gzip: length mismatch".to_string());
+    }
+    Ok(p + 8)
+}
+
+/// CRC-32 (IEEE, reflected), as used by gzip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+                k += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+    !data
+        .iter()
+        .fold(!0u32, |c, &b| TABLE[((c ^ u32::from(b)) & 0xff) as usize] ^ (c >> 8))
+}
+
+// ---- Decompression ----
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, String> {
+        let byte = *self.data.get(self.pos >> 3).ok_or("deflate: unexpected end of data")?;
+        let b = (byte >> (self.pos & 7)) & 1;
+        self.pos += 1;
+        Ok(u32::from(b))
+    }
+
+    /// `n` bits, least significant first.
+    fn bits(&mut self, n: u8) -> Result<u32, String> {
+        let mut v = 0;
+        for i in 0..n {
+            v |= self.bit()? << i;
+        }
+        Ok(v)
+    }
+
+    fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+}
+
+/// Canonical Huffman decoding table: code counts per length plus the
+/// symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, String> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        // Reject over-subscribed codes (incomplete ones are legal for
+        // single-symbol distance trees).
+        let mut left: i32 = 1;
+        for &c in &counts[1..] {
+            left = (left << 1) - i32::from(c);
+            if left < 0 {
+                return Err("deflate: over-subscribed Huffman code".to_string());
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; offsets[MAX_BITS + 1] as usize];
+        for (sym, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbols[offsets[l as usize] as usize] = sym as u16;
+                offsets[l as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, r: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= r.bit()? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("deflate: invalid Huffman code".to_string())
+    }
+}
+
+/// Inflate a raw DEFLATE stream.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    inflate_into(data, &mut out)?;
+    Ok(out)
+}
+
+/// Inflate onto the end of `out`; returns the number of input bytes
+/// consumed (through the end of the final block, rounded up).
+fn inflate_into(data: &[u8], out: &mut Vec<u8>) -> Result<usize, String> {
+    let mut r = BitReader { data, pos: 0 };
+    let start = out.len();
+    loop {
+        let last = r.bit()? == 1;
+        match r.bits(2)? {
+            0 => stored_block(&mut r, out)?,
+            1 => {
+                let (lit, dist) = fixed_tables()?;
+                codes(&mut r, out, start, &lit, &dist)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut r)?;
+                codes(&mut r, out, start, &lit, &dist)?;
+            }
+            _ => return Err("deflate: invalid block type 3".to_string()),
+        }
+        if last {
+            r.align();
+            return Ok(r.pos / 8);
+        }
+    }
+}
+
+fn stored_block(r: &mut BitReader, out: &mut Vec<u8>) -> Result<(), String> {
+    r.align();
+    let len = r.bits(16)? as usize;
+    let nlen = r.bits(16)? as usize;
+    if len != !nlen & 0xffff {
+        return Err("deflate: stored block length check failed".to_string());
+    }
+    let at = r.pos / 8;
+    let bytes = r.data.get(at..at + len).ok_or("deflate: unexpected end of data")?;
+    out.extend_from_slice(bytes);
+    r.pos += len * 8;
+    Ok(())
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman), String> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_tables(r: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let nlit = r.bits(5)? as usize + 257;
+    let ndist = r.bits(5)? as usize + 1;
+    let nclen = r.bits(4)? as usize + 4;
+    if nlit > 286 || ndist > 30 {
+        return Err("deflate: bad table sizes".to_string());
+    }
+    let mut clen = [0u8; 19];
+    for &i in &CLEN_ORDER[..nclen] {
+        clen[i] = r.bits(3)? as u8;
+    }
+    let clen = Huffman::new(&clen)?;
+
+    let mut lengths = Vec::with_capacity(nlit + ndist);
+    while lengths.len() < nlit + ndist {
+        let (value, repeat) = match clen.decode(r)? {
+            sym @ 0..=15 => (sym as u8, 1),
+            16 => {
+                let prev = *lengths.last().ok_or("deflate: repeat with no previous length")?;
+                (prev, 3 + r.bits(2)? as usize)
+            }
+            17 => (0, 3 + r.bits(3)? as usize),
+            _ => (0, 11 + r.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > nlit + ndist {
+            return Err("deflate: code lengths overrun".to_string());
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths[256] == 0 {
+        return Err("deflate: missing end-of-block code".to_string());
+    }
+    Ok((Huffman::new(&lengths[..nlit])?, Huffman::new(&lengths[nlit..])?))
+}
+
+/// Decode literal/length + distance codes until end-of-block. `start`
+/// is where this stream's output begins in `out`; back-references may
+/// not reach before it.
+fn codes(
+    r: &mut BitReader,
+    out: &mut Vec<u8>,
+    start: usize,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let sym = lit.decode(r)? as usize;
+        match sym {
+            0..=255 => out.push(sym as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = sym - 257;
+                if i >= LEN_BASE.len() {
+                    return Err(format!("deflate: invalid length code {}", sym));
+                }
+                let len = LEN_BASE[i] as usize + r.bits(LEN_EXTRA[i])? as usize;
+                let d = dist.decode(r)? as usize;
+                if d >= DIST_BASE.len() {
+                    return Err(format!("deflate: invalid distance code {}", d));
+                }
+                let distance = DIST_BASE[d] as usize + r.bits(DIST_EXTRA[d])? as usize;
+                if distance > out.len() - start {
+                    return Err("deflate: distance too far back".to_string());
+                }
+                // Byte at a time: the source may overlap what we're writing.
+                let from = out.len() - distance;
+                for k in 0..len {
+                    out.push(out[from + k]);
+                }
+            }
+        }
+    }
+}
+
+// ---- Compression ----
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    nbits: u8,
+}
+
+impl BitWriter {
+    /// `n` bits of `value`, least significant first.
+    fn bits(&mut self, value: u32, n: u8) {
+        for i in 0..n {
+            self.acc |= ((value >> i) & 1) << self.nbits;
+            self.nbits += 1;
+            if self.nbits == 8 {
+                self.out.push(self.acc as u8);
+                self.acc = 0;
+                self.nbits = 0;
+            }
+        }
+    }
+
+    /// A Huffman code, which is packed most significant bit first.
+    fn code(&mut self, code: u32, len: u8) {
+        let reversed = code.reverse_bits() >> (32 - u32::from(len));
+        self.bits(reversed, len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// Fixed literal/length code (RFC 1951 §3.2.6).
+fn fixed_literal(w: &mut BitWriter, sym: u16) {
+    let sym = u32::from(sym);
+    match sym {
+        0..=143 => w.code(0x30 + sym, 8),
+        144..=255 => w.code(0x190 + sym - 144, 9),
+        256..=279 => w.code(sym - 256, 7),
+        _ => w.code(0xc0 + sym - 280, 8),
+    }
+}
+
+/// Index of the last entry in `base` that is `<= value`.
+fn bucket(base: &[u16], value: usize) -> usize {
+    base.iter().rposition(|&b| b as usize <= value).unwrap_or(0)
+}
+
+/// Compress `data` to a raw DEFLATE stream.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    const HASH_SIZE: usize = 1 << 15;
+    let hash = |i: usize| {
+        let h = u32::from(data[i]) << 10 ^ u32::from(data[i + 1]) << 5 ^ u32::from(data[i + 2]);
+        h as usize & (HASH_SIZE - 1)
+    };
+
+    let mut w = BitWriter { out: Vec::new(), acc: 0, nbits: 0 };
+    w.bits(1, 1); // BFINAL
+    w.bits(1, 2); // BTYPE = fixed Huffman
+
+    // head[h]: most recent position with hash h; prev[i]: the one before i.
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(i);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max = MAX_MATCH.min(data.len() - i);
+            let mut cand = head[hash(i)];
+            let mut chain = 0;
+            while cand != usize::MAX && i - cand <= WINDOW && chain < MAX_CHAIN {
+                let len = data[cand..].iter().zip(&data[i..i + max]).take_while(|(a, b)| a == b).count();
+                if len > best_len {
+                    (best_len, best_dist) = (len, i - cand);
+                    if len == max {
+                        break;
+                    }
+                }
+                cand = prev[cand];
+                chain += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            let l = bucket(&LEN_BASE, best_len);
+            fixed_literal(&mut w, 257 + l as u16);
+            w.bits((best_len - LEN_BASE[l] as usize) as u32, LEN_EXTRA[l]);
+            let d = bucket(&DIST_BASE, best_dist);
+            w.code(d as u32, 5);
+            w.bits((best_dist - DIST_BASE[d] as usize) as u32, DIST_EXTRA[d]);
+            for k in i..i + best_len {
+                insert(k, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            fixed_literal(&mut w, u16::from(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    fixed_literal(&mut w, 256);
+    w.finish()
+}
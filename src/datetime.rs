@@ -0,0 +1,405 @@
+//! Date/time support: UTC and fixed-offset datetimes plus durations.
+//!
+//! Everything is std-only. A point in time is nanoseconds since the Unix
+//! epoch (i128, so proleptic Gregorian dates far outside 1970±292y still
+//! work); a `ZonedDateTime` additionally carries a fixed UTC offset that
+//! only affects how its fields are read and printed. There's no tz
+//! database, so named zones aren't supported — offsets are explicit.
+//!
+//! Calendar math uses Howard Hinnant's `days_from_civil` /
+//! `civil_from_days` algorithms.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September",
+    "October", "November", "December",
+];
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday",
+];
+
+/// A point in time. `offset` is `None` for UTC values and `Some(secs)`
+/// for zoned ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub nanos: i128,
+    pub offset: Option<i32>,
+}
+
+/// A signed span of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duration {
+    pub nanos: i128,
+}
+
+/// Broken-down local fields of a `DateTime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Civil {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub nanosecond: u32,
+    /// 0 = Sunday.
+    pub weekday: u32,
+    /// 1-based day of the year.
+    pub ordinal: u32,
+}
+
+fn is_leap(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        _ if is_leap(year) => 29,
+        _ => 28,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = i64::from(month);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl DateTime {
+    pub fn now() -> Self {
+        Self::from_system_time(SystemTime::now())
+    }
+
+    /// UTC datetime for a `SystemTime` (e.g. a file's mtime).
+    pub fn from_system_time(t: SystemTime) -> Self {
+        let nanos = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128),
+        };
+        DateTime { nanos, offset: None }
+    }
+
+    /// UTC datetime from calendar fields, validated.
+    pub fn from_civil(
+        year: i64,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    ) -> Result<Self, String> {
+        if !(1..=12).contains(&month) {
+            return Err(format!("month {} out of range 1..=12", month));
+        }
+        if day < 1 || day > days_in_month(year, month) {
+            return Err(format!("day {} out of range for {}-{:02}", day, year, month));
+        }
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(format!("time {:02}:{:02}:{:02} out of range", hour, minute, second));
+        }
+        let secs = days_from_civil(year, month, day) * SECS_PER_DAY
+            + i64::from(hour) * 3600
+            + i64::from(minute) * 60
+            + i64::from(second);
+        Ok(DateTime { nanos: i128::from(secs) * NANOS_PER_SEC, offset: None })
+    }
+
+    pub fn from_unix(secs: i64) -> Self {
+        DateTime { nanos: i128::from(secs) * NANOS_PER_SEC, offset: None }
+    }
+
+    pub fn unix_seconds(&self) -> i64 {
+        self.nanos.div_euclid(NANOS_PER_SEC) as i64
+    }
+
+    /// Same instant viewed at a fixed UTC offset.
+    pub fn with_offset(self, offset_secs: i32) -> Result<Self, String> {
+        if offset_secs.abs() >= 24 * 3600 {
+            return Err(format!("UTC offset {}s out of range", offset_secs));
+        }
+        Ok(DateTime { offset: Some(offset_secs), ..self })
+    }
+
+    pub fn to_utc(self) -> Self {
+        DateTime { offset: None, ..self }
+    }
+
+    pub fn plus(self, d: Duration) -> Self {
+        DateTime { nanos: self.nanos + d.nanos, ..self }
+    }
+
+    pub fn since(self, earlier: DateTime) -> Duration {
+        Duration { nanos: self.nanos - earlier.nanos }
+    }
+
+    /// Local calendar fields (offset applied for zoned values).
+    pub fn civil(&self) -> Civil {
+        let local = self.nanos + i128::from(self.offset.unwrap_or(0)) * NANOS_PER_SEC;
+        let secs = local.div_euclid(NANOS_PER_SEC) as i64;
+        let nanosecond = local.rem_euclid(NANOS_PER_SEC) as u32;
+        let days = secs.div_euclid(SECS_PER_DAY);
+        let sod = secs.rem_euclid(SECS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        Civil {
+            year,
+            month,
+            day,
+            hour: (sod / 3600) as u32,
+            minute: (sod % 3600 / 60) as u32,
+            second: (sod % 60) as u32,
+            nanosecond,
+            // 1970-01-01 was a Thursday.
+            weekday: (days + 4).rem_euclid(7) as u32,
+            ordinal: (days - days_from_civil(year, 1, 1) + 1) as u32,
+        }
+    }
+
+    /// strftime-style formatting. Supported: `%Y %y %m %d %e %H %M %S %f
+    /// (nanoseconds) %j %a %A %b %B %z (+hhmm) %:z (+hh:mm) %Z (UTC or
+    /// offset) %s %F %T %%`.
+    pub fn format(&self, pattern: &str) -> Result<String, String> {
+        let c = self.civil();
+        let mut out = String::new();
+        let mut chars = pattern.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                out.push(ch);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => out.push_str(&format!("{:04}", c.year)),
+                Some('y') => out.push_str(&format!("{:02}", c.year.rem_euclid(100))),
+                Some('m') => out.push_str(&format!("{:02}", c.month)),
+                Some('d') => out.push_str(&format!("{:02}", c.day)),
+                Some('e') => out.push_str(&format!("{:>2}", c.day)),
+                Some('H') => out.push_str(&format!("{:02}", c.hour)),
+                Some('M') => out.push_str(&format!("{:02}", c.minute)),
+                Some('S') => out.push_str(&format!("{:02}", c.second)),
+                Some('f') => out.push_str(&format!("{:09}", c.nanosecond)),
+                Some('j') => out.push_str(&format!("{:03}", c.ordinal)),
+                Some('a') => out.push_str(&WEEKDAY_NAMES[c.weekday as usize][..3]),
+                Some('A') => out.push_str(WEEKDAY_NAMES[c.weekday as usize]),
+                Some('b') => out.push_str(&MONTH_NAMES[c.month as usize - 1][..3]),
+                Some('B') => out.push_str(MONTH_NAMES[c.month as usize - 1]),
+                Some('z') => out.push_str(&offset_string(self.offset.unwrap_or(0), "")),
+                Some(':') if chars.next() == Some('z') => {
+                    out.push_str(&offset_string(self.offset.unwrap_or(0), ":"))
+                }
+                Some('Z') => match self.offset {
+                    None => out.push_str("UTC"),
+                    Some(o) => out.push_str(&offset_string(o, ":")),
+                },
+                Some('s') => out.push_str(&self.unix_seconds().to_string()),
+                Some('F') => out.push_str(&format!("{:04}-{:02}-{:02}", c.year, c.month, c.day)),
+                Some('T') => {
+                    out.push_str(&format!("{:02}:{:02}:{:02}", c.hour, c.minute, c.second))
+                }
+                Some('%') => out.push('%'),
+                Some(other) => return Err(format!("unsupported format directive %{}", other)),
+                None => return Err("format string ends with a lone %".to_string()),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Parse `input` against a strftime-style pattern. Supported: `%Y %m
+    /// %d %H %M %S %b %z %:z %F %T %%`; missing fields default to
+    /// 1970-01-01 00:00:00. With `%z` the result is zoned at that offset,
+    /// otherwise it's UTC.
+    pub fn parse(input: &str, pattern: &str) -> Result<Self, String> {
+        let mut p = Parser { rest: input };
+        let (mut year, mut month, mut day) = (1970i64, 1u32, 1u32);
+        let (mut hour, mut minute, mut second) = (0u32, 0u32, 0u32);
+        let mut offset: Option<i32> = None;
+
+        let expanded = pattern.replace("%F", "%Y-%m-%d").replace("%T", "%H:%M:%S");
+        let mut chars = expanded.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                p.literal(ch)?;
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => year = p.signed_number(4)?,
+                Some('m') => month = p.number(2)? as u32,
+                Some('d') => day = p.number(2)? as u32,
+                Some('H') => hour = p.number(2)? as u32,
+                Some('M') => minute = p.number(2)? as u32,
+                Some('S') => second = p.number(2)? as u32,
+                Some('b') => month = p.month_abbrev()?,
+                Some('z') => offset = Some(p.offset()?),
+                Some(':') if chars.next() == Some('z') => offset = Some(p.offset()?),
+                Some('%') => p.literal('%')?,
+                Some(other) => return Err(format!("unsupported parse directive %{}", other)),
+                None => return Err("format string ends with a lone %".to_string()),
+            }
+        }
+        if !p.rest.is_empty() {
+            return Err(format!("trailing input: {:?}", p.rest));
+        }
+
+        let local = DateTime::from_civil(year, month, day, hour, minute, second)?;
+        Ok(match offset {
+            None => local,
+            Some(o) => DateTime {
+                nanos: local.nanos - i128::from(o) * NANOS_PER_SEC,
+                offset: Some(o),
+            },
+        })
+    }
+}
+
+/// RFC 3339: `2024-01-02T03:04:05Z`, with `.fffffffff` when there are
+/// sub-second nanos and `+hh:mm` for zoned values.
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = self.civil();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            c.year, c.month, c.day, c.hour, c.minute, c.second
+        )?;
+        if c.nanosecond != 0 {
+            write!(f, ".{:09}", c.nanosecond)?;
+        }
+        match self.offset {
+            None => write!(f, "Z"),
+            Some(o) => write!(f, "{}", offset_string(o, ":")),
+        }
+    }
+}
+
+fn offset_string(offset_secs: i32, sep: &str) -> String {
+    let sign = if offset_secs < 0 { '-' } else { '+' };
+    let abs = offset_secs.abs();
+    format!("{}{:02}{}{:02}", sign, abs / 3600, sep, abs % 3600 / 60)
+}
+
+impl Duration {
+    pub fn from_secs(secs: i64) -> Self {
+        Duration { nanos: i128::from(secs) * NANOS_PER_SEC }
+    }
+
+    pub fn from_millis(ms: i64) -> Self {
+        Duration { nanos: i128::from(ms) * 1_000_000 }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        (self.nanos / NANOS_PER_SEC) as i64
+    }
+
+    pub fn millis(&self) -> i64 {
+        (self.nanos / 1_000_000) as i64
+    }
+}
+
+/// Seconds with the fractional part trimmed: `90s`, `1.5s`, `-0.001s`.
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.nanos < 0 { "-" } else { "" };
+        let abs = self.nanos.abs();
+        let (secs, frac) = (abs / NANOS_PER_SEC, abs % NANOS_PER_SEC);
+        if frac == 0 {
+            write!(f, "{}{}s", sign, secs)
+        } else {
+            let digits = format!("{:09}", frac);
+            write!(f, "{}{}.{}s", sign, secs, digits.trim_end_matches('0'))
+        }
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn literal(&mut self, ch: char) -> Result<(), String> {
+        match self.rest.strip_prefix(ch) {
+            Some(r) => {
+                self.rest = r;
+                Ok(())
+            }
+            None => Err(format!("expected {:?} at {:?}", ch, self.rest)),
+        }
+    }
+
+    /// Up to `max` digits (at least one).
+    fn number(&mut self, max: usize) -> Result<i64, String> {
+        let len = self
+            .rest
+            .char_indices()
+            .take(max)
+            .take_while(|(_, c)| c.is_ascii_digit())
+            .count();
+        if len == 0 {
+            return Err(format!("expected a number at {:?}", self.rest));
+        }
+        let (digits, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        digits.parse().map_err(|e| format!("bad number {:?}: {}", digits, e))
+    }
+
+    fn signed_number(&mut self, max: usize) -> Result<i64, String> {
+        if let Some(r) = self.rest.strip_prefix('-') {
+            self.rest = r;
+            return Ok(-self.number(max)?);
+        }
+        self.number(max)
+    }
+
+    fn month_abbrev(&mut self) -> Result<u32, String> {
+        for (i, name) in MONTH_NAMES.iter().enumerate() {
+            let abbrev = &name[..3];
+            if self.rest.get(..3).is_some_and(|s| s.eq_ignore_ascii_case(abbrev)) {
+                self.rest = &self.rest[3..];
+                return Ok(i as u32 + 1);
+            }
+        }
+        Err(format!("expected a month name at {:?}", self.rest))
+    }
+
+    /// `Z`, `+hhmm`, or `+hh:mm`.
+    fn offset(&mut self) -> Result<i32, String> {
+        if let Some(r) = self.rest.strip_prefix('Z') {
+            self.rest = r;
+            return Ok(0);
+        }
+        let sign = match self.rest.chars().next() {
+            Some('+') => 1,
+            Some('-') => -1,
+            _ => return Err(format!("expected a UTC offset at {:?}", self.rest)),
+        };
+        self.rest = &self.rest[1..];
+        let hours = self.number(2)?;
+        if let Some(r) = self.rest.strip_prefix(':') {
+            self.rest = r;
+        }
+        let minutes = self.number(2)?;
+        Ok(sign * (hours * 3600 + minutes * 60) as i32)
+    }
+}
@@ -0,0 +1,455 @@
+//! Stable codes for diagnostics, and what `rusp explain CODE` prints.
+//!
+//! Errors are plain strings all the way up, so a code isn't attached
+//! where an error is made: `classify` recognizes the message when it's
+//! reported, knowing which stage it came from. A message no entry
+//! recognizes gets the stage's catch-all code. Lints have codes too
+//! (`W0xx`), so `--deny` and `--allow` take either.
+//!
+//! Codes are never reused or renumbered; a retired one stays in `ALL`.
+//!
+//! Type mismatches between long types are rendered as a diff
+//! (`type_mismatch`): one copy of the type with the parts that differ
+//! marked, which `paint` colors for a terminal.
+
+use crate::ast::Type;
+
+/// Where in the pipeline a diagnostic comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Parse,
+    Type,
+    Runtime,
+    Lint,
+}
+
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub stage: Stage,
+    /// A line for `rusp explain`'s list.
+    pub title: &'static str,
+    /// Any of these in a message means it's this one. Empty for a
+    /// stage's catch-all, and for lints (known by name instead).
+    patterns: &'static [&'static str],
+    /// For a lint, its name.
+    pub lint: Option<&'static str>,
+    /// What `rusp explain CODE` prints after the title.
+    pub explanation: &'static str,
+}
+
+pub const ALL: &[Diagnostic] = &[
+    Diagnostic {
+        code: "E001",
+        stage: Stage::Parse,
+        title: "unexpected end of input",
+        patterns: &["Unexpected end of input"],
+        lint: None,
+        explanation: "The input stopped in the middle of a form: a `(` or `[` without its closer, or a string \
+without its closing quote.\n\n    (defn f [x: i32] -> i32\n      (* x 2)\n\nAdd the missing `)`.",
+    },
+    Diagnostic {
+        code: "E002",
+        stage: Stage::Parse,
+        title: "invalid number",
+        patterns: &["Invalid number"],
+        lint: None,
+        explanation: "Something that starts like a number doesn't read as one: letters run into it, or it's \
+too big for an `i64`.\n\n    (+ 5x 1)\n    99999999999999999999\n\nSeparate the number from what \
+follows it, or use an `f64` (`1e20`).",
+    },
+    Diagnostic {
+        code: "E003",
+        stage: Stage::Parse,
+        title: "input nested too deep",
+        patterns: &["forms deep"],
+        lint: None,
+        explanation: "Forms are nested past the reader's depth limit (256 levels unless the embedder set \
+another). Deep nesting is usually generated code: build the structure with a loop or a fold instead.",
+    },
+    Diagnostic {
+        code: "E004",
+        stage: Stage::Parse,
+        title: "`#_` or `#?` without a form",
+        patterns: &["`#_` needs", "#? branch"],
+        lint: None,
+        explanation: "`#_` discards the one form after it, and each `#?` branch is a key followed by a \
+form; the form is missing, or a `#?` key isn't `:interp`, `:jit` or `:default`.\n\n    \
+(list 1 #_)\n    #?(:interp 1 :native 2)",
+    },
+    Diagnostic {
+        code: "E009",
+        stage: Stage::Parse,
+        title: "parse error",
+        patterns: &[],
+        lint: None,
+        explanation: "The reader couldn't make a form out of the input here: a stray `)`, a character that \
+can't start a form, or text left over after a complete one.",
+    },
+    Diagnostic {
+        code: "E010",
+        stage: Stage::Type,
+        title: "undefined name",
+        patterns: &["Undefined variable"],
+        lint: None,
+        explanation: "A name is used that nothing defines at that point. Names are defined by `def`, `defn`, \
+`let`, parameters and patterns, and only from their definition on.\n\n    (defn f [] -> i32 (g 1))\n\n\
+Define `g` before `f`, or check the spelling (`(bindings)` lists what's visible).",
+    },
+    Diagnostic {
+        code: "E011",
+        stage: Stage::Type,
+        title: "argument of the wrong type",
+        patterns: &["Type mismatch in argument"],
+        lint: None,
+        explanation: "A function was called with an argument its signature doesn't accept.\n\n    \
+(defn twice [x: i32] -> i32 (* x 2))\n    (twice 2.5)\n\nConvert the argument (`to-i64`, \
+`bytes->string`, ...) or change the parameter's type. `rusp run --explain-types` shows where each type \
+came from.",
+    },
+    Diagnostic {
+        code: "E012",
+        stage: Stage::Type,
+        title: "wrong number of arguments",
+        patterns: &["Wrong number of arguments", "arguments:", "requires 3 arguments", "takes no arguments"],
+        lint: None,
+        explanation: "A function or special form was given more or fewer arguments than it takes.\n\n    \
+(defn add [a: i32 b: i32] -> i32 (+ a b))\n    (add 1)\n\nEvery parameter needs an argument; to call \
+with fewer, wrap it: `(fn [b: i32] -> i32 (add 1 b))`.",
+    },
+    Diagnostic {
+        code: "E013",
+        stage: Stage::Type,
+        title: "`if` with a non-bool condition or mismatched branches",
+        patterns: &["If condition must be bool", "If branches must have same type"],
+        lint: None,
+        explanation: "An `if`'s condition must be a `bool` (there is no truthiness), and both branches must \
+have the same type, since either can be the result.\n\n    (if 1 \"yes\" \"no\")\n    \
+(if true 10 3.14)\n\nCompare explicitly (`(> n 0)`), and convert one branch to the other's type.",
+    },
+    Diagnostic {
+        code: "E014",
+        stage: Stage::Type,
+        title: "value doesn't match its annotation",
+        patterns: &["Type mismatch: ", "Return type mismatch", "the function returns", "the generator yields"],
+        lint: None,
+        explanation: "A `def`, `let`, `return` or function body produces a value of a different type than \
+was declared for it.\n\n    (defn name [x: i32] -> i32 \"x\")\n\nFix the annotation, or convert \
+the value to the declared type.",
+    },
+    Diagnostic {
+        code: "E015",
+        stage: Stage::Type,
+        title: "calling something that isn't a function",
+        patterns: &["Cannot call non-function"],
+        lint: None,
+        explanation: "The head of a call form has a type that isn't a function.\n\n    (def x 1)\n    (x 2)\n\n\
+To make a list of values, use `(list ...)`.",
+    },
+    Diagnostic {
+        code: "E016",
+        stage: Stage::Type,
+        title: "pattern doesn't fit what's matched",
+        patterns: &["does not match scrutinee", "pattern requires", "or-pattern", "guard expression must be"],
+        lint: None,
+        explanation: "A `match` arm's pattern can never match a value of the scrutinee's type, or its guard \
+isn't a `bool`.\n\n    (match 1 (\"one\" 1) (_ 0))\n\nPatterns must have the scrutinee's type: a \
+literal of that type, `nil` or `cons` for a list, or `_`.",
+    },
+    Diagnostic {
+        code: "E017",
+        stage: Stage::Type,
+        title: "`match` isn't exhaustive",
+        patterns: &["match is not exhaustive"],
+        lint: None,
+        explanation: "Some values of the scrutinee's type match none of the arms, so the `match` could fail \
+at run time. The message lists patterns that aren't covered.\n\n    (match xs (nil 0))\n\nAdd arms for \
+them, or a final `(_ ...)`.",
+    },
+    Diagnostic {
+        code: "E018",
+        stage: Stage::Type,
+        title: "control form out of place",
+        patterns: &[
+            "recur must be in tail position",
+            "recur expects",
+            "is only allowed inside",
+            "yield can't be used as a value",
+            "return-from",
+            "block ",
+        ],
+        lint: None,
+        explanation: "`recur`, `return`, `return-from`, `yield` and `defer` only work in certain places: \
+`recur` as the last thing a `loop` or function does (with one value per binding), `return` inside a \
+function, `return-from` inside the `block` it names, `yield` as a statement of a `defgen`, `defer` in a \
+function body or `with-open`.\n\n    (defn f [n: i32] -> i32 (+ 1 (recur (- n 1))))",
+    },
+    Diagnostic {
+        code: "E019",
+        stage: Stage::Type,
+        title: "definition not allowed here",
+        patterns: &["only allowed at the top level", "cannot redefine constant"],
+        lint: None,
+        explanation: "`def`, `defconst` and `defparam` define top-level names, and a `defconst` can't be \
+defined again.\n\n    (let x 1 (def y 2))\n\nUse `let` for a local; pick another name for a constant.",
+    },
+    Diagnostic {
+        code: "E020",
+        stage: Stage::Type,
+        title: "unknown type",
+        patterns: &["Unknown type", "Invalid type annotation"],
+        lint: None,
+        explanation: "A type annotation names a type that doesn't exist.\n\n    (defn f [x: int] -> int x)\n\n\
+The integer types are `i32` and `i64`; see the README for the full list.",
+    },
+    Diagnostic {
+        code: "E029",
+        stage: Stage::Type,
+        title: "type error",
+        patterns: &[],
+        lint: None,
+        explanation: "The form doesn't type-check; the message says why. `rusp run --explain-types` prints \
+the checker's reasoning for the failing form.",
+    },
+    Diagnostic {
+        code: "E030",
+        stage: Stage::Runtime,
+        title: "undefined name at run time",
+        patterns: &["Undefined variable"],
+        lint: None,
+        explanation: "Evaluation looked up a name with no binding. The checker usually catches this first; \
+at run time it means the binding was removed (`:forget`) or a function was passed as a value before its \
+definition was evaluated.",
+    },
+    Diagnostic {
+        code: "E031",
+        stage: Stage::Runtime,
+        title: "wrong number of arguments at run time",
+        patterns: &["Wrong number of arguments"],
+        lint: None,
+        explanation: "A function value was called with the wrong number of arguments, typically one whose \
+type the checker only knew as `_`.\n\n    (defn call [f: _] -> _ (f 1 2))\n    (call (fn [x: i32] -> i32 x))",
+    },
+    Diagnostic {
+        code: "E032",
+        stage: Stage::Runtime,
+        title: "no `match` arm matched",
+        patterns: &["No match arm matched"],
+        lint: None,
+        explanation: "None of a `match`'s arms matched the value, which can happen when a guard rules out \
+the last candidate arm. End with a `(_ ...)` arm to handle everything else.",
+    },
+    Diagnostic {
+        code: "E033",
+        stage: Stage::Runtime,
+        title: "calling something that isn't a function at run time",
+        patterns: &["Cannot call non-function"],
+        lint: None,
+        explanation: "A value that isn't a function ended up at the head of a call, typically through a \
+parameter typed `_`.",
+    },
+    Diagnostic {
+        code: "E034",
+        stage: Stage::Runtime,
+        title: "division by zero",
+        patterns: &["Division by zero"],
+        lint: None,
+        explanation: "A `/` had zero as its divisor.\n\n    (/ 1 0)\n\nCheck the divisor \
+first: `(if (= d 0) 0 (/ n d))`.",
+    },
+    Diagnostic {
+        code: "E035",
+        stage: Stage::Runtime,
+        title: "index or range out of bounds",
+        patterns: &["out of bounds", "out of range", "Index "],
+        lint: None,
+        explanation: "An index (`nth`) or range (`substring`, `bytes-slice`) reaches past the end of the \
+value.\n\n    (nth 3 (list 1 2))\n\nCheck `length` first.",
+    },
+    Diagnostic {
+        code: "E036",
+        stage: Stage::Runtime,
+        title: "builtin given the wrong kind of value",
+        patterns: &[" requires ", " expects "],
+        lint: None,
+        explanation: "A builtin got a value it can't work with. The message names the builtin and what it \
+needs; the checker lets these through when the argument's type was `_`.\n\n    \
+(defn len [x: _] -> i32 (length x))\n    (len 5)",
+    },
+    Diagnostic {
+        code: "E049",
+        stage: Stage::Runtime,
+        title: "runtime error",
+        patterns: &[],
+        lint: None,
+        explanation: "Evaluation failed; the message says why. Forms before the failing one have taken \
+effect.",
+    },
+    Diagnostic {
+        code: "W001",
+        stage: Stage::Lint,
+        title: "definition shadows a builtin",
+        patterns: &[],
+        lint: Some(crate::lint::SHADOW_BUILTIN),
+        explanation: "A top-level definition has the name of a builtin, which it replaces from then on.\n\n    \
+(defn print [x: i32] -> i32 x)\n\nRename it, or mark a deliberate replacement with \
+`^{:allow :shadow-builtin}`.",
+    },
+    Diagnostic {
+        code: "W002",
+        stage: Stage::Lint,
+        title: "`match` arm can never be chosen",
+        patterns: &[],
+        lint: Some(crate::lint::REDUNDANT_ARM),
+        explanation: "Every value this arm matches is already matched by an arm before it.\n\n    \
+(match n (_ 0) (1 1))\n\nRemove it, or move it above the arm that covers it.",
+    },
+    Diagnostic {
+        code: "W003",
+        stage: Stage::Lint,
+        title: "call to a deprecated function",
+        patterns: &[],
+        lint: Some(crate::lint::DEPRECATED),
+        explanation: "The function was defined with `^{:deprecated ...}`; the message carries its note, \
+which usually names the replacement.",
+    },
+    Diagnostic {
+        code: "W004",
+        stage: Stage::Lint,
+        title: "value of a top-level expression is unused",
+        patterns: &[],
+        lint: Some(crate::lint::UNUSED_RESULT),
+        explanation: "When a file runs, the value of each top-level form is thrown away, so an \
+expression there is only worth running for what it does.\n\n    \
+(fact 10)\n\nPrint it with `(println (fact 10))`, bind it with `def`, or drop it.",
+    },
+];
+
+/// The entry `message` from `stage` is.
+pub fn classify(stage: Stage, message: &str) -> &'static Diagnostic {
+    let mut entries = ALL.iter().filter(|d| d.stage == stage);
+    entries
+        .clone()
+        .find(|d| d.patterns.iter().any(|p| message.contains(p)))
+        .or_else(|| entries.find(|d| d.patterns.is_empty()))
+        .expect("every stage has a catch-all")
+}
+
+/// The entry for `code` (any case).
+pub fn lookup(code: &str) -> Option<&'static Diagnostic> {
+    ALL.iter().find(|d| d.code.eq_ignore_ascii_case(code))
+}
+
+/// The lint `name_or_code` refers to (`shadow-builtin` or `W001`).
+pub fn lint_name(name_or_code: &str) -> Option<&'static str> {
+    match lookup(name_or_code) {
+        Some(d) => d.lint,
+        None => crate::lint::ALL.iter().copied().find(|&name| name == name_or_code),
+    }
+}
+
+/// `lint`'s code.
+pub fn lint_code(lint: &str) -> Option<&'static str> {
+    ALL.iter().find(|d| d.lint == Some(lint)).map(|d| d.code)
+}
+
+/// What `rusp explain CODE` prints; None for a code that doesn't exist.
+pub fn explain(code: &str) -> Option<String> {
+    let d = lookup(code)?;
+    let lint = d.lint.map(|name| format!(" (lint `{}`)", name)).unwrap_or_default();
+    Some(format!("{}: {}{}\n\n{}", d.code, d.title, lint, d.explanation))
+}
+
+/// One line per code, for `rusp explain` alone.
+pub fn index() -> String {
+    ALL.iter().map(|d| format!("{}  {}", d.code, d.title)).collect::<Vec<_>>().join("\n")
+}
+
+/// Mismatches whose `expected ..., got ...` is longer than this are
+/// shown as a diff.
+const DIFF_OVER: usize = 40;
+
+/// `expected E, got A` for an error message. When that's long and the
+/// two have the same shape (both functions of the same arity, both
+/// lists, ...), it's one copy of the type with each part that differs
+/// written `[-expected-]{+actual+}`, followed by where those parts are:
+///
+/// ```text
+/// fn(i32, [-String-]{+i64+}, bool) -> i32 (parameter 2: expected String, got i64)
+/// ```
+pub fn type_mismatch(expected: &Type, actual: &Type) -> String {
+    let (expected, actual) = (&crate::types::resolve(expected), &crate::types::resolve(actual));
+    let full = format!("expected {}, got {}", expected, actual);
+    if full.len() <= DIFF_OVER {
+        return full;
+    }
+    let mut differences = Vec::new();
+    match diff(expected, actual, &mut Vec::new(), &mut differences) {
+        Some(merged) if !differences.is_empty() => format!("{} ({})", merged, differences.join("; ")),
+        _ => full,
+    }
+}
+
+/// `expected` and `actual` as one type, marking what differs and noting
+/// it in `differences` under `path`; None if they differ at the top.
+fn diff(expected: &Type, actual: &Type, path: &mut Vec<String>, differences: &mut Vec<String>) -> Option<String> {
+    match (expected, actual) {
+        (Type::Function { params: ep, return_type: er }, Type::Function { params: ap, return_type: ar })
+            if ep.len() == ap.len() =>
+        {
+            let params: Vec<String> = ep
+                .iter()
+                .zip(ap)
+                .enumerate()
+                .map(|(i, (e, a))| diff_part(format!("parameter {}", i + 1), e, a, path, differences))
+                .collect();
+            let ret = diff_part("return type".to_string(), er, ar, path, differences);
+            Some(format!("fn({}) -> {}", params.join(", "), ret))
+        }
+        (Type::List(e), Type::List(a)) => Some(format!("List<{}>", diff_part("element".to_string(), e, a, path, differences))),
+        (Type::Ref(e), Type::Ref(a)) => Some(format!("Ref<{}>", diff_part("contents".to_string(), e, a, path, differences))),
+        (Type::Gen(e), Type::Gen(a)) => Some(format!("Gen<{}>", diff_part("element".to_string(), e, a, path, differences))),
+        (Type::Pair(eh, et), Type::Pair(ah, at)) => {
+            let head = diff_part("head".to_string(), eh, ah, path, differences);
+            let tail = diff_part("tail".to_string(), et, at, path, differences);
+            Some(format!("Pair<{}, {}>", head, tail))
+        }
+        (Type::Map(ek, ev), Type::Map(ak, av)) => {
+            let key = diff_part("key".to_string(), ek, ak, path, differences);
+            let value = diff_part("value".to_string(), ev, av, path, differences);
+            Some(format!("Map<{}, {}>", key, value))
+        }
+        (Type::Vector(e), Type::Vector(a)) => Some(format!("Vec<{}>", diff_part("element".to_string(), e, a, path, differences))),
+        (Type::Slice(e), Type::Slice(a)) => Some(format!("Slice<{}>", diff_part("element".to_string(), e, a, path, differences))),
+        (Type::Array(e, en), Type::Array(a, an)) if en == an => {
+            Some(format!("Array<{}, {}>", diff_part("element".to_string(), e, a, path, differences), en))
+        }
+        _ if crate::types::compatible(expected, actual) => Some(expected.to_string()),
+        _ => None,
+    }
+}
+
+/// One part of a type `diff` is going through: merged if it can be,
+/// otherwise marked whole and noted.
+fn diff_part(label: String, expected: &Type, actual: &Type, path: &mut Vec<String>, differences: &mut Vec<String>) -> String {
+    path.push(label);
+    let merged = diff(expected, actual, path, differences).unwrap_or_else(|| {
+        let at = path.iter().rev().cloned().collect::<Vec<_>>().join(" of ");
+        differences.push(format!("{}: expected {}, got {}", at, expected, actual));
+        format!("[-{}-]{{+{}+}}", expected, actual)
+    });
+    path.pop();
+    merged
+}
+
+/// `message` with `type_mismatch`'s markers turned into color: what was
+/// expected in red, what was got in green. For a terminal.
+pub fn paint(message: &str) -> String {
+    if !message.contains("[-") {
+        return message.to_string();
+    }
+    message
+        .replace("[-", "\x1b[31m")
+        .replace("-]", "\x1b[0m")
+        .replace("{+", "\x1b[32m")
+        .replace("+}", "\x1b[0m")
+}
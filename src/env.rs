@@ -1,5 +1,81 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::ast::Type;
+use crate::datetime::{DateTime, Duration};
+use crate::keyword::Keyword;
+use crate::matrix::Matrix;
+use crate::text::Str;
+
+/// An array's elements, shared by the array and its slices.
+pub type Elements = Rc<RefCell<Vec<Value>>>;
+
+/// What a map is keyed by: the values that hash by their contents.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Integer32(i32),
+    Integer64(i64),
+    Bool(bool),
+    String(Str),
+    Keyword(Keyword),
+}
+
+impl MapKey {
+    pub fn of(value: &Value) -> Result<MapKey, String> {
+        match value {
+            Value::Integer32(n) => Ok(MapKey::Integer32(*n)),
+            Value::Integer64(n) => Ok(MapKey::Integer64(*n)),
+            Value::Bool(b) => Ok(MapKey::Bool(*b)),
+            Value::String(s) => Ok(MapKey::String(s.as_str().try_into()?)),
+            Value::Keyword(k) => Ok(MapKey::Keyword(*k)),
+            other => Err(format!("{} can't be a map key", other.type_name())),
+        }
+    }
+
+    pub fn value(&self) -> Value {
+        match self {
+            MapKey::Integer32(n) => Value::Integer32(*n),
+            MapKey::Integer64(n) => Value::Integer64(*n),
+            MapKey::Bool(b) => Value::Bool(*b),
+            MapKey::String(s) => Value::String(s.clone()),
+            MapKey::Keyword(k) => Value::Keyword(*k),
+        }
+    }
+
+    /// The order maps print and give their `keys` in: numbers by value,
+    /// strings and keywords by name.
+    fn order(&self, other: &MapKey) -> Ordering {
+        match (self, other) {
+            (MapKey::Integer32(a), MapKey::Integer32(b)) => a.cmp(b),
+            (MapKey::Integer64(a), MapKey::Integer64(b)) => a.cmp(b),
+            (MapKey::Bool(a), MapKey::Bool(b)) => a.cmp(b),
+            (MapKey::String(a), MapKey::String(b)) => a.as_str().cmp(b.as_str()),
+            (MapKey::Keyword(a), MapKey::Keyword(b)) => a.name().cmp(&b.name()),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            MapKey::Integer32(_) => 0,
+            MapKey::Integer64(_) => 1,
+            MapKey::Bool(_) => 2,
+            MapKey::String(_) => 3,
+            MapKey::Keyword(_) => 4,
+        }
+    }
+}
+
+/// A map's entries, in key order.
+pub fn map_entries(map: &HashMap<MapKey, Value>) -> Vec<(&MapKey, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.order(b));
+    entries
+}
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -7,18 +83,69 @@ pub enum Value {
     Integer64(i64),
     Float(f64),
     Bool(bool),
-    String(String),
-    Function {
-        params: Vec<String>,
-        body: crate::ast::Expr,
-        env: Environment,
-    },
+    /// The value of type `Unit`, what side effects give.
+    Unit,
+    /// Possibly a slice of a larger string; see `text::Str`.
+    String(Str),
+    /// Shared, mutable buffer: `sb-push!` appends in place, so building
+    /// a large string is linear instead of quadratic.
+    StringBuilder(Rc<RefCell<String>>),
+    /// Byte buffer. Shared like `StringBuilder` so in-place writers
+    /// (`write-u16-be!` etc.) are visible through every alias.
+    Bytes(Rc<RefCell<Vec<u8>>>),
+    /// Fixed-size array. Its elements can be replaced in place
+    /// (`array-set!`), but it never grows or shrinks.
+    Array(Elements),
+    /// `items[start..end]` of an array (`slice`), without copying:
+    /// `array-set!` through it writes to the array.
+    Slice { items: Elements, start: usize, end: usize },
+    /// `[1 2 3]`: indexed in O(1). `vec-push` gives a new vector; copies
+    /// of a vector, and `slice`s of it, share its elements, so
+    /// `array-set!` through a slice writes to the vector.
+    Vector(Elements),
+    /// `{:a 1 :b 2}`. `assoc` and `dissoc` give a new map.
+    Map(Rc<HashMap<MapKey, Value>>),
+    /// UTC (`DateTime`) or fixed-offset (`ZonedDateTime`) instant.
+    DateTime(Box<DateTime>),
+    Duration(Box<Duration>),
+    KvStore(Rc<RefCell<crate::kv::KvStore>>),
+    /// Shared like `Bytes`: `mat-set!` writes in place.
+    Matrix(Rc<RefCell<Matrix>>),
+    Keyword(Keyword),
+    /// Mutable cell (`ref`). Copies of the value share the cell, so a
+    /// closure that captures it sees later `set-ref!`s.
+    Ref(Rc<RefCell<Value>>),
+    /// A running `defgen` (or a `map` over one). Copies share the
+    /// position, so consuming through one copy advances them all.
+    Generator(Rc<RefCell<crate::generator::Generator>>),
+    Port(Port),
+    /// Embedder data; see `foreign`.
+    Foreign(Rc<crate::foreign::Foreign>),
+    /// A `defparam`'s bindings, innermost `parameterize` last. Never seen
+    /// by programs: looking the name up gives the current value.
+    Param(Rc<RefCell<Vec<Value>>>),
+    Function(Rc<Closure>),
     BuiltinFunction {
-        name: String,
-        arity: usize,
+        name: &'static str,
+        arity: u32,
         func: fn(&[Value]) -> Result<Value, String>,
     },
+    /// A `defn` the tiered interpreter has JIT-compiled (see `tier`).
+    /// Behaves exactly like the `Function` it replaced.
+    Native(Rc<crate::tier::NativeFn>),
+    /// What a `shift` took out of the evaluation; calling it with a value
+    /// carries on from there.
+    Continuation(Rc<crate::eval::Continuation>),
     List(Vec<Value>),  // List value
+    /// Dotted pair: what `cons` makes when the tail isn't a list.
+    Pair(Rc<(Value, Value)>),
+    /// What `type-of` gives.
+    Type(Rc<Type>),
+    /// A value of a `deftype`.
+    Constructed(Rc<Constructed>),
+    /// A `deftype` constructor that takes fields; calling it makes a
+    /// `Constructed`. One without fields is bound to its value instead.
+    Constructor(Rc<Constructor>),
     Nil,               // Empty list / nil
 }
 
@@ -29,13 +156,50 @@ impl fmt::Display for Value {
             Value::Integer64(n) => write!(f, "{}", n),
             Value::Float(n) => write!(f, "{}", n),
             Value::Bool(b) => write!(f, "{}", b),
+            Value::Unit => write!(f, "()"),
             Value::String(s) => write!(f, "{}", s),
-            Value::Function { params, .. } => {
-                write!(f, "#<function:{}>", params.len())
+            Value::StringBuilder(sb) => write!(f, "#<string-builder:{}>", sb.borrow().len()),
+            Value::Bytes(b) => write!(f, "{}", crate::ast::render_bytes(&b.borrow())),
+            Value::Array(_) | Value::Slice { .. } => {
+                let (items, start, end) = self.view().expect("an array or slice");
+                let items: Vec<String> = items.borrow()[start..end].iter().map(Value::to_string).collect();
+                write!(f, "#[{}]", items.join(" "))
+            }
+            Value::Vector(items) => {
+                let items: Vec<String> = items.borrow().iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(" "))
+            }
+            Value::Map(map) => {
+                let entries: Vec<String> =
+                    map_entries(map).iter().map(|(k, v)| format!("{} {}", k.value(), v)).collect();
+                write!(f, "{{{}}}", entries.join(" "))
+            }
+            Value::DateTime(t) => write!(f, "{}", t),
+            Value::Duration(d) => write!(f, "{}", d),
+            Value::KvStore(db) => {
+                let db = db.borrow();
+                let state = if db.is_open() { "" } else { ":closed" };
+                write!(f, "#<kv-store:{}{}>", db.path(), state)
             }
+            Value::Matrix(m) => {
+                let m = m.borrow();
+                write!(f, "#<matrix:{}x{}>", m.rows(), m.cols())
+            }
+            Value::Keyword(k) => write!(f, "{}", k),
+            Value::Ref(cell) => write!(f, "#<ref:{}>", cell.borrow()),
+            Value::Generator(_) => write!(f, "#<generator>"),
+            Value::Port(Port::Stdout) => write!(f, "#<port:stdout>"),
+            Value::Port(Port::Stderr) => write!(f, "#<port:stderr>"),
+            Value::Port(Port::String(buf)) => write!(f, "#<port:string:{}>", buf.borrow().len()),
+            Value::Port(Port::File(file)) => write!(f, "#<port:file:{}>", file.path),
+            Value::Foreign(foreign) => write!(f, "#<foreign:{}>", foreign.type_name()),
+            Value::Param(cell) => write!(f, "#<param:{}>", cell.borrow().last().map_or(Value::Nil, Value::clone)),
+            Value::Function(closure) => write!(f, "{}", closure),
             Value::BuiltinFunction { name, arity, .. } => {
                 write!(f, "#<builtin:{}:{}>", name, arity)
             }
+            Value::Native(native) => write!(f, "{}", native.source),
+            Value::Continuation(_) => write!(f, "#<continuation>"),
             Value::List(values) => {
                 write!(f, "(")?;
                 for (i, val) in values.iter().enumerate() {
@@ -46,6 +210,29 @@ impl fmt::Display for Value {
                 }
                 write!(f, ")")
             }
+            Value::Pair(pair) => {
+                // `(1 2 . 3)` rather than `(1 . (2 . 3))`.
+                write!(f, "({}", pair.0)?;
+                let mut tail = &pair.1;
+                while let Value::Pair(next) = tail {
+                    write!(f, " {}", next.0)?;
+                    tail = &next.1;
+                }
+                write!(f, " . {})", tail)
+            }
+            Value::Type(ty) => write!(f, "{}", ty),
+            Value::Constructed(value) if value.fields.is_empty() => write!(f, "{}", value.constructor.tag),
+            Value::Constructed(value) => {
+                write!(f, "({}", value.constructor.tag)?;
+                for (i, field) in value.fields.iter().enumerate() {
+                    match value.constructor.labels.get(i) {
+                        Some(label) => write!(f, " :{} {}", label, field)?,
+                        None => write!(f, " {}", field)?,
+                    }
+                }
+                write!(f, ")")
+            }
+            Value::Constructor(ctor) => write!(f, "#<constructor:{}>", ctor.tag),
             Value::Nil => write!(f, "nil"),
         }
     }
@@ -58,19 +245,461 @@ impl Value {
             Value::Integer64(_) => "i64",
             Value::Float(_) => "f64",
             Value::Bool(_) => "bool",
+            Value::Unit => "Unit",
             Value::String(_) => "String",
-            Value::Function { .. } => "function",
+            Value::StringBuilder(_) => "StringBuilder",
+            Value::Bytes(_) => "Bytes",
+            Value::Array(_) => "Array",
+            Value::Slice { .. } => "Slice",
+            Value::Vector(_) => "Vec",
+            Value::Map(_) => "Map",
+            Value::DateTime(t) if t.offset.is_some() => "ZonedDateTime",
+            Value::DateTime(_) => "DateTime",
+            Value::Duration(_) => "Duration",
+            Value::KvStore(_) => "KvStore",
+            Value::Matrix(_) => "Matrix",
+            Value::Keyword(_) => "Keyword",
+            Value::Ref(_) => "Ref",
+            Value::Generator(_) => "Gen",
+            Value::Port(_) => "Port",
+            Value::Foreign(foreign) => foreign.type_name(),
+            Value::Param(_) => "param",
+            Value::Function(_) | Value::Native(_) | Value::Continuation(_) => "function",
             Value::BuiltinFunction { .. } => "builtin",
             Value::List(_) => "list",
+            Value::Pair(_) => "pair",
+            Value::Type(_) => "Type",
+            Value::Constructed(value) => &value.constructor.type_name,
+            Value::Constructor(_) => "function",
             Value::Nil => "nil",
         }
     }
+
+    /// As much of this value's type as the value itself tells: a list's
+    /// element type is its first element's, and a function's parameter
+    /// and return types are the ones it declared.
+    pub fn type_of(&self) -> Type {
+        let unknown = || Box::new(Type::Inferred);
+        match self {
+            Value::Integer32(_) => Type::I32,
+            Value::Integer64(_) => Type::I64,
+            Value::Float(_) => Type::F64,
+            Value::Bool(_) => Type::Bool,
+            Value::Unit => Type::Unit,
+            Value::String(_) => Type::String,
+            Value::StringBuilder(_) => Type::StringBuilder,
+            Value::Bytes(_) => Type::Bytes,
+            Value::Array(items) => {
+                let items = items.borrow();
+                Type::Array(Box::new(items.first().map_or(Type::Inferred, Value::type_of)), items.len())
+            }
+            Value::Slice { items, start, end } => {
+                let items = &items.borrow()[*start..*end];
+                Type::Slice(Box::new(items.first().map_or(Type::Inferred, Value::type_of)))
+            }
+            Value::Vector(items) => Type::Vector(Box::new(items.borrow().first().map_or(Type::Inferred, Value::type_of))),
+            Value::Map(map) => match map.iter().next() {
+                Some((key, value)) => Type::Map(Box::new(key.value().type_of()), Box::new(value.type_of())),
+                None => Type::Map(Box::new(Type::Inferred), Box::new(Type::Inferred)),
+            },
+            Value::DateTime(t) if t.offset.is_some() => Type::ZonedDateTime,
+            Value::DateTime(_) => Type::DateTime,
+            Value::Duration(_) => Type::Duration,
+            Value::KvStore(_) => Type::KvStore,
+            Value::Matrix(_) => Type::Matrix,
+            Value::Keyword(_) => Type::Keyword,
+            Value::Ref(cell) => Type::Ref(Box::new(cell.borrow().type_of())),
+            Value::Generator(_) => Type::Gen(unknown()),
+            Value::Port(_) => Type::Port,
+            Value::Foreign(foreign) => Type::Foreign(foreign.type_name().to_string()),
+            Value::Param(cell) => cell.borrow().last().map_or(Type::List(unknown()), Value::type_of),
+            Value::Function(closure) => closure.signature(),
+            Value::Native(native) => native.source.signature(),
+            Value::BuiltinFunction { arity, .. } => {
+                Type::Function { params: vec![Type::Inferred; *arity as usize], return_type: unknown() }
+            }
+            Value::Continuation(_) => Type::Function { params: vec![Type::Inferred], return_type: unknown() },
+            Value::List(items) => Type::List(Box::new(items.first().map_or(Type::Inferred, Value::type_of))),
+            Value::Pair(pair) => Type::Pair(Box::new(pair.0.type_of()), Box::new(pair.1.type_of())),
+            Value::Type(_) => Type::Type,
+            Value::Constructed(value) => Type::Named(value.constructor.type_name.clone()),
+            Value::Constructor(ctor) => ctor.signature(),
+            Value::Nil => Type::List(unknown()),
+        }
+    }
+
+    /// The elements an array or slice indexes into, and its range of
+    /// them.
+    pub fn view(&self) -> Option<(&Elements, usize, usize)> {
+        match self {
+            Value::Array(items) => Some((items, 0, items.borrow().len())),
+            Value::Slice { items, start, end } => Some((items, *start, *end)),
+            _ => None,
+        }
+    }
+
+    /// This value the way rusp source writes it, as the REPL echoes it:
+    /// like `Display`, except strings anywhere in it are quoted and
+    /// escaped, and floats keep their decimal point.
+    pub fn written(&self) -> String {
+        match self {
+            Value::Float(n) => crate::ast::render_float(*n),
+            Value::String(s) => crate::ast::render_string(s),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Value::written).collect();
+                format!("({})", items.join(" "))
+            }
+            Value::Array(_) | Value::Slice { .. } => {
+                let (items, start, end) = self.view().expect("an array or slice");
+                let items: Vec<String> = items.borrow()[start..end].iter().map(Value::written).collect();
+                format!("#[{}]", items.join(" "))
+            }
+            Value::Vector(items) => {
+                let items: Vec<String> = items.borrow().iter().map(Value::written).collect();
+                format!("[{}]", items.join(" "))
+            }
+            Value::Map(map) => {
+                let entries: Vec<String> =
+                    map_entries(map).iter().map(|(k, v)| format!("{} {}", k.value().written(), v.written())).collect();
+                format!("{{{}}}", entries.join(" "))
+            }
+            Value::Pair(pair) => {
+                let mut out = format!("({}", pair.0.written());
+                let mut tail = &pair.1;
+                while let Value::Pair(next) = tail {
+                    out.push(' ');
+                    out.push_str(&next.0.written());
+                    tail = &next.1;
+                }
+                format!("{} . {})", out, tail.written())
+            }
+            // A struct's fields go in order, unlabeled, since that's how
+            // its constructor takes them.
+            Value::Constructed(value) if !value.fields.is_empty() => {
+                let fields: Vec<String> = value.fields.iter().map(Value::written).collect();
+                format!("({} {})", value.constructor.tag, fields.join(" "))
+            }
+            other => other.to_string(),
+        }
+    }
+}
+
+/// One constructor of a `deftype`: the type it makes, its name and the
+/// types of the fields it takes. A `defstruct`'s also names its fields.
+#[derive(Debug)]
+pub struct Constructor {
+    pub type_name: String,
+    pub tag: String,
+    pub fields: Vec<Type>,
+    /// Field names, for `get`; empty for a `deftype` constructor.
+    pub labels: Vec<String>,
+}
+
+impl Constructor {
+    /// Its type: the fields in, the `deftype` out. A constructor without
+    /// fields is a value of the type rather than a function.
+    pub fn signature(&self) -> Type {
+        let made = Type::Named(self.type_name.clone());
+        if self.fields.is_empty() {
+            return made;
+        }
+        Type::Function { params: self.fields.clone(), return_type: Box::new(made) }
+    }
 }
 
+/// What calling a `Constructor` makes.
+#[derive(Debug)]
+pub struct Constructed {
+    pub constructor: Rc<Constructor>,
+    pub fields: Vec<Value>,
+}
+
+/// An output destination, for `*out*`.
 #[derive(Debug, Clone)]
-pub struct Environment {
+pub enum Port {
+    Stdout,
+    Stderr,
+    /// `string-port`: collects what's written, for `port-string`.
+    String(Rc<RefCell<String>>),
+    /// `open-file`: writes to a file until `close`d.
+    File(Rc<FilePort>),
+}
+
+#[derive(Debug)]
+pub struct FilePort {
+    pub path: String,
+    writer: RefCell<Option<std::io::BufWriter<std::fs::File>>>,
+}
+
+impl FilePort {
+    pub fn create(path: &str) -> Result<FilePort, String> {
+        let file = std::fs::File::create(path).map_err(|e| format!("open-file: {}: {}", path, e))?;
+        Ok(FilePort { path: path.to_string(), writer: RefCell::new(Some(std::io::BufWriter::new(file))) })
+    }
+
+    fn write(&self, text: &str) -> Result<(), String> {
+        use std::io::Write;
+        match self.writer.borrow_mut().as_mut() {
+            Some(w) => w.write_all(text.as_bytes()).map_err(|e| format!("{}: {}", self.path, e)),
+            None => Err(format!("port {} is closed", self.path)),
+        }
+    }
+
+    /// Flush and close; false if it was already closed.
+    pub fn close(&self) -> Result<bool, String> {
+        use std::io::Write;
+        match self.writer.borrow_mut().take() {
+            Some(mut w) => w.flush().map(|()| true).map_err(|e| format!("{}: {}", self.path, e)),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Close a resource `with-open` or `close` was given: file ports, kv
+/// stores and foreign values (which runs their finalizer). Other ports
+/// have nothing to release. False if it was already closed.
+pub fn close(value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Port(Port::File(file)) => file.close(),
+        Value::Port(_) => Ok(false),
+        Value::KvStore(db) => Ok(db.borrow_mut().close()),
+        Value::Foreign(foreign) => Ok(foreign.finalize()),
+        other => Err(format!("close: {} is not a resource", other.type_name())),
+    }
+}
+
+thread_local! {
+    /// `*out*`'s bindings. `print` and `println` write to the last one.
+    static OUT: Rc<RefCell<Vec<Value>>> = Rc::new(RefCell::new(vec![Value::Port(Port::Stdout)]));
+}
+
+thread_local! {
+    /// Where the `stdout` and `stderr` ports write: the process's own
+    /// streams, unless a host embedding the interpreter replaced them.
+    static STDOUT: RefCell<Box<dyn std::io::Write>> = RefCell::new(Box::new(std::io::stdout()));
+    static STDERR: RefCell<Box<dyn std::io::Write>> = RefCell::new(Box::new(std::io::stderr()));
+}
+
+/// Send what this thread's programs write to `stdout` to `writer`
+/// instead, returning the writer it replaces.
+pub fn set_stdout(writer: Box<dyn std::io::Write>) -> Box<dyn std::io::Write> {
+    STDOUT.with(|out| std::mem::replace(&mut *out.borrow_mut(), writer))
+}
+
+/// `set_stdout` for `stderr`.
+pub fn set_stderr(writer: Box<dyn std::io::Write>) -> Box<dyn std::io::Write> {
+    STDERR.with(|err| std::mem::replace(&mut *err.borrow_mut(), writer))
+}
+
+/// What `capture_output` collected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// A writer that appends to a shared buffer.
+struct Collect(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for Collect {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run `run` with `stdout` and `stderr` going to buffers, returning what
+/// it wrote to each. Output sent to a string or file port isn't included.
+pub fn capture_output<T>(run: impl FnOnce() -> T) -> (T, Captured) {
+    let out = Rc::new(RefCell::new(Vec::new()));
+    let err = Rc::new(RefCell::new(Vec::new()));
+    let saved_out = set_stdout(Box::new(Collect(Rc::clone(&out))));
+    let saved_err = set_stderr(Box::new(Collect(Rc::clone(&err))));
+    let result = run();
+    set_stdout(saved_out);
+    set_stderr(saved_err);
+    let text = |buf: &Rc<RefCell<Vec<u8>>>| String::from_utf8_lossy(&buf.borrow()).into_owned();
+    (result, Captured { stdout: text(&out), stderr: text(&err) })
+}
+
+/// Write `text` to the `stdout` stream, whatever `*out*` is.
+fn write_stdout(text: &str) -> Result<(), String> {
+    STDOUT.with(|out| out.borrow_mut().write_all(text.as_bytes())).map_err(|e| format!("stdout: {}", e))
+}
+
+/// Write `text` to the current `*out*`.
+fn write_out(text: &str) -> Result<(), String> {
+    let port = OUT.with(|out| out.borrow().last().cloned());
+    match port {
+        Some(Value::Port(Port::Stderr)) => {
+            STDERR.with(|err| err.borrow_mut().write_all(text.as_bytes())).map_err(|e| format!("stderr: {}", e))?
+        }
+        Some(Value::Port(Port::String(buf))) => buf.borrow_mut().push_str(text),
+        Some(Value::Port(Port::File(file))) => file.write(text)?,
+        _ => write_stdout(text)?,
+    }
+    Ok(())
+}
+
+/// A `fn` / `defn` value. Shared behind an `Rc` so copying a function
+/// value doesn't copy its body and captured environment.
+#[derive(Debug)]
+pub struct Closure {
+    /// The `defn` / `defgen` name; None for a `fn`.
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    /// As declared, `Inferred` where there was no annotation. Only for
+    /// display: checking is done by then.
+    pub param_types: Vec<Type>,
+    pub return_type: Type,
+    pub meta: crate::ast::Meta,
+    pub body: crate::ast::Expr,
+    pub env: Environment,
+    /// Made by `defgen`: a call returns a generator over `body` instead
+    /// of running it.
+    pub generator: bool,
+    /// `body` has a `defer` of its own, so a call needs a defer scope.
+    pub defers: bool,
+    /// A multi-arity `defn`'s other arities, for the calls whose number
+    /// of arguments doesn't fit `params`.
+    pub arities: Vec<Rc<Closure>>,
+}
+
+impl Closure {
+    /// Its type as declared; a `defgen` gives a `Gen`.
+    pub fn signature(&self) -> Type {
+        if self.arities.is_empty() {
+            return self.own_signature();
+        }
+        let mut arities: Vec<&Closure> = self.all_arities().collect();
+        arities.sort_by_key(|c| c.params.len());
+        Type::MultiArity(arities.into_iter().map(Closure::own_signature).collect())
+    }
+
+    fn own_signature(&self) -> Type {
+        let return_type = if self.generator { Type::Gen(Box::new(Type::Inferred)) } else { self.return_type.clone() };
+        Type::Function { params: self.param_types.clone(), return_type: Box::new(return_type) }
+    }
+
+    /// This closure, then its other arities.
+    fn all_arities(&self) -> impl Iterator<Item = &Closure> {
+        std::iter::once(self).chain(self.arities.iter().map(|c| &**c))
+    }
+
+    /// The arity a call with `count` arguments runs, if there's one.
+    pub fn arity(&self, count: usize) -> Option<&Closure> {
+        self.all_arities().find(|c| c.params.len() == count)
+    }
+
+    /// The form that made it, rebuilt from what it kept: a `defn-` comes
+    /// back as a `defn`, and a `#(...)` as the `fn` it stood for.
+    pub fn source(&self) -> crate::ast::Expr {
+        if self.arities.is_empty() {
+            return self.own_source();
+        }
+        crate::ast::Expr::MultiArity(self.all_arities().map(Closure::own_source).collect())
+    }
+
+    fn own_source(&self) -> crate::ast::Expr {
+        use crate::ast::Expr;
+        let params = self.params.iter().cloned().zip(self.param_types.iter().cloned()).collect();
+        let body = Box::new(self.body.clone());
+        match &self.name {
+            Some(name) if self.generator => Expr::Defgen { name: name.clone(), params, body, meta: self.meta.clone() },
+            Some(name) => Expr::Defn {
+                name: name.clone(),
+                params,
+                return_type: self.return_type.clone(),
+                body,
+                private: false,
+                meta: self.meta.clone(),
+            },
+            None => {
+                let return_type = (self.return_type != Type::Inferred).then(|| self.return_type.clone());
+                Expr::Lambda { params, return_type, body }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match (self.generator, &self.name) {
+            (true, _) => "defgen",
+            (false, Some(_)) => "function",
+            (false, None) => "fn",
+        };
+        if self.arities.is_empty() {
+            let params = self.params.iter().zip(&self.param_types);
+            return write!(f, "{}", describe_function(kind, self.name.as_deref(), params, &self.return_type));
+        }
+        // `#<function greet [name] | [name greeting]>`
+        let arities: Vec<String> = self
+            .all_arities()
+            .map(|c| describe_params(c.params.iter().zip(&c.param_types), &c.return_type))
+            .collect();
+        write!(f, "#<{} {} {}>", kind, self.name.as_deref().unwrap_or(""), arities.join(" | "))
+    }
+}
+
+/// How a function displays: `#<function add [a: i32 b: i32] -> i32>`.
+/// Types that weren't written down are left out, as in the source:
+/// `#<fn [x]>`.
+pub fn describe_function<'a>(
+    kind: &str,
+    name: Option<&str>,
+    params: impl IntoIterator<Item = (&'a String, &'a Type)>,
+    return_type: &Type,
+) -> String {
+    let mut out = format!("#<{}", kind);
+    if let Some(name) = name {
+        out += &format!(" {}", name);
+    }
+    out + " " + &describe_params(params, return_type) + ">"
+}
+
+/// `[a: i32 b] -> i32`, the part of `describe_function` after the name.
+fn describe_params<'a>(params: impl IntoIterator<Item = (&'a String, &'a Type)>, return_type: &Type) -> String {
+    let params: Vec<String> = params
+        .into_iter()
+        .map(|(name, ty)| if *ty == Type::Inferred { name.clone() } else { format!("{}: {}", name, ty) })
+        .collect();
+    let mut out = format!("[{}]", params.join(" "));
+    if *return_type != Type::Inferred {
+        out += &format!(" -> {}", return_type);
+    }
+    out
+}
+
+/// One scope's bindings, and the scope it's nested in.
+#[derive(Default)]
+struct Scope {
     values: HashMap<String, Value>,
-    parent: Option<Box<Environment>>,
+    parent: Option<Environment>,
+}
+
+/// A chain of scopes. Cloning one shares it rather than copying it: a
+/// closure holds the very scopes it was made in, so it sees what's bound
+/// there later — a `defn` can call itself, or one defined after it — and
+/// a `set` through any handle shows through all of them.
+#[derive(Clone)]
+pub struct Environment {
+    scope: Rc<RefCell<Scope>>,
+}
+
+impl fmt::Debug for Environment {
+    // A scope can hold a closure that holds the scope, so this names the
+    // bindings instead of descending into them.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scope = self.scope.borrow();
+        let mut names: Vec<_> = scope.values.keys().collect();
+        names.sort();
+        f.debug_struct("Environment").field("names", &names).finish_non_exhaustive()
+    }
 }
 
 impl Default for Environment {
@@ -81,259 +710,1002 @@ impl Default for Environment {
 
 impl Environment {
     pub fn new() -> Self {
-        let mut env = Environment {
-            values: HashMap::new(),
-            parent: None,
-        };
+        let mut env = Environment::blank();
         
-        env.values.insert("+".to_string(), Value::BuiltinFunction {
-            name: "+".to_string(),
-            arity: 2,
+        // Arithmetic on two numbers of the same kind: integers, where mixed
+        // widths promote to i64 (see `Ints`) and overflow is an error rather
+        // than a silent wrap, or two f64s. Mixing an integer with an f64
+        // takes an explicit conversion.
+        let arith: [(&str, u32, BuiltinFn); 5] = [
+            ("+", 2, |args| arith("+", args, i32::checked_add, i64::checked_add, |a, b| a + b)),
+            ("-", 2, |args| arith("-", args, i32::checked_sub, i64::checked_sub, |a, b| a - b)),
+            ("*", 2, |args| arith("*", args, i32::checked_mul, i64::checked_mul, |a, b| a * b)),
+            ("/", 2, |args| {
+                if matches!(args[1], Value::Integer32(0) | Value::Integer64(0) | Value::Float(0.0)) {
+                    return Err("Division by zero".to_string());
+                }
+                arith("/", args, i32::checked_div, i64::checked_div, |a, b| a / b)
+            }),
+            ("to-i64", 1, |args| match &args[0] {
+                Value::Integer32(n) => Ok(Value::Integer64(i64::from(*n))),
+                Value::Integer64(n) => Ok(Value::Integer64(*n)),
+                other => Err(format!("to-i64 requires an integer, got {}", other.type_name())),
+            }),
+        ];
+        for (name, arity, func) in arith {
+            env.set(name.to_string(), Value::BuiltinFunction { name, arity, func });
+        }
+        // Conversions between the numeric types. Narrowing ones fail
+        // rather than wrap; `f64->i32` and `f64->i64` truncate toward zero.
+        let conversions: [(&str, u32, BuiltinFn); 6] = [
+            ("i32->i64", 1, |args| match &args[0] {
+                Value::Integer32(n) => Ok(Value::Integer64(i64::from(*n))),
+                other => Err(format!("i32->i64 requires an i32, got {}", other.type_name())),
+            }),
+            ("i32->f64", 1, |args| match &args[0] {
+                Value::Integer32(n) => Ok(Value::Float(f64::from(*n))),
+                other => Err(format!("i32->f64 requires an i32, got {}", other.type_name())),
+            }),
+            ("i64->i32", 1, |args| match &args[0] {
+                Value::Integer64(n) => i32::try_from(*n)
+                    .map(Value::Integer32)
+                    .map_err(|_| format!("i64->i32: {} does not fit in i32", n)),
+                other => Err(format!("i64->i32 requires an i64, got {}", other.type_name())),
+            }),
+            ("i64->f64", 1, |args| match &args[0] {
+                Value::Integer64(n) => Ok(Value::Float(*n as f64)),
+                other => Err(format!("i64->f64 requires an i64, got {}", other.type_name())),
+            }),
+            ("f64->i32", 1, |args| match &args[0] {
+                // NaN fails both bounds.
+                Value::Float(x) if (f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&x.trunc()) => {
+                    Ok(Value::Integer32(x.trunc() as i32))
+                }
+                Value::Float(x) => Err(format!("f64->i32: {} does not fit in i32", x)),
+                other => Err(format!("f64->i32 requires an f64, got {}", other.type_name())),
+            }),
+            ("f64->i64", 1, |args| float_to_int("f64->i64", args, f64::trunc)),
+        ];
+        for (name, arity, func) in conversions {
+            env.set(name.to_string(), Value::BuiltinFunction { name, arity, func });
+        }
+        
+        let float_tests: [(&str, u32, BuiltinFn); 3] = [
+            ("nan?", 1, |args| float_test("nan?", args, f64::is_nan)),
+            ("is-nan?", 1, |args| float_test("is-nan?", args, f64::is_nan)),
+            ("is-finite?", 1, |args| float_test("is-finite?", args, f64::is_finite)),
+        ];
+        for (name, arity, func) in float_tests {
+            env.set(name.to_string(), Value::BuiltinFunction { name, arity, func });
+        }
+        // Approximate equality; see `approx_eq` for the tolerance.
+        let approx: [(&str, u32, BuiltinFn); 3] = [
+            ("approx=", 3, |args| match (&args[0], &args[1], &args[2]) {
+                (Value::Float(a), Value::Float(b), Value::Float(eps)) => Ok(Value::Bool(approx_eq(*a, *b, *eps))),
+                _ => Err("approx= requires three floats".to_string()),
+            }),
+            ("approx-eq", 2, |args| match (&args[0], &args[1]) {
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(approx_eq(*a, *b, DEFAULT_EPSILON))),
+                _ => Err("approx-eq requires two floats".to_string()),
+            }),
+            ("assert-approx-eq", 2, |args| match (&args[0], &args[1]) {
+                (Value::Float(actual), Value::Float(expected)) if approx_eq(*actual, *expected, DEFAULT_EPSILON) => {
+                    Ok(Value::Bool(true))
+                }
+                (Value::Float(actual), Value::Float(expected)) => Err(format!(
+                    "assert-approx-eq failed: expected {}, got {} (off by {:e}, tolerance {:e})",
+                    expected,
+                    actual,
+                    (actual - expected).abs(),
+                    DEFAULT_EPSILON
+                )),
+                _ => Err("assert-approx-eq requires two floats".to_string()),
+            }),
+        ];
+        for (name, arity, func) in approx {
+            env.set(name.to_string(), Value::BuiltinFunction { name, arity, func });
+        }
+        // Rounding. The `-int` variants are the only way from f64 to an
+        // integer; they fail rather than saturate when the result
+        // doesn't fit in i64.
+        let rounding: [(&str, u32, BuiltinFn); 8] = [
+            ("floor", 1, |args| float_unary("floor", args, f64::floor)),
+            ("ceil", 1, |args| float_unary("ceil", args, f64::ceil)),
+            ("round", 1, |args| float_unary("round", args, f64::round)),
+            ("trunc", 1, |args| float_unary("trunc", args, f64::trunc)),
+            ("floor-int", 1, |args| float_to_int("floor-int", args, f64::floor)),
+            ("ceil-int", 1, |args| float_to_int("ceil-int", args, f64::ceil)),
+            ("round-int", 1, |args| float_to_int("round-int", args, f64::round)),
+            ("trunc-int", 1, |args| float_to_int("trunc-int", args, f64::trunc)),
+        ];
+        for (name, arity, func) in rounding {
+            env.set(name.to_string(), Value::BuiltinFunction { name, arity, func });
+        }
+        // Work on any numeric type: integers promote like `+`, floats
+        // must pair with floats.
+        let numeric: [(&str, u32, BuiltinFn); 5] = [
+            ("min", 2, |args| num_binary("min", &args[0], &args[1], i64::min, f64::min)),
+            ("max", 2, |args| num_binary("max", &args[0], &args[1], i64::max, f64::max)),
+            ("abs", 1, |args| match &args[0] {
+                Value::Integer32(n) => n.checked_abs().map(Value::Integer32).ok_or_else(|| format!("(abs {}) overflows i32", n)),
+                Value::Integer64(n) => n.checked_abs().map(Value::Integer64).ok_or_else(|| format!("(abs {}) overflows i64", n)),
+                Value::Float(x) => Ok(Value::Float(x.abs())),
+                other => Err(format!("abs requires a number, got {}", other.type_name())),
+            }),
+            ("neg", 1, |args| match &args[0] {
+                Value::Integer32(n) => n.checked_neg().map(Value::Integer32).ok_or_else(|| format!("(neg {}) overflows i32", n)),
+                Value::Integer64(n) => n.checked_neg().map(Value::Integer64).ok_or_else(|| format!("(neg {}) overflows i64", n)),
+                Value::Float(x) => Ok(Value::Float(-x)),
+                other => Err(format!("neg requires a number, got {}", other.type_name())),
+            }),
+            ("clamp", 3, |args| {
+                let (x, lo, hi) = (&args[0], &args[1], &args[2]);
+                if num_order(lo, hi) == Some(Ordering::Greater) {
+                    return Err(format!("clamp: lower bound {} is above upper bound {}", lo, hi));
+                }
+                let below_hi = num_binary("clamp", x, hi, i64::min, f64::min)?;
+                num_binary("clamp", lo, &below_hi, i64::max, f64::max)
+            }),
+        ];
+        for (name, arity, func) in numeric {
+            env.set(name.to_string(), Value::BuiltinFunction { name, arity, func });
+        }
+        env.set("nan".to_string(), Value::Float(f64::NAN));
+        env.set("inf".to_string(), Value::Float(f64::INFINITY));
+        
+        // Comparisons promote like arithmetic and also take two f64s, which
+        // follow IEEE 754: every comparison with NaN is false, including
+        // `(= nan nan)`. Use `nan?` to test for it. `=` also compares
+        // keywords.
+        let comparisons: [(&str, u32, BuiltinFn); 5] = [
+            ("=", 2, |args| match (&args[0], &args[1]) {
+                (Value::Keyword(a), Value::Keyword(b)) => Ok(Value::Bool(a == b)),
+                _ => num_cmp("=", args, Ordering::is_eq),
+            }),
+            ("<", 2, |args| num_cmp("<", args, Ordering::is_lt)),
+            (">", 2, |args| num_cmp(">", args, Ordering::is_gt)),
+            ("<=", 2, |args| num_cmp("<=", args, Ordering::is_le)),
+            (">=", 2, |args| num_cmp(">=", args, Ordering::is_ge)),
+        ];
+        for (name, arity, func) in comparisons {
+            env.set(name.to_string(), Value::BuiltinFunction { name, arity, func });
+        }
+        
+        env.set("not".to_string(), Value::BuiltinFunction {
+            name: "not",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Integer32(a + b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Integer64(a + b)),
-                    _ => Err("+ requires two integers of the same type".to_string()),
+                match &args[0] {
+                    Value::Bool(b) => Ok(Value::Bool(!b)),
+                    _ => Err("not requires a boolean".to_string()),
                 }
             },
         });
         
-        env.values.insert("-".to_string(), Value::BuiltinFunction {
-            name: "-".to_string(),
-            arity: 2,
+        env.set("print".to_string(), Value::BuiltinFunction {
+            name: "print",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Integer32(a - b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Integer64(a - b)),
-                    _ => Err("- requires two integers of the same type".to_string()),
+                match &args[0] {
+                    Value::String(s) => write_out(s)?,
+                    v => write_out(&v.to_string())?,
                 }
+                Ok(Value::Unit)
             },
         });
         
-        env.values.insert("*".to_string(), Value::BuiltinFunction {
-            name: "*".to_string(),
-            arity: 2,
+        env.set("println".to_string(), Value::BuiltinFunction {
+            name: "println",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Integer32(a * b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Integer64(a * b)),
-                    _ => Err("* requires two integers of the same type".to_string()),
+                match &args[0] {
+                    Value::String(s) => write_out(&format!("{}\n", s))?,
+                    v => write_out(&format!("{}\n", v))?,
                 }
+                Ok(Value::Unit)
+            },
+        });
+
+        // Output ports. `print` and `println` go to `*out*`, which
+        // `parameterize` can point somewhere else for a while.
+        env.set("stdout".to_string(), Value::Port(Port::Stdout));
+        env.set("stderr".to_string(), Value::Port(Port::Stderr));
+        env.set("*out*".to_string(), Value::Param(OUT.with(Rc::clone)));
+        env.set("string-port".to_string(), Value::BuiltinFunction {
+            name: "string-port",
+            arity: 0,
+            func: |_| Ok(Value::Port(Port::String(Rc::new(RefCell::new(String::new()))))),
+        });
+        env.set("open-file".to_string(), Value::BuiltinFunction {
+            name: "open-file",
+            arity: 1,
+            func: |args| match &args[0] {
+                Value::String(path) => Ok(Value::Port(Port::File(Rc::new(FilePort::create(path)?)))),
+                _ => Err("open-file requires a path string".to_string()),
+            },
+        });
+        env.set("close".to_string(), Value::BuiltinFunction {
+            name: "close",
+            arity: 1,
+            func: |args| close(&args[0]).map(Value::Bool),
+        });
+        env.set("port-string".to_string(), Value::BuiltinFunction {
+            name: "port-string",
+            arity: 1,
+            func: |args| match &args[0] {
+                Value::Port(Port::String(buf)) => Ok(Value::String(buf.borrow().as_str().try_into()?)),
+                other => Err(format!("port-string requires a string port, got {}", other)),
             },
         });
         
-        env.values.insert("/".to_string(), Value::BuiltinFunction {
-            name: "/".to_string(),
+        env.set("type-of".to_string(), Value::BuiltinFunction {
+            name: "type-of",
+            arity: 1,
+            func: |args| Ok(Value::Type(Rc::new(args[0].type_of()))),
+        });
+        env.set("type=".to_string(), Value::BuiltinFunction {
+            name: "type=",
             arity: 2,
+            func: |args| match (&args[0], &args[1]) {
+                (Value::Type(a), Value::Type(b)) => Ok(Value::Bool(a == b)),
+                _ => Err("type= requires two types".to_string()),
+            },
+        });
+        env.set("int?".to_string(), Value::BuiltinFunction {
+            name: "int?",
+            arity: 1,
+            func: |args| Ok(Value::Bool(matches!(args[0], Value::Integer32(_) | Value::Integer64(_)))),
+        });
+        env.set("fn?".to_string(), Value::BuiltinFunction {
+            name: "fn?",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => {
-                        if *b == 0 {
-                            Err("Division by zero".to_string())
-                        } else {
-                            Ok(Value::Integer32(a / b))
-                        }
-                    }
-                    (Value::Integer64(a), Value::Integer64(b)) => {
-                        if *b == 0 {
-                            Err("Division by zero".to_string())
-                        } else {
-                            Ok(Value::Integer64(a / b))
-                        }
-                    }
-                    _ => Err("/ requires two integers of the same type".to_string()),
+                let callable = matches!(
+                    args[0],
+                    Value::Function(_) | Value::BuiltinFunction { .. } | Value::Native(_) | Value::Continuation(_)
+                );
+                Ok(Value::Bool(callable))
+            },
+        });
+        env.set("arity".to_string(), Value::BuiltinFunction {
+            name: "arity",
+            arity: 1,
+            func: |args| match &args[0] {
+                Value::BuiltinFunction { arity, .. } => Ok(Value::Integer32(*arity as i32)),
+                Value::Continuation(_) => Ok(Value::Integer32(1)),
+                other => Ok(Value::Integer32(user_function(other, "arity")?.params.len() as i32)),
+            },
+        });
+        env.set("param-names".to_string(), Value::BuiltinFunction {
+            name: "param-names",
+            arity: 1,
+            func: |args| {
+                let closure = user_function(&args[0], "param-names")?;
+                let names = closure.params.iter().map(|p| Ok(Value::String(p.as_str().try_into()?)));
+                let names: Vec<Value> = names.collect::<Result<_, String>>()?;
+                Ok(if names.is_empty() { Value::Nil } else { Value::List(names) })
+            },
+        });
+        env.set("fn-source".to_string(), Value::BuiltinFunction {
+            name: "fn-source",
+            arity: 1,
+            func: |args| Ok(Value::String(user_function(&args[0], "fn-source")?.source().to_string().try_into()?)),
+        });
+        env.set("meta".to_string(), Value::BuiltinFunction {
+            name: "meta",
+            arity: 1,
+            func: |args| {
+                let meta = match &args[0] {
+                    Value::BuiltinFunction { .. } | Value::Continuation(_) => return Ok(Value::Nil),
+                    other => &user_function(other, "meta")?.meta,
+                };
+                let mut pairs = Vec::with_capacity(meta.len());
+                for (key, value) in meta {
+                    // Literals, so there's nothing to look up.
+                    let value = crate::eval::eval(value, &mut Environment::blank())?;
+                    pairs.push(Value::Pair(Rc::new((Value::Keyword(*key), value))));
                 }
+                Ok(if pairs.is_empty() { Value::Nil } else { Value::List(pairs) })
             },
         });
+        // The simple types by name, to compare `type-of`s against.
+        for ty in [Type::I32, Type::I64, Type::F64, Type::Bool, Type::String, Type::Keyword] {
+            env.set(ty.to_string(), Value::Type(Rc::new(ty)));
+        }
         
-        env.values.insert("+.".to_string(), Value::BuiltinFunction {
-            name: "+.".to_string(),
+        // String operations
+        env.set("str-concat".to_string(), Value::BuiltinFunction {
+            name: "str-concat",
             arity: 2,
             func: |args| {
                 match (&args[0], &args[1]) {
-                    (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
-                    _ => Err("+. requires two floats".to_string()),
+                    (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b).try_into()?)),
+                    _ => Err("str-concat requires two strings".to_string()),
                 }
             },
         });
         
-        env.values.insert("-.".to_string(), Value::BuiltinFunction {
-            name: "-.".to_string(),
+        env.set("string-builder".to_string(), Value::BuiltinFunction {
+            name: "string-builder",
+            arity: 0,
+            func: |_| Ok(Value::StringBuilder(Rc::new(RefCell::new(String::new())))),
+        });
+        
+        env.set("sb-push!".to_string(), Value::BuiltinFunction {
+            name: "sb-push!",
             arity: 2,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
-                    _ => Err("-. requires two floats".to_string()),
+                match &args[0] {
+                    Value::StringBuilder(sb) => {
+                        // Strings go in verbatim; anything else as it prints.
+                        match &args[1] {
+                            Value::String(s) => sb.borrow_mut().push_str(s),
+                            other => sb.borrow_mut().push_str(&other.to_string()),
+                        }
+                        Ok(args[0].clone())
+                    }
+                    _ => Err("sb-push! requires a string builder".to_string()),
                 }
             },
         });
         
-        env.values.insert("*.".to_string(), Value::BuiltinFunction {
-            name: "*.".to_string(),
-            arity: 2,
+        env.set("sb-build".to_string(), Value::BuiltinFunction {
+            name: "sb-build",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
-                    _ => Err("*. requires two floats".to_string()),
+                match &args[0] {
+                    Value::StringBuilder(sb) => Ok(Value::String(sb.borrow().as_str().try_into()?)),
+                    _ => Err("sb-build requires a string builder".to_string()),
                 }
             },
         });
         
-        env.values.insert("/.".to_string(), Value::BuiltinFunction {
-            name: "/.".to_string(),
-            arity: 2,
+        // Slicing: results share the argument's buffer (see `text::Str`).
+        env.set("substring".to_string(), Value::BuiltinFunction {
+            name: "substring",
+            arity: 3,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Float(a), Value::Float(b)) => {
-                        if *b == 0.0 {
-                            Err("Division by zero".to_string())
-                        } else {
-                            Ok(Value::Float(a / b))
+                match (&args[0], &args[1], &args[2]) {
+                    (Value::String(s), Value::Integer32(start), Value::Integer32(end)) => {
+                        // Offsets count characters, not bytes.
+                        let len = s.chars().count();
+                        if *start < 0 || *end < *start || *end as usize > len {
+                            return Err(format!(
+                                "substring range {}..{} out of bounds for length {}",
+                                start, end, len
+                            ));
                         }
+                        let byte = |i: usize| s.char_indices().nth(i).map_or(s.len(), |(b, _)| b);
+                        let part = &s[byte(*start as usize)..byte(*end as usize)];
+                        Ok(Value::String(s.slice_of(part)))
                     }
-                    _ => Err("/. requires two floats".to_string()),
+                    _ => Err("substring requires a string and two integer offsets".to_string()),
                 }
             },
         });
         
-        env.values.insert("=".to_string(), Value::BuiltinFunction {
-            name: "=".to_string(),
+        env.set("split".to_string(), Value::BuiltinFunction {
+            name: "split",
             arity: 2,
             func: |args| {
                 match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Bool(a == b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Bool(a == b)),
-                    _ => Err("= requires two integers of the same type".to_string()),
+                    (Value::String(_), Value::String(sep)) if sep.is_empty() => {
+                        Err("split: separator must not be empty".to_string())
+                    }
+                    (Value::String(s), Value::String(sep)) => Ok(Value::List(
+                        s.split(sep.as_str()).map(|part| Value::String(s.slice_of(part))).collect(),
+                    )),
+                    _ => Err("split requires two strings".to_string()),
                 }
             },
         });
         
-        env.values.insert("<".to_string(), Value::BuiltinFunction {
-            name: "<".to_string(),
-            arity: 2,
+        env.set("trim".to_string(), Value::BuiltinFunction {
+            name: "trim",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Bool(a < b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Bool(a < b)),
-                    _ => Err("< requires two integers of the same type".to_string()),
+                match &args[0] {
+                    Value::String(s) => Ok(Value::String(s.slice_of(s.trim()))),
+                    _ => Err("trim requires a string".to_string()),
                 }
             },
         });
         
-        env.values.insert(">".to_string(), Value::BuiltinFunction {
-            name: ">".to_string(),
-            arity: 2,
+        // Keywords. `keyword` interns at runtime, so `(= (keyword "a") :a)`.
+        env.set("keyword".to_string(), Value::BuiltinFunction {
+            name: "keyword",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Bool(a > b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Bool(a > b)),
-                    _ => Err("> requires two integers of the same type".to_string()),
+                match &args[0] {
+                    Value::String(s) => Ok(Value::Keyword(Keyword::intern(s))),
+                    _ => Err("keyword requires a string".to_string()),
                 }
             },
         });
         
-        env.values.insert("<=".to_string(), Value::BuiltinFunction {
-            name: "<=".to_string(),
-            arity: 2,
+        env.set("keyword-name".to_string(), Value::BuiltinFunction {
+            name: "keyword-name",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Bool(a <= b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Bool(a <= b)),
-                    _ => Err("<= requires two integers of the same type".to_string()),
+                match &args[0] {
+                    Value::Keyword(k) => Ok(Value::String((*k.name()).try_into()?)),
+                    _ => Err("keyword-name requires a keyword".to_string()),
                 }
             },
         });
         
-        env.values.insert(">=".to_string(), Value::BuiltinFunction {
-            name: ">=".to_string(),
+        // Struct fields: `(get p :x)`, or `(.x p)`, which reads as it; and
+        // a map's value for a key.
+        env.set("get".to_string(), Value::BuiltinFunction {
+            name: "get",
             arity: 2,
             func: |args| {
                 match (&args[0], &args[1]) {
-                    (Value::Integer32(a), Value::Integer32(b)) => Ok(Value::Bool(a >= b)),
-                    (Value::Integer64(a), Value::Integer64(b)) => Ok(Value::Bool(a >= b)),
-                    _ => Err(">= requires two integers of the same type".to_string()),
+                    (Value::Map(map), key) => map
+                        .get(&MapKey::of(key)?)
+                        .cloned()
+                        .ok_or_else(|| format!("get: no key {} in the map", key.written())),
+                    (Value::Constructed(value), Value::Keyword(k)) if !value.constructor.labels.is_empty() => {
+                        let name = k.name();
+                        match value.constructor.labels.iter().position(|label| **label == *name) {
+                            Some(i) => Ok(value.fields[i].clone()),
+                            None => Err(format!("{} has no field `{}`", value.constructor.type_name, name)),
+                        }
+                    }
+                    (_, Value::Keyword(_)) => Err(format!("get requires a struct, got {}", args[0].type_name())),
+                    _ => Err("get requires a field keyword, e.g. (get p :x)".to_string()),
                 }
             },
         });
         
-        env.values.insert("and".to_string(), Value::BuiltinFunction {
-            name: "and".to_string(),
-            arity: 2,
+        // Byte buffers
+        env.set("bytes-len".to_string(), Value::BuiltinFunction {
+            name: "bytes-len",
+            arity: 1,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a && *b)),
-                    _ => Err("and requires two booleans".to_string()),
+                match &args[0] {
+                    Value::Bytes(b) => Ok(Value::Integer32(b.borrow().len() as i32)),
+                    _ => Err("bytes-len requires bytes".to_string()),
                 }
             },
         });
         
-        env.values.insert("or".to_string(), Value::BuiltinFunction {
-            name: "or".to_string(),
-            arity: 2,
+        env.set("bytes-slice".to_string(), Value::BuiltinFunction {
+            name: "bytes-slice",
+            arity: 3,
             func: |args| {
-                match (&args[0], &args[1]) {
-                    (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a || *b)),
-                    _ => Err("or requires two booleans".to_string()),
+                match (&args[0], &args[1], &args[2]) {
+                    (Value::Bytes(b), Value::Integer32(start), Value::Integer32(end)) => {
+                        let b = b.borrow();
+                        if *start < 0 || *end < *start || *end as usize > b.len() {
+                            return Err(format!(
+                                "bytes-slice range {}..{} out of bounds for length {}",
+                                start, end, b.len()
+                            ));
+                        }
+                        let slice = b[*start as usize..*end as usize].to_vec();
+                        Ok(Value::Bytes(Rc::new(RefCell::new(slice))))
+                    }
+                    _ => Err("bytes-slice requires bytes and two integer offsets".to_string()),
                 }
             },
         });
         
-        env.values.insert("not".to_string(), Value::BuiltinFunction {
-            name: "not".to_string(),
+        env.set("bytes->string".to_string(), Value::BuiltinFunction {
+            name: "bytes->string",
             arity: 1,
             func: |args| {
                 match &args[0] {
-                    Value::Bool(b) => Ok(Value::Bool(!b)),
-                    _ => Err("not requires a boolean".to_string()),
+                    Value::Bytes(b) => String::from_utf8(b.borrow().clone())
+                        .map_err(|e| format!("bytes->string: invalid UTF-8: {}", e))
+                        .and_then(|s| Ok(Value::String(s.try_into()?))),
+                    _ => Err("bytes->string requires bytes".to_string()),
                 }
             },
         });
         
-        env.values.insert("print".to_string(), Value::BuiltinFunction {
-            name: "print".to_string(),
+        env.set("string->bytes".to_string(), Value::BuiltinFunction {
+            name: "string->bytes",
             arity: 1,
             func: |args| {
                 match &args[0] {
-                    Value::String(s) => {
-                        print!("{}", s);
-                        Ok(Value::String(s.clone()))
-                    }
-                    v => {
-                        print!("{}", v);
-                        Ok(v.clone())
-                    }
+                    Value::String(s) => Ok(Value::Bytes(Rc::new(RefCell::new(s.as_bytes().to_vec())))),
+                    _ => Err("string->bytes requires a string".to_string()),
                 }
             },
         });
         
-        env.values.insert("println".to_string(), Value::BuiltinFunction {
-            name: "println".to_string(),
+        env.set("read-file-bytes".to_string(), Value::BuiltinFunction {
+            name: "read-file-bytes",
             arity: 1,
             func: |args| {
                 match &args[0] {
-                    Value::String(s) => {
-                        println!("{}", s);
-                        Ok(Value::String(s.clone()))
-                    }
-                    v => {
-                        println!("{}", v);
-                        Ok(v.clone())
-                    }
+                    Value::String(path) => std::fs::read(path.as_str())
+                        .map(|b| Value::Bytes(Rc::new(RefCell::new(b))))
+                        .map_err(|e| format!("read-file-bytes: {}: {}", path, e)),
+                    _ => Err("read-file-bytes requires a path string".to_string()),
                 }
             },
         });
         
-        env.values.insert("type-of".to_string(), Value::BuiltinFunction {
-            name: "type-of".to_string(),
+        // A loaded file's `(embed-file "path")` is replaced by the text
+        // when it's loaded (see `modules`); this is the REPL's, relative
+        // to the current directory.
+        env.set("embed-file".to_string(), Value::BuiltinFunction {
+            name: "embed-file",
             arity: 1,
             func: |args| {
-                Ok(Value::String(args[0].type_name().to_string()))
-            },
+                match &args[0] {
+                    Value::String(path) => std::fs::read_to_string(path.as_str())
+                        .map_err(|e| format!("embed-file: could not read {}: {}", path, e))
+                        .and_then(|text| Ok(Value::String(text.try_into()?))),
+                    _ => Err("embed-file requires a path string".to_string()),
+                }
+            },
+        });
+
+        env.set("write-file-bytes".to_string(), Value::BuiltinFunction {
+            name: "write-file-bytes",
+            arity: 2,
+            func: |args| {
+                match (&args[0], &args[1]) {
+                    (Value::String(path), Value::Bytes(b)) => {
+                        let b = b.borrow();
+                        std::fs::write(path.as_str(), &*b)
+                            .map(|_| Value::Integer32(b.len() as i32))
+                            .map_err(|e| format!("write-file-bytes: {}: {}", path, e))
+                    }
+                    _ => Err("write-file-bytes requires a path string and bytes".to_string()),
+                }
+            },
+        });
+        
+        // Compression. `read-file-bytes-as` decodes while reading: "raw"
+        // is `read-file-bytes`, "gzip" requires gzip data, and "auto"
+        // decompresses only when the gzip magic number is present.
+        env.set("gzip-compress".to_string(), Value::BuiltinFunction {
+            name: "gzip-compress",
+            arity: 1,
+            func: |args| match &args[0] {
+                Value::Bytes(b) => Ok(shared_bytes(crate::compress::gzip_compress(&b.borrow()))),
+                _ => Err("gzip-compress requires bytes".to_string()),
+            },
+        });
+        env.set("gzip-decompress".to_string(), Value::BuiltinFunction {
+            name: "gzip-decompress",
+            arity: 1,
+            func: |args| match &args[0] {
+                Value::Bytes(b) => crate::compress::gzip_decompress(&b.borrow()).map(shared_bytes),
+                _ => Err("gzip-decompress requires bytes".to_string()),
+            },
+        });
+        env.set("read-file-bytes-as".to_string(), Value::BuiltinFunction {
+            name: "read-file-bytes-as",
+            arity: 2,
+            func: |args| match (&args[0], &args[1]) {
+                (Value::String(path), Value::String(encoding)) => {
+                    let raw = std::fs::read(path.as_str())
+                        .map_err(|e| format!("read-file-bytes-as: {}: {}", path, e))?;
+                    let gzip = match encoding.as_str() {
+                        "raw" => false,
+                        "gzip" => true,
+                        "auto" => crate::compress::is_gzip(&raw),
+                        other => {
+                            return Err(format!(
+                                "read-file-bytes-as: unknown encoding {:?} (expected raw, gzip or auto)",
+                                other
+                            ));
+                        }
+                    };
+                    let data = if gzip {
+                        crate::compress::gzip_decompress(&raw)
+                            .map_err(|e| format!("read-file-bytes-as: {}: {}", path, e))?
+                    } else {
+                        raw
+                    };
+                    Ok(shared_bytes(data))
+                }
+                _ => Err("read-file-bytes-as requires a path string and an encoding string".to_string()),
+            },
         });
         
+        // Binary encode/decode over byte buffers. Readers take
+        // `(buf offset)`; writers take `(buf offset value)`, write in place
+        // and return `buf`. Results wider than i32 (u32, i64) are i64.
+        let binary: [(&str, u32, BuiltinFn); 26] = [
+            ("bytes-alloc", 1, |args| match &args[0] {
+                Value::Integer32(n) if *n >= 0 => {
+                    Ok(Value::Bytes(Rc::new(RefCell::new(vec![0; *n as usize]))))
+                }
+                _ => Err("bytes-alloc requires a non-negative length".to_string()),
+            }),
+            ("read-u8", 2, |args| read_int(args, "read-u8", 1, false, Endian::Little)),
+            ("read-i8", 2, |args| read_int(args, "read-i8", 1, true, Endian::Little)),
+            ("read-u16-le", 2, |args| read_int(args, "read-u16-le", 2, false, Endian::Little)),
+            ("read-u16-be", 2, |args| read_int(args, "read-u16-be", 2, false, Endian::Big)),
+            ("read-i16-le", 2, |args| read_int(args, "read-i16-le", 2, true, Endian::Little)),
+            ("read-i16-be", 2, |args| read_int(args, "read-i16-be", 2, true, Endian::Big)),
+            ("read-u32-le", 2, |args| read_int(args, "read-u32-le", 4, false, Endian::Little)),
+            ("read-u32-be", 2, |args| read_int(args, "read-u32-be", 4, false, Endian::Big)),
+            ("read-i32-le", 2, |args| read_int(args, "read-i32-le", 4, true, Endian::Little)),
+            ("read-i32-be", 2, |args| read_int(args, "read-i32-be", 4, true, Endian::Big)),
+            ("read-i64-le", 2, |args| read_int(args, "read-i64-le", 8, true, Endian::Little)),
+            ("read-i64-be", 2, |args| read_int(args, "read-i64-be", 8, true, Endian::Big)),
+            ("read-f64-le", 2, |args| read_f64(args, "read-f64-le", Endian::Little)),
+            ("read-f64-be", 2, |args| read_f64(args, "read-f64-be", Endian::Big)),
+            ("write-u8!", 3, |args| write_int(args, "write-u8!", 1, Endian::Little)),
+            ("write-u16-le!", 3, |args| write_int(args, "write-u16-le!", 2, Endian::Little)),
+            ("write-u16-be!", 3, |args| write_int(args, "write-u16-be!", 2, Endian::Big)),
+            ("write-u32-le!", 3, |args| write_int(args, "write-u32-le!", 4, Endian::Little)),
+            ("write-u32-be!", 3, |args| write_int(args, "write-u32-be!", 4, Endian::Big)),
+            ("write-u64-le!", 3, |args| write_int(args, "write-u64-le!", 8, Endian::Little)),
+            ("write-u64-be!", 3, |args| write_int(args, "write-u64-be!", 8, Endian::Big)),
+            ("write-f64-le!", 3, |args| write_f64(args, "write-f64-le!", Endian::Little)),
+            ("write-f64-be!", 3, |args| write_f64(args, "write-f64-be!", Endian::Big)),
+            ("pack", 2, pack),
+            ("unpack", 3, unpack),
+        ];
+        for (name, arity, func) in binary {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
+        // Paths. Pure string manipulation except `absolute-path` (which
+        // consults the current directory) and `path-exists?`. Missing
+        // components come back as "".
+        let paths: [(&str, u32, BuiltinFn); 6] = [
+            ("path-join", 2, |args| {
+                let (base, rest) = (path_arg(&args[0], "path-join")?, path_arg(&args[1], "path-join")?);
+                path_value(&base.join(rest))
+            }),
+            ("path-parent", 1, |args| {
+                let p = path_arg(&args[0], "path-parent")?;
+                path_value(p.parent().unwrap_or(Path::new("")))
+            }),
+            ("path-filename", 1, |args| {
+                let p = path_arg(&args[0], "path-filename")?;
+                Ok(Value::String(p.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default().try_into()?))
+            }),
+            ("path-extension", 1, |args| {
+                let p = path_arg(&args[0], "path-extension")?;
+                Ok(Value::String(p.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default().try_into()?))
+            }),
+            ("absolute-path", 1, |args| {
+                let p = path_arg(&args[0], "absolute-path")?;
+                let abs = std::path::absolute(p).map_err(|e| format!("absolute-path: {}: {}", p.display(), e))?;
+                path_value(&abs)
+            }),
+            ("path-exists?", 1, |args| Ok(Value::Bool(path_arg(&args[0], "path-exists?")?.exists()))),
+        ];
+        for (name, arity, func) in paths {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
+        // Directories and metadata. Listings are sorted so scripts behave
+        // the same on every filesystem; `walk-dir` doesn't follow symlinks.
+        let fs: [(&str, u32, BuiltinFn); 5] = [
+            ("list-dir", 1, |args| {
+                let dir = path_arg(&args[0], "list-dir")?;
+                Ok(Value::List(read_dir_sorted(dir, "list-dir")?.iter().map(|p| path_value(p)).collect::<Result<_, _>>()?))
+            }),
+            ("walk-dir", 1, |args| {
+                let mut out = Vec::new();
+                walk_dir(path_arg(&args[0], "walk-dir")?, &mut out)?;
+                Ok(Value::List(out))
+            }),
+            ("file-size", 1, |args| {
+                let p = path_arg(&args[0], "file-size")?;
+                Ok(Value::Integer64(file_metadata(p, "file-size")?.len() as i64))
+            }),
+            ("is-dir?", 1, |args| Ok(Value::Bool(path_arg(&args[0], "is-dir?")?.is_dir()))),
+            ("modified-time", 1, |args| {
+                let p = path_arg(&args[0], "modified-time")?;
+                file_metadata(p, "modified-time")?
+                    .modified()
+                    .map(|t| Value::DateTime(DateTime::from_system_time(t).into()))
+                    .map_err(|e| format!("modified-time: {}: {}", p.display(), e))
+            }),
+        ];
+        for (name, arity, func) in fs {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
+        // Script arguments and `cli/parse` (see `cli`). `cli/parse`
+        // stops the script when `--help` is among the arguments, leaving
+        // the usage for the driver to print.
+        let cli: [(&str, u32, BuiltinFn); 4] = [
+            ("args", 0, |_| crate::cli::args().into_iter().map(|s| Ok(Value::String(s.try_into()?))).collect::<Result<_, String>>().map(Value::List)),
+            ("cli/parse", 2, |args| {
+                let argv = match &args[1] {
+                    Value::List(items) => items
+                        .iter()
+                        .map(|a| match a {
+                            Value::String(s) => Ok(s.to_string()),
+                            other => Err(format!("cli/parse: arguments must be strings, got {}", other)),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                    Value::Nil => Vec::new(),
+                    _ => return Err("cli/parse requires a spec list and an argument list".to_string()),
+                };
+                match crate::cli::parse(&args[0], &argv)? {
+                    crate::cli::Parsed::Values(pairs) => Ok(Value::List(
+                        pairs
+                            .into_iter()
+                            .map(|(k, v)| Ok(Value::List(vec![Value::String(k.try_into()?), Value::String(v.try_into()?)])))
+                            .collect::<Result<_, String>>()?,
+                    )),
+                    crate::cli::Parsed::Help(usage) => Err(crate::cli::request_help(usage)),
+                }
+            }),
+            ("cli/get", 2, |args| match &args[1] {
+                Value::String(name) => Ok(Value::String(crate::cli::get(&args[0], name)?.try_into()?)),
+                _ => Err("cli/get requires a parsed argument list and a name".to_string()),
+            }),
+            ("cli/flag?", 2, |args| match &args[1] {
+                Value::String(name) => Ok(Value::Bool(crate::cli::get(&args[0], name)? == "true")),
+                _ => Err("cli/flag? requires a parsed argument list and a name".to_string()),
+            }),
+        ];
+        for (name, arity, func) in cli {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
+        // Ref cells: the one place values change in place.
+        let refs: [(&str, u32, BuiltinFn); 3] = [
+            ("ref", 1, |args| Ok(Value::Ref(Rc::new(RefCell::new(args[0].clone()))))),
+            ("deref", 1, |args| match &args[0] {
+                Value::Ref(cell) => Ok(cell.borrow().clone()),
+                other => Err(format!("deref requires a ref, got {}", other.type_name())),
+            }),
+            ("set-ref!", 2, |args| match &args[0] {
+                Value::Ref(cell) => {
+                    *cell.borrow_mut() = args[1].clone();
+                    Ok(Value::Unit)
+                }
+                other => Err(format!("set-ref! requires a ref, got {}", other.type_name())),
+            }),
+        ];
+        for (name, arity, func) in refs {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
+        // Persistent key-value store (see `kv`). Keys and values are
+        // strings; `kv-put!` returns the store so writes can be chained.
+        let kv: [(&str, u32, BuiltinFn); 8] = [
+            ("kv-open", 1, |args| match &args[0] {
+                Value::String(path) => Ok(Value::KvStore(Rc::new(RefCell::new(crate::kv::KvStore::open(path)?)))),
+                _ => Err("kv-open requires a path string".to_string()),
+            }),
+            ("kv-get", 2, |args| {
+                let (db, key) = kv_args(args, "kv-get")?;
+                let db = db.borrow();
+                match db.get(key)? {
+                    Some(v) => Ok(Value::String(v.as_str().try_into()?)),
+                    None => Err(format!("kv-get: no key {:?} in {}", key, db.path())),
+                }
+            }),
+            ("kv-get-or", 3, |args| {
+                let (db, key) = kv_args(args, "kv-get-or")?;
+                let found = db.borrow().get(key)?.cloned();
+                match (found, &args[2]) {
+                    (Some(v), _) => Ok(Value::String(v.try_into()?)),
+                    (None, default @ Value::String(_)) => Ok(default.clone()),
+                    _ => Err("kv-get-or requires a string default".to_string()),
+                }
+            }),
+            ("kv-has?", 2, |args| {
+                let (db, key) = kv_args(args, "kv-has?")?;
+                Ok(Value::Bool(db.borrow().get(key)?.is_some()))
+            }),
+            ("kv-put!", 3, |args| {
+                let (db, key) = kv_args(args, "kv-put!")?;
+                let Value::String(value) = &args[2] else {
+                    return Err("kv-put! requires a string value".to_string());
+                };
+                db.borrow_mut().put(key, value)?;
+                Ok(args[0].clone())
+            }),
+            ("kv-delete!", 2, |args| {
+                let (db, key) = kv_args(args, "kv-delete!")?;
+                Ok(Value::Bool(db.borrow_mut().delete(key)?))
+            }),
+            ("kv-keys", 1, |args| match &args[0] {
+                Value::KvStore(db) => {
+                    let keys = db.borrow().keys()?.into_iter().map(|s| Ok(Value::String(s.try_into()?)));
+                    Ok(Value::List(keys.collect::<Result<_, String>>()?))
+                }
+                _ => Err("kv-keys requires a kv store".to_string()),
+            }),
+            ("kv-close", 1, |args| match &args[0] {
+                Value::KvStore(db) => Ok(Value::Bool(db.borrow_mut().close())),
+                _ => Err("kv-close requires a kv store".to_string()),
+            }),
+        ];
+        for (name, arity, func) in kv {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
+        // Matrices (see `matrix`). Results are new matrices, except that
+        // `mat-set!` writes in place and returns its matrix.
+        let matrices: [(&str, u32, BuiltinFn); 13] = [
+            ("mat", 3, |args| match (&args[0], &args[1], &args[2]) {
+                (Value::Integer32(rows), Value::Integer32(cols), Value::Float(init)) if *rows >= 0 && *cols >= 0 => {
+                    Ok(shared_matrix(Matrix::filled("mat", *rows as usize, *cols as usize, *init)?))
+                }
+                _ => Err("mat requires non-negative rows and cols and an f64: (mat 2 3 0.0)".to_string()),
+            }),
+            ("mat-rows", 1, |args| Ok(Value::Integer32(matrix_arg(&args[0], "mat-rows")?.borrow().rows() as i32))),
+            ("mat-cols", 1, |args| Ok(Value::Integer32(matrix_arg(&args[0], "mat-cols")?.borrow().cols() as i32))),
+            ("mat-get", 3, |args| {
+                let m = matrix_arg(&args[0], "mat-get")?.borrow();
+                let (row, col) = m.check_index("mat-get", int_arg(&args[1], "mat-get")?, int_arg(&args[2], "mat-get")?)?;
+                Ok(Value::Float(m.get(row, col)))
+            }),
+            ("mat-set!", 4, |args| {
+                let m = matrix_arg(&args[0], "mat-set!")?;
+                let (row, col) =
+                    m.borrow().check_index("mat-set!", int_arg(&args[1], "mat-set!")?, int_arg(&args[2], "mat-set!")?)?;
+                let Value::Float(x) = args[3] else {
+                    return Err("mat-set! requires an f64 value".to_string());
+                };
+                m.borrow_mut().set(row, col, x);
+                Ok(args[0].clone())
+            }),
+            ("mat-mul", 2, |args| {
+                let product = matrix_arg(&args[0], "mat-mul")?.borrow().mul(&matrix_arg(&args[1], "mat-mul")?.borrow())?;
+                Ok(shared_matrix(product))
+            }),
+            ("mat-add", 2, |args| elementwise(args, "mat-add", |a, b| a + b)),
+            ("mat-sub", 2, |args| elementwise(args, "mat-sub", |a, b| a - b)),
+            ("mat-emul", 2, |args| elementwise(args, "mat-emul", |a, b| a * b)),
+            ("mat-scale", 2, |args| match &args[1] {
+                Value::Float(k) => Ok(shared_matrix(matrix_arg(&args[0], "mat-scale")?.borrow().scale(*k))),
+                _ => Err("mat-scale requires a matrix and an f64".to_string()),
+            }),
+            ("mat-transpose", 1, |args| Ok(shared_matrix(matrix_arg(&args[0], "mat-transpose")?.borrow().transpose()))),
+            ("mat->list", 1, |args| {
+                let rows = matrix_arg(&args[0], "mat->list")?.borrow().to_rows();
+                let row = |row: Vec<f64>| if row.is_empty() { Value::Nil } else { Value::List(row.into_iter().map(Value::Float).collect()) };
+                Ok(if rows.is_empty() { Value::Nil } else { Value::List(rows.into_iter().map(row).collect()) })
+            }),
+            ("list->mat", 1, |args| {
+                let err = || "list->mat requires a list of lists of f64".to_string();
+                let rows = match &args[0] {
+                    Value::List(rows) => rows.as_slice(),
+                    Value::Nil => &[],
+                    _ => return Err(err()),
+                };
+                let rows = rows
+                    .iter()
+                    .map(|row| match row {
+                        Value::List(items) => items
+                            .iter()
+                            .map(|x| if let Value::Float(x) = x { Ok(*x) } else { Err(err()) })
+                            .collect(),
+                        Value::Nil => Ok(Vec::new()),
+                        _ => Err(err()),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(shared_matrix(Matrix::from_rows(rows)?))
+            }),
+        ];
+        for (name, arity, func) in matrices {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
+        // Date/time. Functions that take "a datetime" accept both UTC and
+        // zoned values; field accessors read the zoned value's local time.
+        let time: [(&str, u32, BuiltinFn); 28] = [
+            ("now", 0, |_| Ok(Value::DateTime(DateTime::now().into()))),
+            ("date", 3, |args| {
+                let (y, f) = civil_args(args, "date")?;
+                Ok(Value::DateTime(DateTime::from_civil(y, f[0], f[1], 0, 0, 0)?.into()))
+            }),
+            ("datetime", 6, |args| {
+                let (y, f) = civil_args(args, "datetime")?;
+                Ok(Value::DateTime(DateTime::from_civil(y, f[0], f[1], f[2], f[3], f[4])?.into()))
+            }),
+            ("from-unix", 1, |args| Ok(Value::DateTime(DateTime::from_unix(time_int(&args[0], "from-unix")?).into()))),
+            ("unix-seconds", 1, |args| Ok(Value::Integer64(datetime_arg(&args[0], "unix-seconds")?.unix_seconds()))),
+            ("to-zone", 2, |args| {
+                let t = datetime_arg(&args[0], "to-zone")?;
+                let minutes = time_int(&args[1], "to-zone")?;
+                let secs = minutes
+                    .checked_mul(60)
+                    .and_then(|secs| i32::try_from(secs).ok())
+                    .ok_or_else(|| format!("UTC offset {} minutes out of range", minutes))?;
+                Ok(Value::DateTime(t.with_offset(secs)?.into()))
+            }),
+            ("to-utc", 1, |args| Ok(Value::DateTime(datetime_arg(&args[0], "to-utc")?.to_utc().into()))),
+            ("format-time", 2, |args| match &args[1] {
+                Value::String(pattern) => Ok(Value::String(datetime_arg(&args[0], "format-time")?.format(pattern)?.try_into()?)),
+                _ => Err("format-time requires a format string".to_string()),
+            }),
+            ("parse-time", 2, |args| match (&args[0], &args[1]) {
+                (Value::String(input), Value::String(pattern)) => {
+                    Ok(Value::DateTime(DateTime::parse(input, pattern)?.to_utc().into()))
+                }
+                _ => Err("parse-time requires an input string and a format string".to_string()),
+            }),
+            ("parse-zoned", 2, |args| match (&args[0], &args[1]) {
+                (Value::String(input), Value::String(pattern)) => {
+                    let t = DateTime::parse(input, pattern)?;
+                    if t.offset.is_none() {
+                        return Err("parse-zoned: format has no %z offset".to_string());
+                    }
+                    Ok(Value::DateTime(t.into()))
+                }
+                _ => Err("parse-zoned requires an input string and a format string".to_string()),
+            }),
+            ("time-add", 2, |args| {
+                let d = duration_arg(&args[1], "time-add")?;
+                Ok(Value::DateTime(datetime_arg(&args[0], "time-add")?.plus(d).into()))
+            }),
+            ("time-diff", 2, |args| {
+                let (a, b) = (datetime_arg(&args[0], "time-diff")?, datetime_arg(&args[1], "time-diff")?);
+                Ok(Value::Duration(a.since(b).into()))
+            }),
+            ("time-before?", 2, |args| {
+                let (a, b) = (datetime_arg(&args[0], "time-before?")?, datetime_arg(&args[1], "time-before?")?);
+                Ok(Value::Bool(a.nanos < b.nanos))
+            }),
+            ("millis", 1, |args| Ok(Value::Duration(Duration::from_millis(time_int(&args[0], "millis")?).into()))),
+            ("seconds", 1, |args| Ok(Value::Duration(Duration::from_secs(time_int(&args[0], "seconds")?).into()))),
+            ("minutes", 1, |args| duration_in(&args[0], "minutes", 60)),
+            ("hours", 1, |args| duration_in(&args[0], "hours", 3600)),
+            ("days", 1, |args| duration_in(&args[0], "days", 86_400)),
+            ("duration-add", 2, |args| {
+                let (a, b) = (duration_arg(&args[0], "duration-add")?, duration_arg(&args[1], "duration-add")?);
+                Ok(Value::Duration(Duration { nanos: a.nanos + b.nanos }.into()))
+            }),
+            ("duration-seconds", 1, |args| Ok(Value::Integer64(duration_arg(&args[0], "duration-seconds")?.seconds()))),
+            ("duration-millis", 1, |args| Ok(Value::Integer64(duration_arg(&args[0], "duration-millis")?.millis()))),
+            ("year", 1, |args| Ok(Value::Integer32(datetime_arg(&args[0], "year")?.civil().year as i32))),
+            ("month", 1, |args| Ok(Value::Integer32(datetime_arg(&args[0], "month")?.civil().month as i32))),
+            ("day", 1, |args| Ok(Value::Integer32(datetime_arg(&args[0], "day")?.civil().day as i32))),
+            ("hour", 1, |args| Ok(Value::Integer32(datetime_arg(&args[0], "hour")?.civil().hour as i32))),
+            ("minute", 1, |args| Ok(Value::Integer32(datetime_arg(&args[0], "minute")?.civil().minute as i32))),
+            ("second", 1, |args| Ok(Value::Integer32(datetime_arg(&args[0], "second")?.civil().second as i32))),
+            ("weekday", 1, |args| Ok(Value::Integer32(datetime_arg(&args[0], "weekday")?.civil().weekday as i32))),
+        ];
+        for (name, arity, func) in time {
+            env.set(name.to_string(), Value::BuiltinFunction {
+                name,
+                arity,
+                func,
+            });
+        }
+        
         // List operations
-        env.values.insert("cons".to_string(), Value::BuiltinFunction {
-            name: "cons".to_string(),
+        env.set("cons".to_string(), Value::BuiltinFunction {
+            name: "cons",
             arity: 2,
             func: |args| {
                 match &args[1] {
@@ -345,25 +1717,26 @@ impl Environment {
                     Value::Nil => {
                         Ok(Value::List(vec![args[0].clone()]))
                     }
-                    _ => Err("cons requires a list as second argument".to_string()),
+                    tail => Ok(Value::Pair(Rc::new((args[0].clone(), tail.clone())))),
                 }
             },
         });
         
-        env.values.insert("car".to_string(), Value::BuiltinFunction {
-            name: "car".to_string(),
+        env.set("car".to_string(), Value::BuiltinFunction {
+            name: "car",
             arity: 1,
             func: |args| {
                 match &args[0] {
                     Value::List(lst) if !lst.is_empty() => Ok(lst[0].clone()),
+                    Value::Pair(pair) => Ok(pair.0.clone()),
                     Value::List(_) | Value::Nil => Err("car of empty list".to_string()),
-                    _ => Err("car requires a list".to_string()),
+                    _ => Err("car requires a list or pair".to_string()),
                 }
             },
         });
         
-        env.values.insert("cdr".to_string(), Value::BuiltinFunction {
-            name: "cdr".to_string(),
+        env.set("cdr".to_string(), Value::BuiltinFunction {
+            name: "cdr",
             arity: 1,
             func: |args| {
                 match &args[0] {
@@ -374,14 +1747,15 @@ impl Environment {
                             Ok(Value::List(lst[1..].to_vec()))
                         }
                     }
+                    Value::Pair(pair) => Ok(pair.1.clone()),
                     Value::List(_) | Value::Nil => Err("cdr of empty list".to_string()),
-                    _ => Err("cdr requires a list".to_string()),
+                    _ => Err("cdr requires a list or pair".to_string()),
                 }
             },
         });
         
-        env.values.insert("null?".to_string(), Value::BuiltinFunction {
-            name: "null?".to_string(),
+        env.set("null?".to_string(), Value::BuiltinFunction {
+            name: "null?",
             arity: 1,
             func: |args| {
                 match &args[0] {
@@ -392,8 +1766,20 @@ impl Environment {
             },
         });
         
-        env.values.insert("length".to_string(), Value::BuiltinFunction {
-            name: "length".to_string(),
+        env.set("pair?".to_string(), Value::BuiltinFunction {
+            name: "pair?",
+            arity: 1,
+            func: |args| {
+                match &args[0] {
+                    Value::Pair(_) => Ok(Value::Bool(true)),
+                    Value::List(lst) => Ok(Value::Bool(!lst.is_empty())),
+                    _ => Ok(Value::Bool(false)),
+                }
+            },
+        });
+        
+        env.set("length".to_string(), Value::BuiltinFunction {
+            name: "length",
             arity: 1,
             func: |args| {
                 match &args[0] {
@@ -403,9 +1789,15 @@ impl Environment {
                 }
             },
         });
+
+        for &(alias, builtin) in LIST_ALIASES {
+            if let Some(Value::BuiltinFunction { arity, func, .. }) = env.get(builtin) {
+                env.set(alias.to_string(), Value::BuiltinFunction { name: alias, arity, func });
+            }
+        }
         
-        env.values.insert("append".to_string(), Value::BuiltinFunction {
-            name: "append".to_string(),
+        env.set("append".to_string(), Value::BuiltinFunction {
+            name: "append",
             arity: 2,
             func: |args| {
                 match (&args[0], &args[1]) {
@@ -422,8 +1814,8 @@ impl Environment {
             },
         });
         
-        env.values.insert("nth".to_string(), Value::BuiltinFunction {
-            name: "nth".to_string(),
+        env.set("nth".to_string(), Value::BuiltinFunction {
+            name: "nth",
             arity: 2,
             func: |args| {
                 match (&args[0], &args[1]) {
@@ -440,34 +1832,942 @@ impl Environment {
             },
         });
         
+        // Fixed-size arrays (`#[1 2 3]`) and slices of them; indexing is
+        // bounds-checked here and, for literal indices into an array, by
+        // the type checker.
+        env.set("array-len".to_string(), Value::BuiltinFunction {
+            name: "array-len",
+            arity: 1,
+            func: |args| {
+                match args[0].view() {
+                    Some((_, start, end)) => Ok(Value::Integer32((end - start) as i32)),
+                    None => Err("array-len requires an array".to_string()),
+                }
+            },
+        });
+        
+        env.set("array-get".to_string(), Value::BuiltinFunction {
+            name: "array-get",
+            arity: 2,
+            func: |args| {
+                match (args[0].view(), &args[1]) {
+                    (Some((items, start, end)), Value::Integer32(i)) => {
+                        let i = array_index("array-get", *i, end - start)?;
+                        Ok(items.borrow()[start + i].clone())
+                    }
+                    _ => Err("array-get requires an array and an integer index".to_string()),
+                }
+            },
+        });
+        
+        env.set("array-set!".to_string(), Value::BuiltinFunction {
+            name: "array-set!",
+            arity: 3,
+            func: |args| {
+                match (args[0].view(), &args[1]) {
+                    (Some((items, start, end)), Value::Integer32(i)) => {
+                        let i = array_index("array-set!", *i, end - start)?;
+                        items.borrow_mut()[start + i] = args[2].clone();
+                        Ok(args[0].clone())
+                    }
+                    _ => Err("array-set! requires an array, an integer index and a value".to_string()),
+                }
+            },
+        });
+        
+        env.set("array->list".to_string(), Value::BuiltinFunction {
+            name: "array->list",
+            arity: 1,
+            func: |args| {
+                match args[0].view() {
+                    Some((_, start, end)) if start == end => Ok(Value::Nil),
+                    Some((items, start, end)) => Ok(Value::List(items.borrow()[start..end].to_vec())),
+                    None => Err("array->list requires an array".to_string()),
+                }
+            },
+        });
+        
+        // `(slice a start end)` shares `a`'s elements; a slice of a slice
+        // is a narrower view of the same array or vector.
+        env.set("slice".to_string(), Value::BuiltinFunction {
+            name: "slice",
+            arity: 3,
+            func: |args| {
+                let view = match &args[0] {
+                    Value::Vector(items) => Some((items, 0, items.borrow().len())),
+                    value => value.view(),
+                };
+                match (view, &args[1], &args[2]) {
+                    (Some((items, offset, end)), Value::Integer32(from), Value::Integer32(to)) => {
+                        let len = end - offset;
+                        if *from < 0 || *to < *from || *to as usize > len {
+                            return Err(format!("slice: range {}..{} out of bounds for length {}", from, to, len));
+                        }
+                        Ok(Value::Slice {
+                            items: items.clone(),
+                            start: offset + *from as usize,
+                            end: offset + *to as usize,
+                        })
+                    }
+                    _ => Err("slice requires an array, slice or vector and two integer offsets".to_string()),
+                }
+            },
+        });
+        
+        // `(list->array xs n)`: the length is part of the array's type, so
+        // the list has to have exactly `n` items.
+        env.set("list->array".to_string(), Value::BuiltinFunction {
+            name: "list->array",
+            arity: 2,
+            func: |args| {
+                match (&args[0], &args[1]) {
+                    (Value::List(items), Value::Integer32(n)) => sized_array("list->array", items.clone(), *n),
+                    (Value::Nil, Value::Integer32(n)) => sized_array("list->array", Vec::new(), *n),
+                    _ => Err("list->array requires a list and a length".to_string()),
+                }
+            },
+        });
+        
+        env.set("array->vec".to_string(), Value::BuiltinFunction {
+            name: "array->vec",
+            arity: 1,
+            func: |args| {
+                match args[0].view() {
+                    Some((items, start, end)) => Ok(vector(items.borrow()[start..end].to_vec())),
+                    None => Err("array->vec requires an array".to_string()),
+                }
+            },
+        });
+        
+        env.set("vec->array".to_string(), Value::BuiltinFunction {
+            name: "vec->array",
+            arity: 2,
+            func: |args| {
+                match (&args[0], &args[1]) {
+                    (Value::Vector(items), Value::Integer32(n)) => sized_array("vec->array", items.borrow().clone(), *n),
+                    _ => Err("vec->array requires a vector and a length".to_string()),
+                }
+            },
+        });
+        
+        // Maps (`{:a 1}`)
+        env.set("assoc".to_string(), Value::BuiltinFunction {
+            name: "assoc",
+            arity: 3,
+            func: |args| {
+                match &args[0] {
+                    Value::Map(map) => {
+                        let mut map = (**map).clone();
+                        map.insert(MapKey::of(&args[1])?, args[2].clone());
+                        Ok(Value::Map(Rc::new(map)))
+                    }
+                    _ => Err("assoc requires a map, a key and a value".to_string()),
+                }
+            },
+        });
+        
+        env.set("dissoc".to_string(), Value::BuiltinFunction {
+            name: "dissoc",
+            arity: 2,
+            func: |args| {
+                match &args[0] {
+                    Value::Map(map) => {
+                        let mut map = (**map).clone();
+                        map.remove(&MapKey::of(&args[1])?);
+                        Ok(Value::Map(Rc::new(map)))
+                    }
+                    _ => Err("dissoc requires a map and a key".to_string()),
+                }
+            },
+        });
+        
+        env.set("contains?".to_string(), Value::BuiltinFunction {
+            name: "contains?",
+            arity: 2,
+            func: |args| {
+                match &args[0] {
+                    Value::Map(map) => Ok(Value::Bool(map.contains_key(&MapKey::of(&args[1])?))),
+                    _ => Err("contains? requires a map and a key".to_string()),
+                }
+            },
+        });
+        
+        env.set("keys".to_string(), Value::BuiltinFunction {
+            name: "keys",
+            arity: 1,
+            func: |args| {
+                match &args[0] {
+                    Value::Map(map) if map.is_empty() => Ok(Value::Nil),
+                    Value::Map(map) => Ok(Value::List(map_entries(map).into_iter().map(|(k, _)| k.value()).collect())),
+                    _ => Err("keys requires a map".to_string()),
+                }
+            },
+        });
+        
+        env.set("vals".to_string(), Value::BuiltinFunction {
+            name: "vals",
+            arity: 1,
+            func: |args| {
+                match &args[0] {
+                    Value::Map(map) if map.is_empty() => Ok(Value::Nil),
+                    Value::Map(map) => Ok(Value::List(map_entries(map).into_iter().map(|(_, v)| v.clone()).collect())),
+                    _ => Err("vals requires a map".to_string()),
+                }
+            },
+        });
+        
+        // Vectors (`[1 2 3]`)
+        env.set("vec-len".to_string(), Value::BuiltinFunction {
+            name: "vec-len",
+            arity: 1,
+            func: |args| {
+                match &args[0] {
+                    Value::Vector(items) => Ok(Value::Integer32(items.borrow().len() as i32)),
+                    _ => Err("vec-len requires a vector".to_string()),
+                }
+            },
+        });
+        
+        env.set("vec-get".to_string(), Value::BuiltinFunction {
+            name: "vec-get",
+            arity: 2,
+            func: |args| {
+                match (&args[0], &args[1]) {
+                    (Value::Vector(items), Value::Integer32(i)) => {
+                        let items = items.borrow();
+                        array_index("vec-get", *i, items.len()).map(|i| items[i].clone())
+                    }
+                    _ => Err("vec-get requires a vector and an integer index".to_string()),
+                }
+            },
+        });
+        
+        env.set("vec-push".to_string(), Value::BuiltinFunction {
+            name: "vec-push",
+            arity: 2,
+            func: |args| {
+                match &args[0] {
+                    Value::Vector(items) => {
+                        let mut items = items.borrow().clone();
+                        items.push(args[1].clone());
+                        Ok(vector(items))
+                    }
+                    _ => Err("vec-push requires a vector and a value".to_string()),
+                }
+            },
+        });
+        
+        // Unlike `slice`, `vec-slice` copies: the result is a vector of its
+        // own that `vec-push` can grow.
+        env.set("vec-slice".to_string(), Value::BuiltinFunction {
+            name: "vec-slice",
+            arity: 3,
+            func: |args| {
+                match (&args[0], &args[1], &args[2]) {
+                    (Value::Vector(items), Value::Integer32(start), Value::Integer32(end)) => {
+                        let items = items.borrow();
+                        if *start < 0 || *end < *start || *end as usize > items.len() {
+                            return Err(format!(
+                                "vec-slice: range {}..{} out of bounds for length {}",
+                                start, end, items.len()
+                            ));
+                        }
+                        Ok(vector(items[*start as usize..*end as usize].to_vec()))
+                    }
+                    _ => Err("vec-slice requires a vector and two integer offsets".to_string()),
+                }
+            },
+        });
+        
         env
     }
     
-    pub fn get(&self, name: &str) -> Option<&Value> {
-        self.values.get(name).or_else(|| {
-            self.parent.as_ref().and_then(|p| p.get(name))
-        })
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let scope = self.scope.borrow();
+        match scope.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => scope.parent.as_ref()?.get(name),
+        }
     }
     
     pub fn set(&mut self, name: String, value: Value) {
-        self.values.insert(name, value);
+        self.scope.borrow_mut().values.insert(name, value);
+    }
+
+    /// Drop `name` from this scope, returning what it was bound to.
+    /// Enclosing scopes keep theirs.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.scope.borrow_mut().values.remove(name)
     }
     
+    /// A new scope inside this one.
     pub fn extend(&self) -> Self {
-        Environment {
-            values: HashMap::new(),
-            parent: Some(Box::new(self.clone())),
+        let child = self.clone().into_child();
+        if crate::profile::enabled() {
+            crate::profile::record("environment", child.footprint());
+        }
+        child
+    }
+
+    /// An environment with nothing in it, not even the builtins: a
+    /// placeholder while the real one is elsewhere.
+    pub(crate) fn blank() -> Self {
+        Environment { scope: Rc::default() }
+    }
+
+    /// Like `extend`, but without reporting to `profile`.
+    pub(crate) fn into_child(self) -> Self {
+        Environment { scope: Rc::new(RefCell::new(Scope { values: HashMap::new(), parent: Some(self) })) }
+    }
+
+    /// Undo `into_child`: the enclosing scope, without this one's bindings.
+    pub(crate) fn into_parent(self) -> Option<Self> {
+        self.scope.borrow().parent.clone()
+    }
+
+    /// A fresh scope beside this one, for the next round of a loop: the
+    /// closures the last round made keep the bindings they saw.
+    pub(crate) fn renew(&self) -> Self {
+        match self.scope.borrow().parent.clone() {
+            Some(parent) => parent.into_child(),
+            None => Environment::blank(),
         }
     }
 
+    /// Every name in scope with its value, innermost scope first and each
+    /// scope in name order. A shadowed name only shows up once, with the
+    /// value it has here.
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut seen = std::collections::HashSet::new();
+        let mut out = Vec::new();
+        let mut scope = Some(self.clone());
+        while let Some(env) = scope {
+            let env = env.scope.borrow();
+            let mut names: Vec<_> = env
+                .values
+                .iter()
+                .filter(|(name, _)| seen.insert((*name).clone()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            names.sort_by(|a, b| a.0.cmp(&b.0));
+            out.extend(names);
+            scope = env.parent.clone();
+        }
+        out
+    }
+
     /// Capture the current local-scope bindings (parent chain unchanged).
     /// Used by or-pattern evaluation to restore state after a failed branch.
     pub fn snapshot(&self) -> HashMap<String, Value> {
-        self.values.clone()
+        self.scope.borrow().values.clone()
     }
 
     /// Replace the local-scope bindings with `snap`. Pairs with `snapshot`.
     pub fn restore(&mut self, snap: HashMap<String, Value>) {
-        self.values = snap;
+        self.scope.borrow_mut().values = snap;
+    }
+
+    /// Roughly what this scope allocates: its table's entries and names.
+    /// The scopes around it are shared, not copied. For `profile`.
+    pub(crate) fn footprint(&self) -> usize {
+        let entry = std::mem::size_of::<(String, Value)>();
+        let scope = self.scope.borrow();
+        std::mem::size_of::<Scope>() + scope.values.keys().map(|name| entry + name.len()).sum::<usize>()
+    }
+
+    /// What this environment keeps alive: see `HeapStats`.
+    pub fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats { keywords: Keyword::count(), ..HeapStats::default() };
+        stats.visit_env(self, &mut std::collections::HashSet::new());
+        stats
+    }
+}
+
+/// Everything reachable from an environment, for `memory-stats`: what a
+/// long-running session is holding on to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeapStats {
+    /// Values by `type_name`, including list elements, pair halves, `ref`
+    /// contents and whatever functions captured. Something shared behind
+    /// an `Rc` (a function, a pair, a ref) counts once however many
+    /// places hold it.
+    pub values: std::collections::BTreeMap<String, usize>,
+    /// Scopes: this environment's chain plus those functions captured,
+    /// each counted once.
+    pub environments: usize,
+    /// Interned keywords; see `Keyword::count`.
+    pub keywords: usize,
+}
+
+impl HeapStats {
+    fn visit_env(&mut self, env: &Environment, seen: &mut std::collections::HashSet<*const ()>) {
+        let mut scope = Some(env.clone());
+        while let Some(env) = scope {
+            // Closures share their scopes; past one that's been counted,
+            // so has the rest of the chain.
+            if !seen.insert(Rc::as_ptr(&env.scope).cast()) {
+                break;
+            }
+            self.environments += 1;
+            let env = env.scope.borrow();
+            for value in env.values.values() {
+                self.visit(value, seen);
+            }
+            scope = env.parent.clone();
+        }
+    }
+
+    fn visit(&mut self, value: &Value, seen: &mut std::collections::HashSet<*const ()>) {
+        let mut first = |ptr: *const ()| seen.insert(ptr);
+        let new = match value {
+            Value::Function(closure) => first(Rc::as_ptr(closure).cast()),
+            Value::Native(native) => first(Rc::as_ptr(native).cast()),
+            Value::Pair(pair) => first(Rc::as_ptr(pair).cast()),
+            Value::Ref(cell) => first(Rc::as_ptr(cell).cast()),
+            Value::Param(cell) => first(Rc::as_ptr(cell).cast()),
+            _ => true,
+        };
+        if !new {
+            return;
+        }
+        *self.values.entry(value.type_name().to_string()).or_insert(0) += 1;
+        match value {
+            Value::Function(closure) => self.visit_env(&closure.env, seen),
+            Value::Native(native) => self.visit_env(&native.source.env, seen),
+            Value::List(items) => items.iter().for_each(|item| self.visit(item, seen)),
+            Value::Pair(pair) => {
+                self.visit(&pair.0, seen);
+                self.visit(&pair.1, seen);
+            }
+            Value::Ref(cell) => self.visit(&cell.borrow(), seen),
+            Value::Param(cell) => cell.borrow().iter().for_each(|value| self.visit(value, seen)),
+            _ => {}
+        }
+    }
+}
+
+/// The `fn` / `defn` behind `value`, for the reflection builtins; `op` is
+/// whose error it is.
+fn user_function<'a>(value: &'a Value, op: &str) -> Result<&'a Closure, String> {
+    match value {
+        Value::Function(closure) => Ok(closure),
+        Value::Native(native) => Ok(&native.source),
+        Value::BuiltinFunction { name, .. } => Err(format!("{}: {} is a builtin", op, name)),
+        Value::Continuation(_) => Err(format!("{}: not available for a continuation", op)),
+        other => Err(format!("{} requires a function, got {}", op, other.type_name())),
+    }
+}
+
+/// Signature of every builtin's implementation.
+pub type BuiltinFn = fn(&[Value]) -> Result<Value, String>;
+
+/// Other Lisps' names for `car`, `cdr` and `null?`, as `(alias, builtin)`.
+pub const LIST_ALIASES: &[(&str, &str)] = &[("head", "car"), ("tail", "cdr"), ("empty?", "null?")];
+
+/// Two-argument builtins that also take more: `(+ 1 2 3)` folds left to
+/// right, and `(< a b c)` holds when each neighbouring pair does.
+pub const VARIADIC: &[&str] = &[
+    "+", "-", "*", "/", "str-concat", "=", "<", ">", "<=", ">=",
+];
+
+/// Call the `VARIADIC` builtin `name` with more than two arguments.
+pub fn call_variadic(name: &str, func: BuiltinFn, args: &[Value]) -> Result<Value, String> {
+    let Some((first, rest)) = args.split_first() else { return func(args) };
+    if name.starts_with(['=', '<', '>']) {
+        for pair in args.windows(2) {
+            if let Value::Bool(false) = func(pair)? {
+                return Ok(Value::Bool(false));
+            }
+        }
+        return Ok(Value::Bool(true));
+    }
+    rest.iter().try_fold(first.clone(), |acc, arg| func(&[acc, arg.clone()]))
+}
+
+/// Operands of a binary integer builtin at a common width: two i32s stay
+/// i32; if either is i64, both are widened to i64.
+enum Ints {
+    I32(i32, i32),
+    I64(i64, i64),
+}
+
+fn ints(a: &Value, b: &Value) -> Option<Ints> {
+    match (a, b) {
+        (Value::Integer32(a), Value::Integer32(b)) => Some(Ints::I32(*a, *b)),
+        (Value::Integer32(a), Value::Integer64(b)) => Some(Ints::I64(i64::from(*a), *b)),
+        (Value::Integer64(a), Value::Integer32(b)) => Some(Ints::I64(*a, i64::from(*b))),
+        (Value::Integer64(a), Value::Integer64(b)) => Some(Ints::I64(*a, *b)),
+        _ => None,
+    }
+}
+
+fn arith(
+    op: &str,
+    args: &[Value],
+    on_i32: fn(i32, i32) -> Option<i32>,
+    on_i64: fn(i64, i64) -> Option<i64>,
+    on_f64: fn(f64, f64) -> f64,
+) -> Result<Value, String> {
+    if let (Value::Float(a), Value::Float(b)) = (&args[0], &args[1]) {
+        return Ok(Value::Float(on_f64(*a, *b)));
+    }
+    match ints(&args[0], &args[1]) {
+        Some(Ints::I32(a, b)) => on_i32(a, b).map(Value::Integer32).ok_or_else(|| {
+            format!("({} {} {}) overflows i32; widen an operand with (to-i64 x)", op, a, b)
+        }),
+        Some(Ints::I64(a, b)) => on_i64(a, b)
+            .map(Value::Integer64)
+            .ok_or_else(|| format!("({} {} {}) overflows i64", op, a, b)),
+        None => Err(mixed_numbers(op, &args[0], &args[1])),
+    }
+}
+
+/// The error for an arithmetic or comparison builtin given operands it
+/// can't combine, pointing at a conversion when one is an f64.
+fn mixed_numbers(op: &str, a: &Value, b: &Value) -> String {
+    let hint = match (a, b) {
+        (Value::Float(_), Value::Integer32(_)) | (Value::Integer32(_), Value::Float(_)) => "; convert with i32->f64",
+        (Value::Float(_), Value::Integer64(_)) | (Value::Integer64(_), Value::Float(_)) => "; convert with i64->f64",
+        _ => "",
+    };
+    format!("{} requires two numbers of the same kind, got {} and {}{}", op, a.type_name(), b.type_name(), hint)
+}
+
+/// Tolerance `approx-eq` and `assert-approx-eq` use.
+const DEFAULT_EPSILON: f64 = 1e-9;
+
+/// `a` and `b` differ by at most `eps`, scaled by their magnitude once
+/// that exceeds 1 — so `eps` is an absolute tolerance near zero and a
+/// relative one for large values. NaN is never approximately anything;
+/// equal infinities are.
+fn approx_eq(a: f64, b: f64, eps: f64) -> bool {
+    a == b || (a - b).abs() <= eps * 1f64.max(a.abs()).max(b.abs())
+}
+
+/// `on_int` must return one of its operands, so an i32 pair's result
+/// narrows back to i32 losslessly.
+fn num_binary(
+    name: &str,
+    a: &Value,
+    b: &Value,
+    on_int: fn(i64, i64) -> i64,
+    on_float: fn(f64, f64) -> f64,
+) -> Result<Value, String> {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => Ok(Value::Float(on_float(*a, *b))),
+        _ => match ints(a, b) {
+            Some(Ints::I32(a, b)) => Ok(Value::Integer32(on_int(a.into(), b.into()) as i32)),
+            Some(Ints::I64(a, b)) => Ok(Value::Integer64(on_int(a, b))),
+            None => Err(format!("{} requires two numbers of the same kind, got {} and {}", name, a.type_name(), b.type_name())),
+        },
+    }
+}
+
+fn num_order(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        _ => match ints(a, b)? {
+            Ints::I32(a, b) => Some(a.cmp(&b)),
+            Ints::I64(a, b) => Some(a.cmp(&b)),
+        },
+    }
+}
+
+fn float_unary(name: &str, args: &[Value], f: fn(f64) -> f64) -> Result<Value, String> {
+    match &args[0] {
+        Value::Float(x) => Ok(Value::Float(f(*x))),
+        other => Err(format!("{} requires a float, got {}", name, other.type_name())),
+    }
+}
+
+fn float_to_int(name: &str, args: &[Value], f: fn(f64) -> f64) -> Result<Value, String> {
+    let Value::Float(x) = &args[0] else {
+        return Err(format!("{} requires a float, got {}", name, args[0].type_name()));
+    };
+    let rounded = f(*x);
+    // i64::MAX isn't representable as f64; 2^63 is the first value past it.
+    if rounded.is_nan() || rounded < i64::MIN as f64 || rounded >= 9_223_372_036_854_775_808.0 {
+        return Err(format!("{}: {} does not fit in i64", name, x));
+    }
+    Ok(Value::Integer64(rounded as i64))
+}
+
+fn float_test(name: &str, args: &[Value], holds: fn(f64) -> bool) -> Result<Value, String> {
+    match &args[0] {
+        Value::Float(x) => Ok(Value::Bool(holds(*x))),
+        other => Err(format!("{} requires a float, got {}", name, other.type_name())),
+    }
+}
+
+fn num_cmp(op: &str, args: &[Value], holds: fn(Ordering) -> bool) -> Result<Value, String> {
+    match (&args[0], &args[1]) {
+        (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a.partial_cmp(b).is_some_and(holds))),
+        (a, b) => match ints(a, b) {
+            Some(Ints::I32(a, b)) => Ok(Value::Bool(holds(a.cmp(&b)))),
+            Some(Ints::I64(a, b)) => Ok(Value::Bool(holds(a.cmp(&b)))),
+            None => Err(mixed_numbers(op, a, b)),
+        },
+    }
+}
+
+/// An array of `items`, which have to number `len`: the length an
+/// array's type was given.
+/// A vector of `items`.
+pub fn vector(items: Vec<Value>) -> Value {
+    Value::Vector(Rc::new(RefCell::new(items)))
+}
+
+fn sized_array(name: &str, items: Vec<Value>, len: i32) -> Result<Value, String> {
+    if len < 0 || len as usize != items.len() {
+        return Err(format!("{}: expected {} items, got {}", name, len, items.len()));
+    }
+    Ok(Value::Array(Rc::new(RefCell::new(items))))
+}
+
+/// Checks an array index, naming the builtin in the error.
+fn array_index(name: &str, index: i32, len: usize) -> Result<usize, String> {
+    if index < 0 || index as usize >= len {
+        Err(format!("{}: index {} out of bounds for length {}", name, index, len))
+    } else {
+        Ok(index as usize)
+    }
+}
+
+type SharedBytes = Rc<RefCell<Vec<u8>>>;
+
+fn shared_bytes(data: Vec<u8>) -> Value {
+    Value::Bytes(Rc::new(RefCell::new(data)))
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+/// Resolve `(buf offset ...)` and bounds-check a `width`-byte window.
+fn byte_window(
+    args: &[Value],
+    name: &str,
+    width: usize,
+) -> Result<(SharedBytes, usize), String> {
+    match (&args[0], &args[1]) {
+        (Value::Bytes(buf), Value::Integer32(offset)) => {
+            let len = buf.borrow().len();
+            if *offset < 0 || *offset as usize + width > len {
+                return Err(format!(
+                    "{}: offset {} out of bounds for {}-byte buffer",
+                    name, offset, len
+                ));
+            }
+            Ok((buf.clone(), *offset as usize))
+        }
+        _ => Err(format!("{} requires bytes and an integer offset", name)),
+    }
+}
+
+fn load(bytes: &[u8], endian: Endian) -> u64 {
+    let fold = |acc: u64, b: &u8| (acc << 8) | u64::from(*b);
+    match endian {
+        Endian::Big => bytes.iter().fold(0, fold),
+        Endian::Little => bytes.iter().rev().fold(0, fold),
+    }
+}
+
+fn store(bytes: &mut [u8], value: u64, endian: Endian) {
+    let n = bytes.len();
+    for (i, b) in bytes.iter_mut().enumerate() {
+        let shift = match endian {
+            Endian::Little => i,
+            Endian::Big => n - 1 - i,
+        };
+        *b = (value >> (shift * 8)) as u8;
     }
-}
\ No newline at end of file
+}
+
+/// Integer result in the narrowest Rusp type that holds every value of
+/// the encoded width: i32 up to i32/u16, i64 for u32 and 64-bit.
+fn int_value(raw: u64, width: usize, signed: bool) -> Value {
+    let bits = width * 8;
+    let v = if signed && bits < 64 && raw >> (bits - 1) & 1 == 1 {
+        (raw | (!0u64 << bits)) as i64
+    } else {
+        raw as i64
+    };
+    if width < 4 || (width == 4 && signed) {
+        Value::Integer32(v as i32)
+    } else {
+        Value::Integer64(v)
+    }
+}
+
+fn read_int(args: &[Value], name: &str, width: usize, signed: bool, endian: Endian) -> Result<Value, String> {
+    let (buf, offset) = byte_window(args, name, width)?;
+    let raw = load(&buf.borrow()[offset..offset + width], endian);
+    Ok(int_value(raw, width, signed))
+}
+
+/// Accept any value representable in `width` bytes as either signed or
+/// unsigned; negative values are stored in two's complement.
+fn encodable_int(v: &Value, name: &str, width: usize) -> Result<u64, String> {
+    let n = match v {
+        Value::Integer32(n) => i64::from(*n),
+        Value::Integer64(n) => *n,
+        other => return Err(format!("{} requires an integer value, got {}", name, other.type_name())),
+    };
+    let bits = width * 8;
+    if bits < 64 && (n < -(1i64 << (bits - 1)) || n >= 1i64 << bits) {
+        return Err(format!("{}: {} does not fit in {} bytes", name, n, width));
+    }
+    Ok(n as u64)
+}
+
+fn write_int(args: &[Value], name: &str, width: usize, endian: Endian) -> Result<Value, String> {
+    let (buf, offset) = byte_window(args, name, width)?;
+    let raw = encodable_int(&args[2], name, width)?;
+    store(&mut buf.borrow_mut()[offset..offset + width], raw, endian);
+    Ok(args[0].clone())
+}
+
+fn read_f64(args: &[Value], name: &str, endian: Endian) -> Result<Value, String> {
+    let (buf, offset) = byte_window(args, name, 8)?;
+    let raw = load(&buf.borrow()[offset..offset + 8], endian);
+    Ok(Value::Float(f64::from_bits(raw)))
+}
+
+fn write_f64(args: &[Value], name: &str, endian: Endian) -> Result<Value, String> {
+    let (buf, offset) = byte_window(args, name, 8)?;
+    let Value::Float(f) = &args[2] else {
+        return Err(format!("{} requires an f64 value", name));
+    };
+    store(&mut buf.borrow_mut()[offset..offset + 8], f.to_bits(), endian);
+    Ok(args[0].clone())
+}
+
+/// One field of a `pack`/`unpack` format: byte width and signedness.
+/// `pad` marks an `x` byte, which consumes no value.
+struct Field {
+    width: usize,
+    signed: bool,
+    pad: bool,
+}
+
+/// Parse a struct-style format: optional byte order (`<` little, the
+/// default; `>` or `!` big), then codes `b B h H i I q Q x`, each with an
+/// optional repeat count (`4B`).
+fn parse_format(fmt: &str, name: &str) -> Result<(Endian, Vec<Field>), String> {
+    let mut chars = fmt.chars().peekable();
+    let endian = match chars.peek() {
+        Some('<') => {
+            chars.next();
+            Endian::Little
+        }
+        Some('>') | Some('!') => {
+            chars.next();
+            Endian::Big
+        }
+        _ => Endian::Little,
+    };
+    let mut fields = Vec::new();
+    let mut count = String::new();
+    for c in chars {
+        if c.is_ascii_digit() {
+            count.push(c);
+            continue;
+        }
+        let (width, signed, pad) = match c {
+            'x' => (1, false, true),
+            'b' => (1, true, false),
+            'B' => (1, false, false),
+            'h' => (2, true, false),
+            'H' => (2, false, false),
+            'i' => (4, true, false),
+            'I' => (4, false, false),
+            'q' => (8, true, false),
+            'Q' => (8, false, false),
+            c if c.is_whitespace() => continue,
+            other => return Err(format!("{}: unknown format code '{}'", name, other)),
+        };
+        let n: usize = if count.is_empty() { 1 } else { count.parse().unwrap_or(0) };
+        count.clear();
+        for _ in 0..n {
+            fields.push(Field { width, signed, pad });
+        }
+    }
+    if !count.is_empty() {
+        return Err(format!("{}: repeat count without a format code", name));
+    }
+    Ok((endian, fields))
+}
+
+/// `(pack fmt values)` — encode a list of integers into fresh bytes.
+fn pack(args: &[Value]) -> Result<Value, String> {
+    let Value::String(fmt) = &args[0] else {
+        return Err("pack requires a format string".to_string());
+    };
+    let values: &[Value] = match &args[1] {
+        Value::List(vs) => vs,
+        Value::Nil => &[],
+        _ => return Err("pack requires a list of values".to_string()),
+    };
+    let (endian, fields) = parse_format(fmt, "pack")?;
+    let wanted = fields.iter().filter(|f| !f.pad).count();
+    if wanted != values.len() {
+        return Err(format!("pack: format needs {} values, got {}", wanted, values.len()));
+    }
+    let mut out = Vec::new();
+    let mut values = values.iter();
+    for f in &fields {
+        let start = out.len();
+        out.resize(start + f.width, 0);
+        if !f.pad {
+            let v = values.next().expect("counted above");
+            let raw = encodable_int(v, "pack", f.width)?;
+            store(&mut out[start..], raw, endian);
+        }
+    }
+    Ok(Value::Bytes(Rc::new(RefCell::new(out))))
+}
+
+/// `(unpack fmt buf offset)` — decode integers as a list of i64.
+fn unpack(args: &[Value]) -> Result<Value, String> {
+    let Value::String(fmt) = &args[0] else {
+        return Err("unpack requires a format string".to_string());
+    };
+    let (endian, fields) = parse_format(fmt, "unpack")?;
+    let total: usize = fields.iter().map(|f| f.width).sum();
+    let (buf, mut offset) = byte_window(&args[1..], "unpack", total)?;
+    let buf = buf.borrow();
+    let mut out = Vec::new();
+    for f in &fields {
+        if !f.pad {
+            let raw = load(&buf[offset..offset + f.width], endian);
+            let v = match int_value(raw, f.width, f.signed) {
+                Value::Integer32(n) => i64::from(n),
+                Value::Integer64(n) => n,
+                _ => unreachable!("int_value only returns integers"),
+            };
+            out.push(Value::Integer64(v));
+        }
+        offset += f.width;
+    }
+    Ok(if out.is_empty() { Value::Nil } else { Value::List(out) })
+}
+
+fn path_arg<'a>(v: &'a Value, name: &str) -> Result<&'a Path, String> {
+    match v {
+        Value::String(s) => Ok(Path::new(s.as_str())),
+        _ => Err(format!("{} requires a path string, got {}", name, v.type_name())),
+    }
+}
+
+fn path_value(p: &Path) -> Result<Value, String> {
+    Ok(Value::String(p.to_string_lossy().into_owned().try_into()?))
+}
+
+fn file_metadata(p: &Path, name: &str) -> Result<std::fs::Metadata, String> {
+    std::fs::metadata(p).map_err(|e| format!("{}: {}: {}", name, p.display(), e))
+}
+
+fn read_dir_sorted(dir: &Path, name: &str) -> Result<Vec<std::path::PathBuf>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}: {}", name, dir.display(), e))?;
+    let mut paths = entries
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}: {}", name, dir.display(), e))?;
+    paths.sort();
+    Ok(paths)
+}
+
+/// Pre-order: each directory is listed before its contents.
+fn walk_dir(dir: &Path, out: &mut Vec<Value>) -> Result<(), String> {
+    for p in read_dir_sorted(dir, "walk-dir")? {
+        out.push(path_value(&p)?);
+        let is_real_dir = std::fs::symlink_metadata(&p).map(|m| m.is_dir()).unwrap_or(false);
+        if is_real_dir {
+            walk_dir(&p, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// `(db key ...)` for the `kv-*` builtins.
+fn kv_args<'a>(args: &'a [Value], name: &str) -> Result<(&'a Rc<RefCell<crate::kv::KvStore>>, &'a str), String> {
+    match (&args[0], &args[1]) {
+        (Value::KvStore(db), Value::String(key)) => Ok((db, key)),
+        _ => Err(format!("{} requires a kv store and a string key", name)),
+    }
+}
+
+// Matrix argument helpers.
+
+fn shared_matrix(m: Matrix) -> Value {
+    Value::Matrix(Rc::new(RefCell::new(m)))
+}
+
+fn matrix_arg<'a>(v: &'a Value, name: &str) -> Result<&'a Rc<RefCell<Matrix>>, String> {
+    match v {
+        Value::Matrix(m) => Ok(m),
+        _ => Err(format!("{} requires a matrix, got {}", name, v.type_name())),
+    }
+}
+
+fn int_arg(v: &Value, name: &str) -> Result<i32, String> {
+    match v {
+        Value::Integer32(n) => Ok(*n),
+        _ => Err(format!("{} requires an i32 index, got {}", name, v.type_name())),
+    }
+}
+
+/// `(mat-add a b)` and the like.
+fn elementwise(args: &[Value], name: &str, op: fn(f64, f64) -> f64) -> Result<Value, String> {
+    let a = matrix_arg(&args[0], name)?.borrow();
+    let b = matrix_arg(&args[1], name)?.borrow();
+    Ok(shared_matrix(a.zip(&b, name, op)?))
+}
+
+// Date/time argument helpers.
+
+fn datetime_arg(v: &Value, name: &str) -> Result<DateTime, String> {
+    match v {
+        Value::DateTime(t) => Ok(**t),
+        _ => Err(format!("{} requires a datetime, got {}", name, v.type_name())),
+    }
+}
+
+fn duration_arg(v: &Value, name: &str) -> Result<Duration, String> {
+    match v {
+        Value::Duration(d) => Ok(**d),
+        _ => Err(format!("{} requires a duration, got {}", name, v.type_name())),
+    }
+}
+
+fn time_int(v: &Value, name: &str) -> Result<i64, String> {
+    match v {
+        Value::Integer32(n) => Ok(i64::from(*n)),
+        Value::Integer64(n) => Ok(*n),
+        _ => Err(format!("{} requires integer arguments, got {}", name, v.type_name())),
+    }
+}
+
+/// `(name n)`: `n` units of `unit_secs` seconds each, if that many
+/// seconds fit in an i64.
+fn duration_in(v: &Value, name: &str, unit_secs: i64) -> Result<Value, String> {
+    let n = time_int(v, name)?;
+    let secs = n.checked_mul(unit_secs).ok_or_else(|| format!("({} {}) overflows i64 seconds", name, n))?;
+    Ok(Value::Duration(Duration::from_secs(secs).into()))
+}
+
+/// `(year field...)` for `date`/`datetime`. Fields are range-checked by
+/// `DateTime::from_civil`; this only rejects negatives.
+fn civil_args(args: &[Value], name: &str) -> Result<(i64, Vec<u32>), String> {
+    let year = time_int(&args[0], name)?;
+    let fields = args[1..]
+        .iter()
+        .map(|a| {
+            let n = time_int(a, name)?;
+            u32::try_from(n).map_err(|_| format!("{}: field {} out of range", name, n))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((year, fields))
+}
@@ -1,225 +1,1251 @@
-use crate::ast::{Expr, Pattern};
-use crate::env::{Environment, Value};
+//! The evaluator.
+//!
+//! Evaluation runs on an explicit stack of frames rather than the Rust
+//! stack: each frame is what's left to do once the expression being
+//! evaluated has a value. Calls, however deeply they nest, only grow that
+//! stack, and an `Evaluation` can be stopped between any two steps and
+//! picked up again later.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Pattern, Type};
+use crate::env::{Closure, Constructed, Constructor, Environment, MapKey, Value};
+use crate::generator::Generator;
+use crate::keyword::Keyword;
 
 pub fn eval(expr: &Expr, env: &mut Environment) -> Result<Value, String> {
-    match expr {
-        Expr::Integer32(n) => Ok(Value::Integer32(*n)),
-        Expr::Integer64(n) => Ok(Value::Integer64(*n)),
-        Expr::Float(f) => Ok(Value::Float(*f)),
-        Expr::Bool(b) => Ok(Value::Bool(*b)),
-        Expr::String(s) => Ok(Value::String(s.clone())),
-        Expr::Nil => Ok(Value::Nil),
-
-        Expr::Symbol(name) => {
-            env.get(name)
-                .cloned()
-                .ok_or_else(|| format!("Undefined variable: {}", name))
+    let mut evaluation = Evaluation::new(expr.clone(), std::mem::replace(env, Environment::blank()));
+    let result = evaluation.run();
+    *env = evaluation.into_env();
+    result
+}
+
+/// An evaluation in progress.
+pub struct Evaluation {
+    /// Where the expression in `control` runs. Frames that run something
+    /// elsewhere keep the one to come back to.
+    env: Environment,
+    control: Control,
+    frames: Vec<Frame>,
+}
+
+#[derive(Clone)]
+enum Control {
+    Eval(Expr),
+    Value(Value),
+    Error(String),
+}
+
+/// What to do with the value (or error) of the expression evaluated on
+/// top of this frame.
+#[derive(Clone)]
+enum Frame {
+    /// Go back to this environment.
+    Restore(Environment),
+    /// Leave the scope a `let`, `match` arm, `loop` or `with-open` opened.
+    PopScope,
+    If { then_branch: Expr, else_branch: Expr },
+    /// `and` (when `and`) or `or`: the operands still to test, last first.
+    Logic { and: bool, pending: Vec<Expr> },
+    Let { name: String, body: Expr },
+    /// The forms of a `do` still to run, last first.
+    Do(Vec<Expr>),
+    Match(Vec<(Pattern, Expr)>),
+    Def(String),
+    Defparam(String),
+    /// Arguments of a `form` evaluated so far, and the rest, last first.
+    Args { form: Form, done: Vec<Value>, pending: Vec<Expr> },
+    /// `take`'s count is in; its sequence is next.
+    Take(Expr),
+    /// `take`'s count, checked; the sequence is being evaluated.
+    TakeFrom(usize),
+    /// `map` over a list: results so far, and the items left, last first.
+    Map { f: Value, done: Vec<Value>, pending: Vec<Value> },
+    /// `filter`: the item being tested, those kept, and the rest.
+    Filter { pred: Value, item: Value, kept: Vec<Value>, pending: Vec<Value> },
+    Fold { f: Value, pending: Vec<Value>, right: bool },
+    /// A `loop` or function body, which a tail `recur` goes back to.
+    Loop { names: Vec<String>, body: Expr },
+    LoopBinding { name: String, pending: Vec<(String, Expr)>, names: Vec<String>, body: Expr },
+    /// The body of a call, where a `return` stops.
+    Body,
+    /// Where a `shift`'s continuation ends.
+    Reset,
+    Block(Keyword),
+    /// `return` (no label) or `return-from`, waiting for its value.
+    Return(Option<Keyword>),
+    /// `(the i64 e)`: widen `e` if it came out an `i32`.
+    Widen,
+    ParamBinding {
+        cell: Rc<RefCell<Vec<Value>>>,
+        done: Vec<(Rc<RefCell<Vec<Value>>>, Value)>,
+        pending: Vec<(String, Expr)>,
+        body: Expr,
+    },
+    /// Pop these parameters' bindings on the way out, however it's left.
+    ParamBody(Vec<Rc<RefCell<Vec<Value>>>>),
+    OpenBinding { name: String, opened: Vec<Value>, pending: Vec<(String, Expr)>, body: Expr },
+    /// Close these resources on the way out, however it's left.
+    OpenBody(Vec<Value>),
+    /// Run the scope's `defer`s on the way out.
+    DeferScope,
+    /// A deferred expression is running; these are still to come, and
+    /// `result` is what the scope ends with unless one of them fails.
+    Deferred { pending: Vec<(Expr, Environment)>, result: Result<Value, String> },
+}
+
+/// The frames a `shift` took, down to (not including) its `reset`, and
+/// the environment it was in.
+#[derive(Clone)]
+pub struct Continuation {
+    frames: Vec<Frame>,
+    env: Environment,
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Continuation({} frames)", self.frames.len())
+    }
+}
+
+/// Forms whose arguments are evaluated left to right before anything
+/// else happens.
+#[derive(Clone)]
+enum Form {
+    /// The function first, then its arguments.
+    Call(Option<String>),
+    Recur,
+    List,
+    /// `#[a b c]`
+    Array,
+    /// `[a b c]`
+    Vector,
+    /// `{k v ...}`
+    HashMap,
+    Map,
+    Filter,
+    /// `foldr` when `right`: from the last element, which comes first in
+    /// each call.
+    Fold { right: bool },
+}
+
+impl Evaluation {
+    pub fn new(expr: Expr, env: Environment) -> Evaluation {
+        Evaluation { env, control: Control::Eval(expr), frames: Vec::new() }
+    }
+
+    /// Take one step: the result once there's nothing left to do, None
+    /// while there is.
+    pub fn step(&mut self) -> Option<Result<Value, String>> {
+        let control = std::mem::replace(&mut self.control, Control::Value(Value::Nil));
+        self.control = match control {
+            Control::Eval(expr) => self.start(expr),
+            Control::Value(value) => match self.frames.pop() {
+                Some(frame) => self.resume(frame, Ok(value)),
+                None => {
+                    self.control = Control::Value(value.clone());
+                    return Some(Ok(value));
+                }
+            },
+            Control::Error(e) => match self.frames.pop() {
+                Some(frame) => self.resume(frame, Err(e)),
+                None => {
+                    self.control = Control::Error(e.clone());
+                    return Some(Err(e));
+                }
+            },
+        };
+        None
+    }
+
+    /// Step until done.
+    pub fn run(&mut self) -> Result<Value, String> {
+        loop {
+            if let Some(result) = self.step() {
+                return result;
+            }
         }
-        
-        Expr::If { condition, then_branch, else_branch } => {
-            let cond_val = eval(condition, env)?;
-            match cond_val {
-                Value::Bool(true) => eval(then_branch, env),
-                Value::Bool(false) => eval(else_branch, env),
-                _ => Err("If condition must be a boolean".to_string()),
+    }
+
+    /// The environment the evaluation was started in, with whatever its
+    /// top-level `def`s have added so far.
+    pub fn into_env(mut self) -> Environment {
+        while let Some(frame) = self.frames.pop() {
+            match frame {
+                Frame::Restore(env) => self.env = env,
+                Frame::PopScope => self.pop_scope(),
+                Frame::ParamBody(cells) => {
+                    for cell in &cells {
+                        cell.borrow_mut().pop();
+                    }
+                }
+                Frame::DeferScope => {
+                    DEFERRED.with(|scopes| scopes.borrow_mut().pop());
+                }
+                _ => {}
             }
         }
-        
-        Expr::Let { name, value, body, .. } => {
-            let val = eval(value, env)?;
-            
-            if let Some(body_expr) = body {
-                // Let-in expression: evaluate body in new scope
-                let mut new_env = env.extend();
-                new_env.set(name.clone(), val);
-                eval(body_expr, &mut new_env)
-            } else {
-                // Simple let: set in current environment
-                env.set(name.clone(), val.clone());
-                Ok(val)
+        self.env
+    }
+
+    fn push_scope(&mut self) {
+        self.env = self.env.clone().into_child();
+        self.frames.push(Frame::PopScope);
+    }
+
+    fn pop_scope(&mut self) {
+        self.env = self.env.clone().into_parent().expect("a scope to leave");
+    }
+
+    /// Run `expr` in `env`, then come back here.
+    fn eval_in(&mut self, expr: Expr, env: Environment) -> Control {
+        let outer = std::mem::replace(&mut self.env, env);
+        self.frames.push(Frame::Restore(outer));
+        Control::Eval(expr)
+    }
+
+    /// Evaluate `pending` (last first) for `form`, then finish it.
+    fn args(&mut self, form: Form, done: Vec<Value>, mut pending: Vec<Expr>) -> Control {
+        match pending.pop() {
+            Some(next) => {
+                self.frames.push(Frame::Args { form, done, pending });
+                Control::Eval(next)
             }
+            None => self.finish(form, done),
         }
-        
-        Expr::Defn { name, params, body, .. } => {
-            // Extract parameters and body
-            let func_params: Vec<String> = params.iter().map(|(n, _)| n.clone()).collect();
-            let func_body = *body.clone();
-            
-            // Store the function name in the closure environment
-            // We'll look it up at runtime from the calling environment
-            let func = Value::Function {
-                params: func_params,
-                body: func_body,
-                env: env.clone(),  // Use the current environment
-            };
-            
-            // Store the function in the outer environment
-            env.set(name.clone(), func.clone());
-            Ok(func)
+    }
+
+    fn start(&mut self, expr: Expr) -> Control {
+        match expr {
+            Expr::Integer32(n) => Control::Value(Value::Integer32(n)),
+            Expr::Integer64(n) => Control::Value(Value::Integer64(n)),
+            Expr::Float(f) => Control::Value(Value::Float(f)),
+            Expr::Bool(b) => Control::Value(Value::Bool(b)),
+            Expr::String(s) => match s.try_into() {
+                Ok(s) => Control::Value(built(Value::String(s))),
+                Err(e) => Control::Error(e),
+            },
+            Expr::Bytes(b) => Control::Value(Value::Bytes(Rc::new(RefCell::new(b)))),
+            Expr::Keyword(k) => Control::Value(Value::Keyword(k)),
+            Expr::Nil => Control::Value(Value::Nil),
+
+            Expr::Symbol(name) => match self.env.get(&name) {
+                Some(Value::Param(cell)) => Control::Value(cell.borrow().last().cloned().unwrap_or(Value::Nil)),
+                Some(value) => {
+                    if crate::profile::enabled() {
+                        crate::profile::record_clone(&value);
+                    }
+                    Control::Value(value)
+                }
+                None => Control::Error(format!("Undefined variable: {}", name)),
+            },
+
+            Expr::If { condition, then_branch, else_branch } => {
+                self.frames.push(Frame::If { then_branch: *then_branch, else_branch: *else_branch });
+                Control::Eval(*condition)
+            }
+
+            Expr::Let { name, value, body, .. } => {
+                self.frames.push(Frame::Let { name, body: *body });
+                Control::Eval(*value)
+            }
+
+            Expr::Do(mut exprs) => {
+                if exprs.is_empty() {
+                    return Control::Error("do needs at least one form".to_string());
+                }
+                exprs.reverse();
+                let first = exprs.pop().expect("checked above");
+                if !exprs.is_empty() {
+                    self.frames.push(Frame::Do(exprs));
+                }
+                Control::Eval(first)
+            }
+
+            Expr::Match { scrutinee, arms } => {
+                self.frames.push(Frame::Match(arms));
+                Control::Eval(*scrutinee)
+            }
+
+            Expr::Recur(mut args) => {
+                args.reverse();
+                self.args(Form::Recur, Vec::new(), args)
+            }
+
+            Expr::Def { name, value, .. } => {
+                self.frames.push(Frame::Def(name));
+                Control::Eval(*value)
+            }
+
+            Expr::Defn { ref name, .. } => {
+                let Some(closure) = self.defn_closure(&expr) else { unreachable!("a defn") };
+                let func = self.define(name.clone(), closure);
+                crate::tier::record_defn(&expr);
+                Control::Value(func)
+            }
+
+            Expr::MultiArity(defns) => {
+                let mut arities: Vec<Closure> = defns.iter().filter_map(|d| self.defn_closure(d)).collect();
+                if arities.is_empty() {
+                    return Control::Error("internal: a multi-arity defn with no defns".to_string());
+                }
+                let mut closure = arities.remove(0);
+                closure.arities = arities.into_iter().map(Rc::new).collect();
+                let name = closure.name.clone().unwrap_or_default();
+                Control::Value(self.define(name, closure))
+            }
+
+            Expr::Defparam { name, value } => {
+                self.frames.push(Frame::Defparam(name));
+                Control::Eval(*value)
+            }
+
+            Expr::Deftype { name, variants } => {
+                for (tag, fields) in variants {
+                    let constructor = Rc::new(Constructor { type_name: name.clone(), tag: tag.clone(), fields, labels: Vec::new() });
+                    let value = if constructor.fields.is_empty() {
+                        Value::Constructed(Rc::new(Constructed { constructor, fields: Vec::new() }))
+                    } else {
+                        Value::Constructor(constructor)
+                    };
+                    self.env.set(tag, value);
+                }
+                Control::Value(Value::Type(Rc::new(Type::Named(name))))
+            }
+
+            Expr::Defstruct { name, fields } => {
+                let (labels, fields) = fields.into_iter().unzip();
+                let constructor = Constructor { type_name: name.clone(), tag: name.clone(), fields, labels };
+                self.env.set(name.clone(), Value::Constructor(Rc::new(constructor)));
+                Control::Value(Value::Type(Rc::new(Type::Named(name))))
+            }
+
+            Expr::Parameterize { mut bindings, body } => {
+                bindings.reverse();
+                self.parameterize(Vec::new(), bindings, *body)
+            }
+
+            Expr::WithOpen { mut bindings, body } => {
+                self.push_scope();
+                bindings.reverse();
+                self.open(Vec::new(), bindings, *body)
+            }
+
+            Expr::Defer(deferred) => DEFERRED.with(|scopes| match scopes.borrow_mut().last_mut() {
+                Some(scope) => {
+                    scope.push((*deferred, self.env.clone()));
+                    Control::Value(Value::Nil)
+                }
+                None => {
+                    Control::Error("defer is only allowed inside a function body or with-open".to_string())
+                }
+            }),
+
+            Expr::Defgen { name, params, body, meta } => {
+                let func = Value::Function(Rc::new(Closure {
+                    name: Some(name.clone()),
+                    params: params.iter().map(|(n, _)| n.clone()).collect(),
+                    param_types: params.iter().map(|(_, t)| t.clone()).collect(),
+                    return_type: Type::Inferred,
+                    meta,
+                    body: *body,
+                    env: self.env.clone(),
+                    generator: true,
+                    defers: false,
+                    arities: Vec::new(),
+                }));
+                let func = built(func);
+                self.env.set(name, func.clone());
+                Control::Value(func)
+            }
+
+            // Generator bodies run on `generator`'s frames, which handle
+            // `yield` themselves.
+            Expr::Yield(_) => Control::Error("yield is only allowed inside defgen".to_string()),
+
+            Expr::Return(value) => {
+                self.frames.push(Frame::Return(None));
+                Control::Eval(*value)
+            }
+
+            Expr::The { ty: Type::I64, expr } => {
+                self.frames.push(Frame::Widen);
+                Control::Eval(*expr)
+            }
+            Expr::The { expr, .. } => Control::Eval(*expr),
+
+            Expr::SetReaderMacro { .. } => Control::Value(Value::Nil),
+
+            Expr::Reset(body) => {
+                self.frames.push(Frame::Reset);
+                Control::Eval(*body)
+            }
+
+            Expr::Shift { name, body } => self.shift(name, *body),
+
+            Expr::Block { label, body } => {
+                self.frames.push(Frame::Block(label));
+                Control::Eval(*body)
+            }
+
+            Expr::ReturnFrom { label, value } => {
+                self.frames.push(Frame::Return(Some(label)));
+                Control::Eval(*value)
+            }
+
+            Expr::Lambda { params, return_type, body } => {
+                let defers = has_defer(&body);
+                let (params, param_types) = params.into_iter().unzip();
+                Control::Value(built(Value::Function(Rc::new(Closure {
+                    name: None,
+                    params,
+                    param_types,
+                    return_type: return_type.unwrap_or(Type::Inferred),
+                    meta: Vec::new(),
+                    body: *body,
+                    env: self.env.clone(),
+                    generator: false,
+                    defers,
+                    arities: Vec::new(),
+                }))))
+            }
+
+            Expr::Loop { bindings, body } => {
+                self.push_scope();
+                let names = bindings.iter().map(|(name, _, _)| name.clone()).collect();
+                let mut pending: Vec<(String, Expr)> =
+                    bindings.into_iter().map(|(name, _, value)| (name, value)).collect();
+                pending.reverse();
+                self.loop_bindings(pending, names, *body)
+            }
+
+            Expr::Call { func, mut args } => {
+                // Pass the call-site name (if any) so the call can rebind
+                // the function for recursive calls.
+                let call_name = match &*func {
+                    Expr::Symbol(name) => Some(name.clone()),
+                    _ => None,
+                };
+                // Last first, so the function (evaluated first) goes at the end.
+                args.reverse();
+                args.push(*func);
+                self.args(Form::Call(call_name), Vec::new(), args)
+            }
+
+            Expr::List(exprs) => self.start_list(exprs),
         }
-        
-        Expr::Lambda { params, body, .. } => {
-            Ok(Value::Function {
-                params: params.iter().map(|(n, _)| n.clone()).collect(),
-                body: *body.clone(),
-                env: env.clone(),
-            })
+    }
+
+    fn start_list(&mut self, mut exprs: Vec<Expr>) -> Control {
+        if exprs.is_empty() {
+            return Control::Error("Empty list".to_string());
         }
-        
-        Expr::Match { scrutinee, arms } => {
-            let value = eval(scrutinee, env)?;
-            for (pat, body) in arms {
-                let mut new_env = env.extend();
-                if pattern_match(pat, &value, &mut new_env) {
-                    return eval(body, &mut new_env);
+        let op = match &exprs[0] {
+            Expr::Symbol(op) => op.as_str(),
+            _ => "",
+        };
+        let arity_error = |n: usize, message: &str| (exprs.len() != n).then(|| Control::Error(message.to_string()));
+        let error = match op {
+            "if" => arity_error(4, "If requires 3 arguments"),
+            "map" => arity_error(3, "map requires 2 arguments: (map f lst)"),
+            "filter" => arity_error(3, "filter requires 2 arguments: (filter pred lst)"),
+            "take" => arity_error(3, "take requires 2 arguments: (take n seq)"),
+            "fold" | "foldl" => arity_error(4, "fold requires 3 arguments: (fold f init lst)"),
+            "foldr" => arity_error(4, "foldr requires 3 arguments: (foldr f init lst)"),
+            "bindings" => arity_error(1, "bindings takes no arguments"),
+            "memory-stats" => arity_error(1, "memory-stats takes no arguments"),
+            _ => None,
+        };
+        if let Some(error) = error {
+            return error;
+        }
+        let form = match op {
+            "if" => {
+                let else_branch = exprs.pop().expect("checked above");
+                let then_branch = exprs.pop().expect("checked above");
+                let condition = exprs.pop().expect("checked above");
+                return self.start(Expr::If {
+                    condition: Box::new(condition),
+                    then_branch: Box::new(then_branch),
+                    else_branch: Box::new(else_branch),
+                });
+            }
+            "let" => {
+                if exprs.len() < 3 {
+                    return Control::Error("Let requires at least 2 arguments".to_string());
                 }
+                let Expr::Symbol(name) = exprs[1].clone() else {
+                    return Control::Error("Let binding must have a symbol name".to_string());
+                };
+                if exprs.len() == 3 {
+                    return Control::Error("let requires a body".to_string());
+                } else if exprs.len() != 4 {
+                    return Control::Error("Invalid let expression".to_string());
+                }
+                let body = exprs.pop().expect("checked above");
+                let value = exprs.pop().expect("checked above");
+                return self.start(Expr::Let {
+                    name,
+                    type_ann: None,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                });
             }
-            Err(format!("No match arm matched value: {}", value))
-        }
+            "and" | "or" => {
+                let and = op == "and";
+                let mut pending = exprs.split_off(1);
+                pending.reverse();
+                let Some(first) = pending.pop() else { return Control::Value(Value::Bool(and)) };
+                self.frames.push(Frame::Logic { and, pending });
+                return Control::Eval(first);
+            }
+            "bindings" => {
+                let pairs = self.env.bindings().into_iter().map(|(name, value)| -> Result<Value, String> {
+                    // A builtin's value only knows its arity; the checker
+                    // has its signature.
+                    let ty = match value {
+                        Value::BuiltinFunction { name, .. } => crate::lint::builtin_type(name),
+                        _ => None,
+                    };
+                    let ty = ty.unwrap_or_else(|| value.type_of());
+                    let name = Value::String(name.as_str().try_into()?);
+                    Ok(Value::Pair(Rc::new((name, Value::String(ty.to_string().try_into()?)))))
+                });
+                return match pairs.collect() {
+                    Ok(pairs) => Control::Value(Value::List(pairs)),
+                    Err(e) => Control::Error(e),
+                };
+            }
+            "memory-stats" => {
+                let stats = self.env.heap_stats();
+                let entry = |key: &str, n: usize| {
+                    let n = Value::Integer32(i32::try_from(n).unwrap_or(i32::MAX));
+                    Value::Pair(Rc::new((Value::Keyword(Keyword::intern(key)), n)))
+                };
+                let mut entries = vec![entry("environments", stats.environments), entry("keywords", stats.keywords)];
+                entries.extend(stats.values.iter().map(|(kind, &n)| entry(kind, n)));
+                return Control::Value(Value::List(entries));
+            }
+            "defined?" => {
+                return match &exprs[1..] {
+                    [Expr::Symbol(name)] => Control::Value(Value::Bool(self.env.get(name).is_some())),
+                    _ => Control::Error("defined? expects a name: (defined? x)".to_string()),
+                };
+            }
+            "take" => {
+                let seq = exprs.pop().expect("checked above");
+                self.frames.push(Frame::Take(seq));
+                return Control::Eval(exprs.pop().expect("checked above"));
+            }
+            "list" => Form::List,
+            "array" => Form::Array,
+            "vector" => Form::Vector,
+            "hash-map" => Form::HashMap,
+            "map" => Form::Map,
+            "filter" => Form::Filter,
+            "fold" | "foldl" => Form::Fold { right: false },
+            "foldr" => Form::Fold { right: true },
+            _ => {
+                let func = exprs.remove(0);
+                return self.start(Expr::Call { func: Box::new(func), args: exprs });
+            }
+        };
+        exprs.remove(0);
+        exprs.reverse();
+        self.args(form, Vec::new(), exprs)
+    }
 
-        Expr::Call { func, args } => {
-            let func_val = eval(func, env)?;
-            let arg_vals: Result<Vec<_>, _> = args.iter().map(|a| eval(a, env)).collect();
-            let arg_vals = arg_vals?;
+    fn resume(&mut self, frame: Frame, outcome: Result<Value, String>) -> Control {
+        // Frames that run on the way out whether or not there was an error.
+        let outcome = match frame {
+            Frame::Restore(env) => {
+                self.env = env;
+                return control(outcome);
+            }
+            Frame::PopScope => {
+                self.pop_scope();
+                return control(outcome);
+            }
+            Frame::Body => {
+                if crate::profile::enabled() {
+                    crate::profile::leave();
+                }
+                return control(catch_return(None, outcome));
+            }
+            Frame::Block(label) => return control(catch_return(Some(label), outcome)),
+            Frame::ParamBody(cells) => {
+                for cell in &cells {
+                    cell.borrow_mut().pop();
+                }
+                return control(outcome);
+            }
+            Frame::OpenBody(opened) => return control(close_all(&opened, outcome)),
+            Frame::OpenBinding { opened, .. } if outcome.is_err() => {
+                return control(close_all(&opened, outcome));
+            }
+            Frame::DeferScope => {
+                let deferred = DEFERRED.with(|scopes| scopes.borrow_mut().pop()).unwrap_or_default();
+                return self.run_deferred(deferred, outcome);
+            }
+            Frame::Deferred { pending, mut result } => {
+                // A failing deferred expression only replaces a successful
+                // result or a `return`, so the original error isn't lost.
+                if let Err(e) = outcome
+                    && ok_or_returning(&result)
+                {
+                    result = Err(e);
+                }
+                return self.run_deferred(pending, result);
+            }
+            frame => match outcome {
+                Ok(value) => (frame, value),
+                Err(e) => return Control::Error(e),
+            },
+        };
 
-            // Pass the call-site name (if any) so apply_function can rebind
-            // the function for recursive calls.
-            let call_name = if let Expr::Symbol(name) = &**func {
-                Some(name.as_str())
-            } else {
-                None
-            };
-            apply_function(&func_val, &arg_vals, env, call_name)
-        }
-        
-        Expr::List(exprs) => {
-            if exprs.is_empty() {
-                return Err("Empty list".to_string());
-            }
-            
-            if let Expr::Symbol(op) = &exprs[0] {
-                match op.as_str() {
-                    "if" => {
-                        if exprs.len() != 4 {
-                            return Err("If requires 3 arguments".to_string());
-                        }
-                        eval(&Expr::If {
-                            condition: Box::new(exprs[1].clone()),
-                            then_branch: Box::new(exprs[2].clone()),
-                            else_branch: Box::new(exprs[3].clone()),
-                        }, env)
-                    }
-                    "list" => {
-                        // Evaluate all arguments and create a list
-                        let values = exprs
-                            .iter()
-                            .skip(1)
-                            .map(|e| eval(e, env))
-                            .collect::<Result<Vec<_>, _>>()?;
-                        Ok(Value::List(values))
+        let (frame, value) = outcome;
+        match frame {
+            Frame::If { then_branch, else_branch } => match value {
+                Value::Bool(true) => Control::Eval(then_branch),
+                Value::Bool(false) => Control::Eval(else_branch),
+                other => Control::Error(format!("If condition must be bool, got {}", other.type_name())),
+            },
+            // `and` stops at the first false, `or` at the first true.
+            Frame::Logic { and, mut pending } => match value {
+                Value::Bool(b) if b != and => Control::Value(Value::Bool(b)),
+                Value::Bool(b) => match pending.pop() {
+                    Some(next) => {
+                        self.frames.push(Frame::Logic { and, pending });
+                        Control::Eval(next)
                     }
-                    "map" => {
-                        if exprs.len() != 3 {
-                            return Err("map requires 2 arguments: (map f lst)".to_string());
-                        }
-                        let f = eval(&exprs[1], env)?;
-                        let lst = eval(&exprs[2], env)?;
-                        let items = list_items(&lst, "map")?;
-                        let mut result = Vec::with_capacity(items.len());
-                        for item in items {
-                            result.push(apply_function(&f, &[item], env, None)?);
-                        }
-                        Ok(Value::List(result))
+                    None => Control::Value(Value::Bool(b)),
+                },
+                other => Control::Error(format!(
+                    "{} requires booleans, got {}",
+                    if and { "and" } else { "or" },
+                    other.type_name()
+                )),
+            },
+            Frame::Let { name, body } => {
+                self.push_scope();
+                self.env.set(name, value);
+                Control::Eval(body)
+            }
+            Frame::Do(mut rest) => {
+                let next = rest.pop().expect("a Do frame has forms left");
+                if !rest.is_empty() {
+                    self.frames.push(Frame::Do(rest));
+                }
+                Control::Eval(next)
+            }
+            Frame::Match(arms) => {
+                for (pat, body) in arms {
+                    self.push_scope();
+                    match pattern_match(&pat, &value, &mut self.env) {
+                        Ok(true) => return Control::Eval(body),
+                        Ok(false) => {}
+                        Err(e) => return Control::Error(e),
                     }
-                    "filter" => {
-                        if exprs.len() != 3 {
-                            return Err("filter requires 2 arguments: (filter pred lst)".to_string());
-                        }
-                        let pred = eval(&exprs[1], env)?;
-                        let lst = eval(&exprs[2], env)?;
-                        let items = list_items(&lst, "filter")?;
-                        let mut result = Vec::new();
-                        for item in items {
-                            match apply_function(&pred, std::slice::from_ref(&item), env, None)? {
-                                Value::Bool(true) => result.push(item),
-                                Value::Bool(false) => {}
-                                other => {
-                                    return Err(format!(
-                                        "filter predicate must return bool, got {}",
-                                        other.type_name()
-                                    ))
-                                }
+                    self.frames.pop();
+                    self.pop_scope();
+                }
+                Control::Error(format!("No match arm matched value: {}", value))
+            }
+            Frame::Def(name) => {
+                self.env.set(name, value.clone());
+                Control::Value(value)
+            }
+            Frame::Defparam(name) => {
+                self.env.set(name, Value::Param(Rc::new(RefCell::new(vec![value.clone()]))));
+                Control::Value(value)
+            }
+            Frame::Args { form, mut done, pending } => {
+                done.push(value);
+                self.args(form, done, pending)
+            }
+            Frame::Take(seq) => match value {
+                Value::Integer32(n) if n >= 0 => self.take_from(n as usize, seq),
+                Value::Integer64(n) if n >= 0 => self.take_from(n as usize, seq),
+                other => Control::Error(format!("take expects a non-negative count, got {}", other)),
+            },
+            Frame::TakeFrom(n) => {
+                let taken = match value {
+                    Value::Generator(generator) => {
+                        let mut taken = Vec::with_capacity(n.min(1024));
+                        while taken.len() < n {
+                            match crate::generator::next(&generator) {
+                                Ok(Some(value)) => taken.push(value),
+                                Ok(None) => break,
+                                Err(e) => return Control::Error(e),
                             }
                         }
-                        if result.is_empty() {
-                            Ok(Value::Nil)
-                        } else {
-                            Ok(Value::List(result))
-                        }
+                        taken
                     }
-                    "fold" => {
-                        if exprs.len() != 4 {
-                            return Err(
-                                "fold requires 3 arguments: (fold f init lst)".to_string()
-                            );
-                        }
-                        let f = eval(&exprs[1], env)?;
-                        let mut acc = eval(&exprs[2], env)?;
-                        let lst = eval(&exprs[3], env)?;
-                        let items = list_items(&lst, "fold")?;
-                        for item in items {
-                            acc = apply_function(&f, &[acc, item], env, None)?;
-                        }
-                        Ok(acc)
+                    lst => match list_items(&lst, "take") {
+                        Ok(items) => items.into_iter().take(n).collect(),
+                        Err(e) => return Control::Error(e),
+                    },
+                };
+                Control::Value(if taken.is_empty() { Value::Nil } else { Value::List(taken) })
+            }
+            Frame::Map { f, mut done, pending } => {
+                done.push(value);
+                self.map(f, done, pending)
+            }
+            Frame::Filter { pred, item, mut kept, pending } => match value {
+                Value::Bool(true) => {
+                    kept.push(item);
+                    self.filter(pred, kept, pending)
+                }
+                Value::Bool(false) => self.filter(pred, kept, pending),
+                other => Control::Error(format!("filter predicate must return bool, got {}", other.type_name())),
+            },
+            Frame::Fold { f, pending, right } => self.fold(f, value, pending, right),
+            Frame::Loop { .. } | Frame::Reset => Control::Value(value),
+            Frame::LoopBinding { name, pending, names, body } => {
+                self.env.set(name, value);
+                self.loop_bindings(pending, names, body)
+            }
+            Frame::Return(label) => control(start_return(label, value)),
+            Frame::Widen => match value {
+                Value::Integer32(n) => Control::Value(Value::Integer64(n.into())),
+                other => Control::Value(other),
+            },
+            Frame::ParamBinding { cell, mut done, pending, body } => {
+                done.push((cell, value));
+                self.parameterize(done, pending, body)
+            }
+            Frame::OpenBinding { name, mut opened, pending, body } => {
+                self.env.set(name, value.clone());
+                opened.push(value);
+                self.open(opened, pending, body)
+            }
+            Frame::Restore(_)
+            | Frame::PopScope
+            | Frame::Body
+            | Frame::Block(_)
+            | Frame::ParamBody(_)
+            | Frame::OpenBody(_)
+            | Frame::DeferScope
+            | Frame::Deferred { .. } => unreachable!("handled above"),
+        }
+    }
+
+    /// Every argument of `form` is in.
+    fn finish(&mut self, form: Form, mut values: Vec<Value>) -> Control {
+        match form {
+            Form::Call(call_name) => {
+                let mut func = values.remove(0);
+                // Compiled code whose callees were redefined since goes
+                // back to being interpreted, binding and all.
+                if let Value::Native(native) = &func
+                    && native.is_stale()
+                {
+                    func = Value::Function(Rc::clone(&native.source));
+                    if let Some(name) = &call_name {
+                        self.env.set(name.clone(), func.clone());
                     }
-                    "let" => {
-                        if exprs.len() < 3 {
-                            return Err("Let requires at least 2 arguments".to_string());
-                        }
-                        
-                        if let Expr::Symbol(name) = &exprs[1] {
-                            let (value, body) = if exprs.len() == 4 {
-                                // Could be (let name type value) or (let name value body)
-                                // We need to check if exprs[2] is a type
-                                (exprs[2].clone(), Some(Box::new(exprs[3].clone())))
-                            } else if exprs.len() == 3 {
-                                (exprs[2].clone(), None)
-                            } else {
-                                return Err("Invalid let expression".to_string());
-                            };
-                            
-                            eval(&Expr::Let {
-                                name: name.clone(),
-                                type_ann: None,
-                                value: Box::new(value),
-                                body,
-                            }, env)
-                        } else {
-                            Err("Let binding must have a symbol name".to_string())
+                }
+                // Hot `defn`s get swapped for their compiled version, in this
+                // scope's binding so later calls from here skip the lookup.
+                if let Some(name) = &call_name
+                    && let Some(native) = crate::tier::on_call(name, &func)
+                {
+                    let native = Value::Native(native);
+                    self.env.set(name.clone(), native.clone());
+                    return self.apply(&native, values);
+                }
+                self.apply(&func, values)
+            }
+            Form::Recur => self.recur(values),
+            Form::List => Control::Value(built(Value::List(values))),
+            Form::Array => Control::Value(Value::Array(Rc::new(RefCell::new(values)))),
+            Form::Vector => Control::Value(built(Value::Vector(Rc::new(RefCell::new(values))))),
+            Form::HashMap => {
+                let mut map = HashMap::with_capacity(values.len() / 2);
+                let mut values = values.into_iter();
+                while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                    match MapKey::of(&key) {
+                        Ok(key) => map.insert(key, value),
+                        Err(e) => return Control::Error(e),
+                    };
+                }
+                Control::Value(Value::Map(Rc::new(map)))
+            }
+            Form::Map => {
+                let lst = values.pop().expect("map has two arguments");
+                let f = values.pop().expect("map has two arguments");
+                // Lazy over a generator: nothing runs until it's pulled.
+                if let Value::Generator(source) = lst {
+                    return Control::Value(Value::Generator(Rc::new(RefCell::new(Generator::map(source, f)))));
+                }
+                match list_items(&lst, "map") {
+                    Ok(mut items) => {
+                        items.reverse();
+                        self.map(f, Vec::new(), items)
+                    }
+                    Err(e) => Control::Error(e),
+                }
+            }
+            Form::Filter => {
+                let lst = values.pop().expect("filter has two arguments");
+                let pred = values.pop().expect("filter has two arguments");
+                match list_items(&lst, "filter") {
+                    Ok(mut items) => {
+                        items.reverse();
+                        self.filter(pred, Vec::new(), items)
+                    }
+                    Err(e) => Control::Error(e),
+                }
+            }
+            Form::Fold { right } => {
+                let lst = values.pop().expect("fold has three arguments");
+                let acc = values.pop().expect("fold has three arguments");
+                let f = values.pop().expect("fold has three arguments");
+                match list_items(&lst, if right { "foldr" } else { "fold" }) {
+                    Ok(mut items) => {
+                        if !right {
+                            items.reverse();
                         }
+                        self.fold(f, acc, items, right)
                     }
-                    _ => {
-                        eval(&Expr::Call {
-                            func: Box::new(exprs[0].clone()),
-                            args: exprs[1..].to_vec(),
-                        }, env)
+                    Err(e) => Control::Error(e),
+                }
+            }
+        }
+    }
+
+    /// The closure a `defn` makes, here.
+    fn defn_closure(&self, defn: &Expr) -> Option<Closure> {
+        let Expr::Defn { name, params, return_type, body, meta, .. } = defn else { return None };
+        Some(Closure {
+            name: Some(name.clone()),
+            params: params.iter().map(|(n, _)| n.clone()).collect(),
+            param_types: params.iter().map(|(_, t)| t.clone()).collect(),
+            return_type: return_type.clone(),
+            meta: meta.clone(),
+            body: (**body).clone(),
+            env: self.env.clone(),
+            generator: false,
+            defers: has_defer(body),
+            arities: Vec::new(),
+        })
+    }
+
+    /// Bind `name` to the function `closure`.
+    fn define(&mut self, name: String, closure: Closure) -> Value {
+        let func = Value::Function(Rc::new(closure));
+        if crate::profile::enabled() {
+            crate::profile::define(&name);
+            crate::profile::record_value(&func);
+        }
+        self.env.set(name, func.clone());
+        func
+    }
+
+    /// Call `func` with `args`. A closure's body runs on this stack, in a
+    /// scope of its own inside the one the closure was made in.
+    fn apply(&mut self, func: &Value, args: Vec<Value>) -> Control {
+        let args_len = args.len();
+        match func {
+            Value::Function(closure) => {
+                let Some(arity) = closure.arity(args.len()) else {
+                    let counts: Vec<String> = std::iter::once(closure.params.len())
+                        .chain(closure.arities.iter().map(|c| c.params.len()))
+                        .map(|n| n.to_string())
+                        .collect();
+                    return Control::Error(format!(
+                        "Wrong number of arguments: expected {}, got {}",
+                        counts.join(" or "),
+                        args.len()
+                    ));
+                };
+                let Closure { name, params, body, env: func_env, generator, defers, .. } = arity;
+
+                let mut new_env = func_env.extend();
+                for (param, arg) in params.iter().zip(args) {
+                    new_env.set(param.clone(), arg);
+                }
+
+                if *generator {
+                    let generator = Generator::new(params, body, new_env);
+                    return Control::Value(Value::Generator(Rc::new(RefCell::new(generator))));
+                }
+                if crate::profile::enabled() {
+                    crate::profile::enter(name.as_deref());
+                }
+                let outer = std::mem::replace(&mut self.env, new_env);
+                self.frames.push(Frame::Restore(outer));
+                self.frames.push(Frame::Body);
+                if *defers {
+                    DEFERRED.with(|scopes| scopes.borrow_mut().push(Vec::new()));
+                    self.frames.push(Frame::DeferScope);
+                }
+                self.frames.push(Frame::Loop { names: params.clone(), body: body.clone() });
+                Control::Eval(body.clone())
+            }
+            Value::BuiltinFunction { arity, func, name } => {
+                if *arity == 2 && args.len() > 2 && crate::env::VARIADIC.contains(name) {
+                    return control(crate::env::call_variadic(name, *func, &args).map(built));
+                }
+                if args.len() != *arity as usize {
+                    return Control::Error(format!(
+                        "Wrong number of arguments for {}: expected {}, got {}",
+                        name,
+                        arity,
+                        args.len()
+                    ));
+                }
+                control(func(&args).map(built))
+            }
+            Value::Constructor(constructor) => {
+                if args.len() != constructor.fields.len() {
+                    return Control::Error(format!(
+                        "Wrong number of arguments for {}: expected {}, got {}",
+                        constructor.tag,
+                        constructor.fields.len(),
+                        args.len()
+                    ));
+                }
+                let constructor = Rc::clone(constructor);
+                Control::Value(built(Value::Constructed(Rc::new(Constructed { constructor, fields: args }))))
+            }
+            Value::Native(native) if native.is_stale() => self.apply(&Value::Function(Rc::clone(&native.source)), args),
+            Value::Native(native) => control(native.call(&args)),
+            Value::Continuation(k) => {
+                let Ok([value]) = <[Value; 1]>::try_from(args) else {
+                    return Control::Error(format!("Wrong number of arguments: expected 1, got {}", args_len));
+                };
+                // Run the captured frames as if `value` had just come out
+                // of the `shift`, then come back here with what they make.
+                let outer = std::mem::replace(&mut self.env, k.env.clone());
+                self.frames.push(Frame::Restore(outer));
+                self.frames.push(Frame::Reset);
+                self.frames.extend(k.frames.iter().cloned());
+                Control::Value(value)
+            }
+            _ => Control::Error(format!("Cannot call non-function value: {}", func)),
+        }
+    }
+
+    /// Go back to the nearest `loop` or function body with `values`. Only
+    /// scopes opened since then may be left behind (`let`s and `match`
+    /// arms, which leave their bodies in tail position); anything else
+    /// means the `recur` wasn't in tail position.
+    fn recur(&mut self, values: Vec<Value>) -> Control {
+        loop {
+            match self.frames.pop() {
+                Some(Frame::PopScope) => self.pop_scope(),
+                Some(Frame::Loop { names, body }) => {
+                    // A fresh scope each round, so closures made in the last
+                    // one keep what they saw.
+                    self.env = self.env.renew();
+                    if let Err(e) = rebind(names.iter(), values, &mut self.env) {
+                        return Control::Error(e);
                     }
+                    self.frames.push(Frame::Loop { names, body: body.clone() });
+                    return Control::Eval(body);
                 }
-            } else {
-                eval(&Expr::Call {
-                    func: Box::new(exprs[0].clone()),
-                    args: exprs[1..].to_vec(),
-                }, env)
+                other => {
+                    self.frames.extend(other);
+                    return Control::Error("recur must be in tail position".to_string());
+                }
+            }
+        }
+    }
+
+    /// Take the frames down to the nearest `Reset` out of the stack into a
+    /// continuation, and run `body` with it bound to `name`, under the
+    /// `Reset`, in the environment the taken frames would have left.
+    fn shift(&mut self, name: String, body: Expr) -> Control {
+        let Some(reset) = self.frames.iter().rposition(|frame| matches!(frame, Frame::Reset)) else {
+            return Control::Error("shift is only allowed inside reset".to_string());
+        };
+        // These undo something when they're left; a continuation could
+        // leave them never, or more than once.
+        let unwinds = |frame: &Frame| {
+            matches!(
+                frame,
+                Frame::ParamBody(_)
+                    | Frame::OpenBinding { .. }
+                    | Frame::OpenBody(_)
+                    | Frame::DeferScope
+                    | Frame::Deferred { .. }
+            )
+        };
+        if self.frames[reset + 1..].iter().any(unwinds) {
+            return Control::Error("shift can't capture a parameterize, with-open or defer".to_string());
+        }
+        let frames = self.frames.split_off(reset + 1);
+        let mut env = self.env.clone();
+        for frame in frames.iter().rev() {
+            match frame {
+                Frame::Restore(outer) => env = outer.clone(),
+                Frame::PopScope => env = env.into_parent().expect("a scope to leave"),
+                _ => {}
+            }
+        }
+        let k = Value::Continuation(Rc::new(Continuation { frames, env: self.env.clone() }));
+        self.frames.push(Frame::Restore(env));
+        self.push_scope();
+        self.env.set(name, k);
+        Control::Eval(body)
+    }
+
+    fn loop_bindings(&mut self, mut pending: Vec<(String, Expr)>, names: Vec<String>, body: Expr) -> Control {
+        match pending.pop() {
+            Some((name, value)) => {
+                self.frames.push(Frame::LoopBinding { name, pending, names, body });
+                Control::Eval(value)
+            }
+            None => {
+                self.frames.push(Frame::Loop { names, body: body.clone() });
+                Control::Eval(body)
+            }
+        }
+    }
+
+    fn take_from(&mut self, n: usize, seq: Expr) -> Control {
+        self.frames.push(Frame::TakeFrom(n));
+        Control::Eval(seq)
+    }
+
+    fn map(&mut self, f: Value, done: Vec<Value>, mut pending: Vec<Value>) -> Control {
+        match pending.pop() {
+            Some(item) => {
+                self.frames.push(Frame::Map { f: f.clone(), done, pending });
+                self.apply(&f, vec![item])
             }
+            None => Control::Value(Value::List(done)),
         }
     }
+
+    fn filter(&mut self, pred: Value, kept: Vec<Value>, mut pending: Vec<Value>) -> Control {
+        match pending.pop() {
+            Some(item) => {
+                self.frames.push(Frame::Filter { pred: pred.clone(), item: item.clone(), kept, pending });
+                self.apply(&pred, vec![item])
+            }
+            None if kept.is_empty() => Control::Value(Value::Nil),
+            None => Control::Value(Value::List(kept)),
+        }
+    }
+
+    fn fold(&mut self, f: Value, acc: Value, mut pending: Vec<Value>, right: bool) -> Control {
+        match pending.pop() {
+            Some(item) => {
+                self.frames.push(Frame::Fold { f: f.clone(), pending, right });
+                let args = if right { vec![item, acc] } else { vec![acc, item] };
+                self.apply(&f, args)
+            }
+            None => Control::Value(acc),
+        }
+    }
+
+    /// Evaluate the next `parameterize` binding, or, with all of them in,
+    /// bind them and run the body.
+    fn parameterize(
+        &mut self,
+        done: Vec<(Rc<RefCell<Vec<Value>>>, Value)>,
+        mut pending: Vec<(String, Expr)>,
+        body: Expr,
+    ) -> Control {
+        match pending.pop() {
+            Some((name, value)) => {
+                let Some(Value::Param(cell)) = self.env.get(&name) else {
+                    return Control::Error(format!("parameterize: `{}` is not a parameter", name));
+                };
+                self.frames.push(Frame::ParamBinding { cell, done, pending, body });
+                Control::Eval(value)
+            }
+            None => {
+                let mut cells = Vec::with_capacity(done.len());
+                for (cell, value) in done {
+                    cell.borrow_mut().push(value);
+                    cells.push(cell);
+                }
+                self.frames.push(Frame::ParamBody(cells));
+                Control::Eval(body)
+            }
+        }
+    }
+
+    /// Open the next `with-open` resource, or, with all of them open,
+    /// run the body as a defer scope.
+    fn open(&mut self, opened: Vec<Value>, mut pending: Vec<(String, Expr)>, body: Expr) -> Control {
+        match pending.pop() {
+            Some((name, value)) => {
+                self.frames.push(Frame::OpenBinding { name, opened, pending, body });
+                Control::Eval(value)
+            }
+            None => {
+                self.frames.push(Frame::OpenBody(opened));
+                DEFERRED.with(|scopes| scopes.borrow_mut().push(Vec::new()));
+                self.frames.push(Frame::DeferScope);
+                Control::Eval(body)
+            }
+        }
+    }
+
+    /// Run a defer scope's deferred expressions, last first, each where
+    /// it was met; then carry on with `result`.
+    fn run_deferred(&mut self, mut pending: Vec<(Expr, Environment)>, result: Result<Value, String>) -> Control {
+        match pending.pop() {
+            Some((expr, env)) => {
+                self.frames.push(Frame::Deferred { pending, result });
+                self.eval_in(expr, env)
+            }
+            None => control(result),
+        }
+    }
+}
+
+fn control(result: Result<Value, String>) -> Control {
+    match result {
+        Ok(value) => Control::Value(value),
+        Err(e) => Control::Error(e),
+    }
+}
+
+/// `value`, just made: told to the allocation profiler when one's running.
+fn built(value: Value) -> Value {
+    if crate::profile::enabled() {
+        crate::profile::record_value(&value);
+    }
+    value
+}
+
+/// Close what a `with-open` opened, last first. A failure to close only
+/// replaces a successful result or a `return`: the first error wins.
+fn close_all(opened: &[Value], mut result: Result<Value, String>) -> Result<Value, String> {
+    for resource in opened.iter().rev() {
+        if let Err(e) = crate::env::close(resource)
+            && ok_or_returning(&result)
+        {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+thread_local! {
+    /// One list per active defer scope (a call whose body has a `defer`,
+    /// or a `with-open`): the deferred expressions and where they were met.
+    static DEFERRED: RefCell<Vec<Vec<(Expr, Environment)>>> = const { RefCell::new(Vec::new()) };
+    /// The value of a `return` on its way out to its call, or of a
+    /// `return-from` on its way to the `block` with that label.
+    static RETURNING: RefCell<Option<(Option<Keyword>, Value)>> = const { RefCell::new(None) };
+}
+
+/// What `(return v)` fails with, so everything between it and its function
+/// unwinds the way it does for an error: `with-open` closes, defers run,
+/// `parameterize` pops. The call's `Body` frame turns it back into `v`;
+/// one that gets any further was outside a function.
+const RETURN_SIGNAL: &str = "return is only allowed inside defn or fn";
+/// The same for `return-from`, caught by its `block`.
+const RETURN_FROM_SIGNAL: &str = "return-from is only allowed inside its block";
+
+/// Start unwinding with `value`, for the `block` called `label` or, with
+/// no label, for the function.
+fn start_return(label: Option<Keyword>, value: Value) -> Result<Value, String> {
+    RETURNING.with(|returning| *returning.borrow_mut() = Some((label, value)));
+    Err(if label.is_some() { RETURN_FROM_SIGNAL } else { RETURN_SIGNAL }.to_string())
+}
+
+/// `result`, or the value being returned if it's a return for `label`.
+fn catch_return(label: Option<Keyword>, result: Result<Value, String>) -> Result<Value, String> {
+    match result {
+        Err(e) if e == RETURN_SIGNAL || e == RETURN_FROM_SIGNAL => RETURNING.with(|returning| {
+            let mut returning = returning.borrow_mut();
+            match returning.take() {
+                Some((l, value)) if l == label => Ok(value),
+                other => {
+                    *returning = other;
+                    Err(e)
+                }
+            }
+        }),
+        result => result,
+    }
+}
+
+/// Whether `result` went well or is a return passing through, i.e. a
+/// later error should take its place.
+fn ok_or_returning(result: &Result<Value, String>) -> bool {
+    match result {
+        Ok(_) => true,
+        Err(e) => e == RETURN_SIGNAL || e == RETURN_FROM_SIGNAL,
+    }
+}
+
+/// Whether `expr` has a `defer` that belongs to it rather than to a
+/// nested function or `with-open`.
+fn has_defer(expr: &Expr) -> bool {
+    match expr {
+        Expr::Defer(_) => true,
+        Expr::If { condition, then_branch, else_branch } => {
+            has_defer(condition) || has_defer(then_branch) || has_defer(else_branch)
+        }
+        Expr::Let { value, body, .. } => has_defer(value) || has_defer(body),
+        Expr::Match { scrutinee, arms } => has_defer(scrutinee) || arms.iter().any(|(_, body)| has_defer(body)),
+        Expr::Loop { bindings, body } => bindings.iter().any(|(_, _, v)| has_defer(v)) || has_defer(body),
+        Expr::Parameterize { bindings, body } => bindings.iter().any(|(_, v)| has_defer(v)) || has_defer(body),
+        Expr::WithOpen { bindings, .. } => bindings.iter().any(|(_, v)| has_defer(v)),
+        Expr::Do(exprs) | Expr::Recur(exprs) | Expr::List(exprs) => exprs.iter().any(has_defer),
+        Expr::Call { func, args } => has_defer(func) || args.iter().any(has_defer),
+        Expr::Yield(value)
+        | Expr::Return(value)
+        | Expr::The { expr: value, .. }
+        | Expr::Block { body: value, .. }
+        | Expr::ReturnFrom { value, .. }
+        | Expr::Reset(value)
+        | Expr::Shift { body: value, .. }
+        | Expr::Def { value, .. }
+        | Expr::Defparam { value, .. } => has_defer(value),
+        Expr::Defn { .. }
+        | Expr::MultiArity(_)
+        | Expr::Defgen { .. }
+        | Expr::Deftype { .. }
+        | Expr::Defstruct { .. }
+        | Expr::Lambda { .. }
+        | Expr::Integer32(_)
+        | Expr::Integer64(_)
+        | Expr::Float(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Bytes(_)
+        | Expr::Keyword(_)
+        | Expr::Symbol(_)
+        | Expr::SetReaderMacro { .. }
+        | Expr::Nil => false,
+    }
+}
+
+/// Bind a `recur`'s values to the names it jumps back to.
+pub(crate) fn rebind<'a>(
+    names: impl ExactSizeIterator<Item = &'a String>,
+    values: Vec<Value>,
+    env: &mut Environment,
+) -> Result<(), String> {
+    if names.len() != values.len() {
+        return Err(format!("recur expects {} arguments, got {}", names.len(), values.len()));
+    }
+    for (name, value) in names.zip(values) {
+        env.set(name.clone(), value);
+    }
+    Ok(())
 }
 
 /// Try to match `value` against `pattern`, binding any captured variables
 /// into `env`. Returns true on success. On failure the caller should
-/// discard `env` (bindings already written are considered scratch).
-fn pattern_match(pattern: &Pattern, value: &Value, env: &mut Environment) -> bool {
-    match (pattern, value) {
+/// discard `env` (bindings already written are considered scratch). A
+/// guard that fails to evaluate, or yields something other than a bool,
+/// is an error rather than a failed match.
+pub(crate) fn pattern_match(pattern: &Pattern, value: &Value, env: &mut Environment) -> Result<bool, String> {
+    Ok(match (pattern, value) {
         (Pattern::Wildcard, _) => true,
         (Pattern::Variable(name), v) => {
             env.set(name.clone(), v.clone());
@@ -229,7 +1255,8 @@ fn pattern_match(pattern: &Pattern, value: &Value, env: &mut Environment) -> boo
         (Pattern::LiteralI64(a), Value::Integer64(b)) => a == b,
         (Pattern::LiteralF64(a), Value::Float(b)) => a == b,
         (Pattern::LiteralBool(a), Value::Bool(b)) => a == b,
-        (Pattern::LiteralString(a), Value::String(b)) => a == b,
+        (Pattern::LiteralString(a), Value::String(b)) => b == a,
+        (Pattern::LiteralKeyword(a), Value::Keyword(b)) => a == b,
         (Pattern::Nil, Value::Nil) => true,
         (Pattern::Nil, Value::List(items)) => items.is_empty(),
         (Pattern::Cons(head_pat, tail_pat), Value::List(items)) if !items.is_empty() => {
@@ -239,25 +1266,41 @@ fn pattern_match(pattern: &Pattern, value: &Value, env: &mut Environment) -> boo
             } else {
                 Value::List(items[1..].to_vec())
             };
-            pattern_match(head_pat, &head, env) && pattern_match(tail_pat, &tail, env)
+            pattern_match(head_pat, &head, env)? && pattern_match(tail_pat, &tail, env)?
+        }
+        (Pattern::Cons(head_pat, tail_pat), Value::Pair(pair)) => {
+            pattern_match(head_pat, &pair.0, env)? && pattern_match(tail_pat, &pair.1, env)?
         }
         // Match the inner pattern first; only bind the alias if it
         // succeeds so failed branches don't leak the alias.
-        (Pattern::As(inner, name), v) if pattern_match(inner, v, env) => {
+        (Pattern::As(inner, name), v) => {
+            if !pattern_match(inner, v, env)? {
+                return Ok(false);
+            }
             env.set(name.clone(), v.clone());
             true
         }
         (Pattern::Guard(inner, guard_expr), v) => {
             // Inner pattern first so its bindings are visible to the guard.
-            // If the guard returns false (or, defensively, anything else), the
-            // arm fails and the caller's per-arm env extension is discarded —
-            // see the Match arm in `eval`.
-            if !pattern_match(inner, v, env) {
-                return false;
-            }
-            // Type checker rejects non-bool guard expressions, so any other
-            // result here is treated as a failed match defensively.
-            matches!(eval(guard_expr, env), Ok(Value::Bool(true)))
+            // If the guard returns false the arm fails and the caller's
+            // per-arm env extension is discarded — see `Frame::Match`.
+            if !pattern_match(inner, v, env)? {
+                return Ok(false);
+            }
+            match eval(guard_expr, env)? {
+                Value::Bool(b) => b,
+                other => return Err(format!("guard expression must be bool, got {}", other.type_name())),
+            }
+        }
+        (Pattern::Constructor(tag, pats), Value::Constructed(value))
+            if value.constructor.tag == *tag && pats.len() == value.fields.len() =>
+        {
+            for (pat, field) in pats.iter().zip(&value.fields) {
+                if !pattern_match(pat, field, env)? {
+                    return Ok(false);
+                }
+            }
+            true
         }
         (Pattern::Or(branches), v) => {
             // Try each branch left-to-right. On failure, restore the env
@@ -265,15 +1308,15 @@ fn pattern_match(pattern: &Pattern, value: &Value, env: &mut Environment) -> boo
             // the next attempt or the arm body.
             let snap = env.snapshot();
             for b in branches {
-                if pattern_match(b, v, env) {
-                    return true;
+                if pattern_match(b, v, env)? {
+                    return Ok(true);
                 }
                 env.restore(snap.clone());
             }
             false
         }
         _ => false,
-    }
+    })
 }
 
 /// Normalize a list-ish Value into an owned Vec<Value>.
@@ -283,56 +1326,19 @@ fn list_items(value: &Value, op: &str) -> Result<Vec<Value>, String> {
     match value {
         Value::List(items) => Ok(items.clone()),
         Value::Nil => Ok(Vec::new()),
+        Value::Vector(items) => Ok(items.borrow().clone()),
+        // Arrays and slices are walked as lists of their elements.
+        Value::Array(_) | Value::Slice { .. } => {
+            let (items, start, end) = value.view().expect("an array or slice");
+            Ok(items.borrow()[start..end].to_vec())
+        }
         other => Err(format!("{} expects a list, got {}", op, other.type_name())),
     }
 }
 
 /// Apply a function value to pre-evaluated arguments.
-///
-/// `call_name` is the symbol the function was looked up under at the call
-/// site, if any. It's used to rebind the function into its own closure
-/// environment so direct recursion works.
-pub fn apply_function(
-    func_val: &Value,
-    args: &[Value],
-    env: &Environment,
-    call_name: Option<&str>,
-) -> Result<Value, String> {
-    match func_val {
-        Value::Function { params, body, env: func_env } => {
-            if params.len() != args.len() {
-                return Err(format!(
-                    "Wrong number of arguments: expected {}, got {}",
-                    params.len(),
-                    args.len()
-                ));
-            }
-
-            let mut new_env = func_env.extend();
-
-            // For recursion: if the function was called by name, make that
-            // name resolvable inside the body too.
-            if let Some(name) = call_name
-                && let Some(func_value) = env.get(name)
-            {
-                new_env.set(name.to_string(), func_value.clone());
-            }
-
-            for (param, arg) in params.iter().zip(args.iter()) {
-                new_env.set(param.clone(), arg.clone());
-            }
-
-            eval(body, &mut new_env)
-        }
-        Value::BuiltinFunction { arity, func, name } => {
-            if args.len() != *arity {
-                return Err(format!(
-                    "Wrong number of arguments for {}: expected {}, got {}",
-                    name, arity, args.len()
-                ));
-            }
-            func(args)
-        }
-        _ => Err(format!("Cannot call non-function value: {}", func_val)),
-    }
+pub fn apply_function(func_val: &Value, args: &[Value]) -> Result<Value, String> {
+    let mut evaluation = Evaluation::new(Expr::Nil, Environment::blank());
+    evaluation.control = evaluation.apply(func_val, args.to_vec());
+    evaluation.run()
 }
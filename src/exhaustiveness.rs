@@ -1,9 +1,9 @@
 //! Exhaustiveness checking for `match` expressions.
 //!
 //! Verifies at type-check time that arms cover every possible value of the
-//! scrutinee type, for the structurally-finite types `Bool` and `List<T>`.
-//! Other types are considered exhaustive only when at least one arm is
-//! irrefutable (wildcard / variable / `(_ as name)`).
+//! scrutinee type, for the structurally-finite types `Bool`, `List<T>`,
+//! `Pair<A, B>` and those declared with `deftype`. Other types are considered exhaustive only when at least
+//! one arm is irrefutable (wildcard / variable / `(_ as name)`).
 //!
 //! Algorithm: a width-1 reduction of Maranget's usefulness algorithm.
 //! For each constructor of the scrutinee type, recurse into the sub-patterns
@@ -14,8 +14,13 @@
 //! Guarded arms (`Pattern::Guard`) intentionally do NOT contribute to
 //! exhaustiveness: their truth value is only known at runtime, matching
 //! the Rust/OCaml posture.
+//!
+//! `redundant_arms` runs the same algorithm the other way round: an arm is
+//! redundant when it isn't *useful* — no value reaches it that earlier
+//! (unguarded) arms don't already take.
 
 use crate::ast::{Pattern, Type};
+use crate::types::Adts;
 
 /// A concrete value not covered by any arm. Rendered into Pattern syntax
 /// so the user can drop it directly into the match as a new arm.
@@ -24,13 +29,14 @@ enum Witness {
     Bool(bool),
     Nil,
     Cons(Box<Witness>, Box<Witness>),
+    Constructor(String, Vec<Witness>),
 }
 
 const MAX_DEPTH: usize = 3;
 
 /// Entry point. Returns Ok if `arms` cover every value of `scrutinee`,
 /// otherwise an error listing the missing patterns.
-pub fn check(scrutinee: &Type, arms: &[&Pattern]) -> Result<(), String> {
+pub fn check(scrutinee: &Type, arms: &[&Pattern], adts: &Adts) -> Result<(), String> {
     // Flatten top-level `(or p1 p2 ...)` arms into sibling patterns so the
     // existing constructor-based reduction sees them directly. We deliberately
     // do NOT descend into Guard or Cons sub-patterns: guards stay opaque
@@ -40,7 +46,7 @@ pub fn check(scrutinee: &Type, arms: &[&Pattern]) -> Result<(), String> {
     for a in arms {
         flatten_or(a, &mut flat);
     }
-    let witnesses = missing(scrutinee, &flat, 0);
+    let witnesses = missing(scrutinee, &flat, adts, 0);
     if witnesses.is_empty() {
         return Ok(());
     }
@@ -83,7 +89,7 @@ fn arm_is_irrefutable(p: &Pattern) -> bool {
 
 /// Compute witnesses for values of `ty` not covered by `arms`.
 /// `depth` bounds the recursion into nested list types.
-fn missing(ty: &Type, arms: &[&Pattern], depth: usize) -> Vec<Witness> {
+fn missing(ty: &Type, arms: &[&Pattern], adts: &Adts, depth: usize) -> Vec<Witness> {
     // An irrefutable arm covers everything at this level.
     if arms.iter().any(|p| arm_is_irrefutable(p)) {
         return Vec::new();
@@ -106,14 +112,21 @@ fn missing(ty: &Type, arms: &[&Pattern], depth: usize) -> Vec<Witness> {
             if !nil_covered(arms) {
                 out.push(Witness::Nil);
             }
-            if let Some(cons_witness) = missing_cons(elem, arms, depth) {
+            if let Some(cons_witness) = missing_cons(elem, ty, arms, adts, depth) {
                 out.push(cons_witness);
             }
             out
         }
+        // A dotted pair only ever has the cons shape.
+        Type::Pair(head, tail) => missing_cons(head, tail, arms, adts, depth).into_iter().collect(),
+        // A declared type is covered when each of its constructors is.
+        Type::Named(name) if adts.contains_key(name) => adts[name]
+            .iter()
+            .filter_map(|(tag, fields)| missing_constructor(tag, fields, arms, adts, depth))
+            .collect(),
         // Inferred: skip silently. Useful exhaustiveness needs concrete
         // types, which arrive with bidirectional inference (#8).
-        Type::Inferred => Vec::new(),
+        Type::Inferred | Type::Var(_) | Type::Generic(_) => Vec::new(),
         // Infinite / opaque types: only irrefutable arms can cover them,
         // and the early-return above already handled that case.
         _ => vec![Witness::Wild],
@@ -135,7 +148,8 @@ fn nil_covered(arms: &[&Pattern]) -> bool {
 /// Returns Some(witness) when there exists a cons value not covered by any
 /// arm, None when every cons value is covered. Recurses into head/tail
 /// patterns so nested structure (e.g. `List<Bool>`) is handled precisely.
-fn missing_cons(elem: &Type, arms: &[&Pattern], depth: usize) -> Option<Witness> {
+/// `tail` is the list type itself, or the second half of a `Pair`.
+fn missing_cons(head: &Type, tail: &Type, arms: &[&Pattern], adts: &Adts, depth: usize) -> Option<Witness> {
     // Collect (head, tail) pairs from cons-shaped arms. A wildcard or
     // variable arm at this level was already handled by the irrefutable
     // early-return in `missing`, so we don't see it here.
@@ -165,9 +179,8 @@ fn missing_cons(elem: &Type, arms: &[&Pattern], depth: usize) -> Option<Witness>
     }
 
     // Recurse into the head (element type) and tail (same list type).
-    let head_witnesses = missing(elem, &head_pats, depth + 1);
-    let list_ty = Type::List(Box::new(elem.clone()));
-    let tail_witnesses = missing(&list_ty, &tail_pats, depth + 1);
+    let head_witnesses = missing(head, &head_pats, adts, depth + 1);
+    let tail_witnesses = missing(tail, &tail_pats, adts, depth + 1);
 
     // If both head and tail are fully covered, the cons case is exhaustive.
     if head_witnesses.is_empty() && tail_witnesses.is_empty() {
@@ -182,11 +195,192 @@ fn missing_cons(elem: &Type, arms: &[&Pattern], depth: usize) -> Option<Witness>
     Some(Witness::Cons(Box::new(head), Box::new(tail)))
 }
 
+/// Like `missing_cons`, for the `deftype` constructor `tag`: each field
+/// is checked on its own against what the arms for `tag` have there.
+fn missing_constructor(tag: &str, fields: &[Type], arms: &[&Pattern], adts: &Adts, depth: usize) -> Option<Witness> {
+    let rows: Vec<&Vec<Pattern>> = arms
+        .iter()
+        .filter_map(|p| match peel_as(p) {
+            Pattern::Constructor(t, pats) if t == tag && pats.len() == fields.len() => Some(pats),
+            _ => None,
+        })
+        .collect();
+    let wild = || fields.iter().map(|_| Witness::Wild).collect();
+    if rows.is_empty() {
+        return Some(Witness::Constructor(tag.to_string(), wild()));
+    }
+    if depth >= MAX_DEPTH {
+        return None;
+    }
+    for (i, field) in fields.iter().enumerate() {
+        let column: Vec<&Pattern> = rows.iter().map(|pats| &pats[i]).collect();
+        if let Some(witness) = missing(field, &column, adts, depth + 1).into_iter().next() {
+            let mut witnesses: Vec<Witness> = wild();
+            witnesses[i] = witness;
+            return Some(Witness::Constructor(tag.to_string(), witnesses));
+        }
+    }
+    None
+}
+
 fn render(w: &Witness) -> String {
     match w {
         Witness::Wild => "_".to_string(),
         Witness::Bool(b) => b.to_string(),
         Witness::Nil => "nil".to_string(),
         Witness::Cons(h, t) => format!("(cons {} {})", render(h), render(t)),
+        Witness::Constructor(tag, fields) if fields.is_empty() => tag.clone(),
+        Witness::Constructor(tag, fields) => {
+            let fields: Vec<String> = fields.iter().map(render).collect();
+            format!("({} {})", tag, fields.join(" "))
+        }
+    }
+}
+
+/// Indices of arms no value can reach, because the unguarded arms before
+/// them match everything they would. Purely syntactic: the constructors a
+/// column can hold are read off the patterns themselves (`true`/`false`,
+/// `nil`/`cons`), so it works before the scrutinee's type is known. The
+/// patterns can't tell how many constructors a `deftype` has, so a
+/// catch-all after all of them is never reported.
+pub fn redundant_arms(arms: &[&Pattern]) -> Vec<usize> {
+    let mut rows: Vec<Vec<&Pattern>> = Vec::new();
+    let mut dead = Vec::new();
+    for (i, arm) in arms.iter().enumerate() {
+        if !useful(&rows, &[arm]) {
+            dead.push(i);
+        }
+        // A guard may fail, so a guarded arm never covers later ones.
+        if !matches!(peel_as(arm), Pattern::Guard(..)) {
+            rows.push(vec![arm]);
+        }
+    }
+    dead
+}
+
+static WILD: Pattern = Pattern::Wildcard;
+
+/// Head constructor of a pattern. Literals are their own constructors.
+#[derive(PartialEq)]
+enum Ctor<'a> {
+    Literal(&'a Pattern),
+    Nil,
+    Cons,
+    /// A `deftype` constructor and how many fields it has.
+    Constructor(&'a str, usize),
+}
+
+/// `None` for patterns that match anything.
+fn ctor(p: &Pattern) -> Option<Ctor<'_>> {
+    match p {
+        Pattern::Wildcard | Pattern::Variable(_) => None,
+        Pattern::Nil => Some(Ctor::Nil),
+        Pattern::Cons(..) => Some(Ctor::Cons),
+        Pattern::Constructor(tag, fields) => Some(Ctor::Constructor(tag, fields.len())),
+        _ => Some(Ctor::Literal(p)),
+    }
+}
+
+fn arity(c: &Ctor) -> usize {
+    match c {
+        Ctor::Cons => 2,
+        Ctor::Constructor(_, n) => *n,
+        _ => 0,
+    }
+}
+
+/// Whether `sigma` holds every constructor of its type, so a wildcard is
+/// only useful if it's useful under one of them.
+fn complete(sigma: &[Ctor]) -> bool {
+    let has = |p: Pattern| sigma.contains(&Ctor::Literal(&p));
+    (has(Pattern::LiteralBool(true)) && has(Pattern::LiteralBool(false)))
+        || (sigma.contains(&Ctor::Nil) && sigma.contains(&Ctor::Cons))
+}
+
+/// Rewrite rows into ones whose first column has no `as` or `or`
+/// wrapper. Guarded sub-patterns cover nothing, so they drop out.
+fn expand<'a>(row: &[&'a Pattern], out: &mut Vec<Vec<&'a Pattern>>) {
+    match row.first().map(|p| peel_as(p)) {
+        Some(Pattern::Or(branches)) => {
+            for b in branches {
+                let mut r = vec![b];
+                r.extend_from_slice(&row[1..]);
+                expand(&r, out);
+            }
+        }
+        Some(Pattern::Guard(..)) => {}
+        Some(first) => {
+            let mut r = vec![first];
+            r.extend_from_slice(&row[1..]);
+            out.push(r);
+        }
+        None => out.push(row.to_vec()),
+    }
+}
+
+/// Rows that can match constructor `c`, with `c`'s sub-patterns spliced
+/// in place of the first column.
+fn specialize<'a>(rows: &[Vec<&'a Pattern>], c: &Ctor) -> Vec<Vec<&'a Pattern>> {
+    let mut out = Vec::new();
+    for row in rows {
+        let mut r: Vec<&Pattern> = match (row[0], ctor(row[0])) {
+            (_, None) => vec![&WILD; arity(c)],
+            (Pattern::Cons(h, t), Some(Ctor::Cons)) if *c == Ctor::Cons => vec![&**h, &**t],
+            (Pattern::Constructor(_, fields), Some(rc)) if rc == *c => fields.iter().collect(),
+            (_, Some(rc)) if rc == *c => Vec::new(),
+            _ => continue,
+        };
+        r.extend_from_slice(&row[1..]);
+        out.push(r);
+    }
+    out
+}
+
+/// Whether some value matched by the pattern vector `v` is matched by
+/// none of `rows` (Maranget's `U(P, q)`).
+fn useful<'a>(rows: &[Vec<&'a Pattern>], v: &[&'a Pattern]) -> bool {
+    let Some((&first, rest)) = v.split_first() else {
+        return rows.is_empty();
+    };
+    let mut expanded = Vec::new();
+    for row in rows {
+        expand(row, &mut expanded);
+    }
+    let rows = expanded;
+    let with_rest = |head: Vec<&'a Pattern>| -> Vec<&'a Pattern> {
+        let mut next = head;
+        next.extend_from_slice(rest);
+        next
+    };
+    match peel_as(first) {
+        Pattern::Or(branches) => branches.iter().any(|b| useful(&rows, &with_rest(vec![b]))),
+        Pattern::Guard(inner, _) => useful(&rows, &with_rest(vec![&**inner])),
+        p => match ctor(p) {
+            Some(c) => {
+                let args = match p {
+                    Pattern::Cons(h, t) => vec![&**h, &**t],
+                    Pattern::Constructor(_, fields) => fields.iter().collect(),
+                    _ => Vec::new(),
+                };
+                useful(&specialize(&rows, &c), &with_rest(args))
+            }
+            None => {
+                let mut sigma: Vec<Ctor> = Vec::new();
+                for c in rows.iter().filter_map(|r| ctor(r[0])) {
+                    if !sigma.contains(&c) {
+                        sigma.push(c);
+                    }
+                }
+                if complete(&sigma) {
+                    sigma
+                        .iter()
+                        .any(|c| useful(&specialize(&rows, c), &with_rest(vec![&WILD; arity(c)])))
+                } else {
+                    let defaults: Vec<Vec<&'a Pattern>> =
+                        rows.iter().filter(|r| ctor(r[0]).is_none()).map(|r| r[1..].to_vec()).collect();
+                    useful(&defaults, rest)
+                }
+            }
+        },
     }
 }
@@ -0,0 +1,79 @@
+//! Native data an embedder hands to scripts (`Value::Foreign`).
+//!
+//! Scripts can only pass a foreign value around; the embedder's own
+//! builtins get at the data with `downcast_ref`. A finalizer, if given,
+//! releases whatever native resource the data stands for: it runs once,
+//! either on `close` (and so at the end of a `with-open`) or when the last
+//! copy of the value is dropped.
+//!
+//! Values are reference counted, so "unreachable" means "no copies left".
+//! A value caught in a cycle (a closure that captures itself, say) is never
+//! dropped, and its finalizer only runs if something `close`s it.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+
+type Finalizer = Box<dyn FnOnce(&dyn Any)>;
+
+pub struct Foreign {
+    type_name: &'static str,
+    data: Box<dyn Any>,
+    finalizer: RefCell<Option<Finalizer>>,
+}
+
+impl Foreign {
+    /// `type_name` is what `type-of` and error messages show.
+    pub fn new<T: Any>(type_name: &'static str, data: T) -> Foreign {
+        Foreign { type_name, data: Box::new(data), finalizer: RefCell::new(None) }
+    }
+
+    pub fn with_finalizer<T: Any>(
+        type_name: &'static str,
+        data: T,
+        finalizer: impl FnOnce(&T) + 'static,
+    ) -> Foreign {
+        let finalizer: Finalizer = Box::new(move |data| {
+            if let Some(data) = data.downcast_ref::<T>() {
+                finalizer(data)
+            }
+        });
+        Foreign { type_name, data: Box::new(data), finalizer: RefCell::new(Some(finalizer)) }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The data, if it's a `T`. Still there after finalizing, so builtins
+    /// should check their own "released" state if that matters.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.data.downcast_ref()
+    }
+
+    /// Run the finalizer now, if it hasn't run. True if it ran.
+    pub fn finalize(&self) -> bool {
+        // Take it out first so a finalizer that reaches this value again
+        // finds nothing left to run.
+        let finalizer = self.finalizer.borrow_mut().take();
+        match finalizer {
+            Some(finalizer) => {
+                finalizer(&*self.data);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Foreign {
+    fn drop(&mut self) {
+        self.finalize();
+    }
+}
+
+impl fmt::Debug for Foreign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Foreign({})", self.type_name)
+    }
+}
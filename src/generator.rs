@@ -0,0 +1,184 @@
+//! Generators: what calling a `defgen` returns.
+//!
+//! A generator body runs on an explicit stack of frames instead of the
+//! Rust stack, so it can stop at a `yield` and carry on from there when
+//! the next value is asked for. Only the forms a `yield` can sit in get
+//! frames (`do`, `if`, `let`, `match`, `loop`, `recur`); every other
+//! expression, and any of those forms without a `yield` inside, goes
+//! straight through `eval`.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::Expr;
+use crate::env::{Environment, Value};
+use crate::eval::{apply_function, eval, pattern_match, rebind};
+
+#[derive(Debug)]
+pub struct Generator {
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    Frames(Vec<Frame>),
+    /// `(map f gen)`: `f` applied to each value as it's pulled.
+    Map { source: Rc<RefCell<Generator>>, f: Value },
+    /// Ran off the end of its body, or failed.
+    Done,
+}
+
+#[derive(Debug)]
+enum Frame {
+    /// Run `expr` for its `yield`s; its value is dropped.
+    Run(Expr, Environment),
+    /// The forms of a `do` still to run, last first.
+    Rest(Vec<Expr>, Environment),
+    /// A `loop`, or the `defgen` itself, that a `recur` goes back to.
+    Loop { names: Vec<String>, body: Expr, env: Environment },
+}
+
+impl Generator {
+    /// A generator over a `defgen` body; `env` already binds `params`.
+    pub fn new(params: &[String], body: &Expr, env: Environment) -> Generator {
+        let frames = vec![
+            Frame::Loop { names: params.to_vec(), body: body.clone(), env: env.clone() },
+            Frame::Run(body.clone(), env),
+        ];
+        Generator { state: State::Frames(frames) }
+    }
+
+    pub fn map(source: Rc<RefCell<Generator>>, f: Value) -> Generator {
+        Generator { state: State::Map { source, f } }
+    }
+
+    /// Run to the next `yield`: its value, or None once the body is done.
+    /// A generator that fails is done from then on.
+    pub fn pull(&mut self) -> Result<Option<Value>, String> {
+        let result = match &mut self.state {
+            State::Frames(frames) => resume(frames),
+            State::Map { source, f } => next(source).and_then(|value| match value {
+                Some(value) => apply_function(f, &[value]).map(Some),
+                None => Ok(None),
+            }),
+            State::Done => return Ok(None),
+        };
+        if !matches!(result, Ok(Some(_))) {
+            self.state = State::Done;
+        }
+        result
+    }
+}
+
+/// `Generator::pull` through a shared handle. A generator whose body
+/// pulls from itself finds it already borrowed.
+pub fn next(generator: &RefCell<Generator>) -> Result<Option<Value>, String> {
+    generator
+        .try_borrow_mut()
+        .map_err(|_| "generator is already running".to_string())?
+        .pull()
+}
+
+fn resume(frames: &mut Vec<Frame>) -> Result<Option<Value>, String> {
+    while let Some(frame) = frames.pop() {
+        let (expr, mut env) = match frame {
+            Frame::Run(expr, env) => (expr, env),
+            Frame::Rest(mut exprs, env) => {
+                if let Some(expr) = exprs.pop() {
+                    if !exprs.is_empty() {
+                        frames.push(Frame::Rest(exprs, env.clone()));
+                    }
+                    frames.push(Frame::Run(expr, env));
+                }
+                continue;
+            }
+            // The body finished without a `recur`.
+            Frame::Loop { .. } => continue,
+        };
+        match expr {
+            Expr::Yield(value) => return eval(&value, &mut env).map(Some),
+            Expr::Recur(args) => {
+                let values = args.iter().map(|a| eval(a, &mut env)).collect::<Result<Vec<_>, _>>()?;
+                // `recur` is in tail position, so every frame above its
+                // target is spent.
+                while let Some(frame) = frames.pop() {
+                    if let Frame::Loop { names, body, env } = frame {
+                        let mut env = env.renew();
+                        rebind(names.iter(), values, &mut env)?;
+                        let run = Frame::Run(body.clone(), env.clone());
+                        frames.push(Frame::Loop { names, body, env });
+                        frames.push(run);
+                        break;
+                    }
+                }
+            }
+            expr if !needs_frames(&expr, true) => {
+                eval(&expr, &mut env)?;
+            }
+            Expr::Do(mut exprs) => {
+                exprs.reverse();
+                frames.push(Frame::Rest(exprs, env));
+            }
+            Expr::If { condition, then_branch, else_branch } => {
+                let branch = match eval(&condition, &mut env)? {
+                    Value::Bool(true) => then_branch,
+                    Value::Bool(false) => else_branch,
+                    _ => return Err("If condition must be a boolean".to_string()),
+                };
+                frames.push(Frame::Run(*branch, env));
+            }
+            Expr::Let { name, value, body, .. } => {
+                let value = eval(&value, &mut env)?;
+                let mut body_env = env.extend();
+                body_env.set(name, value);
+                frames.push(Frame::Run(*body, body_env));
+            }
+            Expr::Match { scrutinee, arms } => {
+                let value = eval(&scrutinee, &mut env)?;
+                let mut matched = None;
+                for (pat, body) in arms {
+                    let mut arm_env = env.extend();
+                    if pattern_match(&pat, &value, &mut arm_env)? {
+                        matched = Some((body, arm_env));
+                        break;
+                    }
+                }
+                let (body, arm_env) = matched.ok_or_else(|| format!("No match arm matched value: {}", value))?;
+                frames.push(Frame::Run(body, arm_env));
+            }
+            Expr::Loop { bindings, body } => {
+                let mut loop_env = env.extend();
+                let mut names = Vec::with_capacity(bindings.len());
+                for (name, _, value) in bindings {
+                    let value = eval(&value, &mut loop_env)?;
+                    loop_env.set(name.clone(), value);
+                    names.push(name);
+                }
+                let run = Frame::Run((*body).clone(), loop_env.clone());
+                frames.push(Frame::Loop { names, body: *body, env: loop_env });
+                frames.push(run);
+            }
+            other => {
+                eval(&other, &mut env)?;
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Whether `expr` can only run on frames: it yields, or ends in a `recur`
+/// back to a frame (when `recur_escapes`; a plain `loop` catches its own).
+fn needs_frames(expr: &Expr, recur_escapes: bool) -> bool {
+    match expr {
+        Expr::Yield(_) => true,
+        Expr::Recur(_) => recur_escapes,
+        Expr::Do(exprs) => exprs.iter().any(|e| needs_frames(e, recur_escapes)),
+        Expr::If { then_branch, else_branch, .. } => {
+            needs_frames(then_branch, recur_escapes) || needs_frames(else_branch, recur_escapes)
+        }
+        Expr::Let { body, .. } => needs_frames(body, recur_escapes),
+        Expr::Match { arms, .. } => arms.iter().any(|(_, body)| needs_frames(body, recur_escapes)),
+        Expr::Loop { body, .. } => needs_frames(body, false),
+        _ => false,
+    }
+}
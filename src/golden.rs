@@ -0,0 +1,141 @@
+//! Golden tests: run example programs and compare what they do with a
+//! blessed copy.
+//!
+//! `rusp test --golden DIR` runs every `.rusp` file directly in DIR and
+//! compares its transcript with the `.expected` file beside it
+//! (`hello.rusp` → `hello.expected`). A transcript is everything the
+//! program printed to `stdout`, then `=> VALUE` for the last form's value
+//! as source writes it (unless it's `Unit`), or `error: ...` for the form that failed
+//! (nothing after it runs).
+//! `--update` writes the transcripts out instead of comparing.
+//!
+//! Each file runs on a thread of its own, so the interpreter's
+//! thread-local state (`*out*`, tier counters, `(args)`) starts fresh
+//! for every one, and a panic fails that file rather than the run.
+
+use std::path::{Path, PathBuf};
+
+use crate::env::{self, Environment};
+use crate::eval::eval;
+use crate::modules::Loader;
+use crate::types::{TypeEnv, declare_defns, type_check};
+
+/// What became of one file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail { expected: String, actual: String },
+    /// There's no `.expected` file yet.
+    Missing,
+    /// `--update` wrote the `.expected` file.
+    Updated,
+}
+
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+}
+
+/// The `.expected` file for `path`.
+pub fn expected_path(path: &Path) -> PathBuf {
+    path.with_extension("expected")
+}
+
+/// Run every `.rusp` file in `dir`, in name order.
+pub fn run_dir(dir: &Path, update: bool) -> Result<Vec<Case>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "rusp"))
+        .collect();
+    paths.sort();
+    paths.into_iter().map(|path| run_case(path, update)).collect()
+}
+
+fn run_case(path: PathBuf, update: bool) -> Result<Case, String> {
+    let actual = transcript(&path);
+    let expected_path = expected_path(&path);
+    let outcome = if update {
+        std::fs::write(&expected_path, &actual)
+            .map_err(|e| format!("could not write {}: {}", expected_path.display(), e))?;
+        Outcome::Updated
+    } else {
+        match std::fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => Outcome::Pass,
+            Ok(expected) => Outcome::Fail { expected, actual },
+            Err(_) => Outcome::Missing,
+        }
+    };
+    Ok(Case { path, outcome })
+}
+
+/// Run `path` on a fresh thread and describe what it did. Positions in
+/// errors name the file without its directory, so the transcript is the
+/// same wherever the tests are run from.
+pub fn transcript(path: &Path) -> String {
+    let file = path.to_string_lossy().to_string();
+    let name = path.file_name().map_or(file.clone(), |name| name.to_string_lossy().to_string());
+    let run = std::thread::Builder::new().stack_size(crate::parser::STACK_SIZE).spawn(move || {
+        crate::cli::set_args(&file, Vec::new());
+        let (result, captured) = env::capture_output(|| run_file(&file));
+        let mut out = captured.stdout;
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        match result {
+            Ok(Some(env::Value::Unit)) | Ok(None) => {}
+            Ok(Some(value)) => out.push_str(&format!("=> {}\n", value.written())),
+            Err(e) => out.push_str(&format!("error: {}\n", e.replace(&file, &name))),
+        }
+        out
+    });
+    let run = run.expect("could not start the interpreter thread");
+    run.join().unwrap_or_else(|_| "error: the interpreter panicked\n".to_string())
+}
+
+/// Type-check and evaluate each form of `file`, the way `rusp run` does.
+/// The last form's value, or None for a file with no forms.
+fn run_file(file: &str) -> Result<Option<env::Value>, String> {
+    let mut env = Environment::new();
+    let mut type_env = TypeEnv::new();
+    let mut last = None;
+    let sources = Loader::new().load(file)?;
+    declare_defns(sources.iter().map(|sf| &sf.form), &mut type_env);
+    for sf in sources {
+        let at = |e: String| format!("{}: {}", sf.loc, e);
+        type_check(&sf.form, &mut type_env).map_err(|e| at(format!("type error: {}", e)))?;
+        last = Some(eval(&sf.form, &mut env).map_err(at)?);
+    }
+    Ok(last)
+}
+
+/// The lines that differ between two transcripts, `-` for expected and
+/// `+` for actual, with the lines they share around them unmarked.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    // Longest common subsequence, filled in from the end.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!("  {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("- {}\n", old[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+ {}\n", new[j]));
+            j += 1;
+        }
+    }
+    out
+}
@@ -0,0 +1,131 @@
+//! Syntax highlighting for REPL input.
+//!
+//! `highlight` colors a line of source with ANSI escapes and, given the
+//! cursor position, marks the bracket that matches the one just before
+//! it. It works on the lexemes of `parser::lexer`, not the parser's
+//! output, so unfinished input (the usual state of a prompt line) still
+//! colors sensibly, and brackets in strings and comments are skipped
+//! the same way `reader::is_complete` skips them.
+//!
+//! The REPL's line editor calls it with the whole line and the cursor
+//! after every keystroke.
+
+use crate::parser::lexer::{Lexeme, lex};
+
+/// Heads the parser reads as syntax rather than as calls.
+const SPECIAL_FORMS: &[&str] = &[
+    "block", "def", "defconst", "defer", "defgen", "defn", "defn-", "defparam", "defstruct", "deftype", "do", "fn", "if", "lambda",
+    "let", "loop", "match", "parameterize", "recur", "reset", "return", "return-from", "set-reader-macro!",
+    "shift", "with-open", "yield",
+];
+
+const RESET: &str = "\x1b[0m";
+const SPECIAL: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const KEYWORD: &str = "\x1b[35m";
+const CONSTANT: &str = "\x1b[33m";
+const COMMENT: &str = "\x1b[90m";
+/// The bracket matching the one at the cursor.
+const MATCH: &str = "\x1b[7m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Open,
+    Close,
+    /// A string literal, possibly unterminated.
+    String,
+    /// `#\c`.
+    Char,
+    Comment,
+    Number,
+    Keyword,
+    /// `true`, `false`, `nil`.
+    Constant,
+    Special,
+    Symbol,
+    Space,
+}
+
+/// Split `line` into byte ranges with their token kind. Everything is
+/// covered, so the pieces join back into `line`.
+pub fn tokens(line: &str) -> Vec<(Token, std::ops::Range<usize>)> {
+    lex(line)
+        .into_iter()
+        .map(|(lexeme, range)| {
+            let kind = match lexeme {
+                Lexeme::Open => Token::Open,
+                Lexeme::Close => Token::Close,
+                Lexeme::String { .. } => Token::String,
+                Lexeme::Comment { .. } => Token::Comment,
+                Lexeme::Char => Token::Char,
+                Lexeme::Space => Token::Space,
+                Lexeme::Atom => atom_kind(&line[range.clone()]),
+            };
+            (kind, range)
+        })
+        .collect()
+}
+
+fn atom_kind(atom: &str) -> Token {
+    let digits = atom.strip_prefix('-').unwrap_or(atom);
+    if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        Token::Number
+    } else if atom.len() > 1 && atom.starts_with(':') {
+        Token::Keyword
+    } else if matches!(atom, "true" | "false" | "nil") {
+        Token::Constant
+    } else if SPECIAL_FORMS.contains(&atom) {
+        Token::Special
+    } else {
+        Token::Symbol
+    }
+}
+
+/// Where the bracket that `close` (a byte offset of a closing bracket)
+/// closes starts, if it's on this line. Brackets in strings don't count.
+pub fn matching_open(line: &str, close: usize) -> Option<usize> {
+    let mut open = Vec::new();
+    for (kind, range) in tokens(line) {
+        match kind {
+            Token::Open => open.push(range.start),
+            Token::Close if range.start == close => return open.pop(),
+            Token::Close => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `line` with ANSI colors. With a cursor just after a closing bracket,
+/// the bracket it matches is shown in reverse video.
+pub fn highlight(line: &str, cursor: Option<usize>) -> String {
+    let matched = cursor
+        .and_then(|pos| pos.checked_sub(1))
+        .filter(|&pos| line[pos..].starts_with([')', ']', '}']))
+        .and_then(|pos| matching_open(line, pos));
+    let mut out = String::with_capacity(line.len() * 2);
+    for (kind, range) in tokens(line) {
+        let color = match kind {
+            Token::Open if matched == Some(range.start) => Some(MATCH),
+            Token::String | Token::Char => Some(STRING),
+            Token::Comment => Some(COMMENT),
+            Token::Number => Some(NUMBER),
+            Token::Keyword => Some(KEYWORD),
+            Token::Constant => Some(CONSTANT),
+            Token::Special => Some(SPECIAL),
+            _ => None,
+        };
+        match color {
+            Some(color) => {
+                out.push_str(color);
+                out.push_str(&line[range]);
+                out.push_str(RESET);
+            }
+            None => out.push_str(&line[range]),
+        }
+    }
+    out
+}
@@ -0,0 +1,74 @@
+//! Keywords: `:name` literals.
+//!
+//! Every keyword is interned, so a `Keyword` is just an index into a
+//! per-thread table. Equality and hashing compare that index, never the
+//! name, which keeps keyword-heavy code (dispatch on `:tag`s, keyword map
+//! keys) cheap.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Keyword(u32);
+
+#[derive(Default)]
+struct Interner {
+    names: Vec<Rc<str>>,
+    ids: HashMap<Rc<str>, u32>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Keyword {
+    /// The keyword `:name` (`name` without the colon).
+    pub fn intern(name: &str) -> Keyword {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&id) = interner.ids.get(name) {
+                return Keyword(id);
+            }
+            let id = interner.names.len() as u32;
+            let name: Rc<str> = name.into();
+            interner.names.push(Rc::clone(&name));
+            interner.ids.insert(name, id);
+            Keyword(id)
+        })
+    }
+
+    /// How many keywords this thread has interned. The table never
+    /// shrinks.
+    pub fn count() -> usize {
+        INTERNER.with(|interner| interner.borrow().names.len())
+    }
+
+    /// Every keyword this thread has interned, in order: what `seed`
+    /// takes to give another thread the same ids.
+    pub fn interned() -> Vec<String> {
+        INTERNER.with(|interner| interner.borrow().names.iter().map(|name| name.to_string()).collect())
+    }
+
+    /// Intern `names` in order, so forms read on the thread `interned`
+    /// came from mean the same keywords here. Only before this thread has
+    /// interned anything else.
+    pub fn seed(names: &[String]) {
+        for (id, name) in names.iter().enumerate() {
+            let keyword = Keyword::intern(name);
+            assert_eq!(keyword.0 as usize, id, "Keyword::seed on a thread that already interned keywords");
+        }
+    }
+
+    /// The name, without the leading colon.
+    pub fn name(self) -> Rc<str> {
+        INTERNER.with(|interner| Rc::clone(&interner.borrow().names[self.0 as usize]))
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ":{}", self.name())
+    }
+}
@@ -0,0 +1,185 @@
+//! A tiny persistent key-value store backed by an append-only log.
+//!
+//! Every `put`/`delete` appends one line to the file and flushes it, so
+//! state survives the process exiting at any point; opening replays the
+//! log into memory. A torn final line (the process died mid-write) is
+//! truncated away. When dead records outnumber live keys the log is compacted on
+//! open by rewriting it to a temporary file and renaming it over the
+//! original.
+//!
+//! Record format, one per line, fields tab-separated with `\\`, `\t` and
+//! `\n` escaped:
+//!
+//! ```text
+//! P <key> <value>
+//! D <key>
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Don't bother compacting logs with fewer dead records than this.
+const COMPACT_MIN_DEAD: usize = 64;
+
+#[derive(Debug)]
+pub struct KvStore {
+    path: PathBuf,
+    /// `None` once closed.
+    log: Option<File>,
+    /// Ordered so `keys` is deterministic.
+    map: BTreeMap<String, String>,
+}
+
+impl KvStore {
+    /// Open (creating if needed) the store at `path`.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let path = PathBuf::from(path);
+        let err = |e: std::io::Error| format!("kv-open: {}: {}", path.display(), e);
+        let mut map = BTreeMap::new();
+        let mut records = 0;
+        if path.exists() {
+            let data = fs::read(&path).map_err(err)?;
+            // Only newline-terminated records count; cut off a torn tail
+            // so the next append starts on a fresh line.
+            let complete = data.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            if complete < data.len() {
+                let file = OpenOptions::new().write(true).open(&path).map_err(err)?;
+                file.set_len(complete as u64).map_err(err)?;
+            }
+            for line in data[..complete].split(|&b| b == b'\n') {
+                let Some(record) = std::str::from_utf8(line).ok().and_then(parse_record) else {
+                    // Foreign line: skip rather than refuse to open.
+                    continue;
+                };
+                records += 1;
+                match record {
+                    Record::Put(k, v) => map.insert(k, v),
+                    Record::Delete(k) => map.remove(&k),
+                };
+            }
+        }
+
+        let dead = records - map.len();
+        if dead >= COMPACT_MIN_DEAD && dead > map.len() {
+            compact(&path, &map).map_err(err)?;
+        }
+        let log = OpenOptions::new().create(true).append(true).open(&path).map_err(err)?;
+        Ok(KvStore { path, log: Some(log), map })
+    }
+
+    pub fn path(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.log.is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<&String>, String> {
+        self.check_open("kv-get")?;
+        Ok(self.map.get(key))
+    }
+
+    pub fn put(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.append("kv-put!", &format!("P\t{}\t{}\n", escape(key), escape(value)))?;
+        self.map.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Remove `key`; returns whether it was present.
+    pub fn delete(&mut self, key: &str) -> Result<bool, String> {
+        self.check_open("kv-delete!")?;
+        if !self.map.contains_key(key) {
+            return Ok(false);
+        }
+        self.append("kv-delete!", &format!("D\t{}\n", escape(key)))?;
+        self.map.remove(key);
+        Ok(true)
+    }
+
+    pub fn keys(&self) -> Result<Vec<String>, String> {
+        self.check_open("kv-keys")?;
+        Ok(self.map.keys().cloned().collect())
+    }
+
+    /// Close the log; returns `false` if it was already closed.
+    pub fn close(&mut self) -> bool {
+        self.log.take().is_some()
+    }
+
+    fn check_open(&self, op: &str) -> Result<(), String> {
+        match self.log {
+            Some(_) => Ok(()),
+            None => Err(format!("{}: store {} is closed", op, self.path.display())),
+        }
+    }
+
+    fn append(&mut self, op: &str, line: &str) -> Result<(), String> {
+        self.check_open(op)?;
+        let path = &self.path;
+        let log = self.log.as_mut().expect("checked open above");
+        log.write_all(line.as_bytes())
+            .and_then(|_| log.flush())
+            .map_err(|e| format!("{}: {}: {}", op, path.display(), e))
+    }
+}
+
+enum Record {
+    Put(String, String),
+    Delete(String),
+}
+
+fn parse_record(line: &str) -> Option<Record> {
+    let mut fields = line.split('\t');
+    let record = match (fields.next()?, fields.next(), fields.next()) {
+        ("P", Some(k), Some(v)) => Record::Put(unescape(k)?, unescape(v)?),
+        ("D", Some(k), None) => Record::Delete(unescape(k)?),
+        _ => return None,
+    };
+    fields.next().is_none().then_some(record)
+}
+
+fn compact(path: &PathBuf, map: &BTreeMap<String, String>) -> std::io::Result<()> {
+    let tmp = path.with_extension("compact");
+    {
+        let mut out = File::create(&tmp)?;
+        for (k, v) in map {
+            out.write_all(format!("P\t{}\t{}\n", escape(k), escape(v)).as_bytes())?;
+        }
+        out.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        out.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            _ => return None,
+        });
+    }
+    Some(out)
+}
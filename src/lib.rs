@@ -4,11 +4,30 @@
 //! it — share the same front end as the binary REPL.
 
 pub mod ast;
+pub mod cache;
+pub mod check;
+pub mod cli;
 pub mod codegen;
+pub mod compress;
+pub mod datetime;
+pub mod diagnostics;
 pub mod env;
 pub mod eval;
 pub mod exhaustiveness;
+pub mod foreign;
+pub mod generator;
+pub mod golden;
+pub mod highlight;
+pub mod keyword;
+pub mod kv;
+pub mod lint;
+pub mod manifest;
+pub mod matrix;
+pub mod modules;
 pub mod parser;
+pub mod profile;
+pub mod text;
+pub mod tier;
 pub mod types;
 
 #[cfg(test)]
@@ -0,0 +1,304 @@
+//! Lints: warnings about code that is legal but probably a mistake.
+//!
+//! Each lint has a name that the driver's `--deny NAME` turns into a hard
+//! error. Lints look at one top-level form at a time, before it's
+//! type-checked.
+
+use std::fmt;
+
+use crate::ast::{Expr, Meta, Pattern, Type};
+use crate::parser::lexer::{Lexeme, lex};
+use crate::types::TypeEnv;
+
+/// A top-level `defn` / `let` whose name is a builtin's. The definition
+/// replaces the builtin for the rest of the session.
+pub const SHADOW_BUILTIN: &str = "shadow-builtin";
+
+/// A `match` arm that can never be taken because earlier arms already
+/// match everything it would (e.g. a literal after a wildcard).
+pub const REDUNDANT_ARM: &str = "redundant-arm";
+
+/// A call to a function defined with `^{:deprecated "..."}`. Raised by
+/// the type checker, which knows what each name refers to, rather than
+/// by `check`.
+pub const DEPRECATED: &str = "deprecated";
+
+/// A top-level expression in a file whose value nothing uses, like
+/// `(fact 10)` without a `println`. Raised by the driver from the form's
+/// checked type (see `unused_result`), only when running a file.
+pub const UNUSED_RESULT: &str = "unused-result";
+
+/// Every lint name `--deny` accepts.
+pub const ALL: &[&str] = &[SHADOW_BUILTIN, REDUNDANT_ARM, DEPRECATED, UNUSED_RESULT];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub name: &'static str,
+    pub message: String,
+    /// The part of the form the lint is about, if not all of it.
+    pub site: Option<Site>,
+}
+
+/// A list inside a top-level form, or one element of one. The AST has no
+/// positions below the top-level form, so a site holds the list's node
+/// by address, which stays good while the form does, and `locate` finds
+/// it in the form's source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Site {
+    node: *const Expr,
+    child: Option<usize>,
+}
+
+impl Site {
+    /// The call `(f ...)` whose function is `func`.
+    pub fn call(func: &Expr) -> Site {
+        Site { node: func, child: None }
+    }
+
+    /// Element `child` of the `(match ...)` `expr`, counting `match` as
+    /// 0: arm `i` is element `2 + i`.
+    pub fn element(expr: &Expr, child: usize) -> Site {
+        Site { node: expr, child: Some(child) }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.name)
+    }
+}
+
+thread_local! {
+    /// The builtin signatures, as a fresh session sees them.
+    static BUILTINS: TypeEnv = TypeEnv::new();
+}
+
+/// The builtin signature `name` has in a fresh session, if it is one.
+pub fn builtin_type(name: &str) -> Option<Type> {
+    BUILTINS.with(|env| env.get(name).cloned())
+}
+
+/// Builtins the evaluator and type checker dispatch on by name before
+/// looking anything up, so a definition with the same name is never
+/// called.
+const BUILTIN_FORMS: &[&str] = &["if", "and", "or", "let", "list", "array", "vector", "hash-map", "map", "filter", "fold", "foldl", "foldr", "take", "bindings", "defined?", "memory-stats"];
+
+/// Lints that fire on `form`.
+pub fn check(form: &Expr) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    // A multi-arity `defn`'s arities share the name and metadata.
+    let definition = match form {
+        Expr::MultiArity(defns) => defns.first().unwrap_or(form),
+        _ => form,
+    };
+    if let Expr::Defn { name, .. } | Expr::Defgen { name, .. } | Expr::Def { name, .. } | Expr::Defparam { name, .. } = definition {
+        let message = if BUILTIN_FORMS.contains(&name.as_str()) {
+            Some(format!("`{}` names a builtin form; calls to `{}` will still use the builtin", name, name))
+        } else {
+            builtin_type(name).map(|ty| format!("`{}` shadows the builtin `{}: {}`", name, name, ty))
+        };
+        if let Some(message) = message {
+            lints.push(Lint { name: SHADOW_BUILTIN, message, site: None });
+        }
+    }
+    redundant_arms(form, &mut lints);
+    if let Expr::Defn { meta, .. } | Expr::Defgen { meta, .. } = definition {
+        lints.retain(|lint| !allows(meta, lint.name));
+    }
+    lints
+}
+
+/// `UNUSED_RESULT` for top-level `form` of a file, which checked as
+/// `ty`. Definitions are statements, and so is an expression giving
+/// `Unit`; one whose type is still unknown gets the benefit of the doubt.
+pub fn unused_result(form: &Expr, ty: &Type) -> Option<Lint> {
+    let statement = matches!(
+        form,
+        Expr::Def { .. }
+            | Expr::Defparam { .. }
+            | Expr::Deftype { .. }
+            | Expr::Defstruct { .. }
+            | Expr::Defn { .. }
+            | Expr::MultiArity(_)
+            | Expr::Defgen { .. }
+            | Expr::SetReaderMacro { .. }
+    );
+    if statement || matches!(ty, Type::Unit | Type::Inferred | Type::Var(_)) {
+        return None;
+    }
+    Some(Lint { name: UNUSED_RESULT, message: format!("the {} result of `{}` is unused", ty, form), site: None })
+}
+
+/// Whether a definition's metadata turns lint `name` off for it with
+/// `^{:allow :lint-name}`.
+pub fn allows(meta: &Meta, name: &str) -> bool {
+    meta.iter().any(|(key, value)| {
+        &*key.name() == "allow" && matches!(value, Expr::Keyword(lint) if &*lint.name() == name)
+    })
+}
+
+/// `REDUNDANT_ARM` for every `match` anywhere in `form`, at the dead arm.
+fn redundant_arms(form: &Expr, lints: &mut Vec<Lint>) {
+    walk(form, &mut |expr| {
+        let Expr::Match { scrutinee, arms } = expr else {
+            return;
+        };
+        let patterns: Vec<&Pattern> = arms.iter().map(|(p, _)| p).collect();
+        for i in crate::exhaustiveness::redundant_arms(&patterns) {
+            lints.push(Lint {
+                name: REDUNDANT_ARM,
+                message: format!("arm `{}` is unreachable: earlier arms of (match {} ...) cover it", arms[i].0, scrutinee),
+                site: Some(Site::element(expr, 2 + i)),
+            });
+        }
+    });
+}
+
+/// Byte offset of `site` in `text`, the source of top-level `form`.
+///
+/// A list is found by its head and how many lists with the same head
+/// come before it, since that survives what the reader does between the
+/// two: the AST's `(f ...)` is the `n`th one in the text too. Heads are
+/// compared by their last `/` segment, as the loader qualifies names.
+/// `None` if the node isn't in `form` (the checker works on a copy of
+/// some parts) or the count comes out wrong.
+pub fn locate(form: &Expr, text: &str, site: Site) -> Option<usize> {
+    let mut heads = Vec::new();
+    walk(form, &mut |expr| match expr {
+        Expr::List(items) if !items.is_empty() => heads.push((&items[0] as *const Expr, head(&items[0]))),
+        Expr::Call { func, .. } => heads.push((&**func as *const Expr, head(func))),
+        Expr::Match { .. } => heads.push((expr as *const Expr, Some("match"))),
+        _ => {}
+    });
+    let at = heads.iter().position(|(node, _)| *node == site.node)?;
+    let name = heads[at].1?;
+    let nth = heads[..at].iter().filter(|(_, h)| *h == Some(name)).count();
+
+    let lexemes: Vec<_> = lex(text).into_iter().filter(|(l, _)| !matches!(l, Lexeme::Space | Lexeme::Comment { .. })).collect();
+    let atom = |i: usize| match lexemes.get(i) {
+        Some((Lexeme::Atom, range)) => Some(&text[range.clone()]),
+        _ => None,
+    };
+    let open = (0..lexemes.len())
+        .filter(|&i| &text[lexemes[i].1.clone()] == "(" && atom(i + 1).map(short) == Some(short(name)))
+        .nth(nth)?;
+    let Some(child) = site.child else {
+        return Some(lexemes[open].1.start);
+    };
+    // Step over the list's elements, a bracketed one (with any `'` or
+    // `#` stuck to its front) as one.
+    let mut i = open + 1;
+    for _ in 0..child {
+        let mut depth = 0;
+        loop {
+            let (lexeme, range) = lexemes.get(i)?;
+            i += 1;
+            match lexeme {
+                Lexeme::Open => depth += 1,
+                Lexeme::Close if depth == 0 => return None,
+                Lexeme::Close => depth -= 1,
+                Lexeme::Atom if lexemes.get(i).is_some_and(|(l, r)| *l == Lexeme::Open && r.start == range.end) => continue,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+    match lexemes.get(i)? {
+        (Lexeme::Close, _) => None,
+        (_, range) => Some(range.start),
+    }
+}
+
+fn head(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Symbol(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn short(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// Call `f` on `expr` and every expression inside it, parents first and
+/// otherwise in source order.
+fn walk<'a>(expr: &'a Expr, f: &mut dyn FnMut(&'a Expr)) {
+    f(expr);
+    match expr {
+        Expr::Match { scrutinee, arms } => {
+            walk(scrutinee, f);
+            for (pattern, body) in arms {
+                walk_guards(pattern, f);
+                walk(body, f);
+            }
+        }
+        Expr::If { condition, then_branch, else_branch } => {
+            for e in [condition, then_branch, else_branch] {
+                walk(e, f);
+            }
+        }
+        Expr::Let { value, body, .. } => {
+            walk(value, f);
+            walk(body, f);
+        }
+        Expr::Def { value, .. } => walk(value, f),
+        Expr::Defn { body, .. } | Expr::Defgen { body, .. } | Expr::Lambda { body, .. } => walk(body, f),
+        Expr::MultiArity(defns) => defns.iter().for_each(|d| walk(d, f)),
+        Expr::Yield(value)
+        | Expr::Return(value)
+        | Expr::The { expr: value, .. }
+        | Expr::Block { body: value, .. }
+        | Expr::ReturnFrom { value, .. }
+        | Expr::Reset(value)
+        | Expr::Shift { body: value, .. }
+        | Expr::Defer(value)
+        | Expr::Defparam { value, .. } => walk(value, f),
+        Expr::Parameterize { bindings, body } | Expr::WithOpen { bindings, body } => {
+            for (_, value) in bindings {
+                walk(value, f);
+            }
+            walk(body, f);
+        }
+        Expr::Loop { bindings, body } => {
+            for (_, _, value) in bindings {
+                walk(value, f);
+            }
+            walk(body, f);
+        }
+        Expr::Recur(args) | Expr::Do(args) | Expr::List(args) => args.iter().for_each(|a| walk(a, f)),
+        Expr::Call { func, args } => {
+            walk(func, f);
+            args.iter().for_each(|a| walk(a, f));
+        }
+        Expr::Integer32(_)
+        | Expr::Integer64(_)
+        | Expr::Float(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Bytes(_)
+        | Expr::Keyword(_)
+        | Expr::Symbol(_)
+        | Expr::Deftype { .. }
+        | Expr::Defstruct { .. }
+        | Expr::SetReaderMacro { .. }
+        | Expr::Nil => {}
+    }
+}
+
+fn walk_guards<'a>(pattern: &'a Pattern, f: &mut dyn FnMut(&'a Expr)) {
+    match pattern {
+        Pattern::Guard(inner, cond) => {
+            walk_guards(inner, f);
+            walk(cond, f);
+        }
+        Pattern::As(inner, _) => walk_guards(inner, f),
+        Pattern::Cons(h, t) => {
+            walk_guards(h, f);
+            walk_guards(t, f);
+        }
+        Pattern::Or(branches) | Pattern::Constructor(_, branches) => branches.iter().for_each(|b| walk_guards(b, f)),
+        _ => {}
+    }
+}
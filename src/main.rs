@@ -1,18 +1,37 @@
-use std::io::{self, Write};
+use std::borrow::Cow;
+use std::io::{self, IsTerminal};
 
 use rusp::ast::{self, Expr, Type};
 use rusp::codegen;
 use rusp::env::{self, Environment};
 use rusp::eval::eval;
+use rusp::golden::Outcome;
+use rusp::lint;
+use rusp::modules::{Loader, SourceForm, defined_names};
 use rusp::parser;
-use rusp::types::{type_check, TypeEnv};
+use rusp::types::{declare_defns, type_check, TypeEnv};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
 
 fn main() {
     // CLI dispatch:
     //   rusp                       → REPL (tree-walking interpreter)
     //   rusp --llvm                → REPL (LLVM JIT)
+    //   rusp --no-tier             → REPL, never JIT hot functions
+    //   rusp run FILE [ARGS...]    → evaluate FILE; `(args)` sees ARGS
+    //   rusp run - [ARGS...]       → evaluate stdin, form by form
+    //   rusp FILE.rusp [ARGS...]   → same as `rusp run FILE.rusp`
+    //   rusp run --deny LINT FILE  → ... with lint LINT as an error
+    //   rusp run --profile-alloc FILE → ... then print where it allocated
+    //   rusp run --explain-types FILE → ... explaining a type error
     //   rusp build FILE --emit ll  → write FILE.ll
-    //   rusp build FILE --emit obj → write FILE.o
+    //   rusp build FILE --emit obj → write FILE.o (unchanged programs come from the cache)
+    //   rusp build FILE --emit exe → ... and link it (--target TRIPLE to cross-compile)
+    //   rusp test --golden DIR     → compare DIR/*.rusp with their .expected
+    //   rusp -e EXPR               → evaluate EXPR, print its value
+    //   rusp explain [CODE]        → what diagnostic CODE means (or list them)
+    //   rusp check FILE...         → type-check without running, in parallel
     let args: Vec<String> = std::env::args().skip(1).collect();
     if let Some(first) = args.first()
         && first == "build"
@@ -23,21 +42,84 @@ fn main() {
         }
         return;
     }
+    if let Some(first) = args.first()
+        && first == "check"
+    {
+        if let Err(e) = run_check(&args[1..]) {
+            eprintln!("rusp check: {}", e);
+            std::process::exit(e.status());
+        }
+        return;
+    }
+    if let Some(first) = args.first()
+        && first == "explain"
+    {
+        if let Err(e) = run_explain(&args[1..]) {
+            eprintln!("rusp explain: {}", e);
+            std::process::exit(4);
+        }
+        return;
+    }
+    if let Some(first) = args.first()
+        && first == "-e"
+    {
+        if let Err(e) = run_expr(&args[1..]) {
+            eprintln!("rusp -e: {}", for_stderr(&e.with_code()));
+            std::process::exit(e.status());
+        }
+        return;
+    }
+    if let Some(first) = args.first()
+        && first == "test"
+    {
+        match run_test(&args[1..]) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("rusp test: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(first) = args.first()
+        && (first == "run" || first.ends_with(".rusp"))
+    {
+        let script_args = if first == "run" { &args[1..] } else { &args[..] };
+        if let Err(e) = run_script(script_args) {
+            eprintln!("rusp run: {}", for_stderr(&e.with_code()));
+            std::process::exit(e.status());
+        }
+        return;
+    }
 
     let use_llvm = args.iter().any(|a| a == "--llvm");
-    let unknown: Vec<&String> = args.iter().filter(|a| a.as_str() != "--llvm").collect();
+    let no_tier = args.iter().any(|a| a == "--no-tier");
+    let unknown: Vec<&String> = args
+        .iter()
+        .filter(|a| !matches!(a.as_str(), "--llvm" | "--no-tier"))
+        .collect();
     if !unknown.is_empty() {
         eprintln!("Rusp: unknown argument(s): {:?}", unknown);
-        eprintln!("Usage: rusp [--llvm] | rusp build FILE --emit ll|obj");
+        eprintln!(
+            "Usage: rusp [--llvm] [--no-tier] | rusp run [--deny LINT] [--profile-alloc] [--explain-types] FILE [ARGS...] | rusp FILE.rusp [ARGS...] | rusp build FILE --emit ll|obj [-g] | rusp test --golden DIR [--update] | rusp -e EXPR | rusp explain [CODE] | rusp check [--jobs N] FILE..."
+        );
         std::process::exit(2);
     }
+    if no_tier {
+        rusp::tier::set_threshold(None);
+    }
+    if use_llvm {
+        parser::set_backend(parser::Backend::Jit);
+    }
 
     println!("Rusp REPL v0.1.0{}", if use_llvm { " (LLVM JIT mode)" } else { "" });
     println!("Type 'exit' or press Ctrl+C to quit");
-    println!("(blank line cancels a multi-line input)\n");
+    println!("(blank line cancels a multi-line input; :paste reads everything up to :end)\n");
 
-    let mut env = Environment::new();
-    let mut type_env = TypeEnv::new();
+    let mut session = Session::default();
+    if let Err(e) = session.config.load_rc() {
+        eprintln!("Error: {}", e);
+    }
     // In `--llvm` mode each expression is compiled in a fresh module, so
     // any `defn`s the user has typed earlier need to be re-emitted along
     // with the new expression. We keep the AST around and prepend it.
@@ -48,21 +130,56 @@ fn main() {
     // Accumulates partial input across lines when brackets are not yet
     // balanced. Empty once the user has dispatched a complete form.
     let mut buffer = String::new();
+    // After `:paste`: the lines so far, blank ones included, until `:end`.
+    let mut paste: Option<String> = None;
+    let mut editor = match Editor::<ReplHelper, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error: could not start the line editor: {}", e);
+            std::process::exit(1);
+        }
+    };
+    editor.set_helper(Some(ReplHelper));
 
     loop {
-        let prompt = if buffer.is_empty() { "> " } else { ".. " };
-        print!("{}", prompt);
-        io::stdout().flush().unwrap();
-
-        let mut line = String::new();
-        match io::stdin().read_line(&mut line) {
-            Ok(0) => {
-                // EOF (Ctrl-D)
+        let prompt = if paste.is_some() {
+            ""
+        } else if buffer.is_empty() {
+            &session.config.prompt
+        } else {
+            &session.config.continuation
+        };
+        match editor.readline(prompt) {
+            Err(ReadlineError::Eof | ReadlineError::Interrupted) => {
+                // Ctrl-D or Ctrl-C
                 println!();
                 break;
             }
-            Ok(_) => {
+            Ok(mut line) => {
+                line.push('\n');
                 let trimmed = line.trim();
+                if paste.is_none() && !trimmed.is_empty() {
+                    let _ = editor.add_history_entry(trimmed);
+                }
+
+                if let Some(text) = &mut paste {
+                    if trimmed != ":end" {
+                        text.push_str(&line);
+                        continue;
+                    }
+                    let text = paste.take().unwrap_or_default();
+                    for form in parser::reader::Reader::new(text.as_bytes()) {
+                        let evaluated = form.map(|form| repl_eval(form, use_llvm, &mut session, &mut jit_defns));
+                        if !matches!(evaluated, Ok(true)) {
+                            if let Err(e) = evaluated {
+                                eprintln!("Error: {}", e);
+                            }
+                            eprintln!("(the rest of the paste was skipped)");
+                            break;
+                        }
+                    }
+                    continue;
+                }
 
                 // Top-level commands: only honor them on a fresh prompt so
                 // the user can still type "exit" as part of a symbol mid-form
@@ -71,6 +188,22 @@ fn main() {
                     println!("Goodbye!");
                     break;
                 }
+                if buffer.is_empty() && trimmed == ":paste" {
+                    println!("(pasting: finish with :end on a line of its own)");
+                    paste = Some(String::new());
+                    continue;
+                }
+                if buffer.is_empty() && trimmed.starts_with(':') {
+                    if use_llvm && !trimmed.starts_with(":set") {
+                        eprintln!("Error: {} is only available in the interpreter REPL", trimmed);
+                        continue;
+                    }
+                    match repl_command(trimmed, &mut session) {
+                        Ok(report) => println!("{}", report),
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                    continue;
+                }
 
                 // Blank line: on a fresh prompt, just redraw the prompt.
                 // Inside a multi-line input, treat as "cancel this form".
@@ -83,7 +216,7 @@ fn main() {
 
                 buffer.push_str(&line);
 
-                if !is_complete(&buffer) {
+                if !parser::reader::is_complete(&buffer) {
                     // Wait for more input to balance brackets / close strings.
                     continue;
                 }
@@ -91,21 +224,16 @@ fn main() {
                 let input = std::mem::take(&mut buffer);
                 let input = input.trim();
 
-                if use_llvm {
-                    match process_input_llvm(input, &mut type_env, &mut jit_defns) {
-                        Ok(Some((rendered, ty))) => println!("{}: {}", rendered, ty),
-                        Ok(None) => {}
-                        Err(e) => eprintln!("Error: {}", e),
-                    }
-                } else {
-                    match process_input(input, &mut env, &mut type_env) {
-                        Ok((value, ty)) => {
-                            println!("{}: {}", value, ty);
-                        }
-                        Err(e) => {
-                            eprintln!("Error: {}", e);
+                // Several forms on a line run in turn, up to the first error.
+                match parser::parse_program(input) {
+                    Ok(forms) => {
+                        for form in forms {
+                            if !repl_eval(form, use_llvm, &mut session, &mut jit_defns) {
+                                break;
+                            }
                         }
                     }
+                    Err(e) => eprintln!("Error: {}", e),
                 }
             }
             Err(error) => {
@@ -116,76 +244,119 @@ fn main() {
     }
 }
 
-/// Returns true when `input` is ready to be parsed as a complete form.
-///
-/// A form is complete when every open `(` / `[` has been closed and we are
-/// not currently inside a string literal. Brackets inside strings are
-/// ignored. If the user has typed more closers than openers the form is
-/// also considered "complete" — we let the parser produce the real error
-/// rather than deadlocking the REPL.
-fn is_complete(input: &str) -> bool {
-    let mut depth: i32 = 0;
-    let mut in_string = false;
-    let mut escaped = false;
-
-    for ch in input.chars() {
-        if in_string {
-            if escaped {
-                escaped = false;
-            } else if ch == '\\' {
-                escaped = true;
-            } else if ch == '"' {
-                in_string = false;
-            }
-            continue;
-        }
+/// The REPL's line editor hooks: input is colored as it's typed, and a
+/// closing bracket shows the one it closes.
+struct ReplHelper;
 
-        match ch {
-            '"' => in_string = true,
-            '(' | '[' => depth += 1,
-            ')' | ']' => depth -= 1,
-            _ => {}
+impl rustyline::highlight::Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        Cow::Owned(rusp::highlight::highlight(line, Some(pos)))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        // Any keystroke can recolor the line or move the matched bracket.
+        true
+    }
+}
+
+impl rustyline::completion::Completer for ReplHelper {
+    type Candidate = String;
+}
+
+impl rustyline::hint::Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl rustyline::validate::Validator for ReplHelper {}
+
+impl rustyline::Helper for ReplHelper {}
+
+/// Evaluate one form read at the REPL with the chosen backend and print
+/// its value or its error. False if it failed.
+fn repl_eval(form: Expr, use_llvm: bool, session: &mut Session, jit_defns: &mut Vec<Expr>) -> bool {
+    let result = if use_llvm {
+        process_input_llvm(form, &mut session.type_env, jit_defns)
+    } else {
+        process_input(&form, &mut session.env, &mut session.type_env)
+            .map(|(value, ty)| Some((session.config.render(&value), ty)))
+    };
+    match result {
+        Ok(Some((rendered, ty))) if session.config.types => println!("{}: {}", rendered, ty),
+        Ok(Some((rendered, _))) => println!("{}", rendered),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Error: {}", for_stderr(&e));
+            return false;
         }
     }
+    true
+}
 
-    !in_string && depth <= 0
+/// `message` as printed to stderr: with a type diff's parts in color
+/// (see `diagnostics::paint`) if that's a terminal.
+fn for_stderr(message: &str) -> String {
+    if io::stderr().is_terminal() {
+        rusp::diagnostics::paint(message)
+    } else {
+        message.to_string()
+    }
 }
 
 fn process_input(
-    input: &str,
+    ast: &Expr,
     env: &mut Environment,
     type_env: &mut TypeEnv,
 ) -> Result<(env::Value, ast::Type), String> {
-    let ast = parser::parse(input).map_err(|e| e.to_string())?;
-
-    let ty = type_check(&ast, type_env)?;
+    report_lints(lint::check(ast), None, &[], &[])?;
+    let checked = type_check(ast, type_env);
+    report_lints(type_env.take_warnings(), None, &[], &[])?;
+    let ty = checked?;
 
-    let value = eval(&ast, env)?;
+    let value = eval(ast, env)?;
 
     Ok((value, ty))
 }
 
-/// `rusp build FILE --emit ll|obj` — read source, type-check every
-/// form, and emit either textual LLVM IR or a native object.
+/// `rusp build FILE --emit ll|obj|exe [-g] [--target TRIPLE]` — read
+/// source, type-check every form, and emit either textual LLVM IR or a
+/// native object (`exe` links the object too). `-g` adds DWARF debug
+/// info; `--target` cross-compiles.
 ///
 /// The source must be a sequence of `defn`s ending with
 /// `(defn main [] -> i32 ...)`; that defn becomes the C-ABI entry
 /// point, so `cc out.o -o out` is enough to make an executable.
+///
+/// Artifacts are cached by a hash of every module's source (see
+/// `rusp::cache`), so rebuilding an unchanged program only type-checks
+/// it again; `--no-cache` always compiles.
 fn run_build(args: &[String]) -> Result<(), String> {
-    // Parse the sub-arg vector. We expect: <file> --emit <kind>.
+    const USAGE: &str = "Usage: rusp build FILE --emit ll|obj|exe [-g] [--target TRIPLE] [--linker CMD]";
+    // Parse the sub-arg vector.
     let mut file: Option<&String> = None;
     let mut emit: Option<&String> = None;
+    let mut target: Option<&String> = None;
+    let mut linker: Option<&String> = None;
+    let mut debug_info = false;
+    let mut use_cache = true;
     let mut i = 0;
     while i < args.len() {
         match args[i].as_str() {
-            "--emit" => {
+            "-g" => debug_info = true,
+            "--no-cache" => use_cache = false,
+            flag @ ("--emit" | "--target" | "--linker") => {
                 i += 1;
-                emit = args.get(i);
-                if emit.is_none() {
-                    return Err("--emit requires an argument (ll|obj)".into());
+                let value = args.get(i).ok_or_else(|| match flag {
+                    "--emit" => "--emit requires an argument (ll|obj|exe)".to_string(),
+                    "--target" => "--target requires a target triple (e.g. aarch64-unknown-linux-gnu)".to_string(),
+                    _ => "--linker requires a command".to_string(),
+                })?;
+                match flag {
+                    "--emit" => emit = Some(value),
+                    "--target" => target = Some(value),
+                    _ => linker = Some(value),
                 }
             }
-            other if !other.starts_with("--") => {
+            other if !other.starts_with('-') => {
                 if file.is_some() {
                     return Err(format!("unexpected positional argument: {}", other));
                 }
@@ -195,58 +366,790 @@ fn run_build(args: &[String]) -> Result<(), String> {
         }
         i += 1;
     }
-    let file = file.ok_or("missing input file. Usage: rusp build FILE --emit ll|obj")?;
-    let emit = emit.ok_or("missing --emit. Usage: rusp build FILE --emit ll|obj")?;
-
-    let source = std::fs::read_to_string(file)
-        .map_err(|e| format!("could not read {}: {}", file, e))?;
-
-    // Parse all top-level forms. The single-form `parser::parse` rejects
-    // trailing input, so we drive `parse_expr` in a loop.
-    let mut forms: Vec<Expr> = Vec::new();
-    let mut rest = source.trim();
-    while !rest.is_empty() {
-        let (remaining, expr) = parser::expr::parse_expr(rest)
-            .map_err(|e| format!("parse error: {}", e))?;
-        forms.push(expr);
-        rest = remaining.trim();
+    let file = file.ok_or_else(|| format!("missing input file. {}", USAGE))?;
+    let emit = emit.ok_or_else(|| format!("missing --emit. {}", USAGE))?;
+    let ext = match emit.as_str() {
+        "ll" => "ll",
+        "obj" | "exe" => "o",
+        other => return Err(format!("--emit: expected `ll`, `obj` or `exe`, got `{}`", other)),
+    };
+    if let Some(linker) = linker {
+        if emit != "exe" {
+            return Err("--linker only applies to --emit exe".into());
+        }
+        if linker.trim().is_empty() {
+            return Err("--linker requires a command".into());
+        }
     }
 
+    // Imports are expanded here, so codegen sees one flat program.
+    parser::set_backend(parser::Backend::Jit);
+    let mut loader = Loader::new();
+    let sources = loader.load(file)?;
+
     // Type-check every form against a shared TypeEnv so `defn`s can
     // reference each other.
     let mut type_env = TypeEnv::new();
-    for f in &forms {
-        rusp::types::type_check(f, &mut type_env)
-            .map_err(|e| format!("type error: {}", e))?;
+    declare_defns(sources.iter().map(|sf| &sf.form), &mut type_env);
+    for sf in &sources {
+        report_lints(lint::check(&sf.form), Some(sf), &[], &[])?;
+        let checked = rusp::types::type_check(&sf.form, &mut type_env);
+        report_lints(type_env.take_warnings(), Some(sf), &[], &[])?;
+        checked.map_err(|e| format!("{}: type error: {}", sf.loc, e))?;
+    }
+
+    let out_path = format!("{}.{}", file, ext);
+    let out = std::path::Path::new(&out_path);
+    // The source map names files as they were given, so the path to
+    // FILE is part of the key along with the options.
+    let cache = rusp::cache::Cache::for_file(file);
+    let options = [file.as_str(), ext, if debug_info { "-g" } else { "" }, target.map_or("", |t| t.as_str())];
+    let key = rusp::cache::key(loader.files(), &options)?;
+    if use_cache && cache.fetch(&key, ext, out)? {
+        eprintln!("wrote {} (cached)", out_path);
+    } else {
+        let (forms, locs): (Vec<Expr>, Vec<_>) = sources.into_iter().map(|sf| (sf.form, sf.loc)).unzip();
+        let opts = codegen::AotOptions {
+            source_map: Some(codegen::SourceMap::from_locs(file, locs)),
+            debug_info,
+            target: target.cloned(),
+        };
+        if ext == "ll" {
+            let ir = codegen::compile_to_ll_with(&forms, &opts)?;
+            std::fs::write(out, ir).map_err(|e| format!("could not write {}: {}", out_path, e))?;
+        } else {
+            codegen::compile_to_obj_with(&forms, out, &opts)?;
+        }
+        eprintln!("wrote {}", out_path);
+        // A build that can't be cached still succeeded.
+        if use_cache && let Err(e) = cache.store(&key, ext, out) {
+            eprintln!("warning: {}", e);
+        }
+    }
+
+    if emit == "exe" {
+        let exe = executable_path(file);
+        let command = link_command(target.map(String::as_str), linker.map(String::as_str), &out_path, &exe);
+        let status = std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .map_err(|e| format!("could not run linker `{}`: {}", command[0], e))?;
+        if !status.success() {
+            return Err(format!("linking failed ({}): {}", status, command.join(" ")));
+        }
+        eprintln!("wrote {}", exe);
+    }
+    Ok(())
+}
+
+/// Where `--emit exe` puts the executable: FILE without its extension
+/// (`hello.rusp` → `hello`), or `FILE.out` if it has none.
+fn executable_path(file: &str) -> String {
+    let path = std::path::Path::new(file);
+    match path.extension() {
+        Some(_) => path.with_extension("").to_string_lossy().into_owned(),
+        None => format!("{}.out", file),
+    }
+}
+
+/// The command that links `object` into `exe`. `--linker CMD` (split on
+/// whitespace, so it can carry flags) wins; otherwise `cc` for the host,
+/// or `clang --target=TRIPLE` when cross-compiling, since a host `cc`
+/// can't link for another target.
+fn link_command(target: Option<&str>, linker: Option<&str>, object: &str, exe: &str) -> Vec<String> {
+    let mut command: Vec<String> = match (linker, target) {
+        (Some(linker), _) => linker.split_whitespace().map(str::to_string).collect(),
+        (None, Some(target)) => vec!["clang".to_string(), format!("--target={}", target)],
+        (None, None) => vec!["cc".to_string()],
+    };
+    command.extend([object.to_string(), "-o".to_string(), exe.to_string()]);
+    command
+}
+
+/// `rusp -e EXPR [ARGS...]` — type-check and evaluate the forms in EXPR
+/// and print the last one's value, for shell one-liners. `(args)` sees
+/// ARGS.
+fn run_expr(args: &[String]) -> Result<(), ScriptError> {
+    let (expr, script_args) = args
+        .split_first()
+        .ok_or(ScriptError::Usage("missing expression. Usage: rusp -e EXPR [ARGS...]".to_string()))?;
+    rusp::cli::set_args("-e", script_args.to_vec());
+    let mut session = Session::default();
+    if let Some(value) = or_help(eval_stream(expr.as_bytes(), "<expr>", &mut session))?.flatten() {
+        println!("{}", value);
+    }
+    Ok(())
+}
+
+/// `result`, unless the script stopped because `cli/parse` saw `--help`:
+/// then the usage is printed and the run counts as a success, with
+/// nothing more to show.
+fn or_help<T>(result: Result<T, ScriptError>) -> Result<Option<T>, ScriptError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => match rusp::cli::take_help() {
+            Some(usage) => {
+                print!("{}", usage);
+                Ok(None)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// `rusp check [--jobs N] FILE...` — lint and type-check the files (and
+/// what they import) as one program without running it, reporting every
+/// type error and warning in source order. Independent forms are
+/// checked on up to N threads (default: one per core); see `check`.
+fn run_check(args: &[String]) -> Result<(), ScriptError> {
+    const USAGE: &str = "Usage: rusp check [--jobs N] FILE...";
+    let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut files = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--jobs" => {
+                jobs = rest
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&n| n > 0)
+                    .ok_or(ScriptError::Usage(format!("--jobs requires a positive number. {}", USAGE)))?;
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        return Err(ScriptError::Usage(format!("missing input file. {}", USAGE)));
+    }
+    let mut loader = Loader::new();
+    let mut forms = Vec::new();
+    for file in files {
+        forms.extend(loader.load(file).map_err(ScriptError::loading)?);
+    }
+    let count = forms.len();
+    let findings = rusp::check::check(forms, jobs);
+    let errors = findings.iter().filter(|f| f.error).count();
+    for finding in &findings {
+        if finding.error {
+            let code = rusp::diagnostics::classify(rusp::diagnostics::Stage::Type, &finding.message).code;
+            eprintln!("{}: type error: {} [{}]", finding.loc, for_stderr(&finding.message), code);
+        } else {
+            eprintln!("{}: warning: {}", finding.loc, finding.message);
+        }
+    }
+    let summary = format!("{} forms, {} errors, {} warnings", count, errors, findings.len() - errors);
+    if errors > 0 {
+        return Err(ScriptError::Type(summary));
+    }
+    eprintln!("{}", summary);
+    Ok(())
+}
+
+/// `rusp explain [CODE]` — print what diagnostic CODE means, with
+/// examples; with no CODE, list every code.
+fn run_explain(args: &[String]) -> Result<(), String> {
+    match args {
+        [] => println!("{}", rusp::diagnostics::index()),
+        [code] => match rusp::diagnostics::explain(code) {
+            Some(text) => println!("{}", text),
+            None => return Err(format!("no diagnostic `{}`; `rusp explain` lists them", code)),
+        },
+        _ => return Err("Usage: rusp explain [CODE]".to_string()),
+    }
+    Ok(())
+}
+
+/// `rusp test --golden DIR [--update]` — run every `.rusp` file in DIR
+/// and compare each transcript with its `.expected` file (see `golden`),
+/// printing a diff for each mismatch. `--update` rewrites the
+/// `.expected` files instead. Ok(false) if any file failed.
+fn run_test(args: &[String]) -> Result<bool, String> {
+    const USAGE: &str = "Usage: rusp test --golden DIR [--update]";
+    let mut dir = None;
+    let mut update = false;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--golden" => dir = Some(rest.next().ok_or(format!("--golden requires a directory. {}", USAGE))?),
+            "--update" => update = true,
+            other => return Err(format!("unexpected argument: {}. {}", other, USAGE)),
+        }
+    }
+    let dir = dir.ok_or(format!("missing --golden DIR. {}", USAGE))?;
+
+    let cases = rusp::golden::run_dir(std::path::Path::new(dir), update)?;
+    let mut failed = 0;
+    for case in &cases {
+        let path = case.path.display();
+        match &case.outcome {
+            Outcome::Pass => println!("ok      {}", path),
+            Outcome::Updated => println!("updated {}", path),
+            Outcome::Missing => {
+                failed += 1;
+                let expected = rusp::golden::expected_path(&case.path);
+                println!("FAIL    {}: no {} (run with --update to create it)", path, expected.display());
+            }
+            Outcome::Fail { expected, actual } => {
+                failed += 1;
+                println!("FAIL    {}", path);
+                print!("{}", rusp::golden::diff(expected, actual));
+            }
+        }
+    }
+    if update {
+        println!("{} updated", cases.len());
+    } else {
+        println!("{} passed, {} failed", cases.len() - failed, failed);
+    }
+    Ok(failed == 0)
+}
+
+/// `rusp run [--deny LINT]... [--allow LINT]... [--profile-alloc]
+/// [--explain-types] FILE [ARGS...]` — type-check and evaluate each form
+/// of FILE in order with the tree-walking interpreter. Values aren't
+/// echoed (use `print`); everything after FILE is the script's `(args)`.
+/// `--deny` turns a lint's warning into an error and `--allow` silences
+/// it (by name or code, see `diagnostics`); `--profile-alloc` prints the sites
+/// that allocated the most to stderr afterwards (see `profile`);
+/// `--explain-types` prints the checker's derivation for a form that
+/// fails to type-check.
+fn run_script(args: &[String]) -> Result<(), ScriptError> {
+    const USAGE: &str =
+        "Usage: rusp run [--deny LINT]... [--allow LINT]... [--profile-alloc] [--explain-types] FILE|- [ARGS...]";
+    // Rows of the `--profile-alloc` report.
+    const PROFILE_TOP: usize = 20;
+    let mut session = Session::default();
+    let mut profile = false;
+    let mut rest = args;
+    while let Some((flag, after)) = rest.split_first() {
+        match flag.as_str() {
+            "--deny" | "--allow" => {
+                let (name, after) = after
+                    .split_first()
+                    .ok_or(ScriptError::Usage(format!("{} requires a lint name or code. {}", flag, USAGE)))?;
+                let Some(lint) = rusp::diagnostics::lint_name(name) else {
+                    return Err(ScriptError::Usage(format!(
+                        "{}: unknown lint `{}` (known: {})",
+                        flag,
+                        name,
+                        lint::ALL.join(", ")
+                    )));
+                };
+                if flag == "--deny" {
+                    session.deny.push(lint.to_string());
+                } else {
+                    session.allow.push(lint.to_string());
+                }
+                rest = after;
+            }
+            "--profile-alloc" => {
+                profile = true;
+                rest = after;
+            }
+            "--explain-types" => {
+                session.explain = true;
+                rest = after;
+            }
+            _ => break,
+        }
+    }
+    let (file, script_args) =
+        rest.split_first().ok_or(ScriptError::Usage(format!("missing input file. {}", USAGE)))?;
+    rusp::cli::set_args(file, script_args.to_vec());
+
+    if profile {
+        rusp::profile::start();
+    }
+    let result = if file == "-" {
+        eval_stream(io::stdin().lock(), "<stdin>", &mut session).map(|_| ())
+    } else {
+        eval_file(file, &mut session).map(|_| ())
+    };
+    if profile {
+        eprintln!("allocations by site, most bytes first:\n{}", rusp::profile::stop().top(PROFILE_TOP));
+    }
+    or_help(result).map(|_| ())
+}
+
+/// `rusp run -` and `rusp -e`: type-check and evaluate forms as they
+/// arrive on `input`, each one as soon as it's complete, returning the
+/// last one's value. Errors name the form by its position in `source`,
+/// since there's no file to point into.
+fn eval_stream(
+    input: impl io::BufRead,
+    source: &str,
+    session: &mut Session,
+) -> Result<Option<env::Value>, ScriptError> {
+    let mut last = None;
+    for (i, form) in parser::reader::Reader::new(input).enumerate() {
+        let at = |e: String| format!("{}: form {}: {}", source, i + 1, e);
+        let form = form.map_err(|e| ScriptError::loading(at(e)))?;
+        rusp::profile::set_site(format!("{}: form {}", source, i + 1));
+        if rusp::modules::import_target(&form).map_err(|e| ScriptError::Usage(at(e)))?.is_some() {
+            return Err(ScriptError::Usage(at("import needs a file; use `rusp run FILE`".to_string())));
+        }
+        report_lints(lint::check(&form), None, &session.deny, &session.allow).map_err(ScriptError::Type)?;
+        let checked = check_form(&form, session);
+        report_lints(session.type_env.take_warnings(), None, &session.deny, &session.allow).map_err(ScriptError::Type)?;
+        checked.map_err(|e| ScriptError::Type(at(format!("type error: {}", e))))?;
+        last = Some(eval(&form, &mut session.env).map_err(|e| ScriptError::Runtime(at(e)))?);
+    }
+    Ok(last)
+}
+
+/// Why `rusp run` or `rusp -e` stopped. Each kind exits with its own
+/// status, so a wrapper can tell them apart without reading stderr.
+#[derive(Debug)]
+enum ScriptError {
+    /// The program started and an evaluation failed: status 1.
+    Runtime(String),
+    /// A form didn't parse: status 2.
+    Parse(String),
+    /// A form didn't type-check, or tripped a `--deny` lint: status 3.
+    Type(String),
+    /// Bad arguments, or a file or import that couldn't be read or
+    /// found: status 4.
+    Usage(String),
+}
+
+impl ScriptError {
+    fn status(&self) -> i32 {
+        match self {
+            ScriptError::Runtime(_) => 1,
+            ScriptError::Parse(_) => 2,
+            ScriptError::Type(_) => 3,
+            ScriptError::Usage(_) => 4,
+        }
+    }
+
+    /// The message with its diagnostic code after it, as `rusp run` and
+    /// `rusp -e` report it. Usage errors have no code, and neither does a
+    /// `--deny`'d lint, which already carries its name.
+    fn with_code(&self) -> String {
+        use rusp::diagnostics::{Stage, classify};
+        let (stage, message) = match self {
+            ScriptError::Runtime(e) => (Stage::Runtime, e),
+            ScriptError::Parse(e) => (Stage::Parse, e),
+            ScriptError::Type(e) if e.ends_with(']') => return e.clone(),
+            ScriptError::Type(e) => (Stage::Type, e),
+            ScriptError::Usage(e) => return e.clone(),
+        };
+        format!("{} [{}]", message, classify(stage, message).code)
+    }
+
+    /// An error from reading a program in: a parse error (`Loader` and
+    /// `Reader` say so in the message), a program the loader rejects
+    /// (`modules::PROGRAM_ERRORS`), or a file that can't be had.
+    fn loading(message: String) -> ScriptError {
+        if message.contains("parse error: ") {
+            ScriptError::Parse(message)
+        } else if rusp::modules::PROGRAM_ERRORS.iter().any(|p| message.contains(p)) {
+            ScriptError::Type(message)
+        } else {
+            ScriptError::Usage(message)
+        }
+    }
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScriptError::Runtime(e) | ScriptError::Parse(e) | ScriptError::Type(e) | ScriptError::Usage(e) => {
+                write!(f, "{}", e)
+            }
+        }
+    }
+}
+
+impl From<ScriptError> for String {
+    fn from(e: ScriptError) -> String {
+        e.to_string()
+    }
+}
+
+/// Interpreter state that outlives a single form: what `rusp run` and
+/// the REPL's `:load` evaluate into.
+#[derive(Default)]
+struct Session {
+    env: Environment,
+    type_env: TypeEnv,
+    loader: Loader,
+    /// Files brought in with `:load`, for `:reload`.
+    loaded: Vec<LoadedFile>,
+    /// Lints reported as errors rather than warnings.
+    deny: Vec<String>,
+    /// Lints not reported at all.
+    allow: Vec<String>,
+    /// On a type error, show how the checker got there.
+    explain: bool,
+    /// How the REPL prompts and prints, set with `:set` or the rc file.
+    config: ReplConfig,
+}
+
+/// REPL settings. `:set NAME VALUE` changes one; `:set` alone lists them.
+struct ReplConfig {
+    prompt: String,
+    /// The prompt for the second and later lines of an unfinished form.
+    continuation: String,
+    /// Print `: TYPE` after each value.
+    types: bool,
+    /// Print at most this many elements of a list, then `...`.
+    max_items: Option<usize>,
+    /// Cut strings longer than this many characters, ending them in `...`.
+    max_string: Option<usize>,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        ReplConfig {
+            prompt: "> ".to_string(),
+            continuation: ".. ".to_string(),
+            types: true,
+            max_items: None,
+            max_string: None,
+        }
+    }
+}
+
+impl ReplConfig {
+    const USAGE: &str = "usage: :set prompt|continuation TEXT, :set types on|off, :set max-items|max-string N|none";
+
+    /// `:set NAME VALUE`. A prompt may be quoted to keep spaces at its
+    /// ends: `:set prompt "λ "`.
+    fn set(&mut self, arg: &str) -> Result<String, String> {
+        if arg.is_empty() {
+            return Ok(self.describe());
+        }
+        let (name, value) = arg.split_once(char::is_whitespace).ok_or(Self::USAGE)?;
+        let value = value.trim();
+        let limit = || match value {
+            "none" | "off" => Ok(None),
+            n => n.parse::<usize>().map(Some).map_err(|_| format!("{}: expected a number or none, got {}", name, n)),
+        };
+        match name {
+            "prompt" | "continuation" => {
+                let text = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value).to_string();
+                if name == "prompt" {
+                    self.prompt = text;
+                } else {
+                    self.continuation = text;
+                }
+            }
+            "types" => {
+                self.types = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    other => return Err(format!("types: expected on or off, got {}", other)),
+                }
+            }
+            "max-items" => self.max_items = limit()?,
+            "max-string" => self.max_string = limit()?,
+            other => return Err(format!("unknown setting {} ({})", other, Self::USAGE)),
+        }
+        Ok(self.describe())
+    }
+
+    fn describe(&self) -> String {
+        let limit = |n: Option<usize>| n.map_or("none".to_string(), |n| n.to_string());
+        format!(
+            "prompt {:?}, continuation {:?}, types {}, max-items {}, max-string {}",
+            self.prompt,
+            self.continuation,
+            if self.types { "on" } else { "off" },
+            limit(self.max_items),
+            limit(self.max_string)
+        )
+    }
+
+    /// `value` as the REPL prints it, cut down to the limits.
+    fn render(&self, value: &env::Value) -> String {
+        use env::Value;
+        match value {
+            Value::String(s) => self.cut(s),
+            Value::List(items) => {
+                let shown = self.max_items.unwrap_or(items.len()).min(items.len());
+                let mut parts: Vec<String> = items[..shown].iter().map(|item| self.render(item)).collect();
+                if shown < items.len() {
+                    parts.push("...".to_string());
+                }
+                format!("({})", parts.join(" "))
+            }
+            Value::Pair(pair) => {
+                let mut out = format!("({}", self.render(&pair.0));
+                let mut tail = &pair.1;
+                while let Value::Pair(next) = tail {
+                    out.push(' ');
+                    out.push_str(&self.render(&next.0));
+                    tail = &next.1;
+                }
+                format!("{} . {})", out, self.render(tail))
+            }
+            other => other.written(),
+        }
     }
 
-    match emit.as_str() {
-        "ll" => {
-            let ir = codegen::compile_to_ll(&forms)?;
-            let out_path = format!("{}.ll", file);
-            std::fs::write(&out_path, ir)
-                .map_err(|e| format!("could not write {}: {}", out_path, e))?;
-            eprintln!("wrote {}", out_path);
-            Ok(())
+    /// `text` as a string literal, with what's past the limit left out.
+    fn cut(&self, text: &str) -> String {
+        match self.max_string {
+            Some(max) if text.chars().count() > max => {
+                format!("{}...", ast::render_string(&text.chars().take(max).collect::<String>()))
+            }
+            _ => ast::render_string(text),
         }
-        "obj" => {
-            let out_path = format!("{}.o", file);
-            codegen::compile_to_obj(&forms, std::path::Path::new(&out_path))?;
-            eprintln!("wrote {}", out_path);
-            Ok(())
+    }
+
+    /// Load settings from the rc file: `$RUSP_RC`, or `~/.rusprc`. Each
+    /// line is a `:set` command; blank lines and lines starting with `;`
+    /// are skipped. A missing file is fine.
+    fn load_rc(&mut self) -> Result<(), String> {
+        let path = match std::env::var_os("RUSP_RC") {
+            Some(path) => std::path::PathBuf::from(path),
+            None => match std::env::var_os("HOME") {
+                Some(home) => std::path::Path::new(&home).join(".rusprc"),
+                None => return Ok(()),
+            },
+        };
+        let Ok(text) = std::fs::read_to_string(&path) else { return Ok(()) };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let set = line.strip_prefix(":set").filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+            let result = match set {
+                Some(arg) => self.set(arg.trim()).map(|_| ()),
+                None => Err(format!("expected a :set command, got {}", line)),
+            };
+            result.map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))?;
         }
-        other => Err(format!("--emit: expected `ll` or `obj`, got `{}`", other)),
+        Ok(())
     }
 }
 
+/// Print `lints` (what `lint::check` finds in a form, or what the type
+/// checker raised while checking it) as warnings, or fail on the first
+/// one in `deny`. Lints in `allow` aren't reported.
+fn report_lints(lints: Vec<lint::Lint>, form: Option<&SourceForm>, deny: &[String], allow: &[String]) -> Result<(), String> {
+    for lint in lints {
+        let at = |s: String| match form {
+            Some(sf) => format!("{}: {}", sf.loc_of(&lint), s),
+            None => s,
+        };
+        if allow.iter().any(|a| a == lint.name) {
+            continue;
+        }
+        if deny.iter().any(|d| d == lint.name) {
+            return Err(at(format!("error: {}", lint)));
+        }
+        eprintln!("{}", at(format!("warning: {}", lint)));
+    }
+    Ok(())
+}
+
+/// Type-check `form` in the session. Under `--explain-types`, a form
+/// that fails has the checker's derivation for it printed first.
+fn check_form(form: &Expr, session: &mut Session) -> Result<Type, String> {
+    if !session.explain {
+        return type_check(form, &mut session.type_env);
+    }
+    let (checked, derivation) = rusp::types::explain(|| type_check(form, &mut session.type_env));
+    if checked.is_err() {
+        eprintln!("{}", derivation);
+    }
+    checked
+}
+
+/// Type-check and evaluate every form of `path` (with its imports
+/// expanded), returning the top-level names the file itself defines
+/// (`defn`s and `def`s) in order. Errors are prefixed with the
+/// failing form's `FILE:LINE:COL`; forms before it stay evaluated.
+fn eval_file(path: &str, session: &mut Session) -> Result<Vec<String>, ScriptError> {
+    let sources = session.loader.load(path).map_err(ScriptError::loading)?;
+    declare_defns(sources.iter().map(|sf| &sf.form), &mut session.type_env);
+    let mut names = Vec::new();
+    for sf in &sources {
+        let at = |e: String| format!("{}: {}", sf.loc, e);
+        rusp::profile::set_site(sf.loc.to_string());
+        report_lints(lint::check(&sf.form), Some(sf), &session.deny, &session.allow).map_err(ScriptError::Type)?;
+        let checked = check_form(&sf.form, session);
+        report_lints(session.type_env.take_warnings(), Some(sf), &session.deny, &session.allow).map_err(ScriptError::Type)?;
+        let ty = checked.map_err(|e| ScriptError::Type(at(format!("type error: {}", e))))?;
+        let unused = lint::unused_result(&sf.form, &ty).into_iter().collect();
+        report_lints(unused, Some(sf), &session.deny, &session.allow).map_err(ScriptError::Type)?;
+        eval(&sf.form, &mut session.env).map_err(|e| ScriptError::Runtime(at(e)))?;
+        if sf.loc.file == path {
+            for name in defined_names(&sf.form) {
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// A file brought in with `:load`, remembered for `:reload`.
+struct LoadedFile {
+    path: String,
+    names: Vec<String>,
+}
+
+/// REPL meta-commands (`:load FILE`, `:reload [NAME]`, `:forget NAME`,
+/// `:set ...`, `:ast FORM`).
+/// Returns the text to print.
+fn repl_command(
+    line: &str,
+    session: &mut Session,
+) -> Result<String, String> {
+    let (cmd, arg) = match line.split_once(char::is_whitespace) {
+        Some((c, a)) => (c, a.trim()),
+        None => (line, ""),
+    };
+    match cmd {
+        ":load" => {
+            if arg.is_empty() {
+                return Err("usage: :load FILE".to_string());
+            }
+            let names = eval_file(arg, session)?;
+            let report = format!("loaded {} ({})", arg, describe_names(&names));
+            match session.loaded.iter_mut().find(|f| f.path == arg) {
+                Some(f) => f.names = names,
+                None => session.loaded.push(LoadedFile { path: arg.to_string(), names }),
+            }
+            Ok(report)
+        }
+        ":reload" => {
+            let targets: Vec<usize> = if arg.is_empty() {
+                (0..session.loaded.len()).collect()
+            } else {
+                let matches: Vec<usize> = (0..session.loaded.len())
+                    .filter(|&i| file_matches(&session.loaded[i].path, arg))
+                    .collect();
+                if matches.is_empty() {
+                    return Err(format!("no loaded file matches `{}`", arg));
+                }
+                matches
+            };
+            if targets.is_empty() {
+                return Err("nothing to reload (use :load FILE first)".to_string());
+            }
+            let mut reports = Vec::new();
+            for i in targets {
+                let path = session.loaded[i].path.clone();
+                // Reloading is redefining the file's own constants.
+                for name in &session.loaded[i].names {
+                    session.type_env.forget_constant(name);
+                }
+                let names = eval_file(&path, session)?;
+                let file = &mut session.loaded[i];
+                reports.push(reload_report(&file.path, &file.names, &names));
+                file.names = names;
+            }
+            Ok(reports.join("\n"))
+        }
+        ":forget" => {
+            if arg.is_empty() {
+                return Err("usage: :forget NAME...".to_string());
+            }
+            let names: Vec<&str> = arg.split_whitespace().collect();
+            if let Some(name) = names.iter().find(|name| session.env.get(name).is_none()) {
+                return Err(format!("`{}` is not defined", name));
+            }
+            // Calls look their function up when they run, so what's left
+            // would fail at runtime; forget the users along with it.
+            for name in &names {
+                let users: Vec<String> = session
+                    .type_env
+                    .users_of(name)
+                    .into_iter()
+                    .filter(|user| !names.contains(&user.as_str()))
+                    .map(|user| format!("`{}`", user))
+                    .collect();
+                if !users.is_empty() {
+                    return Err(format!(
+                        "cannot forget `{}`: {} {} it",
+                        name,
+                        users.join(", "),
+                        if users.len() == 1 { "uses" } else { "use" }
+                    ));
+                }
+            }
+            let mut reports = Vec::new();
+            for name in names {
+                session.env.remove(name);
+                session.type_env.remove(name);
+                // A definition that replaced a builtin gives it back.
+                match (Environment::new().get(name), lint::builtin_type(name)) {
+                    (Some(value), Some(ty)) => {
+                        session.env.set(name.to_string(), value);
+                        session.type_env.insert(name.to_string(), ty);
+                        reports.push(format!("forgot {} (the builtin is back)", name));
+                    }
+                    _ => reports.push(format!("forgot {}", name)),
+                }
+            }
+            Ok(reports.join("\n"))
+        }
+        ":set" => session.config.set(arg),
+        ":ast" => {
+            if arg.is_empty() {
+                return Err("usage: :ast FORM".to_string());
+            }
+            parser::parse(arg).map(|expr| expr.to_string()).map_err(|e| e.to_string())
+        }
+        other => Err(format!(
+            "unknown command {} (try :load FILE, :reload [NAME], :forget NAME, :set or :ast FORM)",
+            other
+        )),
+    }
+}
+
+/// `:reload util` matches `util.rusp`, `lib/util.rusp`, or the exact path.
+fn file_matches(path: &str, query: &str) -> bool {
+    let p = std::path::Path::new(path);
+    path == query
+        || p.file_name().is_some_and(|f| f == query)
+        || p.file_stem().is_some_and(|s| s == query)
+}
+
+fn describe_names(names: &[String]) -> String {
+    match names.len() {
+        0 => "no definitions".to_string(),
+        1 => "1 definition".to_string(),
+        n => format!("{} definitions", n),
+    }
+}
+
+/// Summarize what changed between two loads of the same file. Names that
+/// disappeared stay bound to their old values; they're only reported.
+fn reload_report(path: &str, old: &[String], new: &[String]) -> String {
+    let redefined: Vec<&str> = new.iter().filter(|n| old.contains(n)).map(String::as_str).collect();
+    let added: Vec<&str> = new.iter().filter(|n| !old.contains(n)).map(String::as_str).collect();
+    let removed: Vec<&str> = old.iter().filter(|n| !new.contains(n)).map(String::as_str).collect();
+    let mut report = format!("reloaded {}", path);
+    if !redefined.is_empty() {
+        report.push_str(&format!("\n  redefined: {}", redefined.join(", ")));
+    }
+    if !added.is_empty() {
+        report.push_str(&format!("\n  new: {}", added.join(", ")));
+    }
+    if !removed.is_empty() {
+        report.push_str(&format!(
+            "\n  no longer defined (old bindings kept): {}",
+            removed.join(", ")
+        ));
+    }
+    report
+}
+
 /// `--llvm` REPL pipeline.
 ///
-/// Always runs parse + type-check (so the user gets a uniform diagnostic
+/// Always runs type-check (so the user gets a uniform diagnostic
 /// experience regardless of backend). For `defn`, the form is registered
 /// in the type env and accumulated in `jit_defns` so subsequent
 /// expressions can call it; we don't JIT the body yet, but we return the
-/// same `#<function:N>: fn(...)` line as the tree-walking REPL (`N` is
-/// arity, matching `Display` for `Value::Function`).
+/// same `#<function name [params] -> T>: fn(...)` line as the tree-walking
+/// REPL (matching `Display` for `Value::Function`).
 ///
 /// For an expression, we build a program slice of `[..jit_defns, expr]`
 /// and dispatch to the right `jit_eval_*_program` based on the
@@ -255,17 +1158,17 @@ fn run_build(args: &[String]) -> Result<(), String> {
 /// Top-level `let` (without body), `match`, list literals, and string
 /// literals fall outside the MVP JIT scope and produce a clean error.
 fn process_input_llvm(
-    input: &str,
+    ast: Expr,
     type_env: &mut TypeEnv,
     jit_defns: &mut Vec<Expr>,
 ) -> Result<Option<(String, Type)>, String> {
-    let ast = parser::parse(input).map_err(|e| e.to_string())?;
-    let ty = type_check(&ast, type_env)?;
+    let checked = type_check(&ast, type_env);
+    report_lints(type_env.take_warnings(), None, &[], &[])?;
+    let ty = checked?;
 
-    if let Expr::Defn { params, .. } = &ast {
-        // Match interpreter REPL: `#<function:<arity>>: fn(...) -> T`
-        // (`Value::Function` uses `params.len()` for the display id).
-        let rendered = format!("#<function:{}>", params.len());
+    if let Expr::Defn { name, params, return_type, .. } = &ast {
+        let params = params.iter().map(|(param, ty)| (param, ty));
+        let rendered = env::describe_function("function", Some(name), params, return_type);
         jit_defns.push(ast);
         return Ok(Some((rendered, ty)));
     }
@@ -292,6 +1195,7 @@ fn process_input_llvm(
 mod process_input_llvm_tests {
     use super::process_input_llvm;
     use rusp::ast::Type;
+    use rusp::parser::parse;
     use rusp::types::TypeEnv;
 
     #[test]
@@ -299,13 +1203,13 @@ mod process_input_llvm_tests {
         let mut type_env = TypeEnv::new();
         let mut jit_defns = Vec::new();
         let (s, ty) = process_input_llvm(
-            "(defn twice [x: i32] -> i32 (* x 2))",
+            parse("(defn twice [x: i32] -> i32 (* x 2))").unwrap(),
             &mut type_env,
             &mut jit_defns,
         )
         .unwrap()
         .expect("defn should print like interpreter");
-        assert_eq!(s, "#<function:1>");
+        assert_eq!(s, "#<function twice [x: i32] -> i32>");
         assert!(
             matches!(ty, Type::Function { .. }),
             "expected function type, got {:?}",
@@ -319,19 +1223,19 @@ mod process_input_llvm_tests {
         let mut type_env = TypeEnv::new();
         let mut jit_defns = Vec::new();
         let (s, _) = process_input_llvm(
-            "(defn add [a: i32 b: i32] -> i32 (+ a b))",
+            parse("(defn add [a: i32 b: i32] -> i32 (+ a b))").unwrap(),
             &mut type_env,
             &mut jit_defns,
         )
         .unwrap()
         .unwrap();
-        assert_eq!(s, "#<function:2>");
+        assert_eq!(s, "#<function add [a: i32 b: i32] -> i32>");
     }
 }
 
 #[cfg(test)]
 mod main_tests {
-    use super::is_complete;
+    use rusp::parser::reader::is_complete;
 
     #[test]
     fn complete_simple() {
@@ -384,3 +1288,193 @@ mod main_tests {
         assert!(is_complete("(+ 1 2))"));
     }
 }
+
+#[cfg(test)]
+mod repl_command_tests {
+    use super::{Session, repl_command};
+    use rusp::env::Value;
+
+    #[test]
+    fn reload_reports_redefined_new_and_removed() {
+        let path = std::env::temp_dir().join(format!("rusp-reload-{}.rusp", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        let mut session = Session::default();
+
+        std::fs::write(&path, "(defn f [] -> i32 1)\n(defn g [] -> i32 2)").unwrap();
+        let report = repl_command(&format!(":load {}", path_str), &mut session).unwrap();
+        assert!(report.ends_with("(2 definitions)"), "got: {}", report);
+
+        std::fs::write(&path, "(defn f [] -> i32 10)\n(def h 3)").unwrap();
+        let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+        let report = repl_command(&format!(":reload {}", stem), &mut session).unwrap();
+        assert!(report.contains("redefined: f"), "got: {}", report);
+        assert!(report.contains("new: h"), "got: {}", report);
+        assert!(report.contains("no longer defined (old bindings kept): g"), "got: {}", report);
+        assert!(matches!(session.env.get("h"), Some(Value::Integer32(3))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn forget_removes_a_definition() {
+        let mut session = Session::default();
+        for form in ["(defconst f 1)", "(defn print [x: i32] -> i32 x)"] {
+            let form = rusp::parser::parse(form).unwrap();
+            rusp::types::type_check(&form, &mut session.type_env).unwrap();
+            rusp::eval::eval(&form, &mut session.env).unwrap();
+        }
+        assert_eq!(repl_command(":forget f print", &mut session).unwrap(), "forgot f\nforgot print (the builtin is back)");
+        assert!(session.env.get("f").is_none());
+        assert!(session.type_env.get("f").is_none());
+        assert!(matches!(session.env.get("print"), Some(Value::BuiltinFunction { .. })));
+        // No longer a constant, so it can be defined again.
+        let form = rusp::parser::parse("(def f 2)").unwrap();
+        assert!(rusp::types::type_check(&form, &mut session.type_env).is_ok());
+        assert_eq!(repl_command(":forget nope", &mut session).unwrap_err(), "`nope` is not defined");
+    }
+
+    #[test]
+    fn forget_refuses_a_name_still_in_use() {
+        let mut session = Session::default();
+        for form in ["(def x 1)", "(defn f [] -> i32 (+ x 1))"] {
+            let form = rusp::parser::parse(form).unwrap();
+            rusp::types::type_check(&form, &mut session.type_env).unwrap();
+            rusp::eval::eval(&form, &mut session.env).unwrap();
+        }
+        assert_eq!(repl_command(":forget x", &mut session).unwrap_err(), "cannot forget `x`: `f` uses it");
+        assert!(session.env.get("x").is_some());
+        // Together they can go, and `x` can then come back as anything.
+        assert_eq!(repl_command(":forget x f", &mut session).unwrap(), "forgot x\nforgot f");
+        let form = rusp::parser::parse("(def x \"one\")").unwrap();
+        assert!(rusp::types::type_check(&form, &mut session.type_env).is_ok());
+    }
+
+    #[test]
+    fn set_changes_prompt_and_printing() {
+        let mut session = Session::default();
+        repl_command(":set prompt \"λ \"", &mut session).unwrap();
+        repl_command(":set types off", &mut session).unwrap();
+        let report = repl_command(":set max-items 2", &mut session).unwrap();
+        assert_eq!(report, "prompt \"λ \", continuation \".. \", types off, max-items 2, max-string none");
+        repl_command(":set max-string 3", &mut session).unwrap();
+
+        let config = &session.config;
+        let list = Value::List(vec![Value::Integer32(1), Value::Integer32(2), Value::Integer32(3)]);
+        assert_eq!(config.render(&list), "(1 2 ...)");
+        assert_eq!(config.render(&Value::String("abcdef".try_into().unwrap())), "\"abc\"...");
+        assert_eq!(config.render(&Value::String("a\"b".try_into().unwrap())), "\"a\\\"b\"");
+        let err = repl_command(":set max-items lots", &mut session).unwrap_err();
+        assert_eq!(err, "max-items: expected a number or none, got lots");
+        assert!(repl_command(":set colour on", &mut session).unwrap_err().starts_with("unknown setting colour"));
+    }
+
+    #[test]
+    fn reload_may_redefine_the_files_constants() {
+        let path = std::env::temp_dir().join(format!("rusp-reload-const-{}.rusp", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        let mut session = Session::default();
+        std::fs::write(&path, "(defconst N 1)").unwrap();
+        repl_command(&format!(":load {}", path_str), &mut session).unwrap();
+        std::fs::write(&path, "(defconst N 2)").unwrap();
+        let report = repl_command(":reload", &mut session).unwrap();
+        assert!(report.contains("redefined: N"), "got: {}", report);
+        assert!(matches!(session.env.get("N"), Some(Value::Integer32(2))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reload_errors() {
+        let mut session = Session::default();
+        let err = repl_command(":reload", &mut session).unwrap_err();
+        assert!(err.contains("nothing to reload"), "got: {}", err);
+        let err = repl_command(":reload nope", &mut session).unwrap_err();
+        assert!(err.contains("no loaded file matches"), "got: {}", err);
+        let err = repl_command(":frobnicate", &mut session).unwrap_err();
+        assert!(err.starts_with("unknown command :frobnicate"), "got: {}", err);
+    }
+
+    #[test]
+    fn ast_prints_source_that_reads_back() {
+        let mut session = Session::default();
+        let form = r#"(str-concat "say \"hi\"\n" (if true "a\\b" "c"))"#;
+        let printed = repl_command(&format!(":ast {}", form), &mut session).unwrap();
+        assert_eq!(printed, form);
+        assert_eq!(repl_command(":ast 1.0", &mut session).unwrap(), "1.0");
+        assert_eq!(repl_command(":ast", &mut session).unwrap_err(), "usage: :ast FORM");
+    }
+}
+
+#[cfg(test)]
+mod run_script_tests {
+    use super::{Session, eval_file, run_script};
+
+    #[test]
+    fn deny_turns_a_lint_into_an_error() {
+        let path = std::env::temp_dir().join(format!("rusp-deny-{}.rusp", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        std::fs::write(&path, "(defn f [] -> i32 1)\n(defn print [x: i32] -> i32 x)").unwrap();
+
+        let mut session = Session::default();
+        assert!(eval_file(&path_str, &mut session).is_ok());
+
+        let mut session = Session { deny: vec!["shadow-builtin".to_string()], ..Session::default() };
+        let err = eval_file(&path_str, &mut session).unwrap_err();
+        assert_eq!(err.status(), 3);
+        assert_eq!(
+            err.to_string(),
+            format!("{}:2:1: error: `print` shadows the builtin `print: {}` [shadow-builtin]",
+                path_str,
+                rusp::lint::builtin_type("print").unwrap())
+        );
+        // Forms before the offending one were still evaluated.
+        assert!(session.env.get("f").is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn loader_rejections_are_type_errors() {
+        let path = std::env::temp_dir().join(format!("rusp-constant-{}.rusp", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        std::fs::write(&path, "(defconst n 1)\n(def n 2)").unwrap();
+        let err = run_script(std::slice::from_ref(&path_str)).unwrap_err();
+        assert_eq!(err.status(), 3, "{}", err);
+        assert!(err.to_string().contains("cannot redefine constant `n`"), "{}", err);
+        std::fs::remove_file(&path).unwrap();
+        let missing = path_str.replace("constant", "missing");
+        assert_eq!(run_script(&[missing]).unwrap_err().status(), 4);
+    }
+
+    #[test]
+    fn help_stops_the_script_successfully() {
+        let path = std::env::temp_dir().join(format!("rusp-help-{}.rusp", std::process::id()));
+        let path_str = path.to_string_lossy().to_string();
+        std::fs::write(&path, "(def opts (cli/parse (list (list \"flag\" \"verbose\" \"v\" \"Talk more\")) (args)))\n(undefined-after-help)").unwrap();
+        // The form after `cli/parse` never runs, and there's no error.
+        assert!(run_script(&[path_str.clone(), "--help".to_string()]).is_ok());
+        assert_eq!(run_script(&[path_str, "-v".to_string()]).unwrap_err().status(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod build_tests {
+    use super::{executable_path, link_command};
+
+    #[test]
+    fn executable_drops_the_extension() {
+        assert_eq!(executable_path("dir/hello.rusp"), "dir/hello");
+        assert_eq!(executable_path("hello"), "hello.out");
+    }
+
+    #[test]
+    fn linker_follows_the_target() {
+        assert_eq!(link_command(None, None, "a.rusp.o", "a"), ["cc", "a.rusp.o", "-o", "a"]);
+        assert_eq!(
+            link_command(Some("aarch64-unknown-linux-gnu"), None, "a.rusp.o", "a"),
+            ["clang", "--target=aarch64-unknown-linux-gnu", "a.rusp.o", "-o", "a"]
+        );
+        assert_eq!(
+            link_command(Some("aarch64-unknown-linux-gnu"), Some("aarch64-linux-gnu-gcc -static"), "a.rusp.o", "a"),
+            ["aarch64-linux-gnu-gcc", "-static", "a.rusp.o", "-o", "a"]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! `rusp.toml` project manifests and `rusp.lock` lockfiles.
+//!
+//! Only the slice of TOML these files need is understood: `[section]`
+//! headers, `key = "string"`, and inline tables of strings
+//! (`key = { path = "../lib" }`), with `#` comments.
+//!
+//! ```toml
+//! [package]
+//! name = "app"
+//! version = "0.1.0"
+//! src = "src"            # where `(import util)` looks; defaults to "."
+//!
+//! [dependencies]
+//! mathlib = { path = "../mathlib" }
+//! strutil = { git = "https://example.com/strutil.git", rev = "v1.2" }
+//! ```
+//!
+//! The lockfile uses the same syntax: one `[dependencies]` table whose
+//! git entries carry the exact commit they resolved to.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub const MANIFEST_FILE: &str = "rusp.toml";
+pub const LOCK_FILE: &str = "rusp.lock";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    /// Module root, relative to the manifest's directory.
+    pub src: PathBuf,
+    /// Keyed by the name `import` uses.
+    pub dependencies: BTreeMap<String, DepSource>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DepSource {
+    /// Relative to the manifest's directory.
+    Path(PathBuf),
+    /// `rev` is any commit-ish `git checkout` accepts; `None` means the
+    /// remote's default branch.
+    Git { url: String, rev: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TomlValue {
+    Str(String),
+    Table(BTreeMap<String, String>),
+}
+
+type Sections = BTreeMap<String, BTreeMap<String, TomlValue>>;
+
+/// Parse `rusp.toml` text. `origin` names the file in error messages.
+pub fn parse_manifest(text: &str, origin: &str) -> Result<Manifest, String> {
+    let mut sections = parse_toml(text, origin)?;
+    let mut package = sections.remove("package").unwrap_or_default();
+    let mut string = |key: &str| match package.remove(key) {
+        Some(TomlValue::Str(s)) => Ok(Some(s)),
+        Some(_) => Err(format!("{}: [package] {} must be a string", origin, key)),
+        None => Ok(None),
+    };
+    let name = string("name")?.ok_or_else(|| format!("{}: [package] is missing `name`", origin))?;
+    let version = string("version")?.unwrap_or_else(|| "0.0.0".to_string());
+    let src = PathBuf::from(string("src")?.unwrap_or_else(|| ".".to_string()));
+    if let Some(key) = package.keys().next() {
+        return Err(format!("{}: unknown [package] key `{}`", origin, key));
+    }
+    let dependencies = parse_dependencies(sections.remove("dependencies").unwrap_or_default(), origin)?;
+    if let Some(section) = sections.keys().next() {
+        return Err(format!("{}: unknown section [{}]", origin, section));
+    }
+    Ok(Manifest { name, version, src, dependencies })
+}
+
+/// Parse `rusp.lock` text into its dependency table.
+pub fn parse_lockfile(text: &str, origin: &str) -> Result<BTreeMap<String, DepSource>, String> {
+    let mut sections = parse_toml(text, origin)?;
+    parse_dependencies(sections.remove("dependencies").unwrap_or_default(), origin)
+}
+
+/// Render a lockfile. Entries come out sorted, so an unchanged
+/// resolution produces a byte-identical file.
+pub fn render_lockfile(deps: &BTreeMap<String, DepSource>) -> String {
+    let mut out = String::from("# Generated by rusp. Do not edit by hand.\n\n[dependencies]\n");
+    for (name, source) in deps {
+        let fields = match source {
+            DepSource::Path(p) => format!("path = {}", quote(&p.to_string_lossy())),
+            DepSource::Git { url, rev: Some(rev) } => format!("git = {}, rev = {}", quote(url), quote(rev)),
+            DepSource::Git { url, rev: None } => format!("git = {}", quote(url)),
+        };
+        out.push_str(&format!("{} = {{ {} }}\n", name, fields));
+    }
+    out
+}
+
+fn parse_dependencies(table: BTreeMap<String, TomlValue>, origin: &str) -> Result<BTreeMap<String, DepSource>, String> {
+    let mut deps = BTreeMap::new();
+    for (name, value) in table {
+        let TomlValue::Table(mut fields) = value else {
+            return Err(format!("{}: dependency `{}` must be a table like {{ path = \"...\" }}", origin, name));
+        };
+        let source = match (fields.remove("path"), fields.remove("git")) {
+            (Some(path), None) => DepSource::Path(PathBuf::from(path)),
+            (None, Some(url)) => DepSource::Git { url, rev: fields.remove("rev") },
+            (Some(_), Some(_)) => {
+                return Err(format!("{}: dependency `{}` has both `path` and `git`", origin, name));
+            }
+            (None, None) => return Err(format!("{}: dependency `{}` needs `path` or `git`", origin, name)),
+        };
+        if let Some(key) = fields.keys().next() {
+            return Err(format!("{}: dependency `{}` has unknown key `{}`", origin, name, key));
+        }
+        deps.insert(name, source);
+    }
+    Ok(deps)
+}
+
+fn parse_toml(text: &str, origin: &str) -> Result<Sections, String> {
+    let mut sections: Sections = BTreeMap::new();
+    let mut current: Option<String> = None;
+    for (i, raw) in text.lines().enumerate() {
+        let err = |msg: &str| format!("{}:{}: {}", origin, i + 1, msg);
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header.strip_suffix(']').ok_or_else(|| err("unterminated section header"))?.trim();
+            if !is_bare_key(name) {
+                return Err(err("invalid section name"));
+            }
+            if sections.contains_key(name) {
+                return Err(err(&format!("duplicate section [{}]", name)));
+            }
+            sections.insert(name.to_string(), BTreeMap::new());
+            current = Some(name.to_string());
+            continue;
+        }
+        let section = current.as_ref().ok_or_else(|| err("key outside of any [section]"))?;
+        let (key, value) = line.split_once('=').ok_or_else(|| err("expected `key = value`"))?;
+        let key = key.trim();
+        if !is_bare_key(key) {
+            return Err(err(&format!("invalid key `{}`", key)));
+        }
+        let value = parse_value(value.trim()).map_err(|e| err(&e))?;
+        let table = sections.get_mut(section).expect("current section exists");
+        if table.insert(key.to_string(), value).is_some() {
+            return Err(err(&format!("duplicate key `{}`", key)));
+        }
+    }
+    Ok(sections)
+}
+
+fn parse_value(text: &str) -> Result<TomlValue, String> {
+    if let Some(inner) = text.strip_prefix('{') {
+        let inner = inner.strip_suffix('}').ok_or("unterminated inline table")?;
+        let mut table = BTreeMap::new();
+        let mut rest = inner.trim();
+        while !rest.is_empty() {
+            let (key, after) = rest.split_once('=').ok_or("expected `key = \"value\"` in inline table")?;
+            let key = key.trim();
+            if !is_bare_key(key) {
+                return Err(format!("invalid key `{}`", key));
+            }
+            let (value, after) = parse_string(after.trim_start())?;
+            if table.insert(key.to_string(), value).is_some() {
+                return Err(format!("duplicate key `{}`", key));
+            }
+            rest = after.trim_start();
+            if let Some(r) = rest.strip_prefix(',') {
+                rest = r.trim_start();
+            } else if !rest.is_empty() {
+                return Err("expected `,` between inline table entries".to_string());
+            }
+        }
+        return Ok(TomlValue::Table(table));
+    }
+    let (s, rest) = parse_string(text)?;
+    if !rest.trim().is_empty() {
+        return Err(format!("unexpected text after value: `{}`", rest.trim()));
+    }
+    Ok(TomlValue::Str(s))
+}
+
+/// A basic `"..."` string with `\"`, `\\`, `\n` and `\t` escapes.
+/// Returns the string and the text after the closing quote.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let body = text.strip_prefix('"').ok_or("expected a \"quoted string\"")?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return Ok((out, &body[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '"')) => out.push('"'),
+                Some((_, '\\')) => out.push('\\'),
+                Some((_, 'n')) => out.push('\n'),
+                Some((_, 't')) => out.push('\t'),
+                _ => return Err("unsupported escape in string".to_string()),
+            },
+            c => out.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Drop a trailing `# comment`, ignoring `#` inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_bare_key(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
@@ -0,0 +1,121 @@
+//! Dense f64 matrices for quick numeric experiments.
+//!
+//! Elements are stored row-major in one `Vec<f64>`, so `mat-mul` and the
+//! elementwise ops run as plain loops over slices instead of going
+//! through the evaluator one number at a time.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    /// A `rows` by `cols` matrix with every element `init`, or an error
+    /// naming the builtin `name` if there isn't memory for one that big.
+    pub fn filled(name: &str, rows: usize, cols: usize, init: f64) -> Result<Matrix, String> {
+        let too_big = || format!("{}: a {}x{} matrix is too large", name, rows, cols);
+        let len = rows.checked_mul(cols).ok_or_else(too_big)?;
+        let mut data = Vec::new();
+        data.try_reserve_exact(len).map_err(|_| too_big())?;
+        data.resize(len, init);
+        Ok(Matrix { rows, cols, data })
+    }
+
+    /// A matrix from its rows, which all have to be as long.
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Result<Matrix, String> {
+        let cols = rows.first().map_or(0, Vec::len);
+        if let Some(row) = rows.iter().find(|row| row.len() != cols) {
+            return Err(format!("list->mat: rows of {} and {} elements", cols, row.len()));
+        }
+        Ok(Matrix { rows: rows.len(), cols, data: rows.concat() })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.cols + col]
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, value: f64) {
+        self.data[row * self.cols + col] = value;
+    }
+
+    /// Each row as a `Vec`.
+    pub fn to_rows(&self) -> Vec<Vec<f64>> {
+        if self.cols == 0 {
+            return vec![Vec::new(); self.rows];
+        }
+        self.data.chunks(self.cols).map(<[f64]>::to_vec).collect()
+    }
+
+    /// Checks `(row, col)` is inside the matrix, naming the builtin in the
+    /// error.
+    pub fn check_index(&self, name: &str, row: i32, col: i32) -> Result<(usize, usize), String> {
+        if row < 0 || col < 0 || row as usize >= self.rows || col as usize >= self.cols {
+            return Err(format!(
+                "{}: index ({}, {}) out of bounds for a {}x{} matrix",
+                name, row, col, self.rows, self.cols
+            ));
+        }
+        Ok((row as usize, col as usize))
+    }
+
+    /// The matrix product `self * other`.
+    pub fn mul(&self, other: &Matrix) -> Result<Matrix, String> {
+        if self.cols != other.rows {
+            return Err(format!(
+                "mat-mul: can't multiply a {}x{} matrix by a {}x{} one",
+                self.rows, self.cols, other.rows, other.cols
+            ));
+        }
+        let mut out = Matrix::filled("mat-mul", self.rows, other.cols, 0.0)?;
+        // i-k-j order walks both `other` and `out` along their rows.
+        for i in 0..self.rows {
+            for k in 0..self.cols {
+                let a = self.get(i, k);
+                let row = &other.data[k * other.cols..(k + 1) * other.cols];
+                let out_row = &mut out.data[i * other.cols..(i + 1) * other.cols];
+                for (o, b) in out_row.iter_mut().zip(row) {
+                    *o += a * b;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// `op` applied to each pair of elements of two matrices of the same
+    /// shape.
+    pub fn zip(&self, other: &Matrix, name: &str, op: fn(f64, f64) -> f64) -> Result<Matrix, String> {
+        if (self.rows, self.cols) != (other.rows, other.cols) {
+            return Err(format!(
+                "{}: shapes {}x{} and {}x{} differ",
+                name, self.rows, self.cols, other.rows, other.cols
+            ));
+        }
+        let data = self.data.iter().zip(&other.data).map(|(a, b)| op(*a, *b)).collect();
+        Ok(Matrix { rows: self.rows, cols: self.cols, data })
+    }
+
+    /// Every element times `k`.
+    pub fn scale(&self, k: f64) -> Matrix {
+        Matrix { rows: self.rows, cols: self.cols, data: self.data.iter().map(|x| x * k).collect() }
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut out = Matrix { rows: self.cols, cols: self.rows, data: vec![0.0; self.data.len()] };
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                out.set(j, i, self.get(i, j));
+            }
+        }
+        out
+    }
+}
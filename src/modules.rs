@@ -0,0 +1,772 @@
+//! Module loading: `(import name)` and project dependencies.
+//!
+//! A file is expanded into a flat list of top-level forms before anything
+//! is type-checked: each `(import name)` is replaced by the forms of the
+//! module it names, so the type checker, interpreter and AOT backend all
+//! see one ordinary program. Every module is included once per `Loader`
+//! (later imports of it are no-ops), and import cycles are an error.
+//!
+//! Resolution, relative to the package the importing file belongs to:
+//!
+//! - `(import util)` → `<src>/util.rusp`, `(import geo/vec)` →
+//!   `<src>/geo/vec.rusp`.
+//! - If the first segment names a dependency in `rusp.toml`, the module
+//!   comes from that dependency instead: `(import mathlib)` is its
+//!   `<src>/lib.rusp`, `(import mathlib/trig)` its `<src>/trig.rusp`.
+//!
+//! Definitions in an imported module are namespaced: `(defn sqrt ...)` in
+//! module `math` is bound as `math/sqrt`, and callers refer to it by that
+//! name (`(import math :as m)` lets them write `m/sqrt`). Inside the
+//! module itself the short name still works. The file being run or loaded
+//! keeps its own names unqualified. Definitions made with `defn-` are
+//! private: they are qualified like any other, but referring to one from
+//! outside its module is an error. Modules of a dependency are qualified
+//! by the dependency's name (`mathlib`, `mathlib/trig`) however they were
+//! reached, so two libraries' `util` modules don't collide.
+//!
+//! `(embed-file "path")` is replaced by the text of that file, read
+//! relative to the file the form is in, as the file is loaded.
+//!
+//! A file's package is found by walking up from its directory to the
+//! nearest `rusp.toml`; without one, imports resolve next to the file and
+//! there are no dependencies. Git dependencies are cloned into
+//! `.rusp/deps/<name>` under the package root, and the commit each one
+//! resolved to is recorded in `rusp.lock`. A locked commit is reused
+//! until the manifest names a different `rev`; delete the lockfile entry
+//! (or the whole file) to move a floating dependency forward.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Pattern};
+use crate::codegen::srcmap::{SourceLoc, line_col};
+use crate::keyword::Keyword;
+use crate::lint::{self, Lint};
+use crate::manifest::{self, DepSource};
+use crate::parser;
+
+/// What the loader's errors say when the program itself is wrong (an
+/// import cycle, a private name, a constant defined twice) rather than
+/// a file missing or unreadable, for the driver to report them as it
+/// does type errors.
+pub const PROGRAM_ERRORS: &[&str] = &[
+    "import expects a module name",
+    "import cycle: ",
+    "` is used by both ",
+    "` already names module ",
+    "cannot redefine constant `",
+    "` is not imported here",
+    "` has no definition `",
+    "` is private to module `",
+];
+
+/// A top-level form and where it came from.
+#[derive(Debug, Clone)]
+pub struct SourceForm {
+    pub form: Expr,
+    pub loc: SourceLoc,
+    /// The source from the form's start up to the next form's.
+    pub text: String,
+}
+
+impl SourceForm {
+    /// Where `lint`, raised on this form, is: at its site, if it has one
+    /// that can be found, else at the form.
+    pub fn loc_of(&self, lint: &Lint) -> SourceLoc {
+        let Some(offset) = lint.site.and_then(|site| lint::locate(&self.form, &self.text, site)) else {
+            return self.loc.clone();
+        };
+        let (line, col) = line_col(&self.text, offset);
+        let col = if line == 1 { self.loc.col + col - 1 } else { col };
+        SourceLoc { file: self.loc.file.clone(), line: self.loc.line + line - 1, col }
+    }
+}
+
+/// Parse every top-level form in `source`, with the byte offset each one
+/// starts at. The single-form `parser::parse` rejects trailing input, so
+/// we drive `parse_expr` in a loop; `rest` always points into `source`,
+/// so its offset is where the next form starts. Reader macros the file
+/// sets don't outlive it.
+pub fn parse_forms(source: &str) -> Result<(Vec<Expr>, Vec<usize>), String> {
+    parser::with_reader_scope(|| read_forms(source))
+}
+
+fn read_forms(source: &str) -> Result<(Vec<Expr>, Vec<usize>), String> {
+    let mut forms: Vec<Expr> = Vec::new();
+    let mut offsets: Vec<usize> = Vec::new();
+    let error = |e| format!("parse error: {}", parser::error::ParseError::from(e));
+    let skip_space = |rest| parser::ws0(rest).map(|(rest, _)| rest).map_err(error);
+    let mut rest = skip_space(source)?;
+    while !rest.is_empty() {
+        offsets.push(rest.as_ptr() as usize - source.as_ptr() as usize);
+        let (remaining, expr) = parser::expr::parse_expr(rest).map_err(error)?;
+        forms.push(expr);
+        rest = skip_space(remaining)?;
+    }
+    Ok((forms, offsets))
+}
+
+/// An `(import name)` or `(import name :as alias)` form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import<'a> {
+    pub name: &'a str,
+    pub alias: Option<&'a str>,
+}
+
+/// The import described by `form`, if it is one.
+pub fn import_target(form: &Expr) -> Result<Option<Import<'_>>, String> {
+    let Expr::List(items) = form else { return Ok(None) };
+    match items.as_slice() {
+        [Expr::Symbol(head), rest @ ..] if head == "import" => match rest {
+            [Expr::Symbol(name)] => Ok(Some(Import { name, alias: None })),
+            [Expr::Symbol(name), Expr::Keyword(kw), Expr::Symbol(alias)] if *kw == Keyword::intern("as") => {
+                Ok(Some(Import { name, alias: Some(alias) }))
+            }
+            _ => Err("import expects a module name: (import name) or (import name :as alias)".to_string()),
+        },
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug)]
+struct Package {
+    src_dir: PathBuf,
+    /// Dependency name → that dependency's root directory.
+    deps: BTreeMap<String, PathBuf>,
+    /// For a dependency, the name its modules are qualified with.
+    prefix: Option<String>,
+}
+
+#[derive(Debug)]
+struct Module {
+    /// Short names of its top-level definitions → the names they're
+    /// bound under (`mathlib/trig/sin`).
+    defs: HashMap<String, String>,
+    /// The subset declared with `defn-`.
+    private: HashSet<String>,
+}
+
+/// Expands files and their imports; remembers what's been included.
+#[derive(Default)]
+pub struct Loader {
+    /// Keyed by root directory (or, for files outside any project, the
+    /// file's own directory).
+    packages: HashMap<PathBuf, Rc<Package>>,
+    /// Every module included so far, by file.
+    modules: HashMap<PathBuf, Module>,
+    /// Qualifier → the file that owns it.
+    names: HashMap<String, PathBuf>,
+    /// Modules currently being expanded, for cycle detection.
+    stack: Vec<PathBuf>,
+    /// Files read by the last `load`, in the order they were read,
+    /// `embed-file`s included.
+    files: Vec<PathBuf>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The files the last `load` read: the file itself, then each
+    /// import it included (not the ones this loader already had) and
+    /// each file embedded with `embed-file`.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Expand `path` and everything it imports. The file itself is
+    /// always re-read (so `:reload` works) and keeps its names
+    /// unqualified; its imports are included only if this loader hasn't
+    /// included them already.
+    pub fn load(&mut self, path: &str) -> Result<Vec<SourceForm>, String> {
+        let canonical = std::fs::canonicalize(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        self.stack.clear();
+        self.files.clear();
+        let mut out = Vec::new();
+        self.expand(&canonical, path, None, &mut out)?;
+        Ok(out)
+    }
+
+    /// Append the forms of `file` to `out`, qualifying its definitions
+    /// with `module` when it's being imported.
+    fn expand(&mut self, file: &Path, display: &str, module: Option<&str>, out: &mut Vec<SourceForm>) -> Result<(), String> {
+        let source = std::fs::read_to_string(file).map_err(|e| format!("could not read {}: {}", display, e))?;
+        self.files.push(file.to_path_buf());
+        let (forms, offsets) = parse_forms(&source).map_err(|e| format!("{}: {}", display, e))?;
+        let dir = file.parent().unwrap_or(Path::new("."));
+        let package = self.package_for(dir)?;
+        let ends = offsets.iter().skip(1).copied().chain(std::iter::once(source.len()));
+        let locs: Vec<(SourceLoc, String)> = offsets
+            .iter()
+            .zip(ends)
+            .map(|(&offset, end)| {
+                let (line, col) = line_col(&source, offset);
+                (SourceLoc { file: display.to_string(), line, col }, source[offset..end].to_string())
+            })
+            .collect();
+
+        // Short name → bound name for this file's own definitions.
+        let mut own: HashMap<String, String> = HashMap::new();
+        if let Some(module) = module {
+            // Type names stay global: annotations have no way to qualify
+            // them. So does a `defstruct`'s constructor, which is one.
+            let defs: HashMap<String, String> = forms
+                .iter()
+                .flat_map(|form| defined_names(form).into_iter().map(move |d| (form, d)))
+                .map(|(form, d)| match form {
+                    Expr::Deftype { name, .. } | Expr::Defstruct { name, .. } if name == d => {
+                        (d.to_string(), d.to_string())
+                    }
+                    _ => (d.to_string(), format!("{}/{}", module, d)),
+                })
+                .collect();
+            let private = forms
+                .iter()
+                .filter_map(|f| match f {
+                    Expr::Defn { name, private: true, .. } => Some(name.clone()),
+                    Expr::MultiArity(defns) => match defns.first() {
+                        Some(Expr::Defn { name, private: true, .. }) => Some(name.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect();
+            own = defs.clone();
+            self.modules.insert(file.to_path_buf(), Module { defs, private });
+        }
+        self.stack.push(file.to_path_buf());
+
+        // Qualifier as written in this file → the module it refers to.
+        let mut aliases: HashMap<String, PathBuf> = HashMap::new();
+        let mut body = Vec::new();
+        for (form, (loc, text)) in forms.into_iter().zip(locs) {
+            let Some(import) = import_target(&form).map_err(|e| format!("{}: {}", loc, e))? else {
+                body.push(SourceForm { form, loc, text });
+                continue;
+            };
+            let (target, name) = self.resolve(&package, import.name).map_err(|e| format!("{}: {}", loc, e))?;
+            if let Some(pos) = self.stack.iter().position(|p| *p == target) {
+                let chain: Vec<String> = self.stack[pos..]
+                    .iter()
+                    .chain(std::iter::once(&target))
+                    .map(|p| display_path(p))
+                    .collect();
+                return Err(format!("{}: import cycle: {}", loc, chain.join(" -> ")));
+            }
+            if !self.modules.contains_key(&target) {
+                if let Some(other) = self.names.get(&name).filter(|p| **p != target) {
+                    return Err(format!(
+                        "{}: module name `{}` is used by both {} and {}",
+                        loc,
+                        name,
+                        display_path(other),
+                        display_path(&target)
+                    ));
+                }
+                self.names.insert(name.clone(), target.clone());
+                self.expand(&target, &display_path(&target), Some(&name), out)?;
+            }
+            for prefix in std::iter::once(import.name).chain(import.alias) {
+                if let Some(prev) = aliases.insert(prefix.to_string(), target.clone())
+                    && prev != target
+                {
+                    return Err(format!("{}: `{}` already names module {}", loc, prefix, display_path(&prev)));
+                }
+            }
+        }
+        self.stack.pop();
+
+        check_constants(&body)?;
+        let names = Names {
+            module,
+            own: &own,
+            aliases: &aliases,
+            modules: &self.modules,
+            qualifiers: &self.names,
+            dir,
+            embedded: RefCell::new(Vec::new()),
+        };
+        for mut sf in body {
+            if module.is_some() {
+                qualify_definition(&mut sf.form, &own);
+            }
+            names.rewrite(&mut sf.form, &mut Vec::new()).map_err(|e| format!("{}: {}", sf.loc, e))?;
+            out.push(sf);
+        }
+        let embedded = names.embedded.into_inner();
+        self.files.extend(embedded);
+        Ok(())
+    }
+
+    /// File for module `name`, imported from `package`, and the name it
+    /// is qualified with.
+    fn resolve(&mut self, package: &Package, name: &str) -> Result<(PathBuf, String), String> {
+        let (first, rest) = match name.split_once('/') {
+            Some((f, r)) => (f, Some(r)),
+            None => (name, None),
+        };
+        let (src_dir, module, qualified) = match package.deps.get(first) {
+            Some(dep_root) => {
+                let dep = self.dep_package(dep_root, first)?;
+                (dep.src_dir.clone(), rest.unwrap_or("lib"), qualify_in(&dep, rest))
+            }
+            None if name == "lib" && package.prefix.is_some() => {
+                (package.src_dir.clone(), name, qualify_in(package, None))
+            }
+            None => (package.src_dir.clone(), name, qualify_in(package, Some(name))),
+        };
+        let file = src_dir.join(format!("{}.rusp", module));
+        let file = std::fs::canonicalize(&file)
+            .map_err(|_| format!("cannot find module `{}` (looked for {})", name, file.display()))?;
+        Ok((file, qualified))
+    }
+
+    /// A dependency's own package. Unlike `package_for` this doesn't look
+    /// above `root`: a dependency without a manifest is a bare directory
+    /// of modules, not part of whatever project happens to enclose it.
+    fn dep_package(&mut self, root: &Path, name: &str) -> Result<Rc<Package>, String> {
+        if let Some(p) = self.packages.get(root) {
+            return Ok(p.clone());
+        }
+        let mut package = if root.join(manifest::MANIFEST_FILE).is_file() {
+            open_package(root)?
+        } else {
+            Package { src_dir: root.to_path_buf(), deps: BTreeMap::new(), prefix: None }
+        };
+        package.prefix = Some(name.to_string());
+        let package = Rc::new(package);
+        self.packages.insert(root.to_path_buf(), package.clone());
+        Ok(package)
+    }
+
+    /// The package containing `dir`, discovering and resolving its
+    /// manifest the first time.
+    fn package_for(&mut self, dir: &Path) -> Result<Rc<Package>, String> {
+        let root = dir
+            .ancestors()
+            .find(|d| d.join(manifest::MANIFEST_FILE).is_file())
+            .map(Path::to_path_buf);
+        let key = root.clone().unwrap_or_else(|| dir.to_path_buf());
+        if let Some(p) = self.packages.get(&key) {
+            return Ok(p.clone());
+        }
+        let package = Rc::new(match root {
+            Some(root) => open_package(&root)?,
+            None => Package { src_dir: dir.to_path_buf(), deps: BTreeMap::new(), prefix: None },
+        });
+        self.packages.insert(key, package.clone());
+        Ok(package)
+    }
+}
+
+fn open_package(root: &Path) -> Result<Package, String> {
+    let manifest_path = root.join(manifest::MANIFEST_FILE);
+    let origin = display_path(&manifest_path);
+    let text = std::fs::read_to_string(&manifest_path).map_err(|e| format!("could not read {}: {}", origin, e))?;
+    let manifest = manifest::parse_manifest(&text, &origin)?;
+
+    let lock_path = root.join(manifest::LOCK_FILE);
+    let old_lock = match std::fs::read_to_string(&lock_path) {
+        Ok(text) => manifest::parse_lockfile(&text, &display_path(&lock_path))?,
+        Err(_) => BTreeMap::new(),
+    };
+    let mut lock = BTreeMap::new();
+    let mut deps = BTreeMap::new();
+    for (name, source) in &manifest.dependencies {
+        let dep_root = match source {
+            DepSource::Path(p) => {
+                lock.insert(name.clone(), source.clone());
+                root.join(p)
+            }
+            DepSource::Git { url, rev } => {
+                let locked = match old_lock.get(name) {
+                    Some(DepSource::Git { url: locked_url, rev: Some(commit) }) if locked_url == url => {
+                        Some(commit.as_str())
+                    }
+                    _ => None,
+                };
+                let dest = root.join(".rusp").join("deps").join(name);
+                let commit = fetch_git(name, url, rev.as_deref(), locked, &dest)?;
+                lock.insert(name.clone(), DepSource::Git { url: url.clone(), rev: Some(commit) });
+                dest
+            }
+        };
+        let dep_root = std::fs::canonicalize(&dep_root)
+            .map_err(|_| format!("{}: dependency `{}` not found at {}", origin, name, dep_root.display()))?;
+        deps.insert(name.clone(), dep_root);
+    }
+    // Projects without dependencies don't get a lockfile at all.
+    let wanted = !lock.is_empty() || lock_path.exists();
+    if wanted && lock != old_lock {
+        std::fs::write(&lock_path, manifest::render_lockfile(&lock))
+            .map_err(|e| format!("could not write {}: {}", lock_path.display(), e))?;
+    }
+
+    Ok(Package { src_dir: root.join(&manifest.src), deps, prefix: None })
+}
+
+/// The qualifier for `module` (`None` for a dependency's `lib`) of
+/// `package`.
+fn qualify_in(package: &Package, module: Option<&str>) -> String {
+    match (&package.prefix, module) {
+        (Some(prefix), Some(m)) => format!("{}/{}", prefix, m),
+        (Some(prefix), None) => prefix.clone(),
+        (None, m) => m.unwrap_or("lib").to_string(),
+    }
+}
+
+/// The globals a top-level form defines: its name, and for a `deftype`
+/// its constructors too. A `defstruct`'s constructor is its name.
+pub fn defined_names(form: &Expr) -> Vec<&str> {
+    match form {
+        Expr::Defn { name, .. } | Expr::Defgen { name, .. } | Expr::Def { name, .. } | Expr::Defparam { name, .. } => {
+            vec![name]
+        }
+        Expr::MultiArity(defns) => defns.first().map(defined_names).unwrap_or_default(),
+        Expr::Deftype { name, variants } => {
+            std::iter::once(name.as_str()).chain(variants.iter().map(|(tag, _)| tag.as_str())).collect()
+        }
+        Expr::Defstruct { name, .. } => vec![name],
+        _ => Vec::new(),
+    }
+}
+
+/// Reject a top-level definition of a name an earlier `defconst` in the
+/// same file already took, pointing at both.
+fn check_constants(body: &[SourceForm]) -> Result<(), String> {
+    let mut constants: HashMap<&str, &SourceLoc> = HashMap::new();
+    for sf in body {
+        for name in defined_names(&sf.form) {
+            if let Some(original) = constants.get(name) {
+                return Err(format!("{}: cannot redefine constant `{}` (defined at {})", sf.loc, name, original));
+            }
+            if let Expr::Def { constant: true, .. } = sf.form {
+                constants.insert(name, &sf.loc);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn qualify_definition(form: &mut Expr, own: &HashMap<String, String>) {
+    if let Expr::MultiArity(defns) = form {
+        defns.iter_mut().for_each(|d| qualify_definition(d, own));
+        return;
+    }
+    if let Expr::Deftype { variants, .. } = form {
+        for (tag, _) in variants {
+            if let Some(q) = own.get(tag.as_str()) {
+                *tag = q.clone();
+            }
+        }
+        return;
+    }
+    if let Expr::Defn { name, .. }
+    | Expr::Defgen { name, .. }
+    | Expr::Def { name, .. }
+    | Expr::Defparam { name, .. } = form
+        && let Some(q) = own.get(name.as_str())
+    {
+        *name = q.clone();
+    }
+}
+
+/// How the symbols of one file map to bound names.
+struct Names<'a> {
+    /// The file's own qualifier, when it's being imported.
+    module: Option<&'a str>,
+    own: &'a HashMap<String, String>,
+    aliases: &'a HashMap<String, PathBuf>,
+    modules: &'a HashMap<PathBuf, Module>,
+    /// Every module qualifier in use, imported here or not.
+    qualifiers: &'a HashMap<String, PathBuf>,
+    /// The file's directory, which `embed-file` paths are relative to.
+    dir: &'a Path,
+    /// The files `embed-file` has read so far.
+    embedded: RefCell<Vec<PathBuf>>,
+}
+
+impl Names<'_> {
+    /// Rewrite references in `expr`: short names of the file's own
+    /// definitions (unless shadowed by a local) and `alias/name`
+    /// references to imported modules. `(embed-file "path")` becomes the
+    /// text of that file.
+    fn rewrite(&self, expr: &mut Expr, locals: &mut Vec<String>) -> Result<(), String> {
+        match expr {
+            Expr::Symbol(s) | Expr::SetReaderMacro { handler: s, .. } => {
+                if let Some(bound) = self.resolve_symbol(s, locals)? {
+                    *s = bound;
+                }
+            }
+            Expr::List(items)
+                if matches!(items.first(), Some(Expr::Symbol(head)) if head == "embed-file")
+                    && !locals.iter().any(|l| l == "embed-file") =>
+            {
+                let [_, Expr::String(path)] = &items[..] else {
+                    return Err("embed-file takes one literal path: (embed-file \"file\")".to_string());
+                };
+                let file = self.dir.join(path);
+                let text = std::fs::read_to_string(&file)
+                    .map_err(|e| format!("embed-file: could not read {}: {}", display_path(&file), e))?;
+                self.embedded.borrow_mut().push(file);
+                *expr = Expr::String(text);
+            }
+            Expr::List(items) => {
+                for item in items {
+                    self.rewrite(item, locals)?;
+                }
+            }
+            Expr::If { condition, then_branch, else_branch } => {
+                self.rewrite(condition, locals)?;
+                self.rewrite(then_branch, locals)?;
+                self.rewrite(else_branch, locals)?;
+            }
+            Expr::Let { name, value, body, .. } => {
+                self.rewrite(value, locals)?;
+                self.scoped(locals, [name.clone()], |names, locals| names.rewrite(body, locals))?;
+            }
+            Expr::Def { value, .. } => self.rewrite(value, locals)?,
+            Expr::Defn { name, params, body, .. } | Expr::Defgen { name, params, body, .. } => {
+                let bound = std::iter::once(name.clone()).chain(params.iter().map(|(p, _)| p.clone()));
+                self.scoped(locals, bound, |names, locals| names.rewrite(body, locals))?;
+            }
+            Expr::Lambda { params, body, .. } => {
+                let bound = params.iter().map(|(p, _)| p.clone());
+                self.scoped(locals, bound, |names, locals| names.rewrite(body, locals))?;
+            }
+            Expr::Shift { name, body } => {
+                self.scoped(locals, [name.clone()], |names, locals| names.rewrite(body, locals))?;
+            }
+            Expr::Loop { bindings, body } => {
+                // Each binding sees the ones before it, like nested `let`s.
+                let depth = locals.len();
+                let result = bindings.iter_mut().try_for_each(|(name, _, value)| {
+                    self.rewrite(value, locals)?;
+                    locals.push(name.clone());
+                    Ok(())
+                });
+                let result = result.and_then(|()| self.rewrite(body, locals));
+                locals.truncate(depth);
+                result?;
+            }
+            Expr::Recur(args) | Expr::Do(args) | Expr::MultiArity(args) => {
+                for arg in args {
+                    self.rewrite(arg, locals)?;
+                }
+            }
+            Expr::Yield(value)
+            | Expr::Return(value)
+            | Expr::The { expr: value, .. }
+            | Expr::Block { body: value, .. }
+            | Expr::ReturnFrom { value, .. }
+            | Expr::Reset(value)
+            | Expr::Defer(value)
+            | Expr::Defparam { value, .. } => self.rewrite(value, locals)?,
+            Expr::WithOpen { bindings, body } => {
+                // Like `loop`: each binding sees the ones before it.
+                let depth = locals.len();
+                let result = bindings.iter_mut().try_for_each(|(name, value)| {
+                    self.rewrite(value, locals)?;
+                    locals.push(name.clone());
+                    Ok(())
+                });
+                let result = result.and_then(|()| self.rewrite(body, locals));
+                locals.truncate(depth);
+                result?;
+            }
+            Expr::Parameterize { bindings, body } => {
+                // The names are references to the parameters, not new locals.
+                for (name, value) in bindings {
+                    if let Some(bound) = self.resolve_symbol(name, locals)? {
+                        *name = bound;
+                    }
+                    self.rewrite(value, locals)?;
+                }
+                self.rewrite(body, locals)?;
+            }
+            Expr::Call { func, args } => {
+                self.rewrite(func, locals)?;
+                for arg in args {
+                    self.rewrite(arg, locals)?;
+                }
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.rewrite(scrutinee, locals)?;
+                for (pattern, body) in arms {
+                    let mut bound = Vec::new();
+                    pattern_names(pattern, &mut bound);
+                    self.scoped(locals, bound, |names, locals| {
+                        names.rewrite_pattern(pattern, locals)?;
+                        names.rewrite(body, locals)
+                    })?;
+                }
+            }
+            Expr::Integer32(_)
+            | Expr::Integer64(_)
+            | Expr::Float(_)
+            | Expr::Bool(_)
+            | Expr::String(_)
+            | Expr::Bytes(_)
+            | Expr::Keyword(_)
+            | Expr::Deftype { .. }
+            | Expr::Defstruct { .. }
+            | Expr::Nil => {}
+        }
+        Ok(())
+    }
+
+    /// Rewrite the constructors and guards of `pattern`.
+    fn rewrite_pattern(&self, pattern: &mut Pattern, locals: &mut Vec<String>) -> Result<(), String> {
+        match pattern {
+            Pattern::Guard(inner, guard) => {
+                self.rewrite_pattern(inner, locals)?;
+                self.rewrite(guard, locals)
+            }
+            Pattern::Cons(head, tail) => {
+                self.rewrite_pattern(head, locals)?;
+                self.rewrite_pattern(tail, locals)
+            }
+            Pattern::As(inner, _) => self.rewrite_pattern(inner, locals),
+            Pattern::Or(branches) => branches.iter_mut().try_for_each(|b| self.rewrite_pattern(b, locals)),
+            Pattern::Constructor(tag, fields) => {
+                // Constructors can't be shadowed by pattern variables.
+                if let Some(bound) = self.resolve_symbol(tag, &[])? {
+                    *tag = bound;
+                }
+                fields.iter_mut().try_for_each(|f| self.rewrite_pattern(f, locals))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn scoped(
+        &self,
+        locals: &mut Vec<String>,
+        bound: impl IntoIterator<Item = String>,
+        f: impl FnOnce(&Self, &mut Vec<String>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let depth = locals.len();
+        locals.extend(bound);
+        let result = f(self, locals);
+        locals.truncate(depth);
+        result
+    }
+
+    /// The name `s` is bound under, if it needs rewriting.
+    fn resolve_symbol(&self, s: &str, locals: &[String]) -> Result<Option<String>, String> {
+        if locals.iter().any(|l| l == s) {
+            return Ok(None);
+        }
+        if let Some(bound) = self.own.get(s) {
+            return Ok(Some(bound.clone()));
+        }
+        // Longest qualifier first, so `geo/vec/len` prefers `geo/vec`
+        // over a module imported as `geo`.
+        let mut split = s.len();
+        while let Some(i) = s[..split].rfind('/') {
+            let (prefix, short) = (&s[..i], &s[i + 1..]);
+            split = i;
+            let Some(module) = self.aliases.get(prefix).map(|file| &self.modules[file]) else {
+                // Already-qualified names would otherwise reach around
+                // both the import and `defn-`.
+                if self.module == Some(prefix) {
+                    return Ok(None);
+                }
+                if self.qualifiers.contains_key(prefix) {
+                    return Err(format!("module `{}` is not imported here", prefix));
+                }
+                continue;
+            };
+            let Some(bound) = module.defs.get(short) else {
+                return Err(format!("module `{}` has no definition `{}`", prefix, short));
+            };
+            if module.private.contains(short) {
+                return Err(format!("`{}` is private to module `{}`", short, prefix));
+            }
+            return Ok(Some(bound.clone()));
+        }
+        Ok(None)
+    }
+}
+
+fn pattern_names(pattern: &Pattern, out: &mut Vec<String>) {
+    match pattern {
+        Pattern::Variable(name) => out.push(name.clone()),
+        Pattern::Cons(head, tail) => {
+            pattern_names(head, out);
+            pattern_names(tail, out);
+        }
+        Pattern::As(inner, name) => {
+            pattern_names(inner, out);
+            out.push(name.clone());
+        }
+        Pattern::Guard(inner, _) => pattern_names(inner, out),
+        Pattern::Or(branches) | Pattern::Constructor(_, branches) => {
+            branches.iter().for_each(|b| pattern_names(b, out))
+        }
+        _ => {}
+    }
+}
+
+/// Make `dest` a checkout of `url` at the requested commit and return
+/// the full commit hash. An explicit `rev` wins over the lockfile; the
+/// locked commit pins dependencies that follow a branch.
+fn fetch_git(name: &str, url: &str, rev: Option<&str>, locked: Option<&str>, dest: &Path) -> Result<String, String> {
+    let git = |args: &[&str], cwd: Option<&Path>| -> Result<String, String> {
+        let mut cmd = Command::new("git");
+        if let Some(dir) = cwd {
+            cmd.arg("-C").arg(dir);
+        }
+        let output = cmd
+            .args(args)
+            .output()
+            .map_err(|e| format!("dependency `{}`: could not run git: {}", name, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "dependency `{}`: git {} failed: {}",
+                name,
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    if !dest.join(".git").exists() {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("dependency `{}`: {}", name, e))?;
+        }
+        let dest_str = dest.to_string_lossy();
+        git(&["clone", "--quiet", url, &dest_str], None)?;
+    }
+    let Some(target) = rev.or(locked) else {
+        return git(&["rev-parse", "HEAD"], Some(dest));
+    };
+    let wanted = format!("{}^{{commit}}", target);
+    let commit = match git(&["rev-parse", "--verify", "--quiet", &wanted], Some(dest)) {
+        Ok(c) => c,
+        Err(_) => {
+            // Not in the existing clone yet: fetch and look again.
+            git(&["fetch", "--quiet", "--tags", "origin"], Some(dest))?;
+            git(&["rev-parse", "--verify", "--quiet", &wanted], Some(dest))
+                .map_err(|_| format!("dependency `{}`: unknown revision `{}`", name, target))?
+        }
+    };
+    if git(&["rev-parse", "HEAD"], Some(dest))? != commit {
+        git(&["checkout", "--quiet", "--detach", &commit], Some(dest))?;
+    }
+    Ok(commit)
+}
+
+/// `p` relative to the working directory when it's under it.
+fn display_path(p: &Path) -> String {
+    let cwd = std::env::current_dir().ok().and_then(|d| std::fs::canonicalize(d).ok());
+    match cwd.as_deref().and_then(|d| p.strip_prefix(d).ok()) {
+        Some(rel) => rel.display().to_string(),
+        None => p.display().to_string(),
+    }
+}
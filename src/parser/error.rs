@@ -6,6 +6,8 @@ pub enum ParseError {
     UnexpectedInput(String),
     UnexpectedEof,
     InvalidNumber(String),
+    /// Forms nested past the depth limit (see `parser::set_max_depth`).
+    TooDeep(usize),
     NomError(String),
 }
 
@@ -15,6 +17,7 @@ impl fmt::Display for ParseError {
             ParseError::UnexpectedInput(s) => write!(f, "Unexpected input: {}", s),
             ParseError::UnexpectedEof => write!(f, "Unexpected end of input"),
             ParseError::InvalidNumber(s) => write!(f, "Invalid number: {}", s),
+            ParseError::TooDeep(limit) => write!(f, "Input nests more than {} forms deep", limit),
             ParseError::NomError(s) => write!(f, "Parse error: {}", s),
         }
     }
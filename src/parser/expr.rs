@@ -1,33 +1,183 @@
-use crate::ast::{Expr, Type};
+use crate::ast::{Expr, Meta, Type};
+use crate::keyword::Keyword;
 use crate::parser::types::parse_type_annotation;
+use crate::parser::{Nesting, backend, is_space, reader_macro, ws0, ws1};
 use nom::{
     branch::alt,
-    bytes::complete::{escaped, tag, take_while1},
-    character::complete::{char, digit1, multispace0, multispace1, none_of},
+    bytes::complete::{escaped_transform, tag, take_while},
+    character::complete::{char, digit1, none_of, satisfy},
     combinator::{map, opt, recognize, value},
-    multi::many0,
-    sequence::{delimited, preceded, tuple},
+    multi::{many0, many1},
+    sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
+use std::cell::Cell;
+use unicode_ident::{is_xid_continue, is_xid_start};
 
+/// Dispatches on the first character: a list, a registered reader
+/// macro, or an atom.
 pub fn parse_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
-    alt((
-        parse_list,
-        parse_atom,
-    ))(input)
+    let _nesting = Nesting::enter()?;
+    let (input, _) = ws0(input)?;
+    match input.chars().next() {
+        Some('(') => parse_list(input),
+        Some('#') if input.starts_with("#(") => parse_short_lambda(&input[1..]),
+        Some('#') if input.starts_with("#[") => parse_bracketed(&input[2..], "array", "#["),
+        // Parameter lists are read by their forms' own parsers, so a `[`
+        // that gets here starts a vector.
+        Some('[') => parse_bracketed(&input[1..], "vector", "["),
+        Some('{') => parse_map_literal(&input[1..]),
+        // `ws0` above has skipped it if it has no branch for us.
+        Some('#') if input.starts_with("#?(") => match parse_conditional(&input[2..])? {
+            (input, Some(expr)) => Ok((input, expr)),
+            (_, None) => Err(nom::Err::Error(crate::parser::error::ParseError::UnexpectedInput(input.to_string()))),
+        },
+        Some(c) => match reader_macro(c) {
+            Some(handler) => parse_reader_macro(&input[c.len_utf8()..], c, handler),
+            None => parse_atom(input),
+        },
+        None => parse_atom(input),
+    }
+}
+
+/// What follows a reader macro character: a string literal, or the
+/// characters up to the next space or bracket. Either way it's handed to
+/// the handler as a string.
+fn parse_reader_macro(input: &str, c: char, handler: String) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, text) = if input.starts_with('"') {
+        parse_string(input)?
+    } else {
+        let (rest, token) = take_while(|c: char| !is_space(c) && !"()[]{}\"".contains(c))(input)?;
+        if token.is_empty() {
+            return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(format!(
+                "reader macro `{}` needs something right after it: {}token",
+                c, c
+            ))));
+        }
+        (rest, Expr::String(token.to_string()))
+    };
+
+    Ok((input, Expr::List(vec![Expr::Symbol(handler), text])))
+}
+
+/// `(:interp a :jit b)` after a `#?`: the first branch whose key is
+/// the current backend's (or `:default`), or None if there isn't one.
+/// Every branch has to parse either way.
+pub(crate) fn parse_conditional(input: &str) -> IResult<&str, Option<Expr>, crate::parser::error::ParseError> {
+    let fail = |msg: &str| nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(msg.to_string()));
+    let mut input = input.strip_prefix('(').ok_or_else(|| fail("#? needs a list of branches: #?(:interp a :jit b)"))?;
+    let mut chosen = None;
+    loop {
+        let (rest, _) = ws0(input)?;
+        if let Some(rest) = rest.strip_prefix(')') {
+            return Ok((rest, chosen));
+        }
+        let key = match parse_expr(rest) {
+            Ok((after, Expr::Keyword(key))) if matches!(&*key.name(), "interp" | "jit" | "default") => {
+                input = after;
+                key
+            }
+            _ => return Err(fail("#? branches start with :interp, :jit or :default")),
+        };
+        let (rest, _) = ws0(input)?;
+        let (rest, expr) = match parse_expr(rest) {
+            Ok(parsed) => parsed,
+            Err(nom::Err::Error(_)) => return Err(fail(&format!("#? branch :{} needs a form", key.name()))),
+            Err(e) => return Err(e),
+        };
+        let name = key.name();
+        if chosen.is_none() && (&*name == backend().key() || &*name == "default") {
+            chosen = Some(expr);
+        }
+        input = rest;
+    }
 }
 
 fn parse_atom(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     alt((
         parse_bool,
         parse_number,
         parse_string,
+        parse_bytes,
+        parse_char,
+        parse_percent_arg,
         parse_symbol,
     ))(input)
 }
 
+thread_local! {
+    /// Inside a `#(...)`: how many arguments its `%`s call for so far.
+    /// None everywhere else.
+    static SHORT_LAMBDA_ARITY: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// `#(+ % 1)`: a `fn` whose parameters are the `%1`, `%2`, ... (`%` is
+/// `%1`) its body uses, up to the highest one, types left to inference.
+fn parse_short_lambda(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    if SHORT_LAMBDA_ARITY.get().is_some() {
+        return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(
+            "#(...) can't be nested; use (fn [...] ...) for the inner one".to_string(),
+        )));
+    }
+    SHORT_LAMBDA_ARITY.set(Some(0));
+    let body = parse_list(input);
+    let arity = SHORT_LAMBDA_ARITY.take().unwrap_or(0);
+    let (input, body) = body?;
+
+    Ok((input, Expr::Lambda {
+        params: (1..=arity).map(|i| (format!("%{}", i), Type::Inferred)).collect(),
+        return_type: None,
+        body: Box::new(body),
+    }))
+}
+
+/// `#[1 2 3]` or `[1 2 3]`, after the `opener`: `(array 1 2 3)` or
+/// `(vector 1 2 3)`, as `head` says.
+fn parse_bracketed<'a>(input: &'a str, head: &str, opener: &str) -> IResult<&'a str, Expr, crate::parser::error::ParseError> {
+    let (input, items) = many0(preceded(ws0, parse_expr))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(']')(input).map_err(|_: nom::Err<crate::parser::error::ParseError>| {
+        nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(format!("`{}` is never closed with `]`", opener)))
+    })?;
+
+    Ok((input, Expr::List(std::iter::once(Expr::Symbol(head.to_string())).chain(items).collect())))
+}
+
+/// `{:a 1 :b 2}`, after the `{`: `(hash-map :a 1 :b 2)`.
+fn parse_map_literal(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let fail = |msg: &str| nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(msg.to_string()));
+    let (input, items) = many0(preceded(ws0, parse_expr))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char('}')(input).map_err(|_: nom::Err<crate::parser::error::ParseError>| fail("`{` is never closed with `}`"))?;
+    if items.len() % 2 != 0 {
+        return Err(fail("a map literal needs a value for every key: {:a 1 :b 2}"));
+    }
+
+    Ok((input, Expr::List(std::iter::once(Expr::Symbol("hash-map".to_string())).chain(items).collect())))
+}
+
+/// `%` or `%N` inside a `#(...)`.
+fn parse_percent_arg(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = char('%')(input)?;
+    let (input, digits) = opt(digit1)(input)?;
+    let fail = |msg: String| nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(msg));
+    let n = match digits {
+        None => 1,
+        Some(digits) => digits
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=9).contains(n))
+            .ok_or_else(|| fail(format!("%{}: arguments go from %1 to %9", digits)))?,
+    };
+    let Some(arity) = SHORT_LAMBDA_ARITY.get() else {
+        return Err(fail(format!("%{} is only allowed inside #(...)", n)));
+    };
+    SHORT_LAMBDA_ARITY.set(Some(arity.max(n)));
+
+    Ok((input, Expr::Symbol(format!("%{}", n))))
+}
+
 fn parse_bool(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
     alt((
         value(Expr::Bool(true), tag("true")),
@@ -36,11 +186,23 @@ fn parse_bool(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseErr
 }
 
 fn parse_number(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
-    alt((
+    let (input, _) = ws0(input)?;
+    let (rest, number) = alt((
         parse_float,
         parse_integer,
-    ))(input)
+    ))(input)?;
+
+    // A number ends where the token does: `5x` or `1-2` is a typo, not
+    // `5` and `x`.
+    let (after, _) = take_while(continues_identifier("/."))(rest)?;
+    if after.len() != rest.len() {
+        let token = &input[..input.len() - after.len()];
+        return Err(nom::Err::Failure(crate::parser::error::ParseError::InvalidNumber(format!(
+            "{} is not a number",
+            token
+        ))));
+    }
+    Ok((rest, number))
 }
 
 fn parse_integer(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
@@ -89,12 +251,18 @@ fn parse_string(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseE
     let (input, s) = delimited(
         char('"'),
         map(
-            opt(escaped(
+            opt(escaped_transform(
                 none_of("\"\\"),
                 '\\',
-                nom::character::complete::one_of("\"\\ntr"),
+                alt((
+                    value("\"", char('"')),
+                    value("\\", char('\\')),
+                    value("\n", char('n')),
+                    value("\t", char('t')),
+                    value("\r", char('r')),
+                )),
             )),
-            |s| s.unwrap_or("").to_string(),
+            Option::unwrap_or_default,
         ),
         char('"'),
     )(input)?;
@@ -102,28 +270,106 @@ fn parse_string(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseE
     Ok((input, Expr::String(s)))
 }
 
+/// `#\c`: the one-character string `"c"`.
+fn parse_char(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = tag("#\\")(input)?;
+    let (input, c) = nom::character::complete::anychar(input)?;
+
+    Ok((input, Expr::String(c.to_string())))
+}
+
+/// Byte-string literals: `#b"..."` is text where `\xNN`, `\n`, `\t`,
+/// `\r`, `\0`, `\\` and `\"` escape single bytes (other characters
+/// contribute their UTF-8 encoding); `#x"..."` is hex digit pairs with
+/// optional whitespace between them.
+fn parse_bytes(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, prefix) = alt((tag("#b\""), tag("#x\"")))(input)?;
+    let fail = |msg: String| {
+        nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(msg))
+    };
+
+    let mut out = Vec::new();
+    let mut chars = input.char_indices();
+    if prefix == "#x\"" {
+        let end = input
+            .find('"')
+            .ok_or_else(|| fail("unterminated #x\"...\" literal".to_string()))?;
+        let digits: String = input[..end].chars().filter(|c| !c.is_whitespace()).collect();
+        if !digits.len().is_multiple_of(2) {
+            return Err(fail(format!("#x literal has an odd number of hex digits: {}", digits)));
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).unwrap_or("");
+            let byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| fail(format!("invalid hex byte in #x literal: {}", pair)))?;
+            out.push(byte);
+        }
+        return Ok((&input[end + 1..], Expr::Bytes(out)));
+    }
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&input[i + 1..], Expr::Bytes(out))),
+            '\\' => {
+                let (_, esc) = chars
+                    .next()
+                    .ok_or_else(|| fail("unterminated #b\"...\" literal".to_string()))?;
+                match esc {
+                    'n' => out.push(b'\n'),
+                    't' => out.push(b'\t'),
+                    'r' => out.push(b'\r'),
+                    '0' => out.push(0),
+                    '\\' => out.push(b'\\'),
+                    '"' => out.push(b'"'),
+                    'x' => {
+                        let hex: String = chars.by_ref().take(2).map(|(_, c)| c).collect();
+                        let byte = u8::from_str_radix(&hex, 16)
+                            .ok()
+                            .filter(|_| hex.len() == 2)
+                            .ok_or_else(|| fail(format!("invalid \\x escape: \\x{}", hex)))?;
+                        out.push(byte);
+                    }
+                    other => return Err(fail(format!("unknown escape in #b literal: \\{}", other))),
+                }
+            }
+            c => {
+                let mut buf = [0u8; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    Err(fail("unterminated #b\"...\" literal".to_string()))
+}
+
 fn parse_symbol(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, s) = take_while1(|c: char| {
-        c.is_alphanumeric() || "+-*/<>=!&|_?.".contains(c)
-    })(input)?;
+    // A leading `:` makes a keyword (`:as` in `(import m :as x)`, or any
+    // `:tag` value); a bare `:` stays reserved for type annotations.
+    let (input, s) = recognize(preceded(opt(char(':')), identifier("/.")))(input)?;
     
     // Check for special symbols
     match s {
         "nil" => Ok((input, Expr::Nil)),
-        _ => Ok((input, Expr::Symbol(s.to_string()))),
+        _ => match s.strip_prefix(':') {
+            Some(name) => Ok((input, Expr::Keyword(Keyword::intern(name)))),
+            // `-x` is `(neg x)`; operators like `->` stay symbols.
+            None => match s.strip_prefix('-') {
+                Some(name) if name.starts_with(is_xid_start) => Ok((input, negate(Expr::Symbol(name.to_string())))),
+                _ => Ok((input, Expr::Symbol(s.to_string()))),
+            },
+        },
     }
 }
 
 fn parse_list(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, _) = char('(')(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     
     let (input, first) = opt(parse_expr)(input)?;
     
     match first {
         None => {
-            let (input, _) = multispace0(input)?;
+            let (input, _) = ws0(input)?;
             let (input, _) = char(')')(input)?;
             Ok((input, Expr::Nil))  // Empty list is nil
         }
@@ -131,19 +377,37 @@ fn parse_list(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseErr
             match &first_expr {
                 Expr::Symbol(s) if s == "if" => parse_if_expr(input),
                 Expr::Symbol(s) if s == "let" => parse_let_expr(input),
-                Expr::Symbol(s) if s == "defn" => parse_defn_expr(input),
+                Expr::Symbol(s) if s == "def" => parse_def_expr(input, false),
+                Expr::Symbol(s) if s == "defconst" => parse_def_expr(input, true),
+                Expr::Symbol(s) if s == "defn" => parse_defn_expr(input, false),
+                Expr::Symbol(s) if s == "defn-" => parse_defn_expr(input, true),
                 Expr::Symbol(s) if s == "fn" || s == "lambda" => parse_lambda_expr(input),
                 Expr::Symbol(s) if s == "match" => parse_match_expr(input),
+                Expr::Symbol(s) if s == "defgen" => parse_defgen_expr(input),
+                Expr::Symbol(s) if s == "defparam" => parse_defparam_expr(input),
+                Expr::Symbol(s) if s == "deftype" => parse_deftype_expr(input),
+                Expr::Symbol(s) if s == "defstruct" => parse_defstruct_expr(input),
+                Expr::Symbol(s) if s == "parameterize" => parse_parameterize_expr(input),
+                Expr::Symbol(s) if s == "with-open" => parse_with_open_expr(input),
+                Expr::Symbol(s) if s == "defer" => parse_defer_expr(input),
+                Expr::Symbol(s) if s == "do" => parse_do_expr(input),
+                Expr::Symbol(s) if s == "yield" => parse_yield_expr(input),
+                Expr::Symbol(s) if s == "return" => parse_return_expr(input),
+                Expr::Symbol(s) if s == "the" => parse_the_expr(input),
+                Expr::Symbol(s) if s == "block" => parse_block_expr(input),
+                Expr::Symbol(s) if s == "set-reader-macro!" => parse_set_reader_macro(input),
+                Expr::Symbol(s) if s == "return-from" => parse_return_from_expr(input),
+                Expr::Symbol(s) if s == "reset" => parse_reset_expr(input),
+                Expr::Symbol(s) if s == "shift" => parse_shift_expr(input),
+                Expr::Symbol(s) if s == "loop" => parse_loop_expr(input),
+                Expr::Symbol(s) if s == "recur" => parse_recur_expr(input),
                 _ => {
-                    let (input, _) = multispace0(input)?;
-                    let (input, rest) = many0(preceded(multispace0, parse_expr))(input)?;
-                    let (input, _) = multispace0(input)?;
+                    let (input, _) = ws0(input)?;
+                    let (input, rest) = many0(preceded(ws0, parse_expr))(input)?;
+                    let (input, _) = ws0(input)?;
                     let (input, _) = char(')')(input)?;
                     
-                    let mut exprs = vec![first_expr];
-                    exprs.extend(rest);
-                    
-                    Ok((input, Expr::List(exprs)))
+                    call_form(first_expr, rest).map(|expr| (input, expr))
                 }
             }
         }
@@ -151,13 +415,13 @@ fn parse_list(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseErr
 }
 
 fn parse_if_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, condition) = parse_expr(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, then_branch) = parse_expr(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, else_branch) = parse_expr(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, _) = char(')')(input)?;
     
     Ok((input, Expr::If {
@@ -167,92 +431,594 @@ fn parse_if_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::Parse
     }))
 }
 
-fn parse_let_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+/// `name`, optional type annotation and value: what `let` and `def` share.
+fn parse_binding(input: &str) -> IResult<&str, (String, Option<Type>, Expr), crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
     let (input, name) = parse_symbol_name(input)?;
     
     // Check for colon after name (new syntax)
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, has_colon) = opt(char(':'))(input)?;
     
     let (input, type_ann) = if has_colon.is_some() {
         // New syntax: (let x: i32 42) or (let x: 42)
-        let (input, _) = multispace0(input)?;
+        let (input, _) = ws0(input)?;
         // Try to parse type, if it fails, it means it's (let x: value) syntax
         match opt(parse_type_annotation)(input) {
             Ok((remaining, Some(ty))) => (remaining, Some(ty)),
+            Err(nom::Err::Failure(e)) => return Err(nom::Err::Failure(e)),
             _ => (input, None), // Type inference
         }
     } else {
         // Old syntax: (let x i32 42) or (let x 42)
-        let (input, _) = multispace0(input)?;
+        let (input, _) = ws0(input)?;
         opt(parse_type_annotation)(input)?
     };
     
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, value) = parse_expr(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
+    Ok((input, (name, type_ann, value)))
+}
+
+fn parse_let_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, (name, type_ann, value)) = parse_binding(input)?;
     
-    // Check if there's a body expression (let-in form)
-    // Peek ahead to see if there's another expression before the closing paren
-    let (input, body) = match char::<&str, crate::parser::error::ParseError>(')')(input) {
-        Ok((remaining, _)) => {
-            // No body, just value
-            (remaining, None)
+    // `let` is strictly scoped: a missing body is an error rather than
+    // a global definition (that's `def`).
+    if input.starts_with(')') {
+        return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(format!(
+            "`let` needs a body: (let {} <value> <body>); use (def {} <value>) for a global definition",
+            name, name
+        ))));
+    }
+    let (input, body) = parse_expr(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+    
+    Ok((input, Expr::Let {
+        name,
+        type_ann,
+        value: Box::new(value),
+        body: Box::new(body),
+    }))
+}
+
+/// `(loop [name value ...] body...)`; each binding is written like a
+/// `let` binding, so `[acc: i64 1]` works too.
+fn parse_loop_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, _) = char('[')(input)?;
+    let (input, bindings) = many0(parse_binding)(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(']')(input)?;
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, Expr::Loop { bindings, body: Box::new(body) }))
+}
+
+fn parse_defparam_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, name) = parse_symbol_name(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, value) = parse_expr(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    Ok((input, Expr::Defparam { name, value: Box::new(value) }))
+}
+
+/// `(deftype Name (Ctor field-type ...) Ctor ...)`: a constructor with no
+/// fields can be written bare. The type and its constructors are
+/// capitalized, which is how a pattern tells a constructor from a name to
+/// bind.
+fn parse_deftype_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, name) = parse_symbol_name(input)?;
+    let (input, variants) = many0(preceded(ws0, parse_variant))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    let fail = |message: String| Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(message)));
+    if !is_constructor_name(&name) {
+        return fail(format!("deftype: type name `{}` must start with an uppercase letter", name));
+    }
+    if variants.is_empty() {
+        return fail(format!("deftype {} needs at least one constructor", name));
+    }
+    for (i, (ctor, _)) in variants.iter().enumerate() {
+        if !is_constructor_name(ctor) {
+            return fail(format!("deftype {}: constructor `{}` must start with an uppercase letter", name, ctor));
+        }
+        if variants[..i].iter().any(|(other, _)| other == ctor) {
+            return fail(format!("deftype {}: constructor `{}` is declared twice", name, ctor));
+        }
+    }
+    Ok((input, Expr::Deftype { name, variants }))
+}
+
+/// `(defstruct Point [x: f64 y: f64])`: fields are written like `defn`
+/// parameters.
+fn parse_defstruct_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, name) = parse_symbol_name(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, fields) = parse_params(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    let fail = |message: String| Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(message)));
+    if !is_constructor_name(&name) {
+        return fail(format!("defstruct: type name `{}` must start with an uppercase letter", name));
+    }
+    if fields.is_empty() {
+        return fail(format!("defstruct {} needs at least one field", name));
+    }
+    for (i, (field, _)) in fields.iter().enumerate() {
+        if fields[..i].iter().any(|(other, _)| other == field) {
+            return fail(format!("defstruct {}: field `{}` is declared twice", name, field));
         }
-        Err(_) => {
-            // There's a body expression
-            let (input, body_expr) = parse_expr(input)?;
-            let (input, _) = multispace0(input)?;
+    }
+    Ok((input, Expr::Defstruct { name, fields }))
+}
+
+/// One constructor of a `deftype`: `(Circle f64)` or `Empty`.
+fn parse_variant(input: &str) -> IResult<&str, (String, Vec<Type>), crate::parser::error::ParseError> {
+    alt((
+        map(parse_symbol_name, |name| (name, Vec::new())),
+        |input| {
+            let (input, _) = char('(')(input)?;
+            let (input, _) = ws0(input)?;
+            let (input, name) = parse_symbol_name(input)?;
+            let (input, fields) = many0(preceded(ws0, parse_type_annotation))(input)?;
+            let (input, _) = ws0(input)?;
             let (input, _) = char(')')(input)?;
-            (input, Some(Box::new(body_expr)))
+            Ok((input, (name, fields)))
+        },
+    ))(input)
+}
+
+/// Whether `name` is written like a type or constructor: `Circle`, not
+/// `circle`.
+fn is_constructor_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+/// A constructor in a pattern, which may be qualified: `shapes/Circle`.
+fn is_constructor_ref(name: &str) -> bool {
+    name.rsplit('/').next().is_some_and(is_constructor_name)
+}
+
+fn parse_parameterize_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, bindings) = parse_bracketed_pairs(input)?;
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, Expr::Parameterize { bindings, body: Box::new(body) }))
+}
+
+fn parse_with_open_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, bindings) = parse_bracketed_pairs(input)?;
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, Expr::WithOpen { bindings, body: Box::new(body) }))
+}
+
+fn parse_defer_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, expr) = parse_expr(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    Ok((input, Expr::Defer(Box::new(expr))))
+}
+
+/// `[name value ...]`, as `parameterize` and `with-open` take.
+fn parse_bracketed_pairs(input: &str) -> IResult<&str, Vec<(String, Expr)>, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, _) = char('[')(input)?;
+    let (input, pairs) = many0(tuple((
+        preceded(ws0, parse_symbol_name),
+        preceded(ws0, parse_expr),
+    )))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(']')(input)?;
+    Ok((input, pairs))
+}
+
+fn parse_defgen_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, meta) = parse_meta(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, name) = parse_symbol_name(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, params) = parse_params(input)?;
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, Expr::Defgen { name, params, body: Box::new(body), meta }))
+}
+
+fn parse_do_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    parse_body(input)
+}
+
+fn parse_yield_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, value) = parse_expr(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    Ok((input, Expr::Yield(Box::new(value))))
+}
+
+fn parse_return_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, value) = parse_expr(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    Ok((input, Expr::Return(Box::new(value))))
+}
+
+fn parse_the_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, ty) = parse_type_annotation(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, expr) = parse_expr(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    Ok((input, Expr::The { ty, expr: Box::new(expr) }))
+}
+
+fn parse_block_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, label) = parse_label(input, "block")?;
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, Expr::Block { label, body: Box::new(body) }))
+}
+
+fn parse_return_from_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, label) = parse_label(input, "return-from")?;
+    let (input, _) = ws0(input)?;
+    let (input, value) = parse_expr(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    Ok((input, Expr::ReturnFrom { label, value: Box::new(value) }))
+}
+
+fn parse_reset_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, Expr::Reset(Box::new(body))))
+}
+
+fn parse_shift_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    let (input, name) = match parse_symbol(input) {
+        Ok((input, Expr::Symbol(name))) => (input, name),
+        _ => {
+            return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(
+                "`shift` needs a name for the continuation: (shift k ...)".to_string(),
+            )))
         }
     };
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, Expr::Shift { name, body: Box::new(body) }))
+}
+
+/// `(set-reader-macro! #\$ handler)`. Takes effect as soon as it's read,
+/// so the forms after it can use it.
+fn parse_set_reader_macro(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let fail = |msg: String| nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(msg));
+    let usage = || fail("set-reader-macro! expects a character and a function name: (set-reader-macro! #\\$ name)".to_string());
+    let (input, c) = match parse_atom(input) {
+        Ok((input, Expr::String(s))) if s.chars().count() == 1 => (input, s.chars().next().unwrap_or_default()),
+        _ => return Err(usage()),
+    };
+    let (input, handler) = match parse_atom(input) {
+        Ok((input, Expr::Symbol(handler))) => (input, handler),
+        _ => return Err(usage()),
+    };
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+    crate::parser::set_reader_macro(c, &handler).map_err(fail)?;
+
+    Ok((input, Expr::SetReaderMacro { ch: c, handler }))
+}
+
+/// The `:name` a `block` or `return-from` starts with.
+fn parse_label<'a>(input: &'a str, form: &str) -> IResult<&'a str, Keyword, crate::parser::error::ParseError> {
+    let (input, _) = ws0(input)?;
+    match parse_symbol(input) {
+        Ok((input, Expr::Keyword(label))) => Ok((input, label)),
+        _ => Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(format!(
+            "`{}` needs a keyword label: ({} :name ...)",
+            form, form
+        )))),
+    }
+}
+
+/// One or more forms up to the closing `)`: the form itself if there's
+/// just one, else a `do` of them all. A `(let x v)` without a body binds
+/// `x` over the forms after it, as if they were nested inside it.
+fn parse_body(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, forms) = many1(preceded(ws0, parse_body_form))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    let sequence = |mut exprs: Vec<Expr>| if exprs.len() == 1 { exprs.remove(0) } else { Expr::Do(exprs) };
+    let mut rest = Vec::new();
+    for form in forms.into_iter().rev() {
+        match form {
+            BodyForm::Expr(expr) => rest.push(expr),
+            BodyForm::Let(name, _, _) if rest.is_empty() => {
+                return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(format!(
+                    "`let` needs a body: (let {} <value>) has to be followed by the forms that use it",
+                    name
+                ))));
+            }
+            BodyForm::Let(name, type_ann, value) => {
+                rest.reverse();
+                let body = sequence(std::mem::take(&mut rest));
+                rest.push(Expr::Let { name, type_ann, value: Box::new(value), body: Box::new(body) });
+            }
+        }
+    }
+    rest.reverse();
+    Ok((input, sequence(rest)))
+}
+
+/// One form of a body: a `let` without a body of its own is kept apart
+/// so `parse_body` can scope it over what follows.
+enum BodyForm {
+    Expr(Expr),
+    Let(String, Option<Type>, Expr),
+}
+
+fn parse_body_form(input: &str) -> IResult<&str, BodyForm, crate::parser::error::ParseError> {
+    match tuple((char('('), ws0, tag("let"), ws1, parse_binding, char(')')))(input) {
+        Ok((input, (_, _, _, _, (name, type_ann, value), _))) => Ok((input, BodyForm::Let(name, type_ann, value))),
+        Err(nom::Err::Error(_)) => parse_expr(input).map(|(input, expr)| (input, BodyForm::Expr(expr))),
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_recur_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, args) = many0(preceded(ws0, parse_expr))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    Ok((input, Expr::Recur(args)))
+}
+
+fn parse_def_expr(input: &str, constant: bool) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, (name, type_ann, value)) = parse_binding(input)?;
+    let (input, _) = char(')')(input)?;
     
-    Ok((input, Expr::Let {
+    Ok((input, Expr::Def {
         name,
         type_ann,
         value: Box::new(value),
-        body,
+        constant,
     }))
 }
 
-fn parse_defn_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    // Already consumed "defn", parse the rest
-    let (input, _) = multispace0(input)?;
+fn parse_defn_expr(input: &str, private: bool) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    // Already consumed "defn" / "defn-", parse the rest
+    let (input, _) = ws0(input)?;
+    let (input, meta) = parse_meta(input)?;
+    let (input, _) = ws0(input)?;
     let (input, name) = parse_symbol_name(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
+    if input.strip_prefix('(').is_some_and(|rest| rest.trim_start_matches(is_space).starts_with('[')) {
+        return parse_defn_arities(input, name, private, meta);
+    }
+    if !input.starts_with('[') {
+        return parse_defn_clauses(input, name, private, meta);
+    }
     
     let (input, params) = parse_params(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     
     let (input, return_type) = opt(parse_return_type)(input)?;
     let return_type = return_type.unwrap_or(Type::Inferred);
-    let (input, _) = multispace0(input)?;
-    
-    let (input, body) = parse_expr(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, _) = char(')')(input)?;
+    let (input, body) = parse_body(input)?;
     
     Ok((input, Expr::Defn {
         name,
         params,
         return_type,
         body: Box::new(body),
+        private,
+        meta,
     }))
 }
 
+/// `(defn greet ([name] ...) ([name greeting] ...))`: a `defn` for each
+/// `([params] -> T body...)`, no two taking the same number of arguments.
+fn parse_defn_arities(
+    input: &str,
+    name: String,
+    private: bool,
+    meta: Meta,
+) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let arity = |input| {
+        let (input, _) = char('(')(input)?;
+        let (input, _) = ws0(input)?;
+        let (input, params) = parse_params(input)?;
+        let (input, _) = ws0(input)?;
+        let (input, return_type) = opt(parse_return_type)(input)?;
+        let (input, body) = parse_body(input)?;
+        Ok((input, (params, return_type, body)))
+    };
+    let (input, arities) = many1(preceded(ws0, arity))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    let mut defns: Vec<Expr> = Vec::new();
+    for (params, return_type, body) in arities {
+        if defns.iter().any(|d| matches!(d, Expr::Defn { params: p, .. } if p.len() == params.len())) {
+            return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(format!(
+                "`{}` has more than one arity taking {} arguments",
+                name,
+                params.len()
+            ))));
+        }
+        defns.push(Expr::Defn {
+            name: name.clone(),
+            params,
+            return_type: return_type.unwrap_or(Type::Inferred),
+            body: Box::new(body),
+            private,
+            meta: meta.clone(),
+        });
+    }
+    let expr = if defns.len() == 1 { defns.remove(0) } else { Expr::MultiArity(defns) };
+
+    Ok((input, expr))
+}
+
+/// `(defn fact ((0) 1) ((n) (* n (fact (- n 1)))))`: one `((patterns) body)`
+/// clause per case, tried in order. It becomes a `defn` of `%1`, `%2`, ...
+/// whose body matches the arguments (a dotted chain of them when there are
+/// several) against each clause, so the `match` checks cover every case.
+fn parse_defn_clauses(
+    input: &str,
+    name: String,
+    private: bool,
+    meta: Meta,
+) -> IResult<&str, Expr, crate::parser::error::ParseError> {
+    let (input, return_type) = opt(parse_return_type)(input)?;
+    let (input, clauses) = many1(preceded(ws0, parse_defn_clause))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+
+    let arity = clauses[0].0.len();
+    if let Some((pats, _)) = clauses.iter().find(|(pats, _)| pats.len() != arity) {
+        return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(format!(
+            "clauses of `{}` must take the same number of arguments: {} and {}",
+            name,
+            arity,
+            pats.len()
+        ))));
+    }
+    // A parameter's type is the one its literal patterns have, if any.
+    let params: Vec<(String, Type)> = (0..arity)
+        .map(|i| {
+            let ty = clauses.iter().find_map(|(pats, _)| pattern_type(&pats[i])).unwrap_or(Type::Inferred);
+            (format!("%{}", i + 1), ty)
+        })
+        .collect();
+    let cons = |head: Expr, tail: Expr| Expr::List(vec![Expr::Symbol("cons".to_string()), head, tail]);
+    let (scrutinee, arms) = if arity == 1 {
+        let arms = clauses.into_iter().map(|(mut pats, body)| (pats.remove(0), body)).collect();
+        (Expr::Symbol(params[0].0.clone()), arms)
+    } else {
+        // Ending the chain in `true` keeps it a `Pair` even when the last
+        // argument is a list.
+        let scrutinee = params.iter().rev().fold(Expr::Bool(true), |tail, (p, _)| cons(Expr::Symbol(p.clone()), tail));
+        let arms = clauses
+            .into_iter()
+            .map(|(pats, body)| {
+                let pat = pats.into_iter().rev().fold(crate::ast::Pattern::Wildcard, |tail, head| {
+                    crate::ast::Pattern::Cons(Box::new(head), Box::new(tail))
+                });
+                (pat, body)
+            })
+            .collect();
+        (scrutinee, arms)
+    };
+
+    Ok((input, Expr::Defn {
+        name,
+        params,
+        return_type: return_type.unwrap_or(Type::Inferred),
+        body: Box::new(Expr::Match { scrutinee: Box::new(scrutinee), arms }),
+        private,
+        meta,
+    }))
+}
+
+/// `((p1 p2 ...) body ...)`, one clause of a `defn`.
+fn parse_defn_clause(
+    input: &str,
+) -> IResult<&str, (Vec<crate::ast::Pattern>, Expr), crate::parser::error::ParseError> {
+    let (input, _) = char('(')(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char('(')(input)?;
+    let (input, pats) = many0(preceded(ws0, parse_pattern))(input)?;
+    let (input, _) = ws0(input)?;
+    let (input, _) = char(')')(input)?;
+    let (input, body) = parse_body(input)?;
+
+    Ok((input, (pats, body)))
+}
+
+/// The type of the values a pattern can match at all, if it says.
+fn pattern_type(pat: &crate::ast::Pattern) -> Option<Type> {
+    use crate::ast::Pattern;
+    match pat {
+        Pattern::LiteralI32(_) => Some(Type::I32),
+        Pattern::LiteralI64(_) => Some(Type::I64),
+        Pattern::LiteralF64(_) => Some(Type::F64),
+        Pattern::LiteralBool(_) => Some(Type::Bool),
+        Pattern::LiteralString(_) => Some(Type::String),
+        Pattern::LiteralKeyword(_) => Some(Type::Keyword),
+        Pattern::Nil | Pattern::Cons(..) => Some(Type::List(Box::new(Type::Inferred))),
+        Pattern::As(inner, _) | Pattern::Guard(inner, _) => pattern_type(inner),
+        Pattern::Or(branches) => branches.iter().find_map(pattern_type),
+        // Which type a constructor belongs to is the checker's to find out.
+        Pattern::Wildcard | Pattern::Variable(_) | Pattern::Constructor(..) => None,
+    }
+}
+
+/// `^{:key value ...}` before a definition's name, or nothing. The values
+/// are read, never evaluated, so they have to be literals.
+fn parse_meta(input: &str) -> IResult<&str, Meta, crate::parser::error::ParseError> {
+    let Some(mut input) = input.strip_prefix("^{") else { return Ok((input, Vec::new())) };
+    let fail = |msg: &str| nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(msg.to_string()));
+    let mut meta = Vec::new();
+    loop {
+        let (rest, _) = ws0(input)?;
+        if let Some(rest) = rest.strip_prefix('}') {
+            return Ok((rest, meta));
+        }
+        let (rest, key) = match parse_expr(rest) {
+            Ok((rest, Expr::Keyword(key))) => (rest, key),
+            _ => return Err(fail("metadata keys are keywords: ^{:key value ...}")),
+        };
+        let (rest, _) = ws0(rest)?;
+        let (rest, value) = match parse_expr(rest) {
+            Ok((rest, value)) if is_literal(&value) => (rest, value),
+            _ => return Err(fail("metadata values are literals: strings, numbers, booleans, keywords or nil")),
+        };
+        meta.push((key, value));
+        input = rest;
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Integer32(_)
+            | Expr::Integer64(_)
+            | Expr::Float(_)
+            | Expr::Bool(_)
+            | Expr::String(_)
+            | Expr::Keyword(_)
+            | Expr::Nil
+    )
+}
+
 fn parse_lambda_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, params) = parse_params(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     
     let (input, return_type) = opt(parse_return_type)(input)?;
-    let (input, _) = multispace0(input)?;
-    
-    let (input, body) = parse_expr(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, _) = char(')')(input)?;
+    let (input, body) = parse_body(input)?;
     
     Ok((input, Expr::Lambda {
         params,
@@ -263,7 +1029,7 @@ fn parse_lambda_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::P
 
 fn parse_params(input: &str) -> IResult<&str, Vec<(String, Type)>, crate::parser::error::ParseError> {
     let (input, _) = char('[')(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     
     let mut params = Vec::new();
     let mut current_input = input;
@@ -280,19 +1046,19 @@ fn parse_params(input: &str) -> IResult<&str, Vec<(String, Type)>, crate::parser
         params.push(param);
         
         // Skip whitespace after parameter
-        let (next_input, _) = multispace0(next_input)?;
+        let (next_input, _) = ws0(next_input)?;
         current_input = next_input;
     }
 }
 
 fn parse_param(input: &str) -> IResult<&str, (String, Type), crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, name) = parse_symbol_name(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     
     // Type annotation is optional
     let (input, ty) = if let Ok((input2, _)) = char::<&str, crate::parser::error::ParseError>(':')(input) {
-        let (input2, _) = multispace0(input2)?;
+        let (input2, _) = ws0(input2)?;
         let (input2, ty) = parse_type_annotation(input2)?;
         (input2, ty)
     } else {
@@ -305,30 +1071,97 @@ fn parse_param(input: &str) -> IResult<&str, (String, Type), crate::parser::erro
 
 fn parse_return_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
     preceded(
-        tuple((tag("->"), multispace0)),
+        tuple((tag("->"), ws0)),
         parse_type_annotation,
     )(input)
 }
 
+/// A name being bound. Same characters as a symbol reference except `/`
+/// (reserved for module-qualified names) and `.`.
 fn parse_symbol_name(input: &str) -> IResult<&str, String, crate::parser::error::ParseError> {
-    let (input, s) = take_while1(|c: char| {
-        c.is_alphanumeric() || "_-".contains(c)
-    })(input)?;
+    let (input, s) = identifier("")(input)?;
 
     Ok((input, s.to_string()))
 }
 
+/// Characters an identifier may use anywhere, on top of the Unicode ones.
+const OPERATOR_CHARS: &str = "+-*<>=!&|_?";
+
+/// Whether `c` can be part of an identifier past its first character.
+fn continues_identifier(extra: &'static str) -> impl Fn(char) -> bool {
+    move |c| is_xid_continue(c) || OPERATOR_CHARS.contains(c) || extra.contains(c)
+}
+
+fn is_dot(expr: &Expr) -> bool {
+    matches!(expr, Expr::Symbol(s) if s == ".")
+}
+
+/// `(a b . c)` is `(cons a (cons b c))`: a proper list if `c` turns out to
+/// be one, a dotted pair otherwise.
+fn dotted_list(mut exprs: Vec<Expr>, dot: usize) -> Result<Expr, nom::Err<crate::parser::error::ParseError>> {
+    if dot == 0 || dot + 2 != exprs.len() || exprs.iter().filter(|e| is_dot(e)).count() > 1 {
+        return Err(nom::Err::Failure(crate::parser::error::ParseError::UnexpectedInput(
+            "`.` goes right before the last element: (a . b) or (a b . c)".to_string(),
+        )));
+    }
+    let tail = exprs.pop().expect("checked above");
+    exprs.pop();
+    Ok(exprs.into_iter().rev().fold(tail, |tail, head| {
+        Expr::List(vec![Expr::Symbol("cons".to_string()), head, tail])
+    }))
+}
+
+/// `(neg expr)`, what `(- x)` and `-x` read as.
+fn negate(expr: Expr) -> Expr {
+    Expr::List(vec![Expr::Symbol("neg".to_string()), expr])
+}
+
+/// A list headed by `head` that isn't special syntax: a call, or the
+/// shorthands `(- x)` for `(neg x)` and `(.x p)` for `(get p :x)`, or a
+/// dotted list.
+fn call_form(head: Expr, mut rest: Vec<Expr>) -> Result<Expr, nom::Err<crate::parser::error::ParseError>> {
+    if let Expr::Symbol(s) = &head
+        && rest.len() == 1
+    {
+        if s == "-" {
+            return Ok(negate(rest.remove(0)));
+        }
+        if let Some(field) = s.strip_prefix('.').filter(|f| f.starts_with(is_xid_start)) {
+            let field = Expr::Keyword(Keyword::intern(field));
+            return Ok(Expr::List(vec![Expr::Symbol("get".to_string()), rest.remove(0), field]));
+        }
+    }
+    let mut exprs = vec![head];
+    exprs.extend(rest);
+    match exprs.iter().position(is_dot) {
+        None => Ok(Expr::List(exprs)),
+        Some(dot) => dotted_list(exprs, dot),
+    }
+}
+
+/// The identifier grammar: a Unicode identifier (`XID_Start` then
+/// `XID_Continue`, as for Rust names, so `名前` and `größe` work) in which
+/// the operator characters, plus `extra`, may also appear, first included.
+fn identifier<'a>(
+    extra: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, crate::parser::error::ParseError> {
+    recognize(pair(
+        satisfy(move |c| is_xid_start(c) || OPERATOR_CHARS.contains(c) || extra.contains(c)),
+        take_while(continues_identifier(extra)),
+    ))
+}
+
 // === Pattern matching ===
 
 /// Parse `(match <expr> (<pat> <body>) ...)`.
 /// Caller has already consumed the leading `(` and the `match` keyword.
 fn parse_match_expr(input: &str) -> IResult<&str, Expr, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, scrutinee) = parse_expr(input)?;
 
     // One or more arms, each a `(pattern body)` S-expression.
-    let (input, arms) = many0(preceded(multispace0, parse_match_arm))(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, arms) = many0(preceded(ws0, parse_match_arm))(input)?;
+    let (input, _) = ws0(input)?;
     let (input, _) = char(')')(input)?;
 
     if arms.is_empty() {
@@ -352,11 +1185,11 @@ fn parse_match_arm(
     input: &str,
 ) -> IResult<&str, (crate::ast::Pattern, Expr), crate::parser::error::ParseError> {
     let (input, _) = char('(')(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, pat) = parse_pattern(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, body) = parse_expr(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, _) = char(')')(input)?;
     Ok((input, (pat, body)))
 }
@@ -364,7 +1197,8 @@ fn parse_match_arm(
 fn parse_pattern(
     input: &str,
 ) -> IResult<&str, crate::ast::Pattern, crate::parser::error::ParseError> {
-    let (input, _) = multispace0(input)?;
+    let _nesting = Nesting::enter()?;
+    let (input, _) = ws0(input)?;
     alt((parse_compound_pattern, parse_atom_pattern))(input)
 }
 
@@ -376,15 +1210,17 @@ fn parse_compound_pattern(
     input: &str,
 ) -> IResult<&str, crate::ast::Pattern, crate::parser::error::ParseError> {
     let (input, _) = char('(')(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, head) = parse_symbol_name(input)?;
+    let (input, _) = ws0(input)?;
+    // `/` for a qualified constructor: `(shapes/Circle r)`.
+    let (input, head) = identifier("/")(input)?;
+    let head = head.to_string();
     match head.as_str() {
         "cons" => {
-            let (input, _) = multispace1(input)?;
+            let (input, _) = ws1(input)?;
             let (input, h) = parse_pattern(input)?;
-            let (input, _) = multispace0(input)?;
+            let (input, _) = ws0(input)?;
             let (input, t) = parse_pattern(input)?;
-            let (input, _) = multispace0(input)?;
+            let (input, _) = ws0(input)?;
             let (input, _) = char(')')(input)?;
             Ok((
                 input,
@@ -394,8 +1230,8 @@ fn parse_compound_pattern(
         "list" => {
             // Zero or more sub-patterns, then `)`.
             let (input, items) =
-                many0(preceded(multispace0, parse_pattern))(input)?;
-            let (input, _) = multispace0(input)?;
+                many0(preceded(ws0, parse_pattern))(input)?;
+            let (input, _) = ws0(input)?;
             let (input, _) = char(')')(input)?;
             // Desugar: (list p1 p2 p3) => (cons p1 (cons p2 (cons p3 nil)))
             let folded = items.into_iter().rev().fold(
@@ -408,11 +1244,11 @@ fn parse_compound_pattern(
         }
         "as" => {
             // (as <pattern> <name>)
-            let (input, _) = multispace1(input)?;
+            let (input, _) = ws1(input)?;
             let (input, inner) = parse_pattern(input)?;
-            let (input, _) = multispace1(input)?;
+            let (input, _) = ws1(input)?;
             let (input, name) = parse_symbol_name(input)?;
-            let (input, _) = multispace0(input)?;
+            let (input, _) = ws0(input)?;
             let (input, _) = char(')')(input)?;
             Ok((
                 input,
@@ -421,11 +1257,11 @@ fn parse_compound_pattern(
         }
         "guard" => {
             // (guard <pattern> <bool-expr>)
-            let (input, _) = multispace1(input)?;
+            let (input, _) = ws1(input)?;
             let (input, inner) = parse_pattern(input)?;
-            let (input, _) = multispace1(input)?;
+            let (input, _) = ws1(input)?;
             let (input, guard_expr) = parse_expr(input)?;
-            let (input, _) = multispace0(input)?;
+            let (input, _) = ws0(input)?;
             let (input, _) = char(')')(input)?;
             Ok((
                 input,
@@ -435,8 +1271,8 @@ fn parse_compound_pattern(
         "or" => {
             // (or <pat> <pat> ...) — 1 or more branches required
             let (input, branches) =
-                many0(preceded(multispace0, parse_pattern))(input)?;
-            let (input, _) = multispace0(input)?;
+                many0(preceded(ws0, parse_pattern))(input)?;
+            let (input, _) = ws0(input)?;
             let (input, _) = char(')')(input)?;
             if branches.is_empty() {
                 return Err(nom::Err::Failure(
@@ -447,6 +1283,13 @@ fn parse_compound_pattern(
             }
             Ok((input, crate::ast::Pattern::Or(branches)))
         }
+        ctor if is_constructor_ref(ctor) => {
+            // (Ctor <pat> ...) — one sub-pattern per field
+            let (input, fields) = many0(preceded(ws0, parse_pattern))(input)?;
+            let (input, _) = ws0(input)?;
+            let (input, _) = char(')')(input)?;
+            Ok((input, crate::ast::Pattern::Constructor(head, fields)))
+        }
         other => Err(nom::Err::Failure(
             crate::parser::error::ParseError::UnexpectedInput(format!(
                 "unknown compound pattern: ({} ...)",
@@ -470,8 +1313,10 @@ fn parse_atom_pattern(
         Expr::Integer64(n) => crate::ast::Pattern::LiteralI64(n),
         Expr::Float(n) => crate::ast::Pattern::LiteralF64(n),
         Expr::String(s) => crate::ast::Pattern::LiteralString(s),
+        Expr::Keyword(k) => crate::ast::Pattern::LiteralKeyword(k),
         Expr::Nil => crate::ast::Pattern::Nil,
         Expr::Symbol(s) if s == "_" => crate::ast::Pattern::Wildcard,
+        Expr::Symbol(s) if is_constructor_ref(&s) => crate::ast::Pattern::Constructor(s, Vec::new()),
         Expr::Symbol(s) => crate::ast::Pattern::Variable(s),
         other => {
             return Err(nom::Err::Failure(
@@ -0,0 +1,88 @@
+//! Splitting raw text into lexemes, for the tools that look at source
+//! without parsing it: the REPL deciding whether a form is finished
+//! (`reader::is_complete`) and coloring what's been typed so far
+//! (`highlight`).
+//!
+//! Unfinished input is the usual case for both, so nothing here fails:
+//! an unterminated string or `#| ... |#` comment runs to the end and
+//! says so.
+
+use std::ops::Range;
+
+use crate::parser::is_space;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lexeme {
+    /// `(`, `[` or `{`, including the bracket of `#(`, `#[` and `#?(`.
+    Open,
+    Close,
+    /// A string literal; `closed` is false if the input ends inside it.
+    String { closed: bool },
+    /// `; ...` to the end of the line, or a `#| ... |#` comment, which
+    /// may nest.
+    Comment { closed: bool },
+    /// `#\c`.
+    Char,
+    /// A run of anything else: a symbol, number, keyword, `#_`, ...
+    Atom,
+    Space,
+}
+
+/// Split `input` into byte ranges with their lexeme. Everything is
+/// covered, so the pieces join back into `input`.
+pub fn lex(input: &str) -> Vec<(Lexeme, Range<usize>)> {
+    let mut out = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let lexeme = match c {
+            '(' | '[' | '{' => Lexeme::Open,
+            ')' | ']' | '}' => Lexeme::Close,
+            '"' => {
+                let mut escaped = false;
+                let mut closed = false;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => {
+                            closed = true;
+                            break;
+                        }
+                        _ => escaped = false,
+                    }
+                }
+                Lexeme::String { closed }
+            }
+            ';' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                Lexeme::Comment { closed: true }
+            }
+            '#' if chars.next_if(|&(_, c)| c == '|').is_some() => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some((_, '|')) if chars.next_if(|&(_, c)| c == '#').is_some() => depth -= 1,
+                        Some((_, '#')) if chars.next_if(|&(_, c)| c == '|').is_some() => depth += 1,
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                Lexeme::Comment { closed: depth == 0 }
+            }
+            '#' if chars.next_if(|&(_, c)| c == '\\').is_some() => {
+                chars.next();
+                Lexeme::Char
+            }
+            c if is_space(c) => {
+                while chars.next_if(|&(_, c)| is_space(c)).is_some() {}
+                Lexeme::Space
+            }
+            _ => {
+                while chars.next_if(|&(_, c)| !is_space(c) && !"()[]{}\";".contains(c)).is_some() {}
+                Lexeme::Atom
+            }
+        };
+        let end = chars.peek().map_or(input.len(), |&(i, _)| i);
+        out.push((lexeme, start..end));
+    }
+    out
+}
@@ -1,13 +1,20 @@
 pub mod error;
 pub mod expr;
+pub mod lexer;
+pub mod reader;
 pub mod types;
 
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
 use crate::ast::Expr;
+use nom::IResult;
+use nom::bytes::complete::{take_while, take_while1};
 
 pub fn parse(input: &str) -> Result<Expr, error::ParseError> {
-    match expr::parse_expr(input) {
+    match expr::parse_expr(input).and_then(|(remaining, expr)| Ok((ws0(remaining)?.0, expr))) {
         Ok((remaining, expr)) => {
-            if remaining.trim().is_empty() {
+            if remaining.is_empty() {
                 Ok(expr)
             } else {
                 Err(error::ParseError::UnexpectedInput(remaining.to_string()))
@@ -17,3 +24,205 @@ pub fn parse(input: &str) -> Result<Expr, error::ParseError> {
     }
 }
 
+/// Every top-level form in `input`, in order. Unlike `parse`, several
+/// forms (or none) are fine.
+pub fn parse_program(input: &str) -> Result<Vec<Expr>, error::ParseError> {
+    let mut forms = Vec::new();
+    let (mut rest, _) = ws0(input)?;
+    while !rest.is_empty() {
+        let (remaining, expr) = expr::parse_expr(rest)?;
+        forms.push(expr);
+        rest = ws0(remaining)?.0;
+    }
+    Ok(forms)
+}
+
+/// Whether `c` separates forms. Commas count, as in Clojure, so
+/// `[x: i32, y: i32]` and `(f 1, 2, 3)` read as if they weren't there.
+pub(crate) fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n' | ',')
+}
+
+/// Optional space between forms. Comments count as space: `;` to the end
+/// of the line, and `#| ... |#`, which may nest. So do a `#_` and the
+/// form after it: that's how a single form is commented out. So does a
+/// `#?(...)` that has nothing for the current backend.
+pub(crate) fn ws0(input: &str) -> IResult<&str, &str, error::ParseError> {
+    let mut rest = input;
+    loop {
+        let (after, _) = take_while(is_space)(rest)?;
+        if let Some(comment) = after.strip_prefix(';') {
+            rest = &comment[comment.find('\n').unwrap_or(comment.len())..];
+            continue;
+        }
+        if after.starts_with("#|") {
+            rest = skip_block_comment(after)?;
+            continue;
+        }
+        // A `#?(...)` with no branch for this backend reads as nothing.
+        if after.starts_with("#?(")
+            && let (skipped, None) = expr::parse_conditional(&after[2..])?
+        {
+            rest = skipped;
+            continue;
+        }
+        let Some(discarded) = after.strip_prefix("#_") else {
+            rest = after;
+            break;
+        };
+        rest = match expr::parse_expr(discarded) {
+            Ok((after, _)) => after,
+            Err(nom::Err::Error(_)) => {
+                return Err(nom::Err::Failure(error::ParseError::UnexpectedInput(
+                    "`#_` needs a form after it to discard".to_string(),
+                )));
+            }
+            Err(e) => return Err(e),
+        };
+    }
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+/// What's after the `#| ... |#` `input` starts with, nested ones included.
+fn skip_block_comment(input: &str) -> Result<&str, nom::Err<error::ParseError>> {
+    let mut depth = 0;
+    let mut rest = input;
+    while let Some(at) = rest.find(['#', '|']) {
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("#|") {
+            depth += 1;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("|#") {
+            depth -= 1;
+            rest = after;
+            if depth == 0 {
+                return Ok(rest);
+            }
+        } else {
+            rest = &rest[1..];
+        }
+    }
+    Err(nom::Err::Failure(error::ParseError::UnexpectedInput("`#|` comment is never closed with `|#`".to_string())))
+}
+
+/// Required space between forms. A comment on its own will do.
+pub(crate) fn ws1(input: &str) -> IResult<&str, &str, error::ParseError> {
+    if input.starts_with(';') || input.starts_with("#|") {
+        return ws0(input);
+    }
+    let (rest, _) = take_while1(is_space)(input)?;
+    let (rest, _) = ws0(rest)?;
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+/// How many forms deep `set_max_depth` lets input nest unless told
+/// otherwise. The parser recurses once per level, so without a limit a
+/// long enough run of `(` overflows the stack. So does the type checker,
+/// which in a debug build takes up to about 50 KiB a level: this many
+/// fit in `STACK_SIZE`.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// The stack a thread needs to check and run input `DEFAULT_MAX_DEPTH`
+/// deep: what a main thread gets. Threads that do either ask for it.
+pub const STACK_SIZE: usize = 8 << 20;
+
+thread_local! {
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// From now on, fail with `ParseError::TooDeep` on forms nested more
+/// than `depth` deep.
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.with(|max| max.set(depth));
+}
+
+/// One level of nesting, given back when dropped.
+pub(crate) struct Nesting(());
+
+impl Nesting {
+    pub(crate) fn enter() -> Result<Nesting, nom::Err<error::ParseError>> {
+        let limit = MAX_DEPTH.with(Cell::get);
+        let depth = DEPTH.with(|depth| {
+            depth.set(depth.get() + 1);
+            depth.get()
+        });
+        let nesting = Nesting(());
+        if depth > limit {
+            return Err(nom::Err::Failure(error::ParseError::TooDeep(limit)));
+        }
+        Ok(nesting)
+    }
+}
+
+impl Drop for Nesting {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// The execution backend `#?(:interp ... :jit ...)` picks a branch for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The tree-walking interpreter (`rusp`, `rusp run`).
+    Interp,
+    /// LLVM: the `--llvm` REPL and `rusp build`.
+    Jit,
+}
+
+impl Backend {
+    /// The `#?` key that selects this backend.
+    pub fn key(self) -> &'static str {
+        match self {
+            Backend::Interp => "interp",
+            Backend::Jit => "jit",
+        }
+    }
+}
+
+thread_local! {
+    static BACKEND: Cell<Backend> = const { Cell::new(Backend::Interp) };
+}
+
+/// From now on, read `#?(...)` for `backend`.
+pub fn set_backend(backend: Backend) {
+    BACKEND.with(|b| b.set(backend));
+}
+
+pub(crate) fn backend() -> Backend {
+    BACKEND.with(Cell::get)
+}
+
+/// Characters that can start a reader macro; every other character
+/// already means something at the start of a form.
+pub const READER_MACRO_CHARS: &str = "$@^~'`";
+
+thread_local! {
+    /// Reader macro character → the function its forms expand to a call of.
+    static READER_MACROS: RefCell<HashMap<char, String>> = RefCell::new(HashMap::new());
+}
+
+/// From now on, read `$text` (or `$"text"`) as `(handler "text")`.
+pub fn set_reader_macro(ch: char, handler: &str) -> Result<(), String> {
+    if !READER_MACRO_CHARS.contains(ch) {
+        return Err(format!(
+            "set-reader-macro!: `{}` can't start a reader macro; use one of {}",
+            ch, READER_MACRO_CHARS
+        ));
+    }
+    READER_MACROS.with(|macros| macros.borrow_mut().insert(ch, handler.to_string()));
+    Ok(())
+}
+
+pub(crate) fn reader_macro(ch: char) -> Option<String> {
+    READER_MACROS.with(|macros| macros.borrow().get(&ch).cloned())
+}
+
+/// Run `read`, then forget the reader macros it set: a file's reader
+/// macros apply to the rest of that file only.
+pub fn with_reader_scope<T>(read: impl FnOnce() -> T) -> T {
+    let saved = READER_MACROS.with(|macros| macros.borrow().clone());
+    let result = read();
+    READER_MACROS.with(|macros| *macros.borrow_mut() = saved);
+    result
+}
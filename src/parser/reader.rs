@@ -0,0 +1,95 @@
+//! Reading forms from a stream, one at a time.
+//!
+//! `parse` wants the whole text up front. A `Reader` instead pulls lines
+//! from its input only while the form it's reading is unfinished, so a
+//! form is handed on as soon as its last line arrives: a script piped
+//! into `rusp run -`, or a REPL on the far end of a socket, runs as it's
+//! typed.
+
+use std::io::BufRead;
+
+use crate::ast::Expr;
+use crate::parser::error::ParseError;
+use crate::parser::lexer::{Lexeme, lex};
+use crate::parser::{expr, is_space, ws0};
+
+pub struct Reader<R> {
+    input: R,
+    /// Text read but not yet parsed; always starts at a form boundary.
+    buffer: String,
+    eof: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Self {
+        Reader { input, buffer: String::new(), eof: false }
+    }
+
+    /// The next form, or None once the input ends. After a parse error
+    /// the rest of the buffered text is dropped and reading carries on
+    /// with the next line.
+    pub fn next_form(&mut self) -> Result<Option<Expr>, String> {
+        loop {
+            let attempt = ws0(&self.buffer).and_then(|(rest, _)| {
+                if rest.is_empty() {
+                    Ok(None)
+                } else {
+                    expr::parse_expr(rest).map(|(after, form)| Some((form, after.len())))
+                }
+            });
+            match attempt {
+                Ok(None) if self.eof => {
+                    self.buffer.clear();
+                    return Ok(None);
+                }
+                // A form that runs to the end of the buffer might be a
+                // number or symbol the next line carries on.
+                Ok(Some((form, left))) if left > 0 || self.eof => {
+                    self.buffer.drain(..self.buffer.len() - left);
+                    return Ok(Some(form));
+                }
+                Err(e) if self.eof || is_complete(&self.buffer) => {
+                    self.buffer.clear();
+                    return Err(format!("parse error: {}", ParseError::from(e)));
+                }
+                _ => self.fill()?,
+            }
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), String> {
+        let read = self.input.read_line(&mut self.buffer).map_err(|e| format!("read error: {}", e))?;
+        self.eof = read == 0;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Expr, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_form().transpose()
+    }
+}
+
+/// Returns true when `input` is ready to be parsed as a complete form.
+///
+/// A form is complete when every open `(` / `[` has been closed, we are
+/// not currently inside a string literal or `#| |#` comment, and no `#_`
+/// is still waiting for the form it discards. Brackets inside strings,
+/// comments and `#\c` characters are ignored. If the
+/// user has typed more closers than openers the form is also considered
+/// "complete" — we let the parser produce the real error rather than
+/// deadlocking the REPL.
+pub fn is_complete(input: &str) -> bool {
+    let mut depth: i32 = 0;
+    for (lexeme, _) in lex(input) {
+        match lexeme {
+            Lexeme::Open => depth += 1,
+            Lexeme::Close => depth -= 1,
+            Lexeme::String { closed: false } | Lexeme::Comment { closed: false } => return false,
+            _ => {}
+        }
+    }
+    depth <= 0 && !input.trim_end_matches(is_space).ends_with("#_")
+}
@@ -1,22 +1,55 @@
 use crate::ast::Type;
+use crate::parser::{ws0, ws1};
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{char, multispace0},
-    combinator::value,
-    multi::separated_list0,
-    sequence::{delimited, tuple},
+    bytes::complete::{tag, take_while},
+    character::complete::{char, digit1, multispace0, satisfy},
+    combinator::{recognize, value},
+    multi::{many0, separated_list0},
+    sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
 
 pub fn parse_type_annotation(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let _nesting = crate::parser::Nesting::enter()?;
     alt((
         parse_function_type,
+        parse_paren_function_type,
+        parse_paren_list_type,
         parse_list_type,
+        parse_ref_type,
+        parse_gen_type,
+        parse_pair_type,
+        parse_array_type,
+        parse_paren_array_type,
+        parse_slice_type,
+        parse_paren_slice_type,
+        parse_vector_type,
+        parse_paren_vector_type,
+        parse_map_type,
+        parse_paren_map_type,
+        parse_named_type,
         parse_basic_type,
     ))(input)
 }
 
+/// A type declared with `deftype`: any capitalized name that isn't one
+/// of the built-in types.
+fn parse_named_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (rest, name) = recognize(pair(
+        satisfy(|c| c.is_ascii_uppercase()),
+        take_while(|c: char| c.is_alphanumeric() || c == '_'),
+    ))(input)?;
+    let builtin = matches!(parse_basic_type(name), Ok(("", _))) || ["List", "Ref", "Gen", "Pair", "Array", "Slice", "Vec", "Map"].contains(&name);
+    if builtin {
+        return Err(nom::Err::Error(crate::parser::error::ParseError::UnexpectedInput(format!(
+            "`{}` is a built-in type",
+            name
+        ))));
+    }
+    Ok((rest, Type::Named(name.to_string())))
+}
+
 fn parse_list_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
     let (input, _) = tag("List")(input)?;
     let (input, _) = char('<')(input)?;
@@ -26,13 +59,146 @@ fn parse_list_type(input: &str) -> IResult<&str, Type, crate::parser::error::Par
     Ok((input, Type::List(Box::new(inner_type))))
 }
 
+fn parse_ref_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tag("Ref")(input)?;
+    let (input, _) = char('<')(input)?;
+    let (input, inner_type) = parse_type_annotation(input)?;
+    let (input, _) = char('>')(input)?;
+    
+    Ok((input, Type::Ref(Box::new(inner_type))))
+}
+
+fn parse_gen_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tag("Gen")(input)?;
+    let (input, _) = char('<')(input)?;
+    let (input, inner_type) = parse_type_annotation(input)?;
+    let (input, _) = char('>')(input)?;
+    
+    Ok((input, Type::Gen(Box::new(inner_type))))
+}
+
+fn parse_pair_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tag("Pair")(input)?;
+    let (input, _) = char('<')(input)?;
+    let (input, head) = parse_type_annotation(input)?;
+    let (input, _) = tuple((multispace0, char(','), multispace0))(input)?;
+    let (input, tail) = parse_type_annotation(input)?;
+    let (input, _) = char('>')(input)?;
+    
+    Ok((input, Type::Pair(Box::new(head), Box::new(tail))))
+}
+
+fn parse_array_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tag("Array")(input)?;
+    let (input, _) = char('<')(input)?;
+    let (input, elem) = parse_type_annotation(input)?;
+    let (input, _) = tuple((multispace0, char(','), multispace0))(input)?;
+    let (input, len) = parse_length(input)?;
+    let (input, _) = char('>')(input)?;
+
+    Ok((input, Type::Array(Box::new(elem), len)))
+}
+
+/// `(Array i32 4)`: the same type as `Array<i32, 4>`.
+fn parse_paren_array_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tuple((char('('), multispace0, tag("Array"), ws1))(input)?;
+    let (input, elem) = parse_type_annotation(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, len) = parse_length(input)?;
+    let (input, _) = tuple((ws0, char(')')))(input)?;
+
+    Ok((input, Type::Array(Box::new(elem), len)))
+}
+
+fn parse_slice_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tag("Slice")(input)?;
+    let (input, _) = char('<')(input)?;
+    let (input, elem) = parse_type_annotation(input)?;
+    let (input, _) = char('>')(input)?;
+
+    Ok((input, Type::Slice(Box::new(elem))))
+}
+
+/// `(Slice i32)`: the same type as `Slice<i32>`.
+fn parse_paren_slice_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tuple((char('('), multispace0, tag("Slice"), ws1))(input)?;
+    let (input, elem) = parse_type_annotation(input)?;
+    let (input, _) = tuple((ws0, char(')')))(input)?;
+
+    Ok((input, Type::Slice(Box::new(elem))))
+}
+
+fn parse_vector_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tag("Vec")(input)?;
+    let (input, _) = char('<')(input)?;
+    let (input, elem) = parse_type_annotation(input)?;
+    let (input, _) = char('>')(input)?;
+
+    Ok((input, Type::Vector(Box::new(elem))))
+}
+
+/// `(Vec i32)`: the same type as `Vec<i32>`.
+fn parse_paren_vector_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tuple((char('('), multispace0, tag("Vec"), ws1))(input)?;
+    let (input, elem) = parse_type_annotation(input)?;
+    let (input, _) = tuple((ws0, char(')')))(input)?;
+
+    Ok((input, Type::Vector(Box::new(elem))))
+}
+
+fn parse_map_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tag("Map")(input)?;
+    let (input, _) = char('<')(input)?;
+    let (input, key) = parse_type_annotation(input)?;
+    let (input, _) = tuple((multispace0, char(','), multispace0))(input)?;
+    let (input, value) = parse_type_annotation(input)?;
+    let (input, _) = char('>')(input)?;
+
+    Ok((input, Type::Map(Box::new(key), Box::new(value))))
+}
+
+/// `(Map Keyword i32)`: the same type as `Map<Keyword, i32>`.
+fn parse_paren_map_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tuple((char('('), multispace0, tag("Map"), ws1))(input)?;
+    let (input, key) = parse_type_annotation(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, value) = parse_type_annotation(input)?;
+    let (input, _) = tuple((ws0, char(')')))(input)?;
+
+    Ok((input, Type::Map(Box::new(key), Box::new(value))))
+}
+
+/// An array type's length: a literal count.
+fn parse_length(input: &str) -> IResult<&str, usize, crate::parser::error::ParseError> {
+    let (rest, digits) = digit1(input)?;
+    match digits.parse() {
+        Ok(len) => Ok((rest, len)),
+        Err(_) => Err(nom::Err::Failure(crate::parser::error::ParseError::InvalidNumber(format!(
+            "array length {} is too large",
+            digits
+        )))),
+    }
+}
+
 fn parse_basic_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
     alt((
         value(Type::I32, tag("i32")),
         value(Type::I64, tag("i64")),
         value(Type::F64, tag("f64")),
         value(Type::Bool, tag("bool")),
+        // Longest match first: `String` is a prefix of `StringBuilder`.
+        value(Type::StringBuilder, tag("StringBuilder")),
         value(Type::String, tag("String")),
+        value(Type::Bytes, tag("Bytes")),
+        value(Type::DateTime, tag("DateTime")),
+        value(Type::ZonedDateTime, tag("ZonedDateTime")),
+        value(Type::Duration, tag("Duration")),
+        value(Type::KvStore, tag("KvStore")),
+        value(Type::Matrix, tag("Matrix")),
+        value(Type::Keyword, tag("Keyword")),
+        value(Type::Port, tag("Port")),
+        value(Type::Type, tag("Type")),
+        value(Type::Unit, tag("Unit")),
         value(Type::Inferred, tag("_")),
     ))(input)
 }
@@ -57,4 +223,28 @@ fn parse_function_type(input: &str) -> IResult<&str, Type, crate::parser::error:
         params,
         return_type: Box::new(return_type),
     }))
-}
\ No newline at end of file
+}
+
+/// `(fn (i32 i32) -> i32)`: the same type as `fn(i32, i32) -> i32`,
+/// written the way the rest of the program is.
+fn parse_paren_function_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tuple((char('('), multispace0, tag("fn"), multispace0, char('(')))(input)?;
+    let (input, params) = many0(preceded(ws0, parse_type_annotation))(input)?;
+    let (input, _) = tuple((ws0, char(')'), ws0, tag("->"), ws0))(input)?;
+    let (input, return_type) = parse_type_annotation(input)?;
+    let (input, _) = tuple((ws0, char(')')))(input)?;
+
+    Ok((input, Type::Function {
+        params,
+        return_type: Box::new(return_type),
+    }))
+}
+
+/// `(List i32)`: the same type as `List<i32>`.
+fn parse_paren_list_type(input: &str) -> IResult<&str, Type, crate::parser::error::ParseError> {
+    let (input, _) = tuple((char('('), multispace0, tag("List"), ws1))(input)?;
+    let (input, elem) = parse_type_annotation(input)?;
+    let (input, _) = tuple((ws0, char(')')))(input)?;
+
+    Ok((input, Type::List(Box::new(elem))))
+}
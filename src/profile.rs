@@ -0,0 +1,238 @@
+//! Allocation profiling (`rusp run --profile-alloc`).
+//!
+//! While a profile is running, the interpreter reports what it allocates:
+//! values it builds (closures, lists, strings, builtin results), values
+//! it copies out of a variable, and scopes it creates when a call or
+//! `let` needs one of its own. Each allocation is charged to a site:
+//! the function it happened in, or the top-level form when it's outside
+//! any `defn`. The scope a call creates is charged to the caller.
+//! Byte counts are estimates (the size of what was copied, not what the
+//! allocator actually handed out), good for comparing sites against each
+//! other.
+//!
+//! Like `tier`, the state is a thread-local so every `Environment`
+//! reports into the same profile.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::size_of;
+
+use crate::env::Value;
+
+#[derive(Default)]
+struct Profile {
+    /// The top-level form being evaluated, as set by `set_site`.
+    form: String,
+    /// One entry per function call in progress: the site its
+    /// allocations are charged to.
+    calls: Vec<String>,
+    /// Where each `defn` was defined, to name its site by.
+    defined: HashMap<String, String>,
+    counts: HashMap<(String, &'static str), Counts>,
+}
+
+impl Profile {
+    fn site(&self) -> &str {
+        self.calls.last().unwrap_or(&self.form)
+    }
+}
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static PROFILE: RefCell<Profile> = RefCell::new(Profile::default());
+}
+
+/// How much was allocated at one site.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+/// One row of a `Report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Site {
+    /// `NAME (FILE:LINE:COL)` for a `defn`, otherwise whatever
+    /// `set_site` was given for the top-level form.
+    pub site: String,
+    /// `environment`, `clone`, or the `type-of` name of a value built.
+    pub kind: &'static str,
+    pub counts: Counts,
+}
+
+/// What `stop` gives back: every site and kind that allocated, most
+/// bytes first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub sites: Vec<Site>,
+}
+
+impl Report {
+    /// A table of the first `n` sites.
+    pub fn top(&self, n: usize) -> String {
+        let mut out = format!("{:>12} {:>10}  site", "bytes", "count");
+        for site in self.sites.iter().take(n) {
+            out.push_str(&format!(
+                "\n{:>12} {:>10}  {}: {}",
+                site.counts.bytes, site.counts.count, site.site, site.kind
+            ));
+        }
+        if self.sites.len() > n {
+            out.push_str(&format!("\n({} more)", self.sites.len() - n));
+        }
+        out
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.top(self.sites.len()))
+    }
+}
+
+/// Start a fresh profile on this thread.
+pub fn start() {
+    PROFILE.with(|profile| *profile.borrow_mut() = Profile::default());
+    ENABLED.with(|enabled| enabled.set(true));
+}
+
+/// Stop profiling and return what was recorded since `start`.
+pub fn stop() -> Report {
+    ENABLED.with(|enabled| enabled.set(false));
+    let profile = PROFILE.with(|profile| std::mem::take(&mut *profile.borrow_mut()));
+    let mut sites: Vec<Site> =
+        profile.counts.into_iter().map(|((site, kind), counts)| Site { site, kind, counts }).collect();
+    sites.sort_by(|a, b| {
+        (b.counts.bytes, b.counts.count).cmp(&(a.counts.bytes, a.counts.count)).then_with(|| {
+            (&a.site, a.kind).cmp(&(&b.site, b.kind))
+        })
+    });
+    Report { sites }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+/// Charge what happens from now on outside any function to `site`
+/// (e.g. a top-level form's `FILE:LINE:COL`). Calls left unfinished by
+/// an error are forgotten.
+pub fn set_site(site: String) {
+    if enabled() {
+        PROFILE.with(|profile| {
+            let mut profile = profile.borrow_mut();
+            profile.form = site;
+            profile.calls.clear();
+        });
+    }
+}
+
+/// `name` was just defined by a `defn`; its allocations are charged to
+/// it from now on.
+pub(crate) fn define(name: &str) {
+    PROFILE.with(|profile| {
+        let mut profile = profile.borrow_mut();
+        let site = format!("{} ({})", name, profile.form);
+        profile.defined.insert(name.to_string(), site);
+    });
+}
+
+/// A call to `name` started (None: a `fn`, which is charged to whoever
+/// called it). Pairs with `leave`.
+pub(crate) fn enter(name: Option<&str>) {
+    PROFILE.with(|profile| {
+        let mut profile = profile.borrow_mut();
+        let site = match name.and_then(|name| profile.defined.get(name)) {
+            Some(site) => site.clone(),
+            None => profile.site().to_string(),
+        };
+        profile.calls.push(site);
+    });
+}
+
+pub(crate) fn leave() {
+    PROFILE.with(|profile| profile.borrow_mut().calls.pop());
+}
+
+/// `bytes` were allocated for a `kind` at the current site.
+pub(crate) fn record(kind: &'static str, bytes: usize) {
+    PROFILE.with(|profile| {
+        let mut profile = profile.borrow_mut();
+        let site = profile.site().to_string();
+        let counts = profile.counts.entry((site, kind)).or_default();
+        counts.count += 1;
+        counts.bytes += bytes as u64;
+    });
+}
+
+/// `value` was just built.
+pub(crate) fn record_value(value: &Value) {
+    let bytes = owned_bytes(value);
+    if bytes > 0 {
+        record(kind_name(value), bytes);
+    }
+}
+
+/// `value` was copied; only copies that allocate are counted.
+pub(crate) fn record_clone(value: &Value) {
+    let bytes = copied_bytes(value);
+    if bytes > 0 {
+        record("clone", bytes);
+    }
+}
+
+fn kind_name(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "String",
+        Value::StringBuilder(_) => "StringBuilder",
+        Value::Bytes(_) => "Bytes",
+        Value::Array(_) => "Array",
+        Value::Slice { .. } => "Slice",
+        Value::Vector(_) => "Vec",
+        Value::Map(_) => "Map",
+        Value::Matrix(_) => "Matrix",
+        Value::DateTime(_) => "DateTime",
+        Value::Duration(_) => "Duration",
+        Value::Ref(_) => "Ref",
+        Value::Generator(_) => "Gen",
+        Value::Function(_) => "function",
+        Value::List(_) => "list",
+        Value::Pair(_) => "pair",
+        _ => "value",
+    }
+}
+
+/// Heap a freshly built `value` owns. Elements of a list are counted
+/// with it, since a builtin that returns a list built those too.
+fn owned_bytes(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::StringBuilder(sb) => size_of::<String>() + sb.borrow().capacity(),
+        Value::Bytes(b) => size_of::<Vec<u8>>() + b.borrow().capacity(),
+        Value::Array(items) | Value::Vector(items) => {
+            let items = items.borrow();
+            size_of::<Vec<Value>>() + items.len() * size_of::<Value>() + items.iter().map(owned_bytes).sum::<usize>()
+        }
+        Value::Matrix(m) => {
+            let m = m.borrow();
+            size_of::<crate::matrix::Matrix>() + m.rows() * m.cols() * size_of::<f64>()
+        }
+        Value::Ref(_) => size_of::<Value>(),
+        Value::Pair(pair) => 2 * size_of::<Value>() + owned_bytes(&pair.0) + owned_bytes(&pair.1),
+        // The scope a closure captures is shared, not built for it.
+        Value::Function(_) => size_of::<crate::env::Closure>(),
+        _ => copied_bytes(value),
+    }
+}
+
+/// Heap that cloning `value` allocates: a list's elements are copied;
+/// strings and everything behind an `Rc` are shared.
+fn copied_bytes(value: &Value) -> usize {
+    match value {
+        Value::List(items) => items.len() * size_of::<Value>() + items.iter().map(copied_bytes).sum::<usize>(),
+        Value::DateTime(_) => size_of::<crate::datetime::DateTime>(),
+        Value::Duration(_) => size_of::<crate::datetime::Duration>(),
+        _ => 0,
+    }
+}
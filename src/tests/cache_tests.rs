@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::cache::{Cache, key};
+    use crate::modules::Loader;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusp-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn build_key(dir: &Path, options: &[&str]) -> String {
+        let mut loader = Loader::new();
+        loader.load(&dir.join("main.rusp").to_string_lossy()).unwrap();
+        key(loader.files(), options).unwrap()
+    }
+
+    #[test]
+    fn test_key_covers_imports() {
+        let dir = scratch("key");
+        std::fs::write(dir.join("util.rusp"), "(defn twice [x: i32] -> i32 (* 2 x))").unwrap();
+        std::fs::write(dir.join("main.rusp"), "(import util)\n(defn main [] -> i32 (util/twice 21))").unwrap();
+        let before = build_key(&dir, &["o"]);
+        assert_eq!(build_key(&dir, &["o"]), before);
+        assert_ne!(build_key(&dir, &["o", "-g"]), before);
+        assert_ne!(build_key(&dir, &["ll"]), before);
+
+        // Only the imported module changed.
+        std::fs::write(dir.join("util.rusp"), "(defn twice [x: i32] -> i32 (+ x x))").unwrap();
+        assert_ne!(build_key(&dir, &["o"]), before);
+    }
+
+    #[test]
+    fn test_key_covers_embedded_files() {
+        let dir = scratch("embed");
+        std::fs::write(dir.join("banner.txt"), "hello").unwrap();
+        std::fs::write(dir.join("main.rusp"), "(defn main [] -> String (embed-file \"banner.txt\"))").unwrap();
+        let before = build_key(&dir, &["o"]);
+        std::fs::write(dir.join("banner.txt"), "goodbye").unwrap();
+        assert_ne!(build_key(&dir, &["o"]), before);
+    }
+
+    #[test]
+    fn test_fetch_and_store() {
+        let dir = scratch("store");
+        let cache = Cache::new(dir.join("cache"));
+        let built = dir.join("main.rusp.o");
+        std::fs::write(&built, b"object").unwrap();
+        let out = dir.join("copy.o");
+        assert!(!cache.fetch("0123", "o", &out).unwrap());
+        cache.store("0123", "o", &built).unwrap();
+        assert!(cache.fetch("0123", "o", &out).unwrap());
+        assert_eq!(std::fs::read(&out).unwrap(), b"object");
+        // Same key, other kind of artifact.
+        assert!(!cache.fetch("0123", "ll", &out).unwrap());
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use crate::check::{self, Finding};
+    use crate::modules::{Loader, SourceForm};
+
+    fn forms(name: &str, source: &str) -> Vec<SourceForm> {
+        let path = std::env::temp_dir().join(format!("rusp-check-{}-{}.rusp", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let forms = Loader::new().load(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        forms
+    }
+
+    #[test]
+    fn test_groups() {
+        let forms = forms(
+            "groups",
+            "(defn a [] -> i32 1)\n(defn b [] -> i32 2)\n(+ (a) 1)\n(do (defn c [] -> i32 3))\n(c)\n(defn a [] -> i32 4)\n(b)",
+        );
+        // `(+ (a) 1)` uses a, the `do` defines c for `(c)`, and a second
+        // `a` goes with the first.
+        assert_eq!(check::groups(&forms), vec![vec![0, 2, 5], vec![1, 6], vec![3, 4]]);
+    }
+
+    #[test]
+    fn test_forward_reference() {
+        let source = "(defn main [] -> bool (ev? 4))\n\
+            (defn ev? [n: i32] -> bool (if (= n 0) true (od? (- n 1))))\n\
+            (defn od? [n: i32] -> bool (if (= n 0) false (ev? (- n 1))))";
+        let forms = forms("forward", source);
+        // Calling a `defn` before it comes still ties the caller to it.
+        assert_eq!(check::groups(&forms), vec![vec![0, 1, 2]]);
+        assert!(check::check(forms, 2).is_empty());
+    }
+
+    #[test]
+    fn test_deftype_groups_with_its_uses() {
+        let source = "(deftype Shape (Circle f64) Dot)\n\
+            (defn area [s: Shape] -> f64 (match s ((Circle r) (* r r)) (Dot 0.0)))\n\
+            (defn dot [] -> Shape Dot)\n\
+            (def a (area (Circle 2.0)))";
+        let forms = forms("deftype", source);
+        // The annotations, the pattern and the constructors all tie back
+        // to the `deftype`.
+        assert_eq!(check::groups(&forms), vec![vec![0, 1, 2, 3]]);
+        assert!(check::check(forms, 4).is_empty());
+    }
+
+    #[test]
+    fn test_defstruct_groups_with_its_uses() {
+        let source = "(defstruct Point [x: f64 y: f64])\n\
+            (defn px [p: Point] -> f64 (.x p))\n\
+            (def x (px (Point 1.0 2.0)))";
+        let forms = forms("defstruct", source);
+        assert_eq!(check::groups(&forms), vec![vec![0, 1, 2]]);
+        assert!(check::check(forms, 4).is_empty());
+    }
+
+    #[test]
+    fn test_deprecated_calls_point_at_the_call() {
+        let source = "(defn ^{:deprecated \"use g\"} old [] -> i32 1)\n\
+            (defn f [] -> i32\n  (+ (old) ; (old)\n     (let old (fn [] -> i32 2) (old)) (old)))\n\
+            (defn h ([] -> i32 (old)) ([x: i32] -> i32 (+ x (old))))";
+        let at: Vec<(usize, usize)> = check::check(forms("deprecated", source), 1).iter().map(|f| (f.loc.line, f.loc.col)).collect();
+        assert_eq!(at, vec![(3, 6), (4, 39), (5, 20), (5, 49)]);
+    }
+
+    #[test]
+    fn test_redundant_arm_points_at_the_arm() {
+        let source = "(defn f [n: i32] -> i32\n  (match n\n    (_ (match n (x x) (0 0)))\n    (1 1)))";
+        let at: Vec<(usize, usize)> = check::check(forms("redundant", source), 1).iter().map(|f| (f.loc.line, f.loc.col)).collect();
+        assert_eq!(at, vec![(4, 5), (3, 23)]);
+    }
+
+    #[test]
+    fn test_check_in_parallel() {
+        let source = "(defn f [x: i32] -> i32 x)\n\
+            (defn ^{:deprecated \"use g\"} old [] -> i32 1)\n\
+            (f \"a\")\n\
+            (defn g [s: String] -> String s)\n\
+            (old)\n\
+            (g 1)\n\
+            (defn print [x: i32] -> i32 x)";
+        let messages = |findings: Vec<Finding>| -> Vec<String> {
+            findings.iter().map(|f| format!("{}{} {}", f.loc.line, if f.error { "E" } else { "W" }, f.message)).collect()
+        };
+        let one = messages(check::check(forms("one", source), 1));
+        assert_eq!(
+            one,
+            vec![
+                "3E Type mismatch in argument: expected i32, got String".to_string(),
+                "5W `old` is deprecated: use g [deprecated]".to_string(),
+                "5W the i32 result of `(old)` is unused [unused-result]".to_string(),
+                "6E Type mismatch in argument: expected String, got i32".to_string(),
+                format!("7W `print` shadows the builtin `print: {}` [shadow-builtin]", crate::lint::builtin_type("print").unwrap()),
+            ]
+        );
+        // However many threads, the same findings in the same order.
+        assert_eq!(messages(check::check(forms("four", source), 4)), one);
+    }
+}
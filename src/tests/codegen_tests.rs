@@ -118,6 +118,13 @@ mod tests {
         );
     }
 
+    #[test]
+    fn jit_mixed_widths_promote_to_i64() {
+        assert_eq!(jit_i64("(+ 3000000000 1)").unwrap(), 3_000_000_001);
+        assert_eq!(jit_i64("(- 1 3000000000)").unwrap(), -2_999_999_999);
+        assert!(jit_bool("(< 1 3000000000)").unwrap());
+    }
+
     #[test]
     fn jit_width_mismatch_is_caught() {
         // Asking for i32 when the body produced i64 surfaces a clean
@@ -243,22 +250,22 @@ mod tests {
 
     #[test]
     fn jit_f64_arithmetic() {
-        assert_eq!(jit_f64("(+. 1.5 2.5)").unwrap(), 4.0);
-        assert_eq!(jit_f64("(-. 5.0 1.5)").unwrap(), 3.5);
-        assert_eq!(jit_f64("(*. 2.0 3.5)").unwrap(), 7.0);
-        assert_eq!(jit_f64("(/. 7.0 2.0)").unwrap(), 3.5);
+        assert_eq!(jit_f64("(+ 1.5 2.5)").unwrap(), 4.0);
+        assert_eq!(jit_f64("(- 5.0 1.5)").unwrap(), 3.5);
+        assert_eq!(jit_f64("(* 2.0 3.5)").unwrap(), 7.0);
+        assert_eq!(jit_f64("(/ 7.0 2.0)").unwrap(), 3.5);
     }
 
     #[test]
     fn jit_f64_variadic_left_fold() {
-        assert_eq!(jit_f64("(+. 1.0 2.0 3.0 4.0)").unwrap(), 10.0);
-        assert_eq!(jit_f64("(*. 2.0 3.0 4.0)").unwrap(), 24.0);
+        assert_eq!(jit_f64("(+ 1.0 2.0 3.0 4.0)").unwrap(), 10.0);
+        assert_eq!(jit_f64("(* 2.0 3.0 4.0)").unwrap(), 24.0);
     }
 
     #[test]
     fn jit_f64_nested() {
-        assert_eq!(jit_f64("(*. (+. 1.0 2.0) 3.0)").unwrap(), 9.0);
-        assert_eq!(jit_f64("(+. (*. 2.0 3.0) (-. 10.0 4.0))").unwrap(), 12.0);
+        assert_eq!(jit_f64("(* (+ 1.0 2.0) 3.0)").unwrap(), 9.0);
+        assert_eq!(jit_f64("(+ (* 2.0 3.0) (- 10.0 4.0))").unwrap(), 12.0);
     }
 
     #[test]
@@ -272,6 +279,15 @@ mod tests {
         assert!(jit_bool("(>= 2.0 1.0)").unwrap());
     }
 
+    #[test]
+    fn jit_f64_dotted_comparison() {
+        assert!(jit_bool("(= 1.5 1.5)").unwrap());
+        assert!(jit_bool("(< 1.5 2.5)").unwrap());
+        assert!(!jit_bool("(> 1.5 2.5)").unwrap());
+        assert!(jit_bool("(<= 2.5 2.5)").unwrap());
+        assert!(jit_bool("(>= 3.0 2.5)").unwrap());
+    }
+
     #[test]
     fn jit_f64_with_if() {
         // `if` returning f64 — phi merge on f64 type.
@@ -282,12 +298,13 @@ mod tests {
     }
 
     #[test]
-    fn jit_int_op_on_float_is_error() {
-        // `+` on floats is rejected by the type checker, but the codegen
-        // path also rejects it defensively.
-        let err = jit_f64("(+ 1.0 2.0)").unwrap_err();
-        // The type checker fires first ("expected i32" or similar).
-        assert!(!err.is_empty());
+    fn jit_arith_dispatches_on_operand_kind() {
+        // `+` on two floats is float addition.
+        assert_eq!(jit_f64("(+ 1.0 2.0)").unwrap(), 3.0);
+        // Mixed operands are rejected by the checker, and the codegen
+        // path rejects them defensively too, whichever side comes first.
+        assert!(!jit_f64("(+ 1.0 2)").unwrap_err().is_empty());
+        assert!(!jit_i32("(+ 1 2.0)").unwrap_err().is_empty());
     }
 
     // ----------- Step 6: let-in -----------
@@ -303,7 +320,7 @@ mod tests {
     #[test]
     fn jit_let_in_basic_f64() {
         assert_eq!(jit_f64("(let half 0.5 half)").unwrap(), 0.5);
-        assert_eq!(jit_f64("(let r 2.0 (*. r r))").unwrap(), 4.0);
+        assert_eq!(jit_f64("(let r 2.0 (* r r))").unwrap(), 4.0);
     }
 
     #[test]
@@ -428,7 +445,7 @@ mod tests {
     #[test]
     fn jit_defn_returns_f64() {
         let src = r#"
-            (defn area [r: f64] -> f64 (*. r r))
+            (defn area [r: f64] -> f64 (* r r))
             (area 3.0)
         "#;
         assert_eq!(jit_f64_prog(src).unwrap(), 9.0);
@@ -446,6 +463,27 @@ mod tests {
         assert_eq!(jit_i32_prog(src).unwrap(), 120);
     }
 
+    #[test]
+    fn jit_defn_early_return() {
+        // Each `return` is its own `ret`; the `if` merges only the arm
+        // that falls through.
+        let src = r#"
+            (defn clamp [x: i32] -> i32
+              (if (< x 0) (return 0) (if (> x 10) (return 10) x)))
+            (+ (clamp -5) (+ (clamp 50) (clamp 7)))
+        "#;
+        assert_eq!(jit_i32_prog(src).unwrap(), 17);
+    }
+
+    #[test]
+    fn jit_unary_minus() {
+        let src = r#"
+            (defn flip [x: i32] -> i32 (- x))
+            (let y 4 (+ (flip 10) -y))
+        "#;
+        assert_eq!(jit_i32_prog(src).unwrap(), -14);
+    }
+
     #[test]
     fn jit_defn_recursion_fib() {
         let src = r#"
@@ -541,12 +579,18 @@ mod tests {
 
     #[test]
     fn jit_lambda_returning_f64() {
-        let src = "(let half (fn [x: f64] -> f64 (*. x 0.5)) (half 8.0))";
+        let src = "(let half (fn [x: f64] -> f64 (* x 0.5)) (half 8.0))";
         let forms = parse_program(src);
         let r = codegen::jit_eval_f64_program(&forms).unwrap();
         assert!((r - 4.0).abs() < 1e-9, "got {}", r);
     }
 
+    #[test]
+    fn jit_lambda_params_from_let_annotation() {
+        let src = "(let twice: fn(i32) -> i32 (fn [x] (* x 2)) (twice 21))";
+        assert_eq!(jit_i32(src).unwrap(), 42);
+    }
+
     #[test]
     fn jit_lambda_inside_defn_body() {
         // Lambda emitted inside a defn — exercises the `module` /
@@ -584,7 +628,7 @@ mod tests {
 
     #[test]
     fn jit_lambda_return_type_inferred_f64() {
-        let src = "(let half (fn [x: f64] (*. x 0.5)) (half 8.0))";
+        let src = "(let half (fn [x: f64] (* x 0.5)) (half 8.0))";
         let forms = parse_program(src);
         let r = codegen::jit_eval_f64_program(&forms).unwrap();
         assert!((r - 4.0).abs() < 1e-9, "got {}", r);
@@ -696,4 +740,235 @@ mod tests {
             err
         );
     }
+
+    // -------- runtime faults + source mapping --------
+
+    #[test]
+    fn jit_division_by_zero_reports_function_and_expr() {
+        let src = r#"
+            (defn f [a: i32 b: i32] -> i32 (/ a b))
+            (f 1 0)
+        "#;
+        let err = jit_i32_prog(src).unwrap_err();
+        assert!(err.contains("division by zero"), "got: {}", err);
+        assert!(err.contains("in function `f`"), "got: {}", err);
+        assert!(err.contains("(/ a b)"), "got: {}", err);
+    }
+
+    #[test]
+    fn jit_fault_unwinds_through_callers() {
+        // The fault happens two frames down; the `+ 1` in `outer` must
+        // not mask it.
+        let src = r#"
+            (defn inner [n: i32] -> i32 (/ 10 n))
+            (defn outer [n: i32] -> i32 (+ 1 (inner n)))
+            (outer 0)
+        "#;
+        let err = jit_i32_prog(src).unwrap_err();
+        assert!(err.contains("in function `inner`"), "got: {}", err);
+    }
+
+    #[test]
+    fn jit_division_overflow_is_a_fault() {
+        let err = jit_i32("(/ -2147483648 -1)").unwrap_err();
+        assert!(err.contains("overflow"), "got: {}", err);
+        assert!(err.contains("<top-level>"), "got: {}", err);
+    }
+
+    #[test]
+    fn jit_guarded_division_still_divides() {
+        assert_eq!(jit_i32_prog("(defn h [a: i32] -> i32 (/ a 2)) (h 9)").unwrap(), 4);
+    }
+
+    #[test]
+    fn srcmap_line_col_counts_chars() {
+        use crate::codegen::srcmap::line_col;
+        let src = "(a)\n  (é) (b)";
+        assert_eq!(line_col(src, 0), (1, 1));
+        assert_eq!(line_col(src, 6), (2, 3));
+        assert_eq!(line_col(src, src.find("(b)").unwrap()), (2, 7));
+    }
+
+    #[test]
+    fn aot_fault_message_carries_source_location() {
+        let src = "(defn f [a: i32] -> i32 (/ 100 a))\n(defn main [] -> i32 (f 0))";
+        let forms = parse_program(src);
+        let opts = codegen::AotOptions {
+            source_map: Some(codegen::SourceMap::from_offsets("prog.rsp", src, &[0, 35])),
+            ..Default::default()
+        };
+        let ir = codegen::compile_to_ll_with(&forms, &opts).unwrap();
+        assert!(ir.contains("prog.rsp:1:1"), "missing location: {}", ir);
+        assert!(ir.contains("@exit"), "missing exit call: {}", ir);
+    }
+
+    #[test]
+    fn aot_debug_info_describes_functions_and_params() {
+        let src = "(defn sq [n: i32] -> i32 (* n n))\n(defn main [] -> i32 (sq 6))";
+        let forms = parse_program(src);
+        let opts = codegen::AotOptions {
+            source_map: Some(codegen::SourceMap::from_offsets("dir/sq.rsp", src, &[0, 34])),
+            debug_info: true,
+            ..Default::default()
+        };
+        let ir = codegen::compile_to_ll_with(&forms, &opts).unwrap();
+        assert!(ir.contains("!DICompileUnit"), "missing compile unit: {}", ir);
+        assert!(ir.contains(r#"!DIFile(filename: "sq.rsp", directory: "dir")"#), "{}", ir);
+        assert!(ir.contains(r#"!DISubprogram(name: "sq""#), "missing sq: {}", ir);
+        assert!(ir.contains(r#"!DILocalVariable(name: "n", arg: 1"#), "missing param: {}", ir);
+        // `main` starts on line 2 of the source.
+        assert!(ir.contains(r#"name: "main", scope:"#) && ir.contains("line: 2"), "{}", ir);
+    }
+
+    #[test]
+    fn aot_without_debug_info_has_no_dwarf() {
+        let forms = parse_program("(defn main [] -> i32 0)");
+        let ir = codegen::compile_to_ll(&forms).unwrap();
+        assert!(!ir.contains("!DICompileUnit"), "unexpected debug info: {}", ir);
+    }
+
+    #[test]
+    fn aot_target_sets_triple_and_data_layout() {
+        let forms = parse_program("(defn main [] -> i32 0)");
+        let opts = codegen::AotOptions { target: Some("aarch64-unknown-linux-gnu".into()), ..Default::default() };
+        let ir = codegen::compile_to_ll_with(&forms, &opts).unwrap();
+        assert!(ir.contains(r#"target triple = "aarch64-unknown-linux-gnu""#), "{}", ir);
+        assert!(ir.contains("target datalayout = \"e-"), "{}", ir);
+        // Host IR doesn't name a target.
+        assert!(!codegen::compile_to_ll(&forms).unwrap().contains("target triple"));
+    }
+
+    #[test]
+    fn aot_unknown_target_is_an_error() {
+        let forms = parse_program("(defn main [] -> i32 0)");
+        let opts = codegen::AotOptions { target: Some("nonsense-unknown-none".into()), ..Default::default() };
+        let err = codegen::compile_to_ll_with(&forms, &opts).unwrap_err();
+        assert!(err.contains("nonsense-unknown-none"), "{}", err);
+    }
+
+    // -------- tiered execution --------
+
+    fn eval_all(src: &str, env: &mut crate::env::Environment) -> Result<crate::env::Value, String> {
+        let mut last = crate::env::Value::Nil;
+        for form in parse_program(src) {
+            last = crate::eval::eval(&form, env)?;
+        }
+        Ok(last)
+    }
+
+    #[test]
+    fn tier_compiles_hot_defn_transparently() {
+        crate::tier::set_threshold(Some(5));
+        let mut env = crate::env::Environment::new();
+        let src = r#"
+            (defn fib [n: i32] -> i32
+              (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+            (fib 20)
+        "#;
+        let v = eval_all(src, &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Integer32(6765)));
+        assert!(crate::tier::is_compiled("fib"));
+        // It got hot deep inside the recursion; the next top-level call
+        // swaps the REPL-level binding too.
+        let v = eval_all("(fib 10)", &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Integer32(55)));
+        assert!(matches!(env.get("fib"), Some(crate::env::Value::Native(_))));
+        assert_eq!(env.get("fib").unwrap().to_string(), "#<function fib [n: i32] -> i32>");
+    }
+
+    #[test]
+    fn tier_compiles_callees_along_with_hot_fn() {
+        crate::tier::set_threshold(Some(2));
+        let mut env = crate::env::Environment::new();
+        let src = r#"
+            (defn half [x: f64] -> f64 (/ x 2.0))
+            (defn shrink [x: f64 n: i32] -> f64
+              (if (= n 0) x (shrink (half x) (- n 1))))
+            (shrink 1024.0 10)
+        "#;
+        let v = eval_all(src, &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Float(f) if f == 1.0));
+        assert!(crate::tier::is_compiled("shrink"));
+    }
+
+    #[test]
+    fn tier_leaves_unsupported_defns_interpreted() {
+        crate::tier::set_threshold(Some(2));
+        let mut env = crate::env::Environment::new();
+        let src = r#"
+            (defn len [xs: List<i32>] -> i32 (length xs))
+            (len (list 1 2))
+            (len (list 1 2 3))
+            (len (list 1 2 3 4))
+        "#;
+        let v = eval_all(src, &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Integer32(4)));
+        assert!(!crate::tier::is_compiled("len"));
+    }
+
+    #[test]
+    fn tier_redefinition_drops_compiled_version() {
+        crate::tier::set_threshold(Some(1));
+        let mut env = crate::env::Environment::new();
+        eval_all("(defn k [x: i32] -> i32 (+ x 1)) (k 1)", &mut env).unwrap();
+        assert!(crate::tier::is_compiled("k"));
+        let v = eval_all("(defn k [x: i32] -> i32 (* x 10)) (k 2)", &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Integer32(20)));
+    }
+
+    #[test]
+    fn tier_redefining_a_callee_invalidates_its_callers() {
+        crate::tier::set_threshold(Some(1));
+        let mut env = crate::env::Environment::new();
+        let src = "(defn step [x: i32] -> i32 (+ x 1)) (defn run [x: i32] -> i32 (step x)) (run 1) (run 1)";
+        eval_all(src, &mut env).unwrap();
+        assert!(crate::tier::is_compiled("run"));
+        assert!(matches!(env.get("run"), Some(crate::env::Value::Native(_))));
+        // `run`'s compiled code has its own copy of the old `step`.
+        eval_all("(defn step [x: i32] -> i32 (* x 10))", &mut env).unwrap();
+        assert!(!crate::tier::is_compiled("run"));
+        let v = eval_all("(run 2)", &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Integer32(20)));
+        // Hot again, it's compiled against the new `step`.
+        assert!(crate::tier::is_compiled("run"));
+        let v = eval_all("(run 3)", &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Integer32(30)));
+    }
+
+    #[test]
+    fn tier_compiles_mutually_recursive_defns() {
+        crate::tier::set_threshold(Some(1));
+        let mut env = crate::env::Environment::new();
+        let src = r#"
+            (defn ev? [n: i32] -> bool (if (= n 0) true (od? (- n 1))))
+            (defn od? [n: i32] -> bool (if (= n 0) false (ev? (- n 1))))
+            (ev? 10)
+        "#;
+        let v = eval_all(src, &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Bool(true)));
+        assert!(crate::tier::is_compiled("ev?"));
+    }
+
+    #[test]
+    fn tier_compiled_fault_is_an_error() {
+        crate::tier::set_threshold(Some(1));
+        let mut env = crate::env::Environment::new();
+        let err = eval_all("(defn d [a: i32] -> i32 (/ 10 a)) (d 0)", &mut env).unwrap_err();
+        assert!(err.contains("division by zero"), "got: {}", err);
+    }
+
+    #[test]
+    fn tier_compiled_overflow_is_an_error() {
+        // Compiled code must not wrap where the interpreter would fail.
+        crate::tier::set_threshold(Some(3));
+        let mut env = crate::env::Environment::new();
+        let src = "(defn scale [x: i32] -> i32 (* x 1000)) (scale 1) (scale 2) (scale 3) (scale 4)";
+        let v = eval_all(src, &mut env).unwrap();
+        assert!(matches!(v, crate::env::Value::Integer32(4000)));
+        assert!(crate::tier::is_compiled("scale"));
+        let err = eval_all("(scale 3000000)", &mut env).unwrap_err();
+        assert!(err.contains("integer overflow"), "got: {}", err);
+        let err = eval_all("(defn flip [x: i32] -> i32 (neg x)) (flip 1) (flip -2147483648)", &mut env).unwrap_err();
+        assert!(err.contains("overflow"), "got: {}", err);
+    }
 }
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::{self, Stage, classify};
+    use crate::env::Environment;
+    use crate::types::{TypeEnv, type_check};
+
+    fn type_error(src: &str) -> String {
+        type_check(&crate::parser::parse(src).unwrap(), &mut TypeEnv::new()).unwrap_err()
+    }
+
+    fn runtime_error(src: &str) -> String {
+        crate::eval::eval(&crate::parser::parse(src).unwrap(), &mut Environment::new()).unwrap_err()
+    }
+
+    #[test]
+    fn test_classify() {
+        let code = |stage, message: &str| classify(stage, message).code;
+        assert_eq!(code(Stage::Type, &type_error("(+ 1 \"a\")")), "E011");
+        assert_eq!(code(Stage::Type, &type_error("(car)")), "E012");
+        assert_eq!(code(Stage::Type, &type_error("(if 1 2 3)")), "E013");
+        assert_eq!(code(Stage::Type, &type_error("nope")), "E010");
+        assert_eq!(code(Stage::Type, &type_error("(match (list 1) (nil 0))")), "E017");
+        assert_eq!(code(Stage::Runtime, &runtime_error("(/ 1 0)")), "E034");
+        assert_eq!(code(Stage::Runtime, &runtime_error("(nope 1)")), "E030");
+        let parse_error = crate::parser::parse("#_").unwrap_err().to_string();
+        assert_eq!(code(Stage::Parse, &parse_error), "E004");
+        // Whatever isn't recognized gets its stage's catch-all.
+        assert_eq!(code(Stage::Parse, "something new"), "E009");
+        assert_eq!(code(Stage::Type, "something new"), "E029");
+        assert_eq!(code(Stage::Runtime, "something new"), "E049");
+    }
+
+    #[test]
+    fn test_codes_and_lints() {
+        for (i, d) in diagnostics::ALL.iter().enumerate() {
+            assert!(diagnostics::ALL[i + 1..].iter().all(|other| other.code != d.code), "{} twice", d.code);
+            assert!(diagnostics::explain(d.code).unwrap().starts_with(&format!("{}: {}", d.code, d.title)));
+        }
+        for lint in crate::lint::ALL {
+            let code = diagnostics::lint_code(lint).expect("every lint has a code");
+            assert_eq!(diagnostics::lint_name(code), Some(*lint));
+            assert_eq!(diagnostics::lint_name(lint), Some(*lint));
+        }
+        assert_eq!(diagnostics::lint_name("w002"), Some(crate::lint::REDUNDANT_ARM));
+        assert_eq!(diagnostics::lint_name("E011"), None);
+        assert!(diagnostics::explain("E999").is_none());
+    }
+
+    #[test]
+    fn test_type_mismatch_diff() {
+        let ty = |s: &str| crate::parser::types::parse_type_annotation(s).unwrap().1;
+        // Short ones read fine in full.
+        assert_eq!(
+            diagnostics::type_mismatch(&ty("List<i32>"), &ty("List<String>")),
+            "expected List<i32>, got List<String>"
+        );
+        assert_eq!(
+            diagnostics::type_mismatch(&ty("fn(i32, String, bool) -> i32"), &ty("fn(i32, i64, bool) -> i32")),
+            "fn(i32, [-String-]{+i64+}, bool) -> i32 (parameter 2: expected String, got i64)"
+        );
+        // `_` is no difference; nested parts say where they are.
+        assert_eq!(
+            diagnostics::type_mismatch(
+                &ty("fn(_, List<i32>) -> Pair<i32, String>"),
+                &ty("fn(bool, List<i64>) -> Pair<i32, bool>")
+            ),
+            "fn(_, List<[-i32-]{+i64+}>) -> Pair<i32, [-String-]{+bool+}> \
+(element of parameter 2: expected i32, got i64; tail of return type: expected String, got bool)"
+        );
+        // Different shapes, or a different arity: both in full.
+        assert_eq!(
+            diagnostics::type_mismatch(&ty("fn(i32, String) -> i32"), &ty("fn(i32) -> List<String>")),
+            "expected fn(i32, String) -> i32, got fn(i32) -> List<String>"
+        );
+
+        let err = type_error("(do (defn k [f: fn(i32, String, bool) -> i32] -> i32 1) (k (fn [a: i32 b: i64 c: bool] -> i32 a)))");
+        assert!(err.ends_with("fn(i32, [-String-]{+i64+}, bool) -> i32 (parameter 2: expected String, got i64)"), "{}", err);
+        assert_eq!(classify(Stage::Type, &err).code, "E011");
+        assert_eq!(
+            diagnostics::paint("a [-x-]{+y+} b"),
+            "a \x1b[31mx\x1b[0m\x1b[32my\x1b[0m b"
+        );
+    }
+}
@@ -1,10 +1,11 @@
 #[cfg(test)]
 mod tests {
-    use crate::ast::Type;
+    use crate::ast::{Expr, Type};
     use crate::env::{Environment, Value};
     use crate::eval::eval;
+    use crate::keyword::Keyword;
     use crate::parser;
-    use crate::types::{type_check, TypeEnv};
+    use crate::types::{declare_defns, type_check, TypeEnv};
     
     fn eval_str(input: &str) -> Result<Value, String> {
         let expr = parser::parse(input).map_err(|e| e.to_string())?;
@@ -68,15 +69,180 @@ mod tests {
         assert!(matches!(result, Value::Integer64(2)));
     }
     
+    #[test]
+    fn test_float_comparisons() {
+        assert_eq!(type_check_str("(< 1.5 2.5)").unwrap(), Type::Bool);
+        assert!(type_check_str("(< 1 2.5)").is_err());
+        for (src, expected) in [
+            ("(= 1.5 1.5)", true),
+            ("(< 1.5 2.5)", true),
+            ("(> 1.5 2.5)", false),
+            ("(<= 2.5 2.5)", true),
+            ("(>= 2.0 2.5)", false),
+        ] {
+            assert!(matches!(eval_str(src).unwrap(), Value::Bool(b) if b == expected), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_nan_semantics() {
+        // Every comparison with NaN is false, even against itself.
+        for src in ["(= nan nan)", "(< nan 1.0)", "(>= nan 1.0)", "(= nan 1.0)"] {
+            assert!(matches!(eval_str(src).unwrap(), Value::Bool(false)), "{}", src);
+        }
+        assert!(matches!(eval_str("(nan? nan)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(is-nan? (* inf 0.0))").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(nan? 1.0)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(is-finite? 1.0)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(is-finite? inf)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(is-finite? nan)").unwrap(), Value::Bool(false)));
+        assert_eq!(type_check_str("(nan? nan)").unwrap(), Type::Bool);
+        assert!(type_check_str("(nan? 1)").is_err());
+    }
+
+    #[test]
+    fn test_approximate_float_equality() {
+        assert!(matches!(eval_str("(= (+ 0.1 0.2) 0.3)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(approx-eq (+ 0.1 0.2) 0.3)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(approx= 1.0 1.05 0.1)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(approx= 1.0 1.5 0.1)").unwrap(), Value::Bool(false)));
+        // Relative for large magnitudes.
+        assert!(matches!(eval_str("(approx= 1000000.0 1000001.0 0.000001)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(approx-eq nan nan)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(approx-eq inf inf)").unwrap(), Value::Bool(true)));
+        assert_eq!(type_check_str("(approx= 1.0 1.0 0.1)").unwrap(), Type::Bool);
+        assert!(type_check_str("(approx-eq 1 1)").is_err());
+    }
+
+    #[test]
+    fn test_assert_approx_eq() {
+        assert!(matches!(eval_str("(assert-approx-eq (* 3.0 0.1) 0.3)").unwrap(), Value::Bool(true)));
+        let err = eval_str("(assert-approx-eq 0.3 0.4)").unwrap_err();
+        assert!(err.contains("expected 0.4, got 0.3"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_rounding() {
+        for (src, expected) in [
+            ("(floor -2.5)", -3.0),
+            ("(ceil -2.5)", -2.0),
+            ("(round 2.5)", 3.0),
+            ("(round -2.5)", -3.0),
+            ("(trunc -2.7)", -2.0),
+        ] {
+            assert!(matches!(eval_str(src).unwrap(), Value::Float(f) if f == expected), "{}", src);
+        }
+        assert_eq!(type_check_str("(floor 1.5)").unwrap(), Type::F64);
+        assert_eq!(type_check_str("(round-int 1.5)").unwrap(), Type::I64);
+        assert!(type_check_str("(floor 1)").is_err());
+        assert!(matches!(eval_str("(floor-int 2.9)").unwrap(), Value::Integer64(2)));
+        assert!(matches!(eval_str("(ceil-int 2.1)").unwrap(), Value::Integer64(3)));
+        assert!(matches!(eval_str("(round-int -0.5)").unwrap(), Value::Integer64(-1)));
+        assert!(matches!(eval_str("(trunc-int -2.9)").unwrap(), Value::Integer64(-2)));
+        assert!(eval_str("(trunc-int nan)").unwrap_err().contains("does not fit in i64"));
+        assert!(eval_str("(floor-int inf)").is_err());
+        assert!(eval_str("(round-int 9223372036854775807.0)").is_err());
+    }
+
+    #[test]
+    fn test_min_max_abs_clamp() {
+        assert!(matches!(eval_str("(min 3 -2)").unwrap(), Value::Integer32(-2)));
+        assert!(matches!(eval_str("(max 3 3000000000)").unwrap(), Value::Integer64(3_000_000_000)));
+        assert!(matches!(eval_str("(max 1.5 -2.5)").unwrap(), Value::Float(f) if f == 1.5));
+        assert!(matches!(eval_str("(abs -7)").unwrap(), Value::Integer32(7)));
+        assert!(matches!(eval_str("(abs -2.5)").unwrap(), Value::Float(f) if f == 2.5));
+        assert!(matches!(eval_str("(clamp 15 0 10)").unwrap(), Value::Integer32(10)));
+        assert!(matches!(eval_str("(clamp -1 0 10)").unwrap(), Value::Integer32(0)));
+        assert!(matches!(eval_str("(clamp 0.5 0.0 1.0)").unwrap(), Value::Float(f) if f == 0.5));
+        assert_eq!(type_check_str("(min 1 2)").unwrap(), Type::I32);
+        assert_eq!(type_check_str("(min 1 3000000000)").unwrap(), Type::I64);
+        assert_eq!(type_check_str("(abs -2.5)").unwrap(), Type::F64);
+        assert_eq!(type_check_str("(clamp 1 0 3000000000)").unwrap(), Type::I64);
+        assert!(type_check_str("(max 1 2.5)").unwrap_err().contains("can't mix f64"));
+        assert!(type_check_str(r#"(abs "x")"#).unwrap_err().contains("expects a number"));
+        assert!(eval_str("(abs -2147483648)").unwrap_err().contains("overflows i32"));
+        assert!(eval_str("(clamp 5 10 0)").unwrap_err().contains("lower bound"));
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        assert!(matches!(eval_str("(let x 5 (- x))").unwrap(), Value::Integer32(-5)));
+        assert!(matches!(eval_str("(let x 5 -x)").unwrap(), Value::Integer32(-5)));
+        assert!(matches!(eval_str("(neg 3000000000)").unwrap(), Value::Integer64(-3_000_000_000)));
+        assert!(matches!(eval_str("(let x 2.5 (- x))").unwrap(), Value::Float(f) if f == -2.5));
+        assert!(matches!(eval_str("(- 7 2)").unwrap(), Value::Integer32(5)));
+        assert_eq!(type_check_str("(neg 1.5)").unwrap(), Type::F64);
+        assert!(type_check_str(r#"(- "x")"#).unwrap_err().contains("expects a number"));
+        assert!(eval_str("(neg -2147483648)").unwrap_err().contains("overflows i32"));
+    }
+
+    #[test]
+    fn test_mixed_width_arithmetic_promotes_to_i64() {
+        assert_eq!(type_check_str("(+ 3000000000 1)").unwrap(), Type::I64);
+        assert_eq!(type_check_str("(* 2 3000000000)").unwrap(), Type::I64);
+        assert_eq!(type_check_str("(+ 1 2)").unwrap(), Type::I32);
+        assert!(matches!(eval_str("(+ 3000000000 1)").unwrap(), Value::Integer64(3_000_000_001)));
+        assert!(matches!(eval_str("(- 1 3000000000)").unwrap(), Value::Integer64(-2_999_999_999)));
+        assert!(matches!(eval_str("(< 1 3000000000)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(= 5 (to-i64 5))").unwrap(), Value::Bool(true)));
+        assert_eq!(type_check_str("(to-i64 1)").unwrap(), Type::I64);
+        let err = type_check_str("(+ 1 2.5)").unwrap_err();
+        assert!(err.contains("convert with i32->f64"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_polymorphic_arithmetic() {
+        assert_eq!(eval_str("(* 2.0 3.5)").unwrap().to_string(), "7");
+        assert_eq!(eval_str("(/ 7.0 2.0)").unwrap().to_string(), "3.5");
+        assert_eq!(eval_str("(- 10.0 1.5 2.5)").unwrap().to_string(), "6");
+        assert_eq!(type_check_str("(+ 1.5 2.5)").unwrap(), Type::F64);
+        assert_eq!(type_check_str("(let x 2.0 (* x x))").unwrap(), Type::F64);
+        // One definition serves every numeric type; the call picks which.
+        let add = "(defn add [a b] (+ a b))";
+        assert_eq!(type_check_seq(&[add, "(add 1.5 2.0)"]).unwrap(), Type::F64);
+        assert_eq!(type_check_seq(&[add, "(add 1 2)"]).unwrap(), Type::I32);
+        assert_eq!(run_seq(&[add, "(add 1.5 2.0)"]).unwrap().to_string(), "3.5");
+        assert!(type_check_str("(defn f [x: f64] -> f64 (+ x 1))").unwrap_err().contains("can't mix f64"));
+        assert!(type_check_str("(< 1.0 2)").unwrap_err().contains("can't mix f64"));
+        assert!(type_check_str(r#"(< "a" "b")"#).unwrap_err().contains("expects a number"));
+        assert!(matches!(eval_str("(= :a :a)").unwrap(), Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_numeric_conversions() {
+        assert!(matches!(eval_str("(i32->i64 7)").unwrap(), Value::Integer64(7)));
+        assert!(matches!(eval_str("(i64->f64 (i32->i64 7))").unwrap(), Value::Float(f) if f == 7.0));
+        assert!(matches!(eval_str("(f64->i32 2.9)").unwrap(), Value::Integer32(2)));
+        assert!(matches!(eval_str("(f64->i32 -2.9)").unwrap(), Value::Integer32(-2)));
+        assert!(matches!(eval_str("(f64->i64 50000000000.9)").unwrap(), Value::Integer64(50_000_000_000)));
+        assert!(matches!(eval_str("(i64->i32 (i32->i64 -5))").unwrap(), Value::Integer32(-5)));
+        assert_eq!(eval_str("(+ (i32->f64 1) 2.5)").unwrap().to_string(), "3.5");
+        assert_eq!(type_check_str("(f64->i32 2.5)").unwrap(), Type::I32);
+        assert!(type_check_str("(i32->i64 2.5)").is_err());
+        assert!(eval_str("(f64->i32 3000000000.0)").unwrap_err().contains("does not fit in i32"));
+        assert!(eval_str("(f64->i32 nan)").unwrap_err().contains("does not fit in i32"));
+        assert!(eval_str("(i64->i32 3000000000)").unwrap_err().contains("does not fit in i32"));
+    }
+
+    #[test]
+    fn test_integer_overflow_is_an_error() {
+        let err = eval_str("(+ 2147483647 1)").unwrap_err();
+        assert!(err.contains("overflows i32") && err.contains("to-i64"), "got: {}", err);
+        assert!(matches!(eval_str("(+ (to-i64 2147483647) 1)").unwrap(), Value::Integer64(2_147_483_648)));
+        let err = eval_str("(* 9223372036854775807 2)").unwrap_err();
+        assert!(err.contains("overflows i64"), "got: {}", err);
+        assert!(eval_str("(/ 1 0)").unwrap_err().contains("Division by zero"));
+    }
+
     #[test]
     fn test_eval_float_arithmetic() {
-        let result = eval_str("(+. 2.5 3.5)").unwrap();
+        let result = eval_str("(+ 2.5 3.5)").unwrap();
         match result {
             Value::Float(f) => assert!((f - 6.0).abs() < 0.001),
             _ => panic!("Expected Float"),
         }
         
-        let result = eval_str("(-. 10.0 3.5)").unwrap();
+        let result = eval_str("(- 10.0 3.5)").unwrap();
         match result {
             Value::Float(f) => assert!((f - 6.5).abs() < 0.001),
             _ => panic!("Expected Float"),
@@ -138,19 +304,54 @@ mod tests {
     }
     
     #[test]
-    fn test_eval_let() {
-        // Simple let
-        let result = eval_str("(let x 10)").unwrap();
+    fn test_eval_def() {
+        // Simple def
+        let result = eval_str("(def x 10)").unwrap();
         assert!(matches!(result, Value::Integer32(10)));
         
-        // Let with type annotation
-        let result = eval_str("(let x: i32 42)").unwrap();
+        // Def with type annotation
+        let result = eval_str("(def x: i32 42)").unwrap();
         assert!(matches!(result, Value::Integer32(42)));
         
-        // Let with i64
-        let result = eval_str("(let x: i64 9223372036854775807)").unwrap();
+        // Def with i64
+        let result = eval_str("(def x: i64 9223372036854775807)").unwrap();
         assert!(matches!(result, Value::Integer64(9223372036854775807)));
     }
+
+    #[test]
+    fn test_let_is_lexical() {
+        let mut env = Environment::new();
+        let mut type_env = TypeEnv::new();
+        let expr = parser::parse("(let x 1 x)").unwrap();
+        type_check(&expr, &mut type_env).unwrap();
+        eval(&expr, &mut env).unwrap();
+        // The binding doesn't leak out of the body.
+        assert!(env.get("x").is_none());
+        assert!(type_env.get("x").is_none());
+    }
+
+    #[test]
+    fn test_defconst_cannot_be_redefined() {
+        let mut env = TypeEnv::new();
+        let check = |src: &str, env: &mut TypeEnv| type_check(&parser::parse(src).unwrap(), env);
+        assert_eq!(check("(defconst PI 3.14159)", &mut env).unwrap(), Type::F64);
+        let err = check("(def PI 3.0)", &mut env).unwrap_err();
+        assert_eq!(err, "cannot redefine constant `PI`");
+        assert!(check("(defconst PI 3.0)", &mut env).is_err());
+        assert!(check("(defn PI [] -> f64 3.0)", &mut env).is_err());
+        // Local bindings may still use the name.
+        assert_eq!(check("(let PI 1 (+ PI 1))", &mut env).unwrap(), Type::I32);
+        env.forget_constant("PI");
+        assert!(check("(def PI 3.0)", &mut env).is_ok());
+    }
+
+    #[test]
+    fn test_def_only_at_top_level() {
+        let err = type_check_str("(let y 1 (def x y))").unwrap_err();
+        assert!(err.contains("`def x` is only allowed at the top level"), "got: {}", err);
+        let err = type_check_str("(fn [a: i32] -> i32 (def x a))").unwrap_err();
+        assert!(err.contains("only allowed at the top level"), "got: {}", err);
+    }
     
     #[test]
     fn test_eval_let_in() {
@@ -174,7 +375,7 @@ mod tests {
         // Define a function
         let expr = parser::parse("(defn add [a: i32 b: i32] -> i32 (+ a b))").unwrap();
         let result = eval(&expr, &mut env).unwrap();
-        assert!(matches!(result, Value::Function { .. }));
+        assert!(matches!(result, Value::Function(_)));
         
         // Call the function
         let expr = parser::parse("(add 5 3)").unwrap();
@@ -233,7 +434,7 @@ mod tests {
         let mut env = Environment::new();
         
         // Define a variable holding a lambda
-        let expr = parser::parse("(let double (fn [x: i32] -> i32 (* x 2)))").unwrap();
+        let expr = parser::parse("(def double (fn [x: i32] -> i32 (* x 2)))").unwrap();
         eval(&expr, &mut env).unwrap();
         
         // Call the lambda
@@ -263,7 +464,7 @@ mod tests {
         let ty = type_check_str("(+ 1 2)").unwrap();
         assert_eq!(ty, Type::I32);
         
-        let ty = type_check_str("(+. 1.0 2.0)").unwrap();
+        let ty = type_check_str("(+ 1.0 2.0)").unwrap();
         assert_eq!(ty, Type::F64);
     }
     
@@ -287,11 +488,14 @@ mod tests {
     
     #[test]
     fn test_type_check_let() {
-        let ty = type_check_str("(let x: i32 42)").unwrap();
+        let ty = type_check_str("(def x: i32 42)").unwrap();
         assert_eq!(ty, Type::I32);
         
-        let ty = type_check_str("(let x: i64 9223372036854775807)").unwrap();
+        let ty = type_check_str("(def x: i64 9223372036854775807)").unwrap();
         assert_eq!(ty, Type::I64);
+        
+        let ty = type_check_str("(let x: i32 42 (+ x 1))").unwrap();
+        assert_eq!(ty, Type::I32);
     }
     
     #[test]
@@ -331,17 +535,43 @@ mod tests {
         assert!(result.unwrap_err().contains("Undefined"));
     }
     
+    #[test]
+    fn test_explain_types() {
+        let mut env = TypeEnv::new();
+        let check = |src: &str, env: &mut TypeEnv| {
+            let expr = parser::parse(src).unwrap();
+            crate::types::explain(|| type_check(&expr, env))
+        };
+        let (result, trace) = check("(defn head [xs: _] -> _ (car xs))", &mut env);
+        assert!(result.is_ok());
+        assert!(trace.starts_with("(defn head [xs: _] -> _ (car xs)) : fn(List<_>) -> _\n"), "{}", trace);
+        assert!(trace.contains("\n    solved _ is List<_>"), "{}", trace);
+
+        // Premises sit under what they prove; a failing form says why.
+        let (result, trace) = check("(+ (car (list 1 2)) \"a\")", &mut env);
+        assert!(result.is_err());
+        let lines: Vec<&str> = trace.lines().collect();
+        assert!(lines[0].starts_with("(+ (car (list 1 2)) \"a\") : error: "), "{}", trace);
+        assert!(lines.contains(&"  (car (list 1 2)) : i32"), "{}", trace);
+        assert!(lines.contains(&"    instantiated fn(List<_>) -> _ as fn(List<i32>) -> i32"), "{}", trace);
+        assert!(lines.contains(&"    unify List<_> with List<i32>: ok"), "{}", trace);
+
+        // Outside `explain`, nothing is recorded.
+        let (_, trace) = crate::types::explain(|| ());
+        assert_eq!(trace, "");
+    }
+
     #[test]
     fn test_eval_print() {
         // print should work with any type
         let result = eval_str("(print \"Hello\")").unwrap();
-        assert!(matches!(result, Value::String(_)));
+        assert!(matches!(result, Value::Unit));
         
         let result = eval_str("(print 42)").unwrap();
-        assert!(matches!(result, Value::Integer32(42)));
+        assert!(matches!(result, Value::Unit));
         
         let result = eval_str("(println true)").unwrap();
-        assert!(matches!(result, Value::Bool(true)));
+        assert!(matches!(result, Value::Unit));
     }
     
     #[test]
@@ -350,7 +580,7 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Division by zero"));
         
-        let result = eval_str("(/. 10.0 0.0)");
+        let result = eval_str("(/ 10.0 0.0)");
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Division by zero"));
     }
@@ -436,7 +666,223 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("car of empty list"));
     }
+
+    #[test]
+    fn test_list_aliases() {
+        assert!(matches!(eval_str("(head (list 1 2 3))").unwrap(), Value::Integer32(1)));
+        assert_eq!(eval_str("(tail (list 1 2 3))").unwrap().to_string(), "(2 3)");
+        assert!(matches!(eval_str("(empty? nil)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(empty? (list 1))").unwrap(), Value::Bool(false)));
+        assert_eq!(type_check_str("(head (list 1 2))").unwrap(), Type::I32);
+        assert_eq!(type_check_str("(tail (list true))").unwrap().to_string(), "List<bool>");
+        assert_eq!(
+            type_check_str("(defn sum [xs: (List i32)] -> i32 (if (empty? xs) 0 (+ (head xs) (sum (tail xs)))))")
+                .unwrap()
+                .to_string(),
+            "fn(List<i32>) -> i32"
+        );
+        // A definition under one of the names is used as it's defined.
+        assert_eq!(type_check_seq(&["(defn head [n: i32] -> bool (> n 0))", "(head 3)"]).unwrap(), Type::Bool);
+    }
+    
+    #[test]
+    fn test_static_arrays() {
+        assert_eq!(eval_str("#[1 2 3]").unwrap().to_string(), "#[1 2 3]");
+        assert!(matches!(eval_str("(array-get #[1 2 3] 2)").unwrap(), Value::Integer32(3)));
+        assert!(matches!(eval_str("(array-len #[])").unwrap(), Value::Integer32(0)));
+        assert_eq!(eval_str("(let a #[1 2 3] (array-set! a 0 9))").unwrap().to_string(), "#[9 2 3]");
+        assert_eq!(eval_str("(array->list #[1 2])").unwrap().to_string(), "(1 2)");
+        assert_eq!(eval_str("(list->array (list 1 2) 2)").unwrap().to_string(), "#[1 2]");
+        assert_eq!(
+            eval_str("(array-get #[1 2 3] (+ 1 2))").unwrap_err(),
+            "array-get: index 3 out of bounds for length 3"
+        );
+        assert_eq!(eval_str("(list->array (list 1) 2)").unwrap_err(), "list->array: expected 2 items, got 1");
+        // The length is part of the type.
+        assert_eq!(type_check_str("#[1.0 2.0]").unwrap().to_string(), "Array<f64, 2>");
+        assert_eq!(type_check_str("(array-get #[true false] 1)").unwrap(), Type::Bool);
+        assert_eq!(type_check_str("(list->array (list 1 2) 2)").unwrap().to_string(), "Array<i32, 2>");
+        assert_eq!(
+            type_check_str("(defn f [a: (Array i32 3)] -> i32 (array-get a 2))").unwrap().to_string(),
+            "fn(Array<i32, 3>) -> i32"
+        );
+        assert!(type_check_seq(&["(defn f [a: Array<i32, 3>] -> i32 (array-get a 0))", "(f #[1 2])"]).is_err());
+        assert_eq!(
+            type_check_str("(array-get #[1 2 3] 3)").unwrap_err(),
+            "array-get: index 3 out of bounds for Array<i32, 3>"
+        );
+        assert!(type_check_str("#[1 \"a\"]").is_err());
+        assert!(type_check_str("(array-set! #[1 2] 0 true)").is_err());
+        assert!(type_check_str("(list->array (list 1) (+ 0 1))").is_err());
+    }
     
+    #[test]
+    fn test_slices() {
+        // A slice shares the array's elements.
+        let shared = "(let a #[1 2 3 4] (let s (slice a 1 3) (do (array-set! s 0 9) a)))";
+        assert_eq!(eval_str(shared).unwrap().to_string(), "#[1 9 3 4]");
+        assert_eq!(eval_str("(slice (slice #[1 2 3 4 5] 1 4) 1 3)").unwrap().to_string(), "#[3 4]");
+        assert!(matches!(eval_str("(array-get (slice #[1 2 3] 1 3) 1)").unwrap(), Value::Integer32(3)));
+        assert!(matches!(eval_str("(array-len (slice #[1 2 3] 2 2))").unwrap(), Value::Integer32(0)));
+        assert_eq!(eval_str("(map (fn [x] (* x 2)) (slice #[1 2 3] 1 3))").unwrap().to_string(), "(4 6)");
+        assert!(matches!(eval_str("(fold + 0 (slice #[1 2 3] 0 2))").unwrap(), Value::Integer32(3)));
+        assert_eq!(
+            eval_str("(array-get (slice #[1 2 3] 1 3) 2)").unwrap_err(),
+            "array-get: index 2 out of bounds for length 2"
+        );
+        assert_eq!(
+            eval_str("(slice (slice #[1 2 3] 1 3) 0 3)").unwrap_err(),
+            "slice: range 0..3 out of bounds for length 2"
+        );
+        assert_eq!(type_check_str("(slice #[1 2 3] 0 2)").unwrap().to_string(), "Slice<i32>");
+        assert_eq!(type_check_str("(fold + 0 (slice #[1 2 3] 0 2))").unwrap(), Type::I32);
+        assert_eq!(
+            type_check_str("(defn total [s: (Slice f64)] -> f64 (fold + 0.0 s))").unwrap().to_string(),
+            "fn(Slice<f64>) -> f64"
+        );
+        assert_eq!(
+            type_check_str("(slice #[1 2 3] 2 4)").unwrap_err(),
+            "slice: range 2..4 out of bounds for Array<i32, 3>"
+        );
+        assert!(type_check_str("(slice (list 1 2) 0 1)").is_err());
+    }
+    
+    #[test]
+    fn test_vectors() {
+        assert_eq!(eval_str("[1 2 3]").unwrap().to_string(), "[1 2 3]");
+        assert!(matches!(eval_str("(vec-get [1 2 3] 2)").unwrap(), Value::Integer32(3)));
+        assert!(matches!(eval_str("(vec-len [])").unwrap(), Value::Integer32(0)));
+        // vec-push leaves the vector it was given alone.
+        assert_eq!(eval_str("(let v [1 2] (list (vec-push v 3) v))").unwrap().to_string(), "([1 2 3] [1 2])");
+        assert_eq!(eval_str("(vec-slice [1 2 3 4] 1 3)").unwrap().to_string(), "[2 3]");
+        // `slice` views a vector's elements instead of copying them.
+        assert_eq!(eval_str("(slice [1 2 3 4 5] 1 4)").unwrap().to_string(), "#[2 3 4]");
+        let shared = "(let v [1 2 3] (let s (slice v 1 3) (do (array-set! s 0 9) v)))";
+        assert_eq!(eval_str(shared).unwrap().to_string(), "[1 9 3]");
+        assert_eq!(type_check_str("(slice [1 2 3 4 5] 1 4)").unwrap().to_string(), "Slice<i32>");
+        assert_eq!(type_check_str("(fold + 0 (slice [1 2 3] 0 2))").unwrap(), Type::I32);
+        assert_eq!(eval_str("(map (fn [x] (* x x)) [1 2 3])").unwrap().to_string(), "(1 4 9)");
+        assert_eq!(eval_str("(array->vec (slice #[1 2 3] 1 3))").unwrap().to_string(), "[2 3]");
+        assert_eq!(eval_str("(vec->array [1 2] 2)").unwrap().to_string(), "#[1 2]");
+        assert_eq!(eval_str("(vec-get [1 2] -1)").unwrap_err(), "vec-get: index -1 out of bounds for length 2");
+        assert_eq!(
+            eval_str("(vec-slice [1 2] 1 3)").unwrap_err(),
+            "vec-slice: range 1..3 out of bounds for length 2"
+        );
+        // Looking a vector up shares it rather than copying its elements.
+        let mut env = Environment::new();
+        eval(&parser::parse("(def v [1 2 3])").unwrap(), &mut env).unwrap();
+        let (Some(Value::Vector(a)), Some(Value::Vector(b))) = (env.get("v"), env.get("v")) else { panic!() };
+        assert!(std::rc::Rc::ptr_eq(&a, &b));
+        assert_eq!(type_check_str("[1.0 2.0]").unwrap().to_string(), "Vec<f64>");
+        assert_eq!(type_check_str("(vec-get [true] 0)").unwrap(), Type::Bool);
+        assert_eq!(type_check_str("(vec-push [] \"a\")").unwrap().to_string(), "Vec<String>");
+        assert_eq!(type_check_str("(vec->array [1 2] 2)").unwrap().to_string(), "Array<i32, 2>");
+        assert_eq!(
+            type_check_str("(defn total [v: (Vec i32)] -> i32 (fold + 0 v))").unwrap().to_string(),
+            "fn(Vec<i32>) -> i32"
+        );
+        assert_eq!(type_check_str("(vec-push [1] true)").unwrap_err(), "vec-push: vector holds i32, got bool");
+        assert!(type_check_str("[1 \"a\"]").is_err());
+        assert!(type_check_str("(vec-len (list 1))").is_err());
+    }
+    
+    #[test]
+    fn test_hash_maps() {
+        // Maps print in key order, whatever order they were written in.
+        assert_eq!(eval_str("{:b 2 :a 1}").unwrap().to_string(), "{:a 1 :b 2}");
+        assert!(matches!(eval_str("(get {:a 1 :b 2} :b)").unwrap(), Value::Integer32(2)));
+        assert!(matches!(eval_str("(get {\"x\" 1} \"x\")").unwrap(), Value::Integer32(1)));
+        assert_eq!(eval_str("(get {:a 1} :z)").unwrap_err(), "get: no key :z in the map");
+        // assoc and dissoc leave the map they were given alone.
+        assert_eq!(
+            eval_str("(let m {:a 1} (list (assoc m :b 2) (dissoc m :a) m))").unwrap().to_string(),
+            "({:a 1 :b 2} {} {:a 1})"
+        );
+        assert!(matches!(eval_str("(contains? {:a 1} :a)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(contains? {:a 1} :b)").unwrap(), Value::Bool(false)));
+        assert_eq!(eval_str("(keys {3 \"c\" 1 \"a\" 2 \"b\"})").unwrap().to_string(), "(1 2 3)");
+        assert_eq!(eval_str("(vals {:b 2 :a 1})").unwrap().to_string(), "(1 2)");
+        assert_eq!(eval_str("{\"k\" \"v\"}").unwrap().written(), "{\"k\" \"v\"}");
+        assert_eq!(type_check_str("{:a 1.0}").unwrap().to_string(), "Map<Keyword, f64>");
+        assert_eq!(type_check_str("(get {:a true} :a)").unwrap(), Type::Bool);
+        assert_eq!(type_check_str("(assoc {} \"k\" 1)").unwrap().to_string(), "Map<String, i32>");
+        assert_eq!(type_check_str("(keys {1 :one})").unwrap().to_string(), "List<i32>");
+        assert_eq!(type_check_str("(vals {1 :one})").unwrap().to_string(), "List<Keyword>");
+        assert_eq!(
+            type_check_str("(defn f [m: (Map Keyword i32)] -> i32 (get m :a))").unwrap().to_string(),
+            "fn(Map<Keyword, i32>) -> i32"
+        );
+        assert_eq!(type_check_str("(get {:a 1} \"a\")").unwrap_err(), "get: the map's keys are Keyword, got String");
+        assert_eq!(type_check_str("(assoc {:a 1} :b true)").unwrap_err(), "assoc: map holds i32, got bool");
+        assert!(type_check_str("{:a 1 :b \"x\"}").is_err());
+        assert!(type_check_str("{(list 1) 1}").is_err());
+        assert!(type_check_str("(keys (list 1))").is_err());
+    }
+    
+    #[test]
+    fn test_matrices() {
+        let square = "(list->mat (list (list 1.0 2.0) (list 3.0 4.0)))";
+        assert_eq!(eval_str("(mat 2 3 0.0)").unwrap().to_string(), "#<matrix:2x3>");
+        assert_eq!(eval_str(&format!("(mat->list (mat-mul {} {}))", square, square)).unwrap().to_string(), "((7 10) (15 22))");
+        assert_eq!(eval_str(&format!("(mat->list (mat-transpose {}))", square)).unwrap().to_string(), "((1 3) (2 4))");
+        assert_eq!(
+            eval_str(&format!("(mat->list (mat-sub (mat-emul {} {}) (mat-scale {} 2.0)))", square, square, square))
+                .unwrap()
+                .to_string(),
+            "((-1 0) (3 8))"
+        );
+        assert!(matches!(
+            eval_str("(let m (mat 2 2 0.0) (do (mat-set! m 1 0 2.5) (mat-get m 1 0)))").unwrap(),
+            Value::Float(x) if x == 2.5
+        ));
+        assert!(matches!(eval_str("(mat-cols (mat 2 3 0.0))").unwrap(), Value::Integer32(3)));
+        assert_eq!(
+            eval_str("(mat-get (mat 2 2 0.0) 0 2)").unwrap_err(),
+            "mat-get: index (0, 2) out of bounds for a 2x2 matrix"
+        );
+        assert_eq!(
+            eval_str("(mat-mul (mat 2 3 1.0) (mat 2 3 1.0))").unwrap_err(),
+            "mat-mul: can't multiply a 2x3 matrix by a 2x3 one"
+        );
+        assert_eq!(eval_str("(mat-add (mat 1 2 1.0) (mat 2 1 1.0))").unwrap_err(), "mat-add: shapes 1x2 and 2x1 differ");
+        // Too large to allocate is an error, not an abort.
+        assert_eq!(
+            eval_str("(mat 2147483647 2147483647 0.0)").unwrap_err(),
+            "mat: a 2147483647x2147483647 matrix is too large"
+        );
+        assert_eq!(type_check_str("(mat-get (mat 1 1 0.0) 0 0)").unwrap(), Type::F64);
+        assert_eq!(type_check_str("(defn id [m: Matrix] -> Matrix m)").unwrap().to_string(), "fn(Matrix) -> Matrix");
+        assert!(type_check_str("(mat 2 2 0)").is_err());
+    }
+    
+    #[test]
+    fn test_body_lets() {
+        let defn = "(defn f [x: i32] -> i32 (let a (* x 2)) (let b (+ a 1)) (+ a b))";
+        assert!(matches!(run_seq(&[defn, "(f 3)"]).unwrap(), Value::Integer32(13)));
+        assert_eq!(type_check_str(defn).unwrap().to_string(), "fn(i32) -> i32");
+        // Each binding only reaches the forms after it, and not past the body.
+        assert!(type_check_str("(defn g [x: i32] -> i32 (+ a 1) (let a x) a)").is_err());
+        assert!(type_check_seq(&["(defn h [x: i32] -> i32 (let a x) a)", "a"]).is_err());
+    }
+    
+    #[test]
+    fn test_short_circuit_logic() {
+        let guarded = "(defn halves-to-2 [n: i32] -> bool (and (not (= n 0)) (= (/ 10 n) 2)))";
+        assert!(matches!(run_seq(&[guarded, "(halves-to-2 0)"]).unwrap(), Value::Bool(false)));
+        assert!(matches!(run_seq(&[guarded, "(halves-to-2 5)"]).unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(or (= 1 1) (= (/ 1 0) 1))").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(and true true false true)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(or false false true)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(and)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(or)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(and true)").unwrap(), Value::Bool(true)));
+        assert_eq!(type_check_str("(and true false true)").unwrap(), Type::Bool);
+        assert!(type_check_str("(or false 1)").unwrap_err().contains("expects bool operands"));
+        // An operand that isn't a bool is an error once it's reached.
+        assert!(eval_str("(and true 1)").unwrap_err().contains("and requires booleans"));
+    }
+
     #[test]
     fn test_eval_cdr() {
         // Get rest of list
@@ -479,6 +925,38 @@ mod tests {
         assert!(matches!(result, Value::Bool(true)));
     }
     
+    #[test]
+    fn test_dotted_pairs() {
+        assert_eq!(eval_str("(1 . 2)").unwrap().to_string(), "(1 . 2)");
+        assert_eq!(eval_str("(1 2 . 3)").unwrap().to_string(), "(1 2 . 3)");
+        assert_eq!(eval_str("(cons :a \"x\")").unwrap().to_string(), "(:a . x)");
+        // A list tail keeps it a proper list.
+        assert_eq!(eval_str("(0 . (list 1 2))").unwrap().to_string(), "(0 1 2)");
+        assert!(matches!(eval_str("(car (1 . 2))").unwrap(), Value::Integer32(1)));
+        assert!(matches!(eval_str("(cdr (1 . 2))").unwrap(), Value::Integer32(2)));
+        assert!(matches!(eval_str("(pair? (1 . 2))").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(pair? (list 1))").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(pair? nil)").unwrap(), Value::Bool(false)));
+        assert_eq!(
+            type_check_str("(1 . \"x\")").unwrap(),
+            Type::Pair(Box::new(Type::I32), Box::new(Type::String))
+        );
+        assert_eq!(type_check_str("(cdr (1 . \"x\"))").unwrap(), Type::String);
+        assert_eq!(type_check_str("(cons 1 (list 2))").unwrap(), Type::List(Box::new(Type::I32)));
+        assert!(type_check_str("(length (1 . 2))").is_err());
+        // Alists destructure with cons patterns.
+        let src = r#"
+            (do
+              (defn lookup [k: Keyword al: List<Pair<Keyword, i32>>] -> i32
+                (match al
+                  (nil 0)
+                  ((cons (cons key v) rest) (if (= k key) v (lookup k rest)))))
+              (lookup :b (list (:a . 1) (:b . 2))))
+        "#;
+        assert!(matches!(eval_str(src).unwrap(), Value::Integer32(2)));
+        assert!(type_check_str("(match (true . 1) ((cons true v) v))").unwrap_err().contains("(cons false _)"));
+    }
+    
     #[test]
     fn test_eval_length() {
         // Length of list
@@ -771,8 +1249,8 @@ mod tests {
 
     #[test]
     fn test_eval_map_with_defn() {
-        // map over a named function; also exercises the function-name lookup
-        // path in apply_function.
+        // map over a named function, which finds its own name through the
+        // scope it was defined in.
         let mut env = Environment::new();
         eval(
             &parser::parse("(defn inc [x: i32] -> i32 (+ x 1))").unwrap(),
@@ -841,6 +1319,25 @@ mod tests {
         assert!(matches!(result, Value::Integer32(42)));
     }
 
+    #[test]
+    fn test_eval_foldl_foldr() {
+        let cons_up = "(fn [acc: List<i32> x: i32] (cons x acc))";
+        assert_eq!(eval_str(&format!("(foldl {} nil (list 1 2 3))", cons_up)).unwrap().to_string(), "(3 2 1)");
+        assert_eq!(type_check_str(&format!("(foldl {} nil (list 1 2 3))", cons_up)).unwrap().to_string(), "List<i32>");
+        // foldr starts from the right, and its function takes the element first.
+        let result = eval_str("(foldr (fn [x: i32 acc: List<i32>] (cons x acc)) nil (list 1 2 3))").unwrap();
+        assert_eq!(result.to_string(), "(1 2 3)");
+        assert!(matches!(eval_str("(foldr - 0 (list 1 2 3))").unwrap(), Value::Integer32(2)));
+        assert!(matches!(eval_str("(foldl - 0 (list 1 2 3))").unwrap(), Value::Integer32(-6)));
+
+        assert_eq!(
+            type_check_str("(foldr (fn [x: i32 acc: String] (str-concat acc \"!\")) \"\" (list 1 2))").unwrap(),
+            Type::String
+        );
+        let err = type_check_str("(foldr (fn [acc: String x: i32] acc) \"\" (list 1 2))").unwrap_err();
+        assert!(err.starts_with("foldr accumulator type i32 does not match"), "got: {}", err);
+    }
+
     #[test]
     fn test_type_check_map() {
         let ty = type_check_str(
@@ -1251,7 +1748,7 @@ mod tests {
             "(match 5 ((guard x (+ x 1)) \"x\") (_ \"y\"))",
         )
         .unwrap_err();
-        assert!(err.contains("Bool"), "expected Bool error, got: {}", err);
+        assert!(err.contains("must be bool"), "expected bool error, got: {}", err);
     }
 
     #[test]
@@ -1404,6 +1901,54 @@ mod tests {
         Ok(last_val)
     }
 
+    #[test]
+    fn test_redefinition_keeps_the_type_its_users_were_checked_against() {
+        // A function calls whatever the name is bound to when it runs, so
+        // a redefinition it would see has to fit what it was checked with.
+        assert_eq!(
+            run_seq(&["(defn k [] -> i32 1)", "(defn user [] -> i32 (k))", "(defn k [] -> String \"s\")"])
+                .unwrap_err(),
+            "cannot redefine `k` as fn() -> String: `user` uses it as fn() -> i32"
+        );
+        assert_eq!(
+            run_seq(&["(def x 1)", "(defn f [] -> i32 x)", "(def x \"s\")"]).unwrap_err(),
+            "cannot redefine `x` as String: `f` uses it as i32"
+        );
+        // The old binding stays, for checking and for running.
+        let mut tenv = TypeEnv::new();
+        let mut env = Environment::new();
+        for src in ["(def x 1)", "(defn f [] -> i32 x)"] {
+            let form = parser::parse(src).unwrap();
+            type_check(&form, &mut tenv).unwrap();
+            eval(&form, &mut env).unwrap();
+        }
+        assert!(type_check(&parser::parse("(def x \"s\")").unwrap(), &mut tenv).is_err());
+        assert_eq!(type_check(&parser::parse("(f)").unwrap(), &mut tenv).unwrap(), Type::I32);
+        // Redefining with the same type, or a name nothing uses, is fine.
+        assert!(matches!(
+            run_seq(&["(def x 1)", "(defn f [] -> i32 x)", "(def x 2)", "(f)"]).unwrap(),
+            Value::Integer32(2)
+        ));
+        assert_eq!(run_seq(&["(def y 1)", "(def y \"s\")"]).unwrap().to_string(), "s");
+    }
+
+    #[test]
+    fn test_calls_look_up_their_function_each_time() {
+        // Every call is hot, so the compiled path runs too: a call finds
+        // what the name is bound to now, compiled or not, and so do the
+        // functions that call it.
+        crate::tier::set_threshold(Some(1));
+        let result = run_seq(&[
+            "(defn step [i: i32] -> i32 (+ i 1))",
+            "(defn twice [i: i32] -> i32 (+ (step i) (step i)))",
+            "(step 1)",
+            "(twice 2)",
+            "(defn step [i: i32] -> i32 (* i 10))",
+            "(+ (step 3) (twice 3))",
+        ]);
+        assert!(matches!(result.unwrap(), Value::Integer32(90)));
+    }
+
     #[test]
     fn test_infer_fold_param() {
         // The `xs: _` param is narrowed to `List<i32>` by the lambda's
@@ -1736,4 +2281,1480 @@ mod tests {
             result
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_string_builder_push_and_build() {
+        let result = eval_str(
+            r#"(let sb (string-builder) (sb-build (sb-push! (sb-push! sb "n=") 42)))"#,
+        )
+        .unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "n=42"), "got {:?}", result);
+    }
+
+    #[test]
+    fn test_string_builder_is_mutated_in_place() {
+        // The push result is discarded; the original binding still sees it.
+        let result = eval_str(
+            r#"(let sb (string-builder) (let ignored (sb-push! sb "x") (sb-build sb)))"#,
+        )
+        .unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "x"), "got {:?}", result);
+    }
+
+    #[test]
+    fn test_string_builder_in_fold() {
+        let src = "(sb-build (fold (fn [b: StringBuilder x: i32] (sb-push! b x)) \
+                   (string-builder) (list 1 2 3)))";
+        assert_eq!(type_check_str(src).unwrap(), Type::String);
+        let result = eval_str(src).unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "123"), "got {:?}", result);
+    }
+
+    #[test]
+    fn test_str_concat_and_builder_types() {
+        assert_eq!(type_check_str(r#"(str-concat "a" "b")"#).unwrap(), Type::String);
+        assert_eq!(type_check_str("(string-builder)").unwrap(), Type::StringBuilder);
+        assert!(type_check_str(r#"(sb-build "a")"#).is_err());
+        let result = eval_str(r#"(str-concat "foo" "bar")"#).unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "foobar"));
+    }
+
+    #[test]
+    fn test_substring_split_trim() {
+        assert_eq!(type_check_str(r#"(substring "abc" 0 1)"#).unwrap(), Type::String);
+        assert_eq!(
+            type_check_str(r#"(split "a,b" ",")"#).unwrap(),
+            Type::List(Box::new(Type::String))
+        );
+        // Offsets are in characters.
+        let result = eval_str(r#"(substring "héllo" 1 4)"#).unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "éll"), "got {:?}", result);
+        assert_eq!(eval_str(r#"(split "a,,b" ",")"#).unwrap().to_string(), "(a  b)");
+        let result = eval_str(r#"(trim "  x y ")"#).unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "x y"), "got {:?}", result);
+        let err = eval_str(r#"(substring "ab" 1 3)"#).unwrap_err();
+        assert!(err.contains("out of bounds"), "got: {}", err);
+        assert!(eval_str(r#"(split "ab" "")"#).is_err());
+    }
+
+    #[test]
+    fn test_value_is_compact() {
+        // Tag plus at most three words: anything bigger goes behind a pointer.
+        assert!(std::mem::size_of::<Value>() <= 32, "Value is {} bytes", std::mem::size_of::<Value>());
+    }
+
+    #[test]
+    fn test_keywords() {
+        assert_eq!(type_check_str(":ok").unwrap(), Type::Keyword);
+        assert_eq!(type_check_str(r#"(keyword "ok")"#).unwrap(), Type::Keyword);
+        assert_eq!(eval_str(":ok").unwrap().to_string(), ":ok");
+        assert!(matches!(eval_str("(= :ok :ok)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(= :ok :err)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str(r#"(= (keyword "ok") :ok)"#).unwrap(), Value::Bool(true)));
+        let result = eval_str("(keyword-name :ok)").unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "ok"), "got {:?}", result);
+        let src = "(match :err (:ok 1) (:err 2) (_ 3))";
+        assert_eq!(type_check_str(src).unwrap(), Type::I32);
+        assert!(matches!(eval_str(src).unwrap(), Value::Integer32(2)));
+        assert!(type_check_str(r#"(match :ok ("ok" 1) (_ 2))"#).is_err());
+    }
+
+    #[test]
+    fn test_keywords_are_interned() {
+        assert_eq!(Keyword::intern("tag"), Keyword::intern("tag"));
+        assert_ne!(Keyword::intern("tag"), Keyword::intern("other"));
+        assert_eq!(&*Keyword::intern("tag").name(), "tag");
+    }
+
+    #[test]
+    fn test_slices_share_the_parent_buffer() {
+        let mut env = Environment::new();
+        let line = eval(&parser::parse(r#"(def line " k=v ")"#).unwrap(), &mut env).unwrap();
+        let parts = eval(&parser::parse(r#"(split (trim line) "=")"#).unwrap(), &mut env).unwrap();
+        let (Value::String(line), Value::List(parts)) = (line, parts) else { panic!() };
+        for part in &parts {
+            let Value::String(part) = part else { panic!("got {:?}", part) };
+            assert!(part.shares_buffer(&line), "{:?} was copied", part);
+        }
+        // Concatenation builds a fresh string.
+        let joined = eval(&parser::parse(r#"(str-concat (trim line) "")"#).unwrap(), &mut env).unwrap();
+        let Value::String(joined) = joined else { panic!() };
+        assert!(!joined.shares_buffer(&line));
+    }
+
+    #[test]
+    fn test_bytes_len_slice_and_conversions() {
+        assert_eq!(type_check_str(r#"(bytes-slice #b"abc" 0 2)"#).unwrap(), Type::Bytes);
+        let result = eval_str(r#"(bytes-len (bytes-slice #x"00010203" 1 3))"#).unwrap();
+        assert!(matches!(result, Value::Integer32(2)));
+        let result = eval_str(r#"(bytes->string (string->bytes "héllo"))"#).unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "héllo"));
+        assert_eq!(eval_str(r#"#b"a\xff""#).unwrap().to_string(), r#"#b"a\xff""#);
+    }
+
+    #[test]
+    fn test_bytes_errors() {
+        let err = eval_str(r#"(bytes->string #x"ff")"#).unwrap_err();
+        assert!(err.contains("invalid UTF-8"), "got: {}", err);
+        let err = eval_str(r#"(bytes-slice #b"ab" 1 5)"#).unwrap_err();
+        assert!(err.contains("out of bounds"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_bytes_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("rusp-bytes-{}.bin", std::process::id()));
+        let path = path.to_string_lossy().replace('\\', "/");
+        let written = eval_str(&format!(r#"(write-file-bytes "{}" #x"00ff10")"#, path)).unwrap();
+        assert!(matches!(written, Value::Integer32(3)));
+        let read = eval_str(&format!(r#"(bytes-len (read-file-bytes "{}"))"#, path)).unwrap();
+        assert!(matches!(read, Value::Integer32(3)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_binary_readers() {
+        let r = eval_str(r#"(read-u16-be #x"0102ff" 0)"#).unwrap();
+        assert!(matches!(r, Value::Integer32(0x0102)));
+        let r = eval_str(r#"(read-u16-le #x"0102ff" 1)"#).unwrap();
+        assert!(matches!(r, Value::Integer32(0xff02)));
+        let r = eval_str(r#"(read-i8 #x"ff" 0)"#).unwrap();
+        assert!(matches!(r, Value::Integer32(-1)));
+        let r = eval_str(r#"(read-u32-le #x"ffffffff" 0)"#).unwrap();
+        assert!(matches!(r, Value::Integer64(0xffff_ffff)));
+        let r = eval_str(r#"(read-i32-be #x"fffffffe" 0)"#).unwrap();
+        assert!(matches!(r, Value::Integer32(-2)));
+        let err = eval_str(r#"(read-u32-le #x"0000" 0)"#).unwrap_err();
+        assert!(err.contains("out of bounds"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_binary_writers_mutate_in_place() {
+        let src = "(let buf (bytes-alloc 4) \
+                   (let ignored (write-u16-be! buf 1 258) (read-u32-be buf 0)))";
+        assert_eq!(type_check_str(src).unwrap(), Type::I64);
+        let r = eval_str(src).unwrap();
+        assert!(matches!(r, Value::Integer64(0x0001_0200)));
+        let r = eval_str("(read-f64-le (write-f64-le! (bytes-alloc 8) 0 1.5) 0)").unwrap();
+        assert!(matches!(r, Value::Float(f) if f == 1.5));
+        let err = eval_str("(write-u8! (bytes-alloc 1) 0 256)").unwrap_err();
+        assert!(err.contains("does not fit"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_pack_unpack_round_trip() {
+        let r = eval_str(r#"(pack ">HxB" (list 258 7))"#).unwrap();
+        assert_eq!(r.to_string(), r#"#b"\x01\x02\x00\x07""#);
+        let r = eval_str(r#"(unpack "<hI" (pack "<hI" (list -2 4000000000)) 0)"#).unwrap();
+        assert_eq!(r.to_string(), "(-2 4000000000)");
+        let err = eval_str(r#"(pack "2B" (list 1))"#).unwrap_err();
+        assert!(err.contains("needs 2 values"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_date_display_and_fields() {
+        let result = eval_str("(date 2024 2 29)").unwrap();
+        assert_eq!(result.to_string(), "2024-02-29T00:00:00Z");
+        assert_eq!(type_check_str("(date 2024 2 29)").unwrap(), Type::DateTime);
+        // 2024-02-29 was a Thursday.
+        assert!(matches!(eval_str("(weekday (date 2024 2 29))").unwrap(), Value::Integer32(4)));
+        let err = eval_str("(date 2023 2 29)").unwrap_err();
+        assert!(err.contains("out of range"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_format_and_parse_time() {
+        let result = eval_str(r#"(format-time (datetime 1999 12 31 23 59 5) "%a %d %b %Y %T")"#).unwrap();
+        assert!(matches!(result, Value::String(ref s) if s == "Fri 31 Dec 1999 23:59:05"), "got {:?}", result);
+        let result = eval_str(r#"(unix-seconds (parse-time "2000-01-01 00:00:00" "%F %T"))"#).unwrap();
+        assert!(matches!(result, Value::Integer64(946_684_800)), "got {:?}", result);
+        let err = eval_str(r#"(parse-time "2000-01-01x" "%F")"#).unwrap_err();
+        assert!(err.contains("trailing input"), "got: {}", err);
+        // A multibyte character where a month name goes is an error, not
+        // a slice through it.
+        let err = eval_str(r#"(parse-time "01 éé 2000" "%d %b %Y")"#).unwrap_err();
+        assert!(err.contains("expected a month name"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_zoned_datetime() {
+        let src = r#"(parse-zoned "2024-06-01T09:30:00+09:00" "%FT%T%:z")"#;
+        assert_eq!(type_check_str(src).unwrap(), Type::ZonedDateTime);
+        assert_eq!(eval_str(src).unwrap().to_string(), "2024-06-01T09:30:00+09:00");
+        let utc = eval_str(&format!("(to-utc {})", src)).unwrap();
+        assert_eq!(utc.to_string(), "2024-06-01T00:30:00Z");
+        let hour = eval_str("(hour (to-zone (date 2024 1 1) -300))").unwrap();
+        assert!(matches!(hour, Value::Integer32(19)), "got {:?}", hour);
+    }
+
+    #[test]
+    fn test_duration_arithmetic() {
+        let src = "(time-add (to-zone (date 2024 12 31) 60) (hours 25))";
+        assert_eq!(type_check_str(src).unwrap(), Type::ZonedDateTime);
+        assert_eq!(eval_str(src).unwrap().to_string(), "2025-01-01T02:00:00+01:00");
+        let diff = eval_str("(time-diff (date 2024 3 1) (date 2024 2 1))").unwrap();
+        assert_eq!(diff.to_string(), "2505600s");
+        let d = eval_str("(duration-add (seconds 1) (millis 500))").unwrap();
+        assert_eq!(d.to_string(), "1.5s");
+        assert!(type_check_str("(time-add (date 2024 1 1) 5)").is_err());
+        for src in ["(days 9223372036854775807)", "(hours -9223372036854775807)", "(minutes 9223372036854775807)"] {
+            let err = eval_str(src).unwrap_err();
+            assert!(err.contains("overflows"), "{}: {}", src, err);
+        }
+        let err = eval_str("(to-zone (date 2024 1 1) 9223372036854775807)").unwrap_err();
+        assert!(err.contains("out of range"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_path_components() {
+        let result = eval_str(r#"(path-join "logs" "app.log.gz")"#).unwrap();
+        let joined = std::path::Path::new("logs").join("app.log.gz");
+        assert_eq!(result.to_string(), joined.to_string_lossy());
+        let ext = eval_str(r#"(path-extension "logs/app.log.gz")"#).unwrap();
+        assert!(matches!(ext, Value::String(ref s) if s == "gz"), "got {:?}", ext);
+        let name = eval_str(r#"(path-filename "logs/app.log.gz")"#).unwrap();
+        assert!(matches!(name, Value::String(ref s) if s == "app.log.gz"), "got {:?}", name);
+        let parent = eval_str(r#"(path-parent "logs/app.log.gz")"#).unwrap();
+        assert!(matches!(parent, Value::String(ref s) if s == "logs"), "got {:?}", parent);
+        let none = eval_str(r#"(path-extension "Makefile")"#).unwrap();
+        assert!(matches!(none, Value::String(ref s) if s.is_empty()), "got {:?}", none);
+    }
+
+    #[test]
+    fn test_absolute_path_and_exists() {
+        let abs = eval_str(r#"(absolute-path "some/file")"#).unwrap();
+        assert!(std::path::Path::new(&abs.to_string()).is_absolute(), "got {}", abs);
+        let dir = std::env::temp_dir().to_string_lossy().replace('\\', "/");
+        let exists = eval_str(&format!(r#"(path-exists? "{}")"#, dir)).unwrap();
+        assert!(matches!(exists, Value::Bool(true)));
+        assert_eq!(type_check_str(r#"(path-exists? "x")"#).unwrap(), Type::Bool);
+        assert!(type_check_str("(path-join 1 2)").is_err());
+    }
+
+    #[test]
+    fn test_list_and_walk_dir() {
+        let root = std::env::temp_dir().join(format!("rusp-walk-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("b.txt"), "hello").unwrap();
+        std::fs::write(root.join("sub").join("a.txt"), "").unwrap();
+        let dir = root.to_string_lossy().replace('\\', "/");
+
+        let listed = eval_str(&format!(r#"(map path-filename (list-dir "{}"))"#, dir)).unwrap();
+        assert_eq!(listed.to_string(), "(b.txt sub)");
+        let walked = eval_str(&format!(r#"(map path-filename (walk-dir "{}"))"#, dir)).unwrap();
+        assert_eq!(walked.to_string(), "(b.txt sub a.txt)");
+        let size = eval_str(&format!(r#"(file-size (path-join "{}" "b.txt"))"#, dir)).unwrap();
+        assert!(matches!(size, Value::Integer64(5)), "got {:?}", size);
+        let is_dir = eval_str(&format!(r#"(is-dir? (path-join "{}" "sub"))"#, dir)).unwrap();
+        assert!(matches!(is_dir, Value::Bool(true)));
+        let year = eval_str(&format!(r#"(year (modified-time "{}"))"#, dir)).unwrap();
+        assert!(matches!(year, Value::Integer32(y) if y >= 2024), "got {:?}", year);
+
+        std::fs::remove_dir_all(&root).unwrap();
+        let err = eval_str(&format!(r#"(list-dir "{}")"#, dir)).unwrap_err();
+        assert!(err.starts_with("list-dir: "), "got: {}", err);
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let src = r#"(let text (string->bytes "abcabcabcabcabcabcabcabcabcabc hello hello hello")
+                       (let packed (gzip-compress text)
+                         (list (bytes-len text) (bytes-len packed)
+                               (bytes->string (gzip-decompress packed)))))"#;
+        let result = eval_str(src).unwrap();
+        let Value::List(items) = result else { panic!("expected a list") };
+        let (Value::Integer32(raw), Value::Integer32(packed)) = (&items[0], &items[1]) else {
+            panic!("expected lengths, got {:?}", items)
+        };
+        assert!(packed < raw, "{} compressed to {}", raw, packed);
+        assert_eq!(items[2].to_string(), "abcabcabcabcabcabcabcabcabcabc hello hello hello");
+    }
+
+    #[test]
+    fn test_gzip_decompress_dynamic_huffman() {
+        // `gzip.compress` output for 12 numbered "quick brown fox" lines;
+        // it uses a dynamic-Huffman block.
+        let src = r#"(bytes->string (gzip-decompress #x"HEX"))"#;
+        let text = eval_str(&src.replace("HEX", GZIP_FOX)).unwrap().to_string();
+        assert_eq!(text.len(), 626);
+        assert!(text.starts_with("line 0: the quick brown fox"));
+        assert!(text.ends_with("line 11: the quick brown fox jumps over the lazy dog\n"));
+        let err = eval_str(r#"(gzip-decompress #b"not gzip at all")"#).unwrap_err();
+        assert!(err.contains("bad magic"), "got: {}", err);
+    }
+
+    const GZIP_FOX: &str = "1f8b08000000000002039dd2b71180300043d19e293402396d4330603036c9a4e939d800d5ba57e92ba905dc1c5b27305b590d2817736834e6446fc76985d9c5f2cdaab82fd4a675d46b3cc2f8840908131226224c4c988430296132e6532a849f253c36c2312572020000";
+
+    #[test]
+    fn test_read_file_bytes_as_gzip() {
+        let path = std::env::temp_dir().join(format!("rusp-gz-{}.gz", std::process::id()));
+        let path = path.to_string_lossy().replace('\\', "/");
+        eval_str(&format!(r#"(write-file-bytes "{}" (gzip-compress (string->bytes "log line")))"#, path)).unwrap();
+        for encoding in ["gzip", "auto"] {
+            let src = format!(r#"(bytes->string (read-file-bytes-as "{}" "{}"))"#, path, encoding);
+            assert_eq!(eval_str(&src).unwrap().to_string(), "log line");
+        }
+        let raw = eval_str(&format!(r#"(bytes-len (read-file-bytes-as "{}" "raw"))"#, path)).unwrap();
+        assert!(matches!(raw, Value::Integer32(n) if n > 8), "got {:?}", raw);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_kv_store_persists_across_opens() {
+        let path = std::env::temp_dir().join(format!("rusp-kv-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_string_lossy().replace('\\', "/");
+        let src = format!(
+            r#"(let db (kv-open "{}")
+                 (let ignored (kv-put! (kv-put! (kv-put! db "a" "1") "b" "tab\there") "a" "2")
+                   (let gone (kv-delete! db "b") (kv-close db))))"#,
+            path
+        );
+        assert!(matches!(eval_str(&src).unwrap(), Value::Bool(true)));
+
+        let src = format!(
+            r#"(let db (kv-open "{}") (list (kv-get db "a") (kv-get-or db "b" "none") (kv-keys db)))"#,
+            path
+        );
+        assert_eq!(eval_str(&src).unwrap().to_string(), "(2 none (a))");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_kv_store_errors() {
+        let path = std::env::temp_dir().join(format!("rusp-kv-err-{}.db", std::process::id()));
+        let path = path.to_string_lossy().replace('\\', "/");
+        let err = eval_str(&format!(r#"(kv-get (kv-open "{}") "missing")"#, path)).unwrap_err();
+        assert!(err.contains("no key"), "got: {}", err);
+        let src = format!(r#"(let db (kv-open "{}") (let c (kv-close db) (kv-put! db "k" "v")))"#, path);
+        let err = eval_str(&src).unwrap_err();
+        assert!(err.contains("closed"), "got: {}", err);
+        assert!(type_check_str(r#"(kv-put! "db" "k" "v")"#).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_kv_store_drops_torn_record() {
+        let path = std::env::temp_dir().join(format!("rusp-kv-torn-{}.db", std::process::id()));
+        std::fs::write(&path, "P\tk\tv\nP\tk\tpartial").unwrap();
+        let path_str = path.to_string_lossy().replace('\\', "/");
+        let src = format!(r#"(let db (kv-open "{}") (kv-get (kv-put! db "x" "y") "k"))"#, path_str);
+        assert_eq!(eval_str(&src).unwrap().to_string(), "v");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "P\tk\tv\nP\tx\ty\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    const CLI_SPEC: &str = r#"(list (list "flag" "verbose" "v" "Print more")
+                                    (list "option" "output" "o" "Output file" "out.txt")
+                                    (list "option" "level" "" "Level")
+                                    (list "arg" "input" "Input file"))"#;
+
+    #[test]
+    fn test_cli_parse_flags_options_and_positionals() {
+        let src = format!(
+            r#"(cli/parse {} (list "-v" "--level=3" "in.log"))"#,
+            CLI_SPEC
+        );
+        let result = eval_str(&src).unwrap();
+        assert_eq!(result.to_string(), "((verbose true) (output out.txt) (level 3) (input in.log))");
+        let src = format!(
+            r#"(let p (cli/parse {} (list "in.log" "-o" "x.txt" "--level" "1")) (list (cli/get p "output") (cli/get p "verbose")))"#,
+            CLI_SPEC
+        );
+        assert_eq!(eval_str(&src).unwrap().to_string(), "(x.txt false)");
+        let src = format!(r#"(cli/flag? (cli/parse {} (list "-vo" "y" "--level" "2" "a")) "verbose")"#, CLI_SPEC);
+        assert!(matches!(eval_str(&src).unwrap(), Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_cli_parse_errors_include_usage() {
+        let src = format!(r#"(cli/parse {} (list "--level" "1"))"#, CLI_SPEC);
+        let err = eval_str(&src).unwrap_err();
+        assert!(err.starts_with("missing argument INPUT"), "got: {}", err);
+        assert!(err.contains("Usage: "), "got: {}", err);
+        assert!(err.contains("-o, --output <output>  Output file [default: out.txt]"), "got: {}", err);
+        let src = format!(r#"(cli/parse {} (list "--nope" "a"))"#, CLI_SPEC);
+        assert!(eval_str(&src).unwrap_err().starts_with("unknown option --nope"));
+        let src = format!(r#"(cli/parse {} (list "a"))"#, CLI_SPEC);
+        assert!(eval_str(&src).unwrap_err().starts_with("missing required option --level"));
+    }
+
+    #[test]
+    fn test_cli_parse_help_stops_the_script() {
+        // The script stops; printing usage and exiting is the driver's call.
+        let src = format!(r#"(cli/parse {} (list "a" "--help"))"#, CLI_SPEC);
+        assert_eq!(eval_str(&src).unwrap_err(), crate::cli::HELP_REQUESTED);
+        let usage = crate::cli::take_help().expect("the usage");
+        assert!(usage.starts_with("Usage: "), "got: {}", usage);
+        assert!(crate::cli::take_help().is_none());
+    }
+
+    #[test]
+    fn test_script_args() {
+        crate::cli::set_args("tool.rusp", vec!["a".to_string(), "--b".to_string()]);
+        assert_eq!(eval_str("(args)").unwrap().to_string(), "(a --b)");
+        assert_eq!(type_check_str("(args)").unwrap(), Type::List(Box::new(Type::String)));
+        crate::cli::set_args("rusp", Vec::new());
+    }
+
+    #[test]
+    fn test_ref_cells_are_shared_by_closures() {
+        let counter = "(defn make-counter [] -> fn() -> i32 \
+                         (let c (ref 0) (fn [] -> i32 (do (set-ref! c (+ (deref c) 1)) (deref c)))))";
+        let result = run_seq(&[
+            counter,
+            "(def next (make-counter))",
+            "(def other (make-counter))",
+            "(let a (next) (let b (next) (let z (other) (+ (* 100 a) (+ (* 10 b) z)))))",
+        ])
+        .unwrap();
+        assert!(matches!(result, Value::Integer32(121)), "got: {}", result);
+        assert_eq!(eval_str("(ref (list 1 2))").unwrap().to_string(), "#<ref:(1 2)>");
+    }
+
+    #[test]
+    fn test_closures_share_their_scope() {
+        // Checked and run the way a file is: every `defn` declared first,
+        // then each form checked and evaluated in turn.
+        let thunks = "(loop [i 0 acc (list)] (if (= i 3) acc (recur (+ i 1) (cons (fn [] i) acc))))";
+        let forms: Vec<Expr> = [
+            // A function sees what's bound after it: a later `defn`, or a
+            // new definition of one it calls.
+            "(defn ev? [n: i32] -> bool (if (= n 0) true (od? (- n 1))))",
+            "(defn od? [n: i32] -> bool (if (= n 0) false (ev? (- n 1))))",
+            "(ev? 10)",
+            "(defn greeting [] -> i32 1)",
+            "(defn greet [] -> i32 (greeting))",
+            "(defn greeting [] -> i32 2)",
+            "(greet)",
+            // Recursion goes through the defining scope, whatever name the
+            // function is called under.
+            "(defn fact [n: i32] -> i32 (if (= n 0) 1 (* n (fact (- n 1)))))",
+            "(let f fact (f 5))",
+            // Each round of a loop binds afresh, so closures keep their round's.
+            &format!("(map (fn [f] (f)) {})", thunks),
+        ]
+        .iter()
+        .map(|src| parser::parse(src).unwrap())
+        .collect();
+        let mut tenv = TypeEnv::new();
+        declare_defns(&forms, &mut tenv);
+        let mut env = Environment::new();
+        let results: Vec<String> = forms
+            .iter()
+            .map(|form| {
+                type_check(form, &mut tenv).unwrap();
+                eval(form, &mut env).unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(results[2], "true");
+        assert_eq!(results[6], "2");
+        assert_eq!(results[8], "120");
+        assert_eq!(results[9], "(2 1 0)");
+    }
+
+    #[test]
+    fn test_ref_types() {
+        assert_eq!(type_check_str("(ref 1)").unwrap(), Type::Ref(Box::new(Type::I32)));
+        assert_eq!(type_check_str("(deref (ref \"s\"))").unwrap(), Type::String);
+        assert_eq!(type_check_str("(let r (ref 1.5) (set-ref! r 2.5))").unwrap(), Type::Unit);
+        let err = type_check_str("(let r (ref 1) (set-ref! r \"no\"))").unwrap_err();
+        assert_eq!(err, "set-ref!: cell holds i32, got String");
+        assert!(type_check_str("(deref 1)").is_err());
+        assert_eq!(
+            type_check_str("(fn [r: Ref<i64>] -> i64 (deref r))").unwrap().to_string(),
+            "fn(Ref<i64>) -> i64"
+        );
+    }
+
+    #[test]
+    fn test_loop_recur() {
+        let sum = "(loop [i 0 acc 0] (if (> i 10) acc (recur (+ i 1) (+ acc i))))";
+        assert_eq!(type_check_str(sum).unwrap(), Type::I32);
+        assert!(matches!(eval_str(sum).unwrap(), Value::Integer32(55)));
+
+        // Each binding sees the ones before it.
+        assert!(matches!(eval_str("(loop [a 2 b (* a 3)] b)").unwrap(), Value::Integer32(6)));
+
+        // `recur` in a match arm and under a `let` is still in tail position.
+        let count = "(loop [xs (list 1 2 3) n 0] \
+                       (match xs (nil n) ((cons _ rest) (let m (+ n 1) (recur rest m)))))";
+        assert_eq!(type_check_str(count).unwrap(), Type::I32);
+        assert!(matches!(eval_str(count).unwrap(), Value::Integer32(3)));
+    }
+
+    #[test]
+    fn test_recur_runs_in_constant_stack() {
+        // Without a frame per round.
+        let result = run_seq(&[
+            "(defn count-up [i: i64 n: i64] -> i64 (if (= i n) i (recur (+ i 1) n)))",
+            "(count-up (to-i64 0) (to-i64 100000))",
+        ])
+        .unwrap();
+        assert!(matches!(result, Value::Integer64(100000)), "got: {}", result);
+
+        let result = run_seq(&["(loop [i 0] (if (< i 100000) (recur (+ i 1)) i))"]).unwrap();
+        assert!(matches!(result, Value::Integer32(100000)), "got: {}", result);
+
+        let lambda = run_seq(&["((fn [n: i32 acc: i32] -> i32 (if (= n 0) acc (recur (- n 1) (+ acc 2)))) 50000 0)"])
+            .unwrap();
+        assert!(matches!(lambda, Value::Integer32(100000)), "got: {}", lambda);
+    }
+
+    #[test]
+    fn test_recur_is_checked() {
+        let err = type_check_str("(loop [i 0] (+ 1 (recur (+ i 1))))").unwrap_err();
+        assert!(err.starts_with("recur must be in tail position"), "got: {}", err);
+        let err = type_check_str("(loop [i 0] (if (= (recur 1) 0) 1 2))").unwrap_err();
+        assert!(err.starts_with("recur must be in tail position"), "got: {}", err);
+        let err = type_check_str("(defn f [n: i32] -> i32 (let x (recur 1) x))").unwrap_err();
+        assert!(err.starts_with("recur must be in tail position"), "got: {}", err);
+
+        assert_eq!(
+            type_check_str("(loop [i 0 j 0] (recur 1))").unwrap_err(),
+            "recur expects 2 arguments, got 1"
+        );
+        assert_eq!(
+            type_check_str("(defn f [n: i32] -> i32 (if (= n 0) 0 (recur \"x\")))").unwrap_err(),
+            "recur argument 1 must be i32, got String"
+        );
+        assert_eq!(
+            type_check_str("(recur 1)").unwrap_err(),
+            "recur is only allowed inside a loop, defn or fn"
+        );
+
+        // A nested fn is its own target: this `recur` restarts the fn, not
+        // the loop, so its arity is the fn's.
+        assert!(type_check_str("(loop [i 0 j 0] (fn [n: i32] -> i32 (if (= n 0) 0 (recur (- n 1)))))").is_ok());
+
+        // Unchecked code gets the same rules at run time.
+        assert_eq!(eval_str("(+ 1 (loop [i 0] (+ 1 (recur i))))").unwrap_err(), "recur must be in tail position");
+        assert_eq!(eval_str("(loop [i 0] (recur 1 2))").unwrap_err(), "recur expects 1 arguments, got 2");
+    }
+
+    const NATURALS: &str = "(defgen naturals [] (loop [i 0] (yield i) (recur (+ i 1))))";
+
+    #[test]
+    fn test_generators() {
+        let take = |src: &str| run_seq(&[NATURALS, src]).unwrap().to_string();
+        assert_eq!(take("(take 5 (naturals))"), "(0 1 2 3 4)");
+        assert_eq!(take("(take 3 (map (fn [x: i32] -> i32 (* x x)) (naturals)))"), "(0 1 4)");
+        assert_eq!(take("(take 0 (naturals))"), "nil");
+
+        // Copies share one position.
+        let result = run_seq(&[NATURALS, "(def g (naturals))", "(take 2 g)", "(take 2 g)"]).unwrap();
+        assert_eq!(result.to_string(), "(2 3)");
+
+        // A finite generator stops short; the defgen itself is a `recur` target.
+        let countdown = "(defgen countdown [n: i32] (yield n) (if (= n 0) nil (recur (- n 1))))";
+        assert_eq!(run_seq(&[countdown, "(take 10 (countdown 3))"]).unwrap().to_string(), "(3 2 1 0)");
+
+        // `yield` under `if`, `let` and `match`, and skipped on some rounds.
+        let odds = "(defgen odds [xs: List<i32>] \
+                      (match xs (nil nil) \
+                        ((cons x rest) (let odd (= 1 (- x (* 2 (/ x 2)))) \
+                                         (do (if odd (yield x) nil) (recur rest))))))";
+        let result = run_seq(&[odds, "(take 10 (odds (list 1 2 3 4 5)))"]).unwrap();
+        assert_eq!(result.to_string(), "(1 3 5)");
+
+        assert_eq!(eval_str("(take 2 (list 1 2 3))").unwrap().to_string(), "(1 2)");
+        assert_eq!(type_check_seq(&[NATURALS, "(naturals)"]).unwrap().to_string(), "Gen<i32>");
+        assert_eq!(
+            type_check_seq(&[NATURALS, "(map (fn [x: i32] -> bool (> x 2)) (naturals))"])
+                .unwrap()
+                .to_string(),
+            "Gen<bool>"
+        );
+    }
+
+    #[test]
+    fn test_generators_are_checked() {
+        let err = type_check_str("(defgen g [] (+ 1 (yield 2)))").unwrap_err();
+        assert!(err.starts_with("yield can't be used as a value"), "got: {}", err);
+        assert_eq!(
+            type_check_str("(defgen g [] (yield 1) (yield \"two\"))").unwrap_err(),
+            "yield: the generator yields i32, got String"
+        );
+        assert_eq!(type_check_str("(yield 1)").unwrap_err(), "yield is only allowed inside defgen");
+        // A fn inside a defgen can't yield for it.
+        assert_eq!(
+            type_check_str("(defgen g [] (yield 1) ((fn [] -> i32 (yield 2))))").unwrap_err(),
+            "yield is only allowed inside defgen"
+        );
+        let err = type_check_str("(defgen g [n: i32] (recur (+ n 1)) (yield n))").unwrap_err();
+        assert!(err.starts_with("recur must be in tail position"), "got: {}", err);
+
+        // A body that pulls from its own generator gets an error, not a panic.
+        let result = run_seq(&[
+            "(def r (ref nil))",
+            "(defgen selfish [] (yield (take 1 (deref r))))",
+            "(def g (selfish))",
+            "(set-ref! r g)",
+            "(take 1 g)",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parameterize() {
+        let level = "(defparam *level* 1)";
+        let at_level = "(defn at-level [] -> i32 *level*)";
+        let run = |src: &str| run_seq(&[level, at_level, src]).unwrap().to_string();
+        assert_eq!(run("(at-level)"), "1");
+        // Seen by everything called inside, and undone afterwards.
+        assert_eq!(run("(parameterize [*level* 3] (at-level))"), "3");
+        assert_eq!(run("(parameterize [*level* 3] (parameterize [*level* 4] (at-level)))"), "4");
+        assert_eq!(run("(do (parameterize [*level* 3] 0) (at-level))"), "1");
+        // Also after the body fails.
+        let mut tenv = TypeEnv::new();
+        let mut env = Environment::new();
+        for src in [level, at_level, "(parameterize [*level* 3] (car nil))"] {
+            let expr = parser::parse(src).unwrap();
+            type_check(&expr, &mut tenv).unwrap();
+            let _ = eval(&expr, &mut env);
+        }
+        let expr = parser::parse("(at-level)").unwrap();
+        assert!(matches!(eval(&expr, &mut env).unwrap(), Value::Integer32(1)));
+
+        assert_eq!(
+            type_check_seq(&[level, "(parameterize [*level* \"high\"] 0)"]).unwrap_err(),
+            "parameterize: `*level*` is i32, got String"
+        );
+        assert_eq!(
+            type_check_seq(&["(defn f [] -> i32 1)", "(parameterize [f 1] 0)"]).unwrap_err(),
+            "parameterize: `f` is not a parameter; declare it with defparam"
+        );
+        assert_eq!(
+            type_check_str("(let x 1 (defparam *y* x))").unwrap_err(),
+            "`defparam *y*` is only allowed at the top level"
+        );
+    }
+
+    #[test]
+    fn test_out_port() {
+        let result = run_seq(&[
+            "(def buf (string-port))",
+            "(defn greet [name: String] -> Unit (println name))",
+            "(parameterize [*out* buf] (do (print \"hello \") (greet \"world\") (print 42)))",
+            "(port-string buf)",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "hello world\n42");
+        assert_eq!(type_check_str("*out*").unwrap(), Type::Port);
+        assert!(eval_str("(port-string stdout)").is_err());
+    }
+
+    #[test]
+    fn test_capture_output() {
+        let (result, captured) = crate::env::capture_output(|| {
+            run_seq(&["(println \"out\")", "(parameterize [*out* stderr] (print \"err\"))", "(print 1)"])
+        });
+        assert!(result.is_ok());
+        assert_eq!(captured.stdout, "out\n1");
+        assert_eq!(captured.stderr, "err");
+
+        // A host's own writer gets it from then on.
+        struct Lines(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+        impl std::io::Write for Lines {
+            fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let buf = std::rc::Rc::default();
+        let saved = crate::env::set_stdout(Box::new(Lines(std::rc::Rc::clone(&buf))));
+        eval_str("(println \"to the host\")").unwrap();
+        crate::env::set_stdout(saved);
+        assert_eq!(&*buf.borrow(), b"to the host\n");
+    }
+
+    #[test]
+    fn test_heap_stats() {
+        use std::rc::Rc;
+        let mut env = crate::env::Environment::new();
+        let before = env.heap_stats();
+        assert!(before.environments >= 1);
+        assert!(before.values["builtin"] > 0);
+
+        // The pair is held twice but counted once; its halves count too.
+        let pair = Value::Pair(Rc::new((Value::Integer32(1), Value::String("a".try_into().unwrap()))));
+        env.set("p".to_string(), pair.clone());
+        env.set("ps".to_string(), Value::List(vec![pair, Value::Integer32(2)]));
+        let after = env.heap_stats();
+        assert_eq!(after.values["pair"], 1);
+        assert_eq!(after.values["list"], before.values.get("list").copied().unwrap_or(0) + 1);
+        assert_eq!(after.values["i32"], before.values.get("i32").copied().unwrap_or(0) + 2);
+
+        let stats = eval_str("(memory-stats)").unwrap().to_string();
+        assert!(stats.starts_with("((:environments . "), "{}", stats);
+        assert!(stats.contains("(:keywords . "), "{}", stats);
+        assert!(eval_str("(memory-stats 1)").unwrap_err().contains("memory-stats takes no arguments"));
+    }
+
+    #[test]
+    fn test_with_open_closes_on_every_exit() {
+        let path = std::env::temp_dir().join(format!("rusp-with-open-{}.txt", std::process::id()));
+        let path = path.to_string_lossy().replace('\\', "/");
+        let src = format!(
+            r#"(with-open [f (open-file "{}")] (parameterize [*out* f] (print "line") (close f)))"#,
+            path
+        );
+        // `close` inside reports it was still open; with-open's own close
+        // after that is a no-op.
+        assert!(matches!(run_seq(&[&src]).unwrap(), Value::Bool(true)));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "line");
+
+        // A failing body still gets its file flushed and closed, and the
+        // error comes through.
+        let src = format!(
+            r#"(with-open [f (open-file "{}")] (parameterize [*out* f] (print "partial") (car nil)))"#,
+            path
+        );
+        assert!(run_seq(&[&src]).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "partial");
+
+        let err = run_seq(&[&format!(
+            r#"(def f (with-open [f (open-file "{}")] f))"#,
+            path
+        ), "(parameterize [*out* f] (print 1))"])
+        .unwrap_err();
+        assert!(err.ends_with("is closed"), "got: {}", err);
+        assert_eq!(
+            type_check_str("(with-open [n 1] n)").unwrap_err(),
+            "with-open: `n` is i32, which has nothing to close"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_defer() {
+        let log = "(def log (ref nil))";
+        let note = "(defn note [s: String] -> Unit (set-ref! log (cons s (deref log))))";
+        // Last deferred runs first, after the body.
+        let result = run_seq(&[
+            log,
+            note,
+            "(defn f [] -> i32 (do (defer (note \"first\")) (defer (note \"second\")) (note \"body\") 1))",
+            "(f)",
+            "(deref log)",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(first second body)");
+
+        // Failing bodies run their defers too, and keep their error.
+        let mut tenv = TypeEnv::new();
+        let mut env = Environment::new();
+        let mut results = Vec::new();
+        for src in [log, note, "(defn g [] -> i32 (do (defer (note \"cleanup\")) (car nil)))", "(g)", "(deref log)"] {
+            let expr = parser::parse(src).unwrap();
+            type_check(&expr, &mut tenv).unwrap();
+            results.push(eval(&expr, &mut env));
+        }
+        assert!(results[3].is_err());
+        assert_eq!(results[4].as_ref().unwrap().to_string(), "(cleanup)");
+
+        assert_eq!(
+            type_check_str("(defer 1)").unwrap_err(),
+            "defer is only allowed inside a function body or with-open"
+        );
+        assert_eq!(
+            type_check_str("(defgen g [] (defer 1) (yield 1))").unwrap_err(),
+            "defer is only allowed inside a function body or with-open"
+        );
+    }
+
+    #[test]
+    fn test_return() {
+        let result = run_seq(&[
+            "(defn sign [n: i32] -> String (do (if (< n 0) (return \"neg\") nil) (if (= n 0) (return \"zero\") nil) \"pos\"))",
+            "(list (sign -3) (sign 0) (sign 4))",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(neg zero pos)");
+
+        // From deep inside a loop, leaving only the innermost function.
+        let result = run_seq(&[
+            "(defn first-over [limit: i32] -> i32 (loop [i 0] (if (> (* i i) limit) (return i) (recur (+ i 1)))))",
+            "(map (fn [x: i32] (+ (first-over x) 0)) (list 10 50))",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(4 8)");
+
+        // Defers still run on the way out.
+        let result = run_seq(&[
+            "(def log (ref nil))",
+            "(defn f [] -> i32 (do (defer (set-ref! log (cons \"deferred\" (deref log)))) (return 1) 2))",
+            "(f)",
+            "(deref log)",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(deferred)");
+
+        assert_eq!(
+            type_check_str("(defn f [] -> i32 (do (return \"no\") 1))").unwrap_err(),
+            "return: the function returns i32, got String"
+        );
+        assert_eq!(
+            type_check_str("(fn [x: i32] (do (if (< x 0) (return x) nil) true))").unwrap_err(),
+            "Lambda return type mismatch: expected i32, got bool"
+        );
+        assert_eq!(type_check_str("(return 1)").unwrap_err(), "return is only allowed inside defn or fn");
+        assert_eq!(
+            type_check_str("(defgen g [] (return 1))").unwrap_err(),
+            "return is only allowed inside defn or fn"
+        );
+    }
+
+    #[test]
+    fn test_block_return_from() {
+        // Out of two loops at once: the first pair adding up to 10.
+        let result = eval_str(
+            "(block :found (loop [i 1] (loop [j i] (if (= (+ i j) 10) (return-from :found (list i j)) (if (< j 9) (recur (+ j 1)) nil))) (recur (+ i 1))))",
+        )
+        .unwrap();
+        assert_eq!(result.to_string(), "(1 9)");
+
+        // Falling off the end gives the body's value; an inner block with
+        // another label lets an outer return-from through.
+        assert_eq!(eval_str("(block :a 1 2)").unwrap().to_string(), "2");
+        assert_eq!(
+            eval_str("(block :outer (+ 100 (block :inner (return-from :outer 1))))").unwrap().to_string(),
+            "1"
+        );
+        assert_eq!(type_check_str("(block :b (if true (return-from :b 1) 2))").unwrap(), Type::I32);
+
+        assert_eq!(
+            type_check_str("(block :a (return-from :b 1))").unwrap_err(),
+            "return-from: no enclosing block :b"
+        );
+        assert_eq!(
+            type_check_str("(block :a (if true (return-from :a 1) (return-from :a \"x\")))").unwrap_err(),
+            "return-from :a: the block gives i32, got String"
+        );
+        assert_eq!(
+            type_check_str("(block :a (do (return-from :a 1) \"x\"))").unwrap_err(),
+            "block :a: the body gives String, but return-from gives i32"
+        );
+        // Labels don't reach into functions defined inside the block.
+        assert_eq!(
+            type_check_str("(block :a (fn [] (return-from :a 1)))").unwrap_err(),
+            "return-from: no enclosing block :a"
+        );
+    }
+
+    #[test]
+    fn test_short_lambda() {
+        assert_eq!(eval_str("(map #(* % 2) (list 1 2 3))").unwrap().to_string(), "(2 4 6)");
+        assert_eq!(eval_str("(fold #(+ %1 %2) 0 (list 1 2 3))").unwrap().to_string(), "6");
+        assert_eq!(eval_str("(#(- %2 %1) 1 10)").unwrap().to_string(), "9");
+        assert_eq!(
+            type_check_str("(map #(+ % 1) (list 1 2))").unwrap(),
+            Type::List(Box::new(Type::I32))
+        );
+    }
+
+    #[test]
+    fn test_reader_macros() {
+        let result = run_seq(&[
+            "(defn parse-date [s: String] -> DateTime (parse-time s \"%Y-%m-%d\"))",
+            "(set-reader-macro! #\\$ parse-date)",
+            "(year $2024-03-15)",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "2024");
+
+        assert_eq!(
+            run_seq(&["(set-reader-macro! #\\~ missing)"]).unwrap_err(),
+            "set-reader-macro!: `missing` is not defined"
+        );
+        assert_eq!(
+            run_seq(&["(defn two [a: i32 b: i32] -> i32 a)", "(set-reader-macro! #\\~ two)"]).unwrap_err(),
+            "set-reader-macro!: `two` must be fn(String) -> _, got fn(i32, i32) -> i32"
+        );
+    }
+
+    #[test]
+    fn test_foreign_finalizers() {
+        use crate::foreign::Foreign;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let released = Rc::new(Cell::new(0));
+        let connection = |id: u32| {
+            let released = Rc::clone(&released);
+            Value::Foreign(Rc::new(Foreign::with_finalizer("Connection", id, move |_| {
+                released.set(released.get() + 1)
+            })))
+        };
+        let run = |env: &mut Environment, src: &str| eval(&parser::parse(src).unwrap(), env);
+
+        let mut env = Environment::new();
+        env.set("conn".to_string(), connection(7));
+        assert_eq!(run(&mut env, "(type-of conn)").unwrap().to_string(), "Connection");
+        run(&mut env, "(let copy conn copy)").unwrap();
+        assert_eq!(released.get(), 0);
+        // The last copy going away releases it.
+        run(&mut env, "(def conn 0)").unwrap();
+        assert_eq!(released.get(), 1);
+
+        // `close`, and so `with-open`, releases it early, and only once.
+        env.set("conn".to_string(), connection(8));
+        assert!(matches!(run(&mut env, "(with-open [c conn] 1)").unwrap(), Value::Integer32(1)));
+        assert_eq!(released.get(), 2);
+        assert!(matches!(run(&mut env, "(close conn)").unwrap(), Value::Bool(false)));
+        drop(env);
+        assert_eq!(released.get(), 2);
+
+        let plain = Foreign::new("Buffer", vec![1u8, 2]);
+        assert_eq!(plain.downcast_ref::<Vec<u8>>(), Some(&vec![1, 2]));
+        assert!(plain.downcast_ref::<u32>().is_none());
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        // Calls don't use the Rust stack, so this needn't be a tail call.
+        let result = run_seq(&[
+            "(defn count [n: i32] -> i32 (if (= n 0) 0 (+ 1 (count (- n 1)))))",
+            "(count 10000)",
+        ])
+        .unwrap();
+        assert!(matches!(result, Value::Integer32(10000)), "got: {}", result);
+
+        let result = run_seq(&[
+            "(defn build [n: i32] -> List<i32> (if (= n 0) nil (cons n (build (- n 1)))))",
+            "(fold (fn [a: i32 b: i32] -> i32 (+ a b)) 0 (map (fn [x: i32] -> i32 (* x 2)) (build 5000)))",
+        ])
+        .unwrap();
+        assert!(matches!(result, Value::Integer32(25005000)), "got: {}", result);
+    }
+
+    #[test]
+    fn test_evaluation_steps() {
+        use crate::eval::Evaluation;
+
+        let mut env = Environment::new();
+        eval(&parser::parse("(defn sq [x: i32] -> i32 (* x x))").unwrap(), &mut env).unwrap();
+        let mut evaluation = Evaluation::new(parser::parse("(do (def y (sq 4)) (+ y 1))").unwrap(), env);
+        let mut steps = 0;
+        let result = loop {
+            if let Some(result) = evaluation.step() {
+                break result;
+            }
+            steps += 1;
+        };
+        assert!(matches!(result, Ok(Value::Integer32(17))));
+        assert!(steps > 5, "took {} steps", steps);
+        // Done stays done.
+        assert!(matches!(evaluation.step(), Some(Ok(Value::Integer32(17)))));
+        let env = evaluation.into_env();
+        assert!(matches!(env.get("y"), Some(Value::Integer32(16))));
+
+        // Stopped halfway, the environment still comes back whole.
+        let mut evaluation = Evaluation::new(parser::parse("(let z 1 (sq z))").unwrap(), env);
+        for _ in 0..4 {
+            assert!(evaluation.step().is_none());
+        }
+        let env = evaluation.into_env();
+        assert!(env.get("z").is_none());
+        assert!(env.get("sq").is_some());
+    }
+
+    #[test]
+    fn test_shift_reset() {
+        // `k` is "double it": the rest of the reset's body.
+        assert_eq!(eval_str("(+ 1 (reset (* 2 (shift k (k (k 10))))))").unwrap().to_string(), "41");
+        // Not calling it abandons the rest.
+        assert_eq!(eval_str("(reset (+ 1 (shift k 5)))").unwrap().to_string(), "5");
+        // Called twice, it runs twice; from a function called under the reset too.
+        let result = run_seq(&[
+            "(defn pick [x: i32] -> i32 (+ x (shift k (list (k 1) (k 2)))))",
+            "(reset (pick 10))",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(11 12)");
+        // Kept and called later, it still ends where its reset did.
+        let result = eval_str("(let k (reset (+ 100 (shift k k))) (list (k 1) (k 2)))").unwrap();
+        assert_eq!(result.to_string(), "(101 102)");
+        // A list built one `shift` per element.
+        let result = run_seq(&[
+            "(defn each [xs: List<i32>] -> i32 (match xs (nil 0) ((cons h t) (do (shift k (cons h (k 0))) (each t)))))",
+            "(reset (do (each (list 1 2 3)) nil))",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(1 2 3)");
+
+        assert_eq!(eval_str("(shift k 1)").unwrap_err(), "shift is only allowed inside reset");
+        assert_eq!(
+            eval_str("(reset (with-open [p (string-port)] (shift k 1)))").unwrap_err(),
+            "shift can't capture a parameterize, with-open or defer"
+        );
+        assert_eq!(
+            eval_str("(reset (+ 1 (shift k (k 1 2))))").unwrap_err(),
+            "Wrong number of arguments: expected 1, got 2"
+        );
+        assert_eq!(type_check_str("(reset 1)").unwrap(), Type::Inferred);
+    }
+
+    #[test]
+    fn test_function_display() {
+        let shown = |src: &str| eval_str(src).unwrap().to_string();
+        assert_eq!(shown("(defn add [a: i32 b: i32] -> i32 (+ a b))"), "#<function add [a: i32 b: i32] -> i32>");
+        assert_eq!(shown("(fn [x: i32] (* x 2))"), "#<fn [x: i32]>");
+        assert_eq!(shown("#(* % 2)"), "#<fn [%1]>");
+        assert_eq!(shown("(defgen upto [n: i32] (yield n))"), "#<defgen upto [n: i32]>");
+        assert_eq!(
+            eval_str("(do (defn one [] -> i32 1) ((one) 2))").unwrap_err(),
+            "Cannot call non-function value: 1"
+        );
+        assert_eq!(
+            eval_str("(do (defn one [] -> i32 1) (one 2))").unwrap_err(),
+            "Wrong number of arguments: expected 0, got 1"
+        );
+    }
+
+    #[test]
+    fn test_bindings_and_defined() {
+        let result = eval_str("(let x 1 (let y \"s\" (let x true (bindings))))").unwrap();
+        let Value::List(pairs) = result else { panic!("not a list: {}", result) };
+        let shown: Vec<String> = pairs.iter().take(2).map(Value::to_string).collect();
+        assert_eq!(shown, ["(x . bool)", "(y . String)"]);
+        // The shadowed `x` isn't listed again; the builtins are.
+        assert_eq!(pairs.iter().filter(|p| p.to_string().starts_with("(x ")).count(), 1);
+        let car = format!("(car . {})", crate::lint::builtin_type("car").unwrap());
+        assert!(car.contains("List"), "car: {}", car);
+        assert!(pairs.iter().any(|p| p.to_string() == car));
+        // Under another name too.
+        let result = eval_str("(let head car (bindings))").unwrap();
+        let Value::List(pairs) = result else { panic!("not a list: {}", result) };
+        assert_eq!(pairs[0].to_string(), car.replacen("car", "head", 1));
+
+        assert!(matches!(eval_str("(let x 1 (defined? x))").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(defined? nowhere)").unwrap(), Value::Bool(false)));
+        assert_eq!(eval_str("(defined? \"x\")").unwrap_err(), "defined? expects a name: (defined? x)");
+        assert_eq!(eval_str("(bindings 1)").unwrap_err(), "bindings takes no arguments");
+
+        assert_eq!(type_check_str("(defined? nowhere)").unwrap(), Type::Bool);
+        assert_eq!(type_check_str("(bindings)").unwrap().to_string(), "List<Pair<String, String>>");
+    }
+
+    #[test]
+    fn test_type_values() {
+        let shown = |src: &str| eval_str(src).unwrap().to_string();
+        assert_eq!(shown("(type-of 42)"), "i32");
+        assert_eq!(shown("(type-of (list (list 1)))"), "List<List<i32>>");
+        assert_eq!(shown("(type-of nil)"), "List<_>");
+        assert_eq!(shown("(type-of (cons 1 \"x\"))"), "Pair<i32, String>");
+        assert_eq!(shown("(type-of (fn [x: i32] -> bool true))"), "fn(i32) -> bool");
+        assert_eq!(shown("(type-of i32)"), "Type");
+
+        let result = run_seq(&[
+            "(defn kind [x: _] -> String (if (type= (type-of x) i32) \"int\" (if (fn? x) \"fn\" \"other\")))",
+            "(list (kind 1) (kind kind) (kind \"s\"))",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(int fn other)");
+        assert!(matches!(eval_str("(int? (to-i64 1))").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(int? 1.5)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(fn? car)").unwrap(), Value::Bool(true)));
+
+        assert_eq!(type_check_str("(type-of 1)").unwrap(), Type::Type);
+        assert_eq!(
+            type_check_str("(type= (type-of 1) \"i32\")").unwrap_err(),
+            "Type mismatch in argument: expected Type, got String"
+        );
+    }
+
+    #[test]
+    fn test_function_reflection() {
+        let result = run_seq(&[
+            "(defn add [a: i32 b: i32] -> i32 (+ a b))",
+            "(list (arity add) (arity car) (arity (fn [] 1)))",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "(2 1 0)");
+        assert_eq!(eval_str("(param-names (fn [x y: i32] x))").unwrap().to_string(), "(x y)");
+        assert_eq!(
+            eval_str("(do (defn add [a: i32 b: i32] -> i32 (+ a b)) (fn-source add))").unwrap().to_string(),
+            "(defn add [a: i32 b: i32] -> i32 (+ a b))"
+        );
+        assert_eq!(eval_str("(fn-source (fn [x: i32] (* x 2)))").unwrap().to_string(), "(fn [x: i32] (* x 2))");
+
+        assert_eq!(eval_str("(param-names car)").unwrap_err(), "param-names: car is a builtin");
+        assert_eq!(eval_str("(fn-source 1)").unwrap_err(), "fn-source requires a function, got i32");
+        assert_eq!(type_check_str("(param-names car)").unwrap().to_string(), "List<String>");
+    }
+
+    #[test]
+    fn test_meta() {
+        let result = run_seq(&[
+            "(defn ^{:author \"kiri\" :since \"0.2\"} f [x: i32] -> i32 x)",
+            "(meta f)",
+        ])
+        .unwrap();
+        assert_eq!(result.to_string(), "((:author . kiri) (:since . 0.2))");
+        assert!(matches!(eval_str("(meta (fn [x: i32] x))").unwrap(), Value::Nil));
+        assert!(matches!(eval_str("(meta car)").unwrap(), Value::Nil));
+        assert_eq!(eval_str("(meta 1)").unwrap_err(), "meta requires a function, got i32");
+        // It survives fn-source.
+        assert_eq!(
+            eval_str("(do (defn ^{:since 2} g [] -> i32 1) (fn-source g))").unwrap().to_string(),
+            "(defn ^{:since 2} g [] -> i32 1)"
+        );
+    }
+
+    #[test]
+    fn test_let_lambda_takes_annotated_types() {
+        assert_eq!(
+            type_check_str("(let f: fn(i32) -> i32 (fn [x] (+ x 1)) (f 2))").unwrap(),
+            Type::I32
+        );
+        assert!(matches!(
+            eval_str("(let f: (fn (i32) -> i32) (fn [x] (+ x 1)) (f 2))").unwrap(),
+            Value::Integer32(3)
+        ));
+        // The parameter really is an i32 now, and the return type is held
+        // to the annotation too.
+        assert!(type_check_str("(let f: fn(i32) -> i32 (fn [x] (+ x 1.0)) (f 2))").is_err());
+        assert!(type_check_str("(let f: fn(i32) -> i32 (fn [x] (> x 1)) (f 2))").is_err());
+        assert_eq!(
+            type_check_str("(let f: fn(i32) -> i32 (fn [x y] 1) (f 2))").unwrap_err(),
+            "Type mismatch: expected fn(i32) -> i32, got fn(_, _) -> i32"
+        );
+        assert_eq!(
+            type_check_str("(let f: fn(i32) -> i32 (fn [x: bool] 1) (f 2))").unwrap_err(),
+            "Type mismatch: fn([-i32-]{+bool+}) -> i32 (parameter 1: expected i32, got bool)"
+        );
+        assert_eq!(
+            run_seq(&["(def inc: fn(i64) -> i64 #(+ % 1))", "(inc (to-i64 41))"]).unwrap().to_string(),
+            "42"
+        );
+    }
+
+    #[test]
+    fn test_functions_as_arguments() {
+        let app = "(defn app [f: fn(i32) -> i32 x: i32] -> i32 (f x))";
+        assert!(matches!(run_seq(&[app, "(app (fn [y] (+ y 1)) 2)"]).unwrap(), Value::Integer32(3)));
+        assert!(matches!(
+            run_seq(&[app, "(defn inc [n: i32] -> i32 (+ n 1))", "(app inc 2)"]).unwrap(),
+            Value::Integer32(3)
+        ));
+        // The fn's parameter is the i32 `app` passes, whether or not it
+        // says so; its arity and result are held to the type too.
+        assert_eq!(
+            type_check_seq(&[app, "(app (fn [y] (if y 1 2)) 2)"]).unwrap_err(),
+            "If condition must be bool, got i32; compare it, e.g. (not (= x 0))"
+        );
+        for call in ["(app (fn [y z] y) 2)", "(app (fn [y: i64] 1) 2)", "(app car 2)"] {
+            let err = type_check_seq(&[app, call]).unwrap_err();
+            assert!(err.starts_with("Type mismatch in argument"), "{}: {}", call, err);
+        }
+        assert_eq!(
+            type_check_seq(&[app, "(app (fn [y] true) 2)"]).unwrap_err(),
+            "Lambda return type mismatch: expected i32, got bool"
+        );
+
+        // An unannotated parameter that's called is a function of that
+        // many arguments.
+        let twice = "(defn twice [f x: i32] -> i32 (f (f x)))";
+        assert_eq!(type_check_seq(&[twice]).unwrap().to_string(), "fn(fn(i32) -> i32, i32) -> i32");
+        assert!(matches!(run_seq(&[twice, "(twice #(* % 3) 2)"]).unwrap(), Value::Integer32(18)));
+        assert!(type_check_seq(&[twice, "(twice (fn [a b] a) 2)"]).is_err());
+    }
+
+    #[test]
+    fn test_functions_as_results() {
+        let adder = "(defn adder [n: i32] -> fn(i32) -> i32 (fn [x] (+ x n)))";
+        let compose = "(defn compose [f: fn(i32) -> i32 g: fn(i32) -> i32] -> fn(i32) -> i32 (fn [x] (f (g x))))";
+        assert_eq!(type_check_seq(&[adder, "(adder 1)"]).unwrap().to_string(), "fn(i32) -> i32");
+        assert!(matches!(run_seq(&[adder, "((adder 1) 2)"]).unwrap(), Value::Integer32(3)));
+        assert!(matches!(
+            run_seq(&[adder, compose, "((compose (adder 1) (adder 10)) 0)"]).unwrap(),
+            Value::Integer32(11)
+        ));
+        assert!(type_check_seq(&[adder, "((adder 1) true)"]).is_err());
+        assert!(type_check_seq(&[adder, "((adder 1) 2 3)"]).is_err());
+        assert!(type_check_str("(defn pos [] -> fn(i32) -> i32 (fn [x] (> x 0)))").is_err());
+
+        // With no `->`, a function's result is its body's type.
+        assert_eq!(
+            type_check_seq(&["(defn positive? [x: i32] (> x 0))", "(positive? 1)"]).unwrap(),
+            Type::Bool
+        );
+        assert_eq!(
+            type_check_seq(&["(defn make [] (fn [x: i32] (* x 2)))", "((make) 4)"]).unwrap(),
+            Type::I32
+        );
+    }
+
+    #[test]
+    fn test_the() {
+        assert_eq!(type_check_str("(the i32 (+ 1 2))").unwrap(), Type::I32);
+        // `i32` widens to `i64`, at runtime too.
+        assert_eq!(type_check_str("(the i64 5)").unwrap(), Type::I64);
+        assert!(matches!(eval_str("(the i64 5)").unwrap(), Value::Integer64(5)));
+        assert!(matches!(eval_str("(+ (the i64 1) (to-i64 2))").unwrap(), Value::Integer64(3)));
+        assert_eq!(
+            type_check_str("(the i32 true)").unwrap_err(),
+            "Type mismatch: expected i32, got bool"
+        );
+        assert!(type_check_str("(the i32 (the i64 1))").is_err());
+
+        // An unknown parameter learns the type, and a `fn` its parameters.
+        assert_eq!(type_check_str("(defn f [x] (the i32 x))").unwrap().to_string(), "fn(i32) -> i32");
+        assert_eq!(
+            type_check_str("(the fn(i32) -> i32 (fn [x] x))").unwrap().to_string(),
+            "fn(i32) -> i32"
+        );
+        // It's transparent to `recur`.
+        assert!(matches!(
+            eval_str("(loop [i 0] (if (< i 3) (the i32 (recur (+ i 1))) i))").unwrap(),
+            Value::Integer32(3)
+        ));
+    }
+
+    #[test]
+    fn test_defn_clauses() {
+        let fact = "(defn fact ((0) 1) ((n) (* n (fact (- n 1)))))";
+        assert_eq!(type_check_str(fact).unwrap().to_string(), "fn(i32) -> i32");
+        assert!(matches!(run_seq(&[fact, "(fact 5)"]).unwrap(), Value::Integer32(120)));
+
+        let len = "(defn len ((nil) 0) (((cons _ t)) (+ 1 (len t))))";
+        assert!(matches!(run_seq(&[len, "(len (list 1 2 3))"]).unwrap(), Value::Integer32(3)));
+
+        // Several arguments are matched together, first clause first.
+        let and = "(defn both ((true true) 1) ((_ _) 0))";
+        assert_eq!(type_check_str(and).unwrap().to_string(), "fn(bool, bool) -> i32");
+        assert!(matches!(run_seq(&[and, "(both true true)"]).unwrap(), Value::Integer32(1)));
+        assert!(matches!(run_seq(&[and, "(both true false)"]).unwrap(), Value::Integer32(0)));
+
+        // The clauses have to cover every argument.
+        assert_eq!(
+            type_check_str("(defn yes ((true) 1))").unwrap_err(),
+            "match is not exhaustive: missing patterns: false"
+        );
+        assert!(type_check_str("(defn first ((0 (cons x _)) x))").is_err());
+    }
+
+    #[test]
+    fn test_multi_arity_defn() {
+        let greet = "(defn greet
+            ([name: String] (greet name \"Hello, \"))
+            ([name: String greeting: String] (str-concat greeting name)))";
+        assert_eq!(
+            type_check_str(greet).unwrap().to_string(),
+            "fn(String) -> String | fn(String, String) -> String"
+        );
+        assert_eq!(run_seq(&[greet, "(greet \"bob\")"]).unwrap().to_string(), "Hello, bob");
+        assert_eq!(run_seq(&[greet, "(greet \"bob\" \"Hi, \")"]).unwrap().to_string(), "Hi, bob");
+        assert_eq!(
+            type_check_seq(&[greet, "(greet \"a\" \"b\" \"c\")"]).unwrap_err(),
+            "Wrong number of arguments: expected 1 or 2, got 3"
+        );
+        assert!(type_check_seq(&[greet, "(greet 1)"]).is_err());
+
+        // It goes where one of its arities would.
+        let apply = "(defn apply1 [f: fn(String) -> String] (f \"x\"))";
+        assert_eq!(type_check_seq(&[greet, apply, "(apply1 greet)"]).unwrap(), Type::String);
+        assert!(type_check_seq(&[greet, "(defn apply2 [f: fn(i32) -> i32] (f 1))", "(apply2 greet)"]).is_err());
+    }
+
+    #[test]
+    fn test_variadic_operators() {
+        assert_eq!(eval_str("(+ 1 2 3 4)").unwrap().to_string(), "10");
+        assert_eq!(eval_str("(- 10 1 2)").unwrap().to_string(), "7");
+        assert_eq!(type_check_str("(* 2 3 (to-i64 4))").unwrap(), Type::I64);
+        assert_eq!(eval_str("(* 2 3 (to-i64 4))").unwrap().to_string(), "24");
+        assert_eq!(eval_str("(+ 1.0 2.0 3.5)").unwrap().to_string(), "6.5");
+        assert_eq!(eval_str("(str-concat \"a\" \"b\" \"c\")").unwrap().to_string(), "abc");
+        assert!(matches!(eval_str("(< 1 2 3)").unwrap(), Value::Bool(true)));
+        assert!(matches!(eval_str("(< 1 3 2)").unwrap(), Value::Bool(false)));
+        assert!(matches!(eval_str("(= 2 2 2)").unwrap(), Value::Bool(true)));
+        assert!(type_check_str("(+ 1 2 \"x\")").is_err());
+        assert!(type_check_str("(+ 1)").is_err());
+
+        // A user function named like an operator keeps its own arity.
+        assert!(type_check_seq(&["(defn + [a: i32 b: i32] (- a b))", "(+ 1 2 3)"]).is_err());
+    }
+
+    #[test]
+    fn test_conditions_are_strictly_bool() {
+        assert_eq!(
+            type_check_str("(if 1 2 3)").unwrap_err(),
+            "If condition must be bool, got i32; compare it, e.g. (not (= x 0))"
+        );
+        assert_eq!(
+            type_check_str("(if (list 1) 2 3)").unwrap_err(),
+            "If condition must be bool, got List<i32>; test it with (null? xs)"
+        );
+        // An unannotated name used as a condition is a bool.
+        assert_eq!(
+            type_check_str("(defn f [x] (if x 1 2))").unwrap().to_string(),
+            "fn(bool) -> i32"
+        );
+
+        // A function passed in is held to how it's used as a condition.
+        let h = "(defn h [f] (if (f 1) 1 2))";
+        assert_eq!(type_check_seq(&[h]).unwrap().to_string(), "fn(fn(i32) -> bool) -> i32");
+        assert_eq!(type_check_seq(&[h, "(h (fn [y] y))"]).unwrap_err(), "Lambda return type mismatch: expected bool, got i32");
+        let g = "(defn g [f] (match 1 ((guard x (f x)) 1) (_ 2)))";
+        assert_eq!(type_check_seq(&[g, "(g (fn [y] y))"]).unwrap_err(), "Lambda return type mismatch: expected bool, got i32");
+        // Unchecked code is held to the same rule at run time.
+        assert_eq!(eval_str("((fn [f] (if (f 1) 1 2)) (fn [y] y))").unwrap_err(), "If condition must be bool, got i32");
+        assert_eq!(
+            eval_str("((fn [f] (match 1 ((guard x (f x)) 1) (_ 2))) (fn [y] y))").unwrap_err(),
+            "guard expression must be bool, got i32"
+        );
+        // A guard that fails is an error, not a failed match.
+        assert_eq!(
+            eval_str("(match 1 ((guard x (car (list))) 1) (_ 2))").unwrap_err(),
+            "car of empty list"
+        );
+    }
+
+    #[test]
+    fn test_deftype() {
+        let shape = "(deftype Shape (Circle f64) (Rect f64 f64) Empty)";
+        let area = "(defn area [s: Shape] -> f64
+            (match s ((Circle r) (* 3.0 (* r r))) ((Rect w h) (* w h)) (Empty 0.0)))";
+        assert_eq!(type_check_str(shape).unwrap(), Type::Type);
+        assert_eq!(run_seq(&[shape, "(Rect 2.0 3.0)"]).unwrap().to_string(), "(Rect 2 3)");
+        assert_eq!(type_check_seq(&[shape, "Empty"]).unwrap(), Type::Named("Shape".to_string()));
+        assert_eq!(run_seq(&[shape, area, "(area (Rect 2.0 3.0))"]).unwrap().to_string(), "6");
+        assert_eq!(run_seq(&[shape, area, "(area Empty)"]).unwrap().to_string(), "0");
+        assert_eq!(
+            type_check_seq(&[shape, "(map Circle (list 1.0 2.0))"]).unwrap().to_string(),
+            "List<Shape>"
+        );
+        assert!(type_check_seq(&[shape, "(Circle 1)"]).is_err());
+        assert!(type_check_seq(&[shape, "(area 1.0)"]).is_err());
+
+        // Matches are held to every constructor, fields included.
+        assert_eq!(
+            type_check_seq(&[shape, "(match (Circle 1.0) ((Circle r) r))"]).unwrap_err(),
+            "match is not exhaustive: missing patterns: (Rect _ _), Empty"
+        );
+        let opt = "(deftype Opt None (Some bool))";
+        assert_eq!(
+            type_check_seq(&[opt, "(match (Some true) ((Some true) 1) (None 0))"]).unwrap_err(),
+            "match is not exhaustive: missing patterns: (Some false)"
+        );
+        assert_eq!(
+            type_check_seq(&[opt, "(match (Some true) ((Some x y) 1) (_ 0))"]).unwrap_err(),
+            "constructor `Some` has 1 field, the pattern gives 2"
+        );
+        assert_eq!(
+            type_check_seq(&[opt, "(match 1 ((Some x) 1) (_ 0))"]).unwrap_err(),
+            "constructor `Some` makes Opt, not i32"
+        );
+        assert_eq!(
+            type_check_seq(&[opt, "(deftype Other (Some i32))"]).unwrap_err(),
+            "constructor `Some` already belongs to type Opt"
+        );
+        // An unannotated scrutinee is the type its constructors make.
+        assert_eq!(
+            type_check_seq(&[opt, "(defn pick [o] (match o ((Some b) b) (None false)))"]).unwrap().to_string(),
+            "fn(Opt) -> bool"
+        );
+
+        // Types can refer to themselves.
+        let tree = "(deftype Tree Leaf (Node Tree i32 Tree))";
+        let sum = "(defn sum [t: Tree] -> i32 (match t (Leaf 0) ((Node l v r) (+ (sum l) v (sum r)))))";
+        assert!(matches!(
+            run_seq(&[tree, sum, "(sum (Node (Node Leaf 1 Leaf) 2 (Node Leaf 3 Leaf)))"]).unwrap(),
+            Value::Integer32(6)
+        ));
+        assert_eq!(
+            type_check_str("(deftype Bad (B Foo))").unwrap_err(),
+            "deftype Bad: unknown type Foo in constructor `B`"
+        );
+        assert!(type_check_str("(let x 1 (deftype Z A))").is_err());
+    }
+
+    #[test]
+    fn test_unit() {
+        assert_eq!(type_check_str("(println 1)").unwrap(), Type::Unit);
+        assert_eq!(eval_str("(print \"\")").unwrap().to_string(), "()");
+        assert_eq!(
+            type_check_str("(defn greet [n: String] (println n))").unwrap().to_string(),
+            "fn(String) -> Unit"
+        );
+        assert_eq!(type_check_str("(do (println 1) 2)").unwrap(), Type::I32);
+        assert!(type_check_str("(defn f [x: i32] -> i32 (println x))").is_err());
+
+        // Where a value is needed, Unit won't do.
+        assert_eq!(
+            type_check_str("(+ 1 (println 2))").unwrap_err(),
+            "(println 2) gives Unit, which has no value to use as an argument"
+        );
+        assert_eq!(
+            type_check_str("(let x (println 1) x)").unwrap_err(),
+            "(println 1) gives Unit, which has no value to use as `x`"
+        );
+        assert!(type_check_str("(list 1 (print 2))").is_err());
+        assert!(type_check_str("(let r (ref 1) (+ 1 (set-ref! r 2)))").is_err());
+        // Unless it's asked for.
+        assert_eq!(type_check_str("(let x: Unit (println 1) x)").unwrap(), Type::Unit);
+        assert!(type_check_seq(&["(defn f [u: Unit] 1)", "(f (println 1))"]).is_ok());
+    }
+
+    #[test]
+    fn test_defstruct() {
+        let point = "(defstruct Point [x: f64 y: f64])";
+        assert_eq!(type_check_str(point).unwrap(), Type::Type);
+        assert_eq!(run_seq(&[point, "(Point 1.0 2.5)"]).unwrap().to_string(), "(Point :x 1 :y 2.5)");
+        // Written out, a struct reads back as the same value.
+        let written = run_seq(&[point, "(Point 1.0 2.5)"]).unwrap().written();
+        assert_eq!(written, "(Point 1.0 2.5)");
+        assert_eq!(run_seq(&[point, &written]).unwrap().written(), written);
+        let named = "(defstruct P [x: f64 name: String])";
+        let written = run_seq(&[named, "(P 1.0 \"n\")"]).unwrap().written();
+        assert_eq!(written, "(P 1.0 \"n\")");
+        assert_eq!(run_seq(&[named, &written]).unwrap().written(), written);
+        assert_eq!(run_seq(&[point, "(.y (Point 1.0 2.5))"]).unwrap().to_string(), "2.5");
+        assert_eq!(run_seq(&[point, "(get (Point 1.0 2.5) :x)"]).unwrap().to_string(), "1");
+        assert_eq!(type_check_seq(&[point, "(.x (Point 1.0 2.5))"]).unwrap(), Type::F64);
+        assert_eq!(
+            run_seq(&[point, "(defn norm1 [p: Point] -> f64 (+ (.x p) (.y p)))", "(norm1 (Point 1.0 2.0))"]).unwrap().to_string(),
+            "3"
+        );
+        // A struct is a type with one constructor, so it destructures too.
+        assert_eq!(run_seq(&[point, "(match (Point 1.0 2.0) ((Point a b) (- a b)))"]).unwrap().to_string(), "-1");
+
+        assert_eq!(type_check_seq(&[point, "(.z (Point 1.0 2.0))"]).unwrap_err(), "Point has no field `z`");
+        assert_eq!(type_check_seq(&[point, "(.x 3)"]).unwrap_err(), "get requires a struct, got i32");
+        assert!(type_check_seq(&[point, "(+ (.x (Point 1.0 2.0)) 1)"]).is_err());
+        assert!(type_check_seq(&[point, "(Point 1 2)"]).is_err());
+        // An unannotated record is the struct with that field.
+        let x_of = "(defn x-of [p] (.x p))";
+        assert_eq!(type_check_seq(&[point, x_of]).unwrap().to_string(), "fn(Point) -> f64");
+        assert_eq!(type_check_seq(&[point, x_of, "(x-of 3)"]).unwrap_err(), "Type mismatch in argument: expected Point, got i32");
+        assert!(type_check_str("(let a 1 (defstruct Q [x: i32]))").unwrap_err().contains("only allowed at the top level"));
+    }
+
+    #[test]
+    fn test_hm_inference() {
+        // What's left open is generalized, and each use gets its own copy.
+        assert_eq!(type_check_str("(defn id [x] x)").unwrap().to_string(), "fn('a) -> 'a");
+        assert_eq!(
+            type_check_str("(defn compose [f g] (fn [x] (f (g x))))").unwrap().to_string(),
+            "fn(fn('a) -> 'b, fn('c) -> 'a) -> fn('c) -> 'b"
+        );
+        assert!(matches!(eval_str("(let id (fn [x] x) (do (id 1) (id true)))").unwrap(), Value::Bool(true)));
+        assert_eq!(type_check_str("(let id (fn [x] x) (id true))").unwrap(), Type::Bool);
+
+        // A lambda's parameters are solved from its body.
+        assert_eq!(
+            type_check_str("(let inc (fn [x] (+ x 1)) (inc true))").unwrap_err(),
+            "Type mismatch in argument: expected i32, got bool"
+        );
+        assert_eq!(
+            type_check_str("(defn len [xs] (match xs (nil 0) ((cons _ t) (+ 1 (len t)))))").unwrap().to_string(),
+            "fn(List<'a>) -> i32"
+        );
+
+        // A type can't contain itself.
+        assert!(type_check_str("(fn [x] (x x))").is_err());
+    }
+}
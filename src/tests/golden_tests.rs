@@ -0,0 +1,40 @@
+#[cfg(test)]
+mod tests {
+    use crate::golden::{self, Outcome};
+
+    #[test]
+    fn test_transcript() {
+        let dir = std::env::temp_dir().join(format!("rusp-golden-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hello = dir.join("hello.rusp");
+        std::fs::write(&hello, "(println \"hi\")\n(print 1)\n(+ 1 2)\n").unwrap();
+        assert_eq!(golden::transcript(&hello), "hi\n1\n=> 3\n");
+        // Values are written as source would write them.
+        let quoted = dir.join("quoted.rusp");
+        std::fs::write(&quoted, "(cons \"a \\\"b\\\"\" 1.0)\n").unwrap();
+        assert_eq!(golden::transcript(&quoted), "=> (\"a \\\"b\\\"\" . 1.0)\n");
+        std::fs::remove_file(&quoted).unwrap();
+        // Errors stop the file and name it without its directory.
+        let broken = dir.join("broken.rusp");
+        std::fs::write(&broken, "(println \"before\")\n(+ 1 \"a\")\n(println \"after\")\n").unwrap();
+        let out = golden::transcript(&broken);
+        assert!(out.starts_with("before\nerror: broken.rusp:2:1: type error: "), "got: {}", out);
+
+        std::fs::write(dir.join("broken.expected"), "before\n").unwrap();
+        let cases = golden::run_dir(&dir, false).unwrap();
+        let outcomes: Vec<&Outcome> = cases.iter().map(|c| &c.outcome).collect();
+        assert!(matches!(outcomes[..], [Outcome::Fail { .. }, Outcome::Missing]), "got: {:?}", outcomes);
+
+        // --update blesses both, after which they pass.
+        assert!(golden::run_dir(&dir, true).unwrap().iter().all(|c| c.outcome == Outcome::Updated));
+        assert_eq!(std::fs::read_to_string(dir.join("hello.expected")).unwrap(), "hi\n1\n=> 3\n");
+        assert!(golden::run_dir(&dir, false).unwrap().iter().all(|c| c.outcome == Outcome::Pass));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(golden::diff("a\nb\nc\n", "a\nx\nc\n"), "  a\n- b\n+ x\n  c\n");
+        assert_eq!(golden::diff("a\n", "a\nb\n"), "  a\n+ b\n");
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use crate::highlight::{Token, highlight, matching_open, tokens};
+
+    fn kinds(line: &str) -> Vec<(Token, &str)> {
+        tokens(line).into_iter().filter(|(k, _)| *k != Token::Space).map(|(k, r)| (k, &line[r])).collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(
+            kinds("(defn f [x: i32] \"a (b\" :k -1 nil)"),
+            [
+                (Token::Open, "("),
+                (Token::Special, "defn"),
+                (Token::Symbol, "f"),
+                (Token::Open, "["),
+                (Token::Symbol, "x:"),
+                (Token::Symbol, "i32"),
+                (Token::Close, "]"),
+                (Token::String, "\"a (b\""),
+                (Token::Keyword, ":k"),
+                (Token::Number, "-1"),
+                (Token::Constant, "nil"),
+                (Token::Close, ")"),
+            ]
+        );
+        // Unfinished input still splits; the pieces cover the line.
+        let line = "(println \"unterminated";
+        assert_eq!(kinds(line).last(), Some(&(Token::String, "\"unterminated")));
+        assert_eq!(tokens(line).iter().map(|(_, r)| &line[r.clone()]).collect::<String>(), line);
+    }
+
+    #[test]
+    fn test_matching_paren() {
+        let line = "(+ (f \")\") [1 2])";
+        assert_eq!(matching_open(line, 9), Some(3));
+        assert_eq!(matching_open(line, 15), Some(11));
+        assert_eq!(matching_open(line, 16), Some(0));
+        assert_eq!(matching_open(")", 0), None);
+
+        assert_eq!(highlight("(f 1)", None), "(f \x1b[36m1\x1b[0m)");
+        assert_eq!(highlight("(f 1)", Some(5)), "\x1b[7m(\x1b[0mf \x1b[36m1\x1b[0m)");
+    }
+
+    #[test]
+    fn test_comments_and_chars_hide_brackets() {
+        // The same lexer as `is_complete`: neither sees these brackets.
+        let line = "(f #\\( #| ) |# 1) ; )";
+        assert_eq!(
+            kinds(line),
+            [
+                (Token::Open, "("),
+                (Token::Symbol, "f"),
+                (Token::Char, "#\\("),
+                (Token::Comment, "#| ) |#"),
+                (Token::Number, "1"),
+                (Token::Close, ")"),
+                (Token::Comment, "; )"),
+            ]
+        );
+        assert_eq!(matching_open(line, 16), Some(0));
+        assert!(crate::parser::reader::is_complete(line));
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use crate::lint::{self, DEPRECATED, REDUNDANT_ARM, SHADOW_BUILTIN, UNUSED_RESULT};
+    use crate::parser;
+    use crate::types::{TypeEnv, type_check};
+
+    fn lints(input: &str) -> Vec<String> {
+        let expr = parser::parse(input).unwrap();
+        lint::check(&expr).iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_shadow_builtin() {
+        assert_eq!(
+            lints("(defn + [a: i32 b: i32] -> i32 (- a b))"),
+            ["`+` shadows the builtin `+: fn(_, _) -> _` [shadow-builtin]"]
+        );
+        let found = lints("(def println 1)");
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with("`println` shadows the builtin `println: fn("), "got: {:?}", found);
+        assert_eq!(
+            lints("(defn map [x: i32] -> i32 x)"),
+            ["`map` names a builtin form; calls to `map` will still use the builtin [shadow-builtin]"]
+        );
+        assert!(lint::ALL.contains(&SHADOW_BUILTIN));
+    }
+
+    #[test]
+    fn test_shadow_builtin_ignores_locals_and_new_names() {
+        assert!(lints("(defn plus [a: i32 b: i32] -> i32 (+ a b))").is_empty());
+        // Scoped bindings don't replace anything outside their body.
+        assert!(lints("(let map 1 (+ map 1))").is_empty());
+        assert!(lints("(fn [list: i32] -> i32 list)").is_empty());
+    }
+
+    #[test]
+    fn test_allow_metadata() {
+        assert!(lints("(defn ^{:allow :shadow-builtin} + [a: i32 b: i32] -> i32 (- a b))").is_empty());
+        // Only the lint it names.
+        assert_eq!(
+            lints("(defn ^{:allow :shadow-builtin} f [n: i32] -> i32 (match n (_ 0) (1 1)))").len(),
+            1
+        );
+        assert_eq!(lints("(defn ^{:allow :redundant-arm} + [a: i32 b: i32] -> i32 a)").len(), 1);
+    }
+
+    /// The warnings the type checker raises for each form in turn.
+    fn type_warnings(forms: &[&str]) -> Vec<Vec<String>> {
+        let mut env = TypeEnv::new();
+        forms
+            .iter()
+            .map(|input| {
+                type_check(&parser::parse(input).unwrap(), &mut env).unwrap();
+                env.take_warnings().iter().map(|l| l.to_string()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deprecated_calls() {
+        let old = "(defn ^{:deprecated \"use new-f\"} old-f [x: i32] -> i32 x)";
+        assert_eq!(
+            type_warnings(&[old, "(defn g [x: i32] -> i32 (+ (old-f x) (old-f 1)))", "(old-f 2)"]),
+            [
+                vec![],
+                vec!["`old-f` is deprecated: use new-f [deprecated]".to_string(); 2],
+                vec!["`old-f` is deprecated: use new-f [deprecated]".to_string()],
+            ]
+        );
+        // Suppressed per definition, not raised for a local of the same
+        // name, and gone once the name is redefined.
+        let quiet = type_warnings(&[
+            old,
+            "(defn ^{:allow :deprecated} g [x: i32] -> i32 (old-f x))",
+            "(let old-f (fn [y: i32] -> i32 y) (old-f 2))",
+            "(defn old-f [x: i32] -> i32 x)",
+            "(old-f 2)",
+        ]);
+        assert!(quiet.iter().all(|w| w.is_empty()), "got: {:?}", quiet);
+        assert_eq!(type_warnings(&["(defn ^{:deprecated true} f [] -> i32 1)", "(f)"])[1], ["`f` is deprecated [deprecated]"]);
+        assert!(lint::ALL.contains(&DEPRECATED));
+    }
+
+    #[test]
+    fn test_redundant_arm() {
+        assert_eq!(
+            lints("(match n (_ 0) (1 1))"),
+            ["arm `1` is unreachable: earlier arms of (match n ...) cover it [redundant-arm]"]
+        );
+        assert_eq!(lints("(match n (1 1) (1 2) (_ 3))").len(), 1);
+        assert_eq!(lints("(match b (true 1) (false 2) (_ 3))").len(), 1);
+        assert_eq!(lints("(match xs (nil 0) ((cons h t) 1) ((cons 1 _) 2))").len(), 1);
+        assert_eq!(lints("(match n ((or 1 2) 0) (2 1) (_ 2))").len(), 1);
+        // Found inside definitions too.
+        assert_eq!(lints("(defn f [n: i32] -> i32 (match n (x x) (0 0)))").len(), 1);
+        assert!(lint::ALL.contains(&REDUNDANT_ARM));
+    }
+
+    #[test]
+    fn test_redundant_arm_needs_full_cover() {
+        assert!(lints("(match xs (nil 0) ((cons 1 _) 1) ((cons _ _) 2))").is_empty());
+        assert!(lints("(match b (true 1) (_ 2))").is_empty());
+        // A guard can fail, so it never makes later arms dead.
+        assert!(lints("(match n ((guard x (> x 0)) 1) (5 2) (_ 3))").is_empty());
+        assert!(lints("(match xs ((cons (cons 1 _) _) 0) ((cons nil _) 1) ((cons _ _) 2) (nil 3))").is_empty());
+    }
+
+    #[test]
+    fn test_unused_result() {
+        // What each form of a file would raise, in order.
+        let unused = |forms: &[&str]| -> Vec<Option<String>> {
+            let mut env = TypeEnv::new();
+            forms
+                .iter()
+                .map(|input| {
+                    let form = parser::parse(input).unwrap();
+                    let ty = type_check(&form, &mut env).unwrap();
+                    lint::unused_result(&form, &ty).map(|l| l.to_string())
+                })
+                .collect()
+        };
+        assert_eq!(
+            unused(&["(defn fact [n: i32] -> i32 (if (= n 0) 1 (* n (fact (- n 1)))))", "(fact 10)"]),
+            [None, Some("the i32 result of `(fact 10)` is unused [unused-result]".to_string())]
+        );
+        // Definitions are statements, whatever they check as, and so is
+        // anything giving Unit.
+        assert!(
+            unused(&["(def x 1)", "(deftype T A)", "(defstruct P [x: i32])", "(println x)", "(do (println 1) (println 2))"])
+                .iter()
+                .all(Option::is_none)
+        );
+        // A value whose type is still unknown isn't flagged.
+        assert_eq!(unused(&["(defn head [xs] (car xs))", "(head nil)"]), [None, None]);
+        assert!(lint::ALL.contains(&UNUSED_RESULT));
+    }
+}
@@ -1,3 +1,11 @@
+mod cache_tests;
+mod check_tests;
 mod codegen_tests;
+mod diagnostics_tests;
 mod eval_tests;
-mod parser_tests;
\ No newline at end of file
+mod golden_tests;
+mod highlight_tests;
+mod lint_tests;
+mod module_tests;
+mod parser_tests;
+mod profile_tests;
@@ -0,0 +1,319 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::ast::Expr;
+    use crate::env::{Environment, Value};
+    use crate::eval::eval;
+    use crate::manifest::{self, DepSource};
+    use crate::modules::Loader;
+    use crate::types::{TypeEnv, type_check};
+
+    /// A fresh scratch directory per test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusp-mod-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, rel: &str, text: &str) {
+        let path = dir.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn defn_names(dir: &Path, file: &str) -> Result<Vec<String>, String> {
+        let forms = Loader::new().load(&dir.join(file).to_string_lossy())?;
+        Ok(forms
+            .into_iter()
+            .filter_map(|sf| match sf.form {
+                Expr::Defn { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let text = r#"
+            # app manifest
+            [package]
+            name = "app"
+            version = "0.2.0"
+            src = "src"   # modules live here
+
+            [dependencies]
+            mathlib = { path = "../mathlib" }
+            strutil = { git = "https://example.com/s.git", rev = "v1" }
+        "#;
+        let m = manifest::parse_manifest(text, "rusp.toml").unwrap();
+        assert_eq!(m.name, "app");
+        assert_eq!(m.src, PathBuf::from("src"));
+        assert_eq!(m.dependencies["mathlib"], DepSource::Path(PathBuf::from("../mathlib")));
+        assert_eq!(
+            m.dependencies["strutil"],
+            DepSource::Git { url: "https://example.com/s.git".to_string(), rev: Some("v1".to_string()) }
+        );
+    }
+
+    #[test]
+    fn test_manifest_errors() {
+        let err = manifest::parse_manifest("[package]\nversion = \"1\"", "rusp.toml").unwrap_err();
+        assert!(err.contains("missing `name`"), "got: {}", err);
+        let err = manifest::parse_manifest("[package]\nname = \"a\"\n[dependencies]\nx = { rev = \"1\" }", "rusp.toml")
+            .unwrap_err();
+        assert!(err.contains("needs `path` or `git`"), "got: {}", err);
+        let err = manifest::parse_manifest("[package]\nname = a", "rusp.toml").unwrap_err();
+        assert!(err.starts_with("rusp.toml:2:"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        let mut deps = std::collections::BTreeMap::new();
+        deps.insert("b".to_string(), DepSource::Path(PathBuf::from("../b")));
+        deps.insert(
+            "a".to_string(),
+            DepSource::Git { url: "file:///tmp/a".to_string(), rev: Some("0123abcd".to_string()) },
+        );
+        let text = manifest::render_lockfile(&deps);
+        assert!(text.contains("a = { git = \"file:///tmp/a\", rev = \"0123abcd\" }"), "got: {}", text);
+        assert_eq!(manifest::parse_lockfile(&text, "rusp.lock").unwrap(), deps);
+    }
+
+    #[test]
+    fn test_import_local_module_once() {
+        let dir = scratch("local");
+        // With a manifest, nested modules resolve from the package's src.
+        write(&dir, "rusp.toml", "[package]\nname = \"local\"");
+        write(&dir, "util.rusp", "(defn twice [x: i32] -> i32 (* x 2))");
+        write(&dir, "geo/vec.rusp", "(import util)\n(defn quad [x: i32] -> i32 (util/twice (util/twice x)))");
+        write(&dir, "main.rusp", "(import util)\n(import geo/vec)\n(defn main [] -> i32 (geo/vec/quad 1))");
+        assert_eq!(defn_names(&dir, "main.rusp").unwrap(), ["util/twice", "geo/vec/quad", "main"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_embed_file() {
+        let dir = scratch("embed");
+        write(&dir, "sql/schema.sql", "create table t (\"id\" int);\n");
+        // Paths are relative to the file the form is in, not the one run.
+        write(&dir, "lib/db.rusp", "(def schema (embed-file \"../sql/schema.sql\"))");
+        write(&dir, "main.rusp", "(import lib/db)\n(embed-file \"sql/schema.sql\")");
+        let forms = Loader::new().load(&dir.join("main.rusp").to_string_lossy()).unwrap();
+        let texts: Vec<String> = forms
+            .iter()
+            .map(|sf| match &sf.form {
+                Expr::Def { value, .. } => value.to_string(),
+                other => other.to_string(),
+            })
+            .collect();
+        assert_eq!(texts, ["\"create table t (\\\"id\\\" int);\\n\""; 2]);
+
+        write(&dir, "missing.rusp", "(embed-file \"nope.txt\")");
+        let err = Loader::new().load(&dir.join("missing.rusp").to_string_lossy()).unwrap_err();
+        assert!(err.contains("missing.rusp:1:1: embed-file: could not read"), "got: {}", err);
+        write(&dir, "computed.rusp", "(embed-file (str-concat \"a\" \"b\"))");
+        let err = Loader::new().load(&dir.join("computed.rusp").to_string_lossy()).unwrap_err();
+        assert!(err.contains("embed-file takes one literal path"), "got: {}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_path_dependency_from_src_dir() {
+        let dir = scratch("pathdep");
+        write(&dir, "mathlib/rusp.toml", "[package]\nname = \"mathlib\"\nsrc = \"lib\"");
+        write(&dir, "mathlib/lib/lib.rusp", "(defn sq [x: i32] -> i32 (* x x))");
+        write(&dir, "mathlib/lib/cube.rusp", "(defn cube [x: i32] -> i32 (* x (* x x)))");
+        write(
+            &dir,
+            "app/rusp.toml",
+            "[package]\nname = \"app\"\nsrc = \"src\"\n\n[dependencies]\nmathlib = { path = \"../mathlib\" }",
+        );
+        write(
+            &dir,
+            "app/src/main.rusp",
+            "(import mathlib)\n(import mathlib/cube :as c)\n(defn main [] -> i32 (c/cube (mathlib/sq 2)))",
+        );
+        assert_eq!(defn_names(&dir, "app/src/main.rusp").unwrap(), ["mathlib/sq", "mathlib/cube/cube", "main"]);
+        // Path-only dependencies are still recorded in the lockfile.
+        let lock = std::fs::read_to_string(dir.join("app/rusp.lock")).unwrap();
+        assert!(lock.contains("mathlib = { path = \"../mathlib\" }"), "got: {}", lock);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Type-check and evaluate a whole program, returning the last value.
+    fn run(dir: &Path, file: &str) -> Result<Value, String> {
+        let mut type_env = TypeEnv::new();
+        let mut env = Environment::new();
+        let mut last = Value::Nil;
+        for sf in Loader::new().load(&dir.join(file).to_string_lossy())? {
+            type_check(&sf.form, &mut type_env)?;
+            last = eval(&sf.form, &mut env)?;
+        }
+        Ok(last)
+    }
+
+    #[test]
+    fn test_qualified_names_keep_modules_apart() {
+        let dir = scratch("qualified");
+        write(&dir, "rusp.toml", "[package]\nname = \"q\"");
+        // Both modules define `scale`; each still sees its own.
+        write(
+            &dir,
+            "a.rusp",
+            "(defn scale [x: i32] -> i32 (* x 2))\n(defn go [x: i32] -> i32 (scale x))\n(def k 10)",
+        );
+        write(&dir, "b.rusp", "(import a :as m)\n(defn scale [x: i32] -> i32 (+ (m/go x) m/k))");
+        // The main file's own names stay unqualified.
+        write(
+            &dir,
+            "main.rusp",
+            "(import a)\n(import b)\n(defn scale [x: i32] -> i32 (* x 100))\n(+ (scale 1) (+ (a/scale 1) (b/scale 1)))",
+        );
+        let result = run(&dir, "main.rusp").unwrap();
+        assert!(matches!(result, Value::Integer32(114)), "got: {}", result);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_imported_constructors_are_qualified() {
+        let dir = scratch("constructors");
+        write(&dir, "rusp.toml", "[package]\nname = \"c\"");
+        write(
+            &dir,
+            "mod1.rusp",
+            "(deftype Shape (Circle f64) Dot)\n\
+             (defn area [s: Shape] -> f64 (match s ((Circle r) (* 3.0 (* r r))) (Dot 0.0)))",
+        );
+        // The type keeps its name; its constructors are qualified, in
+        // patterns too.
+        write(
+            &dir,
+            "main.rusp",
+            "(import mod1)\n\
+             (defn radius [s: Shape] -> f64 (match s ((mod1/Circle r) r) (mod1/Dot 0.0)))\n\
+             (+ (mod1/area (mod1/Circle 2.0)) (radius (mod1/Circle 2.0)))",
+        );
+        let result = run(&dir, "main.rusp").unwrap();
+        assert!(matches!(result, Value::Float(x) if x == 14.0), "got: {}", result);
+        write(&dir, "main.rusp", "(import mod1)\n(mod1/Square 2.0)");
+        let err = run(&dir, "main.rusp").unwrap_err();
+        assert!(err.contains("module `mod1` has no definition `Square`"), "got: {}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_imported_struct_keeps_its_name() {
+        let dir = scratch("struct");
+        write(&dir, "rusp.toml", "[package]\nname = \"s\"");
+        write(&dir, "geo.rusp", "(defstruct Point [x: f64 y: f64])\n(defn px [p: Point] -> f64 (.x p))");
+        // A struct's constructor is its type's name, which isn't
+        // qualified; `geo/Point` still reaches it.
+        write(&dir, "main.rusp", "(import geo)\n(+ (geo/px (geo/Point 1.0 2.0)) (.y (Point 3.0 4.0)))");
+        let result = run(&dir, "main.rusp").unwrap();
+        assert!(matches!(result, Value::Float(x) if x == 5.0), "got: {}", result);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_qualified_name_errors() {
+        let dir = scratch("qualerr");
+        write(&dir, "a.rusp", "(defn f [] -> i32 1)");
+        write(&dir, "b.rusp", "(defn f [] -> i32 2)");
+        write(&dir, "missing.rusp", "(import a :as m)\n(m/g)");
+        let err = defn_names(&dir, "missing.rusp").unwrap_err();
+        assert!(err.contains("missing.rusp:2:1: module `m` has no definition `g`"), "got: {}", err);
+        write(&dir, "clash.rusp", "(import a :as m)\n(import b :as m)");
+        let err = defn_names(&dir, "clash.rusp").unwrap_err();
+        assert!(err.contains("`m` already names module"), "got: {}", err);
+        write(&dir, "bad.rusp", "(import a :alias m)");
+        let err = defn_names(&dir, "bad.rusp").unwrap_err();
+        assert!(err.contains("(import name :as alias)"), "got: {}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_private_definitions() {
+        let dir = scratch("private");
+        write(&dir, "rusp.toml", "[package]\nname = \"p\"");
+        write(&dir, "m.rusp", "(defn- helper [x: i32] -> i32 (+ x 1))\n(defn api [x: i32] -> i32 (helper (m/helper x)))");
+        write(&dir, "ok.rusp", "(import m)\n(m/api 1)");
+        let result = run(&dir, "ok.rusp").unwrap();
+        assert!(matches!(result, Value::Integer32(3)), "got: {}", result);
+
+        write(&dir, "bad.rusp", "(import m :as x)\n(x/helper 1)");
+        let err = defn_names(&dir, "bad.rusp").unwrap_err();
+        assert!(err.contains("bad.rusp:2:1: `helper` is private to module `x`"), "got: {}", err);
+        // Reaching a module only imported by someone else doesn't work
+        // either, so neither does reaching its private names.
+        write(&dir, "via.rusp", "(import m)");
+        write(&dir, "sneaky.rusp", "(import via)\n(m/helper 1)");
+        let err = defn_names(&dir, "sneaky.rusp").unwrap_err();
+        assert!(err.contains("module `m` is not imported here"), "got: {}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_constant_redefinition_points_at_original() {
+        let dir = scratch("consts");
+        write(&dir, "c.rusp", "(defconst LIMIT 10)\n(defn f [] -> i32 LIMIT)\n(def LIMIT 11)");
+        let err = defn_names(&dir, "c.rusp").unwrap_err();
+        let path = dir.join("c.rusp").to_string_lossy().to_string();
+        assert_eq!(err, format!("{}:3:1: cannot redefine constant `LIMIT` (defined at {}:1:1)", path, path));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = scratch("errors");
+        write(&dir, "a.rusp", "(import b)");
+        write(&dir, "b.rusp", "(import a)");
+        let err = defn_names(&dir, "a.rusp").unwrap_err();
+        assert!(err.contains("import cycle"), "got: {}", err);
+        write(&dir, "c.rusp", "(defn f [] -> i32 1)\n(import nowhere)");
+        let err = defn_names(&dir, "c.rusp").unwrap_err();
+        assert!(err.contains("c.rusp:2:1: cannot find module `nowhere`"), "got: {}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_git_dependency_is_cloned_and_locked() {
+        let git = |dir: &Path, args: &[&str]| {
+            let ok = std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false);
+            assert!(ok, "git {:?} failed", args);
+        };
+        if std::process::Command::new("git").arg("--version").output().is_err() {
+            return; // no git on this machine
+        }
+        let dir = scratch("gitdep");
+        write(&dir, "strutil/lib.rusp", "(defn one [] -> i32 1)");
+        let repo = dir.join("strutil");
+        git(&repo, &["init", "--quiet"]);
+        git(&repo, &["add", "-A"]);
+        git(&repo, &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "--quiet", "-m", "init"]);
+
+        let url = repo.to_string_lossy().to_string();
+        write(
+            &dir,
+            "app/rusp.toml",
+            &format!("[package]\nname = \"app\"\n\n[dependencies]\nstrutil = {{ git = \"{}\" }}", url),
+        );
+        write(&dir, "app/main.rusp", "(import strutil)");
+        assert_eq!(defn_names(&dir, "app/main.rusp").unwrap(), ["strutil/one"]);
+        assert!(dir.join("app/.rusp/deps/strutil/lib.rusp").is_file());
+        let lock = std::fs::read_to_string(dir.join("app/rusp.lock")).unwrap();
+        let locked = manifest::parse_lockfile(&lock, "rusp.lock").unwrap();
+        let DepSource::Git { rev: Some(commit), .. } = &locked["strutil"] else {
+            panic!("expected a locked git dependency, got {:?}", locked)
+        };
+        assert_eq!(commit.len(), 40, "got: {}", commit);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
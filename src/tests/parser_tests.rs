@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod tests {
-    use crate::ast::{Expr, Type};
+    use crate::ast::{Expr, Pattern, Type};
+    use crate::keyword::Keyword;
     use crate::parser;
     
     fn parse(input: &str) -> Result<Expr, String> {
@@ -49,6 +50,15 @@ mod tests {
         
         let result = parse("\"\"").unwrap();
         assert_eq!(result, Expr::String("".to_string()));
+
+        // Escapes are read as the characters they stand for, and printed
+        // back as escapes.
+        let result = parse(r#""say \"hi\"\n\ta\\b""#).unwrap();
+        assert_eq!(result, Expr::String("say \"hi\"\n\ta\\b".to_string()));
+        assert_eq!(result.to_string(), r#""say \"hi\"\n\ta\\b""#);
+        for src in [r#"(match s ("a\"b" 1) (_ 2))"#, r#"(str-concat "\\" "\r")"#, "(+ 1.0 2.5)"] {
+            assert_eq!(parse(src).unwrap().to_string(), src);
+        }
     }
     
     #[test]
@@ -58,6 +68,219 @@ mod tests {
         
         let result = parse("foo-bar").unwrap();
         assert_eq!(result, Expr::Symbol("foo-bar".to_string()));
+
+        let result = parse("math/sqrt").unwrap();
+        assert_eq!(result, Expr::Symbol("math/sqrt".to_string()));
+
+        let result = parse(":as").unwrap();
+        assert_eq!(result, Expr::Keyword(Keyword::intern("as")));
+        assert!(parse(":").is_err());
+    }
+
+    #[test]
+    fn test_parse_commas_as_whitespace() {
+        assert_eq!(parse("(f 1, 2, 3)").unwrap(), parse("(f 1 2 3)").unwrap());
+        assert_eq!(
+            parse("(defn add [x: i32, y: i32] -> i32 (+ x y))").unwrap(),
+            parse("(defn add [x: i32 y: i32] -> i32 (+ x y))").unwrap()
+        );
+        assert_eq!(parse("(list 1,2,3,)").unwrap(), parse("(list 1 2 3)").unwrap());
+        assert_eq!(parse("1,").unwrap(), Expr::Integer32(1));
+        // Commas in function types still separate the parameters, and
+        // commas in strings stay put.
+        assert!(parse("(fn [f: fn(i32, i32) -> i32, x: i32] (f x x))").is_ok());
+        assert_eq!(parse("\"a, b\"").unwrap(), Expr::String("a, b".to_string()));
+    }
+
+    #[test]
+    fn test_parse_paren_function_type() {
+        let typed = |src: &str| match parse(src).unwrap() {
+            Expr::Let { type_ann, .. } => type_ann,
+            other => panic!("expected a let, got {}", other),
+        };
+        assert_eq!(
+            typed("(let f: (fn (i32 i64) -> bool) g (f 1 2))"),
+            typed("(let f: fn(i32, i64) -> bool g (f 1 2))")
+        );
+        assert_eq!(typed("(let f: (fn () -> i32) g (f))").unwrap().to_string(), "fn() -> i32");
+        // A lambda in that position is still a lambda.
+        assert_eq!(typed("(let f (fn [x] x) (f 1))"), None);
+    }
+
+    #[test]
+    fn test_parse_paren_list_type() {
+        let typed = |src: &str| match parse(src).unwrap() {
+            Expr::Let { type_ann, .. } => type_ann,
+            other => panic!("expected a let, got {}", other),
+        };
+        assert_eq!(typed("(let xs: (List i32) nil xs)"), typed("(let xs: List<i32> nil xs)"));
+        assert_eq!(typed("(let xs: (List (List _)) nil xs)").unwrap().to_string(), "List<List<_>>");
+        assert!(parse("(let xs: (List) nil xs)").is_err());
+    }
+
+    #[test]
+    fn test_parse_arrays() {
+        let sym = |s: &str| Expr::Symbol(s.to_string());
+        assert_eq!(
+            parse("#[1 x]").unwrap(),
+            Expr::List(vec![sym("array"), Expr::Integer32(1), sym("x")])
+        );
+        assert_eq!(parse("#[]").unwrap(), Expr::List(vec![sym("array")]));
+        assert!(parse("#[1 2").is_err());
+        let typed = |src: &str| match parse(src).unwrap() {
+            Expr::Let { type_ann, .. } => type_ann,
+            other => panic!("expected a let, got {}", other),
+        };
+        assert_eq!(typed("(let a: (Array i32 4) nil a)"), typed("(let a: Array<i32, 4> nil a)"));
+        assert_eq!(typed("(let a: Array<f64,2> nil a)").unwrap().to_string(), "Array<f64, 2>");
+        assert!(parse("(let a: (Array i32) nil a)").is_err());
+        assert_eq!(typed("(let s: (Slice i32) nil s)"), typed("(let s: Slice<i32> nil s)"));
+        // Outside a parameter list, brackets make a vector.
+        assert_eq!(parse("[1 x]").unwrap(), Expr::List(vec![sym("vector"), Expr::Integer32(1), sym("x")]));
+        assert!(matches!(parse("(fn [x] [x])").unwrap(), Expr::Lambda { .. }));
+        assert!(parse("[1 2").is_err());
+        assert_eq!(typed("(let v: (Vec i32) nil v)"), typed("(let v: Vec<i32> nil v)"));
+        assert_eq!(
+            parse("{:a 1}").unwrap(),
+            Expr::List(vec![sym("hash-map"), Expr::Keyword(Keyword::intern("a")), Expr::Integer32(1)])
+        );
+        assert!(parse("{:a 1 :b}").is_err());
+        assert!(parse("{:a 1").is_err());
+        assert_eq!(typed("(let m: (Map Keyword i32) nil m)"), typed("(let m: Map<Keyword, i32> nil m)"));
+    }
+
+    #[test]
+    fn test_parse_unary_minus() {
+        let sym = |s: &str| Expr::Symbol(s.to_string());
+        let neg_x = Expr::List(vec![sym("neg"), sym("x")]);
+        assert_eq!(parse("(- x)").unwrap(), neg_x);
+        assert_eq!(parse("-x").unwrap(), neg_x);
+        assert_eq!(parse("-5").unwrap(), Expr::Integer32(-5));
+        assert_eq!(parse("(- x 1)").unwrap(), Expr::List(vec![sym("-"), sym("x"), Expr::Integer32(1)]));
+        // Operators that start with `-` are still symbols.
+        assert_eq!(parse("-.").unwrap(), sym("-."));
+        assert_eq!(parse("->").unwrap(), sym("->"));
+        assert_eq!(parse("x-1").unwrap(), sym("x-1"));
+        // A number can't run into a symbol.
+        assert!(parse("1-2").is_err());
+        assert!(parse("-5x").is_err());
+    }
+
+    #[test]
+    fn test_parse_dotted_pairs() {
+        let cons = |h: Expr, t: Expr| Expr::List(vec![Expr::Symbol("cons".to_string()), h, t]);
+        let sym = |s: &str| Expr::Symbol(s.to_string());
+        assert_eq!(parse("(a . b)").unwrap(), cons(sym("a"), sym("b")));
+        assert_eq!(parse("(a b . c)").unwrap(), cons(sym("a"), cons(sym("b"), sym("c"))));
+        assert!(parse("(. a)").is_err());
+        assert!(parse("(a . b c)").is_err());
+        assert!(parse("(a . b . c)").is_err());
+        match parse("(let p: Pair<i32, String> (1 . \"x\") p)").unwrap() {
+            Expr::Let { type_ann, .. } => {
+                assert_eq!(type_ann, Some(Type::Pair(Box::new(Type::I32), Box::new(Type::String))))
+            }
+            other => panic!("expected let, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_short_lambda() {
+        let sym = |s: &str| Expr::Symbol(s.to_string());
+        assert_eq!(
+            parse("#(+ % 1)").unwrap(),
+            Expr::Lambda {
+                params: vec![("%1".to_string(), Type::Inferred)],
+                return_type: None,
+                body: Box::new(Expr::List(vec![sym("+"), sym("%1"), Expr::Integer32(1)])),
+            }
+        );
+        // As many parameters as the highest `%N`, used or not.
+        match parse("#(list %3 %1)").unwrap() {
+            Expr::Lambda { params, .. } => {
+                assert_eq!(params.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), ["%1", "%2", "%3"]);
+            }
+            other => panic!("Expected Lambda, got {:?}", other),
+        }
+        assert!(matches!(parse("#(f)").unwrap(), Expr::Lambda { params, .. } if params.is_empty()));
+
+        for (src, msg) in [
+            ("(+ % 1)", "%1 is only allowed inside #(...)"),
+            ("#(f #(g %))", "#(...) can't be nested"),
+            ("#(f %0)", "%0: arguments go from %1 to %9"),
+        ] {
+            let err = parse(src).unwrap_err();
+            assert!(err.contains(msg), "{}: {}", src, err);
+        }
+        // A failed `#(...)` doesn't leave the next one thinking it's nested.
+        assert!(parse("#(f").is_err());
+        assert!(parse("#(f %)").is_ok());
+    }
+
+    #[test]
+    fn test_parse_discard() {
+        assert_eq!(parse("(list 1 #_2 3)").unwrap(), parse("(list 1 3)").unwrap());
+        // The discarded form can be anything, even span lines or nest.
+        assert_eq!(parse("(+ 1 #_(* 2\n (f #_x y)) 2)").unwrap(), parse("(+ 1 2)").unwrap());
+        assert_eq!(parse("(list #_1)").unwrap(), parse("(list)").unwrap());
+        assert_eq!(parse("#_#_1 2 3").unwrap(), Expr::Integer32(3));
+        assert_eq!(parse("3 #_4").unwrap(), Expr::Integer32(3));
+        assert!(matches!(parse("(defn #_old f [x: i32] -> i32 x)").unwrap(), Expr::Defn { name, .. } if name == "f"));
+
+        let (forms, offsets) = crate::modules::parse_forms("#_(broken 1) (a)\n#_b").unwrap();
+        assert_eq!(forms, vec![Expr::List(vec![Expr::Symbol("a".to_string())])]);
+        assert_eq!(offsets, vec![13]);
+
+        let err = parse("(list 1 #_)").unwrap_err();
+        assert!(err.contains("`#_` needs a form after it"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_reader_macros() {
+        assert_eq!(parse("#\\$").unwrap(), Expr::String("$".to_string()));
+        // Unknown until set; after that `$text` reads as a call.
+        assert!(parse("$2024-01-01").is_err());
+        assert_eq!(
+            parse("(set-reader-macro! #\\$ parse-date)").unwrap(),
+            Expr::SetReaderMacro { ch: '$', handler: "parse-date".to_string() }
+        );
+        let call = |text: &str| Expr::List(vec![Expr::Symbol("parse-date".to_string()), Expr::String(text.to_string())]);
+        assert_eq!(parse("$2024-01-01").unwrap(), call("2024-01-01"));
+        assert_eq!(parse("$\"1 Jan 2024\"").unwrap(), call("1 Jan 2024"));
+        assert_eq!(parse("(list $a, $b)").unwrap(), parse("(list (parse-date \"a\") (parse-date \"b\"))").unwrap());
+
+        let err = parse("(set-reader-macro! #\\a f)").unwrap_err();
+        assert!(err.contains("`a` can't start a reader macro"), "{}", err);
+        let err = parse("(set-reader-macro! \"$$\" f)").unwrap_err();
+        assert!(err.contains("expects a character and a function name"), "{}", err);
+        let err = parse("(list $ 1)").unwrap_err();
+        assert!(err.contains("reader macro `$` needs something right after it"), "{}", err);
+
+        // A file's reader macros end with the file.
+        let (forms, _) = crate::modules::parse_forms("(set-reader-macro! #\\@ pct) @50").unwrap();
+        assert_eq!(forms[1], Expr::List(vec![Expr::Symbol("pct".to_string()), Expr::String("50".to_string())]));
+        assert!(parse("@50").is_err());
+    }
+
+    #[test]
+    fn test_parse_unicode_identifiers() {
+        assert_eq!(parse("名前").unwrap(), Expr::Symbol("名前".to_string()));
+        assert_eq!(parse("größe?").unwrap(), Expr::Symbol("größe?".to_string()));
+        assert_eq!(parse(":ключ").unwrap(), Expr::Keyword(Keyword::intern("ключ")));
+
+        // Bound names follow the same grammar.
+        match parse("(defn 倍 [数: i32] -> i32 (* 数 2))").unwrap() {
+            Expr::Defn { name, params, .. } => {
+                assert_eq!(name, "倍");
+                assert_eq!(params[0].0, "数");
+            }
+            other => panic!("Expected Defn, got {:?}", other),
+        }
+        assert!(matches!(parse("(let π 3.14 π)").unwrap(), Expr::Let { name, .. } if name == "π"));
+
+        // Only references may use `/` and `.`.
+        assert!(parse("(let a/b 1 a/b)").is_err());
+        // Emoji and other symbols aren't identifier characters.
+        assert!(parse("🙂").is_err());
     }
     
     #[test]
@@ -74,32 +297,38 @@ mod tests {
     }
     
     #[test]
-    fn test_parse_let_simple() {
+    fn test_parse_def() {
         // Without type annotation
-        let result = parse("(let x 42)").unwrap();
+        let result = parse("(def x 42)").unwrap();
         match result {
-            Expr::Let { name, type_ann, value, body } => {
+            Expr::Def { name, type_ann, value, .. } => {
                 assert_eq!(name, "x");
                 assert_eq!(type_ann, None);
                 assert_eq!(*value, Expr::Integer32(42));
-                assert_eq!(body, None);
             }
-            _ => panic!("Expected Let expression"),
+            _ => panic!("Expected Def expression"),
         }
         
         // With type annotation (colon syntax)
-        let result = parse("(let x: i32 42)").unwrap();
+        let result = parse("(def x: i32 42)").unwrap();
         match result {
-            Expr::Let { name, type_ann, value, body } => {
+            Expr::Def { name, type_ann, value, .. } => {
                 assert_eq!(name, "x");
                 assert_eq!(type_ann, Some(Type::I32));
                 assert_eq!(*value, Expr::Integer32(42));
-                assert_eq!(body, None);
             }
-            _ => panic!("Expected Let expression"),
+            _ => panic!("Expected Def expression"),
         }
     }
     
+    #[test]
+    fn test_parse_let_requires_body() {
+        let err = parse("(let x 42)").unwrap_err().to_string();
+        assert!(err.contains("`let` needs a body"), "got: {}", err);
+        assert!(err.contains("(def x <value>)"), "got: {}", err);
+        assert!(parse("(let x: i32 42)").is_err());
+    }
+    
     #[test]
     fn test_parse_let_in() {
         // let-in expression
@@ -109,17 +338,14 @@ mod tests {
                 assert_eq!(name, "x");
                 assert_eq!(type_ann, Some(Type::I32));
                 assert_eq!(*value, Expr::Integer32(10));
-                assert!(body.is_some());
                 
-                if let Some(body_expr) = body {
-                    match &*body_expr {
-                        Expr::List(list) => {
-                            assert_eq!(list[0], Expr::Symbol("+".to_string()));
-                            assert_eq!(list[1], Expr::Symbol("x".to_string()));
-                            assert_eq!(list[2], Expr::Integer32(5));
-                        }
-                        _ => panic!("Expected List in body"),
+                match &*body {
+                    Expr::List(list) => {
+                        assert_eq!(list[0], Expr::Symbol("+".to_string()));
+                        assert_eq!(list[1], Expr::Symbol("x".to_string()));
+                        assert_eq!(list[2], Expr::Integer32(5));
                     }
+                    _ => panic!("Expected List in body"),
                 }
             }
             _ => panic!("Expected Let expression"),
@@ -130,8 +356,9 @@ mod tests {
     fn test_parse_defn() {
         let result = parse("(defn add [a: i32 b: i32] -> i32 (+ a b))").unwrap();
         match result {
-            Expr::Defn { name, params, return_type, body } => {
+            Expr::Defn { name, params, return_type, body, private, .. } => {
                 assert_eq!(name, "add");
+                assert!(!private);
                 assert_eq!(params.len(), 2);
                 assert_eq!(params[0], ("a".to_string(), Type::I32));
                 assert_eq!(params[1], ("b".to_string(), Type::I32));
@@ -150,6 +377,24 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_parse_body_lets() {
+        // A `let` without a body scopes over the forms after it.
+        let nested = parse("(defn f [x: i32] -> i32 (let a (* x 2)) (let b (+ a 1)) (+ a b))").unwrap();
+        let written = parse("(defn f [x: i32] -> i32 (let a (* x 2) (let b (+ a 1) (+ a b))))").unwrap();
+        assert_eq!(nested, written);
+        let sequenced = parse("(fn [x] (println x) (let y: i32 x) (println y) y)").unwrap();
+        assert_eq!(sequenced, parse("(fn [x] (do (println x) (let y: i32 x (do (println y) y))))").unwrap());
+        assert!(parse("(defn f [x] (println x) (let y x))").is_err());
+    }
+    
+    #[test]
+    fn test_parse_private_defn() {
+        let result = parse("(defn- helper [x: i32] -> i32 x)").unwrap();
+        assert!(matches!(&result, Expr::Defn { name, private: true, .. } if name == "helper"));
+        assert_eq!(result.to_string(), "(defn- helper [x: i32] -> i32 x)");
+    }
+
     #[test]
     fn test_parse_lambda() {
         let result = parse("(fn [x: i32] -> i32 (* x 2))").unwrap();
@@ -227,15 +472,321 @@ mod tests {
     
     #[test]
     fn test_parse_i64_type() {
-        let result = parse("(let x: i64 9223372036854775807)").unwrap();
+        let result = parse("(def x: i64 9223372036854775807)").unwrap();
         match result {
-            Expr::Let { name, type_ann, value, body } => {
+            Expr::Def { name, type_ann, value, .. } => {
                 assert_eq!(name, "x");
                 assert_eq!(type_ann, Some(Type::I64));
                 assert_eq!(*value, Expr::Integer64(9223372036854775807));
-                assert_eq!(body, None);
             }
-            _ => panic!("Expected Let expression"),
+            _ => panic!("Expected Def expression"),
+        }
+    }
+
+    #[test]
+    fn test_parse_bytes_literals() {
+        assert_eq!(parse(r#"#b"hi\x00\n""#).unwrap(), Expr::Bytes(vec![b'h', b'i', 0, b'\n']));
+        assert_eq!(parse(r#"#x"de ad BE ef""#).unwrap(), Expr::Bytes(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(parse(r#"#b"""#).unwrap(), Expr::Bytes(vec![]));
+        assert!(parse(r#"#x"abc""#).is_err());
+        assert!(parse(r#"#b"\xZZ""#).is_err());
+        // Display reads back to the same literal.
+        let lit = Expr::Bytes(vec![b'a', b'"', 0xff]);
+        assert_eq!(parse(&lit.to_string()).unwrap(), lit);
+    }
+
+    #[test]
+    fn test_parse_multi_form_bodies() {
+        // One form is the body itself; several become a `do`.
+        match parse("(loop [i 0] (yield i) (recur (+ i 1)))").unwrap() {
+            Expr::Loop { bindings, body } => {
+                assert_eq!(bindings.len(), 1);
+                assert!(matches!(*body, Expr::Do(ref forms) if forms.len() == 2));
+            }
+            other => panic!("Expected Loop, got {:?}", other),
         }
+        assert!(matches!(parse("(defgen g [] (yield 1))").unwrap(), Expr::Defgen { body, .. } if matches!(*body, Expr::Yield(_))));
+        assert_eq!(parse("(do 1 2)").unwrap(), Expr::Do(vec![Expr::Integer32(1), Expr::Integer32(2)]));
+        // Display reads back to the same form.
+        let src = "(defgen g [n: i32] (yield n) (recur (+ n 1)))";
+        let form = parse(src).unwrap();
+        assert_eq!(parse(&form.to_string()).unwrap(), form);
+    }
+
+    #[test]
+    fn test_parse_block_labels() {
+        let form = parse("(block :done 1 (return-from :done 2))").unwrap();
+        assert!(matches!(&form, Expr::Block { label, body } if label.to_string() == ":done" && matches!(**body, Expr::Do(_))));
+        assert_eq!(parse(&form.to_string()).unwrap(), form);
+
+        let err = parse("(block done 1)").unwrap_err().to_string();
+        assert!(err.contains("`block` needs a keyword label"), "{}", err);
+        let err = parse("(return-from 1)").unwrap_err().to_string();
+        assert!(err.contains("`return-from` needs a keyword label"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_meta() {
+        let form = parse("(defn ^{:author \"kiri\" :since 2} f [] -> i32 1)").unwrap();
+        let Expr::Defn { meta, .. } = &form else { panic!("not a defn: {:?}", form) };
+        assert_eq!(meta.len(), 2);
+        assert_eq!(meta[0].0.to_string(), ":author");
+        assert_eq!(meta[1].1, Expr::Integer32(2));
+        assert_eq!(form.to_string(), "(defn ^{:author \"kiri\" :since 2} f [] -> i32 1)");
+        assert_eq!(parse(&form.to_string()).unwrap(), form);
+        assert!(matches!(parse("(defgen ^{:pure true} g [] (yield 1))").unwrap(), Expr::Defgen { meta, .. } if meta.len() == 1));
+
+        let err = parse("(defn ^{author 1} f [] 1)").unwrap_err().to_string();
+        assert!(err.contains("metadata keys are keywords"), "{}", err);
+        let err = parse("(defn ^{:since (+ 1 1)} f [] 1)").unwrap_err().to_string();
+        assert!(err.contains("metadata values are literals"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_backend_conditional() {
+        assert_eq!(parse("#?(:interp 1 :jit 2)").unwrap(), Expr::Integer32(1));
+        assert_eq!(parse("#?(:jit 2 :default 3)").unwrap(), Expr::Integer32(3));
+        // With no branch for this backend it isn't there at all.
+        assert_eq!(parse("(f 1 #?(:jit 2) 3)").unwrap(), parse("(f 1 3)").unwrap());
+        assert_eq!(parse("(f #?(:jit 2))").unwrap(), parse("(f)").unwrap());
+        assert_eq!(parse("#?(:jit (g)) (h)").unwrap(), parse("(h)").unwrap());
+
+        parser::set_backend(parser::Backend::Jit);
+        let jit = parse("#?(:interp (list 1) :jit 2)");
+        parser::set_backend(parser::Backend::Interp);
+        assert_eq!(jit.unwrap(), Expr::Integer32(2));
+
+        let err = parse("#?(:llvm 1)").unwrap_err();
+        assert!(err.contains("#? branches start with :interp, :jit or :default"), "{}", err);
+        let err = parse("#?(:jit)").unwrap_err();
+        assert!(err.contains("#? branch :jit needs a form"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_shift_reset() {
+        let form = parse("(reset (+ 1 (shift k (k 1) (k 2))))").unwrap();
+        let Expr::Reset(body) = &form else { panic!("not a reset: {:?}", form) };
+        assert!(matches!(&**body, Expr::List(items) if matches!(&items[2], Expr::Shift { name, body } if name == "k" && matches!(**body, Expr::Do(_)))));
+        assert_eq!(parse(&form.to_string()).unwrap(), form);
+
+        let err = parse("(shift :k 1)").unwrap_err().to_string();
+        assert!(err.contains("`shift` needs a name for the continuation"), "{}", err);
+    }
+
+    #[test]
+    fn test_reader_streams_forms() {
+        use crate::parser::reader::Reader;
+        use std::io::Cursor;
+
+        let src = "(+ 1\n   2) :a\n#_ (skipped)\n\"multi\nline\"\n(f x `. y)\n(g)\n";
+        let mut reader = Reader::new(Cursor::new(src));
+        let sym = |s: &str| Expr::Symbol(s.to_string());
+        assert_eq!(
+            reader.next_form().unwrap(),
+            Some(Expr::List(vec![sym("+"), Expr::Integer32(1), Expr::Integer32(2)]))
+        );
+        assert_eq!(reader.next_form().unwrap(), Some(Expr::Keyword(Keyword::intern("a"))));
+        assert_eq!(reader.next_form().unwrap(), Some(Expr::String("multi\nline".to_string())));
+        // A bad form is reported, and reading picks up after it.
+        assert!(reader.next_form().unwrap_err().starts_with("parse error"));
+        assert_eq!(reader.next_form().unwrap(), Some(Expr::List(vec![sym("g")])));
+        assert_eq!(reader.next_form().unwrap(), None);
+
+        // Only as many lines are read as the first form needs.
+        let mut input = Cursor::new("(a\nb)\n(c)\n(d)\n");
+        let first = Reader::new(&mut input).next_form().unwrap();
+        assert_eq!(first, Some(Expr::List(vec![sym("a"), sym("b")])));
+        assert_eq!(input.position(), 6);
+
+        // An unfinished form at the end of the input is an error.
+        let forms: Vec<_> = Reader::new(Cursor::new("1 (2")).collect();
+        assert!(matches!(forms[0], Ok(Expr::Integer32(1))));
+        assert!(forms[1].as_ref().unwrap_err().contains("parse error"));
+    }
+
+    #[test]
+    fn test_is_complete_waits_for_discarded_form() {
+        use crate::parser::reader::is_complete;
+        assert!(!is_complete("(f 1) #_"));
+        assert!(!is_complete("#_\n"));
+        assert!(is_complete("\"#_\""));
+    }
+
+    #[test]
+    fn test_parse_depth_limit() {
+        let nested = |n: usize| "(f ".repeat(n) + "1" + &")".repeat(n);
+        let too_deep = |limit: usize| format!("more than {} forms deep", limit);
+        assert!(parse(&nested(100)).is_ok());
+        // Far past the limit fails cleanly instead of overflowing the stack.
+        let err = parse(&nested(100_000)).unwrap_err();
+        assert!(err.contains(&too_deep(parser::DEFAULT_MAX_DEPTH)), "{}", err);
+        let deep_type = format!("(let x: {}i32{} nil x)", "List<".repeat(100_000), ">".repeat(100_000));
+        assert!(parse(&deep_type).unwrap_err().contains(&too_deep(parser::DEFAULT_MAX_DEPTH)));
+
+        parser::set_max_depth(10);
+        assert!(parse(&nested(9)).is_ok());
+        assert!(parse(&nested(10)).unwrap_err().contains(&too_deep(10)));
+        let deep_pattern = format!("(match x ({}a{} a))", "(cons ".repeat(20), " _)".repeat(20));
+        assert!(parse(&deep_pattern).unwrap_err().contains(&too_deep(10)));
+        parser::set_max_depth(parser::DEFAULT_MAX_DEPTH);
+        // The count goes back down after an error.
+        assert!(parse(&nested(100)).is_ok());
+    }
+
+    #[test]
+    fn test_default_depth_checks_and_runs() {
+        // Everything the parser lets through, the checker and evaluator
+        // must survive on a main thread's stack.
+        let depth = parser::DEFAULT_MAX_DEPTH - 1;
+        let deep = std::thread::Builder::new().stack_size(parser::STACK_SIZE).spawn(move || {
+            for (open, close) in [("(+ 1 ", ")"), ("(if true ", " 0)"), ("[", "]"), ("(do ", ")")] {
+                let source = open.repeat(depth) + "1" + &close.repeat(depth);
+                let expr = parse(&source).unwrap_or_else(|e| panic!("{}: {}", open, e));
+                crate::types::type_check(&expr, &mut crate::types::TypeEnv::new()).unwrap();
+                crate::eval::eval(&expr, &mut crate::env::Environment::new()).unwrap();
+            }
+        });
+        deep.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_the() {
+        let expr = parse("(the i64 (+ 1 2))").unwrap();
+        assert!(matches!(&expr, Expr::The { ty: Type::I64, .. }));
+        assert_eq!(expr.to_string(), "(the i64 (+ 1 2))");
+        assert!(parse("(the i64)").is_err());
+    }
+
+    #[test]
+    fn test_parse_defn_clauses() {
+        let Expr::Defn { params, body, .. } = parse("(defn f ((0 x) x) ((_ _) 1))").unwrap() else {
+            panic!("expected a defn");
+        };
+        assert_eq!(params, vec![("%1".to_string(), Type::I32), ("%2".to_string(), Type::Inferred)]);
+        assert!(matches!(*body, Expr::Match { ref arms, .. } if arms.len() == 2));
+        assert!(parse("(defn f ((x) x) ((x y) y))").unwrap_err().contains("same number of arguments"));
+    }
+
+    #[test]
+    fn test_parse_program() {
+        let forms = parser::parse_program("(def x 1)\n  (+ x 2) #_(ignored)\n").unwrap();
+        assert_eq!(forms.len(), 2);
+        assert_eq!(forms[1].to_string(), "(+ x 2)");
+        assert!(parser::parse_program("  ").unwrap().is_empty());
+        assert!(parser::parse_program("(def x 1) (+ x").is_err());
+    }
+
+    #[test]
+    fn test_parse_multi_arity_defn() {
+        let Expr::MultiArity(defns) = parse("(defn f ([x] (f x 1)) ([x y] -> i32 y))").unwrap() else {
+            panic!("expected a multi-arity defn");
+        };
+        assert_eq!(defns.len(), 2);
+        assert!(matches!(&defns[1], Expr::Defn { params, return_type: Type::I32, .. } if params.len() == 2));
+        // A single arity is just a defn.
+        assert!(matches!(parse("(defn f ([x] x))").unwrap(), Expr::Defn { .. }));
+        assert!(parse("(defn f ([x] x) ([y] y))").unwrap_err().contains("more than one arity"));
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        use crate::parser::reader::is_complete;
+        assert_eq!(parse("(+ 1 ; one\n 2)").unwrap().to_string(), "(+ 1 2)");
+        assert_eq!(parse("; before\n(+ 1 2) ; after").unwrap().to_string(), "(+ 1 2)");
+        assert_eq!(parse("(list 1 #| 2 #| nested |# |# 3)").unwrap().to_string(), "(list 1 3)");
+        let Expr::Defn { params, .. } = parse("(defn f [x: i32 ; the x\n y: i32] x)").unwrap() else {
+            panic!("expected a defn");
+        };
+        assert_eq!(params.len(), 2);
+        assert!(parse("(+ 1 #| 2)").unwrap_err().contains("never closed"));
+
+        assert!(!is_complete("(+ 1 ; )\n"));
+        assert!(is_complete("(+ 1 ; (\n 2)"));
+        assert!(!is_complete("#| (+ 1 2)"));
+        assert!(is_complete("#| ( |# (+ 1 2)"));
+    }
+
+    #[test]
+    fn test_parse_match() {
+        let Expr::Match { scrutinee, arms } = parse("(match x (1 :one) (\"s\" :s) (y y) (_ :other))").unwrap() else {
+            panic!("expected a match");
+        };
+        assert_eq!(*scrutinee, Expr::Symbol("x".to_string()));
+        let patterns: Vec<_> = arms.into_iter().map(|(p, _)| p).collect();
+        assert_eq!(
+            patterns,
+            vec![
+                Pattern::LiteralI32(1),
+                Pattern::LiteralString("s".to_string()),
+                Pattern::Variable("y".to_string()),
+                Pattern::Wildcard,
+            ]
+        );
+
+        let Expr::Match { arms, .. } = parse("(match xs ((cons (cons a _) nil) a) (_ 0))").unwrap() else {
+            panic!("expected a match");
+        };
+        assert_eq!(
+            arms[0].0,
+            Pattern::Cons(
+                Box::new(Pattern::Cons(Box::new(Pattern::Variable("a".to_string())), Box::new(Pattern::Wildcard))),
+                Box::new(Pattern::Nil),
+            )
+        );
+        assert!(parse("(match x)").is_err());
+    }
+
+    #[test]
+    fn test_parse_deftype() {
+        let Expr::Deftype { name, variants } = parse("(deftype Shape (Circle f64) (Rect f64 f64) Empty)").unwrap() else {
+            panic!("expected a deftype");
+        };
+        assert_eq!(name, "Shape");
+        assert_eq!(
+            variants,
+            vec![
+                ("Circle".to_string(), vec![Type::F64]),
+                ("Rect".to_string(), vec![Type::F64, Type::F64]),
+                ("Empty".to_string(), vec![]),
+            ]
+        );
+        assert_eq!(
+            parse("(deftype Tree Leaf (Node Tree i32 Tree))").unwrap().to_string(),
+            "(deftype Tree Leaf (Node Tree i32 Tree))"
+        );
+        assert!(parse("(deftype shape A)").unwrap_err().contains("uppercase"));
+        assert!(parse("(deftype S a)").unwrap_err().contains("uppercase"));
+        assert!(parse("(deftype S)").unwrap_err().contains("at least one constructor"));
+
+        // Capitalized names in patterns are constructors.
+        let Expr::Match { arms, .. } = parse("(match s ((Circle r) r) (Empty 0.0))").unwrap() else {
+            panic!("expected a match");
+        };
+        assert_eq!(arms[0].0, Pattern::Constructor("Circle".to_string(), vec![Pattern::Variable("r".to_string())]));
+        assert_eq!(arms[1].0, Pattern::Constructor("Empty".to_string(), vec![]));
+
+        let Expr::Defn { params, .. } = parse("(defn f [s: Shape l: List<Shape>] s)").unwrap() else {
+            panic!("expected a defn");
+        };
+        assert_eq!(params[0].1, Type::Named("Shape".to_string()));
+        assert_eq!(params[1].1, Type::List(Box::new(Type::Named("Shape".to_string()))));
+        assert!(matches!(parse("(defn f [s: StringBuilder] s)").unwrap(), Expr::Defn { params, .. } if params[0].1 == Type::StringBuilder));
+    }
+
+    #[test]
+    fn test_parse_defstruct() {
+        let Expr::Defstruct { name, fields } = parse("(defstruct Point [x: f64 y: f64])").unwrap() else {
+            panic!("expected a defstruct");
+        };
+        assert_eq!(name, "Point");
+        assert_eq!(fields, vec![("x".to_string(), Type::F64), ("y".to_string(), Type::F64)]);
+        assert_eq!(parse("(defstruct Point [x: f64 y: f64])").unwrap().to_string(), "(defstruct Point [x: f64 y: f64])");
+        assert!(parse("(defstruct point [x: f64])").unwrap_err().contains("uppercase"));
+        assert!(parse("(defstruct P [])").unwrap_err().contains("at least one field"));
+        assert!(parse("(defstruct P [x: i32 x: i32])").unwrap_err().contains("declared twice"));
+
+        // `(.x p)` reads as `(get p :x)`.
+        assert_eq!(parse("(.x p)").unwrap().to_string(), "(get p :x)");
+        assert_eq!(parse("(.x (f p))").unwrap(), parse("(get (f p) :x)").unwrap());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod tests {
+    use crate::env::Environment;
+    use crate::eval::eval;
+    use crate::parser::parse;
+    use crate::profile;
+
+    fn run(env: &mut Environment, site: &str, src: &str) {
+        profile::set_site(site.to_string());
+        eval(&parse(src).unwrap(), env).unwrap();
+    }
+
+    #[test]
+    fn test_profile_charges_sites() {
+        let mut env = Environment::new();
+        run(&mut env, "before", "(list 1 2 3)");
+
+        profile::start();
+        run(&mut env, "a.rusp:1:1", "(defn pairs [n: i32] -> _ (list n n))");
+        run(&mut env, "a.rusp:2:1", "(do (pairs 1) (pairs 2) (pairs 3))");
+        let report = profile::stop();
+
+        let find = |site: &str, kind: &str| {
+            report.sites.iter().find(|s| s.site == site && s.kind == kind).map(|s| s.counts)
+        };
+        // The body's list is charged to the function, named where it was
+        // defined; the scope each call creates, to the caller.
+        assert_eq!(find("pairs (a.rusp:1:1)", "list").map(|c| c.count), Some(3));
+        let envs = find("a.rusp:2:1", "environment").expect("call environments");
+        assert_eq!(envs.count, 3);
+        assert!(envs.bytes > 0);
+        let function = find("a.rusp:1:1", "function").expect("the defn's closure");
+        assert_eq!(function.count, 1);
+        // The closure shares the global scope rather than owning it.
+        assert_eq!(function.bytes, std::mem::size_of::<crate::env::Closure>() as u64);
+        // Nothing from before `start`, and most bytes come first.
+        assert!(report.sites.iter().all(|s| s.site != "before"));
+        assert!(report.sites.windows(2).all(|w| w[0].counts.bytes >= w[1].counts.bytes));
+        assert!(report.top(1).lines().count() == 3, "{}", report.top(1));
+
+        // Stopped: nothing more is recorded.
+        run(&mut env, "after", "(pairs 4)");
+        profile::start();
+        assert!(profile::stop().sites.is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! Runtime strings.
+//!
+//! A `Str` is a byte range into a shared, immutable buffer. Slicing
+//! builtins (`substring`, `split`, `trim`) hand out new ranges over the
+//! same buffer instead of copying; only operations that build new text
+//! (`str-concat`, `sb-push!`, ...) allocate.
+//!
+//! The flip side: a short slice keeps its whole parent buffer alive.
+//!
+//! Offsets are `u32` to keep `Value` small, which caps a single string at
+//! 4 GiB; a `Str` is made with `try_from`, and the builtins that build one
+//! past that return its error.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub struct Str {
+    buf: Rc<str>,
+    start: u32,
+    end: u32,
+}
+
+impl Str {
+    pub fn as_str(&self) -> &str {
+        &self.buf[self.start as usize..self.end as usize]
+    }
+
+    fn whole(buf: Rc<str>) -> Result<Str, String> {
+        let end = u32::try_from(buf.len()).map_err(|_| format!("a string of {} bytes is over the 4 GiB limit", buf.len()))?;
+        Ok(Str { buf, start: 0, end })
+    }
+
+    /// The `Str` for `part`, which must be a subslice of `self.as_str()`
+    /// (e.g. the result of `trim`). Shares `self`'s buffer.
+    pub fn slice_of(&self, part: &str) -> Str {
+        let offset = part.as_ptr() as usize - self.as_str().as_ptr() as usize;
+        assert!(offset + part.len() <= self.len(), "slice_of: not a subslice");
+        let start = self.start + offset as u32;
+        Str { buf: Rc::clone(&self.buf), start, end: start + part.len() as u32 }
+    }
+
+    /// Whether `self` and `other` point into the same buffer.
+    pub fn shares_buffer(&self, other: &Str) -> bool {
+        Rc::ptr_eq(&self.buf, &other.buf)
+    }
+}
+
+impl Deref for Str {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl TryFrom<String> for Str {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Str, String> {
+        Str::whole(s.into())
+    }
+}
+
+impl TryFrom<&str> for Str {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Str, String> {
+        Str::whole(s.into())
+    }
+}
+
+impl fmt::Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Str) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Str {}
+
+impl Hash for Str {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for Str {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Str {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Str {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
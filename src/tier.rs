@@ -0,0 +1,281 @@
+//! Tiered execution: interpret first, JIT-compile hot functions.
+//!
+//! Every call the interpreter makes through a `defn`'s name bumps a
+//! per-name counter. Once a function crosses the threshold it is handed
+//! to the LLVM backend (`codegen::tier`) together with every `defn` it
+//! references; on success the caller's environment binding is swapped
+//! for a `Value::Native` that calls straight into machine code. Functions
+//! outside the JIT subset (lists, strings, closures over locals, …) are
+//! remembered as rejected and simply stay interpreted.
+//!
+//! Bookkeeping lives in a thread-local rather than on `Environment`
+//! because it's per session, not per scope: every scope chain, the
+//! session's and each closure's, has to see the same tier state.
+//!
+//! Identity: the table is keyed by name, so before dispatching a
+//! `Value::Function` to compiled code we check its body against the
+//! compiled `defn`. A lambda that shadows a hot `defn`'s name, or a
+//! closure captured before a redefinition, keeps running interpreted.
+//!
+//! Call sites: there's no bytecode, so there's no per-site inline cache.
+//! A call looks its function up by name each time, and the swapped-in
+//! `Value::Native` binding does a cache's job: later lookups find the
+//! compiled code directly. Compiled code has its own copies of the
+//! `defn`s it calls, so redefining any of them invalidates every
+//! function compiled with it: `record_defn` marks those stale, and a
+//! stale binding goes back to its interpreted `Function` on its next
+//! call.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Pattern};
+use crate::codegen::tier::NativeEntry;
+use crate::env::{Closure, Value};
+
+/// Calls before a `defn` is considered hot.
+pub const DEFAULT_THRESHOLD: u32 = 1000;
+
+/// A compiled `defn` bound in an environment.
+pub struct NativeFn {
+    pub name: String,
+    /// The function this was compiled from, which it displays and
+    /// reflects as.
+    pub source: Rc<Closure>,
+    entry: NativeEntry,
+    /// Body of the `defn` this was compiled from, for the identity check.
+    body: Expr,
+    /// Set when a `defn` compiled into this one is redefined; calls then
+    /// go to `source` instead.
+    stale: Cell<bool>,
+}
+
+impl NativeFn {
+    pub fn arity(&self) -> usize {
+        self.entry.arity()
+    }
+
+    pub fn call(&self, args: &[Value]) -> Result<Value, String> {
+        self.entry.call(args)
+    }
+
+    /// Whether this was compiled against a `defn` that's since been
+    /// redefined.
+    pub fn is_stale(&self) -> bool {
+        self.stale.get()
+    }
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFn({}/{})", self.name, self.arity())
+    }
+}
+
+struct Defn {
+    /// Definition order, which a compiled module emits its `defn`s in.
+    seq: u64,
+    form: Expr,
+}
+
+struct Tier {
+    threshold: Option<u32>,
+    next_seq: u64,
+    defns: HashMap<String, Defn>,
+    counts: HashMap<String, u32>,
+    compiled: HashMap<String, Rc<NativeFn>>,
+    rejected: HashSet<String>,
+    /// `defn` name → the compiled functions with a copy of it.
+    dependents: HashMap<String, HashSet<String>>,
+}
+
+thread_local! {
+    static TIER: RefCell<Tier> = RefCell::new(Tier {
+        threshold: Some(DEFAULT_THRESHOLD),
+        next_seq: 0,
+        defns: HashMap::new(),
+        counts: HashMap::new(),
+        compiled: HashMap::new(),
+        rejected: HashSet::new(),
+        dependents: HashMap::new(),
+    });
+}
+
+/// Set the hotness threshold for this thread. `None` disables tiering.
+pub fn set_threshold(threshold: Option<u32>) {
+    TIER.with(|t| t.borrow_mut().threshold = threshold);
+}
+
+/// Record a `defn` as it is evaluated. Redefining a name resets its
+/// counter and drops any earlier rejection, and every compiled function
+/// with a copy of it (its own compiled version included) goes stale.
+pub fn record_defn(form: &Expr) {
+    let Expr::Defn { name, .. } = form else { return };
+    TIER.with(|t| {
+        let mut t = t.borrow_mut();
+        let seq = t.next_seq;
+        t.next_seq += 1;
+        t.defns.insert(name.clone(), Defn { seq, form: form.clone() });
+        for dependent in t.dependents.remove(name).unwrap_or_default() {
+            if let Some(native) = t.compiled.remove(&dependent) {
+                native.stale.set(true);
+            }
+            t.counts.remove(&dependent);
+        }
+        t.counts.remove(name);
+        t.rejected.remove(name);
+    });
+}
+
+/// Whether `name` currently has a compiled version.
+pub fn is_compiled(name: &str) -> bool {
+    TIER.with(|t| t.borrow().compiled.contains_key(name))
+}
+
+/// Count a call to `func` made through `name`. Returns the compiled
+/// entry point if one exists (or was just created) for this function.
+pub fn on_call(name: &str, func: &Value) -> Option<Rc<NativeFn>> {
+    let Value::Function(closure) = func else { return None };
+    let body = &closure.body;
+    TIER.with(|t| {
+        let mut t = t.borrow_mut();
+        let threshold = t.threshold?;
+        if let Some(native) = t.compiled.get(name) {
+            return (native.body == *body).then(|| native.clone());
+        }
+        if t.rejected.contains(name) || !t.defns.contains_key(name) {
+            return None;
+        }
+        let count = t.counts.entry(name.to_string()).or_insert(0);
+        *count += 1;
+        if *count < threshold {
+            return None;
+        }
+
+        match compile(&t, name, closure) {
+            Some((native, copied)) => {
+                let native = Rc::new(native);
+                t.compiled.insert(name.to_string(), native.clone());
+                for callee in copied {
+                    t.dependents.entry(callee).or_default().insert(name.to_string());
+                }
+                Some(native)
+            }
+            None => {
+                t.rejected.insert(name.to_string());
+                None
+            }
+        }
+    })
+}
+
+/// Compile `name` plus the `defn`s it (transitively) references, which
+/// are returned with it.
+fn compile(t: &Tier, name: &str, source: &Rc<Closure>) -> Option<(NativeFn, Vec<String>)> {
+    let body = &source.body;
+    let root = t.defns.get(name)?;
+    let Expr::Defn { body: defn_body, .. } = &root.form else { return None };
+    if **defn_body != *body {
+        return None;
+    }
+
+    let mut needed: Vec<&Defn> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut pending = vec![name.to_string()];
+    while let Some(n) = pending.pop() {
+        if !seen.insert(n.clone()) {
+            continue;
+        }
+        let Some(d) = t.defns.get(&n) else { continue };
+        let mut refs = HashSet::new();
+        symbols(&d.form, &mut refs);
+        pending.extend(refs.into_iter().filter(|r| t.defns.contains_key(r)));
+        needed.push(d);
+    }
+    needed.sort_by_key(|d| d.seq);
+    let forms: Vec<Expr> = needed.into_iter().map(|d| d.form.clone()).collect();
+
+    let entry = crate::codegen::tier::compile(&forms, name).ok()?;
+    let native = NativeFn {
+        name: name.to_string(),
+        source: Rc::clone(source),
+        entry,
+        body: body.clone(),
+        stale: Cell::new(false),
+    };
+    Some((native, seen.into_iter().filter(|n| t.defns.contains_key(n)).collect()))
+}
+
+/// Every symbol mentioned in `expr`, over-approximating references to
+/// other `defn`s (locals that shadow a `defn` name are included too,
+/// which only costs an unused function in the compiled module).
+pub(crate) fn symbols(expr: &Expr, out: &mut HashSet<String>) {
+    match expr {
+        Expr::Symbol(s) => {
+            out.insert(s.clone());
+        }
+        Expr::List(items) => items.iter().for_each(|e| symbols(e, out)),
+        Expr::If { condition, then_branch, else_branch } => {
+            symbols(condition, out);
+            symbols(then_branch, out);
+            symbols(else_branch, out);
+        }
+        Expr::Let { value, body, .. } => {
+            symbols(value, out);
+            symbols(body, out);
+        }
+        Expr::Def { value, .. } => symbols(value, out),
+        Expr::Defn { body, .. } | Expr::Defgen { body, .. } | Expr::Lambda { body, .. } => symbols(body, out),
+        Expr::MultiArity(defns) => defns.iter().for_each(|d| symbols(d, out)),
+        Expr::Yield(value)
+        | Expr::Return(value)
+        | Expr::The { expr: value, .. }
+        | Expr::Block { body: value, .. }
+        | Expr::ReturnFrom { value, .. }
+        | Expr::Reset(value)
+        | Expr::Shift { body: value, .. }
+        | Expr::Defer(value)
+        | Expr::Defparam { value, .. } => symbols(value, out),
+        Expr::Parameterize { bindings, body } | Expr::WithOpen { bindings, body } => {
+            bindings.iter().for_each(|(_, value)| symbols(value, out));
+            symbols(body, out);
+        }
+        Expr::Loop { bindings, body } => {
+            bindings.iter().for_each(|(_, _, value)| symbols(value, out));
+            symbols(body, out);
+        }
+        Expr::Recur(args) | Expr::Do(args) => args.iter().for_each(|e| symbols(e, out)),
+        Expr::Call { func, args } => {
+            symbols(func, out);
+            args.iter().for_each(|e| symbols(e, out));
+        }
+        Expr::Match { scrutinee, arms } => {
+            symbols(scrutinee, out);
+            for (pat, body) in arms {
+                pattern_symbols(pat, out);
+                symbols(body, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn pattern_symbols(pat: &Pattern, out: &mut HashSet<String>) {
+    match pat {
+        Pattern::Guard(inner, guard) => {
+            pattern_symbols(inner, out);
+            symbols(guard, out);
+        }
+        Pattern::As(inner, _) => pattern_symbols(inner, out),
+        Pattern::Cons(h, t) => {
+            pattern_symbols(h, out);
+            pattern_symbols(t, out);
+        }
+        Pattern::Or(branches) | Pattern::Constructor(_, branches) => {
+            branches.iter().for_each(|b| pattern_symbols(b, out))
+        }
+        _ => {}
+    }
+}
@@ -1,5 +1,10 @@
 use crate::ast::{Expr, Pattern, Type};
-use std::collections::HashMap;
+use crate::diagnostics::type_mismatch;
+use crate::keyword::Keyword;
+use crate::lint::{self, Lint, Site};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct TypeEnv {
@@ -9,8 +14,52 @@ pub struct TypeEnv {
     /// `Defn` reads this back to refine its registered function signature
     /// after the body is checked.
     pub refinements: HashMap<String, Type>,
+    /// True for the child scopes `extend` makes; `def` is rejected there.
+    scoped: bool,
+    /// Globals made with `defconst`, which no later definition may replace.
+    constants: HashSet<String>,
+    /// What a `recur` here jumps back to: the binding types of the
+    /// innermost `loop`, `defn` or `fn`. None at the top level.
+    recur: Option<Vec<Type>>,
+    /// What the innermost `defgen` yields, shared by every scope in its
+    /// body; the first `yield` fills it in. None outside a `defgen`.
+    yields: Option<Rc<RefCell<Type>>>,
+    /// What the innermost `defn` or `fn` returns, shared like `yields`:
+    /// its declared type, or the first `return`'s if it declared none.
+    /// None outside a function.
+    returns: Option<Rc<RefCell<Type>>>,
+    /// The `block`s around here, innermost last, each with the type its
+    /// `return-from`s give. A function body starts with none.
+    blocks: Vec<(Keyword, Rc<RefCell<Type>>)>,
+    /// Globals made with `defparam`, the names `parameterize` accepts.
+    params: HashSet<String>,
+    /// Inside a function body or `with-open`, where `defer` has a scope
+    /// to run at the end of.
+    defer_scope: bool,
+    /// Functions defined with `^{:deprecated "..."}`, with the message.
+    /// Binding the name again (a new `defn`, a parameter, a `let`)
+    /// drops it.
+    deprecated: HashMap<String, Option<String>>,
+    /// Warnings raised while checking, shared by every scope; the driver
+    /// takes them after each top-level form.
+    warnings: Rc<RefCell<Vec<Lint>>>,
+    /// Types declared with `deftype`. Shared by every scope like
+    /// `warnings`, since they're only declared at the top level.
+    adts: Rc<RefCell<Adts>>,
+    /// The field names of each `defstruct`, also in `adts` as a type with
+    /// one constructor.
+    structs: Rc<RefCell<HashMap<String, Vec<String>>>>,
+    /// Each global → the top-level definitions that mention it. A
+    /// function calls whatever a name is bound to when it runs, so a
+    /// global one was checked against can't take a type that doesn't fit.
+    /// Only the top level keeps these.
+    users: HashMap<String, HashSet<String>>,
 }
 
+/// Each `deftype` by name, with its constructors and their field types
+/// in the order declared.
+pub(crate) type Adts = HashMap<String, Vec<(String, Vec<Type>)>>;
+
 impl Default for TypeEnv {
     fn default() -> Self {
         Self::new()
@@ -21,7 +70,7 @@ impl TypeEnv {
     pub fn new() -> Self {
         let mut types = HashMap::new();
         
-        // i32 arithmetic operators
+        // Arithmetic on i32, i64 or f64; the call site works out which.
         types.insert("+".to_string(), Type::Function {
             params: vec![Type::Inferred, Type::Inferred],
             return_type: Box::new(Type::Inferred),
@@ -39,22 +88,66 @@ impl TypeEnv {
             return_type: Box::new(Type::Inferred),
         });
         
-        types.insert("+.".to_string(), Type::Function {
-            params: vec![Type::F64, Type::F64],
-            return_type: Box::new(Type::F64),
-        });
-        types.insert("-.".to_string(), Type::Function {
-            params: vec![Type::F64, Type::F64],
-            return_type: Box::new(Type::F64),
-        });
-        types.insert("*.".to_string(), Type::Function {
-            params: vec![Type::F64, Type::F64],
-            return_type: Box::new(Type::F64),
+        // `(to-i64 x)` widens an i32 (an i64 passes through).
+        types.insert("to-i64".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::I64),
         });
-        types.insert("/.".to_string(), Type::Function {
-            params: vec![Type::F64, Type::F64],
-            return_type: Box::new(Type::F64),
+        
+        // Conversions between the numeric types; see `Environment::new`.
+        for (name, from, to) in [
+            ("i32->i64", Type::I32, Type::I64),
+            ("i32->f64", Type::I32, Type::F64),
+            ("i64->i32", Type::I64, Type::I32),
+            ("i64->f64", Type::I64, Type::F64),
+            ("f64->i32", Type::F64, Type::I32),
+            ("f64->i64", Type::F64, Type::I64),
+        ] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![from],
+                return_type: Box::new(to),
+            });
+        }
+        for name in ["nan?", "is-nan?", "is-finite?"] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::F64],
+                return_type: Box::new(Type::Bool),
+            });
+        }
+        types.insert("approx=".to_string(), Type::Function {
+            params: vec![Type::F64, Type::F64, Type::F64],
+            return_type: Box::new(Type::Bool),
         });
+        for name in ["approx-eq", "assert-approx-eq"] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::F64, Type::F64],
+                return_type: Box::new(Type::Bool),
+            });
+        }
+        for (name, ret) in [
+            ("floor", Type::F64),
+            ("ceil", Type::F64),
+            ("round", Type::F64),
+            ("trunc", Type::F64),
+            ("floor-int", Type::I64),
+            ("ceil-int", Type::I64),
+            ("round-int", Type::I64),
+            ("trunc-int", Type::I64),
+        ] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::F64],
+                return_type: Box::new(ret),
+            });
+        }
+        // Numeric on any width; the result type comes from `numeric_join`.
+        for (name, arity) in [("min", 2), ("max", 2), ("abs", 1), ("neg", 1), ("clamp", 3)] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::Inferred; arity],
+                return_type: Box::new(Type::Inferred),
+            });
+        }
+        types.insert("nan".to_string(), Type::F64);
+        types.insert("inf".to_string(), Type::F64);
         
         types.insert("=".to_string(), Type::Function {
             params: vec![Type::Inferred, Type::Inferred],
@@ -77,14 +170,6 @@ impl TypeEnv {
             return_type: Box::new(Type::Bool),
         });
         
-        types.insert("and".to_string(), Type::Function {
-            params: vec![Type::Bool, Type::Bool],
-            return_type: Box::new(Type::Bool),
-        });
-        types.insert("or".to_string(), Type::Function {
-            params: vec![Type::Bool, Type::Bool],
-            return_type: Box::new(Type::Bool),
-        });
         types.insert("not".to_string(), Type::Function {
             params: vec![Type::Bool],
             return_type: Box::new(Type::Bool),
@@ -94,11 +179,31 @@ impl TypeEnv {
         // We use Inferred to represent "any type" for now
         types.insert("print".to_string(), Type::Function {
             params: vec![Type::Inferred],
-            return_type: Box::new(Type::Inferred),
+            return_type: Box::new(Type::Unit),
         });
         types.insert("println".to_string(), Type::Function {
             params: vec![Type::Inferred],
-            return_type: Box::new(Type::Inferred),
+            return_type: Box::new(Type::Unit),
+        });
+        for name in ["stdout", "stderr", "*out*"] {
+            types.insert(name.to_string(), Type::Port);
+        }
+        types.insert("string-port".to_string(), Type::Function {
+            params: vec![],
+            return_type: Box::new(Type::Port),
+        });
+        types.insert("open-file".to_string(), Type::Function {
+            params: vec![Type::String],
+            return_type: Box::new(Type::Port),
+        });
+        // A Port or a KvStore; checked when it runs.
+        types.insert("close".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::Bool),
+        });
+        types.insert("port-string".to_string(), Type::Function {
+            params: vec![Type::Port],
+            return_type: Box::new(Type::String),
         });
         
         // List operations
@@ -118,6 +223,47 @@ impl TypeEnv {
             params: vec![Type::Inferred],
             return_type: Box::new(Type::Bool),
         });
+        for &(alias, builtin) in crate::env::LIST_ALIASES {
+            let ty = types[builtin].clone();
+            types.insert(alias.to_string(), ty);
+        }
+        types.insert("type-of".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::Type),
+        });
+        types.insert("type=".to_string(), Type::Function {
+            params: vec![Type::Type, Type::Type],
+            return_type: Box::new(Type::Bool),
+        });
+        types.insert("arity".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::I32),
+        });
+        types.insert("param-names".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::List(Box::new(Type::String))),
+        });
+        types.insert("meta".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::List(Box::new(Type::Pair(Box::new(Type::Keyword), Box::new(Type::Inferred))))),
+        });
+        types.insert("fn-source".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::String),
+        });
+        for name in ["int?", "fn?"] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::Inferred],
+                return_type: Box::new(Type::Bool),
+            });
+        }
+        for name in ["i32", "i64", "f64", "bool", "String", "Keyword"] {
+            types.insert(name.to_string(), Type::Type);
+        }
+        types.insert("pair?".to_string(), Type::Function {
+            params: vec![Type::Inferred],
+            return_type: Box::new(Type::Bool),
+        });
         types.insert("length".to_string(), Type::Function {
             params: vec![Type::List(Box::new(Type::Inferred))],
             return_type: Box::new(Type::I32),
@@ -131,7 +277,342 @@ impl TypeEnv {
             return_type: Box::new(Type::Inferred),
         });
         
-        TypeEnv { types, refinements: HashMap::new() }
+        // String operations. `sb-push!` takes any value, like `print`.
+        types.insert("str-concat".to_string(), Type::Function {
+            params: vec![Type::String, Type::String],
+            return_type: Box::new(Type::String),
+        });
+        types.insert("string-builder".to_string(), Type::Function {
+            params: vec![],
+            return_type: Box::new(Type::StringBuilder),
+        });
+        types.insert("sb-push!".to_string(), Type::Function {
+            params: vec![Type::StringBuilder, Type::Inferred],
+            return_type: Box::new(Type::StringBuilder),
+        });
+        types.insert("sb-build".to_string(), Type::Function {
+            params: vec![Type::StringBuilder],
+            return_type: Box::new(Type::String),
+        });
+        types.insert("substring".to_string(), Type::Function {
+            params: vec![Type::String, Type::I32, Type::I32],
+            return_type: Box::new(Type::String),
+        });
+        types.insert("split".to_string(), Type::Function {
+            params: vec![Type::String, Type::String],
+            return_type: Box::new(Type::List(Box::new(Type::String))),
+        });
+        types.insert("trim".to_string(), Type::Function {
+            params: vec![Type::String],
+            return_type: Box::new(Type::String),
+        });
+        
+        // Keywords
+        types.insert("keyword".to_string(), Type::Function {
+            params: vec![Type::String],
+            return_type: Box::new(Type::Keyword),
+        });
+        types.insert("keyword-name".to_string(), Type::Function {
+            params: vec![Type::Keyword],
+            return_type: Box::new(Type::String),
+        });
+        // A `:field` given literally is checked against the struct; a map
+        // is checked at the call site.
+        types.insert("get".to_string(), Type::Function {
+            params: vec![Type::Inferred, Type::Inferred],
+            return_type: Box::new(Type::Inferred),
+        });
+        
+        // Maps; the key and value types are refined at call sites.
+        let maps = [
+            ("assoc", vec![Type::Inferred; 3], Type::Inferred),
+            ("dissoc", vec![Type::Inferred; 2], Type::Inferred),
+            ("contains?", vec![Type::Inferred; 2], Type::Bool),
+            ("keys", vec![Type::Inferred], Type::List(Box::new(Type::Inferred))),
+            ("vals", vec![Type::Inferred], Type::List(Box::new(Type::Inferred))),
+        ];
+        for (name, params, ret) in maps {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Byte buffers
+        types.insert("bytes-len".to_string(), Type::Function {
+            params: vec![Type::Bytes],
+            return_type: Box::new(Type::I32),
+        });
+        types.insert("bytes-slice".to_string(), Type::Function {
+            params: vec![Type::Bytes, Type::I32, Type::I32],
+            return_type: Box::new(Type::Bytes),
+        });
+        types.insert("bytes->string".to_string(), Type::Function {
+            params: vec![Type::Bytes],
+            return_type: Box::new(Type::String),
+        });
+        types.insert("string->bytes".to_string(), Type::Function {
+            params: vec![Type::String],
+            return_type: Box::new(Type::Bytes),
+        });
+        types.insert("read-file-bytes".to_string(), Type::Function {
+            params: vec![Type::String],
+            return_type: Box::new(Type::Bytes),
+        });
+        types.insert("embed-file".to_string(), Type::Function {
+            params: vec![Type::String],
+            return_type: Box::new(Type::String),
+        });
+        types.insert("write-file-bytes".to_string(), Type::Function {
+            params: vec![Type::String, Type::Bytes],
+            return_type: Box::new(Type::I32),
+        });
+        
+        // Compression
+        for name in ["gzip-compress", "gzip-decompress"] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::Bytes],
+                return_type: Box::new(Type::Bytes),
+            });
+        }
+        types.insert("read-file-bytes-as".to_string(), Type::Function {
+            params: vec![Type::String, Type::String],
+            return_type: Box::new(Type::Bytes),
+        });
+        
+        // Binary encode/decode (see `Environment::new` for the runtime
+        // side). Writers accept either integer width for the value.
+        types.insert("bytes-alloc".to_string(), Type::Function {
+            params: vec![Type::I32],
+            return_type: Box::new(Type::Bytes),
+        });
+        let readers = [
+            ("read-u8", Type::I32), ("read-i8", Type::I32),
+            ("read-u16-le", Type::I32), ("read-u16-be", Type::I32),
+            ("read-i16-le", Type::I32), ("read-i16-be", Type::I32),
+            ("read-u32-le", Type::I64), ("read-u32-be", Type::I64),
+            ("read-i32-le", Type::I32), ("read-i32-be", Type::I32),
+            ("read-i64-le", Type::I64), ("read-i64-be", Type::I64),
+            ("read-f64-le", Type::F64), ("read-f64-be", Type::F64),
+        ];
+        for (name, ret) in readers {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::Bytes, Type::I32],
+                return_type: Box::new(ret),
+            });
+        }
+        let writers = [
+            ("write-u8!", Type::Inferred),
+            ("write-u16-le!", Type::Inferred), ("write-u16-be!", Type::Inferred),
+            ("write-u32-le!", Type::Inferred), ("write-u32-be!", Type::Inferred),
+            ("write-u64-le!", Type::Inferred), ("write-u64-be!", Type::Inferred),
+            ("write-f64-le!", Type::F64), ("write-f64-be!", Type::F64),
+        ];
+        for (name, value_ty) in writers {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::Bytes, Type::I32, value_ty],
+                return_type: Box::new(Type::Bytes),
+            });
+        }
+        types.insert("pack".to_string(), Type::Function {
+            params: vec![Type::String, Type::List(Box::new(Type::Inferred))],
+            return_type: Box::new(Type::Bytes),
+        });
+        types.insert("unpack".to_string(), Type::Function {
+            params: vec![Type::String, Type::Bytes, Type::I32],
+            return_type: Box::new(Type::List(Box::new(Type::I64))),
+        });
+        
+        // Paths
+        types.insert("path-join".to_string(), Type::Function {
+            params: vec![Type::String, Type::String],
+            return_type: Box::new(Type::String),
+        });
+        for name in ["path-parent", "path-filename", "path-extension", "absolute-path"] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::String],
+                return_type: Box::new(Type::String),
+            });
+        }
+        types.insert("path-exists?".to_string(), Type::Function {
+            params: vec![Type::String],
+            return_type: Box::new(Type::Bool),
+        });
+        
+        // Directories and metadata
+        for name in ["list-dir", "walk-dir"] {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::String],
+                return_type: Box::new(Type::List(Box::new(Type::String))),
+            });
+        }
+        let metadata = [("file-size", Type::I64), ("is-dir?", Type::Bool), ("modified-time", Type::DateTime)];
+        for (name, ret) in metadata {
+            types.insert(name.to_string(), Type::Function {
+                params: vec![Type::String],
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Script arguments
+        let strings = || Type::List(Box::new(Type::String));
+        let parsed = || Type::List(Box::new(Type::List(Box::new(Type::String))));
+        let cli = [
+            ("args", vec![], strings()),
+            ("cli/parse", vec![parsed(), strings()], parsed()),
+            ("cli/get", vec![parsed(), Type::String], Type::String),
+            ("cli/flag?", vec![parsed(), Type::String], Type::Bool),
+        ];
+        for (name, params, ret) in cli {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Ref cells. `ref`, `deref` and `set-ref!` are refined at call
+        // sites so the cell's element type flows through.
+        let refs = [
+            ("ref", vec![Type::Inferred], Type::Ref(Box::new(Type::Inferred))),
+            ("deref", vec![Type::Ref(Box::new(Type::Inferred))], Type::Inferred),
+            ("set-ref!", vec![Type::Ref(Box::new(Type::Inferred)), Type::Inferred], Type::Unit),
+        ];
+        for (name, params, ret) in refs {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Fixed-size arrays. The length is part of the type, so the array
+        // parameters are left open here and checked at call sites.
+        let list = || Type::List(Box::new(Type::Inferred));
+        let vector = || Type::Vector(Box::new(Type::Inferred));
+        let arrays = [
+            ("array-len", vec![Type::Inferred], Type::I32),
+            ("array-get", vec![Type::Inferred, Type::I32], Type::Inferred),
+            ("array-set!", vec![Type::Inferred, Type::I32, Type::Inferred], Type::Inferred),
+            ("array->list", vec![Type::Inferred], list()),
+            ("list->array", vec![list(), Type::I32], Type::Inferred),
+            ("slice", vec![Type::Inferred, Type::I32, Type::I32], Type::Inferred),
+            ("array->vec", vec![Type::Inferred], vector()),
+            ("vec->array", vec![vector(), Type::I32], Type::Inferred),
+        ];
+        for (name, params, ret) in arrays {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Vectors; the element type is refined at call sites.
+        let vectors = [
+            ("vec-get", vec![vector(), Type::I32], Type::Inferred),
+            ("vec-len", vec![vector()], Type::I32),
+            ("vec-push", vec![vector(), Type::Inferred], vector()),
+            ("vec-slice", vec![vector(), Type::I32, Type::I32], vector()),
+        ];
+        for (name, params, ret) in vectors {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Matrices
+        let f64_rows = Type::List(Box::new(Type::List(Box::new(Type::F64))));
+        let matrices = [
+            ("mat", vec![Type::I32, Type::I32, Type::F64], Type::Matrix),
+            ("mat-rows", vec![Type::Matrix], Type::I32),
+            ("mat-cols", vec![Type::Matrix], Type::I32),
+            ("mat-get", vec![Type::Matrix, Type::I32, Type::I32], Type::F64),
+            ("mat-set!", vec![Type::Matrix, Type::I32, Type::I32, Type::F64], Type::Matrix),
+            ("mat-mul", vec![Type::Matrix, Type::Matrix], Type::Matrix),
+            ("mat-add", vec![Type::Matrix, Type::Matrix], Type::Matrix),
+            ("mat-sub", vec![Type::Matrix, Type::Matrix], Type::Matrix),
+            ("mat-emul", vec![Type::Matrix, Type::Matrix], Type::Matrix),
+            ("mat-scale", vec![Type::Matrix, Type::F64], Type::Matrix),
+            ("mat-transpose", vec![Type::Matrix], Type::Matrix),
+            ("mat->list", vec![Type::Matrix], f64_rows.clone()),
+            ("list->mat", vec![f64_rows], Type::Matrix),
+        ];
+        for (name, params, ret) in matrices {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Key-value store
+        let kv = [
+            ("kv-open", vec![Type::String], Type::KvStore),
+            ("kv-get", vec![Type::KvStore, Type::String], Type::String),
+            ("kv-get-or", vec![Type::KvStore, Type::String, Type::String], Type::String),
+            ("kv-has?", vec![Type::KvStore, Type::String], Type::Bool),
+            ("kv-put!", vec![Type::KvStore, Type::String, Type::String], Type::KvStore),
+            ("kv-delete!", vec![Type::KvStore, Type::String], Type::Bool),
+            ("kv-keys", vec![Type::KvStore], Type::List(Box::new(Type::String))),
+            ("kv-close", vec![Type::KvStore], Type::Bool),
+        ];
+        for (name, params, ret) in kv {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        
+        // Date/time. `_` parameters accept either `DateTime` or
+        // `ZonedDateTime`; `time-add` returns whichever it was given.
+        let dt = Type::Inferred;
+        let time = [
+            ("now", vec![], Type::DateTime),
+            ("date", vec![Type::I32, Type::I32, Type::I32], Type::DateTime),
+            ("datetime", vec![Type::I32; 6], Type::DateTime),
+            ("from-unix", vec![Type::Inferred], Type::DateTime),
+            ("unix-seconds", vec![dt.clone()], Type::I64),
+            ("to-zone", vec![dt.clone(), Type::I32], Type::ZonedDateTime),
+            ("to-utc", vec![dt.clone()], Type::DateTime),
+            ("format-time", vec![dt.clone(), Type::String], Type::String),
+            ("parse-time", vec![Type::String, Type::String], Type::DateTime),
+            ("parse-zoned", vec![Type::String, Type::String], Type::ZonedDateTime),
+            ("time-add", vec![dt.clone(), Type::Duration], Type::Inferred),
+            ("time-diff", vec![dt.clone(), dt.clone()], Type::Duration),
+            ("time-before?", vec![dt.clone(), dt.clone()], Type::Bool),
+            ("millis", vec![Type::Inferred], Type::Duration),
+            ("seconds", vec![Type::Inferred], Type::Duration),
+            ("minutes", vec![Type::Inferred], Type::Duration),
+            ("hours", vec![Type::Inferred], Type::Duration),
+            ("days", vec![Type::Inferred], Type::Duration),
+            ("duration-add", vec![Type::Duration, Type::Duration], Type::Duration),
+            ("duration-seconds", vec![Type::Duration], Type::I64),
+            ("duration-millis", vec![Type::Duration], Type::I64),
+        ];
+        for (name, params, ret) in time {
+            types.insert(name.to_string(), Type::Function {
+                params,
+                return_type: Box::new(ret),
+            });
+        }
+        for field in ["year", "month", "day", "hour", "minute", "second", "weekday"] {
+            types.insert(field.to_string(), Type::Function {
+                params: vec![dt.clone()],
+                return_type: Box::new(Type::I32),
+            });
+        }
+        
+        TypeEnv { types, refinements: HashMap::new(), scoped: false, constants: HashSet::new(),
+            recur: None,
+            yields: None,
+            returns: None,
+            blocks: Vec::new(),
+            params: HashSet::from(["*out*".to_string()]),
+            defer_scope: false,
+            deprecated: HashMap::new(),
+            users: HashMap::new(),
+            warnings: Rc::default(),
+            adts: Rc::default(),
+            structs: Rc::default(),
+        }
     }
 
     pub fn get(&self, name: &str) -> Option<&Type> {
@@ -139,9 +620,112 @@ impl TypeEnv {
     }
 
     pub fn insert(&mut self, name: String, ty: Type) {
+        self.deprecated.remove(&name);
         self.types.insert(name, ty);
     }
 
+    /// Forget everything about the global `name`: its type, whether it's
+    /// a constant, a parameter or deprecated, and what it uses.
+    pub fn remove(&mut self, name: &str) {
+        self.types.remove(name);
+        self.refinements.remove(name);
+        self.constants.remove(name);
+        self.params.remove(name);
+        self.deprecated.remove(name);
+        self.users.remove(name);
+        for uses in self.users.values_mut() {
+            uses.remove(name);
+        }
+    }
+
+    /// The warnings raised since the last call, oldest first.
+    pub fn take_warnings(&mut self) -> Vec<Lint> {
+        std::mem::take(&mut *self.warnings.borrow_mut())
+    }
+
+    /// Record `name` as deprecated if its metadata says so:
+    /// `^{:deprecated "use g"}`, or `^{:deprecated true}` for no message.
+    fn deprecate(&mut self, name: &str, meta: &crate::ast::Meta) {
+        for (key, value) in meta {
+            if &*key.name() != "deprecated" {
+                continue;
+            }
+            let message = match value {
+                Expr::Bool(false) | Expr::Nil => continue,
+                Expr::String(s) => Some(s.clone()),
+                _ => None,
+            };
+            self.deprecated.insert(name.to_string(), message);
+        }
+    }
+
+    /// Drop the warnings raised after the first `since` if `meta` has
+    /// `^{:allow :deprecated}`.
+    fn allow_warnings(&mut self, since: usize, meta: &crate::ast::Meta) {
+        let mut warnings = self.warnings.borrow_mut();
+        let mut raised = warnings.split_off(since);
+        raised.retain(|warning| !lint::allows(meta, warning.name));
+        warnings.append(&mut raised);
+    }
+
+    /// Let `name` be defined again, e.g. when the file that made it a
+    /// constant is reloaded.
+    pub fn forget_constant(&mut self, name: &str) {
+        self.constants.remove(name);
+    }
+
+    /// Finish a top-level definition `defined` of `name`, which was bound
+    /// to `previous` before. If definitions that use `name` were checked
+    /// against a type the new one doesn't fit, fail and put `previous`
+    /// back; otherwise note what `defined` uses in turn.
+    fn rebind(&mut self, name: &str, previous: Option<Type>, defined: &Expr) -> Result<(), String> {
+        if self.scoped {
+            return Ok(());
+        }
+        let users = self.users_of(name);
+        if let (Some(old), Some(new)) = (&previous, self.types.get(name))
+            && !users.is_empty()
+            && !compatible(old, new)
+        {
+            let users: Vec<String> = users.iter().map(|u| format!("`{}`", u)).collect();
+            let err = format!(
+                "cannot redefine `{}` as {}: {} {} it as {}",
+                name,
+                resolve(new),
+                users.join(", "),
+                if users.len() == 1 { "uses" } else { "use" },
+                resolve(old)
+            );
+            self.types.insert(name.to_string(), old.clone());
+            return Err(err);
+        }
+        for uses in self.users.values_mut() {
+            uses.remove(name);
+        }
+        let mut mentioned = HashSet::new();
+        crate::tier::symbols(defined, &mut mentioned);
+        mentioned.remove(name);
+        for used in mentioned {
+            self.users.entry(used).or_default().insert(name.to_string());
+        }
+        Ok(())
+    }
+
+    /// The top-level definitions that mention the global `name`, sorted.
+    pub fn users_of(&self, name: &str) -> Vec<String> {
+        let mut users: Vec<String> = self.users.get(name).into_iter().flatten().cloned().collect();
+        users.sort();
+        users
+    }
+
+    /// Fail if `name` is a constant; definitions call this first.
+    fn check_redefinition(&self, name: &str) -> Result<(), String> {
+        if !self.scoped && self.constants.contains(name) {
+            return Err(format!("cannot redefine constant `{}`", name));
+        }
+        Ok(())
+    }
+
     pub fn extend(&self) -> Self {
         // Child scope inherits known types but starts with a fresh
         // refinements map. Each function body is its own refinement
@@ -149,9 +733,60 @@ impl TypeEnv {
         TypeEnv {
             types: self.types.clone(),
             refinements: HashMap::new(),
+            scoped: true,
+            constants: HashSet::new(),
+            recur: self.recur.clone(),
+            yields: self.yields.clone(),
+            returns: self.returns.clone(),
+            blocks: self.blocks.clone(),
+            params: self.params.clone(),
+            defer_scope: self.defer_scope,
+            deprecated: self.deprecated.clone(),
+            warnings: Rc::clone(&self.warnings),
+            adts: Rc::clone(&self.adts),
+            structs: Rc::clone(&self.structs),
+            users: HashMap::new(),
         }
     }
 
+    /// The `deftype` constructor `tag`: the type it makes and its fields.
+    fn constructor(&self, tag: &str) -> Option<(String, Vec<Type>)> {
+        self.adts.borrow().iter().find_map(|(name, variants)| {
+            let (_, fields) = variants.iter().find(|(t, _)| t == tag)?;
+            Some((name.clone(), fields.clone()))
+        })
+    }
+
+    /// The type of `field` in a value of `record_type`, which must be a
+    /// `defstruct` that has it.
+    fn field_type(&self, record_type: &Type, field: &str) -> Result<Type, String> {
+        let name = match record_type {
+            Type::Inferred => return Ok(Type::Inferred),
+            // A record still being inferred is the one struct with `field`.
+            Type::Var(_) => {
+                let structs = self.structs.borrow();
+                let mut having = structs.iter().filter(|(_, labels)| labels.iter().any(|l| l == field));
+                return match (having.next(), having.next()) {
+                    (Some((name, _)), None) => {
+                        let name = name.clone();
+                        drop(structs);
+                        types_match(record_type, &Type::Named(name.clone()));
+                        self.field_type(&Type::Named(name), field)
+                    }
+                    _ => Ok(Type::Inferred),
+                };
+            }
+            Type::Named(name) if self.structs.borrow().contains_key(name) => name,
+            other => return Err(format!("get requires a struct, got {}", other)),
+        };
+        let structs = self.structs.borrow();
+        let Some(i) = structs[name].iter().position(|label| label == field) else {
+            return Err(format!("{} has no field `{}`", name, field));
+        };
+        let (_, types) = self.constructor(name).expect("a struct's constructor is named after it");
+        Ok(types[i].clone())
+    }
+
     /// Bidirectional inference (段階 A): narrow `name`'s type to `ty`.
     ///
     /// - If `name` is unknown, no-op (only call this for known variables).
@@ -167,6 +802,7 @@ impl TypeEnv {
         };
         match current {
             Type::Inferred => {
+                note(|| format!("solved `{}`: _ is {}", name, ty));
                 self.types.insert(name.to_string(), ty.clone());
                 self.refinements.insert(name.to_string(), ty);
                 Ok(())
@@ -174,6 +810,7 @@ impl TypeEnv {
             Type::List(ref inner) if matches!(**inner, Type::Inferred) => {
                 // `List<_>` accepts narrowing to a more concrete list type.
                 if matches!(ty, Type::List(_)) {
+                    note(|| format!("solved `{}`: {} is {}", name, current, ty));
                     self.types.insert(name.to_string(), ty.clone());
                     self.refinements.insert(name.to_string(), ty);
                     Ok(())
@@ -201,25 +838,40 @@ impl TypeEnv {
 }
 
 pub fn type_check(expr: &Expr, env: &mut TypeEnv) -> Result<Type, String> {
+    if !tracing() {
+        return check(expr, env).map(|ty| resolve(&ty));
+    }
+    // The conclusion goes above its premises, which are only known after.
+    let line = trace_open();
+    let result = check(expr, env).map(|ty| resolve(&ty));
+    let outcome = match &result {
+        Ok(ty) => format!("{} : {}", abbreviate(&expr.to_string()), ty),
+        Err(e) => format!("{} : error: {}", abbreviate(&expr.to_string()), e),
+    };
+    trace_close(line, outcome);
+    result
+}
+
+fn check(expr: &Expr, env: &mut TypeEnv) -> Result<Type, String> {
     match expr {
         Expr::Integer32(_) => Ok(Type::I32),
         Expr::Integer64(_) => Ok(Type::I64),
         Expr::Float(_) => Ok(Type::F64),
         Expr::Bool(_) => Ok(Type::Bool),
         Expr::String(_) => Ok(Type::String),
+        Expr::Bytes(_) => Ok(Type::Bytes),
+        Expr::Keyword(_) => Ok(Type::Keyword),
         Expr::Nil => Ok(Type::List(Box::new(Type::Inferred))),
 
         Expr::Symbol(name) => {
             env.get(name)
-                .cloned()
+                .map(instantiate)
                 .ok_or_else(|| format!("Undefined variable: {}", name))
         }
         
         Expr::If { condition, then_branch, else_branch } => {
             let cond_type = type_check(condition, env)?;
-            if cond_type != Type::Bool {
-                return Err(format!("If condition must be bool, got {}", cond_type));
-            }
+            check_condition("If condition", condition, &cond_type, env)?;
             
             let then_type = type_check(then_branch, env)?;
             let else_type = type_check(else_branch, env)?;
@@ -233,7 +885,9 @@ pub fn type_check(expr: &Expr, env: &mut TypeEnv) -> Result<Type, String> {
             }
             
             // Return the more specific type
-            Ok(if then_type == Type::List(Box::new(Type::Inferred)) && else_type != Type::List(Box::new(Type::Inferred)) {
+            Ok(if then_type == Type::Inferred
+                || then_type == Type::List(Box::new(Type::Inferred)) && else_type != Type::List(Box::new(Type::Inferred))
+            {
                 else_type
             } else {
                 then_type
@@ -241,103 +895,410 @@ pub fn type_check(expr: &Expr, env: &mut TypeEnv) -> Result<Type, String> {
         }
         
         Expr::Let { name, type_ann, value, body } => {
-            let value_type = type_check(value, env)?;
+            let ascribed = type_ann.as_ref().and_then(|ann| ascribe_lambda(value, ann));
+            let value_type = type_check(ascribed.as_ref().unwrap_or(value), env)?;
+            if type_ann.as_ref() != Some(&Type::Unit) {
+                require_value(value, &value_type, &format!("`{}`", name))?;
+            }
             
             let binding_type = if let Some(ann) = type_ann {
-                if ann != &value_type && ann != &Type::Inferred {
-                    return Err(format!(
-                        "Type mismatch: expected {}, got {}",
-                        ann, value_type
-                    ));
+                if !fits_annotation(ann, &value_type) {
+                    return Err(format!("Type mismatch: {}", type_mismatch(ann, &value_type)));
                 }
                 ann.clone()
+            } else if let Expr::Lambda { .. } = &**value {
+                generalize(&value_type, env, name)
             } else {
                 value_type
             };
             
-            if let Some(body_expr) = body {
-                // Let-in expression: type check body in new scope
-                let mut new_env = env.extend();
-                new_env.insert(name.clone(), binding_type);
-                type_check(body_expr, &mut new_env)
-            } else {
-                // Simple let: add to current environment
-                env.insert(name.clone(), binding_type.clone());
-                Ok(binding_type)
-            }
+            let mut new_env = env.extend();
+            new_env.insert(name.clone(), binding_type);
+            type_check(body, &mut new_env)
         }
         
-        Expr::Defn { name, params, return_type, body } => {
-            // First, add the function type to the environment for recursion
-            let func_type = Type::Function {
-                params: params.iter().map(|(_, t)| t.clone()).collect(),
-                return_type: Box::new(return_type.clone()),
+        Expr::Def { name, type_ann, value, constant } => {
+            if env.scoped {
+                return Err(format!(
+                    "`{} {}` is only allowed at the top level; use (let {} <value> <body>) for a local",
+                    if *constant { "defconst" } else { "def" },
+                    name,
+                    name
+                ));
+            }
+            env.check_redefinition(name)?;
+            let ascribed = type_ann.as_ref().and_then(|ann| ascribe_lambda(value, ann));
+            let value_type = type_check(ascribed.as_ref().unwrap_or(value), env)?;
+            if type_ann.as_ref() != Some(&Type::Unit) {
+                require_value(value, &value_type, &format!("`{}`", name))?;
+            }
+            let binding_type = match type_ann {
+                Some(ann) if !fits_annotation(ann, &value_type) => {
+                    return Err(format!("Type mismatch: {}", type_mismatch(ann, &value_type)));
+                }
+                Some(ann) => ann.clone(),
+                None if matches!(**value, Expr::Lambda { .. }) => generalize(&value_type, env, name),
+                None => value_type,
             };
-            env.insert(name.clone(), func_type.clone());
+            let previous = env.types.get(name).cloned();
+            env.insert(name.clone(), binding_type.clone());
+            env.rebind(name, previous, value)?;
+            if *constant {
+                env.constants.insert(name.clone());
+            }
+            Ok(binding_type)
+        }
+        
+        Expr::Defparam { name, value } => {
+            if env.scoped {
+                return Err(format!("`defparam {}` is only allowed at the top level", name));
+            }
+            env.check_redefinition(name)?;
+            let value_type = type_check(value, env)?;
+            env.insert(name.clone(), value_type.clone());
+            env.params.insert(name.clone());
+            Ok(value_type)
+        }
 
-            // Now type-check the body with the function in scope
-            let mut new_env = env.extend();
+        Expr::Deftype { name, variants } => {
+            if env.scoped {
+                return Err(format!("`deftype {}` is only allowed at the top level", name));
+            }
+            env.structs.borrow_mut().remove(name);
+            declare_type("deftype", name, variants, env)
+        }
 
-            for (param_name, param_type) in params {
-                new_env.insert(param_name.clone(), param_type.clone());
+        Expr::Defstruct { name, fields } => {
+            if env.scoped {
+                return Err(format!("`defstruct {}` is only allowed at the top level", name));
             }
+            let types = fields.iter().map(|(_, ty)| ty.clone()).collect();
+            declare_type("defstruct", name, &[(name.clone(), types)], env)?;
+            let labels = fields.iter().map(|(field, _)| field.clone()).collect();
+            env.structs.borrow_mut().insert(name.clone(), labels);
+            Ok(Type::Type)
+        }
 
-            let body_type = type_check(body, &mut new_env)?;
+        Expr::Parameterize { bindings, body } => {
+            for (name, value) in bindings {
+                if !env.params.contains(name) {
+                    return Err(format!("parameterize: `{}` is not a parameter; declare it with defparam", name));
+                }
+                let param_type = env.get(name).cloned().unwrap_or(Type::Inferred);
+                let value_type = type_check(value, env)?;
+                if !types_match(&param_type, &value_type) {
+                    return Err(format!(
+                        "parameterize: `{}` is {}, got {}",
+                        name, param_type, value_type
+                    ));
+                }
+            }
+            type_check(body, env)
+        }
 
-            if !types_match(&body_type, return_type) && return_type != &Type::Inferred {
-                return Err(format!(
-                    "Return type mismatch: expected {}, got {}",
-                    return_type, body_type
-                ));
+        Expr::WithOpen { bindings, body } => {
+            let mut scope_env = env.extend();
+            for (name, value) in bindings {
+                let value_type = type_check(value, &mut scope_env)?;
+                if !matches!(value_type, Type::Port | Type::KvStore | Type::Inferred | Type::Var(_)) {
+                    return Err(format!(
+                        "with-open: `{}` is {}, which has nothing to close",
+                        name, value_type
+                    ));
+                }
+                scope_env.insert(name.clone(), value_type);
+            }
+            scope_env.defer_scope = true;
+            type_check(body, &mut scope_env)
+        }
+
+        Expr::Defer(deferred) => {
+            if !env.defer_scope {
+                return Err("defer is only allowed inside a function body or with-open".to_string());
             }
+            type_check(deferred, env)?;
+            Ok(Type::List(Box::new(Type::Inferred)))
+        }
+
+        Expr::Defn { name, params, return_type, body, meta, .. } => {
+            let previous = env.types.get(name).cloned();
+            let ty = check_defn(name, params, return_type, body, meta, &[], env)?;
+            env.rebind(name, previous, expr)?;
+            Ok(ty)
+        }
 
-            // Bidirectional inference (段階 A): if any `_` parameters were
-            // narrowed during body type-checking, reflect them in the
-            // signature so external callers see the precise type.
-            let refined_params: Vec<Type> = params
+        // Each arity's body can call the others: those checked so far as
+        // they came out, the rest as declared. One that called a later
+        // arity before its return type was known is checked again.
+        Expr::MultiArity(defns) => {
+            let mut arities: Vec<Type> = defns
                 .iter()
-                .map(|(pname, ptype)| {
-                    new_env
-                        .refinements
-                        .get(pname)
-                        .cloned()
-                        .unwrap_or_else(|| ptype.clone())
+                .map(|d| match d {
+                    Expr::Defn { params, return_type, .. } => Ok(Type::Function {
+                        params: params.iter().map(|(_, t)| t.clone()).collect(),
+                        return_type: Box::new(return_type.clone()),
+                    }),
+                    _ => Err("internal: a multi-arity defn holds something other than defns".to_string()),
                 })
+                .collect::<Result<_, _>>()?;
+            let name = crate::modules::defined_names(expr).first().copied().unwrap_or_default();
+            let previous = env.types.get(name).cloned();
+            for pass in 0..2 {
+                for (i, defn) in defns.iter().enumerate() {
+                    let Expr::Defn { name, params, return_type, body, meta, .. } = defn else { continue };
+                    let unknown = matches!(&arities[i], Type::Function { return_type, .. }
+                        if is_unknown(return_type) || matches!(**return_type, Type::Generic(_)));
+                    if pass == 1 && !unknown {
+                        continue;
+                    }
+                    let siblings: Vec<Type> =
+                        arities.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, t)| t.clone()).collect();
+                    arities[i] = check_defn(name, params, return_type, body, meta, &siblings, env)?;
+                }
+            }
+            env.rebind(name, previous, expr)?;
+            Ok(by_arity(arities))
+        }
+        
+        Expr::Defgen { name, params, body, meta, .. } => {
+            env.check_redefinition(name)?;
+            let previous = env.types.get(name).cloned();
+            let warned = env.warnings.borrow().len();
+            let params: Vec<(String, Type)> = params.iter().map(|(p, t)| (p.clone(), unknowns(t))).collect();
+            let param_types: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
+            env.insert(name.clone(), Type::Function {
+                params: param_types.clone(),
+                return_type: Box::new(Type::Gen(Box::new(Type::Inferred))),
+            });
+
+            let mut new_env = env.extend();
+            for (param_name, param_type) in &params {
+                new_env.insert(param_name.clone(), param_type.clone());
+            }
+            let yields = Rc::new(RefCell::new(Type::Inferred));
+            new_env.recur = Some(param_types);
+            new_env.yields = Some(Rc::clone(&yields));
+            new_env.returns = None;
+            new_env.blocks = Vec::new();
+            // A generator can stop for good at any `yield`; nothing would
+            // run its defers.
+            new_env.defer_scope = false;
+
+            type_check(body, &mut new_env)?;
+            check_recur_tail(body, true)?;
+            check_yield_position(body, true)?;
+
+            let refined_params = params
+                .iter()
+                .map(|(pname, ptype)| new_env.refinements.get(pname).cloned().unwrap_or_else(|| ptype.clone()))
                 .collect();
-            let refined_func_type = Type::Function {
+            let func_type = Type::Function {
                 params: refined_params,
-                return_type: Box::new(return_type.clone()),
+                return_type: Box::new(Type::Gen(Box::new(yields.borrow().clone()))),
             };
-            env.insert(name.clone(), refined_func_type.clone());
+            let func_type = generalize(&func_type, env, name);
+            env.insert(name.clone(), func_type.clone());
+            env.rebind(name, previous, expr)?;
+            env.allow_warnings(warned, meta);
+            env.deprecate(name, meta);
+            Ok(func_type)
+        }
 
-            Ok(refined_func_type)
+        Expr::Do(exprs) => {
+            let mut last = Type::Inferred;
+            for expr in exprs {
+                last = type_check(expr, env)?;
+            }
+            Ok(last)
         }
-        
+
+        // Like `recur`, a `yield` produces nothing where it stands;
+        // `check_yield_position` keeps it out of places that want a value.
+        Expr::Yield(value) => {
+            let Some(yields) = env.yields.clone() else {
+                return Err("yield is only allowed inside defgen".to_string());
+            };
+            let value_type = type_check(value, env)?;
+            let mut expected = yields.borrow_mut();
+            if *expected == Type::Inferred {
+                *expected = value_type;
+            } else if !types_match(&expected, &value_type) {
+                return Err(format!("yield: the generator yields {}, got {}", expected, value_type));
+            }
+            Ok(Type::Inferred)
+        }
+
+        // Control leaves the function, so there's no value here either.
+        Expr::Return(value) => {
+            let Some(returns) = env.returns.clone() else {
+                return Err("return is only allowed inside defn or fn".to_string());
+            };
+            let value_type = type_check(value, env)?;
+            let mut expected = returns.borrow_mut();
+            if *expected == Type::Inferred {
+                *expected = value_type;
+            } else if !types_match(&expected, &value_type) {
+                return Err(format!("return: the function returns {}, got {}", resolve(&expected), value_type));
+            }
+            Ok(Type::Inferred)
+        }
+
+        // `(the T e)` says what `e` is: an unknown `e` learns `T`, an
+        // `i32` may widen to `i64`, anything else has to match already.
+        Expr::The { ty, expr } => {
+            let ascribed = ascribe_lambda(expr, ty);
+            let value_type = type_check(ascribed.as_ref().unwrap_or(expr), env)?;
+            if value_type == Type::Inferred {
+                if let Expr::Symbol(name) = expr.as_ref() {
+                    env.refine(name, ty.clone())?;
+                }
+            } else if !(matches!((ty, &value_type), (Type::I64, Type::I32)) || types_match(ty, &value_type)) {
+                return Err(format!("Type mismatch: {}", type_mismatch(ty, &value_type)));
+            }
+            Ok(if *ty == Type::Inferred { value_type } else { ty.clone() })
+        }
+
+        Expr::Block { label, body } => {
+            let gives = Rc::new(RefCell::new(Type::Inferred));
+            env.blocks.push((*label, Rc::clone(&gives)));
+            let body_type = type_check(body, env);
+            env.blocks.pop();
+            let body_type = body_type?;
+            let gives = gives.borrow().clone();
+            if gives == Type::Inferred {
+                Ok(body_type)
+            } else if body_type == Type::Inferred {
+                Ok(gives)
+            } else if types_match(&body_type, &gives) {
+                Ok(body_type)
+            } else {
+                Err(format!("block {}: the body gives {}, but return-from gives {}", label, body_type, gives))
+            }
+        }
+
+        Expr::ReturnFrom { label, value } => {
+            let Some((_, gives)) = env.blocks.iter().rev().find(|(l, _)| l == label).cloned() else {
+                return Err(format!("return-from: no enclosing block {}", label));
+            };
+            let value_type = type_check(value, env)?;
+            let mut expected = gives.borrow_mut();
+            if *expected == Type::Inferred {
+                *expected = value_type;
+            } else if !types_match(&expected, &value_type) {
+                return Err(format!("return-from {}: the block gives {}, got {}", label, expected, value_type));
+            }
+            Ok(Type::Inferred)
+        }
+
+        // What a `reset` gives depends on the `shift`s run under it,
+        // which may be in any function it calls, so it isn't tracked.
+        Expr::Reset(body) => {
+            type_check(body, env)?;
+            Ok(Type::Inferred)
+        }
+
+        // Nor is what `k` takes or gives.
+        Expr::Shift { name, body } => {
+            let mut new_env = env.extend();
+            let k = Type::Function { params: vec![Type::Inferred], return_type: Box::new(Type::Inferred) };
+            new_env.insert(name.clone(), k);
+            type_check(body, &mut new_env)?;
+            Ok(Type::Inferred)
+        }
+
+        // The reader has set it up already; what's left is to check that
+        // the forms it expands to will type-check as calls.
+        Expr::SetReaderMacro { handler, .. } => match env.get(handler) {
+            Some(Type::Function { params, .. }) if params.len() == 1 && types_match(&params[0], &Type::String) => {
+                Ok(Type::List(Box::new(Type::Inferred)))
+            }
+            Some(other) => Err(format!("set-reader-macro!: `{}` must be fn(String) -> _, got {}", handler, other)),
+            None => Err(format!("set-reader-macro!: `{}` is not defined", handler)),
+        },
+
         Expr::Lambda { params, return_type, body } => {
             let mut new_env = env.extend();
+            let params: Vec<(String, Type)> = params.iter().map(|(n, t)| (n.clone(), unknowns(t))).collect();
             
-            for (param_name, param_type) in params {
+            for (param_name, param_type) in &params {
                 new_env.insert(param_name.clone(), param_type.clone());
             }
+            new_env.recur = Some(params.iter().map(|(_, t)| t.clone()).collect());
+            new_env.yields = None;
+            let returns = Rc::new(RefCell::new(unknowns(return_type.as_ref().unwrap_or(&Type::Inferred))));
+            new_env.returns = Some(Rc::clone(&returns));
+            new_env.blocks = Vec::new();
+            new_env.defer_scope = true;
             
-            let body_type = type_check(body, &mut new_env)?;
+            let ascribed = return_type.as_ref().and_then(|rt| ascribe_lambda(body, rt));
+            let body_type = type_check(ascribed.as_ref().unwrap_or(body), &mut new_env)?;
+            check_recur_tail(body, true)?;
             
-            if let Some(rt) = return_type
-                && &body_type != rt
-                && rt != &Type::Inferred
-            {
+            let rt = returns.borrow().clone();
+            if !types_match(&body_type, &rt) && rt != Type::Inferred {
                 return Err(format!(
                     "Lambda return type mismatch: expected {}, got {}",
-                    rt, body_type
+                    resolve(&rt), body_type
                 ));
             }
+            settle(&rt, &body_type);
             
             Ok(Type::Function {
-                params: params.iter().map(|(_, t)| t.clone()).collect(),
-                return_type: Box::new(body_type),
+                // `_` parameters as the body narrowed them, as for `defn`.
+                params: params
+                    .iter()
+                    .map(|(pname, ptype)| new_env.refinements.get(pname).cloned().unwrap_or_else(|| ptype.clone()))
+                    .collect(),
+                // A body that always `return`s has no type of its own.
+                return_type: Box::new(if body_type == Type::Inferred { rt } else { body_type }),
             })
         }
         
+        Expr::Loop { bindings, body } => {
+            let mut loop_env = env.extend();
+            let mut binding_types = Vec::with_capacity(bindings.len());
+            for (name, type_ann, value) in bindings {
+                let value_type = type_check(value, &mut loop_env)?;
+                let binding_type = match type_ann {
+                    Some(ann) if !types_match(ann, &value_type) => {
+                        return Err(format!("Type mismatch: {}", type_mismatch(ann, &value_type)));
+                    }
+                    Some(ann) if ann != &Type::Inferred => ann.clone(),
+                    _ => value_type,
+                };
+                loop_env.insert(name.clone(), binding_type.clone());
+                binding_types.push(binding_type);
+            }
+            loop_env.recur = Some(binding_types);
+            let body_type = type_check(body, &mut loop_env)?;
+            check_recur_tail(body, true)?;
+            Ok(body_type)
+        }
+
+        // Checked against what it jumps back to. It never produces a value
+        // where it stands, so it fits any type; `check_recur_tail` makes
+        // sure it only appears where that's true.
+        Expr::Recur(args) => {
+            let Some(targets) = env.recur.clone() else {
+                return Err("recur is only allowed inside a loop, defn or fn".to_string());
+            };
+            if args.len() != targets.len() {
+                return Err(format!("recur expects {} arguments, got {}", targets.len(), args.len()));
+            }
+            for (i, (arg, target)) in args.iter().zip(&targets).enumerate() {
+                let arg_type = type_check(arg, env)?;
+                if !types_match(target, &arg_type) {
+                    return Err(format!(
+                        "recur argument {} must be {}, got {}",
+                        i + 1,
+                        target,
+                        arg_type
+                    ));
+                }
+            }
+            Ok(Type::Inferred)
+        }
+
         Expr::Match { scrutinee, arms } => {
             let mut scrutinee_type = type_check(scrutinee, env)?;
 
@@ -352,6 +1313,24 @@ pub fn type_check(expr: &Expr, env: &mut TypeEnv) -> Result<Type, String> {
                 env.refine(sym, Type::List(Box::new(Type::Inferred)))?;
                 scrutinee_type = Type::List(Box::new(Type::Inferred));
             }
+            // Likewise, a constructor pattern says which `deftype` it is.
+            if matches!(scrutinee_type, Type::Inferred)
+                && let Some(adt) = arms.iter().find_map(|(p, _)| constructed_type(p, env))
+            {
+                if let Expr::Symbol(sym) = &**scrutinee {
+                    env.refine(sym, adt.clone())?;
+                }
+                scrutinee_type = adt;
+            }
+            // A scrutinee still being inferred takes its shape from the arms.
+            if let Type::Var(_) = scrutinee_type {
+                if arms.iter().any(|(p, _)| is_list_shaped(p)) {
+                    types_match(&scrutinee_type, &Type::List(Box::new(fresh())));
+                } else if let Some(adt) = arms.iter().find_map(|(p, _)| constructed_type(p, env)) {
+                    types_match(&scrutinee_type, &adt);
+                }
+                scrutinee_type = resolve(&scrutinee_type);
+            }
 
             // Validate each arm. Bindings introduced by the pattern are
             // visible only in that arm's body — we clone the env so
@@ -359,11 +1338,12 @@ pub fn type_check(expr: &Expr, env: &mut TypeEnv) -> Result<Type, String> {
             let mut result_type: Option<Type> = None;
             for (pat, body) in arms {
                 check_pattern(pat, &scrutinee_type, env)?;
+                let scrutinee_type = resolve(&scrutinee_type);
                 let mut arm_env = env.extend();
                 bind_pattern(pat, &scrutinee_type, &mut arm_env);
                 let body_type = type_check(body, &mut arm_env)?;
                 match &result_type {
-                    None => result_type = Some(body_type),
+                    None | Some(Type::Inferred) => result_type = Some(body_type),
                     Some(expected) => {
                         if !types_match(expected, &body_type) {
                             return Err(format!(
@@ -379,323 +1359,754 @@ pub fn type_check(expr: &Expr, env: &mut TypeEnv) -> Result<Type, String> {
             // type errors take precedence over a less-specific exhaustiveness
             // message.
             let arm_pats: Vec<&Pattern> = arms.iter().map(|(p, _)| p).collect();
-            crate::exhaustiveness::check(&scrutinee_type, &arm_pats)?;
+            let scrutinee_type = resolve(&scrutinee_type);
+            crate::exhaustiveness::check(&scrutinee_type, &arm_pats, &env.adts.borrow())?;
 
             // Parser guarantees at least one arm, but be defensive.
             result_type.ok_or_else(|| "match has no arms".to_string())
         }
 
-        Expr::Call { func, args } => {
-            let func_type = type_check(func, env)?;
-            
-            match func_type {
-                Type::Function { params, return_type } => {
-                    if args.len() != params.len() {
-                        return Err(format!(
-                            "Wrong number of arguments: expected {}, got {}",
-                            params.len(), args.len()
-                        ));
-                    }
-                    
-                    let mut actual_return_type = *return_type.clone();
+        Expr::Call { func, args } => check_call(func, args, env),
 
-                    for (i, (arg, param_type)) in args.iter().zip(params.iter()).enumerate() {
-                        let arg_type = type_check(arg, env)?;
-                        // Check type compatibility
-                        if !types_match(param_type, &arg_type) {
-                            return Err(format!(
-                                "Type mismatch in argument: expected {}, got {}",
-                                param_type, arg_type
-                            ));
-                        }
-                        // Bidirectional inference (段階 A): if the parameter
-                        // expects a list (any list, possibly `List<_>`) and
-                        // the argument is an `Inferred` symbol, narrow that
-                        // symbol to `List<_>` so subsequent uses see a list.
-                        if matches!(arg_type, Type::Inferred)
-                            && matches!(param_type, Type::List(_))
-                            && let Expr::Symbol(sym) = arg
-                        {
-                            env.refine(sym, Type::List(Box::new(Type::Inferred)))?;
-                        }
-                        // Special handling for list operations
-                        if let Expr::Symbol(fname) = &**func {
-                            match fname.as_str() {
-                                "car" => {
-                                    // car returns the element type of the list
-                                    if let Type::List(elem_type) = &arg_type {
-                                        actual_return_type = *elem_type.clone();
-                                    }
-                                }
-                                "cons" => {
-                                    // cons returns a list of the first argument's type
-                                    if i == 0 {
-                                        actual_return_type = Type::List(Box::new(arg_type.clone()));
-                                    }
-                                }
-                                "cdr" | "append" => {
-                                    // cdr/append preserve the list type of their argument
-                                    if let Type::List(_) = &arg_type {
-                                        actual_return_type = arg_type.clone();
-                                    }
-                                }
-                                "nth" => {
-                                    // nth returns the element type of the list (second arg)
-                                    if i == 1
-                                        && let Type::List(elem_type) = &arg_type
-                                    {
-                                        actual_return_type = *elem_type.clone();
-                                    }
-                                }
-                                _ => {
-                                    // If the function returns Inferred, return the actual argument type
-                                    if *return_type == Type::Inferred {
-                                        actual_return_type = arg_type;
-                                    }
-                                }
-                            }
-                        } else if *return_type == Type::Inferred {
-                            actual_return_type = arg_type;
-                        }
-                    }
-                    
-                    Ok(actual_return_type)
+        Expr::List(exprs) => check_list(exprs, env),
+    }
+}
+
+/// `(f args...)`, apart from the special forms `check_list` takes.
+fn check_call(func: &Expr, args: &[Expr], env: &mut TypeEnv) -> Result<Type, String> {
+    let func_type = type_check(func, env)?;
+    if let Expr::Symbol(fname) = func
+        && let Some(note) = env.deprecated.get(fname)
+    {
+        let message = match note {
+            Some(note) => format!("`{}` is deprecated: {}", fname, note),
+            None => format!("`{}` is deprecated", fname),
+        };
+        env.warnings.borrow_mut().push(Lint { name: lint::DEPRECATED, message, site: Some(Site::call(func)) });
+    }
+
+    // A multi-arity function is called as its arity that fits.
+    let func_type = match func_type {
+        Type::MultiArity(arities) => {
+            let fits = |t: &&Type| matches!(t, Type::Function { params, .. } if params.len() == args.len());
+            match arities.iter().find(fits) {
+                Some(arity) => arity.clone(),
+                None => {
+                    let counts: Vec<String> = arities
+                        .iter()
+                        .map(|t| match t {
+                            Type::Function { params, .. } => params.len().to_string(),
+                            _ => "?".to_string(),
+                        })
+                        .collect();
+                    return Err(format!(
+                        "Wrong number of arguments: expected {}, got {}",
+                        counts.join(" or "),
+                        args.len()
+                    ));
                 }
-                _ => Err(format!("Cannot call non-function type: {}", func_type)),
             }
         }
-        
-        Expr::List(exprs) => {
-            if exprs.is_empty() {
-                return Err("Empty list".to_string());
+        other => other,
+    };
+
+    // `(+ 1 2 3)`: a variadic builtin takes as many more of its
+    // second parameter as it's given.
+    let func_type = match func_type {
+        Type::Function { mut params, return_type }
+            if params.len() == 2
+                && args.len() > 2
+                && let Expr::Symbol(fname) = func
+                && crate::env::VARIADIC.contains(&fname.as_str())
+                && lint::builtin_type(fname).is_some_and(|builtin| {
+                    builtin == Type::Function { params: params.clone(), return_type: return_type.clone() }
+                }) =>
+        {
+            params.resize(args.len(), params[1].clone());
+            Type::Function { params, return_type }
+        }
+        other => other,
+    };
+
+    // An unannotated name that gets called is a function of this
+    // many arguments; what it takes and gives is left open.
+    let func_type = if func_type == Type::Inferred
+        && let Expr::Symbol(name) = func
+    {
+        let called = Type::Function {
+            params: vec![Type::Inferred; args.len()],
+            return_type: Box::new(Type::Inferred),
+        };
+        env.refine(name, called.clone())?;
+        called
+    } else if let Type::Var(_) = func_type {
+        let called = Type::Function {
+            params: args.iter().map(|_| fresh()).collect(),
+            return_type: Box::new(fresh()),
+        };
+        types_match(&func_type, &called);
+        called
+    } else {
+        func_type
+    };
+
+    match func_type {
+        Type::Function { params, return_type } => {
+            if args.len() != params.len() {
+                return Err(format!(
+                    "Wrong number of arguments: expected {}, got {}",
+                    params.len(), args.len()
+                ));
             }
-            
-            if let Expr::Symbol(op) = &exprs[0] {
-                match op.as_str() {
-                    "if" => {
-                        if exprs.len() != 4 {
-                            return Err("If requires 3 arguments".to_string());
+
+            let mut actual_return_type = *return_type.clone();
+            let mut arg_types: Vec<Type> = Vec::with_capacity(args.len());
+            let head = match func {
+                Expr::Symbol(fname) => builtin_name(fname, env),
+                _ => "",
+            };
+
+            for (i, (arg, param_type)) in args.iter().zip(params.iter()).enumerate() {
+                // A `fn` passed where a function type is expected
+                // is checked against it, parameters included.
+                let ascribed = ascribe_lambda(arg, param_type);
+                let arg_type = type_check(ascribed.as_ref().unwrap_or(arg), env)?;
+                if *param_type != Type::Unit {
+                    require_value(arg, &arg_type, "an argument")?;
+                }
+                arg_types.push(arg_type.clone());
+                // `car`/`cdr` take a pair where they take a list,
+                // and `cons` onto a non-list tail makes one.
+                let dotted = matches!(
+                    (head, i, &arg_type),
+                    ("car" | "cdr", 0, Type::Pair(..)) | ("cons", 1, _)
+                );
+                // Check type compatibility
+                if !dotted && !types_match(param_type, &arg_type) {
+                    return Err(format!(
+                        "Type mismatch in argument: {}",
+                        type_mismatch(param_type, &arg_type)
+                    ));
+                }
+                // Bidirectional inference (段階 A): if the parameter
+                // expects a list (any list, possibly `List<_>`) and
+                // the argument is an `Inferred` symbol, narrow that
+                // symbol to `List<_>` so subsequent uses see a list.
+                if matches!(arg_type, Type::Inferred)
+                    && matches!(param_type, Type::List(_))
+                    && let Expr::Symbol(sym) = arg
+                {
+                    env.refine(sym, Type::List(Box::new(Type::Inferred)))?;
+                }
+                // Special handling for list operations
+                if let Expr::Symbol(fname) = func {
+                    match head {
+                        "car" => {
+                            // car returns the element type of the list
+                            match &arg_type {
+                                Type::List(elem_type) | Type::Pair(elem_type, _) => {
+                                    actual_return_type = *elem_type.clone();
+                                }
+                                _ => {}
+                            }
                         }
-                        type_check(&Expr::If {
-                            condition: Box::new(exprs[1].clone()),
-                            then_branch: Box::new(exprs[2].clone()),
-                            else_branch: Box::new(exprs[3].clone()),
-                        }, env)
-                    }
-                    "list" => {
-                        // Empty list: (list) -> List<_>
-                        if exprs.len() == 1 {
-                            return Ok(Type::List(Box::new(Type::Inferred)));
+                        "cons" => {
+                            // cons returns a list of the first argument's type
+                            if i == 0 {
+                                actual_return_type = Type::List(Box::new(arg_type.clone()));
+                            } else if !matches!(arg_type, Type::List(_) | Type::Inferred) {
+                                actual_return_type =
+                                    Type::Pair(Box::new(arg_types[0].clone()), Box::new(arg_type.clone()));
+                            }
                         }
-
-                        // Require all elements to share a type with the first.
-                        // types_match allows Inferred on either side so
-                        // `(list 1 nil)` style mixing with unresolved types
-                        // still works where appropriate.
-                        let first_type = type_check(&exprs[1], env)?;
-                        for (offset, elem) in exprs.iter().enumerate().skip(2) {
-                            let elem_type = type_check(elem, env)?;
-                            if !types_match(&first_type, &elem_type) {
+                        "cdr" if matches!(arg_type, Type::Pair(..)) => {
+                            if let Type::Pair(_, tail) = &arg_type {
+                                actual_return_type = *tail.clone();
+                            }
+                        }
+                        "cdr" | "append" => {
+                            // cdr/append preserve the list type of their argument
+                            if let Type::List(_) = &arg_type {
+                                actual_return_type = arg_type.clone();
+                            }
+                        }
+                        "ref" => {
+                            actual_return_type = Type::Ref(Box::new(arg_type.clone()));
+                        }
+                        "deref" => {
+                            if let Type::Ref(elem_type) = &arg_type {
+                                actual_return_type = *elem_type.clone();
+                            }
+                        }
+                        "set-ref!" => {
+                            // The new value must fit the cell.
+                            if i == 1
+                                && let Type::Ref(elem_type) = &arg_types[0]
+                                && !types_match(elem_type, &arg_type)
+                            {
                                 return Err(format!(
-                                    "List element type mismatch at position {}: expected {}, got {}",
-                                    offset - 1,
-                                    first_type,
-                                    elem_type
+                                    "set-ref!: cell holds {}, got {}",
+                                    elem_type, arg_type
                                 ));
                             }
                         }
-                        Ok(Type::List(Box::new(first_type)))
-                    }
-                    "map" => {
-                        // (map f lst) : List<B> where f : A -> B and lst : List<A>
-                        if exprs.len() != 3 {
-                            return Err("map requires 2 arguments: (map f lst)".to_string());
+                        "+" | "-" | "*" | "/" | "min" | "max" | "abs" | "neg" | "clamp"
+                            if i + 1 == args.len() =>
+                        {
+                            actual_return_type = numeric_join(fname, &arg_types)?;
                         }
-                        let f_type = type_check(&exprs[1], env)?;
-                        let lst_type = type_check(&exprs[2], env)?;
-                        let elem_type = expect_list_elem(&lst_type, "map")?;
-                        let (param_types, ret_type) = expect_function(&f_type, "map")?;
-                        if param_types.len() != 1 {
-                            return Err(format!(
-                                "map requires a unary function, got arity {}",
-                                param_types.len()
-                            ));
+                        // Ordering compares numbers of one kind; `=` also
+                        // takes keywords.
+                        "<" | ">" | "<=" | ">=" if i + 1 == args.len() => {
+                            numeric_join(fname, &arg_types)?;
                         }
-                        if !types_match(&param_types[0], &elem_type) {
-                            return Err(format!(
-                                "map function parameter type {} does not match list element type {}",
-                                param_types[0], elem_type
-                            ));
+                        "=" if i + 1 == args.len() && !arg_types.iter().any(|t| shallow(t) == Type::Keyword) => {
+                            numeric_join(fname, &arg_types)?;
                         }
-                        // Bidirectional inference (段階 A): narrow the source
-                        // variable when the list type is still Inferred but
-                        // the lambda's parameter type is concrete.
-                        if matches!(lst_type, Type::Inferred)
-                            && let Expr::Symbol(sym) = &exprs[2]
-                            && !matches!(param_types[0], Type::Inferred)
-                        {
-                            env.refine(sym, Type::List(Box::new(param_types[0].clone())))?;
+                        // time-add keeps the datetime's zoning
+                        "time-add" if i == 0 => {
+                            actual_return_type = arg_type.clone();
                         }
-                        // If the function's return type is unresolved, the list
-                        // element type is the best guess we have.
-                        let result_elem = if ret_type == Type::Inferred {
-                            elem_type
-                        } else {
-                            ret_type
-                        };
-                        Ok(Type::List(Box::new(result_elem)))
-                    }
-                    "filter" => {
-                        // (filter pred lst) : List<A> where pred : A -> bool
-                        if exprs.len() != 3 {
-                            return Err(
-                                "filter requires 2 arguments: (filter pred lst)".to_string()
-                            );
+                        "array-len" | "array-get" | "array-set!" | "array->list" | "array->vec" | "slice" if i == 0 => {
+                            let elem_type = match &arg_type {
+                                Type::Array(elem_type, _) | Type::Slice(elem_type) => *elem_type.clone(),
+                                // `slice` also views a vector.
+                                Type::Vector(elem_type) if head == "slice" => *elem_type.clone(),
+                                Type::Inferred | Type::Var(_) => Type::Inferred,
+                                _ => return Err(format!("{} expects an array, got {}", head, resolve(&arg_type))),
+                            };
+                            match head {
+                                "array-get" => actual_return_type = elem_type,
+                                "array-set!" => actual_return_type = arg_type.clone(),
+                                "array->list" => actual_return_type = Type::List(Box::new(elem_type)),
+                                "slice" => actual_return_type = Type::Slice(Box::new(elem_type)),
+                                "array->vec" => actual_return_type = Type::Vector(Box::new(elem_type)),
+                                _ => {}
+                            }
                         }
-                        let pred_type = type_check(&exprs[1], env)?;
-                        let lst_type = type_check(&exprs[2], env)?;
-                        let elem_type = expect_list_elem(&lst_type, "filter")?;
-                        let (param_types, ret_type) = expect_function(&pred_type, "filter")?;
-                        if param_types.len() != 1 {
-                            return Err(format!(
-                                "filter requires a unary predicate, got arity {}",
-                                param_types.len()
-                            ));
+                        // A literal index is checked against the
+                        // length in the array's type.
+                        "array-get" | "array-set!" if i == 1 => {
+                            if let (Expr::Integer32(index), Type::Array(_, len)) = (arg, &arg_types[0])
+                                && (*index < 0 || *index as usize >= *len)
+                            {
+                                return Err(format!(
+                                    "{}: index {} out of bounds for {}",
+                                    head, index, arg_types[0]
+                                ));
+                            }
                         }
-                        if !types_match(&param_types[0], &elem_type) {
-                            return Err(format!(
-                                "filter predicate parameter type {} does not match list element type {}",
-                                param_types[0], elem_type
-                            ));
+                        "slice" if i == 2 => {
+                            if let (Expr::Integer32(from), Expr::Integer32(to), Type::Array(_, len)) =
+                                (&args[1], arg, &arg_types[0])
+                                && (*from < 0 || to < from || *to as usize > *len)
+                            {
+                                return Err(format!(
+                                    "slice: range {}..{} out of bounds for {}",
+                                    from, to, arg_types[0]
+                                ));
+                            }
                         }
-                        if !types_match(&ret_type, &Type::Bool) {
-                            return Err(format!(
-                                "filter predicate must return bool, got {}",
-                                ret_type
-                            ));
+                        "array-set!" if i == 2 => {
+                            if let Type::Array(elem_type, _) | Type::Slice(elem_type) = &arg_types[0]
+                                && !types_match(elem_type, &arg_type)
+                            {
+                                return Err(format!(
+                                    "array-set!: array holds {}, got {}",
+                                    elem_type, resolve(&arg_type)
+                                ));
+                            }
                         }
-                        // Bidirectional inference (段階 A): narrow the source
-                        // variable from the predicate's parameter type.
-                        if matches!(lst_type, Type::Inferred)
-                            && let Expr::Symbol(sym) = &exprs[2]
-                            && !matches!(param_types[0], Type::Inferred)
-                        {
-                            env.refine(sym, Type::List(Box::new(param_types[0].clone())))?;
+                        "list->array" | "vec->array" if i == 1 => {
+                            let Expr::Integer32(len @ 0..) = arg else {
+                                return Err(format!("{} takes a literal length: ({} xs 4)", head, head));
+                            };
+                            let elem_type = match &arg_types[0] {
+                                Type::List(elem_type) | Type::Vector(elem_type) => *elem_type.clone(),
+                                _ => Type::Inferred,
+                            };
+                            actual_return_type = Type::Array(Box::new(elem_type), *len as usize);
                         }
-                        let result_elem = if matches!(elem_type, Type::Inferred)
-                            && !matches!(param_types[0], Type::Inferred)
-                        {
-                            param_types[0].clone()
-                        } else {
-                            elem_type
-                        };
-                        Ok(Type::List(Box::new(result_elem)))
-                    }
-                    "fold" => {
-                        // (fold f init lst) : B where f : B -> A -> B, init : B, lst : List<A>
-                        if exprs.len() != 4 {
-                            return Err(
-                                "fold requires 3 arguments: (fold f init lst)".to_string()
-                            );
+                        "get" if i == 0 => {
+                            if let Type::Map(_, value_type) = shallow(&arg_type) {
+                                actual_return_type = *value_type;
+                            }
                         }
-                        let f_type = type_check(&exprs[1], env)?;
-                        let init_type = type_check(&exprs[2], env)?;
-                        let lst_type = type_check(&exprs[3], env)?;
-                        let elem_type = expect_list_elem(&lst_type, "fold")?;
-                        let (param_types, ret_type) = expect_function(&f_type, "fold")?;
-                        if param_types.len() != 2 {
-                            return Err(format!(
-                                "fold requires a binary function, got arity {}",
-                                param_types.len()
-                            ));
+                        "get" if i == 1 => match shallow(&arg_types[0]) {
+                            Type::Map(key_type, _) => check_map_key("get", &key_type, &arg_type)?,
+                            _ if !matches!(arg_type, Type::Keyword | Type::Inferred | Type::Var(_)) => {
+                                return Err("get requires a field keyword, e.g. (get p :x)".to_string());
+                            }
+                            _ => {}
+                        },
+                        "assoc" | "dissoc" | "contains?" | "keys" | "vals" if i == 0 => {
+                            let (key_type, value_type) = expect_map(&arg_type, head)?;
+                            match head {
+                                "assoc" | "dissoc" => actual_return_type = arg_type.clone(),
+                                "keys" => actual_return_type = Type::List(Box::new(key_type)),
+                                "vals" => actual_return_type = Type::List(Box::new(value_type)),
+                                _ => {}
+                            }
                         }
-                        if !types_match(&param_types[0], &init_type) {
-                            return Err(format!(
-                                "fold accumulator type {} does not match init type {}",
-                                param_types[0], init_type
-                            ));
+                        "assoc" | "dissoc" | "contains?" if i == 1 => {
+                            let (key_type, _) = expect_map(&arg_types[0], head)?;
+                            check_map_key(head, &key_type, &arg_type)?;
                         }
-                        if !types_match(&param_types[1], &elem_type) {
-                            return Err(format!(
-                                "fold element parameter type {} does not match list element type {}",
-                                param_types[1], elem_type
-                            ));
+                        // The value has to fit, and fills in an
+                        // empty map's types.
+                        "assoc" if i == 2 => {
+                            let (key_type, value_type) = expect_map(&arg_types[0], head)?;
+                            if !types_match(&value_type, &arg_type) {
+                                return Err(format!(
+                                    "assoc: map holds {}, got {}",
+                                    resolve(&value_type), resolve(&arg_type)
+                                ));
+                            }
+                            if key_type == Type::Inferred || value_type == Type::Inferred {
+                                actual_return_type =
+                                    Type::Map(Box::new(arg_types[1].clone()), Box::new(arg_type.clone()));
+                            }
                         }
-                        if !types_match(&ret_type, &init_type) {
-                            return Err(format!(
-                                "fold return type {} does not match accumulator type {}",
-                                ret_type, init_type
-                            ));
+                        "vec-get" if i == 0 => {
+                            if let Type::Vector(elem_type) = &arg_type {
+                                actual_return_type = *elem_type.clone();
+                            }
                         }
-                        // Bidirectional inference (段階 A): narrow the source
-                        // variable from the lambda's element-parameter type.
-                        if matches!(lst_type, Type::Inferred)
-                            && let Expr::Symbol(sym) = &exprs[3]
-                            && !matches!(param_types[1], Type::Inferred)
-                        {
-                            env.refine(sym, Type::List(Box::new(param_types[1].clone())))?;
+                        "vec-push" | "vec-slice" if i == 0 => {
+                            actual_return_type = arg_type.clone();
                         }
-                        // Prefer the concrete init type over any Inferred from
-                        // the function's return slot.
-                        Ok(init_type)
-                    }
-                    "let" => {
-                        if exprs.len() < 3 {
-                            return Err("Let requires at least 2 arguments".to_string());
+                        // What's pushed has to fit, and fills in an
+                        // empty vector's element type.
+                        "vec-push" if i == 1 => match &arg_types[0] {
+                            Type::Vector(elem_type) if !types_match(elem_type, &arg_type) => {
+                                return Err(format!(
+                                    "vec-push: vector holds {}, got {}",
+                                    resolve(elem_type), resolve(&arg_type)
+                                ));
+                            }
+                            Type::Vector(elem_type) if **elem_type == Type::Inferred => {
+                                actual_return_type = Type::Vector(Box::new(arg_type.clone()));
+                            }
+                            _ => {}
+                        },
+                        "nth" => {
+                            // nth returns the element type of the list (second arg)
+                            if i == 1
+                                && let Type::List(elem_type) = &arg_type
+                            {
+                                actual_return_type = *elem_type.clone();
+                            }
                         }
-                        
-                        if let Expr::Symbol(name) = &exprs[1] {
-                            let (type_ann, value_idx, body) = if exprs.len() == 4 {
-                                // Could be (let name type value) or (let name value body)
-                                if let Expr::Symbol(ty_str) = &exprs[2] {
-                                    if parse_type(ty_str).is_ok() {
-                                        let ty = parse_type(ty_str)?;
-                                        (Some(ty), 3, None)
-                                    } else {
-                                        // Not a type, treat as (let name value body)
-                                        (None, 2, Some(Box::new(exprs[3].clone())))
-                                    }
-                                } else {
-                                    // Not a type symbol, treat as (let name value body)
-                                    (None, 2, Some(Box::new(exprs[3].clone())))
-                                }
-                            } else if exprs.len() == 5 {
-                                // (let name type value body)
-                                if let Expr::Symbol(ty_str) = &exprs[2] {
-                                    let ty = parse_type(ty_str)?;
-                                    (Some(ty), 3, Some(Box::new(exprs[4].clone())))
-                                } else {
-                                    return Err("Invalid type annotation".to_string());
-                                }
+                        _ => {}
+                    }
+                }
+            }
+
+            if arg_types != params || actual_return_type != *return_type {
+                note(|| {
+                    let here = Type::Function { params: arg_types, return_type: Box::new(actual_return_type.clone()) };
+                    let declared = Type::Function { params, return_type };
+                    format!("instantiated {} as {}", declared, here)
+                });
+            }
+            Ok(actual_return_type)
+        }
+        _ => Err(format!("Cannot call non-function type: {}", func_type)),
+    }
+}
+
+/// `(head args...)` as read: the forms that are spelled like calls,
+/// or else a call.
+fn check_list(exprs: &[Expr], env: &mut TypeEnv) -> Result<Type, String> {
+    if exprs.is_empty() {
+        return Err("Empty list".to_string());
+    }
+
+    if let Expr::Symbol(op) = &exprs[0] {
+        match op.as_str() {
+            "if" => {
+                if exprs.len() != 4 {
+                    return Err("If requires 3 arguments".to_string());
+                }
+                type_check(&Expr::If {
+                    condition: Box::new(exprs[1].clone()),
+                    then_branch: Box::new(exprs[2].clone()),
+                    else_branch: Box::new(exprs[3].clone()),
+                }, env)
+            }
+            // Special forms so they can stop early; `(and)` is
+            // true and `(or)` false.
+            "and" | "or" => {
+                for operand in &exprs[1..] {
+                    let operand_type = type_check(operand, env)?;
+                    if !types_match(&Type::Bool, &operand_type) {
+                        return Err(format!(
+                            "Type mismatch in argument: `{}` expects bool operands, got {}",
+                            op,
+                            resolve(&operand_type)
+                        ));
+                    }
+                }
+                Ok(Type::Bool)
+            }
+            "list" => {
+                // Empty list: (list) -> List<_>
+                if exprs.len() == 1 {
+                    return Ok(Type::List(Box::new(Type::Inferred)));
+                }
+
+                // Require all elements to share a type with the first.
+                // types_match allows Inferred on either side so
+                // `(list 1 nil)` style mixing with unresolved types
+                // still works where appropriate.
+                let first_type = type_check(&exprs[1], env)?;
+                require_value(&exprs[1], &first_type, "a list element")?;
+                for (offset, elem) in exprs.iter().enumerate().skip(2) {
+                    let elem_type = type_check(elem, env)?;
+                    require_value(elem, &elem_type, "a list element")?;
+                    if !types_match(&first_type, &elem_type) {
+                        return Err(format!(
+                            "List element type mismatch at position {}: expected {}, got {}",
+                            offset - 1,
+                            first_type,
+                            elem_type
+                        ));
+                    }
+                }
+                Ok(Type::List(Box::new(first_type)))
+            }
+            "hash-map" => {
+                // {k v ...} : Map<K, V>, every key a K and value a V
+                let mut key_type = Type::Inferred;
+                let mut value_type = Type::Inferred;
+                for (offset, pair) in exprs[1..].chunks(2).enumerate() {
+                    let [key, value] = pair else {
+                        return Err("hash-map needs a value for every key".to_string());
+                    };
+                    let this_key = type_check(key, env)?;
+                    check_map_key("hash-map", &key_type, &this_key)?;
+                    let this_value = type_check(value, env)?;
+                    require_value(value, &this_value, "a map value")?;
+                    if !types_match(&value_type, &this_value) {
+                        return Err(format!(
+                            "Map value type mismatch at entry {}: expected {}, got {}",
+                            offset,
+                            resolve(&value_type),
+                            resolve(&this_value)
+                        ));
+                    }
+                    if offset == 0 {
+                        key_type = this_key;
+                        value_type = this_value;
+                    }
+                }
+                Ok(Type::Map(Box::new(key_type), Box::new(value_type)))
+            }
+            kind @ ("array" | "vector") => {
+                // #[a b c] : Array<A, 3> and [a b c] : Vec<A>, every
+                // element an A
+                let mut elem_type = Type::Inferred;
+                for (offset, elem) in exprs.iter().enumerate().skip(1) {
+                    let this = type_check(elem, env)?;
+                    require_value(elem, &this, if kind == "array" { "an array element" } else { "a vector element" })?;
+                    if !types_match(&elem_type, &this) {
+                        return Err(format!(
+                            "{} element type mismatch at position {}: expected {}, got {}",
+                            if kind == "array" { "Array" } else { "Vector" },
+                            offset - 1,
+                            resolve(&elem_type),
+                            resolve(&this)
+                        ));
+                    }
+                    if offset == 1 {
+                        elem_type = this;
+                    }
+                }
+                if kind == "vector" {
+                    return Ok(Type::Vector(Box::new(elem_type)));
+                }
+                Ok(Type::Array(Box::new(elem_type), exprs.len() - 1))
+            }
+            "map" => {
+                // (map f lst) : List<B> where f : A -> B and lst : List<A>
+                if exprs.len() != 3 {
+                    return Err("map requires 2 arguments: (map f lst)".to_string());
+                }
+                let f_type = type_check(&exprs[1], env)?;
+                let lst_type = type_check(&exprs[2], env)?;
+                // A generator maps lazily to another generator.
+                let lazy = matches!(lst_type, Type::Gen(_));
+                let elem_type = match &lst_type {
+                    Type::Gen(elem) => *elem.clone(),
+                    _ => expect_list_elem(&lst_type, "map")?,
+                };
+                let (param_types, ret_type) = expect_function(&f_type, "map", 1)?;
+                if param_types.len() != 1 {
+                    return Err(format!(
+                        "map requires a unary function, got arity {}",
+                        param_types.len()
+                    ));
+                }
+                if !types_match(&param_types[0], &elem_type) {
+                    return Err(format!(
+                        "map function parameter type {} does not match list element type {}",
+                        param_types[0], elem_type
+                    ));
+                }
+                // Bidirectional inference (段階 A): narrow the source
+                // variable when the list type is still Inferred but
+                // the lambda's parameter type is concrete.
+                if matches!(lst_type, Type::Inferred)
+                    && let Expr::Symbol(sym) = &exprs[2]
+                    && !matches!(param_types[0], Type::Inferred)
+                {
+                    env.refine(sym, Type::List(Box::new(param_types[0].clone())))?;
+                }
+                // If the function's return type is unresolved, the list
+                // element type is the best guess we have.
+                let result_elem = if ret_type == Type::Inferred {
+                    elem_type
+                } else {
+                    ret_type
+                };
+                if lazy {
+                    Ok(Type::Gen(Box::new(result_elem)))
+                } else {
+                    Ok(Type::List(Box::new(result_elem)))
+                }
+            }
+            "take" => {
+                // (take n seq) : List<A> where seq : List<A> or Gen<A>
+                if exprs.len() != 3 {
+                    return Err("take requires 2 arguments: (take n seq)".to_string());
+                }
+                let n_type = type_check(&exprs[1], env)?;
+                if !matches!(n_type, Type::I32 | Type::I64 | Type::Inferred | Type::Var(_)) {
+                    return Err(format!("take expects an integer count, got {}", n_type));
+                }
+                let seq_type = type_check(&exprs[2], env)?;
+                let elem_type = match &seq_type {
+                    Type::Gen(elem) => *elem.clone(),
+                    _ => expect_list_elem(&seq_type, "take")?,
+                };
+                Ok(Type::List(Box::new(elem_type)))
+            }
+            "filter" => {
+                // (filter pred lst) : List<A> where pred : A -> bool
+                if exprs.len() != 3 {
+                    return Err(
+                        "filter requires 2 arguments: (filter pred lst)".to_string()
+                    );
+                }
+                let pred_type = type_check(&exprs[1], env)?;
+                let lst_type = type_check(&exprs[2], env)?;
+                let elem_type = expect_list_elem(&lst_type, "filter")?;
+                let (param_types, ret_type) = expect_function(&pred_type, "filter", 1)?;
+                if param_types.len() != 1 {
+                    return Err(format!(
+                        "filter requires a unary predicate, got arity {}",
+                        param_types.len()
+                    ));
+                }
+                if !types_match(&param_types[0], &elem_type) {
+                    return Err(format!(
+                        "filter predicate parameter type {} does not match list element type {}",
+                        param_types[0], elem_type
+                    ));
+                }
+                if !types_match(&ret_type, &Type::Bool) {
+                    return Err(format!(
+                        "filter predicate must return bool, got {}",
+                        ret_type
+                    ));
+                }
+                // Bidirectional inference (段階 A): narrow the source
+                // variable from the predicate's parameter type.
+                if matches!(lst_type, Type::Inferred)
+                    && let Expr::Symbol(sym) = &exprs[2]
+                    && !matches!(param_types[0], Type::Inferred)
+                {
+                    env.refine(sym, Type::List(Box::new(param_types[0].clone())))?;
+                }
+                let result_elem = if matches!(elem_type, Type::Inferred)
+                    && !matches!(param_types[0], Type::Inferred)
+                {
+                    param_types[0].clone()
+                } else {
+                    elem_type
+                };
+                Ok(Type::List(Box::new(result_elem)))
+            }
+            fold @ ("fold" | "foldl" | "foldr") => {
+                // (fold f init lst) : B where f : B -> A -> B, init : B, lst : List<A>;
+                // foldr's f takes the element first: A -> B -> B.
+                if exprs.len() != 4 {
+                    return Err(format!("{} requires 3 arguments: ({} f init lst)", fold, fold));
+                }
+                let f_type = type_check(&exprs[1], env)?;
+                let init_type = type_check(&exprs[2], env)?;
+                let lst_type = type_check(&exprs[3], env)?;
+                let elem_type = expect_list_elem(&lst_type, fold)?;
+                let (param_types, ret_type) = expect_function(&f_type, fold, 2)?;
+                if param_types.len() != 2 {
+                    return Err(format!(
+                        "{} requires a binary function, got arity {}",
+                        fold,
+                        param_types.len()
+                    ));
+                }
+                let (acc_param, elem_param) = if fold == "foldr" {
+                    (&param_types[1], &param_types[0])
+                } else {
+                    (&param_types[0], &param_types[1])
+                };
+                if !types_match(acc_param, &init_type) {
+                    return Err(format!(
+                        "{} accumulator type {} does not match init type {}",
+                        fold, acc_param, init_type
+                    ));
+                }
+                if !types_match(elem_param, &elem_type) {
+                    return Err(format!(
+                        "{} element parameter type {} does not match list element type {}",
+                        fold, elem_param, elem_type
+                    ));
+                }
+                if !types_match(&ret_type, &init_type) {
+                    return Err(format!(
+                        "{} return type {} does not match accumulator type {}",
+                        fold, ret_type, init_type
+                    ));
+                }
+                // Bidirectional inference (段階 A): narrow the source
+                // variable from the lambda's element-parameter type.
+                if matches!(lst_type, Type::Inferred)
+                    && let Expr::Symbol(sym) = &exprs[3]
+                    && !matches!(elem_param, Type::Inferred)
+                {
+                    env.refine(sym, Type::List(Box::new(elem_param.clone())))?;
+                }
+                // Prefer the concrete init type over any Inferred from
+                // the function's return slot, but the function's list
+                // type over an empty `nil` start.
+                match (&init_type, &ret_type) {
+                    (Type::List(elem), Type::List(_)) if **elem == Type::Inferred => Ok(ret_type),
+                    _ => Ok(init_type),
+                }
+            }
+            "get" => match &exprs[1..] {
+                [record, Expr::Keyword(field)] => {
+                    let record_type = type_check(record, env)?;
+                    if let Type::Map(key_type, value_type) = shallow(&record_type) {
+                        check_map_key("get", &key_type, &Type::Keyword)?;
+                        return Ok(*value_type);
+                    }
+                    env.field_type(&record_type, &field.name())
+                }
+                _ => check_call(&exprs[0], &exprs[1..], env),
+            },
+            "bindings" => {
+                if exprs.len() != 1 {
+                    return Err("bindings takes no arguments".to_string());
+                }
+                let pair = Type::Pair(Box::new(Type::String), Box::new(Type::String));
+                Ok(Type::List(Box::new(pair)))
+            }
+            "memory-stats" => {
+                if exprs.len() != 1 {
+                    return Err("memory-stats takes no arguments".to_string());
+                }
+                let pair = Type::Pair(Box::new(Type::Keyword), Box::new(Type::I32));
+                Ok(Type::List(Box::new(pair)))
+            }
+            // The name isn't evaluated, so it needn't be bound.
+            "defined?" => match exprs.get(1) {
+                Some(Expr::Symbol(_)) if exprs.len() == 2 => Ok(Type::Bool),
+                _ => Err("defined? expects a name: (defined? x)".to_string()),
+            },
+            "let" => {
+                if exprs.len() < 3 {
+                    return Err("Let requires at least 2 arguments".to_string());
+                }
+
+                if let Expr::Symbol(name) = &exprs[1] {
+                    let (type_ann, value_idx, body) = if exprs.len() == 4 {
+                        // Could be (let name type value) or (let name value body)
+                        if let Expr::Symbol(ty_str) = &exprs[2] {
+                            if parse_type(ty_str).is_ok() {
+                                return Err("let requires a body".to_string());
                             } else {
-                                (None, 2, None)
-                            };
-                            
-                            type_check(&Expr::Let {
-                                name: name.clone(),
-                                type_ann,
-                                value: Box::new(exprs[value_idx].clone()),
-                                body,
-                            }, env)
+                                // Not a type, treat as (let name value body)
+                                (None, 2, Box::new(exprs[3].clone()))
+                            }
                         } else {
-                            Err("Let binding must have a symbol name".to_string())
+                            // Not a type symbol, treat as (let name value body)
+                            (None, 2, Box::new(exprs[3].clone()))
                         }
-                    }
-                    _ => {
-                        type_check(&Expr::Call {
-                            func: Box::new(exprs[0].clone()),
-                            args: exprs[1..].to_vec(),
-                        }, env)
-                    }
+                    } else if exprs.len() == 5 {
+                        // (let name type value body)
+                        if let Expr::Symbol(ty_str) = &exprs[2] {
+                            let ty = parse_type(ty_str)?;
+                            (Some(ty), 3, Box::new(exprs[4].clone()))
+                        } else {
+                            return Err("Invalid type annotation".to_string());
+                        }
+                    } else {
+                        return Err("let requires a body".to_string());
+                    };
+
+                    type_check(&Expr::Let {
+                        name: name.clone(),
+                        type_ann,
+                        value: Box::new(exprs[value_idx].clone()),
+                        body,
+                    }, env)
+                } else {
+                    Err("Let binding must have a symbol name".to_string())
                 }
-            } else {
-                type_check(&Expr::Call {
-                    func: Box::new(exprs[0].clone()),
-                    args: exprs[1..].to_vec(),
-                }, env)
             }
+            _ => {
+                check_call(&exprs[0], &exprs[1..], env)
+            }
+        }
+    } else {
+        check_call(&exprs[0], &exprs[1..], env)
+    }
+}
+
+/// Result type of arithmetic on `a` and `b`: f64 if both are f64, i32
+/// if both are i32, i64 if either is i64. An operand still being inferred
+/// takes the other's type. Floats and integers don't mix.
+fn promote(op: &str, a: &Type, b: &Type) -> Result<Type, String> {
+    let (a, b) = (&shallow(a), &shallow(b));
+    for operand in [a, b] {
+        if !matches!(operand, Type::I32 | Type::I64 | Type::F64 | Type::Inferred | Type::Var(_)) {
+            return Err(format!("Type mismatch in argument: `{}` expects a number, got {}", op, operand));
         }
     }
+    // An operand still being worked out takes the other's type.
+    let open = |t: &Type| matches!(t, Type::Var(_));
+    if open(a) && *b != Type::Inferred || open(b) && *a != Type::Inferred {
+        types_match(a, b);
+        return Ok(resolve(a));
+    }
+    Ok(match (a, b) {
+        (Type::F64, Type::F64 | Type::Inferred) | (Type::Inferred, Type::F64) => Type::F64,
+        (Type::F64, other) | (other, Type::F64) => {
+            return Err(format!(
+                "Type mismatch in argument: `{}` can't mix f64 with {}; convert with {}->f64",
+                op, other, other
+            ));
+        }
+        (Type::I64, _) | (_, Type::I64) => Type::I64,
+        (Type::Inferred, other) | (other, Type::Inferred) => other.clone(),
+        _ => Type::I32,
+    })
+}
+
+/// Common type of the operands of a numeric builtin like `min` or `+`.
+fn numeric_join(op: &str, operands: &[Type]) -> Result<Type, String> {
+    operands.iter().try_fold(Type::Inferred, |acc, t| promote(op, &acc, t))
 }
 
 pub fn parse_type(s: &str) -> Result<Type, String> {
@@ -705,6 +2116,14 @@ pub fn parse_type(s: &str) -> Result<Type, String> {
         "f64" => Ok(Type::F64),
         "bool" => Ok(Type::Bool),
         "String" => Ok(Type::String),
+        "StringBuilder" => Ok(Type::StringBuilder),
+        "Bytes" => Ok(Type::Bytes),
+        "DateTime" => Ok(Type::DateTime),
+        "ZonedDateTime" => Ok(Type::ZonedDateTime),
+        "Duration" => Ok(Type::Duration),
+        "KvStore" => Ok(Type::KvStore),
+        "Keyword" => Ok(Type::Keyword),
+        "Port" => Ok(Type::Port),
         "_" => Ok(Type::Inferred),
         _ => Err(format!("Unknown type: {}", s)),
     }
@@ -715,23 +2134,364 @@ pub fn parse_type(s: &str) -> Result<Type, String> {
 fn expect_list_elem(ty: &Type, op: &str) -> Result<Type, String> {
     match ty {
         Type::List(elem) => Ok(*elem.clone()),
+        // Arrays, slices and vectors are walked as lists of their elements.
+        Type::Array(elem, _) | Type::Slice(elem) | Type::Vector(elem) => Ok(*elem.clone()),
         // Bidirectional inference (段階 A): an unresolved scrutinee is
         // accepted here. Caller will narrow the source variable via
         // `TypeEnv::refine` once the function/lambda parameter types are
         // known.
         Type::Inferred => Ok(Type::Inferred),
+        Type::Var(_) => {
+            let elem = fresh();
+            types_match(ty, &Type::List(Box::new(elem.clone())));
+            Ok(elem)
+        }
         _ => Err(format!("{} expects a list, got {}", op, ty)),
     }
 }
 
-/// Unwrap a `Function` type, returning `(params, return_type)`.
-fn expect_function(ty: &Type, op: &str) -> Result<(Vec<Type>, Type), String> {
+/// A map type's key and value types; an unresolved one is left open.
+fn expect_map(ty: &Type, op: &str) -> Result<(Type, Type), String> {
+    match shallow(ty) {
+        Type::Map(key, value) => Ok((*key, *value)),
+        Type::Inferred | Type::Var(_) => Ok((Type::Inferred, Type::Inferred)),
+        other => Err(format!("{} expects a map, got {}", op, resolve(&other))),
+    }
+}
+
+/// Checks that `key` can key a map whose keys are `expected`.
+fn check_map_key(op: &str, expected: &Type, key: &Type) -> Result<(), String> {
+    let key = resolve(key);
+    if !matches!(key, Type::I32 | Type::I64 | Type::Bool | Type::String | Type::Keyword | Type::Inferred | Type::Var(_)) {
+        return Err(format!("{}: {} can't be a map key", op, key));
+    }
+    if !types_match(expected, &key) {
+        return Err(format!("{}: the map's keys are {}, got {}", op, resolve(expected), key));
+    }
+    Ok(())
+}
+
+/// `value` with the types `expected` gives it, if it's a `fn` bound
+/// or passed where a function type is expected: in
+/// `(let f: fn(i32) -> i32 (fn [x] (+ x 1)) ...)` `x` is checked as an
+/// i32 without being annotated. Only what the `fn` leaves out is filled
+/// in, so a disagreement still shows up as a mismatch of the two types.
+/// None for anything else, or a `fn` of the wrong arity.
+pub fn ascribe_lambda(value: &Expr, expected: &Type) -> Option<Expr> {
+    let (Expr::Lambda { params, return_type, body }, Type::Function { params: expected_params, return_type: expected_return }) =
+        (value, expected)
+    else {
+        return None;
+    };
+    if params.len() != expected_params.len() {
+        return None;
+    }
+    let params = params
+        .iter()
+        .zip(expected_params)
+        .map(|((name, ty), expected_ty)| match ty {
+            Type::Inferred => (name.clone(), expected_ty.clone()),
+            ty => (name.clone(), ty.clone()),
+        })
+        .collect();
+    let return_type = match return_type {
+        None if **expected_return != Type::Inferred => Some((**expected_return).clone()),
+        other => other.clone(),
+    };
+    Some(Expr::Lambda { params, return_type, body: body.clone() })
+}
+
+/// Bind every top-level `defn` in `forms` to the type its annotations
+/// declare, before any of them is checked, so a body can call a function
+/// defined further down: mutual recursion, or helpers kept below their
+/// callers. What isn't annotated is left open, and checking the `defn`
+/// itself replaces the declaration. Names already bound keep their
+/// binding.
+pub fn declare_defns<'a>(forms: impl IntoIterator<Item = &'a Expr>, env: &mut TypeEnv) {
+    for form in forms {
+        let defns = match form {
+            Expr::MultiArity(defns) => defns.as_slice(),
+            defn @ Expr::Defn { .. } => std::slice::from_ref(defn),
+            _ => continue,
+        };
+        let Some(Expr::Defn { name, .. }) = defns.first() else { continue };
+        if env.get(name).is_some() {
+            continue;
+        }
+        let arities = defns
+            .iter()
+            .filter_map(|defn| match defn {
+                Expr::Defn { params, return_type, .. } => Some(Type::Function {
+                    params: params.iter().map(|(_, t)| t.clone()).collect(),
+                    return_type: Box::new(return_type.clone()),
+                }),
+                _ => None,
+            })
+            .collect();
+        env.insert(name.clone(), by_arity(arities));
+    }
+}
+
+/// Check a `defn` and bind its name. `siblings` are the function's other
+/// arities, if it has several; the name gets them all.
+fn check_defn(
+    name: &str,
+    params: &[(String, Type)],
+    return_type: &Type,
+    body: &Expr,
+    meta: &crate::ast::Meta,
+    siblings: &[Type],
+    env: &mut TypeEnv,
+) -> Result<Type, String> {
+    env.check_redefinition(name)?;
+    let warned = env.warnings.borrow().len();
+    // What's left out is worked out from the body and the calls in it.
+    let params: Vec<(String, Type)> = params.iter().map(|(n, t)| (n.clone(), unknowns(t))).collect();
+    let return_type = &unknowns(return_type);
+    // First, add the function type to the environment for recursion
+    let func_type = Type::Function {
+        params: params.iter().map(|(_, t)| t.clone()).collect(),
+        return_type: Box::new(return_type.clone()),
+    };
+    env.insert(name.to_string(), by_arity(siblings.iter().cloned().chain([func_type]).collect()));
+
+    // Now type-check the body with the function in scope
+    let mut new_env = env.extend();
+
+    for (param_name, param_type) in &params {
+        new_env.insert(param_name.to_string(), param_type.clone());
+    }
+    new_env.recur = Some(params.iter().map(|(_, t)| t.clone()).collect());
+    new_env.yields = None;
+    let returns = Rc::new(RefCell::new(return_type.clone()));
+    new_env.returns = Some(Rc::clone(&returns));
+    new_env.blocks = Vec::new();
+    new_env.defer_scope = true;
+
+    let ascribed = ascribe_lambda(body, return_type);
+    let body_type = type_check(ascribed.as_ref().unwrap_or(body), &mut new_env)?;
+    check_recur_tail(body, true)?;
+
+    let returns = returns.borrow().clone();
+    if !types_match(&body_type, &returns) && returns != Type::Inferred {
+        return Err(format!(
+            "Return type mismatch: {}",
+            type_mismatch(&returns, &body_type)
+        ));
+    }
+    settle(&returns, &body_type);
+    // Without a declared return type, callers see the body's (or
+    // the first `return`'s, if the body always returns).
+    let return_type = match (return_type, &returns) {
+        (Type::Inferred, Type::Inferred) => body_type,
+        (Type::Inferred, returned) => returned.clone(),
+        (declared, _) => declared.clone(),
+    };
+
+    // Bidirectional inference (段階 A): if any `_` parameters were
+    // narrowed during body type-checking, reflect them in the
+    // signature so external callers see the precise type.
+    let refined_params: Vec<Type> = params
+        .iter()
+        .map(|(pname, ptype)| {
+            new_env
+                .refinements
+                .get(pname)
+                .cloned()
+                .unwrap_or_else(|| ptype.clone())
+        })
+        .collect();
+    let refined_func_type = Type::Function {
+        params: refined_params,
+        return_type: Box::new(return_type),
+    };
+    // Each call gets its own copy of whatever is still open.
+    let refined_func_type = generalize(&refined_func_type, env, name);
+    env.insert(name.to_string(), by_arity(siblings.iter().cloned().chain([refined_func_type.clone()]).collect()));
+    env.allow_warnings(warned, meta);
+    env.deprecate(name, meta);
+
+    Ok(refined_func_type)
+}
+
+/// The builtin `name` stands for: `car` for `head` and so on, unless
+/// `name` has been defined as something else.
+fn builtin_name<'a>(name: &'a str, env: &TypeEnv) -> &'a str {
+    match crate::env::LIST_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        Some((_, builtin)) if env.get(name) == lint::builtin_type(name).as_ref() => builtin,
+        _ => name,
+    }
+}
+
+/// One function type, or a `MultiArity` of several ordered by how many
+/// arguments they take.
+fn by_arity(mut arities: Vec<Type>) -> Type {
+    if arities.len() == 1 {
+        return arities.remove(0);
+    }
+    arities.sort_by_key(|t| match t {
+        Type::Function { params, .. } => params.len(),
+        _ => 0,
+    });
+    Type::MultiArity(arities)
+}
+
+/// Unwrap a `Function` type, returning `(params, return_type)`; `arity`
+/// is how many arguments `op` will call it with.
+fn expect_function(ty: &Type, op: &str, arity: usize) -> Result<(Vec<Type>, Type), String> {
     match ty {
         Type::Function { params, return_type } => Ok((params.clone(), *return_type.clone())),
+        // A function not known yet takes what `op` gives it.
+        Type::Var(_) => {
+            let params: Vec<Type> = (0..arity).map(|_| fresh()).collect();
+            let return_type = fresh();
+            types_match(ty, &Type::Function { params: params.clone(), return_type: Box::new(return_type.clone()) });
+            Ok((params, return_type))
+        }
         _ => Err(format!("{} expects a function, got {}", op, ty)),
     }
 }
 
+/// Reject a `recur` anywhere but in tail position of `expr`, the body of a
+/// `loop`, `defn` or `fn`. Tail position runs through `if` branches, `let`
+/// bodies and `match` arm bodies. Nested `loop`, `defn` and `fn` bodies are
+/// their own `recur` targets and get checked when they are.
+fn check_recur_tail(expr: &Expr, tail: bool) -> Result<(), String> {
+    match expr {
+        Expr::Recur(args) => {
+            if !tail {
+                return Err(format!("recur must be in tail position: {}", expr));
+            }
+            args.iter().try_for_each(|a| check_recur_tail(a, false))
+        }
+        Expr::If { condition, then_branch, else_branch } => {
+            check_recur_tail(condition, false)?;
+            check_recur_tail(then_branch, tail)?;
+            check_recur_tail(else_branch, tail)
+        }
+        Expr::Let { value, body, .. } => {
+            check_recur_tail(value, false)?;
+            check_recur_tail(body, tail)
+        }
+        Expr::Do(exprs) => {
+            let Some((last, init)) = exprs.split_last() else { return Ok(()) };
+            init.iter().try_for_each(|e| check_recur_tail(e, false))?;
+            check_recur_tail(last, tail)
+        }
+        Expr::Yield(value)
+        | Expr::Return(value)
+        | Expr::Block { body: value, .. }
+        | Expr::ReturnFrom { value, .. }
+        | Expr::Reset(value)
+        | Expr::Shift { body: value, .. }
+        | Expr::Defer(value) => check_recur_tail(value, false),
+        Expr::The { expr, .. } => check_recur_tail(expr, tail),
+        Expr::Match { scrutinee, arms } => {
+            check_recur_tail(scrutinee, false)?;
+            arms.iter().try_for_each(|(_, body)| check_recur_tail(body, tail))
+        }
+        Expr::Loop { bindings, .. } => {
+            bindings.iter().try_for_each(|(_, _, value)| check_recur_tail(value, false))
+        }
+        Expr::Def { value, .. } | Expr::Defparam { value, .. } => check_recur_tail(value, false),
+        // The body runs before the old values come back (or the resources
+        // are closed), so it's not a tail.
+        Expr::Parameterize { bindings, body } | Expr::WithOpen { bindings, body } => {
+            bindings.iter().try_for_each(|(_, value)| check_recur_tail(value, false))?;
+            check_recur_tail(body, false)
+        }
+        Expr::Call { func, args } => {
+            check_recur_tail(func, false)?;
+            args.iter().try_for_each(|a| check_recur_tail(a, false))
+        }
+        Expr::List(items) => items.iter().try_for_each(|e| check_recur_tail(e, false)),
+        Expr::Defn { .. }
+        | Expr::MultiArity(_)
+        | Expr::Defgen { .. }
+        | Expr::Lambda { .. }
+        | Expr::Integer32(_)
+        | Expr::Integer64(_)
+        | Expr::Float(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Bytes(_)
+        | Expr::Keyword(_)
+        | Expr::Symbol(_)
+        | Expr::Deftype { .. }
+        | Expr::Defstruct { .. }
+        | Expr::SetReaderMacro { .. }
+        | Expr::Nil => Ok(()),
+    }
+}
+
+/// Reject a `yield` used for its value: a `defgen` body can only yield
+/// where it would throw the value away. Those are the forms of a `do`,
+/// `if` branches, `let` bodies, `match` arm bodies and `loop` bodies, when
+/// the enclosing form is itself in such a place (`stmt`).
+fn check_yield_position(expr: &Expr, stmt: bool) -> Result<(), String> {
+    match expr {
+        Expr::Yield(value) => {
+            if !stmt {
+                return Err(format!("yield can't be used as a value: {}", expr));
+            }
+            check_yield_position(value, false)
+        }
+        Expr::Do(exprs) => exprs.iter().try_for_each(|e| check_yield_position(e, stmt)),
+        Expr::If { condition, then_branch, else_branch } => {
+            check_yield_position(condition, false)?;
+            check_yield_position(then_branch, stmt)?;
+            check_yield_position(else_branch, stmt)
+        }
+        Expr::Let { value, body, .. } => {
+            check_yield_position(value, false)?;
+            check_yield_position(body, stmt)
+        }
+        Expr::Match { scrutinee, arms } => {
+            check_yield_position(scrutinee, false)?;
+            arms.iter().try_for_each(|(_, body)| check_yield_position(body, stmt))
+        }
+        Expr::Loop { bindings, body } => {
+            bindings.iter().try_for_each(|(_, _, value)| check_yield_position(value, false))?;
+            check_yield_position(body, stmt)
+        }
+        Expr::Recur(args) => args.iter().try_for_each(|a| check_yield_position(a, false)),
+        Expr::Def { value, .. } | Expr::Defparam { value, .. } => check_yield_position(value, false),
+        Expr::Parameterize { bindings, body } | Expr::WithOpen { bindings, body } => {
+            bindings.iter().try_for_each(|(_, value)| check_yield_position(value, false))?;
+            check_yield_position(body, false)
+        }
+        Expr::Defer(value)
+        | Expr::Return(value)
+        | Expr::Block { body: value, .. }
+        | Expr::ReturnFrom { value, .. }
+        | Expr::Reset(value)
+        | Expr::Shift { body: value, .. } => check_yield_position(value, false),
+        Expr::The { expr, .. } => check_yield_position(expr, false),
+        Expr::Call { func, args } => {
+            check_yield_position(func, false)?;
+            args.iter().try_for_each(|a| check_yield_position(a, false))
+        }
+        Expr::List(items) => items.iter().try_for_each(|e| check_yield_position(e, false)),
+        // Their own bodies can't yield at all.
+        Expr::Defn { .. }
+        | Expr::MultiArity(_)
+        | Expr::Defgen { .. }
+        | Expr::Lambda { .. }
+        | Expr::Integer32(_)
+        | Expr::Integer64(_)
+        | Expr::Float(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Bytes(_)
+        | Expr::Keyword(_)
+        | Expr::Symbol(_)
+        | Expr::Deftype { .. }
+        | Expr::Defstruct { .. }
+        | Expr::SetReaderMacro { .. }
+        | Expr::Nil => Ok(()),
+    }
+}
+
 /// Strip `As` wrappers and report whether the underlying pattern is a
 /// list-shaped constructor (`cons` or `nil`). Used by the bidirectional
 /// scrutinee refinement in `Match` to decide when an `Inferred` scrutinee
@@ -749,6 +2509,86 @@ fn is_list_shaped(pat: &Pattern) -> bool {
     }
 }
 
+/// The `deftype` a constructor pattern (under any `as` or `guard`)
+/// matches values of.
+fn constructed_type(pat: &Pattern, env: &TypeEnv) -> Option<Type> {
+    match pat {
+        Pattern::Constructor(tag, _) => env.constructor(tag).map(|(name, _)| Type::Named(name)),
+        Pattern::As(inner, _) | Pattern::Guard(inner, _) => constructed_type(inner, env),
+        Pattern::Or(branches) => branches.iter().find_map(|b| constructed_type(b, env)),
+        _ => None,
+    }
+}
+
+/// Declare the type `name` with `variants`, binding each constructor:
+/// what `deftype` and `defstruct` share.
+fn declare_type(form: &str, name: &str, variants: &[(String, Vec<Type>)], env: &mut TypeEnv) -> Result<Type, String> {
+    for (tag, fields) in variants {
+        env.check_redefinition(tag)?;
+        if let Some((owner, _)) = env.constructor(tag)
+            && owner != *name
+        {
+            return Err(format!("constructor `{}` already belongs to type {}", tag, owner));
+        }
+        for field in fields {
+            if let Some(unknown) = undeclared_type(field, name, &env.adts.borrow()) {
+                return Err(format!("{} {}: unknown type {} in constructor `{}`", form, name, unknown, tag));
+            }
+        }
+    }
+    // Declaring a type again replaces it, as reloading a file does.
+    let old = env.adts.borrow_mut().insert(name.to_string(), variants.to_vec());
+    for (tag, _) in old.into_iter().flatten() {
+        env.remove(&tag);
+    }
+    let made = Type::Named(name.to_string());
+    for (tag, fields) in variants {
+        let ty = if fields.is_empty() {
+            made.clone()
+        } else {
+            Type::Function { params: fields.clone(), return_type: Box::new(made.clone()) }
+        };
+        env.insert(tag.clone(), ty);
+    }
+    Ok(Type::Type)
+}
+
+/// A type named in `ty` that no `deftype` declares, other than `own`,
+/// the one being declared.
+fn undeclared_type(ty: &Type, own: &str, adts: &Adts) -> Option<String> {
+    match ty {
+        Type::Named(name) if name != own && !adts.contains_key(name) => Some(name.clone()),
+        Type::List(inner) | Type::Ref(inner) | Type::Gen(inner) => undeclared_type(inner, own, adts),
+        Type::Pair(head, tail) => undeclared_type(head, own, adts).or_else(|| undeclared_type(tail, own, adts)),
+        Type::Function { params, return_type } => params
+            .iter()
+            .chain(std::iter::once(&**return_type))
+            .find_map(|t| undeclared_type(t, own, adts)),
+        _ => None,
+    }
+}
+
+/// The field types of constructor `tag`, if it has `arity` fields and
+/// makes values of `scrutinee`.
+fn constructor_fields(tag: &str, arity: usize, scrutinee: &Type, env: &TypeEnv) -> Result<Vec<Type>, String> {
+    let Some((owner, fields)) = env.constructor(tag) else {
+        return Err(format!("unknown constructor `{}`", tag));
+    };
+    if !types_match(scrutinee, &Type::Named(owner.clone())) {
+        return Err(format!("constructor `{}` makes {}, not {}", tag, owner, scrutinee));
+    }
+    if fields.len() != arity {
+        return Err(format!(
+            "constructor `{}` has {} field{}, the pattern gives {}",
+            tag,
+            fields.len(),
+            if fields.len() == 1 { "" } else { "s" },
+            arity
+        ));
+    }
+    Ok(fields)
+}
+
 /// Pure version of `bind_pattern`: returns the (name, type) bindings that
 /// the pattern would introduce, without mutating any environment. Used for
 /// or-pattern soundness — every branch must produce the same set of
@@ -756,6 +2596,7 @@ fn is_list_shaped(pat: &Pattern) -> bool {
 fn collect_bindings(
     pat: &Pattern,
     scrutinee: &Type,
+    env: &TypeEnv,
 ) -> Result<HashMap<String, Type>, String> {
     match pat {
         Pattern::Wildcard
@@ -764,7 +2605,8 @@ fn collect_bindings(
         | Pattern::LiteralI64(_)
         | Pattern::LiteralF64(_)
         | Pattern::LiteralBool(_)
-        | Pattern::LiteralString(_) => Ok(HashMap::new()),
+        | Pattern::LiteralString(_)
+        | Pattern::LiteralKeyword(_) => Ok(HashMap::new()),
         Pattern::Variable(name) => {
             let mut m = HashMap::new();
             m.insert(name.clone(), scrutinee.clone());
@@ -773,28 +2615,29 @@ fn collect_bindings(
         Pattern::Cons(head, tail) => {
             let (head_ty, tail_ty) = match scrutinee {
                 Type::List(elem) => (*elem.clone(), scrutinee.clone()),
+                Type::Pair(head, tail) => (*head.clone(), *tail.clone()),
                 _ => (Type::Inferred, Type::List(Box::new(Type::Inferred))),
             };
-            let mut m = collect_bindings(head, &head_ty)?;
-            for (k, v) in collect_bindings(tail, &tail_ty)? {
+            let mut m = collect_bindings(head, &head_ty, env)?;
+            for (k, v) in collect_bindings(tail, &tail_ty, env)? {
                 m.insert(k, v);
             }
             Ok(m)
         }
         Pattern::As(inner, name) => {
-            let mut m = collect_bindings(inner, scrutinee)?;
+            let mut m = collect_bindings(inner, scrutinee, env)?;
             m.insert(name.clone(), scrutinee.clone());
             Ok(m)
         }
-        Pattern::Guard(inner, _) => collect_bindings(inner, scrutinee),
+        Pattern::Guard(inner, _) => collect_bindings(inner, scrutinee, env),
         Pattern::Or(branches) => {
             // Defensive: parser rejects empty or, but guard the invariant.
             if branches.is_empty() {
                 return Err("empty or-pattern".to_string());
             }
-            let first = collect_bindings(&branches[0], scrutinee)?;
+            let first = collect_bindings(&branches[0], scrutinee, env)?;
             for b in &branches[1..] {
-                let m = collect_bindings(b, scrutinee)?;
+                let m = collect_bindings(b, scrutinee, env)?;
                 // Same key sets in both directions.
                 for k in first.keys() {
                     if !m.contains_key(k) {
@@ -824,6 +2667,14 @@ fn collect_bindings(
             }
             Ok(first)
         }
+        Pattern::Constructor(tag, pats) => {
+            let fields = constructor_fields(tag, pats.len(), scrutinee, env)?;
+            let mut m = HashMap::new();
+            for (pat, field) in pats.iter().zip(&fields) {
+                m.extend(collect_bindings(pat, field, env)?);
+            }
+            Ok(m)
+        }
     }
 }
 
@@ -876,8 +2727,19 @@ fn check_pattern(
                 Err(format!("pattern String does not match scrutinee type {}", scrutinee))
             }
         }
+        Pattern::LiteralKeyword(_) => {
+            if types_match(scrutinee, &Type::Keyword) {
+                Ok(())
+            } else {
+                Err(format!("pattern Keyword does not match scrutinee type {}", scrutinee))
+            }
+        }
         Pattern::Nil => match scrutinee {
             Type::List(_) | Type::Inferred => Ok(()),
+            Type::Var(_) => {
+                types_match(scrutinee, &Type::List(Box::new(fresh())));
+                Ok(())
+            }
             _ => Err(format!("nil pattern requires a list, got {}", scrutinee)),
         },
         Pattern::Cons(head, tail) => match scrutinee {
@@ -886,6 +2748,15 @@ fn check_pattern(
                 check_pattern(tail, scrutinee, env)?;
                 Ok(())
             }
+            Type::Pair(head_ty, tail_ty) => {
+                check_pattern(head, head_ty, env)?;
+                check_pattern(tail, tail_ty, env)
+            }
+            Type::Var(_) => {
+                let list = Type::List(Box::new(fresh()));
+                types_match(scrutinee, &list);
+                check_pattern(pattern, &list, env)
+            }
             // After bidirectional refinement (段階 A), an `Inferred`
             // scrutinee should have been narrowed to `List<_>` before we
             // get here. Surface a defensive internal error if not — this
@@ -893,7 +2764,7 @@ fn check_pattern(
             Type::Inferred => Err(
                 "internal: cons pattern reached Inferred scrutinee — should have been refined".to_string()
             ),
-            _ => Err(format!("cons pattern requires a list, got {}", scrutinee)),
+            _ => Err(format!("cons pattern requires a list or pair, got {}", scrutinee)),
         },
         Pattern::As(inner, _) => check_pattern(inner, scrutinee, env),
         Pattern::Guard(inner, guard_expr) => {
@@ -906,10 +2777,7 @@ fn check_pattern(
             let mut guard_env = env.extend();
             bind_pattern(inner, scrutinee, &mut guard_env);
             let ty = type_check(guard_expr, &mut guard_env)?;
-            if !types_match(&ty, &Type::Bool) {
-                return Err(format!("guard expression must be Bool, got {}", ty));
-            }
-            Ok(())
+            check_condition("guard expression", guard_expr, &ty, &mut guard_env)
         }
         Pattern::Or(branches) => {
             if branches.is_empty() {
@@ -920,7 +2788,14 @@ fn check_pattern(
                 check_pattern(b, scrutinee, env)?;
             }
             // All branches must introduce the same (name, type) bindings.
-            collect_bindings(pattern, scrutinee).map(|_| ())
+            collect_bindings(pattern, scrutinee, env).map(|_| ())
+        }
+        Pattern::Constructor(tag, pats) => {
+            let fields = constructor_fields(tag, pats.len(), scrutinee, env)?;
+            for (pat, field) in pats.iter().zip(&fields) {
+                check_pattern(pat, field, env)?;
+            }
+            Ok(())
         }
     }
 }
@@ -936,6 +2811,7 @@ fn bind_pattern(pattern: &Pattern, scrutinee: &Type, env: &mut TypeEnv) {
         | Pattern::LiteralF64(_)
         | Pattern::LiteralBool(_)
         | Pattern::LiteralString(_)
+        | Pattern::LiteralKeyword(_)
         | Pattern::Nil => {}
         Pattern::Variable(name) => {
             env.insert(name.clone(), scrutinee.clone());
@@ -943,6 +2819,7 @@ fn bind_pattern(pattern: &Pattern, scrutinee: &Type, env: &mut TypeEnv) {
         Pattern::Cons(head, tail) => {
             let (head_ty, tail_ty) = match scrutinee {
                 Type::List(elem) => (*elem.clone(), scrutinee.clone()),
+                Type::Pair(head, tail) => (*head.clone(), *tail.clone()),
                 _ => (Type::Inferred, Type::List(Box::new(Type::Inferred))),
             };
             bind_pattern(head, &head_ty, env);
@@ -966,26 +2843,370 @@ fn bind_pattern(pattern: &Pattern, scrutinee: &Type, env: &mut TypeEnv) {
                 bind_pattern(first, scrutinee, env);
             }
         }
+        Pattern::Constructor(tag, pats) => {
+            // `check_pattern` has vouched for the constructor already.
+            let fields = env.constructor(tag).map(|(_, fields)| fields).unwrap_or_default();
+            for (i, pat) in pats.iter().enumerate() {
+                bind_pattern(pat, fields.get(i).unwrap_or(&Type::Inferred), env);
+            }
+        }
     }
 }
 
+/// Fail if `expr`, of type `ty`, is only run for its effect and has no
+/// value to give where one is wanted, e.g. `(+ 1 (println x))`.
+fn require_value(expr: &Expr, ty: &Type, used_as: &str) -> Result<(), String> {
+    if *ty == Type::Unit {
+        return Err(format!("{} gives Unit, which has no value to use as {}", expr, used_as));
+    }
+    Ok(())
+}
+
+/// `if` conditions and `match` guards take a `bool` and nothing else:
+/// there is no truthiness, so `0`, `""` and `nil` are not false. A
+/// condition of unknown type is left to the evaluator, which holds it to
+/// the same rule; an unannotated name used as one is refined to `bool`.
+fn check_condition(what: &str, condition: &Expr, ty: &Type, env: &mut TypeEnv) -> Result<(), String> {
+    match ty {
+        Type::Bool => Ok(()),
+        Type::Var(_) => {
+            types_match(ty, &Type::Bool);
+            Ok(())
+        }
+        Type::Inferred => match condition {
+            Expr::Symbol(name) => env.refine(name, Type::Bool),
+            _ => Ok(()),
+        },
+        _ => {
+            let suggestion = match ty {
+                Type::I32 | Type::I64 => "compare it, e.g. (not (= x 0))",
+                Type::F64 => "compare it, e.g. (not (= x 0.0))",
+                Type::List(_) => "test it with (null? xs)",
+                _ => "write a comparison that yields one",
+            };
+            Err(format!("{} must be bool, got {}; {}", what, ty, suggestion))
+        }
+    }
+}
+
+/// Whether `actual` fits where `expected` is wanted, solving type
+/// variables on either side to make it so. A mismatch leaves every
+/// variable as it was.
 fn types_match(expected: &Type, actual: &Type) -> bool {
-    match (expected, actual) {
-        // Inferred matches anything
-        (Type::Inferred, _) | (_, Type::Inferred) => true,
-        
+    let mark = trail_mark();
+    let ok = unify(expected, actual);
+    if !ok {
+        undo(mark);
+    }
+    note(|| format!("unify {} with {}: {}", expected, actual, if ok { "ok" } else { "mismatch" }));
+    ok
+}
+
+/// Whether `types_match` would accept `actual` for `expected`, leaving
+/// every variable as it was either way.
+pub(crate) fn compatible(expected: &Type, actual: &Type) -> bool {
+    let mark = trail_mark();
+    let ok = unify(expected, actual);
+    undo(mark);
+    ok
+}
+
+fn unify(expected: &Type, actual: &Type) -> bool {
+    match (&shallow(expected), &shallow(actual)) {
+        // Inferred matches anything, and so does a type parameter seen
+        // without being instantiated (a signature read straight from the
+        // environment).
+        (Type::Inferred | Type::Generic(_), _) | (_, Type::Inferred | Type::Generic(_)) => true,
+
+        (Type::Var(a), Type::Var(b)) if a == b => true,
+        (Type::Var(var), other) | (other, Type::Var(var)) => solve(*var, other),
+
         // List types match if element types match
-        (Type::List(e1), Type::List(e2)) => types_match(e1, e2),
+        (Type::List(e1), Type::List(e2)) => unify(e1, e2),
+        (Type::Ref(e1), Type::Ref(e2)) => unify(e1, e2),
+        (Type::Gen(e1), Type::Gen(e2)) => unify(e1, e2),
+        (Type::Pair(h1, t1), Type::Pair(h2, t2)) => unify(h1, h2) && unify(t1, t2),
+        (Type::Array(e1, n1), Type::Array(e2, n2)) => n1 == n2 && unify(e1, e2),
+        (Type::Slice(e1), Type::Slice(e2)) | (Type::Vector(e1), Type::Vector(e2)) => unify(e1, e2),
+        (Type::Map(k1, v1), Type::Map(k2, v2)) => unify(k1, k2) && unify(v1, v2),
         
         // Function types match if params and return match
         (Type::Function { params: p1, return_type: r1 }, 
          Type::Function { params: p2, return_type: r2 }) => {
             p1.len() == p2.len() && 
-            p1.iter().zip(p2.iter()).all(|(a, b)| types_match(a, b)) &&
-            types_match(r1, r2)
+            p1.iter().zip(p2.iter()).all(|(a, b)| unify(a, b)) &&
+            unify(r1, r2)
         }
         
+        // A multi-arity function will do where one of its arities would.
+        (expected @ Type::Function { .. }, Type::MultiArity(arities)) => {
+            arities.iter().any(|t| types_match(expected, t))
+        }
+
         // Exact match
         (t1, t2) => t1 == t2,
     }
-}
\ No newline at end of file
+}
+
+/// Unification's state: what each type variable has been solved to, by
+/// number, and the order they were solved in, so a failed match can take
+/// its guesses back. One per thread, shared by every `TypeEnv` on it like
+/// the numbering of the variables.
+#[derive(Default)]
+struct Solved {
+    types: Vec<Option<Type>>,
+    trail: Vec<u32>,
+}
+
+thread_local! {
+    static SOLVED: RefCell<Solved> = RefCell::default();
+}
+
+/// A new type variable, not yet solved.
+fn fresh() -> Type {
+    SOLVED.with(|solved| {
+        let mut solved = solved.borrow_mut();
+        solved.types.push(None);
+        Type::Var((solved.types.len() - 1) as u32)
+    })
+}
+
+/// `ty` with each `_` in it a fresh variable: how an unannotated (or
+/// partly annotated) parameter or return type starts out.
+fn unknowns(ty: &Type) -> Type {
+    map_type(ty, &mut |t| matches!(t, Type::Inferred).then(fresh))
+}
+
+/// `ty` with every solved variable replaced by its solution, all the way
+/// down. What `type_check` hands back, so callers only ever see the
+/// variables still open.
+pub(crate) fn resolve(ty: &Type) -> Type {
+    map_type(ty, &mut |t| match t {
+        Type::Var(_) => match shallow(t) {
+            Type::Var(var) => Some(Type::Var(var)),
+            solved => Some(resolve(&solved)),
+        },
+        _ => None,
+    })
+}
+
+/// `ty`, or what it's been solved to if it's a solved variable.
+fn shallow(ty: &Type) -> Type {
+    let mut ty = ty.clone();
+    while let Type::Var(var) = ty {
+        match SOLVED.with(|solved| solved.borrow().types[var as usize].clone()) {
+            Some(solution) => ty = solution,
+            None => break,
+        }
+    }
+    ty
+}
+
+/// Solve `var` as `ty`, unless `ty` contains it: `x` can't be a list of
+/// `x`s.
+fn solve(var: u32, ty: &Type) -> bool {
+    if variables(&resolve(ty)).contains(&var) {
+        note(|| format!("occurs check: _ can't be {}", ty));
+        return false;
+    }
+    note(|| format!("solved _ is {}", ty));
+    SOLVED.with(|solved| {
+        let mut solved = solved.borrow_mut();
+        solved.types[var as usize] = Some(ty.clone());
+        solved.trail.push(var);
+    });
+    true
+}
+
+/// How many solutions there are so far, for `undo`.
+fn trail_mark() -> usize {
+    SOLVED.with(|solved| solved.borrow().trail.len())
+}
+
+/// Forget the solutions found since the trail was `mark` long.
+fn undo(mark: usize) {
+    SOLVED.with(|solved| {
+        let mut solved = solved.borrow_mut();
+        while solved.trail.len() > mark {
+            let var = solved.trail.pop().expect("longer than mark");
+            solved.types[var as usize] = None;
+        }
+    });
+}
+
+/// Whether `ty`, resolved, is still nothing in particular.
+fn is_unknown(ty: &Type) -> bool {
+    matches!(shallow(ty), Type::Inferred | Type::Var(_))
+}
+
+/// The open variables in `ty`, which should be resolved, in the order
+/// they first appear.
+fn variables(ty: &Type) -> Vec<u32> {
+    let mut vars = Vec::new();
+    map_type(ty, &mut |t| {
+        if let Type::Var(var) = t
+            && !vars.contains(var)
+        {
+            vars.push(*var);
+        }
+        None
+    });
+    vars
+}
+
+/// `ty` generalized over the variables in it that nothing in `env` but
+/// `name` mentions: each becomes a type parameter, instantiated afresh
+/// wherever `name` is used.
+fn generalize(ty: &Type, env: &TypeEnv, name: &str) -> Type {
+    let ty = resolve(ty);
+    let mut open = variables(&ty);
+    if open.is_empty() {
+        return ty;
+    }
+    for (other, other_ty) in &env.types {
+        if other != name {
+            let fixed = variables(&resolve(other_ty));
+            open.retain(|var| !fixed.contains(var));
+        }
+    }
+    let generalized = map_type(&ty, &mut |t| match t {
+        Type::Var(var) => open.iter().position(|v| v == var).map(|n| Type::Generic(n as u32)),
+        _ => None,
+    });
+    if !open.is_empty() {
+        note(|| format!("generalized `{}` as {}", name, generalized));
+    }
+    generalized
+}
+
+/// A result still open after a body of unknown type is unknown too,
+/// rather than free to be anything.
+fn settle(result: &Type, body: &Type) {
+    if *body == Type::Inferred
+        && let Type::Var(var) = shallow(result)
+    {
+        solve(var, &Type::Inferred);
+    }
+}
+
+/// Whether a value of type `value` may be bound under annotation `ann`:
+/// exactly, or by solving what's still open in `value`.
+fn fits_annotation(ann: &Type, value: &Type) -> bool {
+    ann == value || *ann == Type::Inferred || (!variables(value).is_empty() && types_match(ann, value))
+}
+
+/// `ty` with fresh variables for its type parameters.
+fn instantiate(ty: &Type) -> Type {
+    let mut params: Vec<(u32, Type)> = Vec::new();
+    map_type(ty, &mut |t| match t {
+        Type::Generic(n) => Some(match params.iter().find(|(p, _)| p == n) {
+            Some((_, var)) => var.clone(),
+            None => {
+                let var = fresh();
+                params.push((*n, var.clone()));
+                var
+            }
+        }),
+        _ => None,
+    })
+}
+
+/// `ty` rebuilt bottom-up, with `f` given first say on each part: what
+/// it returns replaces that part outright.
+fn map_type(ty: &Type, f: &mut impl FnMut(&Type) -> Option<Type>) -> Type {
+    if let Some(replaced) = f(ty) {
+        return replaced;
+    }
+    match ty {
+        Type::Function { params, return_type } => Type::Function {
+            params: params.iter().map(|p| map_type(p, f)).collect(),
+            return_type: Box::new(map_type(return_type, f)),
+        },
+        Type::MultiArity(arities) => Type::MultiArity(arities.iter().map(|t| map_type(t, f)).collect()),
+        Type::List(inner) => Type::List(Box::new(map_type(inner, f))),
+        Type::Ref(inner) => Type::Ref(Box::new(map_type(inner, f))),
+        Type::Gen(inner) => Type::Gen(Box::new(map_type(inner, f))),
+        Type::Pair(head, tail) => Type::Pair(Box::new(map_type(head, f)), Box::new(map_type(tail, f))),
+        Type::Array(elem, len) => Type::Array(Box::new(map_type(elem, f)), *len),
+        Type::Slice(elem) => Type::Slice(Box::new(map_type(elem, f))),
+        Type::Vector(elem) => Type::Vector(Box::new(map_type(elem, f))),
+        Type::Map(key, value) => Type::Map(Box::new(map_type(key, f)), Box::new(map_type(value, f))),
+        other => other.clone(),
+    }
+}
+
+/// `--explain-types`: while `explain` runs, `type_check` writes down each
+/// form it checks (with its type, or the error), indented under the form
+/// it's part of, along with what it decided on the way: which types it
+/// unified, which `_`s got solved, and what a call's signature turned
+/// into for the arguments given.
+struct Trace {
+    depth: usize,
+    lines: Vec<String>,
+}
+
+thread_local! {
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+}
+
+/// Run `run` with the checker tracing, returning what it returned and
+/// the derivation.
+pub fn explain<T>(run: impl FnOnce() -> T) -> (T, String) {
+    let outer = TRACE.with(|trace| trace.replace(Some(Trace { depth: 0, lines: Vec::new() })));
+    let result = run();
+    let trace = TRACE.with(|trace| trace.replace(outer)).expect("explain's trace");
+    (result, trace.lines.join("\n"))
+}
+
+fn tracing() -> bool {
+    TRACE.with(|trace| trace.borrow().is_some())
+}
+
+fn note(line: impl FnOnce() -> String) {
+    TRACE.with(|trace| {
+        if let Some(trace) = trace.borrow_mut().as_mut() {
+            let line = format!("{}{}", "  ".repeat(trace.depth), line());
+            trace.lines.push(line);
+        }
+    });
+}
+
+/// Start a form: a line for it, filled in by `trace_close`, with its
+/// premises one level in below.
+fn trace_open() -> usize {
+    TRACE.with(|trace| {
+        let mut trace = trace.borrow_mut();
+        let trace = trace.as_mut().expect("tracing");
+        trace.lines.push(String::new());
+        trace.depth += 1;
+        trace.lines.len() - 1
+    })
+}
+
+fn trace_close(line: usize, outcome: String) {
+    TRACE.with(|trace| {
+        let mut trace = trace.borrow_mut();
+        let trace = trace.as_mut().expect("tracing");
+        trace.depth -= 1;
+        // A form the checker rewrote and checks again (`(f x)` read as a
+        // list, checked as a call) would show twice: keep the inner one.
+        if trace.lines.get(line + 1).is_some_and(|inner| inner.trim_start() == outcome) {
+            trace.lines.remove(line);
+            for premise in &mut trace.lines[line..] {
+                premise.replace_range(..2, "");
+            }
+            return;
+        }
+        trace.lines[line] = format!("{}{}", "  ".repeat(trace.depth), outcome);
+    });
+}
+
+/// `text` on one line, cut short if it's long.
+fn abbreviate(text: &str) -> String {
+    const MAX: usize = 60;
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}